@@ -0,0 +1,258 @@
+//! The benchmark workload of the workers and the score derived from it.
+//!
+//! A benchmark is started by `pallet_registry` with a challenge derived from the on-chain
+//! randomness. Each iteration of the workload fills a scratchpad with words derived from the
+//! challenge and the iteration index, then performs data dependent reads and writes over it.
+//!
+//! At the end of the session the worker commits to the number of iterations and the Merkle root
+//! of all the iteration outputs. The iterations to spot-check are then derived from the root and
+//! a seed drawn from the on-chain randomness after the commitment, and the worker reveals them
+//! with their Merkle proofs. The chain only verifies the proofs, at a bounded cost, and keeps the
+//! revealed outputs. Anyone can check them off-chain with `run_iteration`, and dispute a wrong one
+//! with `pallet_registry::dispute_bench_sample`, which reruns the iteration and takes the score of
+//! the worker away.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use codec::{Decode, Encode};
+
+/// Number of 64-bit words in the scratchpad (512 KiB)
+pub const SCRATCHPAD_WORDS: usize = 1 << 16;
+/// Number of data dependent read-modify-write rounds in an iteration
+pub const MIX_ROUNDS: usize = 1 << 17;
+/// Number of iterations sampled in a report
+pub const SAMPLES: usize = 16;
+/// The max length of a Merkle proof, enough for `u64::MAX` iterations
+pub const MAX_PROOF_DEPTH: usize = 64;
+/// The upper bound of the score
+pub const MAX_SCORE: u32 = 6000;
+
+const SAMPLE_SALT: u64 = 0x5350_4f54_4348_4543;
+
+/// The random challenge of a benchmark session
+pub type Challenge = [u8; 32];
+
+/// A node of the Merkle tree over the iteration outputs
+pub type Hash = [u8; 32];
+
+/// The hash function of the Merkle tree.
+///
+/// Passed in by the callers so that the runtime can use the host functions of `sp_io` while
+/// pRuntime hashes natively. Both must be blake2_256.
+pub type Hasher = fn(&[u8]) -> Hash;
+
+/// The output of a sampled iteration with the proof of its inclusion in the committed root
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub index: u64,
+    pub output: u64,
+    pub proof: Vec<Hash>,
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn seed_state(challenge: &Challenge, salt: u64) -> u64 {
+    challenge.chunks(8).fold(salt, |acc, chunk| {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(chunk);
+        let mut state = acc ^ u64::from_le_bytes(word);
+        splitmix64(&mut state)
+    })
+}
+
+/// Runs the `index`-th iteration of the workload and returns its output
+pub fn run_iteration(challenge: &Challenge, index: u64) -> u64 {
+    let mut state = seed_state(challenge, index);
+    let mut pad = vec![0u64; SCRATCHPAD_WORDS];
+    for word in pad.iter_mut() {
+        *word = splitmix64(&mut state);
+    }
+    let mut acc = splitmix64(&mut state);
+    for _ in 0..MIX_ROUNDS {
+        let i = (acc as usize) % SCRATCHPAD_WORDS;
+        let value = pad[i];
+        acc = (acc ^ value)
+            .rotate_left(17)
+            .wrapping_mul(0x2545_F491_4F6C_DD1D)
+            .wrapping_add(i as u64);
+        pad[i] = value ^ acc;
+    }
+    acc
+}
+
+fn leaf_hash(hasher: Hasher, index: u64, output: u64) -> Hash {
+    let mut data = [0u8; 17];
+    data[1..9].copy_from_slice(&index.to_le_bytes());
+    data[9..].copy_from_slice(&output.to_le_bytes());
+    hasher(&data)
+}
+
+fn node_hash(hasher: Hasher, left: &Hash, right: &Hash) -> Hash {
+    let mut data = [1u8; 65];
+    data[1..33].copy_from_slice(left);
+    data[33..].copy_from_slice(right);
+    hasher(&data)
+}
+
+/// The Merkle tree over the outputs of the iterations `0..outputs.len()`
+///
+/// The last node of a level without a sibling is promoted to the next level as is.
+pub struct MerkleTree {
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(hasher: Hasher, outputs: &[u64]) -> Self {
+        let leaves: Vec<Hash> = outputs
+            .iter()
+            .enumerate()
+            .map(|(index, output)| leaf_hash(hasher, index as u64, *output))
+            .collect();
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(hasher, left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        MerkleTree { levels }
+    }
+
+    /// The root of the tree, or all zeros if there's no iteration
+    pub fn root(&self) -> Hash {
+        self.levels[self.levels.len() - 1]
+            .first()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The siblings on the path from the leaf of `index` to the root
+    pub fn proof(&self, index: u64) -> Vec<Hash> {
+        let mut pos = index as usize;
+        let mut proof = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(pos ^ 1) {
+                proof.push(*sibling);
+            }
+            pos /= 2;
+        }
+        proof
+    }
+}
+
+/// Checks that `output` is the output of the iteration `index` in the tree of `iterations` leaves
+/// with the given root
+pub fn verify_proof(
+    hasher: Hasher,
+    root: &Hash,
+    iterations: u64,
+    index: u64,
+    output: u64,
+    proof: &[Hash],
+) -> bool {
+    if index >= iterations || proof.len() > MAX_PROOF_DEPTH {
+        return false;
+    }
+    let mut node = leaf_hash(hasher, index, output);
+    let mut siblings = proof.iter();
+    let mut pos = index;
+    let mut width = iterations;
+    while width > 1 {
+        if pos ^ 1 < width {
+            let sibling = match siblings.next() {
+                Some(sibling) => sibling,
+                None => return false,
+            };
+            node = if pos % 2 == 0 {
+                node_hash(hasher, &node, sibling)
+            } else {
+                node_hash(hasher, sibling, &node)
+            };
+        }
+        pos /= 2;
+        width = (width + 1) / 2;
+    }
+    siblings.next().is_none() && &node == root
+}
+
+/// The indices of the iterations to be sampled in a report of `iterations` iterations
+///
+/// The indices depend on the committed root and a seed drawn after the commitment, so a worker
+/// can neither skip the iterations it won't be asked for nor grind the root to choose them.
+pub fn sample_indices(hasher: Hasher, root: &Hash, seed: &Hash, iterations: u64) -> Vec<u64> {
+    if iterations == 0 {
+        return Vec::new();
+    }
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(root);
+    data[32..].copy_from_slice(seed);
+    let mut state = seed_state(&hasher(&data), iterations ^ SAMPLE_SALT);
+    (0..SAMPLES)
+        .map(|_| splitmix64(&mut state) % iterations)
+        .collect()
+}
+
+/// Produces the samples of a report from the outputs of all the iterations
+pub fn make_samples(
+    hasher: Hasher,
+    outputs: &[u64],
+    tree: &MerkleTree,
+    seed: &Hash,
+) -> Vec<Sample> {
+    sample_indices(hasher, &tree.root(), seed, outputs.len() as u64)
+        .into_iter()
+        .map(|index| Sample {
+            index,
+            output: outputs[index as usize],
+            proof: tree.proof(index),
+        })
+        .collect()
+}
+
+/// Checks the samples of a report against the committed root
+///
+/// Costs at most `SAMPLES * (MAX_PROOF_DEPTH + 2)` hashes of at most 65 bytes.
+pub fn verify_samples(
+    hasher: Hasher,
+    root: &Hash,
+    seed: &Hash,
+    iterations: u64,
+    samples: &[Sample],
+) -> bool {
+    if samples.len() != SAMPLES {
+        return false;
+    }
+    let indices = sample_indices(hasher, root, seed, iterations);
+    indices.iter().zip(samples).all(|(index, sample)| {
+        sample.index == *index
+            && verify_proof(
+                hasher,
+                root,
+                iterations,
+                sample.index,
+                sample.output,
+                &sample.proof,
+            )
+    })
+}
+
+/// Calculates the score of a benchmark
+///
+/// The score is the number of iterations per second, capped at `MAX_SCORE`.
+pub fn score(iterations: u64, elapsed_secs: u64) -> u32 {
+    if elapsed_secs == 0 {
+        return 0;
+    }
+    (iterations / elapsed_secs).min(MAX_SCORE as u64) as u32
+}
@@ -5,6 +5,13 @@ use alloc::vec::Vec;
 use codec::{Decode, Encode};
 use core::fmt::Debug;
 
+#[cfg(feature = "enable_serde")]
+use serde::{Deserialize, Serialize};
+
+pub mod benchmark;
+#[cfg(feature = "enable_serde")]
+pub mod query;
+
 // Messages: Phase Wallet
 
 pub mod messaging {
@@ -12,9 +19,9 @@ pub mod messaging {
     use alloc::vec::Vec;
     use codec::{Decode, Encode};
     use core::fmt::Debug;
-    use sp_core::U256;
+    use sp_core::{H256, U256};
 
-    use super::WorkerPublicKey;
+    use super::{CodeIndex, EcdhPublicKey, WorkerPublicKey};
     pub use phala_mq::bind_topic;
     pub use phala_mq::types::*;
 
@@ -54,6 +61,7 @@ pub mod messaging {
             address: String,
             utxo: (Txid, u32, u64),
         },
+        /// Deprecated. The admin is set by `pallet_registry::ContractAdmin` instead.
         SetAdmin {
             new_admin: String,
         },
@@ -66,7 +74,24 @@ pub mod messaging {
     bind_topic!(BalanceEvent<AccountId, Balance>, b"phala/balances/event");
     #[derive(Debug, Clone, Encode, Decode)]
     pub enum BalanceEvent<AccountId, Balance> {
+        /// Sent by the deprecated `pallet_phala::transfer_to_tee`, which doesn't escrow the funds.
+        /// Kept to replay the history.
         TransferToTee(AccountId, Balance),
+        /// `amount` is escrowed for `who` by the `number`-th deposit in `pallet_escrow`.
+        /// `escrowed` is the total escrowed funds after it.
+        Deposit {
+            number: u64,
+            who: AccountId,
+            amount: Balance,
+            escrowed: Balance,
+        },
+        /// The withdrawal `number` is paid on chain
+        WithdrawalConfirmed { number: u64, escrowed: Balance },
+        /// The withdrawal `number` is rejected on chain and must be refunded
+        WithdrawalFailed { number: u64 },
+        /// The `amount` deposited by `TransferToTee` is moved to the escrow by a migration.
+        /// `escrowed` is the total escrowed funds after it.
+        LegacyEscrowed { amount: Balance, escrowed: Balance },
     }
 
     bind_topic!(BalanceCommand<AccountId, Balance>, b"phala/balances/command");
@@ -74,13 +99,38 @@ pub mod messaging {
     pub enum BalanceCommand<AccountId, Balance> {
         Transfer { dest: AccountId, value: Balance },
         TransferToChain { dest: AccountId, value: Balance },
+        /// Transfers `value` to `dest` and notifies it with a `BalancePayment`
+        Pay {
+            dest: AccountId,
+            value: Balance,
+            memo: Vec<u8>,
+        },
     }
 
-    bind_topic!(BalanceTransfer<AccountId, Balance>, b"^phala/balances/transfer");
-    #[derive(Encode, Decode)]
-    pub struct BalanceTransfer<AccountId, Balance> {
-        pub dest: AccountId,
+    /// Sent by the `Balances` contract for each `BalanceCommand::Pay`, so that the contracts can
+    /// be paid. The payments to a contract instance are sent encrypted to its
+    /// `contract_event_topic`. The others go through the chain in plain, so they are public.
+    bind_topic!(BalancePayment<AccountId, Balance>, b"phala/balances/payment");
+    #[derive(Debug, Clone, Encode, Decode)]
+    pub struct BalancePayment<AccountId, Balance> {
+        pub payer: AccountId,
+        pub payee: AccountId,
         pub amount: Balance,
+        pub memo: Vec<u8>,
+    }
+
+    /// The reports of the `Balances` contract to `pallet_escrow`
+    bind_topic!(BalanceReport<AccountId, Balance>, b"^phala/balances/report");
+    #[derive(Debug, Clone, Encode, Decode)]
+    pub enum BalanceReport<AccountId, Balance> {
+        /// The deposit `number` is credited
+        DepositAccepted { number: u64 },
+        /// Pays `amount` from the escrow to `dest`
+        Withdrawal {
+            number: u64,
+            dest: AccountId,
+            amount: Balance,
+        },
     }
 
     // Messages for Assets
@@ -100,6 +150,70 @@ pub mod messaging {
             dest: AccountId,
             value: Balance,
         },
+        /// Allows `spender` to transfer up to `value` from the sender's account
+        Approve {
+            id: AssetId,
+            spender: AccountId,
+            value: Balance,
+        },
+        /// Transfers from `owner` to `dest` within the allowance given to the sender
+        TransferFrom {
+            id: AssetId,
+            owner: AccountId,
+            dest: AccountId,
+            value: Balance,
+        },
+        /// Issues more tokens to `dest` (asset owner only)
+        Mint {
+            id: AssetId,
+            dest: AccountId,
+            value: Balance,
+        },
+        /// Burns tokens from the owner's account (asset owner only)
+        Burn {
+            id: AssetId,
+            value: Balance,
+        },
+        /// Sets the name and the decimals of the asset (asset owner only)
+        SetMetadata {
+            id: AssetId,
+            name: String,
+            decimals: u8,
+        },
+        /// Enables or disables `AssetEvent`s of the asset (asset owner only)
+        SetPublicEvents {
+            id: AssetId,
+            enabled: bool,
+        },
+    }
+
+    /// The events of the assets which opted in by `AssetCommand::SetPublicEvents`, for the pallets
+    /// to react to the confidential transfers. They reveal the accounts and the amounts on chain.
+    bind_topic!(AssetEvent<AccountId, Balance>, b"^phala/assets/event");
+    #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+    pub enum AssetEvent<AccountId, Balance> {
+        Transfer {
+            id: AssetId,
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
+        },
+        Approval {
+            id: AssetId,
+            owner: AccountId,
+            spender: AccountId,
+            value: Balance,
+        },
+        Minted {
+            id: AssetId,
+            dest: AccountId,
+            value: Balance,
+        },
+        Burned {
+            id: AssetId,
+            from: AccountId,
+            value: Balance,
+        },
     }
 
     pub type AssetId = u32;
@@ -110,6 +224,33 @@ pub mod messaging {
     #[derive(Encode, Decode, Debug)]
     pub enum Web3AnalyticsCommand {
         SetConfiguration { skip_stat: bool },
+        /// Registers the site `sid` owned by the sender. The API key is chosen by the owner, and
+        /// only its blake2_256 hash is sent, since the commands are public.
+        RegisterSite { sid: String, api_key_hash: [u8; 32] },
+        /// Replaces the API key of a site. Only for the owner.
+        RotateApiKey { sid: String, api_key_hash: [u8; 32] },
+        /// Sets how long the stats of a site are kept. Only for the owner.
+        SetRetention { sid: String, retention: Retention },
+    }
+
+    /// How long the stats of a site are kept. Expired page views are rolled up into the hourly
+    /// stats, and expired hourly stats into the daily ones.
+    #[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Retention {
+        pub page_view_hours: u32,
+        pub hourly_stat_days: u32,
+        /// Also applies to the weekly stats
+        pub daily_stat_days: u32,
+    }
+
+    impl Default for Retention {
+        fn default() -> Self {
+            Retention {
+                page_view_hours: 48,
+                hourly_stat_days: 30,
+                daily_stat_days: 365,
+            }
+        }
     }
 
     // Messages for diem
@@ -144,6 +285,33 @@ pub mod messaging {
             to: String,
             amount: u64,
         },
+        /// Creates the parent VASP account held by the admin, with a key generated in the
+        /// enclave. Its address is to be registered as a parent VASP on Diem (admin only)
+        NewParentAccount,
+    }
+
+    // Messages for NFTs
+
+    /// The commands of the confidential NFT contract. The token ids are opaque and chosen by the
+    /// minter.
+    bind_topic!(NftCommand<AccountId>, b"phala/nft/command");
+    #[derive(Debug, Clone, Encode, Decode)]
+    pub enum NftCommand<AccountId> {
+        /// Mints a token to `dest` with the metadata only its holder can read (admin only)
+        Mint {
+            id: String,
+            dest: AccountId,
+            metadata: Vec<u8>,
+        },
+        /// Transfers the token (by its owner or the approved account)
+        Transfer { id: String, dest: AccountId },
+        /// Approves `spender` to transfer or burn the token, or clears the approval (owner only)
+        Approve {
+            id: String,
+            spender: Option<AccountId>,
+        },
+        /// Destroys the token (by its owner or the approved account)
+        Burn { id: String },
     }
 
     // Messages for Kitties
@@ -175,6 +343,12 @@ pub mod messaging {
         BenchStart {
             pubkey: WorkerPublicKey,
             start_time: u64,
+            challenge: crate::benchmark::Challenge,
+        },
+        /// The seed to sample the iterations committed by `RegistryEvent::BenchCommit`
+        BenchSeed {
+            pubkey: WorkerPublicKey,
+            seed: crate::benchmark::Hash,
         },
         NewMiningRound(u32),
         RewardSeed(BlockRewardInfo),
@@ -187,6 +361,79 @@ pub mod messaging {
         pub compute_target: U256,
     }
 
+    bind_topic!(GatekeeperEvent, b"phala/gatekeeper/event");
+    #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+    pub enum GatekeeperEvent {
+        /// A new gatekeeper set will take effect at block `apply_at`. The current gatekeepers
+        /// should hand over the master key to the new ones before that.
+        RotationScheduled {
+            gatekeepers: Vec<WorkerPublicKey>,
+            apply_at: u32,
+        },
+        /// The scheduled gatekeeper set took effect
+        RotationApplied { gatekeepers: Vec<WorkerPublicKey> },
+    }
+
+    /// The secret keys sent by the gatekeepers, each encrypted to the registered ECDH key of `dest`
+    bind_topic!(KeyDistribution, b"phala/gatekeeper/key");
+    #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+    pub enum KeyDistribution {
+        /// The master key handed over to a gatekeeper joining the set
+        MasterKey {
+            dest: WorkerPublicKey,
+            key: EncryptedKey,
+        },
+        /// The root of the contract keys, derived from the master key and shared by the workers
+        ContractKey {
+            dest: WorkerPublicKey,
+            key: EncryptedKey,
+        },
+    }
+
+    /// A key encrypted with AES-GCM by the ECDH agreement between the sender and the receiver
+    #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+    pub struct EncryptedKey {
+        /// The ECDH public key of the sender
+        pub ecdh_pubkey: EcdhPublicKey,
+        pub iv: Vec<u8>,
+        pub encrypted_key: Vec<u8>,
+    }
+
+    // Messages: Contracts
+
+    bind_topic!(ContractEvent<AccountId>, b"phala/contract/event");
+    #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+    pub enum ContractEvent<AccountId> {
+        /// A contract is instantiated on chain. The workers create the instance `contract_id` of
+        /// the code `code_index`, initialized with `init_params`.
+        InstantiateCode {
+            contract_id: H256,
+            code_index: CodeIndex,
+            deployer: AccountId,
+            init_params: Vec<u8>,
+        },
+    }
+
+    /// The topic of the commands to the contract instance `contract_id`
+    pub fn contract_command_topic(contract_id: &H256) -> Path {
+        alloc::format!(
+            "phala/contract/{}/command",
+            sp_core::hexdisplay::HexDisplay::from(&contract_id.0)
+        )
+        .into_bytes()
+    }
+
+    /// The topic of the events `topic` sent to the contract instance `contract_id` only, so that
+    /// an instance doesn't receive the events of the others
+    pub fn contract_event_topic(topic: &[u8], contract_id: &H256) -> Path {
+        let mut path = topic.to_vec();
+        path.extend_from_slice(
+            alloc::format!("/{}", sp_core::hexdisplay::HexDisplay::from(&contract_id.0))
+                .as_bytes(),
+        );
+        path
+    }
+
     bind_topic!(WorkerReportEvent, b"^phala/system/report");
     #[derive(Encode, Decode, Clone, Debug)]
     pub enum WorkerReportEvent {
@@ -202,6 +449,7 @@ pub mod messaging {
 // Types used in storage
 
 #[derive(Encode, Decode, PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
 pub enum WorkerStateEnum<BlockNumber> {
     Empty,
     Free,
@@ -218,6 +466,8 @@ impl<BlockNumber> Default for WorkerStateEnum<BlockNumber> {
 }
 
 #[derive(Encode, Decode, Debug, Default, Clone)]
+#[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "enable_serde", serde(rename_all = "camelCase"))]
 pub struct WorkerInfo<BlockNumber> {
     // identity
     pub machine_id: Vec<u8>,
@@ -234,18 +484,24 @@ pub struct WorkerInfo<BlockNumber> {
 }
 
 #[derive(Encode, Decode, Default)]
-pub struct StashInfo<AccountId: Default> {
+#[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "enable_serde", serde(rename_all = "camelCase"))]
+pub struct StashInfo<AccountId> {
     pub controller: AccountId,
     pub payout_prefs: PayoutPrefs<AccountId>,
 }
 
 #[derive(Encode, Decode, Default)]
-pub struct PayoutPrefs<AccountId: Default> {
+#[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "enable_serde", serde(rename_all = "camelCase"))]
+pub struct PayoutPrefs<AccountId> {
     pub commission: u32,
     pub target: AccountId,
 }
 
 #[derive(Encode, Decode, Debug, Default, Clone)]
+#[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "enable_serde", serde(rename_all = "camelCase"))]
 pub struct Score {
     pub overall_score: u32,
     pub features: Vec<u32>,
@@ -255,16 +511,47 @@ type MachineId = [u8; 16];
 pub type WorkerPublicKey = sp_core::ecdsa::Public;
 pub type ContractPublicKey = sp_core::ecdsa::Public;
 
+/// The code a contract is instantiated from
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub enum CodeIndex {
+    /// A native contract built into pRuntime, identified by its legacy contract id (e.g. 3 for
+    /// the assets contract)
+    NativeCode(u32),
+}
+/// The raw ECDH (secp256r1) public key of a worker
+pub type EcdhPublicKey = Vec<u8>;
+
 #[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
 pub struct PRuntimeInfo<AccountId> {
     pub version: u32,
     pub machine_id: MachineId,
     pub pubkey: WorkerPublicKey,
+    /// The key to encrypt the messages to the worker
+    pub ecdh_pubkey: EcdhPublicKey,
     pub features: Vec<u32>,
     pub operator: Option<AccountId>,
 }
 
+/// A statement signed by the current identity key of a worker, handing over the worker identity
+/// to a newly generated key pair.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct WorkerKeyHandover {
+    pub old_pubkey: WorkerPublicKey,
+    pub new_pubkey: WorkerPublicKey,
+    pub new_ecdh_pubkey: EcdhPublicKey,
+}
+
+/// The gatekeeper set waiting to take effect
+#[derive(Encode, Decode, Debug, Default, Clone, PartialEq, Eq)]
+pub struct GatekeeperRotation<BlockNumber> {
+    pub gatekeepers: Vec<WorkerPublicKey>,
+    /// The block from which the set takes effect
+    pub apply_at: BlockNumber,
+}
+
 #[derive(Encode, Decode, Debug, Default)]
+#[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "enable_serde", serde(rename_all = "camelCase"))]
 pub struct RoundInfo<BlockNumber> {
     pub round: u32,
     pub start_block: BlockNumber,
@@ -277,7 +564,25 @@ pub struct StashWorkerStats<Balance> {
     pub online_received: Balance,
 }
 
+/// A miner missed a heartbeat challenge, waiting to be disputed or applied
+#[derive(Encode, Decode, Debug, Default, Clone, PartialEq, Eq)]
+pub struct OffenceRecord<AccountId, BlockNumber> {
+    pub reporter: AccountId,
+    pub reported_at: BlockNumber,
+}
+
+/// The recent offences of a miner, used to graduate the slash
+#[derive(Encode, Decode, Debug, Default, Clone, PartialEq, Eq)]
+pub struct SlashingSpan {
+    /// Number of the applied offences in this span
+    pub offences: u32,
+    /// The round of the last applied offence
+    pub last_round: u32,
+}
+
 #[derive(Encode, Decode, Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "enable_serde", serde(rename_all = "camelCase"))]
 pub struct RoundStats {
     pub round: u32,
     pub online_workers: u32,
@@ -0,0 +1,73 @@
+//! The JSON envelope of the contract queries sent to pRuntime.
+//!
+//! pRuntime is built with serde-sgx, so it keeps its own copy of these types in
+//! `standalone/pruntime/enclave/src/types.rs` and `cryptography/mod.rs`. Keep them in sync.
+
+use alloc::string::String;
+use codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// A message encrypted by AES-256-GCM with the secret agreed by ECDH (secp256r1)
+#[derive(Serialize, Deserialize, Debug, Clone, Encode, Decode)]
+pub struct AeadCipher {
+    pub iv_b64: String,
+    pub cipher_b64: String,
+    /// The ECDH public key of the sender
+    pub pubkey_b64: String,
+}
+
+/// The signer of a query
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Origin {
+    /// The hex encoded public key
+    pub origin: String,
+    /// The signature of `SignedQuery::query_payload`
+    pub sig_b64: String,
+    pub sig_type: SignatureType,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureType {
+    #[serde(rename = "ed25519")]
+    Ed25519,
+    #[serde(rename = "sr25519")]
+    Sr25519,
+    #[serde(rename = "ecdsa")]
+    Ecdsa,
+}
+
+/// A query or a reply, either in plain JSON or encrypted to the ECDH key of the receiver
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Payload {
+    Plain(String),
+    Cipher(AeadCipher),
+}
+
+/// The request of the `query` API of pRuntime
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedQuery {
+    /// The JSON encoded `Payload` wrapping a JSON encoded `Query`
+    pub query_payload: String,
+    pub origin: Option<Origin>,
+}
+
+/// A query to the contract `contract_id`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Query<T> {
+    pub contract_id: u32,
+    /// The hex id of the contract instance (instantiated by `pallet_registry`) to query, instead
+    /// of the built-in contract `contract_id`
+    #[serde(default)]
+    pub instance_id: Option<String>,
+    /// A random number. pRuntime rejects a signed query reusing the nonce of an unexpired query of
+    /// the same origin.
+    pub nonce: u32,
+    /// The hex identity public key of the target worker. Required by the signed queries.
+    #[serde(default)]
+    pub worker_pubkey: Option<String>,
+    /// The last block the query is valid at, at most 100 blocks after the latest block dispatched
+    /// by the worker. Required by the signed queries.
+    #[serde(default)]
+    pub expiry: Option<u32>,
+    pub request: T,
+}
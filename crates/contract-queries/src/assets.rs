@@ -0,0 +1,123 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{Balance, TxRef};
+
+pub type AssetId = u32;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub struct AssetMetadata<AccountId> {
+    pub owner: AccountId,
+    #[serde(with = "crate::serde_balance")]
+    pub total_supply: Balance,
+    pub symbol: String,
+    pub id: AssetId,
+    pub name: String,
+    pub decimals: u8,
+    /// Whether to send `AssetEvent`s to the chain
+    pub public_events: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub struct AssetMetadataBalance<AccountId> {
+    pub metadata: AssetMetadata<AccountId>,
+    #[serde(with = "crate::serde_balance")]
+    pub balance: Balance,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub enum AssetsTxKind {
+    Transfer,
+    Mint,
+    Burn,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub struct AssetsTx<AccountId> {
+    pub txref: TxRef,
+    pub asset_id: AssetId,
+    pub kind: AssetsTxKind,
+    pub from: AccountId,
+    pub to: AccountId,
+    #[serde(with = "crate::serde_balance")]
+    pub amount: Balance,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub enum Request<AccountId> {
+    Balance {
+        id: AssetId,
+        account: AccountId,
+    },
+    TotalSupply {
+        id: AssetId,
+    },
+    Metadata,
+    /// The allowance given by `owner` to `spender`. Only for the owner or the spender.
+    Allowance {
+        id: AssetId,
+        owner: AccountId,
+        spender: AccountId,
+    },
+    /// Lists the transactions of `account` with command index no less than `from`
+    History {
+        account: AccountId,
+        #[serde(default)]
+        from: u64,
+        #[serde(default = "default_limit")]
+        limit: u32,
+    },
+    /// Lists the assets with id no less than `from`, with the balances of the origin
+    ListAssets {
+        #[serde(default)]
+        available_only: bool,
+        #[serde(default)]
+        from: AssetId,
+        #[serde(default = "default_limit")]
+        limit: u32,
+    },
+}
+
+/// The page size when the query doesn't specify one
+pub const DEFAULT_LIMIT: u32 = 100;
+
+fn default_limit() -> u32 {
+    DEFAULT_LIMIT
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub enum Response<AccountId> {
+    Balance {
+        #[serde(with = "crate::serde_balance")]
+        balance: Balance,
+    },
+    TotalSupply {
+        #[serde(with = "crate::serde_balance")]
+        total_issuance: Balance,
+    },
+    Metadata {
+        metadata: Vec<AssetMetadata<AccountId>>,
+    },
+    Allowance {
+        #[serde(with = "crate::serde_balance")]
+        allowance: Balance,
+    },
+    History {
+        history: Vec<AssetsTx<AccountId>>,
+        /// The command index to continue with, if there are more transactions
+        next: Option<u64>,
+    },
+    ListAssets {
+        assets: Vec<AssetMetadataBalance<AccountId>>,
+        /// The asset id to continue with, if there are more assets
+        next: Option<AssetId>,
+    },
+    Error(String),
+}
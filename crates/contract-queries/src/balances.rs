@@ -0,0 +1,43 @@
+use alloc::string::String;
+use serde::{Deserialize, Serialize};
+
+use crate::Balance;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub enum Request<AccountId> {
+    FreeBalance { account: AccountId },
+    TotalIssuance,
+    /// Compares the total issuance with the funds escrowed on chain
+    Reconciliation,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub enum Response {
+    FreeBalance {
+        #[serde(with = "crate::serde_balance")]
+        balance: Balance,
+    },
+    TotalIssuance {
+        #[serde(with = "crate::serde_balance")]
+        total_issuance: Balance,
+    },
+    Reconciliation {
+        #[serde(with = "crate::serde_balance")]
+        total_issuance: Balance,
+        /// The withdrawals not confirmed on chain yet
+        #[serde(with = "crate::serde_balance")]
+        pending_withdrawals: Balance,
+        /// `pallet_escrow::TotalEscrowed` as last reported by the chain
+        #[serde(with = "crate::serde_balance")]
+        escrowed: Balance,
+        #[serde(with = "crate::serde_balance")]
+        unescrowed: Balance,
+        /// The number of the deposits credited
+        deposits: u64,
+        /// Whether `total_issuance + pending_withdrawals == escrowed + unescrowed`
+        reconciled: bool,
+    },
+    Error(String),
+}
@@ -0,0 +1,53 @@
+//! The query types of the native contracts of pRuntime.
+//!
+//! Shared by the contracts in the enclave and the query clients, so both sides always agree on
+//! the JSON encoding. The types are generic over the account, which is encoded as the hex of its
+//! 32 bytes (without `0x`) by both the enclave and the clients.
+
+#![no_std]
+
+extern crate alloc;
+
+#[cfg(feature = "sgx")]
+pub extern crate serde_sgx as serde;
+
+use serde::{Deserialize, Serialize};
+
+pub mod assets;
+pub mod balances;
+pub mod nft;
+pub mod substrate_kitties;
+
+pub type Balance = u128;
+pub type BlockNumber = u32;
+
+/// The reference of a transaction
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub struct TxRef {
+    pub blocknum: BlockNumber,
+    pub index: u64,
+}
+
+/// Serde module to serialize or deserialize Balance as decimal strings
+pub mod serde_balance {
+    use super::Balance;
+    use alloc::string::{String, ToString};
+    use core::str::FromStr;
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(value: &Balance, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = value.to_string();
+        String::serialize(&s, serializer)
+    }
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Balance, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Balance::from_str(&s).map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,30 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/// The token ids are opaque strings chosen by the minter
+pub type TokenId = String;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub enum Request {
+    /// The owner of a token. Only for the owner or the approved account, to keep the holders
+    /// private.
+    OwnerOf { id: TokenId },
+    /// The tokens of the origin
+    OwnedTokens,
+    /// The metadata of a token of the origin
+    Metadata { id: TokenId },
+    /// The account approved to transfer the token. Only for the owner.
+    Approved { id: TokenId },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub enum Response<AccountId> {
+    OwnerOf { owner: AccountId },
+    OwnedTokens { tokens: Vec<TokenId> },
+    Metadata { metadata: Vec<u8> },
+    Approved { spender: Option<AccountId> },
+    Error(String),
+}
@@ -0,0 +1,55 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub struct BlindBox {
+    /// The hex of a random u128
+    pub blind_box_id: String,
+}
+
+/// The errors that the contract could throw for some queries
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub enum Error {
+    NotAuthorized,
+    NotFound,
+}
+
+/// Query requests. The end users can only query the contract states by sending requests.
+/// Queries are not supposed to write to the contract states.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub enum Request {
+    /// Users can require to see the blind boxes list
+    ObserveBox,
+    /// Users can require to see their owned boxes list
+    ObserveOwnedBox,
+    /// Users can require to see the kitties which are not in the boxes
+    ObserveLeftKitties,
+    /// Users can require to know the owner of the specific box(NFT only). Only for the owner or
+    /// the approved account.
+    OwnerOf { blind_box_id: String },
+}
+
+/// Query responses.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "serde")]
+pub enum Response<AccountId> {
+    ObserveBox {
+        blind_box: BTreeMap<String, BlindBox>,
+    },
+    ObserveOwnedBox {
+        owned_box: Vec<String>,
+    },
+    ObserveLeftKitties {
+        kitties: Vec<Vec<u8>>,
+    },
+    OwnerOf {
+        owner: AccountId,
+    },
+    /// Something wrong happened
+    Error(Error),
+}
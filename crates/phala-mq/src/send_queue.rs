@@ -59,6 +59,24 @@ impl MessageSendQueue {
             .sum()
     }
 
+    /// The next sequence and the queued messages of each sender, to be carried over by a secret
+    /// migration.
+    pub fn dump(&self) -> Vec<(SenderId, u64, Vec<SignedMessage>)> {
+        let inner = self.inner.lock();
+        inner
+            .iter()
+            .map(|(k, v)| (k.clone(), v.0, v.1.clone()))
+            .collect()
+    }
+
+    /// Restores the sequences and the messages taken by `dump`.
+    pub fn load(&self, senders: Vec<(SenderId, u64, Vec<SignedMessage>)>) {
+        let mut inner = self.inner.lock();
+        for (sender, sequence, messages) in senders {
+            inner.insert(sender, (sequence, messages));
+        }
+    }
+
     /// Purge the messages which are aready accepted on chain.
     pub fn purge(&self, next_sequence_for: impl Fn(&SenderId) -> u64) {
         let mut inner = self.inner.lock();
@@ -0,0 +1,225 @@
+//! The query types of the native contracts of pRuntime.
+//!
+//! The types are shared with the contracts in the enclave through `phala-contract-queries`,
+//! bound to the [`AccountId`] of the client. Contracts not listed here can be declared with
+//! [`contract_query!`](crate::contract_query).
+
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sp_core::crypto::AccountId32;
+
+crate::contract_query!(
+    /// The contract of the native token
+    pub struct Balances(2, balances::Request, balances::Response)
+);
+crate::contract_query!(
+    /// The contract of the confidential assets
+    pub struct Assets(3, assets::Request, assets::Response)
+);
+crate::contract_query!(
+    /// The contract of the kitty blind boxes
+    pub struct SubstrateKitties(6, substrate_kitties::Request, substrate_kitties::Response)
+);
+crate::contract_query!(
+    /// The contract of the confidential NFTs
+    pub struct Nft(8, nft::Request, nft::Response)
+);
+
+/// An account, encoded as the hex of its 32 bytes (without `0x`)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AccountId(pub AccountId32);
+
+impl From<AccountId32> for AccountId {
+    fn from(account: AccountId32) -> Self {
+        Self(account)
+    }
+}
+
+impl Serialize for AccountId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let raw: &[u8] = self.0.as_ref();
+        serializer.serialize_str(&hex::encode(raw))
+    }
+}
+
+impl<'de> Deserialize<'de> for AccountId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let bytes = hex::decode(&s).map_err(de::Error::custom)?;
+        if bytes.len() != 32 {
+            return Err(de::Error::custom(format!("AccountId hex length wrong: {}", s)));
+        }
+        let mut raw = [0u8; 32];
+        raw.copy_from_slice(&bytes);
+        Ok(Self(raw.into()))
+    }
+}
+
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let raw: &[u8] = self.0.as_ref();
+        write!(f, "{}", hex::encode(raw))
+    }
+}
+
+pub use phala_contract_queries::{serde_balance, Balance, TxRef};
+
+pub mod balances {
+    use super::AccountId;
+
+    pub type Request = phala_contract_queries::balances::Request<AccountId>;
+    pub use phala_contract_queries::balances::Response;
+}
+
+pub mod assets {
+    use super::AccountId;
+
+    pub use phala_contract_queries::assets::{AssetId, AssetsTxKind, DEFAULT_LIMIT};
+
+    pub type AssetMetadata = phala_contract_queries::assets::AssetMetadata<AccountId>;
+    pub type AssetMetadataBalance =
+        phala_contract_queries::assets::AssetMetadataBalance<AccountId>;
+    pub type AssetsTx = phala_contract_queries::assets::AssetsTx<AccountId>;
+    pub type Request = phala_contract_queries::assets::Request<AccountId>;
+    pub type Response = phala_contract_queries::assets::Response<AccountId>;
+}
+
+pub mod substrate_kitties {
+    use super::AccountId;
+
+    pub use phala_contract_queries::substrate_kitties::{BlindBox, Error, Request};
+
+    pub type Response = phala_contract_queries::substrate_kitties::Response<AccountId>;
+}
+
+pub mod nft {
+    use super::AccountId;
+
+    pub use phala_contract_queries::nft::{Request, TokenId};
+
+    pub type Response = phala_contract_queries::nft::Response<AccountId>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_matches_pruntime() {
+        let account = AccountId([1u8; 32].into());
+        let request = serde_json::to_string(&assets::Request::Balance { id: 1, account }).unwrap();
+        assert_eq!(
+            request,
+            format!(r#"{{"Balance":{{"id":1,"account":"{}"}}}}"#, "01".repeat(32))
+        );
+
+        let response: balances::Response =
+            serde_json::from_str(r#"{"FreeBalance":{"balance":"340282366920938463463374607431768211455"}}"#)
+                .unwrap();
+        assert!(matches!(
+            response,
+            balances::Response::FreeBalance { balance } if balance == u128::MAX
+        ));
+
+        assert!(serde_json::from_str::<AccountId>(r#""0102""#).is_err());
+    }
+
+    #[test]
+    fn paging_fields_default() {
+        let request: assets::Request = serde_json::from_str(&format!(
+            r#"{{"History":{{"account":"{}"}}}}"#,
+            "01".repeat(32)
+        ))
+        .unwrap();
+        assert!(matches!(
+            request,
+            assets::Request::History { from: 0, limit, .. } if limit == assets::DEFAULT_LIMIT
+        ));
+
+        let request: assets::Request = serde_json::from_str(r#"{"ListAssets":{}}"#).unwrap();
+        assert!(matches!(
+            request,
+            assets::Request::ListAssets { available_only: false, from: 0, limit }
+                if limit == assets::DEFAULT_LIMIT
+        ));
+    }
+
+    fn round_trip<T>(value: T)
+    where
+        T: Serialize + serde::de::DeserializeOwned + PartialEq + fmt::Debug,
+    {
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(serde_json::from_str::<T>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn query_types_round_trip() {
+        let alice = AccountId([1u8; 32].into());
+        let bob = AccountId([2u8; 32].into());
+        let metadata = assets::AssetMetadata {
+            owner: alice.clone(),
+            total_supply: u128::MAX,
+            symbol: "PHA".to_string(),
+            id: 1,
+            name: "Phala".to_string(),
+            decimals: 12,
+            public_events: true,
+        };
+
+        round_trip(balances::Request::FreeBalance { account: alice.clone() });
+        round_trip(balances::Response::Reconciliation {
+            total_issuance: 10,
+            pending_withdrawals: 1,
+            escrowed: 8,
+            unescrowed: 3,
+            deposits: 2,
+            reconciled: true,
+        });
+        round_trip(assets::Request::Allowance {
+            id: 1,
+            owner: alice.clone(),
+            spender: bob.clone(),
+        });
+        round_trip(assets::Response::ListAssets {
+            assets: vec![assets::AssetMetadataBalance {
+                metadata: metadata.clone(),
+                balance: 1,
+            }],
+            next: Some(2),
+        });
+        round_trip(assets::Response::History {
+            history: vec![assets::AssetsTx {
+                txref: TxRef { blocknum: 1, index: 2 },
+                asset_id: 1,
+                kind: assets::AssetsTxKind::Mint,
+                from: alice.clone(),
+                to: bob.clone(),
+                amount: 3,
+            }],
+            next: None,
+        });
+        round_trip(substrate_kitties::Request::OwnerOf {
+            blind_box_id: "1".to_string(),
+        });
+        round_trip(substrate_kitties::Response::Error(
+            substrate_kitties::Error::NotAuthorized,
+        ));
+        round_trip(nft::Request::Metadata { id: "a".to_string() });
+        round_trip(nft::Response::Approved { spender: Some(bob) });
+
+        // The enclave binds the same types to its own account type, which is also encoded as the
+        // plain hex of the account
+        let json = serde_json::to_string(&assets::Response::Metadata {
+            metadata: vec![metadata],
+        })
+        .unwrap();
+        let decoded: phala_contract_queries::assets::Response<String> =
+            serde_json::from_str(&json).unwrap();
+        assert!(matches!(
+            decoded,
+            phala_contract_queries::assets::Response::Metadata { metadata }
+                if metadata[0].owner == "01".repeat(32)
+        ));
+    }
+}
@@ -0,0 +1,349 @@
+//! A typed client of the contract queries served by pRuntime.
+//!
+//! A query is the JSON encoded [`Query`] of a contract request, optionally encrypted to the ECDH
+//! key of the worker and signed by the querier. [`QueryBuilder`] builds the [`SignedQuery`] to post
+//! to the `query` API of pRuntime, and [`PreparedQuery::decode_reply`] decodes the typed response
+//! from the reply.
+//!
+//! ```ignore
+//! let prepared = QueryBuilder::new()
+//!     .signed_by(&signer)
+//!     .for_worker(&worker_pubkey, blocknum + 10)
+//!     .encrypted_to(&worker_ecdh_pubkey)
+//!     .build::<contracts::Balances>(balances::Request::TotalIssuance)?;
+//! // Post `prepared.query` to pRuntime, and then
+//! let response = prepared.decode_reply(reply)?;
+//! ```
+
+use std::marker::PhantomData;
+
+use anyhow::{anyhow, Context, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, ECDH_P256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{de::DeserializeOwned, Serialize};
+use sp_core::{crypto::Pair as _, ecdsa, ed25519, sr25519};
+
+pub use phala_types::query::{AeadCipher, Origin, Payload, Query, SignatureType, SignedQuery};
+
+pub mod contracts;
+
+/// A contract of pRuntime with the types of its queries
+pub trait ContractQuery {
+    const CONTRACT_ID: u32;
+    type Request: Serialize;
+    type Response: DeserializeOwned;
+}
+
+/// Declares a contract with the types of its queries.
+///
+/// ```ignore
+/// contract_query!(pub struct Diem(5, diem::Request, diem::Response));
+/// ```
+#[macro_export]
+macro_rules! contract_query {
+    ($(#[$attr:meta])* $vis:vis struct $name:ident($id:expr, $req:ty, $resp:ty)) => {
+        $(#[$attr])*
+        $vis struct $name;
+
+        impl $crate::ContractQuery for $name {
+            const CONTRACT_ID: u32 = $id;
+            type Request = $req;
+            type Response = $resp;
+        }
+    };
+}
+
+/// The key pair signing the queries
+pub enum Signer {
+    Sr25519(sr25519::Pair),
+    Ed25519(ed25519::Pair),
+    Ecdsa(ecdsa::Pair),
+}
+
+impl Signer {
+    fn sign(&self, message: &[u8]) -> Origin {
+        let (public, signature, sig_type) = match self {
+            Signer::Sr25519(pair) => (
+                pair.public().as_ref().to_vec(),
+                pair.sign(message).as_ref().to_vec(),
+                SignatureType::Sr25519,
+            ),
+            Signer::Ed25519(pair) => (
+                pair.public().as_ref().to_vec(),
+                pair.sign(message).as_ref().to_vec(),
+                SignatureType::Ed25519,
+            ),
+            Signer::Ecdsa(pair) => (
+                pair.public().as_ref().to_vec(),
+                pair.sign(message).as_ref().to_vec(),
+                SignatureType::Ecdsa,
+            ),
+        };
+        Origin {
+            origin: hex::encode(public),
+            sig_b64: base64::encode(signature),
+            sig_type,
+        }
+    }
+}
+
+/// Builds the queries to a contract
+#[derive(Default)]
+pub struct QueryBuilder<'a> {
+    signer: Option<&'a Signer>,
+    worker_ecdh_pubkey: Option<Vec<u8>>,
+    worker_pubkey: Option<String>,
+    expiry: Option<u32>,
+    instance_id: Option<String>,
+}
+
+impl<'a> QueryBuilder<'a> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Signs the queries. The contracts answer the requests about the querier's account only when
+    /// the query is signed.
+    pub fn signed_by(mut self, signer: &'a Signer) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Binds the queries to the worker with the hex identity public key `worker_pubkey` (the
+    /// `public_key` returned by the `get_info` API of pRuntime), valid until the block `expiry`.
+    ///
+    /// Required by the signed queries, so that they can't be replayed. The expiry must be no later
+    /// than 100 blocks after the `blocknum` reported by the worker.
+    pub fn for_worker(mut self, worker_pubkey: &str, expiry: u32) -> Self {
+        self.worker_pubkey = Some(worker_pubkey.trim_start_matches("0x").to_string());
+        self.expiry = Some(expiry);
+        self
+    }
+
+    /// Queries the contract instance `instance_id` (hex) created by
+    /// `pallet_registry::instantiate_contract`, instead of the built-in contract
+    pub fn instance(mut self, instance_id: &str) -> Self {
+        self.instance_id = Some(instance_id.trim_start_matches("0x").to_string());
+        self
+    }
+
+    /// Encrypts the queries, and their replies, with the ECDH public key of the worker (the
+    /// `ecdh_public_key` returned by the `get_info` API of pRuntime).
+    pub fn encrypted_to(mut self, worker_ecdh_pubkey: &[u8]) -> Self {
+        self.worker_ecdh_pubkey = Some(worker_ecdh_pubkey.to_vec());
+        self
+    }
+
+    pub fn build<C: ContractQuery>(&self, request: C::Request) -> Result<PreparedQuery<C>> {
+        if self.signer.is_some() && self.worker_pubkey.is_none() {
+            return Err(anyhow!(
+                "Signed queries must be bound to a worker, see `QueryBuilder::for_worker`"
+            ));
+        }
+        let rng = SystemRandom::new();
+        let mut nonce = [0u8; 4];
+        rng.fill(&mut nonce)
+            .map_err(|_| anyhow!("Failed to generate the nonce"))?;
+        let query = Query {
+            contract_id: C::CONTRACT_ID,
+            instance_id: self.instance_id.clone(),
+            nonce: u32::from_le_bytes(nonce),
+            worker_pubkey: self.worker_pubkey.clone(),
+            expiry: self.expiry,
+            request,
+        };
+        let query_json = serde_json::to_vec(&query).context("Failed to encode the query")?;
+
+        let (payload, secret) = match &self.worker_ecdh_pubkey {
+            Some(worker_pubkey) => {
+                let (secret, pubkey) = agree_secret(&rng, worker_pubkey)?;
+                let cipher = encrypt(&rng, &secret, query_json, &pubkey)?;
+                (Payload::Cipher(cipher), Some(secret))
+            }
+            None => {
+                let json = String::from_utf8(query_json).expect("serde_json outputs UTF-8; qed.");
+                (Payload::Plain(json), None)
+            }
+        };
+        let query_payload = serde_json::to_string(&payload).context("Failed to encode the payload")?;
+        let origin = self.signer.map(|signer| signer.sign(query_payload.as_bytes()));
+
+        Ok(PreparedQuery {
+            query: SignedQuery {
+                query_payload,
+                origin,
+            },
+            secret,
+            _contract: PhantomData,
+        })
+    }
+}
+
+/// A query built for the contract `C`
+pub struct PreparedQuery<C: ContractQuery> {
+    /// The request to post to the `query` API of pRuntime
+    pub query: SignedQuery,
+    /// The secret shared with the worker to decrypt the reply
+    secret: Option<Vec<u8>>,
+    _contract: PhantomData<C>,
+}
+
+impl<C: ContractQuery> PreparedQuery<C> {
+    /// Decrypts (if the query is encrypted) and decodes the reply of pRuntime
+    pub fn decode_reply(&self, reply: Payload) -> Result<C::Response> {
+        let json = match (reply, &self.secret) {
+            (Payload::Plain(json), None) => json.into_bytes(),
+            (Payload::Cipher(cipher), Some(secret)) => decrypt(secret, &cipher)?,
+            (Payload::Plain(_), Some(_)) => return Err(anyhow!("The reply is not encrypted")),
+            (Payload::Cipher(_), None) => return Err(anyhow!("Unexpected encrypted reply")),
+        };
+        serde_json::from_slice(&json).context("Failed to decode the response")
+    }
+}
+
+/// Agrees a secret with the worker by a new ECDH key, returning the secret and the public key
+fn agree_secret(rng: &SystemRandom, worker_pubkey: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let key = EphemeralPrivateKey::generate(&ECDH_P256, rng)
+        .map_err(|_| anyhow!("Failed to generate the ECDH key"))?;
+    let pubkey = key
+        .compute_public_key()
+        .map_err(|_| anyhow!("Failed to compute the ECDH public key"))?
+        .as_ref()
+        .to_vec();
+    let worker_pubkey = UnparsedPublicKey::new(&ECDH_P256, worker_pubkey);
+    // pRuntime uses the key material as the AES key, without a KDF
+    let secret = agreement::agree_ephemeral(key, &worker_pubkey, anyhow!("Bad worker key"), |km| {
+        Ok(km.to_vec())
+    })?;
+    Ok((secret, pubkey))
+}
+
+fn aead_key(secret: &[u8]) -> Result<LessSafeKey> {
+    let key = UnboundKey::new(&AES_256_GCM, secret).map_err(|_| anyhow!("Bad AES key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn encrypt(
+    rng: &SystemRandom,
+    secret: &[u8],
+    mut data: Vec<u8>,
+    pubkey: &[u8],
+) -> Result<AeadCipher> {
+    let mut iv = [0u8; NONCE_LEN];
+    rng.fill(&mut iv)
+        .map_err(|_| anyhow!("Failed to generate the IV"))?;
+    aead_key(secret)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(iv), Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Failed to encrypt the query"))?;
+    Ok(AeadCipher {
+        iv_b64: base64::encode(&iv),
+        cipher_b64: base64::encode(&data),
+        pubkey_b64: base64::encode(pubkey),
+    })
+}
+
+fn decrypt(secret: &[u8], cipher: &AeadCipher) -> Result<Vec<u8>> {
+    let iv = base64::decode(&cipher.iv_b64).context("Bad iv_b64")?;
+    let mut data = base64::decode(&cipher.cipher_b64).context("Bad cipher_b64")?;
+    let nonce = Nonce::try_assume_unique_for_key(&iv).map_err(|_| anyhow!("Bad IV length"))?;
+    let plain = aead_key(secret)?
+        .open_in_place(nonce, Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Failed to decrypt the reply"))?;
+    Ok(plain.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::{balances, Balances};
+    use sp_core::Pair;
+
+    /// Plays the worker: decrypts the query, and encrypts the reply with the same secret
+    fn worker_reply(
+        worker_key: EphemeralPrivateKey,
+        worker_pubkey: &[u8],
+        query: &SignedQuery,
+        reply: &str,
+    ) -> (Query<balances::Request>, Payload) {
+        let cipher = match serde_json::from_str(&query.query_payload).unwrap() {
+            Payload::Cipher(cipher) => cipher,
+            Payload::Plain(_) => panic!("The query is not encrypted"),
+        };
+        let client_pubkey = base64::decode(&cipher.pubkey_b64).unwrap();
+        let secret = agreement::agree_ephemeral(
+            worker_key,
+            &UnparsedPublicKey::new(&ECDH_P256, client_pubkey),
+            (),
+            |km| Ok(km.to_vec()),
+        )
+        .unwrap();
+        let query = serde_json::from_slice(&decrypt(&secret, &cipher).unwrap()).unwrap();
+        let rng = SystemRandom::new();
+        let reply = encrypt(&rng, &secret, reply.as_bytes().to_vec(), worker_pubkey).unwrap();
+        (query, Payload::Cipher(reply))
+    }
+
+    #[test]
+    fn encrypted_query_round_trip() {
+        let rng = SystemRandom::new();
+        let worker_key = EphemeralPrivateKey::generate(&ECDH_P256, &rng).unwrap();
+        let worker_pubkey = worker_key.compute_public_key().unwrap().as_ref().to_vec();
+
+        let prepared = QueryBuilder::new()
+            .encrypted_to(&worker_pubkey)
+            .build::<Balances>(balances::Request::TotalIssuance)
+            .unwrap();
+        let (query, reply) = worker_reply(
+            worker_key,
+            &worker_pubkey,
+            &prepared.query,
+            r#"{"TotalIssuance":{"total_issuance":"1000000000000000000000"}}"#,
+        );
+        assert_eq!(query.contract_id, 2);
+        assert!(matches!(query.request, balances::Request::TotalIssuance));
+        match prepared.decode_reply(reply).unwrap() {
+            balances::Response::TotalIssuance { total_issuance } => {
+                assert_eq!(total_issuance, 1_000_000_000_000_000_000_000)
+            }
+            other => panic!("Unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn signed_query() {
+        let pair = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let signer = Signer::Sr25519(pair.clone());
+        // Unbound signed queries are replayable
+        assert!(QueryBuilder::new()
+            .signed_by(&signer)
+            .build::<Balances>(balances::Request::TotalIssuance)
+            .is_err());
+
+        let prepared = QueryBuilder::new()
+            .signed_by(&signer)
+            .for_worker("0x02aabb", 42)
+            .build::<Balances>(balances::Request::TotalIssuance)
+            .unwrap();
+        let query: Query<balances::Request> =
+            match serde_json::from_str(&prepared.query.query_payload).unwrap() {
+                Payload::Plain(json) => serde_json::from_str(&json).unwrap(),
+                Payload::Cipher(_) => panic!("The query is encrypted"),
+            };
+        assert_eq!(query.worker_pubkey.as_deref(), Some("02aabb"));
+        assert_eq!(query.expiry, Some(42));
+        let origin = prepared.query.origin.as_ref().unwrap();
+        assert_eq!(origin.origin, hex::encode(pair.public()));
+        assert_eq!(origin.sig_type, SignatureType::Sr25519);
+        let signature = base64::decode(&origin.sig_b64).unwrap();
+        assert!(sr25519::Pair::verify_weak(
+            &signature,
+            prepared.query.query_payload.as_bytes(),
+            pair.public()
+        ));
+        // Plain queries expect plain replies
+        assert!(prepared
+            .decode_reply(Payload::Plain(r#"{"Error":"bad"}"#.to_string()))
+            .is_ok());
+    }
+}
@@ -0,0 +1,189 @@
+//! Chain bridge pallet benchmarking.
+
+#![cfg(feature = "runtime-benchmarks")]
+
+use super::*;
+
+use frame_benchmarking::{account, benchmarks, impl_benchmark_test_suite, whitelisted_caller};
+use frame_support::traits::OnInitialize;
+use frame_system::RawOrigin;
+
+use crate::Module as Bridge;
+
+const SEED: u32 = 0;
+
+/// A whitelisted chain other than this one
+fn src_id<T: Config>() -> ChainId {
+	T::ChainId::get().wrapping_add(1)
+}
+
+fn make_proposal<T: Config>(i: u32) -> T::Proposal
+where
+	T::Proposal: From<frame_system::Call<T>>,
+{
+	frame_system::Call::<T>::remark(i.encode()).into()
+}
+
+/// Whitelists the source chain and a resource, and registers the caller together with another
+/// relayer. The threshold is set to two, so the caller's vote completes the proposals voted by
+/// the other relayer.
+fn setup_bridge<T: Config>(caller: &T::AccountId) -> Result<ResourceId, &'static str> {
+	let r_id = derive_resource_id(src_id::<T>(), b"remark");
+	Bridge::<T>::whitelist(src_id::<T>())?;
+	Bridge::<T>::register_resource(r_id, b"System.remark".to_vec())?;
+	Bridge::<T>::register_relayer(caller.clone())?;
+	Bridge::<T>::register_relayer(account("relayer", 0, SEED))?;
+	Bridge::<T>::set_relayer_threshold(2)?;
+	// Pays the relayer fee to both voters
+	let fee = T::Currency::minimum_balance().max(1u32.into());
+	<RelayerFee<T>>::put(fee);
+	T::Currency::make_free_balance_be(&Bridge::<T>::fee_pot_id(), fee * 1_000_000u32.into());
+	Ok(r_id)
+}
+
+/// Votes for a proposal by the other relayer
+fn prevote<T: Config>(r_id: ResourceId, nonce: DepositNonce, prop: T::Proposal) -> DispatchResult {
+	let relayer: T::AccountId = account("relayer", 0, SEED);
+	Bridge::<T>::vote(relayer, nonce, src_id::<T>(), r_id, Box::new(prop), true)?;
+	Ok(())
+}
+
+benchmarks! {
+	where_clause { where T::Proposal: From<frame_system::Call<T>> }
+
+	set_threshold {
+	}: _(RawOrigin::Root, 3)
+	verify {
+		assert_eq!(Bridge::<T>::relayer_threshold(), 3);
+	}
+
+	set_relayer_fee {
+		let fee = T::Currency::minimum_balance();
+	}: _(RawOrigin::Root, fee)
+	verify {
+		assert_eq!(Bridge::<T>::relayer_fee(), fee);
+	}
+
+	set_resource {
+		let r_id = derive_resource_id(src_id::<T>(), b"remark");
+	}: _(RawOrigin::Root, r_id, b"System.remark".to_vec())
+	verify {
+		assert!(Bridge::<T>::resource_exists(r_id));
+	}
+
+	remove_resource {
+		let r_id = derive_resource_id(src_id::<T>(), b"remark");
+		Bridge::<T>::register_resource(r_id, b"System.remark".to_vec())?;
+	}: _(RawOrigin::Root, r_id)
+	verify {
+		assert!(!Bridge::<T>::resource_exists(r_id));
+	}
+
+	whitelist_chain {
+	}: _(RawOrigin::Root, src_id::<T>())
+	verify {
+		assert!(Bridge::<T>::chain_whitelisted(src_id::<T>()));
+	}
+
+	add_relayer {
+		let relayer: T::AccountId = account("relayer", 0, SEED);
+	}: _(RawOrigin::Root, relayer.clone())
+	verify {
+		assert!(Bridge::<T>::is_relayer(&relayer));
+	}
+
+	remove_relayer {
+		let relayer: T::AccountId = account("relayer", 0, SEED);
+		Bridge::<T>::register_relayer(relayer.clone())?;
+	}: _(RawOrigin::Root, relayer.clone())
+	verify {
+		assert!(!Bridge::<T>::is_relayer(&relayer));
+	}
+
+	// The worst case: the vote approves and executes the proposal, and pays the relayer fees
+	acknowledge_proposal {
+		let caller: T::AccountId = whitelisted_caller();
+		let r_id = setup_bridge::<T>(&caller)?;
+		let prop = make_proposal::<T>(0);
+		prevote::<T>(r_id, 1, prop.clone())?;
+	}: _(RawOrigin::Signed(caller), 1, src_id::<T>(), r_id, Box::new(prop))
+	verify {
+		assert!(Bridge::<T>::executed_deposits(src_id::<T>(), 1));
+	}
+
+	acknowledge_proposals {
+		let p in 1 .. T::MaxProposalsPerBatch::get();
+		let caller: T::AccountId = whitelisted_caller();
+		let r_id = setup_bridge::<T>(&caller)?;
+		let mut proposals = Vec::new();
+		for i in 0..p {
+			let nonce = i as DepositNonce + 1;
+			let prop = make_proposal::<T>(i);
+			prevote::<T>(r_id, nonce, prop.clone())?;
+			proposals.push((nonce, src_id::<T>(), r_id, Box::new(prop)));
+		}
+	}: _(RawOrigin::Signed(caller), proposals)
+	verify {
+		assert!(Bridge::<T>::executed_deposits(src_id::<T>(), p as DepositNonce));
+	}
+
+	// The worst case: the vote rejects the proposal
+	reject_proposal {
+		let caller: T::AccountId = whitelisted_caller();
+		let r_id = setup_bridge::<T>(&caller)?;
+		let prop = make_proposal::<T>(0);
+		let relayer: T::AccountId = account("relayer", 0, SEED);
+		Bridge::<T>::vote(relayer, 1, src_id::<T>(), r_id, Box::new(prop.clone()), false)?;
+	}: _(RawOrigin::Signed(caller), 1, src_id::<T>(), r_id, Box::new(prop.clone()))
+	verify {
+		let key = (1, Bridge::<T>::proposal_hash(&prop));
+		let votes = Bridge::<T>::votes(src_id::<T>(), key).ok_or("proposal not found")?;
+		assert_eq!(votes.status, ProposalStatus::Rejected);
+	}
+
+	// The worst case: the lowered threshold approves and executes the proposal
+	eval_vote_state {
+		let caller: T::AccountId = whitelisted_caller();
+		let r_id = setup_bridge::<T>(&caller)?;
+		let prop = make_proposal::<T>(0);
+		prevote::<T>(r_id, 1, prop.clone())?;
+		Bridge::<T>::set_relayer_threshold(1)?;
+	}: _(RawOrigin::Signed(caller), 1, src_id::<T>(), Box::new(prop))
+	verify {
+		assert!(Bridge::<T>::executed_deposits(src_id::<T>(), 1));
+	}
+
+	// The worst case: the call succeeds and the relayer fees are paid
+	retry_proposal {
+		let caller: T::AccountId = whitelisted_caller();
+		let relayer: T::AccountId = account("relayer", 0, SEED);
+		setup_bridge::<T>(&caller)?;
+		let prop = make_proposal::<T>(0);
+		<FailedProposals<T>>::insert(
+			src_id::<T>(),
+			1,
+			(Bridge::<T>::proposal_hash(&prop), vec![caller.clone(), relayer]),
+		);
+	}: _(RawOrigin::Signed(caller), 1, src_id::<T>(), Box::new(prop))
+	verify {
+		assert!(Bridge::<T>::executed_deposits(src_id::<T>(), 1));
+		assert!(Bridge::<T>::failed_proposals(src_id::<T>(), 1).is_none());
+	}
+
+	prune_proposals {
+		let p in 0 .. T::MaxProposalsPerBatch::get();
+		let caller: T::AccountId = whitelisted_caller();
+		let r_id = setup_bridge::<T>(&caller)?;
+		for i in 0..p {
+			prevote::<T>(r_id, i as DepositNonce + 1, make_proposal::<T>(i))?;
+		}
+		let expiry = frame_system::Pallet::<T>::block_number() + T::ProposalLifetime::get();
+	}: {
+		Bridge::<T>::on_initialize(expiry);
+	}
+	verify {
+		assert!(Bridge::<T>::proposal_expiries(expiry).is_empty());
+	}
+}
+
+impl_benchmark_test_suite!(Bridge, crate::mock::new_test_ext(), crate::mock::Test,);
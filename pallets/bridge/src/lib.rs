@@ -3,35 +3,48 @@
 
 use frame_support::{
 	decl_error, decl_event, decl_module, decl_storage,
-	dispatch::DispatchResult,
+	dispatch::{DispatchError, DispatchResult, DispatchResultWithPostInfo, PostDispatchInfo},
 	ensure,
-	traits::{EnsureOrigin, Get},
-	weights::{GetDispatchInfo, Pays},
+	storage::{with_transaction, TransactionOutcome},
+	traits::{Currency, EnsureOrigin, ExistenceRequirement::KeepAlive, Get},
+	weights::{extract_actual_weight, DispatchClass, GetDispatchInfo, Pays, Weight},
 	PalletId, Parameter,
 };
 
 use frame_system::{self as system, ensure_root, ensure_signed};
 use sp_core::U256;
-use sp_runtime::traits::{AccountIdConversion, Dispatchable};
+use sp_runtime::traits::{AccountIdConversion, Dispatchable, Hash, Zero};
 use sp_runtime::RuntimeDebug;
 use sp_std::prelude::*;
 
 use codec::{Decode, Encode, EncodeLike};
 
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
 #[cfg(test)]
 mod mock;
 #[cfg(test)]
 mod tests;
 
 pub mod hashing;
+pub mod weights;
+
+pub use weights::WeightInfo;
 
 const DEFAULT_RELAYER_THRESHOLD: u32 = 1;
 const MODULE_ID: PalletId = PalletId(*b"phala/bg");
+const FEE_POT_ID: PalletId = PalletId(*b"phala/bf");
+
+/// The storage version of the pallet. Version 1 keys the votes by the proposal hash.
+const STORAGE_VERSION: u32 = 1;
 
 pub type ChainId = u8;
 pub type DepositNonce = u64;
 pub type ResourceId = [u8; 32];
 
+type BalanceOf<T> =
+	<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
 /// Helper function to concatenate a chain ID and some bytes to produce a resource ID.
 /// The common format is (31 bytes unique ID + 1 byte chain ID).
 pub fn derive_resource_id(chain: u8, id: &[u8]) -> ResourceId {
@@ -112,10 +125,19 @@ pub trait Config: system::Config {
 	type ChainId: Get<ChainId>;
 
 	type ProposalLifetime: Get<Self::BlockNumber>;
+	/// The currency the relayer fees are paid in, from the relayer fee pot
+	type Currency: Currency<Self::AccountId>;
+	/// The maximum number of proposals a relayer can vote on in a single batch
+	type MaxProposalsPerBatch: Get<u32>;
+	/// Weight information for extrinsics in this pallet.
+	type WeightInfo: WeightInfo;
 }
 
 decl_event! {
-	pub enum Event<T> where <T as frame_system::Config>::AccountId {
+	pub enum Event<T> where
+		<T as frame_system::Config>::AccountId,
+		Balance = BalanceOf<T>,
+	{
 		/// Vote threshold has changed (new_threshold)
 		RelayerThresholdChanged(u32),
 		/// Chain now available for transfers (chain_id)
@@ -134,6 +156,8 @@ decl_event! {
 		VoteFor(ChainId, DepositNonce, AccountId),
 		/// Vot submitted against proposal
 		VoteAgainst(ChainId, DepositNonce, AccountId),
+		/// A vote in a batch was rejected (src_id, nonce, error)
+		VoteFailed(ChainId, DepositNonce, DispatchError),
 		/// Voting successful for a proposal
 		ProposalApproved(ChainId, DepositNonce),
 		/// Voting rejected a proposal
@@ -142,6 +166,10 @@ decl_event! {
 		ProposalSucceeded(ChainId, DepositNonce),
 		/// Execution of call failed
 		ProposalFailed(ChainId, DepositNonce),
+		/// Relayer fee has changed (new_fee)
+		RelayerFeeChanged(Balance),
+		/// Relayer fee paid for voting in favour of an executed proposal (relayer, fee)
+		RelayerFeePaid(AccountId, Balance),
 	}
 }
 
@@ -177,6 +205,8 @@ decl_error! {
 		ProposalAlreadyComplete,
 		/// Lifetime of proposal has been exceeded
 		ProposalExpired,
+		/// Too many proposals in a batch
+		TooManyProposals,
 	}
 }
 
@@ -194,15 +224,41 @@ decl_storage! {
 		/// Number of relayers in set
 		pub RelayerCount get(fn relayer_count): u32;
 
+		/// Fee paid from the relayer fee pot to each relayer voting for an executed proposal
+		pub RelayerFee get(fn relayer_fee): BalanceOf<T>;
+
 		/// All known proposals.
-		/// The key is the hash of the call and the deposit ID, to ensure it's unique.
+		/// The key is the deposit ID and the hash of the call, to ensure it's unique.
 		pub Votes get(fn votes):
-			double_map hasher(opaque_blake2_256) ChainId, hasher(opaque_blake2_256) (DepositNonce, T::Proposal)
+			double_map hasher(twox_64_concat) ChainId, hasher(blake2_128_concat) (DepositNonce, T::Hash)
 			=> Option<ProposalVotes<T::AccountId, T::BlockNumber>>;
 
+		/// The proposals to prune at the beginning of a block, indexed by their expiry
+		ProposalExpiries get(fn proposal_expiries):
+			map hasher(twox_64_concat) T::BlockNumber => Vec<(ChainId, DepositNonce, T::Hash)>;
+
+		/// Deposits whose proposal has been approved. They can never be proposed again, even
+		/// after the votes are pruned.
+		pub ExecutedDeposits get(fn executed_deposits):
+			double_map hasher(twox_64_concat) ChainId, hasher(twox_64_concat) DepositNonce => bool;
+
+		/// Approved proposals whose call failed, with the relayers to pay once it succeeds. They
+		/// can be retried by `retry_proposal` until a proposal of the deposit is executed.
+		pub FailedProposals get(fn failed_proposals):
+			double_map hasher(twox_64_concat) ChainId, hasher(twox_64_concat) DepositNonce
+			=> Option<(T::Hash, Vec<T::AccountId>)>;
+
+		/// The proposals approved before storage version 1, by their old opaque keys
+		/// `(blake2_256(src_id), blake2_256((nonce, proposal)))`. They can't be proposed again.
+		LegacyApprovedProposals get(fn legacy_approved_proposals):
+			double_map hasher(identity) [u8; 32], hasher(identity) [u8; 32] => bool;
+
 		/// Utilized by the bridge software to map resource IDs to actual methods
 		pub Resources get(fn resources):
-			map hasher(opaque_blake2_256) ResourceId => Option<Vec<u8>>
+			map hasher(opaque_blake2_256) ResourceId => Option<Vec<u8>>;
+
+		/// The storage version of the pallet
+		PalletStorageVersion: u32;
 	}
 }
 
@@ -213,9 +269,48 @@ decl_module! {
 		const ChainIdentity: ChainId = T::ChainId::get();
 		const ProposalLifetime: T::BlockNumber = T::ProposalLifetime::get();
 		const BridgeAccountId: T::AccountId = MODULE_ID.into_account();
+		const RelayerFeePotId: T::AccountId = FEE_POT_ID.into_account();
+		const MaxProposalsPerBatch: u32 = T::MaxProposalsPerBatch::get();
 
 		fn deposit_event() = default;
 
+		fn on_runtime_upgrade() -> Weight {
+			if PalletStorageVersion::get() >= STORAGE_VERSION {
+				return 0;
+			}
+			// The votes were keyed by the opaque hashes of the chain id and the full proposal, so
+			// the deposit nonces can't be recovered to fill `ExecutedDeposits`. The approved
+			// proposals are kept by their hashed keys instead, which `commit_vote` checks to
+			// stop them from being replayed. The relayers simply vote again on the pending ones.
+			let legacy_votes = frame_support::migration::storage_iter::<
+				ProposalVotes<T::AccountId, T::BlockNumber>,
+			>(b"ChainBridge", b"Votes");
+			let approved: Vec<_> = legacy_votes
+				.filter(|(key, votes)| key.len() == 64 && votes.status == ProposalStatus::Approved)
+				.map(|(key, _)| key)
+				.collect();
+			for key in approved {
+				let mut chain_key = [0u8; 32];
+				let mut proposal_key = [0u8; 32];
+				chain_key.copy_from_slice(&key[..32]);
+				proposal_key.copy_from_slice(&key[32..]);
+				LegacyApprovedProposals::insert(chain_key, proposal_key, true);
+			}
+			<Votes<T>>::remove_all(None);
+			PalletStorageVersion::put(STORAGE_VERSION);
+			T::BlockWeights::get().max_block
+		}
+
+		/// Prunes the votes of the proposals expiring in this block, complete or not.
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			let expired = <ProposalExpiries<T>>::take(now);
+			let n = expired.len() as u32;
+			for (src_id, nonce, hash) in expired {
+				<Votes<T>>::remove(src_id, (nonce, hash));
+			}
+			T::WeightInfo::prune_proposals(n)
+		}
+
 		/// Sets the vote threshold for proposals.
 		///
 		/// This threshold is used to determine how many votes are required
@@ -224,18 +319,31 @@ decl_module! {
 		/// # <weight>
 		/// - O(1) lookup and insert
 		/// # </weight>
-		#[weight = 195_000_000]
+		#[weight = T::WeightInfo::set_threshold()]
 		pub fn set_threshold(origin, threshold: u32) -> DispatchResult {
 			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
 			Self::set_relayer_threshold(threshold)
 		}
 
+		/// Sets the fee paid to each relayer voting for an approved proposal.
+		///
+		/// # <weight>
+		/// - O(1) write
+		/// # </weight>
+		#[weight = T::WeightInfo::set_relayer_fee()]
+		pub fn set_relayer_fee(origin, fee: BalanceOf<T>) -> DispatchResult {
+			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
+			<RelayerFee<T>>::put(fee);
+			Self::deposit_event(RawEvent::RelayerFeeChanged(fee));
+			Ok(())
+		}
+
 		/// Stores a method name on chain under an associated resource ID.
 		///
 		/// # <weight>
 		/// - O(1) write
 		/// # </weight>
-		#[weight = 195_000_000]
+		#[weight = T::WeightInfo::set_resource()]
 		pub fn set_resource(origin, id: ResourceId, method: Vec<u8>) -> DispatchResult {
 			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
 			Self::register_resource(id, method)
@@ -249,7 +357,7 @@ decl_module! {
 		/// # <weight>
 		/// - O(1) removal
 		/// # </weight>
-		#[weight = 195_000_000]
+		#[weight = T::WeightInfo::remove_resource()]
 		pub fn remove_resource(origin, id: ResourceId) -> DispatchResult {
 			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
 			Self::unregister_resource(id)
@@ -260,7 +368,7 @@ decl_module! {
 		/// # <weight>
 		/// - O(1) lookup and insert
 		/// # </weight>
-		#[weight = 195_000_000]
+		#[weight = T::WeightInfo::whitelist_chain()]
 		pub fn whitelist_chain(origin, id: ChainId) -> DispatchResult {
 			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
 			Self::whitelist(id)
@@ -271,7 +379,7 @@ decl_module! {
 		/// # <weight>
 		/// - O(1) lookup and insert
 		/// # </weight>
-		#[weight = 195_000_000]
+		#[weight = T::WeightInfo::add_relayer()]
 		pub fn add_relayer(origin, v: T::AccountId) -> DispatchResult {
 			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
 			Self::register_relayer(v)
@@ -282,7 +390,7 @@ decl_module! {
 		/// # <weight>
 		/// - O(1) lookup and removal
 		/// # </weight>
-		#[weight = 195_000_000]
+		#[weight = T::WeightInfo::remove_relayer()]
 		pub fn remove_relayer(origin, v: T::AccountId) -> DispatchResult {
 			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
 			Self::unregister_relayer(v)
@@ -293,32 +401,121 @@ decl_module! {
 		/// If a proposal with the given nonce and source chain ID does not already exist, it will
 		/// be created with an initial vote in favour from the caller.
 		///
+		/// The transaction fee is refunded once the vote is counted.
+		///
 		/// # <weight>
-		/// - weight of proposed call, regardless of whether execution is performed
+		/// - weight of proposed call, refunded if the call isn't executed
 		/// # </weight>
-		#[weight = (call.get_dispatch_info().weight + 195_000_000, call.get_dispatch_info().class, Pays::Yes)]
-		pub fn acknowledge_proposal(origin, nonce: DepositNonce, src_id: ChainId, r_id: ResourceId, call: Box<<T as Config>::Proposal>) -> DispatchResult {
+		#[weight = (
+			T::WeightInfo::acknowledge_proposal().saturating_add(call.get_dispatch_info().weight),
+			call.get_dispatch_info().class,
+			Pays::Yes
+		)]
+		pub fn acknowledge_proposal(origin, nonce: DepositNonce, src_id: ChainId, r_id: ResourceId, call: Box<<T as Config>::Proposal>) -> DispatchResultWithPostInfo {
 			let who = ensure_signed(origin)?;
 			ensure!(Self::is_relayer(&who), Error::<T>::MustBeRelayer);
-			ensure!(Self::chain_whitelisted(src_id), Error::<T>::ChainNotWhitelisted);
-			ensure!(Self::resource_exists(r_id), Error::<T>::ResourceDoesNotExist);
 
-			Self::vote_for(who, nonce, src_id, call)
+			let call_weight = Self::vote(who, nonce, src_id, r_id, call, true)?;
+			Ok(PostDispatchInfo {
+				actual_weight: Some(T::WeightInfo::acknowledge_proposal().saturating_add(call_weight)),
+				pays_fee: Pays::No,
+			})
+		}
+
+		/// Commits votes in favour of many proposals at once.
+		///
+		/// The proposals are voted independently. A rejected vote doesn't revert the others, and
+		/// is reported as a `VoteFailed` event instead. The transaction fee is refunded only if all
+		/// the votes are counted.
+		///
+		/// # <weight>
+		/// - O(P) where P is the number of proposals
+		/// - weight of the proposed calls, refunded for the calls not executed
+		/// # </weight>
+		#[weight = (
+			T::WeightInfo::acknowledge_proposals(proposals.len() as u32)
+				.saturating_add(proposals.iter().fold(0 as Weight, |acc, (_, _, _, call)| {
+					acc.saturating_add(call.get_dispatch_info().weight)
+				})),
+			DispatchClass::Normal,
+			Pays::Yes
+		)]
+		pub fn acknowledge_proposals(origin, proposals: Vec<(DepositNonce, ChainId, ResourceId, Box<<T as Config>::Proposal>)>) -> DispatchResultWithPostInfo {
+			let who = ensure_signed(origin)?;
+			ensure!(Self::is_relayer(&who), Error::<T>::MustBeRelayer);
+			ensure!(
+				proposals.len() <= T::MaxProposalsPerBatch::get() as usize,
+				Error::<T>::TooManyProposals
+			);
+
+			let mut actual_weight = T::WeightInfo::acknowledge_proposals(proposals.len() as u32);
+			let mut pays_fee = Pays::No;
+			for (nonce, src_id, r_id, call) in proposals {
+				let result = with_transaction(|| {
+					match Self::vote(who.clone(), nonce, src_id, r_id, call, true) {
+						Ok(weight) => TransactionOutcome::Commit(Ok(weight)),
+						Err(err) => TransactionOutcome::Rollback(Err(err)),
+					}
+				});
+				match result {
+					Ok(call_weight) => actual_weight = actual_weight.saturating_add(call_weight),
+					Err(err) => {
+						pays_fee = Pays::Yes;
+						Self::deposit_event(RawEvent::VoteFailed(src_id, nonce, err));
+					}
+				}
+			}
+			Ok(PostDispatchInfo {
+				actual_weight: Some(actual_weight),
+				pays_fee,
+			})
 		}
 
 		/// Commits a vote against a provided proposal.
 		///
+		/// The transaction fee is refunded once the vote is counted.
+		///
 		/// # <weight>
 		/// - Fixed, since execution of proposal should not be included
 		/// # </weight>
-		#[weight = 195_000_000]
-		pub fn reject_proposal(origin, nonce: DepositNonce, src_id: ChainId, r_id: ResourceId, call: Box<<T as Config>::Proposal>) -> DispatchResult {
+		#[weight = T::WeightInfo::reject_proposal()]
+		pub fn reject_proposal(origin, nonce: DepositNonce, src_id: ChainId, r_id: ResourceId, call: Box<<T as Config>::Proposal>) -> DispatchResultWithPostInfo {
 			let who = ensure_signed(origin)?;
 			ensure!(Self::is_relayer(&who), Error::<T>::MustBeRelayer);
-			ensure!(Self::chain_whitelisted(src_id), Error::<T>::ChainNotWhitelisted);
-			ensure!(Self::resource_exists(r_id), Error::<T>::ResourceDoesNotExist);
 
-			Self::vote_against(who, nonce, src_id, call)
+			Self::vote(who, nonce, src_id, r_id, call, false)?;
+			Ok(Pays::No.into())
+		}
+
+		/// Executes again an approved proposal whose call failed.
+		///
+		/// The deposit is marked executed and the relayers who voted for the proposal are paid
+		/// once the call succeeds.
+		///
+		/// # <weight>
+		/// - weight of proposed call
+		/// # </weight>
+		#[weight = (
+			T::WeightInfo::retry_proposal().saturating_add(call.get_dispatch_info().weight),
+			call.get_dispatch_info().class,
+			Pays::Yes
+		)]
+		pub fn retry_proposal(origin, nonce: DepositNonce, src_id: ChainId, call: Box<<T as Config>::Proposal>) -> DispatchResultWithPostInfo {
+			let who = ensure_signed(origin)?;
+			ensure!(Self::is_relayer(&who), Error::<T>::MustBeRelayer);
+			ensure!(
+				!ExecutedDeposits::get(src_id, nonce),
+				Error::<T>::ProposalAlreadyComplete
+			);
+			let (hash, relayers) = Self::failed_proposals(src_id, nonce)
+				.ok_or(Error::<T>::ProposalDoesNotExist)?;
+			ensure!(
+				hash == Self::proposal_hash(&call),
+				Error::<T>::ProposalDoesNotExist
+			);
+
+			let call_weight = Self::execute_proposal(src_id, nonce, call, relayers);
+			Ok(Some(T::WeightInfo::retry_proposal().saturating_add(call_weight)).into())
 		}
 
 		/// Evaluate the state of a proposal given the current vote threshold.
@@ -327,13 +524,18 @@ decl_module! {
 		/// will be updated accordingly.
 		///
 		/// # <weight>
-		/// - weight of proposed call, regardless of whether execution is performed
+		/// - weight of proposed call, refunded if the call isn't executed
 		/// # </weight>
-		#[weight = (prop.get_dispatch_info().weight + 195_000_000, prop.get_dispatch_info().class, Pays::Yes)]
-		pub fn eval_vote_state(origin, nonce: DepositNonce, src_id: ChainId, prop: Box<<T as Config>::Proposal>) -> DispatchResult {
+		#[weight = (
+			T::WeightInfo::eval_vote_state().saturating_add(prop.get_dispatch_info().weight),
+			prop.get_dispatch_info().class,
+			Pays::Yes
+		)]
+		pub fn eval_vote_state(origin, nonce: DepositNonce, src_id: ChainId, prop: Box<<T as Config>::Proposal>) -> DispatchResultWithPostInfo {
 			ensure_signed(origin)?;
 
-			Self::try_resolve_proposal(nonce, src_id, prop)
+			let call_weight = Self::try_resolve_proposal(nonce, src_id, prop)?;
+			Ok(Some(T::WeightInfo::eval_vote_state().saturating_add(call_weight)).into())
 		}
 	}
 }
@@ -352,6 +554,11 @@ impl<T: Config> Module<T> {
 		MODULE_ID.into_account()
 	}
 
+	/// The account the relayer fees are paid from, kept apart from the bridged funds
+	pub fn fee_pot_id() -> T::AccountId {
+		FEE_POT_ID.into_account()
+	}
+
 	/// Asserts if a resource is registered
 	pub fn resource_exists(id: ResourceId) -> bool {
 		return Self::resources(id) != None;
@@ -362,6 +569,11 @@ impl<T: Config> Module<T> {
 		return Self::chains(id) != None;
 	}
 
+	/// Returns the hash of a proposal, which keys its votes together with the deposit nonce
+	pub fn proposal_hash(prop: &T::Proposal) -> T::Hash {
+		T::Hashing::hash_of(prop)
+	}
+
 	/// Increments the deposit nonce for the specified chain ID
 	fn bump_nonce(id: ChainId) -> DepositNonce {
 		let nonce = Self::chains(id).unwrap_or_default() + 1;
@@ -429,26 +641,60 @@ impl<T: Config> Module<T> {
 
 	// *** Proposal voting and execution methods ***
 
+	/// Commits a vote of a relayer and resolves the proposal if the vote count allows.
+	///
+	/// Returns the actual weight of the proposed call, or zero if it isn't executed.
+	fn vote(
+		who: T::AccountId,
+		nonce: DepositNonce,
+		src_id: ChainId,
+		r_id: ResourceId,
+		prop: Box<T::Proposal>,
+		in_favour: bool,
+	) -> Result<Weight, DispatchError> {
+		ensure!(Self::chain_whitelisted(src_id), Error::<T>::ChainNotWhitelisted);
+		ensure!(Self::resource_exists(r_id), Error::<T>::ResourceDoesNotExist);
+
+		Self::commit_vote(who, nonce, src_id, &prop, in_favour)?;
+		Self::try_resolve_proposal(nonce, src_id, prop)
+	}
+
+	/// Whether the proposal was approved before the votes were keyed by the proposal hash
+	fn is_legacy_approved(src_id: ChainId, nonce: DepositNonce, prop: &T::Proposal) -> bool {
+		let chain_key = sp_io::hashing::blake2_256(&src_id.encode());
+		let proposal_key = sp_io::hashing::blake2_256(&(nonce, prop).encode());
+		LegacyApprovedProposals::get(chain_key, proposal_key)
+	}
+
 	/// Commits a vote for a proposal. If the proposal doesn't exist it will be created.
 	fn commit_vote(
 		who: T::AccountId,
 		nonce: DepositNonce,
 		src_id: ChainId,
-		prop: Box<T::Proposal>,
+		prop: &T::Proposal,
 		in_favour: bool,
 	) -> DispatchResult {
 		let now = <frame_system::Pallet<T>>::block_number();
-		let mut votes = match <Votes<T>>::get(src_id, (nonce, prop.clone())) {
-			Some(v) => v,
+		let key = (nonce, Self::proposal_hash(prop));
+		let (mut votes, is_new) = match <Votes<T>>::get(src_id, key) {
+			Some(v) => (v, false),
 			None => {
 				let mut v = ProposalVotes::default();
 				v.expiry = now + T::ProposalLifetime::get();
-				v
+				(v, true)
 			}
 		};
 
 		// Ensure the proposal isn't complete and relayer hasn't already voted
 		ensure!(!votes.is_complete(), Error::<T>::ProposalAlreadyComplete);
+		ensure!(
+			!ExecutedDeposits::get(src_id, nonce),
+			Error::<T>::ProposalAlreadyComplete
+		);
+		ensure!(
+			!Self::is_legacy_approved(src_id, nonce, prop),
+			Error::<T>::ProposalAlreadyComplete
+		);
 		ensure!(!votes.is_expired(now), Error::<T>::ProposalExpired);
 		ensure!(!votes.has_voted(&who), Error::<T>::RelayerAlreadyVoted);
 
@@ -460,76 +706,111 @@ impl<T: Config> Module<T> {
 			Self::deposit_event(RawEvent::VoteAgainst(src_id, nonce, who.clone()));
 		}
 
-		<Votes<T>>::insert(src_id, (nonce, prop.clone()), votes.clone());
+		if is_new {
+			<ProposalExpiries<T>>::append(votes.expiry, (src_id, nonce, key.1));
+		}
+		<Votes<T>>::insert(src_id, key, votes);
 
 		Ok(())
 	}
 
 	/// Attempts to finalize or cancel the proposal if the vote count allows.
+	///
+	/// Returns the actual weight of the proposed call, or zero if it isn't executed.
 	fn try_resolve_proposal(
 		nonce: DepositNonce,
 		src_id: ChainId,
 		prop: Box<T::Proposal>,
-	) -> DispatchResult {
-		if let Some(mut votes) = <Votes<T>>::get(src_id, (nonce, prop.clone())) {
+	) -> Result<Weight, DispatchError> {
+		let key = (nonce, Self::proposal_hash(&prop));
+		if let Some(mut votes) = <Votes<T>>::get(src_id, key) {
 			let now = <frame_system::Pallet<T>>::block_number();
 			ensure!(!votes.is_complete(), Error::<T>::ProposalAlreadyComplete);
 			ensure!(!votes.is_expired(now), Error::<T>::ProposalExpired);
 
 			let status = votes.try_to_complete(<RelayerThreshold>::get(), <RelayerCount>::get());
-			<Votes<T>>::insert(src_id, (nonce, prop.clone()), votes.clone());
+			<Votes<T>>::insert(src_id, key, votes.clone());
 
 			match status {
-				ProposalStatus::Approved => Self::finalize_execution(src_id, nonce, prop),
-				ProposalStatus::Rejected => Self::cancel_execution(src_id, nonce),
-				_ => Ok(()),
+				ProposalStatus::Approved => {
+					Ok(Self::finalize_execution(src_id, nonce, prop, votes.votes_for))
+				}
+				ProposalStatus::Rejected => {
+					Self::cancel_execution(src_id, nonce);
+					Ok(0)
+				}
+				_ => Ok(0),
 			}
 		} else {
 			Err(Error::<T>::ProposalDoesNotExist)?
 		}
 	}
 
-	/// Commits a vote in favour of the proposal and executes it if the vote threshold is met.
-	fn vote_for(
-		who: T::AccountId,
-		nonce: DepositNonce,
+	/// Execute the approved proposal and signals the result as an event.
+	///
+	/// Returns the actual weight of the call.
+	fn finalize_execution(
 		src_id: ChainId,
-		prop: Box<T::Proposal>,
-	) -> DispatchResult {
-		Self::commit_vote(who, nonce, src_id, prop.clone(), true)?;
-		Self::try_resolve_proposal(nonce, src_id, prop)
-	}
-
-	/// Commits a vote against the proposal and cancels it if more than (relayers.len() - threshold)
-	/// votes against exist.
-	fn vote_against(
-		who: T::AccountId,
 		nonce: DepositNonce,
-		src_id: ChainId,
-		prop: Box<T::Proposal>,
-	) -> DispatchResult {
-		Self::commit_vote(who, nonce, src_id, prop.clone(), false)?;
-		Self::try_resolve_proposal(nonce, src_id, prop)
+		call: Box<T::Proposal>,
+		relayers: Vec<T::AccountId>,
+	) -> Weight {
+		Self::deposit_event(RawEvent::ProposalApproved(src_id, nonce));
+		Self::execute_proposal(src_id, nonce, call, relayers)
 	}
 
-	/// Execute the proposal and signals the result as an event
-	fn finalize_execution(
+	/// Dispatches an approved proposal.
+	///
+	/// If the call succeeds, the deposit is marked executed and `relayers` are paid. Otherwise
+	/// its changes are reverted and the proposal is kept in `FailedProposals` to be retried.
+	///
+	/// Returns the actual weight of the call.
+	fn execute_proposal(
 		src_id: ChainId,
 		nonce: DepositNonce,
 		call: Box<T::Proposal>,
-	) -> DispatchResult {
-		Self::deposit_event(RawEvent::ProposalApproved(src_id, nonce));
-		call.dispatch(frame_system::RawOrigin::Signed(Self::account_id()).into())
-			.map(|_| ())
-			.map_err(|e| e.error)?;
-		Self::deposit_event(RawEvent::ProposalSucceeded(src_id, nonce));
-		Ok(())
+		relayers: Vec<T::AccountId>,
+	) -> Weight {
+		let info = call.get_dispatch_info();
+		let hash = Self::proposal_hash(&call);
+		let result = with_transaction(|| {
+			let result = call.dispatch(frame_system::RawOrigin::Signed(Self::account_id()).into());
+			if result.is_ok() {
+				TransactionOutcome::Commit(result)
+			} else {
+				TransactionOutcome::Rollback(result)
+			}
+		});
+		if result.is_ok() {
+			ExecutedDeposits::insert(src_id, nonce, true);
+			<FailedProposals<T>>::remove(src_id, nonce);
+			Self::pay_relayer_fees(&relayers);
+			Self::deposit_event(RawEvent::ProposalSucceeded(src_id, nonce));
+		} else {
+			<FailedProposals<T>>::insert(src_id, nonce, (hash, relayers));
+			Self::deposit_event(RawEvent::ProposalFailed(src_id, nonce));
+		}
+		extract_actual_weight(&result, &info)
 	}
 
 	/// Cancels a proposal.
-	fn cancel_execution(src_id: ChainId, nonce: DepositNonce) -> DispatchResult {
+	fn cancel_execution(src_id: ChainId, nonce: DepositNonce) {
 		Self::deposit_event(RawEvent::ProposalRejected(src_id, nonce));
-		Ok(())
+	}
+
+	/// Pays the relayer fee to the relayers who voted for an executed proposal, as long as the
+	/// fee pot can afford it.
+	fn pay_relayer_fees(relayers: &[T::AccountId]) {
+		let fee = Self::relayer_fee();
+		if fee.is_zero() {
+			return;
+		}
+		let fee_pot_id = Self::fee_pot_id();
+		for relayer in relayers {
+			if T::Currency::transfer(&fee_pot_id, relayer, fee, KeepAlive).is_ok() {
+				Self::deposit_event(RawEvent::RelayerFeePaid(relayer.clone(), fee));
+			}
+		}
 	}
 
 	/// Initiates a transfer of a fungible asset out of the chain. This should be called by another pallet.
@@ -610,4 +891,9 @@ impl<T: Config> EnsureOrigin<T::Origin> for EnsureBridge<T> {
 			r => Err(T::Origin::from(r)),
 		})
 	}
+
+	#[cfg(feature = "runtime-benchmarks")]
+	fn successful_origin() -> T::Origin {
+		T::Origin::from(system::RawOrigin::Signed(<Module<T>>::account_id()))
+	}
 }
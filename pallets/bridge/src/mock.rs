@@ -90,6 +90,7 @@ impl pallet_balances::Config for Test {
 parameter_types! {
 	pub const TestChainId: u8 = 5;
 	pub const ProposalLifetime: u64 = 50;
+	pub const MaxProposalsPerBatch: u32 = 4;
 }
 
 impl Config for Test {
@@ -98,6 +99,9 @@ impl Config for Test {
 	type Proposal = Call;
 	type ChainId = TestChainId;
 	type ProposalLifetime = ProposalLifetime;
+	type Currency = Balances;
+	type MaxProposalsPerBatch = MaxProposalsPerBatch;
+	type WeightInfo = ();
 }
 
 // pub const BRIDGE_ID: u64 =
@@ -109,11 +113,12 @@ pub const TEST_THRESHOLD: u32 = 2;
 
 pub fn new_test_ext() -> sp_io::TestExternalities {
 	let bridge_id = PalletId(*b"phala/bg").into_account();
+	let fee_pot_id = PalletId(*b"phala/bf").into_account();
 	let mut t = frame_system::GenesisConfig::default()
 		.build_storage::<Test>()
 		.unwrap();
 	pallet_balances::GenesisConfig::<Test> {
-		balances: vec![(bridge_id, ENDOWED_BALANCE)],
+		balances: vec![(bridge_id, ENDOWED_BALANCE), (fee_pot_id, ENDOWED_BALANCE)],
 	}
 	.assimilate_storage(&mut t)
 	.unwrap();
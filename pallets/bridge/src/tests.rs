@@ -1,12 +1,16 @@
 #![cfg(test)]
 
 use super::mock::{
-	assert_events, new_test_ext, Balances, Bridge, Call, Event, Origin, ProposalLifetime, System,
-	Test, TestChainId, ENDOWED_BALANCE, RELAYER_A, RELAYER_B, RELAYER_C, TEST_THRESHOLD,
+	assert_events, new_test_ext, Balances, Bridge, Call, Event, MaxProposalsPerBatch, Origin,
+	ProposalLifetime, System, Test, TestChainId, ENDOWED_BALANCE, RELAYER_A, RELAYER_B, RELAYER_C,
+	TEST_THRESHOLD,
 };
 use super::*;
 use crate::mock::new_test_ext_initialized;
-use frame_support::{assert_noop, assert_ok};
+use frame_support::{
+	assert_noop, assert_ok,
+	traits::{OnInitialize, OnRuntimeUpgrade},
+};
 
 #[test]
 fn derive_ids() {
@@ -268,7 +272,7 @@ fn create_sucessful_proposal() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![],
@@ -285,7 +289,7 @@ fn create_sucessful_proposal() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![RELAYER_B],
@@ -302,7 +306,7 @@ fn create_sucessful_proposal() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A, RELAYER_C],
 			votes_against: vec![RELAYER_B],
@@ -338,7 +342,7 @@ fn create_unsucessful_proposal() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![],
@@ -355,7 +359,7 @@ fn create_unsucessful_proposal() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![RELAYER_B],
@@ -372,7 +376,7 @@ fn create_unsucessful_proposal() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![RELAYER_B, RELAYER_C],
@@ -413,7 +417,7 @@ fn execute_after_threshold_change() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![],
@@ -433,7 +437,7 @@ fn execute_after_threshold_change() {
 			Box::new(proposal.clone())
 		));
 
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![],
@@ -474,7 +478,7 @@ fn proposal_expires() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![],
@@ -499,7 +503,7 @@ fn proposal_expires() {
 		);
 
 		// Proposal state should remain unchanged
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![],
@@ -518,7 +522,7 @@ fn proposal_expires() {
 			),
 			Error::<Test>::ProposalExpired
 		);
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![],
@@ -532,3 +536,399 @@ fn proposal_expires() {
 		))]);
 	})
 }
+
+#[test]
+fn acknowledge_proposals_in_batch() {
+	let src_id = 1;
+	let r_id = derive_resource_id(src_id, b"remark");
+
+	new_test_ext_initialized(src_id, r_id, b"System.remark".to_vec()).execute_with(|| {
+		let prop_a = make_proposal(vec![10]);
+		let prop_b = make_proposal(vec![11]);
+		let bad_src_id = 2;
+
+		// The vote from a non-whitelisted chain is rejected, and the relayer pays the fee
+		let info = Bridge::acknowledge_proposals(
+			Origin::signed(RELAYER_A),
+			vec![
+				(1, src_id, r_id, Box::new(prop_a.clone())),
+				(2, src_id, r_id, Box::new(prop_b.clone())),
+				(3, bad_src_id, r_id, Box::new(prop_a.clone())),
+			],
+		)
+		.unwrap();
+		assert_eq!(info.pays_fee, Pays::Yes);
+		assert_eq!(
+			Bridge::votes(src_id, (1, Bridge::proposal_hash(&prop_a)))
+				.unwrap()
+				.votes_for,
+			vec![RELAYER_A]
+		);
+		assert_eq!(Bridge::votes(bad_src_id, (3, Bridge::proposal_hash(&prop_a))), None);
+
+		// All the votes are counted, so the fee is refunded
+		let info = Bridge::acknowledge_proposals(
+			Origin::signed(RELAYER_B),
+			vec![
+				(1, src_id, r_id, Box::new(prop_a.clone())),
+				(2, src_id, r_id, Box::new(prop_b.clone())),
+			],
+		)
+		.unwrap();
+		assert_eq!(info.pays_fee, Pays::No);
+		assert!(Bridge::executed_deposits(src_id, 1));
+		assert!(Bridge::executed_deposits(src_id, 2));
+
+		// Too many proposals
+		let proposals = (0..MaxProposalsPerBatch::get() as u64 + 1)
+			.map(|nonce| (nonce + 3, src_id, r_id, Box::new(prop_a.clone())))
+			.collect();
+		assert_noop!(
+			Bridge::acknowledge_proposals(Origin::signed(RELAYER_C), proposals),
+			Error::<Test>::TooManyProposals
+		);
+		// Only relayers can vote
+		assert_noop!(
+			Bridge::acknowledge_proposals(Origin::signed(1), vec![]),
+			Error::<Test>::MustBeRelayer
+		);
+
+		assert_events(vec![
+			Event::Bridge(RawEvent::VoteFor(src_id, 1, RELAYER_A)),
+			Event::Bridge(RawEvent::VoteFor(src_id, 2, RELAYER_A)),
+			Event::Bridge(RawEvent::VoteFailed(
+				bad_src_id,
+				3,
+				Error::<Test>::ChainNotWhitelisted.into(),
+			)),
+			Event::Bridge(RawEvent::VoteFor(src_id, 1, RELAYER_B)),
+			Event::Bridge(RawEvent::ProposalApproved(src_id, 1)),
+			Event::Bridge(RawEvent::ProposalSucceeded(src_id, 1)),
+			Event::Bridge(RawEvent::VoteFor(src_id, 2, RELAYER_B)),
+			Event::Bridge(RawEvent::ProposalApproved(src_id, 2)),
+			Event::Bridge(RawEvent::ProposalSucceeded(src_id, 2)),
+		]);
+	})
+}
+
+#[test]
+fn counted_votes_are_free() {
+	let src_id = 1;
+	let r_id = derive_resource_id(src_id, b"remark");
+
+	new_test_ext_initialized(src_id, r_id, b"System.remark".to_vec()).execute_with(|| {
+		let proposal = make_proposal(vec![10]);
+
+		let info = Bridge::acknowledge_proposal(
+			Origin::signed(RELAYER_A),
+			1,
+			src_id,
+			r_id,
+			Box::new(proposal.clone()),
+		)
+		.unwrap();
+		assert_eq!(info.pays_fee, Pays::No);
+		// The proposal isn't executed yet, so its weight is refunded
+		assert_eq!(
+			info.actual_weight,
+			Some(<() as WeightInfo>::acknowledge_proposal())
+		);
+
+		let info = Bridge::reject_proposal(
+			Origin::signed(RELAYER_B),
+			1,
+			src_id,
+			r_id,
+			Box::new(proposal.clone()),
+		)
+		.unwrap();
+		assert_eq!(info.pays_fee, Pays::No);
+
+		// A duplicated vote is charged
+		let err = Bridge::reject_proposal(
+			Origin::signed(RELAYER_B),
+			1,
+			src_id,
+			r_id,
+			Box::new(proposal.clone()),
+		)
+		.unwrap_err();
+		assert_eq!(err.post_info.pays_fee, Pays::Yes);
+		assert_eq!(err.error, Error::<Test>::RelayerAlreadyVoted.into());
+	})
+}
+
+#[test]
+fn relayer_fee_paid_on_approval() {
+	let src_id = 1;
+	let r_id = derive_resource_id(src_id, b"remark");
+
+	new_test_ext_initialized(src_id, r_id, b"System.remark".to_vec()).execute_with(|| {
+		let proposal = make_proposal(vec![10]);
+		assert_noop!(
+			Bridge::set_relayer_fee(Origin::signed(RELAYER_A), 10),
+			sp_runtime::traits::BadOrigin
+		);
+		assert_ok!(Bridge::set_relayer_fee(Origin::root(), 10));
+
+		assert_ok!(Bridge::acknowledge_proposal(
+			Origin::signed(RELAYER_A),
+			1,
+			src_id,
+			r_id,
+			Box::new(proposal.clone())
+		));
+		assert_eq!(Balances::free_balance(RELAYER_A), 0);
+		assert_ok!(Bridge::acknowledge_proposal(
+			Origin::signed(RELAYER_B),
+			1,
+			src_id,
+			r_id,
+			Box::new(proposal.clone())
+		));
+
+		// Both relayers voting for the proposal are paid from the fee pot
+		assert_eq!(Balances::free_balance(RELAYER_A), 10);
+		assert_eq!(Balances::free_balance(RELAYER_B), 10);
+		assert_eq!(Balances::free_balance(RELAYER_C), 0);
+		assert_eq!(
+			Balances::free_balance(Bridge::fee_pot_id()),
+			ENDOWED_BALANCE - 20
+		);
+		assert_eq!(
+			Balances::free_balance(Bridge::account_id()),
+			ENDOWED_BALANCE
+		);
+
+		let events: Vec<Event> = System::events().into_iter().map(|e| e.event).collect();
+		assert!(events.contains(&Event::Bridge(RawEvent::RelayerFeePaid(RELAYER_A, 10))));
+		assert!(events.contains(&Event::Bridge(RawEvent::RelayerFeePaid(RELAYER_B, 10))));
+	})
+}
+
+#[test]
+fn proposals_pruned_at_expiry() {
+	let src_id = 1;
+	let r_id = derive_resource_id(src_id, b"remark");
+
+	new_test_ext_initialized(src_id, r_id, b"System.remark".to_vec()).execute_with(|| {
+		let approved = make_proposal(vec![10]);
+		let pending = make_proposal(vec![11]);
+		let expiry = ProposalLifetime::get() + 1;
+
+		for relayer in [RELAYER_A, RELAYER_B].iter() {
+			assert_ok!(Bridge::acknowledge_proposal(
+				Origin::signed(*relayer),
+				1,
+				src_id,
+				r_id,
+				Box::new(approved.clone())
+			));
+		}
+		assert_ok!(Bridge::acknowledge_proposal(
+			Origin::signed(RELAYER_A),
+			2,
+			src_id,
+			r_id,
+			Box::new(pending.clone())
+		));
+		assert_eq!(Bridge::proposal_expiries(expiry).len(), 2);
+
+		// Both the complete and the pending proposals are pruned
+		System::set_block_number(expiry);
+		Bridge::on_initialize(expiry);
+		assert_eq!(Bridge::votes(src_id, (1, Bridge::proposal_hash(&approved))), None);
+		assert_eq!(Bridge::votes(src_id, (2, Bridge::proposal_hash(&pending))), None);
+		assert!(Bridge::proposal_expiries(expiry).is_empty());
+
+		// The pending proposal can be voted again
+		assert_ok!(Bridge::acknowledge_proposal(
+			Origin::signed(RELAYER_A),
+			2,
+			src_id,
+			r_id,
+			Box::new(pending.clone())
+		));
+		assert_eq!(
+			Bridge::votes(src_id, (2, Bridge::proposal_hash(&pending)))
+				.unwrap()
+				.expiry,
+			expiry + ProposalLifetime::get()
+		);
+
+		// But the executed deposit can't be replayed, with whatever call
+		assert_noop!(
+			Bridge::acknowledge_proposal(
+				Origin::signed(RELAYER_A),
+				1,
+				src_id,
+				r_id,
+				Box::new(approved.clone())
+			),
+			Error::<Test>::ProposalAlreadyComplete
+		);
+		assert_noop!(
+			Bridge::acknowledge_proposal(
+				Origin::signed(RELAYER_A),
+				1,
+				src_id,
+				r_id,
+				Box::new(pending.clone())
+			),
+			Error::<Test>::ProposalAlreadyComplete
+		);
+	})
+}
+
+#[test]
+fn failed_proposal_retried() {
+	let src_id = 1;
+	let r_id = derive_resource_id(src_id, b"transfer");
+
+	new_test_ext_initialized(src_id, r_id, b"Balances.transfer".to_vec()).execute_with(|| {
+		assert_ok!(Bridge::set_relayer_fee(Origin::root(), 10));
+		// More than the bridge account holds
+		let proposal = Call::Balances(pallet_balances::Call::transfer(
+			RELAYER_C,
+			ENDOWED_BALANCE * 2,
+		));
+
+		for relayer in [RELAYER_A, RELAYER_B].iter() {
+			assert_ok!(Bridge::acknowledge_proposal(
+				Origin::signed(*relayer),
+				1,
+				src_id,
+				r_id,
+				Box::new(proposal.clone())
+			));
+		}
+		assert_events(vec![
+			Event::Bridge(RawEvent::ProposalApproved(src_id, 1)),
+			Event::Bridge(RawEvent::ProposalFailed(src_id, 1)),
+		]);
+
+		// Neither executed nor paid, but kept for retry
+		assert!(!Bridge::executed_deposits(src_id, 1));
+		assert_eq!(Balances::free_balance(RELAYER_A), 0);
+		assert_eq!(
+			Bridge::failed_proposals(src_id, 1),
+			Some((
+				Bridge::proposal_hash(&proposal),
+				vec![RELAYER_A, RELAYER_B]
+			))
+		);
+
+		// Only the failed call can be retried, by a relayer
+		assert_noop!(
+			Bridge::retry_proposal(
+				Origin::signed(RELAYER_A),
+				1,
+				src_id,
+				Box::new(make_proposal(vec![1]))
+			),
+			Error::<Test>::ProposalDoesNotExist
+		);
+		assert_noop!(
+			Bridge::retry_proposal(Origin::signed(5), 1, src_id, Box::new(proposal.clone())),
+			Error::<Test>::MustBeRelayer
+		);
+
+		// Still failing
+		assert_ok!(Bridge::retry_proposal(
+			Origin::signed(RELAYER_C),
+			1,
+			src_id,
+			Box::new(proposal.clone())
+		));
+		assert!(!Bridge::executed_deposits(src_id, 1));
+
+		let _ = Balances::deposit_creating(&Bridge::account_id(), ENDOWED_BALANCE * 2);
+		assert_ok!(Bridge::retry_proposal(
+			Origin::signed(RELAYER_C),
+			1,
+			src_id,
+			Box::new(proposal.clone())
+		));
+		assert!(Bridge::executed_deposits(src_id, 1));
+		assert_eq!(Bridge::failed_proposals(src_id, 1), None);
+		assert_eq!(Balances::free_balance(RELAYER_C), ENDOWED_BALANCE * 2);
+		// The relayers who voted for it are paid
+		assert_eq!(Balances::free_balance(RELAYER_A), 10);
+		assert_eq!(Balances::free_balance(RELAYER_B), 10);
+
+		assert_noop!(
+			Bridge::retry_proposal(
+				Origin::signed(RELAYER_C),
+				1,
+				src_id,
+				Box::new(proposal.clone())
+			),
+			Error::<Test>::ProposalAlreadyComplete
+		);
+	})
+}
+
+#[test]
+fn legacy_approved_proposals_not_replayed() {
+	let src_id = 1;
+	let r_id = derive_resource_id(src_id, b"remark");
+
+	new_test_ext_initialized(src_id, r_id, b"System.remark".to_vec()).execute_with(|| {
+		let approved = make_proposal(vec![10]);
+		let pending = make_proposal(vec![11]);
+		// The votes as stored before version 1
+		let legacy_key = |nonce: DepositNonce, prop: &Call| {
+			let mut key = sp_io::hashing::blake2_256(&src_id.encode()).to_vec();
+			key.extend_from_slice(&sp_io::hashing::blake2_256(&(nonce, prop).encode()));
+			key
+		};
+		let votes = |status| ProposalVotes::<u64, u64> {
+			votes_for: vec![RELAYER_A, RELAYER_B],
+			votes_against: vec![],
+			status,
+			expiry: ProposalLifetime::get() + 1,
+		};
+		frame_support::migration::put_storage_value(
+			b"ChainBridge",
+			b"Votes",
+			&legacy_key(1, &approved),
+			votes(ProposalStatus::Approved),
+		);
+		frame_support::migration::put_storage_value(
+			b"ChainBridge",
+			b"Votes",
+			&legacy_key(2, &pending),
+			votes(ProposalStatus::Initiated),
+		);
+
+		Bridge::on_runtime_upgrade();
+		assert_eq!(
+			frame_support::migration::storage_iter::<ProposalVotes<u64, u64>>(
+				b"ChainBridge",
+				b"Votes"
+			)
+			.count(),
+			0
+		);
+
+		// The approved deposit can't be replayed
+		assert_noop!(
+			Bridge::acknowledge_proposal(
+				Origin::signed(RELAYER_A),
+				1,
+				src_id,
+				r_id,
+				Box::new(approved.clone())
+			),
+			Error::<Test>::ProposalAlreadyComplete
+		);
+		// The pending one is voted again
+		assert_ok!(Bridge::acknowledge_proposal(
+			Origin::signed(RELAYER_A),
+			2,
+			src_id,
+			r_id,
+			Box::new(pending.clone())
+		));
+	})
+}
@@ -0,0 +1,175 @@
+//! Bridge transfer pallet benchmarking.
+
+#![cfg(feature = "runtime-benchmarks")]
+
+use super::*;
+
+use frame_benchmarking::{benchmarks, impl_benchmark_test_suite, whitelisted_caller};
+use frame_system::RawOrigin;
+
+use crate::Module as BridgeTransfer;
+
+const DEST_ID: bridge::ChainId = 0;
+
+fn unit<T: Config>() -> BalanceOf<T> {
+	<T as Config>::Currency::minimum_balance().max(1u32.into()) * 100u32.into()
+}
+
+/// A limit never reached by the benchmarks, but checked anyway
+fn loose_limit<T: Config>() -> TransferLimitOf<T> {
+	TransferLimit {
+		cap: unit::<T>() * 1_000_000u32.into(),
+		window: VOLUME_BUCKETS.into(),
+	}
+}
+
+/// A volume with all the buckets of `loose_limit` taken, moving to the block to check it
+fn full_volume<T: Config>() -> TransferVolumeOf<T> {
+	let limit = loose_limit::<T>();
+	let mut volume = TransferVolume::default();
+	for block in 1..VOLUME_BUCKETS {
+		volume.try_add(&limit, 1u32.into(), block.into());
+	}
+	frame_system::Pallet::<T>::set_block_number(VOLUME_BUCKETS.into());
+	volume
+}
+
+fn whitelist_dest<T: Config>() -> DispatchResult {
+	if !<bridge::Module<T>>::chain_whitelisted(DEST_ID) {
+		<bridge::Module<T>>::whitelist(DEST_ID)?;
+	}
+	Ok(())
+}
+
+benchmarks! {
+	sudo_change_fee {
+		let min_fee = unit::<T>();
+	}: _(RawOrigin::Root, min_fee, 1000, DEST_ID)
+	verify {
+		assert_eq!(BridgeTransfer::<T>::bridge_fee(DEST_ID), (min_fee, 1000));
+	}
+
+	force_lottery_output {
+		whitelist_dest::<T>()?;
+		let lottery = Lottery::BtcAddresses {
+			address_set: vec![vec![0u8; 64]; 16],
+		};
+	}: _(RawOrigin::Root, lottery.encode(), DEST_ID)
+	verify {
+		assert_eq!(<bridge::Module<T>>::chains(DEST_ID), Some(1));
+	}
+
+	// The worst case: checked against the chain, the resource and the account limits, all with
+	// full volumes
+	transfer_native {
+		let caller: T::AccountId = whitelisted_caller();
+		let amount = unit::<T>();
+		let rid = BridgeTransfer::<T>::bridge_tokenid();
+		whitelist_dest::<T>()?;
+		BridgeFee::<T>::insert(DEST_ID, (amount, 1000));
+		ChainLimits::<T>::insert(DEST_ID, loose_limit::<T>());
+		ResourceLimits::<T>::insert(rid, loose_limit::<T>());
+		AccountLimit::<T>::put(loose_limit::<T>());
+		ChainVolumes::<T>::insert(DEST_ID, full_volume::<T>());
+		ResourceVolumes::<T>::insert(rid, full_volume::<T>());
+		AccountVolumes::<T>::insert(&caller, full_volume::<T>());
+		<T as Config>::Currency::make_free_balance_be(&caller, amount * 10u32.into());
+	}: _(RawOrigin::Signed(caller), amount, vec![0u8; 32], DEST_ID)
+	verify {
+		assert_eq!(<bridge::Module<T>>::chains(DEST_ID), Some(1));
+	}
+
+	// The worst case: checked against the resource and the account limits, with full volumes
+	transfer {
+		let to: T::AccountId = whitelisted_caller();
+		let amount = unit::<T>();
+		let rid = BridgeTransfer::<T>::bridge_tokenid();
+		ResourceLimits::<T>::insert(rid, loose_limit::<T>());
+		AccountLimit::<T>::put(loose_limit::<T>());
+		ResourceVolumes::<T>::insert(rid, full_volume::<T>());
+		AccountVolumes::<T>::insert(&to, full_volume::<T>());
+		let bridge_id = <bridge::Module<T>>::account_id();
+		<T as Config>::Currency::make_free_balance_be(&bridge_id, amount * 10u32.into());
+		let origin = T::BridgeOrigin::successful_origin();
+	}: _<T::Origin>(origin, to.clone(), amount, BridgeTransfer::<T>::bridge_tokenid())
+	verify {
+		assert_eq!(<T as Config>::Currency::free_balance(&to), amount);
+	}
+
+	// The worst case: a generic message checked against the allowed topics
+	lottery_handler {
+		let rid = BridgeTransfer::<T>::bridge_lotteryid();
+		let message = envelope::BridgedMessage {
+			topic: b"phala/bridge/benchmark".to_vec(),
+			payload: vec![0u8; 256],
+		};
+		BridgedTopics::insert(rid, &message.topic, true);
+		let metadata = envelope::encode(&message, envelope::CODEC_ABI);
+		let origin = T::BridgeOrigin::successful_origin();
+	}: _<T::Origin>(origin, metadata, rid)
+
+	set_chain_limit {
+		let limit = loose_limit::<T>();
+	}: _(RawOrigin::Root, DEST_ID, Some(limit))
+	verify {
+		assert_eq!(BridgeTransfer::<T>::chain_limits(DEST_ID), Some(limit));
+	}
+
+	set_resource_limit {
+		let rid = BridgeTransfer::<T>::bridge_tokenid();
+		let limit = loose_limit::<T>();
+	}: _(RawOrigin::Root, rid, Some(limit))
+	verify {
+		assert_eq!(BridgeTransfer::<T>::resource_limits(rid), Some(limit));
+	}
+
+	set_account_limit {
+		let limit = loose_limit::<T>();
+	}: _(RawOrigin::Root, Some(limit))
+	verify {
+		assert_eq!(BridgeTransfer::<T>::account_limit(), Some(limit));
+	}
+
+	pause {
+	}: _(RawOrigin::Root)
+	verify {
+		assert!(BridgeTransfer::<T>::paused());
+	}
+
+	resume {
+		Paused::put(true);
+	}: _(RawOrigin::Root)
+	verify {
+		assert!(!BridgeTransfer::<T>::paused());
+	}
+
+	release_held_transfer {
+		let to: T::AccountId = whitelisted_caller();
+		let amount = unit::<T>();
+		let bridge_id = <bridge::Module<T>>::account_id();
+		<T as Config>::Currency::make_free_balance_be(&bridge_id, amount * 10u32.into());
+		let rid = BridgeTransfer::<T>::bridge_tokenid();
+		BridgeTransfer::<T>::hold_transfer(to.clone(), amount, rid);
+	}: _(RawOrigin::Root, 0)
+	verify {
+		assert_eq!(<T as Config>::Currency::free_balance(&to), amount);
+	}
+
+	discard_held_transfer {
+		let to: T::AccountId = whitelisted_caller();
+		BridgeTransfer::<T>::hold_transfer(to, unit::<T>(), BridgeTransfer::<T>::bridge_tokenid());
+	}: _(RawOrigin::Root, 0)
+	verify {
+		assert!(BridgeTransfer::<T>::held_transfers(0).is_none());
+	}
+
+	set_bridged_topic {
+		let rid = BridgeTransfer::<T>::bridge_lotteryid();
+		let topic = b"phala/bridge/benchmark".to_vec();
+	}: _(RawOrigin::Root, rid, topic.clone(), true)
+	verify {
+		assert!(BridgeTransfer::<T>::bridged_topics(rid, topic));
+	}
+}
+
+impl_benchmark_test_suite!(BridgeTransfer, crate::mock::new_test_ext(), crate::mock::Test,);
@@ -0,0 +1,165 @@
+//! The envelope of the generic messages bridged from other chains.
+//!
+//! The first byte of a message is its version:
+//!
+//! - `0` and `1`: the legacy lottery commands (`NewRound` and `OpenBox`), packed as big-endian
+//!   integers after the version byte. They are kept for the contracts already deployed.
+//! - `0x81` (version 1): followed by a codec byte and a body carrying a topic and a payload.
+//!   The body is either SCALE encoded (codec `0`), or ABI encoded as `abi.encode(bytes, bytes)`
+//!   (codec `1`) so it can be built by the EVM contracts with no extra library.
+//!
+//! Versions above 1 have the top bit set to be told apart from the legacy commands.
+
+use codec::{Decode, Encode};
+use sp_std::convert::TryFrom;
+use sp_std::prelude::*;
+
+use crate::LotteryEvent;
+use phala_types::messaging::BindTopic;
+
+pub const LEGACY_NEW_ROUND: u8 = 0;
+pub const LEGACY_OPEN_BOX: u8 = 1;
+pub const VERSION_1: u8 = 0x81;
+
+pub const CODEC_SCALE: u8 = 0;
+pub const CODEC_ABI: u8 = 1;
+
+/// The size of an ABI word
+const WORD: usize = 32;
+
+/// A message to push to the message queue under `topic`
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Debug)]
+pub struct BridgedMessage {
+	pub topic: Vec<u8>,
+	pub payload: Vec<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EnvelopeError {
+	Empty,
+	UnknownVersion,
+	UnknownCodec,
+	Malformed,
+}
+
+/// Decodes a bridged message of any supported version.
+pub fn decode(metadata: &[u8]) -> Result<BridgedMessage, EnvelopeError> {
+	let (&version, body) = metadata.split_first().ok_or(EnvelopeError::Empty)?;
+	match version {
+		LEGACY_NEW_ROUND | LEGACY_OPEN_BOX => {
+			let event = decode_legacy(version, body).ok_or(EnvelopeError::Malformed)?;
+			Ok(BridgedMessage {
+				topic: LotteryEvent::TOPIC.to_vec(),
+				payload: event.encode(),
+			})
+		}
+		VERSION_1 => {
+			let (&codec, body) = body.split_first().ok_or(EnvelopeError::Malformed)?;
+			match codec {
+				CODEC_SCALE => decode_scale(body).ok_or(EnvelopeError::Malformed),
+				CODEC_ABI => decode_abi(body).ok_or(EnvelopeError::Malformed),
+				_ => Err(EnvelopeError::UnknownCodec),
+			}
+		}
+		_ => Err(EnvelopeError::UnknownVersion),
+	}
+}
+
+/// Encodes a message in the version 1 envelope with the given codec.
+pub fn encode(message: &BridgedMessage, codec: u8) -> Vec<u8> {
+	let mut output = vec![VERSION_1, codec];
+	if codec == CODEC_ABI {
+		output.extend(encode_abi(message));
+	} else {
+		message.encode_to(&mut output);
+	}
+	output
+}
+
+fn read_u32(body: &[u8], at: usize) -> Option<u32> {
+	let bytes = body.get(at..at.checked_add(4)?)?;
+	Some(u32::from_be_bytes(<[u8; 4]>::try_from(bytes).ok()?))
+}
+
+/// Decodes `[round_id, total_count, winner_count]` (NewRound) or
+/// `[round_id, token_id, address_len, address]` (OpenBox).
+fn decode_legacy(op: u8, body: &[u8]) -> Option<LotteryEvent> {
+	let round_id = read_u32(body, 0)?;
+	let second = read_u32(body, 4)?;
+	let third = read_u32(body, 8)?;
+	if op == LEGACY_NEW_ROUND {
+		if body.len() != 12 {
+			return None;
+		}
+		Some(LotteryEvent::NewRound(round_id, second, third))
+	} else {
+		let address = body.get(12..)?;
+		if address.is_empty() || address.len() != usize::try_from(third).ok()? {
+			return None;
+		}
+		Some(LotteryEvent::OpenBox(round_id, second, address.to_vec()))
+	}
+}
+
+fn decode_scale(mut body: &[u8]) -> Option<BridgedMessage> {
+	let message = BridgedMessage::decode(&mut body).ok()?;
+	// Trailing bytes are likely a mistake of the sender
+	if !body.is_empty() {
+		return None;
+	}
+	Some(message)
+}
+
+/// Reads an ABI word as an offset or a length, rejecting the values beyond `usize`.
+fn read_word(body: &[u8], at: usize) -> Option<usize> {
+	let word = body.get(at..at.checked_add(WORD)?)?;
+	let (high, low) = word.split_at(WORD - 8);
+	if high.iter().any(|b| *b != 0) {
+		return None;
+	}
+	usize::try_from(u64::from_be_bytes(<[u8; 8]>::try_from(low).ok()?)).ok()
+}
+
+/// Reads the dynamic `bytes` whose offset is at the head slot `slot`.
+fn read_bytes(body: &[u8], slot: usize) -> Option<Vec<u8>> {
+	let offset = read_word(body, slot * WORD)?;
+	let len = read_word(body, offset)?;
+	let start = offset.checked_add(WORD)?;
+	let data = body.get(start..start.checked_add(len)?)?;
+	Some(data.to_vec())
+}
+
+fn decode_abi(body: &[u8]) -> Option<BridgedMessage> {
+	Some(BridgedMessage {
+		topic: read_bytes(body, 0)?,
+		payload: read_bytes(body, 1)?,
+	})
+}
+
+fn abi_word(value: usize) -> [u8; WORD] {
+	let mut word = [0u8; WORD];
+	word[WORD - 8..].copy_from_slice(&(value as u64).to_be_bytes());
+	word
+}
+
+/// Appends the length and the zero padded data of an ABI `bytes`.
+fn abi_append_bytes(output: &mut Vec<u8>, data: &[u8]) {
+	output.extend_from_slice(&abi_word(data.len()));
+	output.extend_from_slice(data);
+	let padding = (WORD - data.len() % WORD) % WORD;
+	output.resize(output.len() + padding, 0);
+}
+
+/// The same as `abi.encode(topic, payload)` in Solidity.
+fn encode_abi(message: &BridgedMessage) -> Vec<u8> {
+	let mut tail = Vec::new();
+	abi_append_bytes(&mut tail, &message.topic);
+	let payload_offset = 2 * WORD + tail.len();
+	abi_append_bytes(&mut tail, &message.payload);
+
+	let mut output = Vec::with_capacity(2 * WORD + tail.len());
+	output.extend_from_slice(&abi_word(2 * WORD));
+	output.extend_from_slice(&abi_word(payload_offset));
+	output.extend(tail);
+	output
+}
@@ -2,28 +2,120 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 use codec::{Decode, Encode};
-use frame_support::traits::{Currency, EnsureOrigin, ExistenceRequirement::AllowDeath};
+use frame_support::traits::{Currency, EnsureOrigin, ExistenceRequirement::AllowDeath, Get};
 use frame_support::{
-	decl_error, decl_event, decl_module, decl_storage, dispatch::DispatchResult, ensure, fail,
+	decl_error, decl_event, decl_module, decl_storage, dispatch::DispatchResult, ensure,
+	transactional, weights::Weight,
 };
 use frame_system::{self as system, ensure_root, ensure_signed};
 use pallet_bridge as bridge;
-use sp_arithmetic::traits::SaturatedConversion;
+use sp_arithmetic::traits::{AtLeast32BitUnsigned, SaturatedConversion, Saturating, Zero};
 use sp_core::U256;
-use sp_std::convert::TryFrom;
+use sp_runtime::RuntimeDebug;
 use sp_std::prelude::*;
 
 use phala_pallets::{pallet_mq, pallet_phala};
 
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+pub mod envelope;
 #[cfg(test)]
 mod mock;
 #[cfg(test)]
 mod tests;
 
+pub mod weights;
+
+pub use weights::WeightInfo;
+
 type ResourceId = bridge::ResourceId;
 
 type BalanceOf<T> =
 	<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+type TransferLimitOf<T> = TransferLimit<BalanceOf<T>, <T as frame_system::Config>::BlockNumber>;
+type TransferVolumeOf<T> = TransferVolume<BalanceOf<T>, <T as frame_system::Config>::BlockNumber>;
+
+/// The max amount of tokens allowed to be transferred in a window of blocks
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub struct TransferLimit<Balance, BlockNumber> {
+	pub cap: Balance,
+	/// The length of the window in blocks
+	pub window: BlockNumber,
+}
+
+/// The number of buckets the window of a limit is split into
+pub const VOLUME_BUCKETS: u32 = 24;
+
+/// The tokens transferred in the recent buckets of blocks, summed over a sliding window.
+///
+/// A window is split into [`VOLUME_BUCKETS`] buckets, so a transfer leaves the volume within a
+/// bucket of blocks after its window elapsed, and the volume holds at most `VOLUME_BUCKETS`
+/// buckets.
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct TransferVolume<Balance, BlockNumber> {
+	/// The first block and the amount of each bucket, in ascending order
+	pub buckets: Vec<(BlockNumber, Balance)>,
+}
+
+impl<Balance, BlockNumber> TransferVolume<Balance, BlockNumber>
+where
+	Balance: AtLeast32BitUnsigned + Copy,
+	BlockNumber: AtLeast32BitUnsigned + Copy,
+{
+	/// The tokens transferred in the buckets
+	pub fn total(&self) -> Balance {
+		self.buckets
+			.iter()
+			.fold(Zero::zero(), |total: Balance, (_, amount)| total.saturating_add(*amount))
+	}
+
+	/// Drops the buckets out of the window ending at `now`. Returns the first block of the
+	/// bucket `now` falls in.
+	fn prune(
+		&mut self,
+		limit: &TransferLimit<Balance, BlockNumber>,
+		now: BlockNumber,
+	) -> BlockNumber {
+		let buckets: BlockNumber = VOLUME_BUCKETS.into();
+		let bucket_len = (limit.window.saturating_add(buckets) - 1u32.into()) / buckets;
+		let bucket_start = now - now % bucket_len;
+		let window_start = bucket_start
+			.saturating_add(bucket_len)
+			.saturating_sub(limit.window);
+		self.buckets.retain(|(start, _)| *start >= window_start);
+		bucket_start
+	}
+
+	/// Adds `amount` to the volume of the window ending at `now`, dropping the buckets out of
+	/// the window. Returns false if the cap of the limit would be exceeded.
+	fn try_add(
+		&mut self,
+		limit: &TransferLimit<Balance, BlockNumber>,
+		amount: Balance,
+		now: BlockNumber,
+	) -> bool {
+		let bucket_start = self.prune(limit, now);
+		let total = self.total().saturating_add(amount);
+		if total > limit.cap {
+			return false;
+		}
+		match self.buckets.last_mut() {
+			Some((start, bucket)) if *start == bucket_start => {
+				*bucket = bucket.saturating_add(amount)
+			}
+			_ => self.buckets.push((bucket_start, amount)),
+		}
+		true
+	}
+}
+
+/// An incoming transfer held back by the limits or the pause switch, waiting for the committee
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct HeldTransfer<AccountId, Balance> {
+	pub to: AccountId,
+	pub amount: Balance,
+	pub resource_id: ResourceId,
+}
 
 phala_types::messaging::bind_topic!(LotteryEvent, b"phala/lottery/event");
 #[derive(Decode, Encode, Debug, PartialEq, Eq, Clone)]
@@ -42,6 +134,12 @@ pub trait Config: system::Config + bridge::Config + pallet_mq::Config {
 
 	/// The currency mechanism.
 	type Currency: Currency<Self::AccountId>;
+
+	/// The origin allowed to pause and resume the transfers in an emergency
+	type PauseOrigin: EnsureOrigin<Self::Origin>;
+
+	/// Weight information for extrinsics in this pallet.
+	type WeightInfo: WeightInfo;
 }
 
 decl_storage! {
@@ -49,6 +147,41 @@ decl_storage! {
 		BridgeTokenId get(fn bridge_tokenid): ResourceId;
 		BridgeLotteryId get(fn bridge_lotteryid): ResourceId;
 		BridgeFee get(fn bridge_fee): map hasher(opaque_blake2_256) bridge::ChainId => (BalanceOf<T>, u32);
+
+		/// Whether the bridge is paused: the native token transfers in both directions and the
+		/// incoming messages
+		Paused get(fn paused): bool;
+
+		/// The limits of the outgoing transfers to each chain
+		ChainLimits get(fn chain_limits):
+			map hasher(twox_64_concat) bridge::ChainId => Option<TransferLimitOf<T>>;
+		ChainVolumes get(fn chain_volumes):
+			map hasher(twox_64_concat) bridge::ChainId => TransferVolumeOf<T>;
+
+		/// The limits of the transfers of each resource, in both directions
+		ResourceLimits get(fn resource_limits):
+			map hasher(blake2_128_concat) ResourceId => Option<TransferLimitOf<T>>;
+		ResourceVolumes get(fn resource_volumes):
+			map hasher(blake2_128_concat) ResourceId => TransferVolumeOf<T>;
+
+		/// The limit of the transfers of each account, sending the outgoing ones or receiving
+		/// the incoming ones
+		AccountLimit get(fn account_limit): Option<TransferLimitOf<T>>;
+		AccountVolumes get(fn account_volumes):
+			map hasher(blake2_128_concat) T::AccountId => TransferVolumeOf<T>;
+		/// The accounts whose latest bucket leaves the window at a block, so their volumes are
+		/// pruned then instead of being kept forever
+		AccountVolumeExpiry get(fn account_volume_expiry):
+			map hasher(twox_64_concat) T::BlockNumber => Vec<T::AccountId>;
+
+		/// The incoming transfers held back, to be released or discarded by the committee
+		HeldTransfers get(fn held_transfers):
+			map hasher(twox_64_concat) u64 => Option<HeldTransfer<T::AccountId, BalanceOf<T>>>;
+		NextHeldTransferId: u64;
+
+		/// The topics the generic messages bridged with a resource are allowed to target
+		BridgedTopics get(fn bridged_topics):
+			double_map hasher(blake2_128_concat) ResourceId, hasher(blake2_128_concat) Vec<u8> => bool;
 	}
 
 	add_extra_genesis {
@@ -64,10 +197,34 @@ decl_storage! {
 decl_event! {
 	pub enum Event<T>
 	where
+		AccountId = <T as frame_system::Config>::AccountId,
 		Balance = BalanceOf<T>,
+		Limit = TransferLimitOf<T>,
 	{
 		/// [chainId, min_fee, fee_scale]
 		FeeUpdated(bridge::ChainId, Balance, u32),
+		/// [chainId, limit]
+		ChainLimitChanged(bridge::ChainId, Option<Limit>),
+		/// [resourceId, limit]
+		ResourceLimitChanged(ResourceId, Option<Limit>),
+		/// [limit]
+		AccountLimitChanged(Option<Limit>),
+		/// The transfers are paused
+		Paused,
+		/// The transfers are resumed
+		Resumed,
+		/// An incoming transfer exceeds the limit of the resource. [resourceId, amount]
+		ResourceLimitReached(ResourceId, Balance),
+		/// An incoming transfer exceeds the limit of the recipient. [to, amount]
+		AccountLimitReached(AccountId, Balance),
+		/// An incoming transfer is held back. [id, to, amount]
+		TransferHeld(u64, AccountId, Balance),
+		/// [id]
+		HeldTransferReleased(u64),
+		/// [id]
+		HeldTransferDiscarded(u64),
+		/// [resourceId, topic, allowed]
+		BridgedTopicChanged(ResourceId, Vec<u8>, bool),
 	}
 }
 
@@ -78,6 +235,21 @@ decl_error! {
 		InvalidPayload,
 		InvalidFeeOption,
 		FeeOptionsMissiing,
+		/// The transfers are paused
+		TransfersPaused,
+		/// The transfer exceeds the limit of the destination chain
+		ChainLimitExceeded,
+		/// The transfer exceeds the limit of the resource
+		ResourceLimitExceeded,
+		/// The transfer exceeds the limit of the account
+		AccountLimitExceeded,
+		/// The window of a limit must be at least one block
+		InvalidLimit,
+		HeldTransferNotFound,
+		/// The bridged message targets a topic not allowed for the resource
+		TopicNotAllowed,
+		/// The topic is empty or reserved
+		InvalidTopic,
 	}
 }
 
@@ -90,8 +262,12 @@ decl_module! {
 
 		fn deposit_event() = default;
 
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			Self::prune_account_volumes(now)
+		}
+
 		/// Change extra bridge transfer fee that user should pay
-		#[weight = 195_000_000]
+		#[weight = <T as Config>::WeightInfo::sudo_change_fee()]
 		pub fn sudo_change_fee(origin, min_fee: BalanceOf<T>, fee_scale: u32, dest_id: bridge::ChainId) -> DispatchResult {
 			ensure_root(origin)?;
 			ensure!(fee_scale <= 1000u32.into(), Error::<T>::InvalidFeeOption);
@@ -100,8 +276,94 @@ decl_module! {
 			Ok(())
 		}
 
+		/// Sets or removes the limit of the outgoing transfers to a chain.
+		#[weight = <T as Config>::WeightInfo::set_chain_limit()]
+		pub fn set_chain_limit(origin, dest_id: bridge::ChainId, limit: Option<TransferLimitOf<T>>) -> DispatchResult {
+			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
+			match limit {
+				Some(limit) => {
+					ensure!(!limit.window.is_zero(), Error::<T>::InvalidLimit);
+					ChainLimits::<T>::insert(dest_id, limit);
+				}
+				None => ChainLimits::<T>::remove(dest_id),
+			}
+			Self::deposit_event(RawEvent::ChainLimitChanged(dest_id, limit));
+			Ok(())
+		}
+
+		/// Sets or removes the limit of the transfers of a resource.
+		#[weight = <T as Config>::WeightInfo::set_resource_limit()]
+		pub fn set_resource_limit(origin, rid: ResourceId, limit: Option<TransferLimitOf<T>>) -> DispatchResult {
+			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
+			match limit {
+				Some(limit) => {
+					ensure!(!limit.window.is_zero(), Error::<T>::InvalidLimit);
+					ResourceLimits::<T>::insert(rid, limit);
+				}
+				None => ResourceLimits::<T>::remove(rid),
+			}
+			Self::deposit_event(RawEvent::ResourceLimitChanged(rid, limit));
+			Ok(())
+		}
+
+		/// Sets or removes the limit of the transfers of each account.
+		#[weight = <T as Config>::WeightInfo::set_account_limit()]
+		pub fn set_account_limit(origin, limit: Option<TransferLimitOf<T>>) -> DispatchResult {
+			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
+			match limit {
+				Some(limit) => {
+					ensure!(!limit.window.is_zero(), Error::<T>::InvalidLimit);
+					AccountLimit::<T>::put(limit);
+				}
+				None => AccountLimit::<T>::kill(),
+			}
+			Self::deposit_event(RawEvent::AccountLimitChanged(limit));
+			Ok(())
+		}
+
+		/// Pauses the bridge. Outgoing transfers and incoming messages are rejected, and incoming
+		/// transfers are held back until released by the committee.
+		#[weight = <T as Config>::WeightInfo::pause()]
+		pub fn pause(origin) -> DispatchResult {
+			T::PauseOrigin::ensure_origin(origin)?;
+			Paused::put(true);
+			Self::deposit_event(RawEvent::Paused);
+			Ok(())
+		}
+
+		/// Resumes the bridge.
+		#[weight = <T as Config>::WeightInfo::resume()]
+		pub fn resume(origin) -> DispatchResult {
+			T::PauseOrigin::ensure_origin(origin)?;
+			Paused::put(false);
+			Self::deposit_event(RawEvent::Resumed);
+			Ok(())
+		}
+
+		/// Pays out a held transfer from the bridge account, regardless of the limits.
+		#[weight = <T as Config>::WeightInfo::release_held_transfer()]
+		#[transactional]
+		pub fn release_held_transfer(origin, id: u64) -> DispatchResult {
+			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
+			let held = HeldTransfers::<T>::take(id).ok_or(Error::<T>::HeldTransferNotFound)?;
+			let bridge_id = <bridge::Module<T>>::account_id();
+			<T as Config>::Currency::transfer(&bridge_id, &held.to, held.amount, AllowDeath)?;
+			Self::deposit_event(RawEvent::HeldTransferReleased(id));
+			Ok(())
+		}
+
+		/// Drops a held transfer, e.g. one forged by a compromised relayer set.
+		#[weight = <T as Config>::WeightInfo::discard_held_transfer()]
+		pub fn discard_held_transfer(origin, id: u64) -> DispatchResult {
+			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
+			ensure!(HeldTransfers::<T>::contains_key(id), Error::<T>::HeldTransferNotFound);
+			HeldTransfers::<T>::remove(id);
+			Self::deposit_event(RawEvent::HeldTransferDiscarded(id));
+			Ok(())
+		}
+
 		/// Transfers an arbitrary signed bitcoin tx to a (whitelisted) destination chain.
-		#[weight = 195_000_000]
+		#[weight = <T as Config>::WeightInfo::force_lottery_output()]
 		pub fn force_lottery_output(origin, payload: Vec<u8>, dest_id: bridge::ChainId) -> DispatchResult {
 			ensure_root(origin)?;
 			let lottery = Lottery::decode(&mut &payload[..])
@@ -110,9 +372,11 @@ decl_module! {
 		}
 
 		/// Transfers some amount of the native token to some recipient on a (whitelisted) destination chain.
-		#[weight = 195_000_000]
+		#[weight = <T as Config>::WeightInfo::transfer_native()]
+		#[transactional]
 		pub fn transfer_native(origin, amount: BalanceOf<T>, recipient: Vec<u8>, dest_id: bridge::ChainId) -> DispatchResult {
 			let source = ensure_signed(origin)?;
+			ensure!(!Self::paused(), Error::<T>::TransfersPaused);
 			ensure!(<bridge::Module<T>>::chain_whitelisted(dest_id), Error::<T>::InvalidTransfer);
 			let bridge_id = <bridge::Module<T>>::account_id();
 			ensure!(BridgeFee::<T>::contains_key(&dest_id), Error::<T>::FeeOptionsMissiing);
@@ -123,9 +387,9 @@ decl_module! {
 			} else {
 				min_fee
 			};
-			T::Currency::transfer(&source, &bridge_id, (amount + fee).into(), AllowDeath)?;
-
 			let resource_id = Self::bridge_tokenid();
+			Self::consume_limits(Some(dest_id), resource_id, &source, amount)?;
+			<T as Config>::Currency::transfer(&source, &bridge_id, (amount + fee).into(), AllowDeath)?;
 
 			<bridge::Module<T>>::transfer_fungible(dest_id, resource_id, recipient, U256::from(amount.saturated_into::<u128>()))
 		}
@@ -134,52 +398,66 @@ decl_module! {
 		// Executable calls. These can be triggered by a bridge transfer initiated on another chain
 		//
 
-		/// Executes a simple currency transfer using the bridge account as the source
-		#[weight = 195_000_000]
-		pub fn transfer(origin, to: T::AccountId, amount: BalanceOf<T>, _rid: ResourceId) -> DispatchResult {
+		/// Executes a simple currency transfer using the bridge account as the source.
+		///
+		/// The transfer is held back instead if the transfers are paused or it exceeds the limit
+		/// of the resource or the recipient.
+		#[weight = <T as Config>::WeightInfo::transfer()]
+		#[transactional]
+		pub fn transfer(origin, to: T::AccountId, amount: BalanceOf<T>, rid: ResourceId) -> DispatchResult {
 			let source = T::BridgeOrigin::ensure_origin(origin)?;
+			if Self::paused() {
+				Self::hold_transfer(to, amount, rid);
+				return Ok(());
+			}
+			if let Err(err) = Self::consume_limits(None, rid, &to, amount) {
+				if matches!(err, Error::<T>::AccountLimitExceeded) {
+					Self::deposit_event(RawEvent::AccountLimitReached(to.clone(), amount));
+				} else {
+					Self::deposit_event(RawEvent::ResourceLimitReached(rid, amount));
+				}
+				Self::hold_transfer(to, amount, rid);
+				return Ok(());
+			}
 			<T as Config>::Currency::transfer(&source, &to, amount.into(), AllowDeath)?;
 			Ok(())
 		}
 
-		/// This can be called by the bridge to demonstrate an arbitrary call from a proposal.
-		#[weight = 195_000_000]
-		pub fn lottery_handler(origin, metadata: Vec<u8>, _rid: ResourceId) -> DispatchResult {
+		/// Pushes a message bridged from another chain to the message queue.
+		///
+		/// The metadata is a versioned envelope (see [`envelope`]). The legacy lottery commands
+		/// are always accepted, while the generic messages must target a topic allowed for the
+		/// resource. Rejected while the bridge is paused.
+		#[weight = <T as Config>::WeightInfo::lottery_handler()]
+		pub fn lottery_handler(origin, metadata: Vec<u8>, rid: ResourceId) -> DispatchResult {
 			T::BridgeOrigin::ensure_origin(origin)?;
+			ensure!(!Self::paused(), Error::<T>::TransfersPaused);
+
+			let is_legacy = metadata.first().map_or(false, |v| *v < envelope::VERSION_1);
+			let message = envelope::decode(&metadata).map_err(|_| Error::<T>::InvalidCommand)?;
+			ensure!(
+				is_legacy || Self::bridged_topics(rid, &message.topic),
+				Error::<T>::TopicNotAllowed
+			);
+			pallet_mq::Pallet::<T>::dispatch_message(Message::new(
+				Self::message_origin(),
+				message.topic,
+				message.payload,
+			));
+			Ok(())
+		}
 
-			let op = u8::from_be_bytes(<[u8; 1]>::try_from(&metadata[..1]).map_err(|_| Error::<T>::InvalidCommand)?);
-			if op == 0 {
-				ensure!(
-					metadata.len() == 13,
-					Error::<T>::InvalidCommand
-				);
-
-				Self::push_message(LotteryEvent::NewRound(
-					u32::from_be_bytes(<[u8; 4]>::try_from(&metadata[1..5]).map_err(|_| Error::<T>::InvalidCommand)?),	// roundId
-					u32::from_be_bytes(<[u8; 4]>::try_from(&metadata[5..9]).map_err(|_| Error::<T>::InvalidCommand)?),	// totalCount
-					u32::from_be_bytes(<[u8; 4]>::try_from(&metadata[9..]).map_err(|_| Error::<T>::InvalidCommand)?)	// winnerCount
-				));
-			} else if op == 1 {
-				ensure!(
-					metadata.len() > 13,
-					Error::<T>::InvalidCommand
-				);
-
-				let address_len: usize = u32::from_be_bytes(<[u8; 4]>::try_from(&metadata[9..13]).map_err(|_| Error::<T>::InvalidCommand)?).saturated_into();
-				ensure!(
-					metadata.len() == (13 + address_len),
-					Error::<T>::InvalidCommand
-				);
-
-				Self::push_message(LotteryEvent::OpenBox(
-					u32::from_be_bytes(<[u8; 4]>::try_from(&metadata[1..5]).map_err(|_| Error::<T>::InvalidCommand)?),	// roundId
-					u32::from_be_bytes(<[u8; 4]>::try_from(&metadata[5..9]).map_err(|_| Error::<T>::InvalidCommand)?),	// tokenId
-					metadata[13..].to_vec()						// btcAddress
-				));
+		/// Allows or disallows the bridged messages of a resource to target a topic.
+		#[weight = <T as Config>::WeightInfo::set_bridged_topic()]
+		pub fn set_bridged_topic(origin, rid: ResourceId, topic: Vec<u8>, allowed: bool) -> DispatchResult {
+			T::BridgeCommitteeOrigin::ensure_origin(origin)?;
+			ensure!(Topic::new(topic.clone()).is_valid(), Error::<T>::InvalidTopic);
+			if allowed {
+				BridgedTopics::insert(rid, &topic, true);
 			} else {
-				fail!(Error::<T>::InvalidCommand);
+				BridgedTopics::remove(rid, &topic);
 			}
-
+			Self::deposit_event(RawEvent::BridgedTopicChanged(rid, topic, allowed));
 			Ok(())
 		}
 
@@ -200,7 +478,7 @@ decl_module! {
 }
 
 use pallet_phala::OnMessageReceived;
-use phala_types::messaging::{BindTopic, Lottery, Message, MessageOrigin};
+use phala_types::messaging::{BindTopic, Lottery, Message, MessageOrigin, Topic};
 
 impl<T: Config> Module<T> {
 	pub fn lottery_output(payload: &Lottery, dest_id: bridge::ChainId) -> DispatchResult {
@@ -213,6 +491,102 @@ impl<T: Config> Module<T> {
 		<bridge::Module<T>>::transfer_generic(dest_id, resource_id, metadata)
 	}
 
+	/// Records an outgoing (with `dest_id`, sent by `who`) or incoming (received by `who`)
+	/// transfer to the volumes of the limits. Fails without any change if it exceeds any of the
+	/// limits.
+	fn consume_limits(
+		dest_id: Option<bridge::ChainId>,
+		rid: ResourceId,
+		who: &T::AccountId,
+		amount: BalanceOf<T>,
+	) -> Result<(), Error<T>> {
+		let now = <frame_system::Pallet<T>>::block_number();
+		let mut chain_volume = None;
+		if let Some(dest_id) = dest_id {
+			if let Some(limit) = Self::chain_limits(dest_id) {
+				let mut volume = Self::chain_volumes(dest_id);
+				ensure!(
+					volume.try_add(&limit, amount, now),
+					Error::<T>::ChainLimitExceeded
+				);
+				chain_volume = Some((dest_id, volume));
+			}
+		}
+		let mut resource_volume = None;
+		if let Some(limit) = Self::resource_limits(rid) {
+			let mut volume = Self::resource_volumes(rid);
+			ensure!(
+				volume.try_add(&limit, amount, now),
+				Error::<T>::ResourceLimitExceeded
+			);
+			resource_volume = Some(volume);
+		}
+		if let Some(limit) = Self::account_limit() {
+			let mut volume = Self::account_volumes(who);
+			let latest = volume.buckets.last().map(|(start, _)| *start);
+			ensure!(
+				volume.try_add(&limit, amount, now),
+				Error::<T>::AccountLimitExceeded
+			);
+			// A new bucket was opened, so the volume now leaves the window later
+			if let Some((start, _)) = volume.buckets.last() {
+				if latest != Some(*start) {
+					AccountVolumeExpiry::<T>::append(start.saturating_add(limit.window), who);
+				}
+			}
+			AccountVolumes::<T>::insert(who, volume);
+		}
+		if let Some(volume) = resource_volume {
+			ResourceVolumes::<T>::insert(rid, volume);
+		}
+		if let Some((dest_id, volume)) = chain_volume {
+			ChainVolumes::<T>::insert(dest_id, volume);
+		}
+		Ok(())
+	}
+
+	/// Prunes the volumes of the accounts scheduled in `AccountVolumeExpiry` at `now`, removing
+	/// the empty ones. A volume still in the window (the limit was widened since it was
+	/// scheduled) is rescheduled by its latest bucket.
+	fn prune_account_volumes(now: T::BlockNumber) -> Weight {
+		let accounts = AccountVolumeExpiry::<T>::take(now);
+		if accounts.is_empty() {
+			return T::DbWeight::get().reads(1);
+		}
+		let limit = Self::account_limit();
+		let count = accounts.len() as Weight;
+		for who in accounts {
+			if let Some(limit) = &limit {
+				let mut volume = Self::account_volumes(&who);
+				volume.prune(limit, now);
+				if let Some((start, _)) = volume.buckets.last() {
+					AccountVolumeExpiry::<T>::append(start.saturating_add(limit.window), &who);
+					AccountVolumes::<T>::insert(&who, volume);
+					continue;
+				}
+			}
+			AccountVolumes::<T>::remove(&who);
+		}
+		T::DbWeight::get().reads_writes(2 + count, 1 + 2 * count)
+	}
+
+	fn hold_transfer(to: T::AccountId, amount: BalanceOf<T>, resource_id: ResourceId) {
+		let id = NextHeldTransferId::mutate(|id| {
+			let current = *id;
+			*id += 1;
+			current
+		});
+		HeldTransfers::<T>::insert(
+			id,
+			HeldTransfer {
+				to: to.clone(),
+				amount,
+				resource_id,
+			},
+		);
+		Self::deposit_event(RawEvent::TransferHeld(id, to, amount));
+	}
+
 	fn push_message(payload: impl Encode + BindTopic) {
 		pallet_mq::Pallet::<T>::push_bound_message(Self::message_origin(), payload);
 	}
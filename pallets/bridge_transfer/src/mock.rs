@@ -3,6 +3,7 @@
 use super::*;
 
 use frame_support::{ord_parameter_types, parameter_types, weights::Weight, PalletId};
+use frame_support_test::TestRandomness;
 use frame_system::{self as system};
 use sp_core::hashing::blake2_128;
 use sp_core::H256;
@@ -34,7 +35,7 @@ frame_support::construct_runtime!(
 		Bridge: bridge::{Pallet, Call, Storage, Event<T>},
 		BridgeTransfer: bride_transfer::{Pallet, Call, Config, Storage, Event<T>},
 		PhalaMq: mq::{Pallet, Call, Event, Storage},
-		PhalaRegistry: reg::{Pallet, Call, Event, Storage},
+		PhalaRegistry: reg::{Pallet, Call, Event<T>, Storage},
 		Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
 	}
 );
@@ -97,6 +98,7 @@ impl pallet_balances::Config for Test {
 parameter_types! {
 	pub const TestChainId: u8 = 5;
 	pub const ProposalLifetime: u64 = 100;
+	pub const MaxProposalsPerBatch: u32 = 4;
 }
 
 impl bridge::Config for Test {
@@ -105,12 +107,17 @@ impl bridge::Config for Test {
 	type Proposal = Call;
 	type ChainId = TestChainId;
 	type ProposalLifetime = ProposalLifetime;
+	type Currency = Balances;
+	type MaxProposalsPerBatch = MaxProposalsPerBatch;
+	type WeightInfo = ();
 }
 
 impl Config for Test {
 	type Event = Event;
 	type BridgeOrigin = bridge::EnsureBridge<Test>;
 	type Currency = Balances;
+	type PauseOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type WeightInfo = ();
 }
 
 impl mq::Config for Test {
@@ -118,9 +125,21 @@ impl mq::Config for Test {
 	type QueueNotifyConfig = ();
 }
 
+parameter_types! {
+	pub const MaxGatekeepers: u32 = 5;
+	pub const MinGatekeepers: u32 = 1;
+	pub const GatekeeperRotationDelay: u64 = 10;
+}
+
 impl reg::Config for Test {
 	type Event = Event;
 	type UnixTime = Timestamp;
+	type Randomness = TestRandomness<Self>;
+	type GatekeeperOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type ContractAdminOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type MaxGatekeepers = MaxGatekeepers;
+	type MinGatekeepers = MinGatekeepers;
+	type GatekeeperRotationDelay = GatekeeperRotationDelay;
 }
 
 impl pallet_timestamp::Config for Test {
@@ -2,16 +2,21 @@
 
 use super::mock::{
 	assert_events, balances, event_exists, expect_event, new_test_ext, Balances, Bridge,
-	BridgeTransfer, Call, Event, Origin, ProposalLifetime, Test, ENDOWED_BALANCE, RELAYER_A,
-	RELAYER_B, RELAYER_C,
+	BridgeTransfer, Call, Event, Origin, ProposalLifetime, System, Test, ENDOWED_BALANCE,
+	RELAYER_A, RELAYER_B, RELAYER_C,
 };
 use super::*;
 use frame_support::dispatch::DispatchError;
+use frame_support::traits::OnInitialize;
 use frame_support::{assert_noop, assert_ok};
 
 use codec::Encode;
 
-use phala_types::messaging::Lottery;
+use phala_pallets::pallet_mq;
+use phala_types::messaging::{Lottery, Message};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::envelope::{self, BridgedMessage, EnvelopeError};
 
 const TEST_THRESHOLD: u32 = 2;
 
@@ -156,7 +161,7 @@ fn create_sucessful_transfer_proposal() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = bridge::ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![],
@@ -173,7 +178,7 @@ fn create_sucessful_transfer_proposal() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = bridge::ProposalVotes {
 			votes_for: vec![RELAYER_A],
 			votes_against: vec![RELAYER_B],
@@ -190,7 +195,7 @@ fn create_sucessful_transfer_proposal() {
 			r_id,
 			Box::new(proposal.clone())
 		));
-		let prop = Bridge::votes(src_id, (prop_id.clone(), proposal.clone())).unwrap();
+		let prop = Bridge::votes(src_id, (prop_id, Bridge::proposal_hash(&proposal))).unwrap();
 		let expected = bridge::ProposalVotes {
 			votes_for: vec![RELAYER_A, RELAYER_C],
 			votes_against: vec![RELAYER_B],
@@ -219,3 +224,582 @@ fn create_sucessful_transfer_proposal() {
 		]);
 	})
 }
+
+#[test]
+fn transfer_native_limits() {
+	new_test_ext().execute_with(|| {
+		let dest_chain = 0;
+		let resource_id = BridgeTransfer::bridge_tokenid();
+		let recipient = vec![99];
+
+		assert_ok!(Bridge::whitelist_chain(Origin::root(), dest_chain));
+		assert_ok!(BridgeTransfer::sudo_change_fee(
+			Origin::root(),
+			0,
+			0,
+			dest_chain
+		));
+		assert_noop!(
+			BridgeTransfer::set_chain_limit(
+				Origin::root(),
+				dest_chain,
+				Some(TransferLimit { cap: 100, window: 0 })
+			),
+			Error::<Test>::InvalidLimit
+		);
+		assert_ok!(BridgeTransfer::set_chain_limit(
+			Origin::root(),
+			dest_chain,
+			Some(TransferLimit {
+				cap: 100,
+				window: 10
+			})
+		));
+		assert_ok!(BridgeTransfer::set_resource_limit(
+			Origin::root(),
+			resource_id,
+			Some(TransferLimit {
+				cap: 150,
+				window: 20
+			})
+		));
+
+		// The chain limit is reached in the window
+		assert_ok!(BridgeTransfer::transfer_native(
+			Origin::signed(RELAYER_A),
+			60,
+			recipient.clone(),
+			dest_chain,
+		));
+		assert_noop!(
+			BridgeTransfer::transfer_native(
+				Origin::signed(RELAYER_A),
+				50,
+				recipient.clone(),
+				dest_chain,
+			),
+			Error::<Test>::ChainLimitExceeded
+		);
+		assert_ok!(BridgeTransfer::transfer_native(
+			Origin::signed(RELAYER_A),
+			40,
+			recipient.clone(),
+			dest_chain,
+		));
+
+		// A new chain window starts, but the resource limit is still reached
+		System::set_block_number(11);
+		assert_noop!(
+			BridgeTransfer::transfer_native(
+				Origin::signed(RELAYER_A),
+				60,
+				recipient.clone(),
+				dest_chain,
+			),
+			Error::<Test>::ResourceLimitExceeded
+		);
+		assert_ok!(BridgeTransfer::transfer_native(
+			Origin::signed(RELAYER_A),
+			50,
+			recipient.clone(),
+			dest_chain,
+		));
+		assert_eq!(BridgeTransfer::chain_volumes(dest_chain).total(), 50);
+		assert_eq!(BridgeTransfer::resource_volumes(resource_id).total(), 150);
+
+		// Removes the limits
+		assert_ok!(BridgeTransfer::set_chain_limit(Origin::root(), dest_chain, None));
+		assert_ok!(BridgeTransfer::set_resource_limit(Origin::root(), resource_id, None));
+		assert_ok!(BridgeTransfer::transfer_native(
+			Origin::signed(RELAYER_A),
+			1000,
+			recipient.clone(),
+			dest_chain,
+		));
+		assert_eq!(
+			Balances::free_balance(RELAYER_A),
+			ENDOWED_BALANCE - 60 - 40 - 50 - 1000
+		);
+	})
+}
+
+#[test]
+fn sliding_window_limits() {
+	let limit = TransferLimit {
+		cap: 100u64,
+		window: 48u64,
+	};
+	let mut volume = TransferVolume::default();
+	// Buckets of 2 blocks
+	assert!(volume.try_add(&limit, 60, 45));
+	assert!(volume.try_add(&limit, 40, 47));
+	assert_eq!(volume.buckets, vec![(44, 60), (46, 40)]);
+	assert!(!volume.try_add(&limit, 10, 91));
+	// Only the first bucket leaves the window, while a fixed window starting at block 45 would
+	// be reset
+	assert!(!volume.try_add(&limit, 61, 93));
+	assert!(volume.try_add(&limit, 60, 93));
+	assert_eq!(volume.buckets, vec![(46, 40), (92, 60)]);
+	assert!(volume.try_add(&limit, 40, 94));
+	assert_eq!(volume.buckets, vec![(92, 60), (94, 40)]);
+	assert_eq!(volume.total(), 100);
+
+	// Never more than `VOLUME_BUCKETS` buckets
+	let mut volume = TransferVolume::default();
+	for block in 0..1000u64 {
+		assert!(volume.try_add(&limit, 1, block));
+		assert!(volume.buckets.len() <= VOLUME_BUCKETS as usize);
+	}
+}
+
+#[test]
+fn account_limits() {
+	new_test_ext().execute_with(|| {
+		let dest_chain = 0;
+		let resource_id = BridgeTransfer::bridge_tokenid();
+		let bridge_id: u64 = Bridge::account_id();
+
+		assert_ok!(Bridge::whitelist_chain(Origin::root(), dest_chain));
+		assert_ok!(BridgeTransfer::sudo_change_fee(
+			Origin::root(),
+			0,
+			0,
+			dest_chain
+		));
+		assert_noop!(
+			BridgeTransfer::set_account_limit(
+				Origin::signed(RELAYER_A),
+				Some(TransferLimit {
+					cap: 100,
+					window: 10
+				})
+			),
+			DispatchError::BadOrigin
+		);
+		assert_ok!(BridgeTransfer::set_account_limit(
+			Origin::root(),
+			Some(TransferLimit {
+				cap: 100,
+				window: 10
+			})
+		));
+
+		// Outgoing transfers are limited by the sender
+		assert_ok!(BridgeTransfer::transfer_native(
+			Origin::signed(RELAYER_A),
+			80,
+			vec![99],
+			dest_chain,
+		));
+		assert_noop!(
+			BridgeTransfer::transfer_native(Origin::signed(RELAYER_A), 30, vec![99], dest_chain),
+			Error::<Test>::AccountLimitExceeded
+		);
+		assert_eq!(BridgeTransfer::account_volumes(RELAYER_A).total(), 80);
+
+		// Incoming transfers are limited by the recipient
+		assert_ok!(BridgeTransfer::transfer(
+			Origin::signed(bridge_id),
+			RELAYER_B,
+			100,
+			resource_id,
+		));
+		assert_ok!(BridgeTransfer::transfer(
+			Origin::signed(bridge_id),
+			RELAYER_B,
+			1,
+			resource_id,
+		));
+		assert_ok!(BridgeTransfer::transfer(
+			Origin::signed(bridge_id),
+			RELAYER_C,
+			1,
+			resource_id,
+		));
+		assert_eq!(Balances::free_balance(RELAYER_B), 100);
+		assert_eq!(Balances::free_balance(RELAYER_C), 1);
+		event_exists(RawEvent::AccountLimitReached(RELAYER_B, 1));
+		event_exists(RawEvent::TransferHeld(0, RELAYER_B, 1));
+
+		assert_ok!(BridgeTransfer::set_account_limit(Origin::root(), None));
+		assert_ok!(BridgeTransfer::transfer_native(
+			Origin::signed(RELAYER_A),
+			30,
+			vec![99],
+			dest_chain,
+		));
+	})
+}
+
+#[test]
+fn account_volumes_pruned() {
+	new_test_ext().execute_with(|| {
+		let dest_chain = 0;
+		assert_ok!(Bridge::whitelist_chain(Origin::root(), dest_chain));
+		assert_ok!(BridgeTransfer::sudo_change_fee(
+			Origin::root(),
+			0,
+			0,
+			dest_chain
+		));
+		assert_ok!(BridgeTransfer::set_account_limit(
+			Origin::root(),
+			Some(TransferLimit {
+				cap: 100,
+				window: 10
+			})
+		));
+
+		// Transfers at blocks 1 and 5 leave the window at blocks 11 and 15
+		assert_ok!(BridgeTransfer::transfer_native(
+			Origin::signed(RELAYER_A),
+			10,
+			vec![99],
+			dest_chain,
+		));
+		System::set_block_number(5);
+		assert_ok!(BridgeTransfer::transfer_native(
+			Origin::signed(RELAYER_A),
+			20,
+			vec![99],
+			dest_chain,
+		));
+		assert_eq!(BridgeTransfer::account_volume_expiry(11), vec![RELAYER_A]);
+		assert_eq!(BridgeTransfer::account_volume_expiry(15), vec![RELAYER_A]);
+
+		// Only the first transfer is pruned at block 11
+		System::set_block_number(11);
+		BridgeTransfer::on_initialize(11);
+		assert!(!AccountVolumeExpiry::<Test>::contains_key(11));
+		assert_eq!(BridgeTransfer::account_volumes(RELAYER_A).total(), 20);
+
+		// The entry is removed once all the transfers left the window
+		System::set_block_number(15);
+		BridgeTransfer::on_initialize(15);
+		assert!(!AccountVolumes::<Test>::contains_key(RELAYER_A));
+		assert!(!AccountVolumeExpiry::<Test>::contains_key(15));
+
+		// Without a limit, the scheduled volumes are removed outright
+		System::set_block_number(16);
+		assert_ok!(BridgeTransfer::transfer_native(
+			Origin::signed(RELAYER_A),
+			10,
+			vec![99],
+			dest_chain,
+		));
+		assert_ok!(BridgeTransfer::set_account_limit(Origin::root(), None));
+		System::set_block_number(26);
+		BridgeTransfer::on_initialize(26);
+		assert!(!AccountVolumes::<Test>::contains_key(RELAYER_A));
+	})
+}
+
+#[test]
+fn pause_transfers() {
+	new_test_ext().execute_with(|| {
+		let dest_chain = 0;
+		let resource_id = BridgeTransfer::bridge_tokenid();
+		let bridge_id: u64 = Bridge::account_id();
+
+		assert_ok!(Bridge::whitelist_chain(Origin::root(), dest_chain));
+		assert_ok!(BridgeTransfer::sudo_change_fee(
+			Origin::root(),
+			0,
+			0,
+			dest_chain
+		));
+		assert_noop!(
+			BridgeTransfer::pause(Origin::signed(RELAYER_A)),
+			DispatchError::BadOrigin
+		);
+		assert_ok!(BridgeTransfer::pause(Origin::root()));
+
+		// Outgoing transfers are rejected
+		assert_noop!(
+			BridgeTransfer::transfer_native(Origin::signed(RELAYER_A), 10, vec![99], dest_chain),
+			Error::<Test>::TransfersPaused
+		);
+		// Incoming messages are rejected
+		let metadata = envelope::encode(
+			&BridgedMessage {
+				topic: LotteryEvent::TOPIC.to_vec(),
+				payload: LotteryEvent::NewRound(1, 10, 1).encode(),
+			},
+			envelope::CODEC_SCALE,
+		);
+		assert_noop!(
+			BridgeTransfer::lottery_handler(
+				Origin::signed(bridge_id),
+				metadata,
+				BridgeTransfer::bridge_lotteryid()
+			),
+			Error::<Test>::TransfersPaused
+		);
+		// Incoming transfers are held back
+		assert_ok!(BridgeTransfer::transfer(
+			Origin::signed(bridge_id),
+			RELAYER_B,
+			10,
+			resource_id,
+		));
+		assert_eq!(Balances::free_balance(RELAYER_B), 0);
+		assert_eq!(
+			BridgeTransfer::held_transfers(0),
+			Some(HeldTransfer {
+				to: RELAYER_B,
+				amount: 10,
+				resource_id,
+			})
+		);
+
+		assert_ok!(BridgeTransfer::resume(Origin::root()));
+		assert_ok!(BridgeTransfer::transfer_native(
+			Origin::signed(RELAYER_A),
+			10,
+			vec![99],
+			dest_chain
+		));
+
+		assert_events(vec![
+			Event::BridgeTransfer(RawEvent::Paused),
+			Event::BridgeTransfer(RawEvent::TransferHeld(0, RELAYER_B, 10)),
+			Event::BridgeTransfer(RawEvent::Resumed),
+		]);
+	})
+}
+
+#[test]
+fn incoming_transfers_held_by_limits() {
+	new_test_ext().execute_with(|| {
+		let resource_id = BridgeTransfer::bridge_tokenid();
+		let bridge_id: u64 = Bridge::account_id();
+
+		assert_ok!(BridgeTransfer::set_resource_limit(
+			Origin::root(),
+			resource_id,
+			Some(TransferLimit {
+				cap: 100,
+				window: 10
+			})
+		));
+		assert_ok!(BridgeTransfer::transfer(
+			Origin::signed(bridge_id),
+			RELAYER_B,
+			80,
+			resource_id,
+		));
+		// Exceeds the limit
+		assert_ok!(BridgeTransfer::transfer(
+			Origin::signed(bridge_id),
+			RELAYER_B,
+			30,
+			resource_id,
+		));
+		assert_ok!(BridgeTransfer::transfer(
+			Origin::signed(bridge_id),
+			RELAYER_C,
+			50,
+			resource_id,
+		));
+		assert_eq!(Balances::free_balance(RELAYER_B), 80);
+		assert_eq!(Balances::free_balance(bridge_id), ENDOWED_BALANCE - 80);
+
+		// The committee releases one and discards the other
+		assert_noop!(
+			BridgeTransfer::release_held_transfer(Origin::signed(RELAYER_A), 0),
+			DispatchError::BadOrigin
+		);
+		assert_ok!(BridgeTransfer::release_held_transfer(Origin::root(), 0));
+		assert_ok!(BridgeTransfer::discard_held_transfer(Origin::root(), 1));
+		assert_noop!(
+			BridgeTransfer::release_held_transfer(Origin::root(), 1),
+			Error::<Test>::HeldTransferNotFound
+		);
+		assert_eq!(Balances::free_balance(RELAYER_B), 110);
+		assert_eq!(Balances::free_balance(RELAYER_C), 0);
+		assert_eq!(Balances::free_balance(bridge_id), ENDOWED_BALANCE - 110);
+
+		event_exists(RawEvent::ResourceLimitReached(resource_id, 30));
+		event_exists(RawEvent::TransferHeld(0, RELAYER_B, 30));
+		event_exists(RawEvent::ResourceLimitReached(resource_id, 50));
+		event_exists(RawEvent::TransferHeld(1, RELAYER_C, 50));
+		assert_events(vec![
+			Event::BridgeTransfer(RawEvent::HeldTransferReleased(0)),
+			Event::BridgeTransfer(RawEvent::HeldTransferDiscarded(1)),
+		]);
+	})
+}
+
+fn random_message(rng: &mut StdRng) -> BridgedMessage {
+	let topic_len = rng.gen_range(0, 80);
+	let payload_len = rng.gen_range(0, 300);
+	BridgedMessage {
+		topic: (0..topic_len).map(|_| rng.gen()).collect(),
+		payload: (0..payload_len).map(|_| rng.gen()).collect(),
+	}
+}
+
+#[test]
+fn decode_legacy_envelope() {
+	let new_round = hex::decode("00000000010000000a00000002").unwrap();
+	assert_eq!(
+		envelope::decode(&new_round),
+		Ok(BridgedMessage {
+			topic: LotteryEvent::TOPIC.to_vec(),
+			payload: LotteryEvent::NewRound(1, 10, 2).encode(),
+		})
+	);
+	let open_box = hex::decode("01000000010000000200000003616263").unwrap();
+	assert_eq!(
+		envelope::decode(&open_box),
+		Ok(BridgedMessage {
+			topic: LotteryEvent::TOPIC.to_vec(),
+			payload: LotteryEvent::OpenBox(1, 2, b"abc".to_vec()).encode(),
+		})
+	);
+	// Wrong lengths
+	assert_eq!(envelope::decode(&new_round[..12]), Err(EnvelopeError::Malformed));
+	assert_eq!(envelope::decode(&open_box[..13]), Err(EnvelopeError::Malformed));
+	assert_eq!(envelope::decode(&[]), Err(EnvelopeError::Empty));
+	assert_eq!(envelope::decode(&[2]), Err(EnvelopeError::UnknownVersion));
+	assert_eq!(
+		envelope::decode(&[envelope::VERSION_1, 2]),
+		Err(EnvelopeError::UnknownCodec)
+	);
+}
+
+#[test]
+fn decode_abi_envelope() {
+	// abi.encode(bytes("topic"), bytes("hi")) built by an EVM contract
+	let body = hex::decode(concat!(
+		"0000000000000000000000000000000000000000000000000000000000000040",
+		"0000000000000000000000000000000000000000000000000000000000000080",
+		"0000000000000000000000000000000000000000000000000000000000000005",
+		"746f706963000000000000000000000000000000000000000000000000000000",
+		"0000000000000000000000000000000000000000000000000000000000000002",
+		"6869000000000000000000000000000000000000000000000000000000000000",
+	))
+	.unwrap();
+	let message = BridgedMessage {
+		topic: b"topic".to_vec(),
+		payload: b"hi".to_vec(),
+	};
+	let mut metadata = vec![envelope::VERSION_1, envelope::CODEC_ABI];
+	metadata.extend_from_slice(&body);
+	assert_eq!(envelope::encode(&message, envelope::CODEC_ABI), metadata);
+	assert_eq!(envelope::decode(&metadata), Ok(message));
+}
+
+#[test]
+fn fuzz_envelope_round_trip() {
+	let mut rng = StdRng::seed_from_u64(0);
+	for _ in 0..1000 {
+		let message = random_message(&mut rng);
+		for codec in [envelope::CODEC_SCALE, envelope::CODEC_ABI].iter() {
+			let metadata = envelope::encode(&message, *codec);
+			assert_eq!(envelope::decode(&metadata), Ok(message.clone()));
+		}
+	}
+}
+
+#[test]
+fn fuzz_envelope_decoder() {
+	let mut rng = StdRng::seed_from_u64(1);
+	for _ in 0..1000 {
+		// Random bytes behind each of the known headers
+		let mut metadata = match rng.gen_range(0, 4) {
+			0 => vec![envelope::LEGACY_NEW_ROUND],
+			1 => vec![envelope::LEGACY_OPEN_BOX],
+			2 => vec![envelope::VERSION_1, envelope::CODEC_SCALE],
+			_ => vec![envelope::VERSION_1, envelope::CODEC_ABI],
+		};
+		let len = rng.gen_range(0, 200);
+		metadata.extend((0..len).map(|_| rng.gen::<u8>()));
+		let _ = envelope::decode(&metadata);
+
+		// Truncated and mutated valid envelopes
+		let codec = rng.gen_range(0, 2);
+		let mut metadata = envelope::encode(&random_message(&mut rng), codec);
+		let at = rng.gen_range(0, metadata.len());
+		let mut truncated = metadata.clone();
+		truncated.truncate(at);
+		let _ = envelope::decode(&truncated);
+		metadata[at] = rng.gen();
+		let _ = envelope::decode(&metadata);
+	}
+}
+
+#[test]
+fn bridged_topics() {
+	new_test_ext().execute_with(|| {
+		let resource_id = BridgeTransfer::bridge_lotteryid();
+		let bridge_id: u64 = Bridge::account_id();
+		let message = BridgedMessage {
+			topic: b"phala/contract/command".to_vec(),
+			payload: vec![1, 2, 3],
+		};
+		let metadata = envelope::encode(&message, envelope::CODEC_SCALE);
+
+		assert_noop!(
+			BridgeTransfer::lottery_handler(
+				Origin::signed(bridge_id),
+				metadata.clone(),
+				resource_id
+			),
+			Error::<Test>::TopicNotAllowed
+		);
+		assert_noop!(
+			BridgeTransfer::set_bridged_topic(
+				Origin::signed(RELAYER_A),
+				resource_id,
+				message.topic.clone(),
+				true
+			),
+			DispatchError::BadOrigin
+		);
+		assert_noop!(
+			BridgeTransfer::set_bridged_topic(Origin::root(), resource_id, vec![], true),
+			Error::<Test>::InvalidTopic
+		);
+		assert_ok!(BridgeTransfer::set_bridged_topic(
+			Origin::root(),
+			resource_id,
+			message.topic.clone(),
+			true
+		));
+		assert_ok!(BridgeTransfer::lottery_handler(
+			Origin::signed(bridge_id),
+			metadata.clone(),
+			resource_id
+		));
+		event_exists(pallet_mq::Event::OutboundMessage(Message::new(
+			BridgeTransfer::message_origin(),
+			message.topic.clone(),
+			message.payload.clone(),
+		)));
+		// Only allowed for the resource
+		assert_noop!(
+			BridgeTransfer::lottery_handler(
+				Origin::signed(bridge_id),
+				metadata.clone(),
+				BridgeTransfer::bridge_tokenid()
+			),
+			Error::<Test>::TopicNotAllowed
+		);
+
+		assert_ok!(BridgeTransfer::set_bridged_topic(
+			Origin::root(),
+			resource_id,
+			message.topic.clone(),
+			false
+		));
+		assert_noop!(
+			BridgeTransfer::lottery_handler(Origin::signed(bridge_id), metadata, resource_id),
+			Error::<Test>::TopicNotAllowed
+		);
+		assert_noop!(
+			BridgeTransfer::lottery_handler(Origin::signed(bridge_id), vec![2], resource_id),
+			Error::<Test>::InvalidCommand
+		);
+	})
+}
@@ -154,7 +154,9 @@ impl<T: Config> Module<T> {
 	fn transfer(to: T::AccountId, kitty_id: T::Hash) -> Result {
 		let sender = Self::account_id();
 
-		let _owner = Self::owner_of(kitty_id).ok_or("No owner for this kitty")?;
+		// The kitty must still be in the custody of the pallet
+		let owner = Self::owner_of(kitty_id).ok_or(Error::<T>::InvalidKitty)?;
+		ensure!(owner == sender, Error::<T>::InvalidOwner);
 		let owned_kitty_count_from = Self::owned_kitties_count(&sender);
 		let owned_kitty_count_to = Self::owned_kitties_count(&to);
 		let new_owned_kitty_count_to = owned_kitty_count_to.checked_add(1)
@@ -0,0 +1,51 @@
+//! Runtime API definition for the mining staking pallet.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Codec, Decode, Encode};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_runtime::RuntimeDebug;
+use sp_std::prelude::*;
+
+/// The stake of a staker to a single target
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct TargetPosition<AccountId, Balance> {
+    pub target: AccountId,
+    /// The stake applied in the previous rounds
+    pub staked: Balance,
+    /// The stake to be applied at the end of the current round
+    pub pending_staking: Balance,
+    /// The stake to be removed at the end of the current round
+    pub pending_unstaking: Balance,
+    /// The unclaimed rewards earned by the stake
+    pub rewards: Balance,
+}
+
+/// The full position of a staker
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct StakerPosition<AccountId, Balance> {
+    /// The tokens deposited to the wallet, including the staked ones
+    pub wallet: Balance,
+    /// The wallet tokens locked by the pending staking
+    pub locked: Balance,
+    /// The unstaked tokens still in the unbonding period
+    pub unbonding: Balance,
+    pub auto_compound: bool,
+    pub targets: Vec<TargetPosition<AccountId, Balance>>,
+}
+
+sp_api::decl_runtime_apis! {
+    pub trait MiningStakingApi<AccountId, Balance> where
+        AccountId: Codec,
+        Balance: Codec,
+    {
+        /// Returns the wallet, the locked and unbonding tokens, and the stake to each target of
+        /// `who`.
+        fn staker_position(who: AccountId) -> StakerPosition<AccountId, Balance>;
+    }
+}
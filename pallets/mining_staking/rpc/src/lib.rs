@@ -0,0 +1,69 @@
+//! RPC interface for the mining staking pallet.
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use codec::Codec;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+pub use pallet_mining_staking_rpc_runtime_api::{
+    MiningStakingApi as MiningStakingRuntimeApi, StakerPosition, TargetPosition,
+};
+
+#[rpc]
+pub trait MiningStakingApi<BlockHash, AccountId, Balance> {
+    /// Returns the wallet, the locked and unbonding tokens, and the stake to each target of
+    /// `who` at the block `at` (the best block by default).
+    #[rpc(name = "pha_getStakerPosition")]
+    fn staker_position(
+        &self,
+        who: AccountId,
+        at: Option<BlockHash>,
+    ) -> Result<StakerPosition<AccountId, Balance>>;
+}
+
+/// Implements the MiningStakingApi RPC trait for interacting with the mining staking pallet.
+pub struct MiningStaking<C, Block> {
+    client: Arc<C>,
+    _marker: PhantomData<Block>,
+}
+
+impl<C, Block> MiningStaking<C, Block> {
+    pub fn new(client: Arc<C>) -> Self {
+        Self {
+            client,
+            _marker: Default::default(),
+        }
+    }
+}
+
+/// Error code of the failed runtime calls.
+const RUNTIME_ERROR: i64 = 1;
+
+impl<C, Block, AccountId, Balance> MiningStakingApi<<Block as BlockT>::Hash, AccountId, Balance>
+    for MiningStaking<C, Block>
+where
+    Block: BlockT,
+    C: 'static + ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync,
+    C::Api: MiningStakingRuntimeApi<Block, AccountId, Balance>,
+    AccountId: Codec,
+    Balance: Codec,
+{
+    fn staker_position(
+        &self,
+        who: AccountId,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<StakerPosition<AccountId, Balance>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.staker_position(&at, who).map_err(|e| RpcError {
+            code: ErrorCode::ServerError(RUNTIME_ERROR),
+            message: "Unable to query the staker position.".into(),
+            data: Some(format!("{:?}", e).into()),
+        })
+    }
+}
@@ -0,0 +1,201 @@
+//! Mining staking pallet benchmarking.
+
+#![cfg(feature = "runtime-benchmarks")]
+
+use super::*;
+
+use frame_benchmarking::{account, benchmarks, impl_benchmark_test_suite, whitelisted_caller};
+use frame_system::RawOrigin;
+
+use crate::Pallet as MiningStaking;
+
+const SEED: u32 = 0;
+
+/// Funds `who` and deposits `value` to its wallet
+fn setup_wallet<T: Config>(who: &T::AccountId, value: BalanceOf<T>) -> Result<(), &'static str> {
+	T::Currency::make_free_balance_be(who, value + value);
+	MiningStaking::<T>::deposit(RawOrigin::Signed(who.clone()).into(), value)?;
+	Ok(())
+}
+
+fn unit<T: Config>() -> BalanceOf<T> {
+	let unit = T::Currency::minimum_balance().max(1u32.into()) * 100u32.into();
+	unit.max(T::MinStake::get())
+}
+
+benchmarks! {
+	deposit {
+		let caller: T::AccountId = whitelisted_caller();
+		let amount = unit::<T>();
+		T::Currency::make_free_balance_be(&caller, amount + amount);
+	}: _(RawOrigin::Signed(caller.clone()), amount)
+	verify {
+		assert_eq!(Wallet::<T>::get(&caller), Some(amount));
+	}
+
+	withdraw {
+		let caller: T::AccountId = whitelisted_caller();
+		let amount = unit::<T>();
+		setup_wallet::<T>(&caller, amount)?;
+	}: _(RawOrigin::Signed(caller.clone()), amount)
+	verify {
+		assert_eq!(Wallet::<T>::get(&caller), Some(Zero::zero()));
+	}
+
+	// The worst case: stakes to a new target, so all the other targets are counted
+	stake {
+		let caller: T::AccountId = whitelisted_caller();
+		let miner: T::AccountId = account("miner", 0, SEED);
+		let amount = unit::<T>();
+		let n = T::MaxTargetsPerStaker::get();
+		setup_wallet::<T>(&caller, amount * n.into())?;
+		for i in 1..n {
+			let other: T::AccountId = account("miner", i, SEED);
+			MiningStaking::<T>::stake(RawOrigin::Signed(caller.clone()).into(), other, amount)?;
+		}
+	}: _(RawOrigin::Signed(caller.clone()), miner.clone(), amount)
+	verify {
+		assert_eq!(PendingStaking::<T>::get(&caller, &miner), Some(amount));
+	}
+
+	// The worst case: cancels some pending staking and unstakes the rest, with pending unstaking
+	// on all the other targets
+	unstake {
+		let caller: T::AccountId = whitelisted_caller();
+		let miner: T::AccountId = account("miner", 0, SEED);
+		let amount = unit::<T>();
+		let n = T::MaxTargetsPerStaker::get();
+		setup_wallet::<T>(&caller, amount * (n + 1).into())?;
+		for i in 0..n {
+			let target: T::AccountId = account("miner", i, SEED);
+			MiningStaking::<T>::stake(RawOrigin::Signed(caller.clone()).into(), target, amount)?;
+		}
+		MiningStaking::<T>::handle_round_end();
+		for i in 1..n {
+			let target: T::AccountId = account("miner", i, SEED);
+			MiningStaking::<T>::unstake(RawOrigin::Signed(caller.clone()).into(), target, amount)?;
+		}
+		MiningStaking::<T>::stake(RawOrigin::Signed(caller.clone()).into(), miner.clone(), amount)?;
+	}: _(RawOrigin::Signed(caller.clone()), miner.clone(), amount + amount)
+	verify {
+		assert_eq!(PendingUnstaking::<T>::get(&caller, &miner), Some(amount));
+	}
+
+	withdraw_unbonded {
+		let c in 1 .. T::MaxUnbondingChunks::get();
+		let caller: T::AccountId = whitelisted_caller();
+		let amount = unit::<T>();
+		let chunks = (0..c)
+			.map(|i| UnbondingChunk {
+				target: account("miner", i, SEED),
+				value: amount,
+				unlock_at: Zero::zero(),
+			})
+			.collect::<Vec<_>>();
+		Unbonding::<T>::insert(&caller, chunks);
+	}: _(RawOrigin::Signed(caller.clone()))
+	verify {
+		assert!(!Unbonding::<T>::contains_key(&caller));
+	}
+
+	claim_rewards {
+		let caller: T::AccountId = whitelisted_caller();
+		let miner: T::AccountId = account("miner", 0, SEED);
+		let amount = unit::<T>();
+		setup_wallet::<T>(&caller, amount)?;
+		MiningStaking::<T>::stake(RawOrigin::Signed(caller.clone()).into(), miner.clone(), amount)?;
+		MiningStaking::<T>::handle_round_end();
+		T::Currency::make_free_balance_be(&MiningStaking::<T>::reward_pot(), amount + amount);
+		MiningStaking::<T>::distribute_reward(&miner, amount);
+	}: _(RawOrigin::Signed(caller.clone()))
+	verify {
+		assert_eq!(Wallet::<T>::get(&caller), Some(amount));
+	}
+
+	set_auto_compound {
+		let caller: T::AccountId = whitelisted_caller();
+	}: _(RawOrigin::Signed(caller.clone()), true)
+	verify {
+		assert!(AutoCompound::<T>::get(&caller));
+	}
+
+	// Applies `s` pending stakes and `u` pending unstakes, and compounds the rewards of `c`
+	// stakers, each on its own miner
+	force_trigger_round_end {
+		let s in 0 .. 500;
+		let u in 0 .. 500;
+		let c in 0 .. 500;
+		let amount = unit::<T>();
+		T::Currency::make_free_balance_be(
+			&MiningStaking::<T>::reward_pot(),
+			amount * (c + 1).into(),
+		);
+		for i in 0..u.max(c) {
+			let staker: T::AccountId = account("staker", i, SEED);
+			let miner: T::AccountId = account("miner", i, SEED);
+			setup_wallet::<T>(&staker, amount)?;
+			MiningStaking::<T>::stake(RawOrigin::Signed(staker).into(), miner, amount)?;
+		}
+		MiningStaking::<T>::handle_round_end();
+		for i in 0..c {
+			let staker: T::AccountId = account("staker", i, SEED);
+			let miner: T::AccountId = account("miner", i, SEED);
+			MiningStaking::<T>::set_auto_compound(RawOrigin::Signed(staker).into(), true)?;
+			MiningStaking::<T>::distribute_reward(&miner, amount);
+		}
+		for i in 0..u {
+			let staker: T::AccountId = account("staker", i, SEED);
+			let miner: T::AccountId = account("miner", i, SEED);
+			MiningStaking::<T>::unstake(RawOrigin::Signed(staker).into(), miner, amount)?;
+		}
+		for i in 0..s {
+			let staker: T::AccountId = account("new_staker", i, SEED);
+			let miner: T::AccountId = account("miner", i, SEED);
+			setup_wallet::<T>(&staker, amount)?;
+			MiningStaking::<T>::stake(RawOrigin::Signed(staker).into(), miner, amount)?;
+		}
+	}: _(RawOrigin::Root, s, u, c)
+	verify {
+		assert_eq!(PendingStakingCount::<T>::get(), 0);
+		assert_eq!(PendingUnstakingCount::<T>::get(), 0);
+	}
+
+	// Slashes `n` stakers of a miner, each with stake, pending staking and an unbonding chunk
+	slash {
+		let n in 1 .. T::MaxStakersPerTarget::get();
+		let miner: T::AccountId = account("miner", 0, SEED);
+		let amount = unit::<T>();
+		for i in 0..n {
+			let staker: T::AccountId = account("staker", i, SEED);
+			setup_wallet::<T>(&staker, amount * 3u32.into())?;
+			MiningStaking::<T>::stake(
+				RawOrigin::Signed(staker).into(),
+				miner.clone(),
+				amount + amount,
+			)?;
+		}
+		MiningStaking::<T>::handle_round_end();
+		for i in 0..n {
+			let staker: T::AccountId = account("staker", i, SEED);
+			MiningStaking::<T>::unstake(RawOrigin::Signed(staker).into(), miner.clone(), amount)?;
+		}
+		MiningStaking::<T>::handle_round_end();
+		for i in 0..n {
+			let staker: T::AccountId = account("staker", i, SEED);
+			MiningStaking::<T>::stake(RawOrigin::Signed(staker).into(), miner.clone(), amount)?;
+		}
+		let slashed;
+	}: {
+		slashed = MiningStaking::<T>::slash(&miner, Permill::from_percent(50));
+	}
+	verify {
+		assert!(!slashed.is_zero());
+		assert_eq!(StakerCount::<T>::get(&miner), n);
+	}
+}
+
+impl_benchmark_test_suite!(
+	MiningStaking,
+	crate::mock::new_test_ext(),
+	crate::mock::Test,
+);
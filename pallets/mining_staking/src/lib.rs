@@ -2,31 +2,59 @@
 
 extern crate alloc;
 
-use codec::FullCodec;
+use codec::{Decode, Encode, FullCodec};
 use sp_std::cmp;
+use sp_std::collections::{btree_map::BTreeMap, btree_set::BTreeSet};
 use sp_std::prelude::*;
 
 use alloc::vec::Vec;
 use frame_support::{
-	traits::{Currency, ExistenceRequirement::AllowDeath, ExistenceRequirement::KeepAlive},
+	traits::{
+		Currency, ExistenceRequirement::AllowDeath, ExistenceRequirement::KeepAlive, Imbalance,
+	},
+	weights::Weight,
 	PalletId,
 };
 pub use pallet::*;
-use sp_runtime::traits::{AccountIdConversion, Zero};
+use sp_runtime::{
+	traits::{AccountIdConversion, Saturating, Zero},
+	Perbill, Permill, RuntimeDebug,
+};
 
 use phala_pallets::pallet_phala;
 
+pub use pallet_mining_staking_rpc_runtime_api::{StakerPosition, TargetPosition};
+
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+
 #[cfg(test)]
 mod mock;
 
 #[cfg(test)]
 mod tests;
 
+pub mod weights;
+pub use weights::WeightInfo;
+
 pub type BalanceOf<T> =
 	<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+pub type NegativeImbalanceOf<T> = <<T as Config>::Currency as Currency<
+	<T as frame_system::Config>::AccountId,
+>>::NegativeImbalance;
 
 const PALLET_ID: PalletId = PalletId(*b"PHAPoWS.");
 
+/// Some unstaked tokens waiting for the unbonding period
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct UnbondingChunk<AccountId, Balance, BlockNumber> {
+	/// The account the tokens were staked to. The chunk can still be slashed by its offences.
+	pub target: AccountId,
+	pub value: Balance,
+	/// The block from which the chunk can be withdrawn
+	pub unlock_at: BlockNumber,
+}
+
 #[frame_support::pallet]
 pub mod pallet {
 	use super::*;
@@ -38,6 +66,25 @@ pub mod pallet {
 	pub trait Config: frame_system::Config {
 		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
 		type Currency: Currency<Self::AccountId>;
+		/// The blocks the unstaked tokens are locked before going back to the wallet
+		#[pallet::constant]
+		type UnbondingPeriod: Get<Self::BlockNumber>;
+		/// The max number of the unbonding chunks of an account
+		#[pallet::constant]
+		type MaxUnbondingChunks: Get<u32>;
+		/// The min stake a staker can keep on a target
+		#[pallet::constant]
+		type MinStake: Get<BalanceOf<Self>>;
+		/// The max stake a target can receive from all the stakers
+		#[pallet::constant]
+		type MaxStakePerTarget: Get<BalanceOf<Self>>;
+		/// The max number of the targets a staker can stake to
+		#[pallet::constant]
+		type MaxTargetsPerStaker: Get<u32>;
+		/// The max number of the stakers a target can receive stake from
+		#[pallet::constant]
+		type MaxStakersPerTarget: Get<u32>;
+		type WeightInfo: WeightInfo;
 	}
 
 	#[pallet::pallet]
@@ -73,6 +120,59 @@ pub mod pallet {
 	#[pallet::getter(fn stake_received)]
 	pub type StakeReceived<T: Config> = StorageMap<_, Twox64Concat, T::AccountId, BalanceOf<T>>;
 
+	/// The stake a target will receive at the end of the current round
+	#[pallet::storage]
+	#[pallet::getter(fn pending_stake_received)]
+	pub type PendingStakeReceived<T: Config> =
+		StorageMap<_, Twox64Concat, T::AccountId, BalanceOf<T>>;
+
+	/// The unstaked tokens of an account in the unbonding period
+	#[pallet::storage]
+	#[pallet::getter(fn unbonding)]
+	pub type Unbonding<T: Config> = StorageMap<
+		_,
+		Twox64Concat,
+		T::AccountId,
+		Vec<UnbondingChunk<T::AccountId, BalanceOf<T>, T::BlockNumber>>,
+		ValueQuery,
+	>;
+
+	/// The unclaimed rewards of a staker, earned by the stake to a target
+	#[pallet::storage]
+	#[pallet::getter(fn rewards)]
+	pub type Rewards<T: Config> =
+		StorageDoubleMap<_, Twox64Concat, T::AccountId, Twox64Concat, T::AccountId, BalanceOf<T>>;
+
+	/// Whether the rewards of a staker are staked again at the end of each round
+	#[pallet::storage]
+	#[pallet::getter(fn auto_compound)]
+	pub type AutoCompound<T: Config> = StorageMap<_, Twox64Concat, T::AccountId, bool, ValueQuery>;
+
+	/// The accounts with stake or pending staking on a target, indexed by the target
+	#[pallet::storage]
+	pub type Stakers<T: Config> =
+		StorageDoubleMap<_, Twox64Concat, T::AccountId, Twox64Concat, T::AccountId, ()>;
+
+	/// The number of `Stakers` of a target
+	#[pallet::storage]
+	#[pallet::getter(fn staker_count)]
+	pub type StakerCount<T: Config> = StorageMap<_, Twox64Concat, T::AccountId, u32, ValueQuery>;
+
+	/// The number of the entries in `PendingStaking`
+	#[pallet::storage]
+	#[pallet::getter(fn pending_staking_count)]
+	pub type PendingStakingCount<T> = StorageValue<_, u32, ValueQuery>;
+
+	/// The number of the entries in `PendingUnstaking`
+	#[pallet::storage]
+	#[pallet::getter(fn pending_unstaking_count)]
+	pub type PendingUnstakingCount<T> = StorageValue<_, u32, ValueQuery>;
+
+	/// The number of the entries in `AutoCompound`
+	#[pallet::storage]
+	#[pallet::getter(fn auto_compound_count)]
+	pub type AutoCompoundCount<T> = StorageValue<_, u32, ValueQuery>;
+
 	#[pallet::event]
 	#[pallet::metadata(T::AccountId = "AccountId", BalanceOf<T> = "Balance")]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
@@ -81,12 +181,36 @@ pub mod pallet {
 		PendingStakeApplied,
 		PendingUnstakeAdded(T::AccountId, T::AccountId, BalanceOf<T>),
 		PendingStakeAdded(T::AccountId, T::AccountId, BalanceOf<T>),
+		/// The stake to a miner was slashed and burnt. [to, amount]
+		Slashed(T::AccountId, BalanceOf<T>),
+		/// Some unbonded tokens went back to the wallet. [who, amount]
+		Withdrawn(T::AccountId, BalanceOf<T>),
+		/// Some rewards were distributed to the stakers of a miner. [to, amount]
+		RewardsDistributed(T::AccountId, BalanceOf<T>),
+		/// Some rewards were claimed to the wallet. [who, amount]
+		RewardsClaimed(T::AccountId, BalanceOf<T>),
+		/// Some rewards were staked again. [who, amount]
+		RewardsCompounded(T::AccountId, BalanceOf<T>),
 	}
 
 	#[pallet::error]
 	pub enum Error<T> {
 		InsufficientFunds,
 		InsufficientStake,
+		/// Too many unbonding chunks; withdraw the unlocked ones first
+		TooManyUnbondingChunks,
+		/// Nothing to withdraw or claim
+		NothingToClaim,
+		/// The stake left on the target would be less than `MinStake`
+		StakeTooLow,
+		/// The target would receive more than `MaxStakePerTarget`
+		TargetStakeCapExceeded,
+		/// The staker already reached `MaxTargetsPerStaker`
+		TooManyTargets,
+		/// The target already reached `MaxStakersPerTarget`
+		TooManyStakers,
+		/// The witness of `force_trigger_round_end` is less than the pending work
+		InvalidWitness,
 	}
 
 	#[pallet::hooks]
@@ -95,7 +219,7 @@ pub mod pallet {
 	#[pallet::call]
 	impl<T: Config> Pallet<T> {
 		/// Deposits to the stash account wallet.
-		#[pallet::weight(0 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(T::WeightInfo::deposit())]
 		pub fn deposit(origin: OriginFor<T>, value: BalanceOf<T>) -> DispatchResultWithPostInfo {
 			let sender = ensure_signed(origin)?;
 			T::Currency::transfer(&sender, &Self::account_id(), value, KeepAlive)?;
@@ -106,7 +230,7 @@ pub mod pallet {
 		}
 
 		/// Withdraws some available token from the stash account.
-		#[pallet::weight(0 + T::DbWeight::get().reads_writes(1,1))]
+		#[pallet::weight(T::WeightInfo::withdraw())]
 		pub fn withdraw(origin: OriginFor<T>, value: BalanceOf<T>) -> DispatchResultWithPostInfo {
 			let sender = ensure_signed(origin)?;
 			let available = Self::available(&sender);
@@ -119,7 +243,7 @@ pub mod pallet {
 		}
 
 		/// Adds some stake to a target
-		#[pallet::weight(0 + T::DbWeight::get().reads_writes(1,1))]
+		#[pallet::weight(T::WeightInfo::stake())]
 		pub fn stake(
 			origin: OriginFor<T>,
 			to: T::AccountId,
@@ -134,9 +258,32 @@ pub mod pallet {
 				value <= pending_unstaking + free,
 				Error::<T>::InsufficientFunds
 			);
+			// Check the limits
+			let staked = Staked::<T>::get(&sender, &to).unwrap_or_default();
+			let pending_staking = PendingStaking::<T>::get(&sender, &to).unwrap_or_default();
+			ensure!(
+				staked + pending_staking + value - pending_unstaking >= T::MinStake::get(),
+				Error::<T>::StakeTooLow
+			);
+			let to_cancel = cmp::min(pending_unstaking, value);
+			let received = StakeReceived::<T>::get(&to).unwrap_or_default()
+				+ PendingStakeReceived::<T>::get(&to).unwrap_or_default();
+			ensure!(
+				received + value - to_cancel <= T::MaxStakePerTarget::get(),
+				Error::<T>::TargetStakeCapExceeded
+			);
+			let is_new_target = staked.is_zero() && pending_staking.is_zero();
+			ensure!(
+				!is_new_target
+					|| (Self::targets(&sender).len() as u32) < T::MaxTargetsPerStaker::get(),
+				Error::<T>::TooManyTargets
+			);
+			ensure!(
+				!is_new_target || StakerCount::<T>::get(&to) < T::MaxStakersPerTarget::get(),
+				Error::<T>::TooManyStakers
+			);
 			// Cancel some unstaking operations first
 			let mut to_stake = value;
-			let to_cancel = cmp::min(pending_unstaking, value);
 			if to_cancel > zero {
 				PendingUnstaking::<T>::mutate(&sender, &to, |v| {
 					*v = Some(v.unwrap_or_default() - to_cancel)
@@ -151,7 +298,9 @@ pub mod pallet {
 		}
 
 		/// Remove some stack from a target
-		#[pallet::weight(0 + T::DbWeight::get().reads_writes(1,1))]
+		///
+		/// The unstaked tokens are locked for `UnbondingPeriod` after the round ends.
+		#[pallet::weight(T::WeightInfo::unstake())]
 		pub fn unstake(
 			origin: OriginFor<T>,
 			to: T::AccountId,
@@ -169,12 +318,30 @@ pub mod pallet {
 				to_unstake + unstaking <= staked,
 				Error::<T>::InsufficientStake
 			);
+			let remaining = staked + pending - unstaking - value;
+			ensure!(
+				remaining.is_zero() || remaining >= T::MinStake::get(),
+				Error::<T>::StakeTooLow
+			);
+			// Each target with pending unstaking adds a chunk at the end of the round
+			let unbonding_targets = PendingUnstaking::<T>::iter_prefix(&sender)
+				.filter(|(target, value)| target != &to && !value.is_zero())
+				.count() + 1;
+			ensure!(
+				to_unstake == zero
+					|| (Unbonding::<T>::decode_len(&sender).unwrap_or(0) + unbonding_targets)
+						as u32 <= T::MaxUnbondingChunks::get(),
+				Error::<T>::TooManyUnbondingChunks
+			);
 			// Cancel some new stake first
 			if to_cancel > zero {
 				Self::cancel_lock(&sender, &to, to_cancel);
 			}
 			// Then add more pending unstaking
 			if to_unstake > zero {
+				if !PendingUnstaking::<T>::contains_key(&sender, &to) {
+					PendingUnstakingCount::<T>::mutate(|n| *n += 1);
+				}
 				PendingUnstaking::<T>::mutate(&sender, &to, |v| {
 					*v = Some(v.unwrap_or_default() + to_unstake)
 				});
@@ -182,11 +349,93 @@ pub mod pallet {
 			Ok(().into())
 		}
 
-		#[pallet::weight(0 + T::DbWeight::get().reads_writes(1,1))]
-		pub fn force_trigger_round_end(origin: OriginFor<T>) -> DispatchResultWithPostInfo {
+		/// Moves the unbonded tokens out of the unbonding period back to the wallet
+		#[pallet::weight(T::WeightInfo::withdraw_unbonded(T::MaxUnbondingChunks::get()))]
+		pub fn withdraw_unbonded(origin: OriginFor<T>) -> DispatchResultWithPostInfo {
+			let sender = ensure_signed(origin)?;
+			let now = frame_system::Pallet::<T>::block_number();
+			let mut unlocked: BalanceOf<T> = Zero::zero();
+			let mut unlocked_targets = BTreeSet::new();
+			Unbonding::<T>::mutate_exists(&sender, |maybe_chunks| {
+				let chunks = match maybe_chunks {
+					Some(chunks) => chunks,
+					None => return,
+				};
+				chunks.retain(|chunk| {
+					if chunk.unlock_at <= now {
+						unlocked += chunk.value;
+						unlocked_targets.insert(chunk.target.clone());
+						false
+					} else {
+						true
+					}
+				});
+				if chunks.is_empty() {
+					*maybe_chunks = None;
+				}
+			});
+			ensure!(!unlocked.is_zero(), Error::<T>::NothingToClaim);
+			for to in unlocked_targets.iter() {
+				Self::update_stakers(&sender, to);
+			}
+			Wallet::<T>::mutate(&sender, |v| *v = Some(v.unwrap_or_default() + unlocked));
+			Self::deposit_event(Event::Withdrawn(sender, unlocked));
+			Ok(().into())
+		}
+
+		/// Claims all the rewards to the wallet
+		#[pallet::weight(T::WeightInfo::claim_rewards())]
+		pub fn claim_rewards(origin: OriginFor<T>) -> DispatchResultWithPostInfo {
+			let sender = ensure_signed(origin)?;
+			let mut claimed: BalanceOf<T> = Zero::zero();
+			for (_, value) in Rewards::<T>::drain_prefix(&sender) {
+				claimed += value;
+			}
+			ensure!(!claimed.is_zero(), Error::<T>::NothingToClaim);
+			Wallet::<T>::mutate(&sender, |v| *v = Some(v.unwrap_or_default() + claimed));
+			Self::deposit_event(Event::RewardsClaimed(sender, claimed));
+			Ok(().into())
+		}
+
+		/// Enables or disables staking the rewards again at the end of each round
+		#[pallet::weight(T::WeightInfo::set_auto_compound())]
+		pub fn set_auto_compound(
+			origin: OriginFor<T>,
+			enabled: bool,
+		) -> DispatchResultWithPostInfo {
+			let sender = ensure_signed(origin)?;
+			let existed = AutoCompound::<T>::contains_key(&sender);
+			if enabled && !existed {
+				AutoCompound::<T>::insert(&sender, true);
+				AutoCompoundCount::<T>::mutate(|n| *n += 1);
+			} else if !enabled && existed {
+				AutoCompound::<T>::remove(&sender);
+				AutoCompoundCount::<T>::mutate(|n| *n -= 1);
+			}
+			Ok(().into())
+		}
+
+		/// Ends the round now.
+		///
+		/// The witness is the number of the entries in `PendingStaking`, `PendingUnstaking` and
+		/// `AutoCompound`, which can be read from their counters.
+		#[pallet::weight(T::WeightInfo::force_trigger_round_end(*stakes, *unstakes, *compounders))]
+		pub fn force_trigger_round_end(
+			origin: OriginFor<T>,
+			stakes: u32,
+			unstakes: u32,
+			compounders: u32,
+		) -> DispatchResultWithPostInfo {
 			ensure_root(origin)?;
+			ensure!(
+				PendingStakingCount::<T>::get() <= stakes
+					&& PendingUnstakingCount::<T>::get() <= unstakes
+					&& AutoCompoundCount::<T>::get() <= compounders,
+				Error::<T>::InvalidWitness
+			);
+			let weight = Self::round_end_weight();
 			Self::handle_round_end();
-			Ok(().into())
+			Ok(Some(weight).into())
 		}
 	}
 
@@ -195,35 +444,260 @@ pub mod pallet {
 			PALLET_ID.into_account()
 		}
 
+		/// The account holding the rewards not distributed to the stakers yet
+		pub fn reward_pot() -> T::AccountId {
+			PALLET_ID.into_sub_account(b"reward")
+		}
+
+		/// The weight of `handle_round_end` with the current pending work
+		pub fn round_end_weight() -> Weight {
+			T::WeightInfo::force_trigger_round_end(
+				PendingStakingCount::<T>::get(),
+				PendingUnstakingCount::<T>::get(),
+				AutoCompoundCount::<T>::get(),
+			)
+		}
+
 		/// Gets the availabe funds (wallet minus the pending staking tokens)
 		pub fn available(who: &T::AccountId) -> BalanceOf<T> {
 			Wallet::<T>::get(who).unwrap_or_default()
 				- WalletLocked::<T>::get(who).unwrap_or_default()
 		}
 
+		/// Gets the targets `who` has stake or pending staking on
+		pub fn targets(who: &T::AccountId) -> BTreeSet<T::AccountId> {
+			Staked::<T>::iter_prefix(who)
+				.chain(PendingStaking::<T>::iter_prefix(who))
+				.filter(|(_, value)| !value.is_zero())
+				.map(|(to, _)| to)
+				.collect()
+		}
+
+		/// The rewards of `who` not claimed yet, earned by the stake to all its targets
+		pub fn pending_rewards(who: &T::AccountId) -> BalanceOf<T> {
+			Rewards::<T>::iter_prefix_values(who).fold(Zero::zero(), |acc, value| acc + value)
+		}
+
+		/// Gets the full staking position of `who`
+		pub fn staker_position(
+			who: &T::AccountId,
+		) -> StakerPosition<T::AccountId, BalanceOf<T>> {
+			let mut targets = BTreeMap::new();
+			for (to, value) in Staked::<T>::iter_prefix(who) {
+				Self::target_position(&mut targets, to).staked = value;
+			}
+			for (to, value) in PendingStaking::<T>::iter_prefix(who) {
+				Self::target_position(&mut targets, to).pending_staking = value;
+			}
+			for (to, value) in PendingUnstaking::<T>::iter_prefix(who) {
+				Self::target_position(&mut targets, to).pending_unstaking = value;
+			}
+			for (to, value) in Rewards::<T>::iter_prefix(who) {
+				Self::target_position(&mut targets, to).rewards = value;
+			}
+			let unbonding = Unbonding::<T>::get(who)
+				.iter()
+				.fold(Zero::zero(), |acc: BalanceOf<T>, chunk| acc + chunk.value);
+			StakerPosition {
+				wallet: Wallet::<T>::get(who).unwrap_or_default(),
+				locked: WalletLocked::<T>::get(who).unwrap_or_default(),
+				unbonding,
+				auto_compound: AutoCompound::<T>::get(who),
+				targets: targets
+					.into_iter()
+					.map(|(_, target)| target)
+					.filter(|target| {
+						!(target.staked.is_zero()
+							&& target.pending_staking.is_zero()
+							&& target.pending_unstaking.is_zero()
+							&& target.rewards.is_zero())
+					})
+					.collect(),
+			}
+		}
+
+		fn target_position(
+			targets: &mut BTreeMap<T::AccountId, TargetPosition<T::AccountId, BalanceOf<T>>>,
+			to: T::AccountId,
+		) -> &mut TargetPosition<T::AccountId, BalanceOf<T>> {
+			targets.entry(to.clone()).or_insert_with(|| TargetPosition {
+				target: to,
+				staked: Zero::zero(),
+				pending_staking: Zero::zero(),
+				pending_unstaking: Zero::zero(),
+				rewards: Zero::zero(),
+			})
+		}
+
 		fn lock(from: &T::AccountId, to: &T::AccountId, value: BalanceOf<T>) {
+			if !PendingStaking::<T>::contains_key(&from, &to) {
+				PendingStakingCount::<T>::mutate(|n| *n += 1);
+			}
 			PendingStaking::<T>::mutate(&from, &to, |v| *v = Some(v.unwrap_or_default() + value));
 			WalletLocked::<T>::mutate(&from, |v| *v = Some(v.unwrap_or_default() + value));
+			PendingStakeReceived::<T>::mutate(&to, |v| *v = Some(v.unwrap_or_default() + value));
+			Self::update_stakers(from, to);
 		}
 
 		fn cancel_lock(from: &T::AccountId, to: &T::AccountId, value: BalanceOf<T>) {
 			PendingStaking::<T>::mutate(&from, &to, |v| *v = Some(v.unwrap_or_default() - value));
 			WalletLocked::<T>::mutate(&from, |v| *v = Some(v.unwrap_or_default() - value));
+			PendingStakeReceived::<T>::mutate(&to, |v| *v = Some(v.unwrap_or_default() - value));
+			Self::update_stakers(from, to);
 		}
 
 		fn inc_stake(from: &T::AccountId, to: &T::AccountId, value: BalanceOf<T>) {
 			Staked::<T>::mutate(&from, &to, |v| *v = Some(v.unwrap_or_default() + value));
 			StakeReceived::<T>::mutate(&to, |v| *v = Some(v.unwrap_or_default() + value));
+			Self::update_stakers(from, to);
 		}
 
 		fn dec_stake(from: &T::AccountId, to: &T::AccountId, value: BalanceOf<T>) {
 			Staked::<T>::mutate(&from, &to, |v| *v = Some(v.unwrap_or_default() - value));
 			StakeReceived::<T>::mutate(&to, |v| *v = Some(v.unwrap_or_default() - value));
+			Self::update_stakers(from, to);
+		}
+
+		/// Keeps `from` in the `Stakers` of `to` as long as it has stake, pending staking or
+		/// unbonding tokens on it, all of which can be slashed by the offences of `to`
+		fn update_stakers(from: &T::AccountId, to: &T::AccountId) {
+			let is_staker = !Staked::<T>::get(from, to).unwrap_or_default().is_zero()
+				|| !PendingStaking::<T>::get(from, to).unwrap_or_default().is_zero()
+				|| Unbonding::<T>::get(from).iter().any(|chunk| &chunk.target == to);
+			let indexed = Stakers::<T>::contains_key(to, from);
+			if is_staker && !indexed {
+				Stakers::<T>::insert(to, from, ());
+				StakerCount::<T>::mutate(to, |n| *n += 1);
+			} else if !is_staker && indexed {
+				Stakers::<T>::remove(to, from);
+				StakerCount::<T>::mutate(to, |n| *n -= 1);
+			}
+		}
+
+		/// Slashes `fraction` of the stake every staker put on `to`, and burns the slashed tokens.
+		///
+		/// The pending staking and the unbonding chunks of `to` are slashed as well, and the
+		/// pending unstaking is clipped to the remaining stake. Returns the total slashed.
+		pub fn slash(to: &T::AccountId, fraction: Permill) -> BalanceOf<T> {
+			// Bounded by `MaxStakersPerTarget`
+			let stakers: Vec<T::AccountId> =
+				Stakers::<T>::iter_prefix(to).map(|(from, _)| from).collect();
+			let mut slashed: BalanceOf<T> = Zero::zero();
+			for from in stakers.iter() {
+				let staked = Staked::<T>::get(from, to).unwrap_or_default();
+				let cut = fraction * staked;
+				if !cut.is_zero() {
+					Self::dec_stake(from, to, cut);
+					let remaining = staked - cut;
+					PendingUnstaking::<T>::mutate(from, to, |v| {
+						if let Some(pending) = v {
+							*pending = cmp::min(*pending, remaining);
+						}
+					});
+					slashed += cut;
+				}
+				// The pending staking is locked in the wallet, and burnt from it
+				let pending = PendingStaking::<T>::get(from, to).unwrap_or_default();
+				let cut = fraction * pending;
+				if !cut.is_zero() {
+					Self::cancel_lock(from, to, cut);
+					Wallet::<T>::mutate(from, |v| *v = Some(v.unwrap_or_default() - cut));
+					slashed += cut;
+				}
+				// The unbonding tokens are still liable for the offences
+				Unbonding::<T>::mutate(from, |chunks| {
+					for chunk in chunks.iter_mut().filter(|chunk| &chunk.target == to) {
+						let cut = fraction * chunk.value;
+						chunk.value -= cut;
+						slashed += cut;
+					}
+				});
+			}
+			if !slashed.is_zero() {
+				// Dropping the imbalance burns the tokens
+				let (imbalance, _) = T::Currency::slash(&Self::account_id(), slashed);
+				drop(imbalance);
+				Self::deposit_event(Event::Slashed(to.clone(), slashed));
+			}
+			slashed
+		}
+
+		/// Distributes `value` to the stakers of `to` pro rata to their stake. The rewards are
+		/// moved from the reward pot to the pallet account.
+		///
+		/// Returns the distributed amount, which is zero if nobody is staking to `to` or the pot
+		/// can't cover it.
+		pub fn distribute_reward(to: &T::AccountId, value: BalanceOf<T>) -> BalanceOf<T> {
+			let total = StakeReceived::<T>::get(to).unwrap_or_default();
+			if total.is_zero() || value.is_zero() {
+				return Zero::zero();
+			}
+			let shares: Vec<(T::AccountId, BalanceOf<T>)> = Stakers::<T>::iter_prefix(to)
+				.map(|(from, _)| {
+					let staked = Staked::<T>::get(&from, to).unwrap_or_default();
+					(from, Perbill::from_rational_approximation(staked, total) * value)
+				})
+				.filter(|(_, share)| !share.is_zero())
+				.collect();
+			let distributed = shares
+				.iter()
+				.fold(Zero::zero(), |acc: BalanceOf<T>, (_, share)| acc + *share);
+			if distributed.is_zero()
+				|| T::Currency::transfer(
+					&Self::reward_pot(),
+					&Self::account_id(),
+					distributed,
+					AllowDeath,
+				)
+				.is_err()
+			{
+				return Zero::zero();
+			}
+			for (from, share) in shares.iter() {
+				Rewards::<T>::mutate(from, to, |v| *v = Some(v.unwrap_or_default() + *share));
+			}
+			Self::deposit_event(Event::RewardsDistributed(to.clone(), distributed));
+			distributed
+		}
+
+		/// Stakes the rewards of the auto-compounding stakers to where they were earned.
+		///
+		/// The rewards over `MaxStakePerTarget` are left unclaimed.
+		fn compound_rewards() {
+			let stakers: Vec<T::AccountId> =
+				AutoCompound::<T>::iter().map(|(who, _)| who).collect();
+			let cap = T::MaxStakePerTarget::get();
+			for from in stakers.iter() {
+				let mut compounded: BalanceOf<T> = Zero::zero();
+				let rewards: Vec<(T::AccountId, BalanceOf<T>)> =
+					Rewards::<T>::iter_prefix(from).collect();
+				for (to, value) in rewards {
+					let received = StakeReceived::<T>::get(&to).unwrap_or_default();
+					let to_stake = cmp::min(value, cap.saturating_sub(received));
+					if to_stake.is_zero() {
+						continue;
+					}
+					Self::inc_stake(from, &to, to_stake);
+					if to_stake == value {
+						Rewards::<T>::remove(from, &to);
+					} else {
+						Rewards::<T>::insert(from, &to, value - to_stake);
+					}
+					compounded += to_stake;
+				}
+				if !compounded.is_zero() {
+					Self::deposit_event(Event::RewardsCompounded(from.clone(), compounded));
+				}
+			}
 		}
 
 		/// Applies the pending staking and unstaking tokens at the end of a round.
+		///
+		/// The unstaked tokens go to the unbonding chunks, and the rewards of the auto-compounding
+		/// stakers are staked again.
 		pub fn handle_round_end() {
 			// Apply staking
+			PendingStakingCount::<T>::kill();
 			group_by_key(PendingStaking::<T>::drain(), |from, group| {
 				let mut staked: BalanceOf<T> = Zero::zero();
 				for (to, value) in group.iter() {
@@ -233,16 +707,29 @@ pub mod pallet {
 				Wallet::<T>::mutate(&from, |v| *v = Some(v.unwrap_or_default() - staked));
 			});
 			// Apply unstaking
+			let unlock_at = frame_system::Pallet::<T>::block_number() + T::UnbondingPeriod::get();
+			PendingUnstakingCount::<T>::kill();
 			group_by_key(PendingUnstaking::<T>::drain(), |from, group| {
-				let mut unstaked: BalanceOf<T> = Zero::zero();
-				for (to, value) in group {
-					unstaked += *value;
+				let group: Vec<_> = group.iter().filter(|(_, value)| !value.is_zero()).collect();
+				Unbonding::<T>::mutate(&from, |chunks| {
+					// Bounded by `MaxUnbondingChunks`, checked by `unstake`
+					for (to, value) in group.iter() {
+						chunks.push(UnbondingChunk {
+							target: to.clone(),
+							value: *value,
+							unlock_at,
+						});
+					}
+				});
+				// After the chunks are added, so that the stakers stay indexed while unbonding
+				for (to, value) in group.iter() {
 					Self::dec_stake(&from, &to, *value);
 				}
-				Wallet::<T>::mutate(&from, |v| *v = Some(v.unwrap_or_default() + unstaked));
 			});
+			Self::compound_rewards();
 			// Clear the pending staking
 			WalletLocked::<T>::drain().for_each(drop);
+			PendingStakeReceived::<T>::drain().for_each(drop);
 			Self::deposit_event(Event::PendingStakeApplied)
 		}
 	}
@@ -251,6 +738,36 @@ pub mod pallet {
 		fn on_round_end(_round: u32) {
 			Self::handle_round_end();
 		}
+
+		fn round_end_weight() -> Weight {
+			Self::round_end_weight()
+		}
+	}
+
+	impl<T: Config> pallet_phala::OnReward<T::AccountId, NegativeImbalanceOf<T>> for Pallet<T> {
+		fn on_reward(
+			stash: &T::AccountId,
+			reward: NegativeImbalanceOf<T>,
+		) -> NegativeImbalanceOf<T> {
+			if StakeReceived::<T>::get(stash).unwrap_or_default().is_zero() {
+				return reward;
+			}
+			// The dust left by the pro rata shares stays in the pot
+			let value = reward.peek();
+			T::Currency::resolve_creating(&Self::reward_pot(), reward);
+			Self::distribute_reward(stash, value);
+			NegativeImbalanceOf::<T>::zero()
+		}
+	}
+
+	impl<T: Config> pallet_phala::OnOffence<T::AccountId, BalanceOf<T>> for Pallet<T> {
+		fn on_offence(stash: &T::AccountId, fraction: Permill) -> BalanceOf<T> {
+			Self::slash(stash, fraction)
+		}
+
+		fn offence_weight(stash: &T::AccountId) -> Weight {
+			T::WeightInfo::slash(StakerCount::<T>::get(stash))
+		}
 	}
 }
 
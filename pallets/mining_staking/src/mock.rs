@@ -29,6 +29,9 @@ frame_support::construct_runtime!(
 		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
 		Phala: phala_pallets::pallet_phala::{Pallet, Call, Config<T>, Storage, Event<T>},
 		PhalaMq: phala_pallets::pallet_mq::{Pallet, Event},
+		PhalaRegistry: phala_pallets::pallet_registry::{Pallet, Event<T>, Storage},
+		PhalaMining: phala_pallets::pallet_mining::{Pallet, Event, Storage},
+		PhalaStakePool: phala_pallets::pallet_stakepool::{Pallet, Event, Storage},
 		MiningStaking: mining_staking::{Pallet, Call, Storage, Event<T>},
 	}
 );
@@ -97,8 +100,12 @@ parameter_types! {
 	pub const RewardRation: u32 = 80_000;
 	pub const OnlineRewardPercentage: Permill = Permill::from_parts(375_000);
 	pub const ComputeRewardPercentage: Permill = Permill::from_parts(625_000);
+	pub const StakerRewardPercentage: Permill = Permill::from_percent(20);
 	pub const OfflineOffenseSlash: Balance = 100 * DOLLARS;
 	pub const OfflineReportReward: Balance = 50 * DOLLARS;
+	pub const OffenceDisputePeriod: BlockNumber = 10;
+	pub const OffenceSlashBase: Permill = Permill::from_percent(1);
+	pub const OffenceSlashMax: Permill = Permill::from_percent(10);
 }
 
 impl phala_pallets::pallet_phala::Config for Test {
@@ -109,6 +116,8 @@ impl phala_pallets::pallet_phala::Config for Test {
 	type Treasury = ();
 	type WeightInfo = ();
 	type OnRoundEnd = ();
+	type OnOffence = MiningStaking;
+	type OnReward = MiningStaking;
 
 	// Parameters
 	type MaxHeartbeatPerWorkerPerHour = MaxHeartbeatPerWorkerPerHour;
@@ -120,8 +129,12 @@ impl phala_pallets::pallet_phala::Config for Test {
 	type RewardRation = RewardRation;
 	type OnlineRewardPercentage = OnlineRewardPercentage;
 	type ComputeRewardPercentage = ComputeRewardPercentage;
+	type StakerRewardPercentage = StakerRewardPercentage;
 	type OfflineOffenseSlash = OfflineOffenseSlash;
 	type OfflineReportReward = OfflineReportReward;
+	type OffenceDisputePeriod = OffenceDisputePeriod;
+	type OffenceSlashBase = OffenceSlashBase;
+	type OffenceSlashMax = OffenceSlashMax;
 }
 
 impl phala_pallets::pallet_mq::Config for Test {
@@ -129,9 +142,61 @@ impl phala_pallets::pallet_mq::Config for Test {
     type QueueNotifyConfig = ();
 }
 
+parameter_types! {
+	pub const MaxGatekeepers: u32 = 5;
+	pub const MinGatekeepers: u32 = 1;
+	pub const GatekeeperRotationDelay: BlockNumber = 10;
+	pub const ContractDeposit: Balance = 100;
+	pub const MaxInitParamsLen: u32 = 64;
+	pub const MaxSaltLen: u32 = 32;
+}
+
+impl phala_pallets::pallet_registry::Config for Test {
+	type Event = Event;
+	type UnixTime = Timestamp;
+	type Randomness = TestRandomness<Self>;
+	type GatekeeperOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type ContractAdminOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type MaxGatekeepers = MaxGatekeepers;
+	type MinGatekeepers = MinGatekeepers;
+	type GatekeeperRotationDelay = GatekeeperRotationDelay;
+	type Currency = Balances;
+	type ContractDeposit = ContractDeposit;
+	type MaxInitParamsLen = MaxInitParamsLen;
+	type MaxSaltLen = MaxSaltLen;
+	type OnWorkerKeyRotated = PhalaMining;
+	type WorkerBindings = PhalaMining;
+}
+
+impl phala_pallets::pallet_mining::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+}
+
+impl phala_pallets::pallet_stakepool::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+}
+
+parameter_types! {
+	pub const UnbondingPeriod: BlockNumber = 10;
+	pub const MaxUnbondingChunks: u32 = 3;
+	pub const MinStake: Balance = 5;
+	pub const MaxStakePerTarget: Balance = 200;
+	pub const MaxTargetsPerStaker: u32 = 3;
+	pub const MaxStakersPerTarget: u32 = 3;
+}
+
 impl mining_staking::Config for Test {
 	type Event = Event;
 	type Currency = Balances;
+	type UnbondingPeriod = UnbondingPeriod;
+	type MaxUnbondingChunks = MaxUnbondingChunks;
+	type MinStake = MinStake;
+	type MaxStakePerTarget = MaxStakePerTarget;
+	type MaxTargetsPerStaker = MaxTargetsPerStaker;
+	type MaxStakersPerTarget = MaxStakersPerTarget;
+	type WeightInfo = ();
 }
 
 pub fn new_test_ext() -> sp_io::TestExternalities {
@@ -1,7 +1,14 @@
-use frame_support::{assert_noop, assert_ok, traits::Currency};
+use frame_support::{
+	assert_noop, assert_ok,
+	traits::{Currency, OnFinalize},
+};
 use pallet_balances::Error as BalancesError;
+use phala_pallets::pallet_phala;
+use phala_types::{messaging::BlockRewardInfo, WorkerInfo, WorkerStateEnum};
+use sp_core::U256;
+use sp_runtime::Permill;
 
-use crate::{mock::*, Error};
+use crate::{mock::*, Error, StakerPosition, TargetPosition, UnbondingChunk};
 
 #[test]
 fn test_group_by() {
@@ -49,6 +56,11 @@ fn setup_deposit() {
 	assert_ok!(MiningStaking::deposit(Origin::signed(1), 100));
 }
 
+fn fund_reward_pot(value: Balance) {
+	let imbalance = Balances::deposit_creating(&MiningStaking::reward_pot(), value);
+	drop(imbalance);
+}
+
 #[test]
 fn test_stake() {
 	new_test_ext().execute_with(|| {
@@ -105,11 +117,380 @@ fn test_unstake() {
 			Error::<Test>::InsufficientStake
 		);
 		// Apply the pending unstaking
+		System::set_block_number(1);
 		MiningStaking::handle_round_end();
-		assert_eq!(MiningStaking::wallet(1), Some(30));
 		assert_eq!(MiningStaking::staked(1, 2), Some(50));
 		assert_eq!(MiningStaking::staked(1, 3), Some(20));
 		assert_eq!(MiningStaking::stake_received(2), Some(50));
 		assert_eq!(MiningStaking::stake_received(3), Some(20));
+		// The unstaked tokens are unbonding
+		assert_eq!(MiningStaking::wallet(1), Some(20));
+		assert_eq!(
+			MiningStaking::unbonding(1),
+			vec![UnbondingChunk {
+				target: 3,
+				value: 10,
+				unlock_at: 1 + UnbondingPeriod::get(),
+			}]
+		);
+		assert_noop!(
+			MiningStaking::withdraw_unbonded(Origin::signed(1)),
+			Error::<Test>::NothingToClaim
+		);
+		System::set_block_number(1 + UnbondingPeriod::get());
+		assert_ok!(MiningStaking::withdraw_unbonded(Origin::signed(1)));
+		assert_eq!(MiningStaking::wallet(1), Some(30));
+		assert!(MiningStaking::unbonding(1).is_empty());
+	});
+}
+
+#[test]
+fn test_stake_limits() {
+	new_test_ext().execute_with(|| {
+		setup_deposit();
+		let imbalance = Balances::deposit_creating(&4, 200);
+		drop(imbalance);
+		assert_ok!(MiningStaking::deposit(Origin::signed(4), 200));
+		// Too small stake
+		assert_noop!(
+			MiningStaking::stake(Origin::signed(1), 2, 4),
+			Error::<Test>::StakeTooLow
+		);
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 50));
+		// Can't leave too small stake, but can unstake all
+		assert_noop!(
+			MiningStaking::unstake(Origin::signed(1), 2, 46),
+			Error::<Test>::StakeTooLow
+		);
+		assert_ok!(MiningStaking::unstake(Origin::signed(1), 2, 50));
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 50));
+		// The cap counts both the applied and the pending stake
+		MiningStaking::handle_round_end();
+		assert_ok!(MiningStaking::stake(Origin::signed(4), 2, 100));
+		assert_eq!(MiningStaking::pending_stake_received(2), Some(100));
+		assert_noop!(
+			MiningStaking::stake(Origin::signed(4), 2, 51),
+			Error::<Test>::TargetStakeCapExceeded
+		);
+		assert_ok!(MiningStaking::stake(Origin::signed(4), 2, 50));
+		MiningStaking::handle_round_end();
+		assert_eq!(MiningStaking::stake_received(2), Some(200));
+		assert_eq!(MiningStaking::pending_stake_received(2), None);
+		// Cancelling the unstaking doesn't count
+		assert_ok!(MiningStaking::unstake(Origin::signed(4), 2, 20));
+		assert_ok!(MiningStaking::stake(Origin::signed(4), 2, 20));
+		// Too many targets
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 3, 10));
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 4, 10));
+		assert_noop!(
+			MiningStaking::stake(Origin::signed(1), 5, 10),
+			Error::<Test>::TooManyTargets
+		);
+		// Existing targets can still be staked to
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 3, 10));
+		// Leaving a target frees the slot
+		assert_ok!(MiningStaking::unstake(Origin::signed(1), 4, 10));
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 5, 10));
+	});
+}
+
+#[test]
+fn test_staker_position() {
+	new_test_ext().execute_with(|| {
+		setup_deposit();
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 50));
+		MiningStaking::handle_round_end();
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 3, 30));
+		assert_ok!(MiningStaking::unstake(Origin::signed(1), 2, 10));
+		fund_reward_pot(5);
+		MiningStaking::distribute_reward(&2, 5);
+		assert_eq!(
+			MiningStaking::staker_position(&1),
+			StakerPosition {
+				wallet: 50,
+				locked: 30,
+				unbonding: 0,
+				auto_compound: false,
+				targets: vec![
+					TargetPosition {
+						target: 2,
+						staked: 50,
+						pending_staking: 0,
+						pending_unstaking: 10,
+						rewards: 5,
+					},
+					TargetPosition {
+						target: 3,
+						staked: 0,
+						pending_staking: 30,
+						pending_unstaking: 0,
+						rewards: 0,
+					},
+				],
+			}
+		);
+		MiningStaking::handle_round_end();
+		let position = MiningStaking::staker_position(&1);
+		assert_eq!(position.wallet, 20);
+		assert_eq!(position.locked, 0);
+		assert_eq!(position.unbonding, 10);
+		assert_eq!(position.targets.len(), 2);
+	});
+}
+
+#[test]
+fn test_unbonding_chunks() {
+	new_test_ext().execute_with(|| {
+		setup_deposit();
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 100));
+		MiningStaking::handle_round_end();
+		// One chunk per round
+		for i in 1..=3 {
+			System::set_block_number(i);
+			assert_ok!(MiningStaking::unstake(Origin::signed(1), 2, 10));
+			MiningStaking::handle_round_end();
+		}
+		assert_eq!(MiningStaking::unbonding(1).len(), 3);
+		assert_noop!(
+			MiningStaking::unstake(Origin::signed(1), 2, 10),
+			Error::<Test>::TooManyUnbondingChunks
+		);
+		// Only the unlocked chunks are withdrawn
+		System::set_block_number(2 + UnbondingPeriod::get());
+		assert_ok!(MiningStaking::withdraw_unbonded(Origin::signed(1)));
+		assert_eq!(MiningStaking::wallet(1), Some(20));
+		assert_eq!(MiningStaking::unbonding(1).len(), 1);
+		assert_ok!(MiningStaking::unstake(Origin::signed(1), 2, 10));
+		// The unbonding chunks are slashed as well
+		assert_eq!(MiningStaking::slash(&2, Permill::from_percent(10)), 8);
+		assert_eq!(MiningStaking::unbonding(1)[0].value, 9);
+		assert_eq!(MiningStaking::staked(1, 2), Some(63));
+	});
+}
+
+#[test]
+fn test_rewards() {
+	new_test_ext().execute_with(|| {
+		setup_deposit();
+		let imbalance = Balances::deposit_creating(&4, 100);
+		drop(imbalance);
+		assert_ok!(MiningStaking::deposit(Origin::signed(4), 100));
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 30));
+		assert_ok!(MiningStaking::stake(Origin::signed(4), 2, 10));
+		fund_reward_pot(50);
+		// Nobody is staking yet
+		assert_eq!(MiningStaking::distribute_reward(&2, 40), 0);
+		MiningStaking::handle_round_end();
+		// The pot can't cover it
+		assert_eq!(MiningStaking::distribute_reward(&2, 60), 0);
+		// Paid from the pot without minting
+		let issuance = Balances::total_issuance();
+		assert_eq!(MiningStaking::distribute_reward(&2, 40), 40);
+		assert_eq!(Balances::total_issuance(), issuance);
+		assert_eq!(Balances::free_balance(MiningStaking::reward_pot()), 10);
+		assert_eq!(MiningStaking::rewards(1, 2), Some(30));
+		assert_eq!(MiningStaking::rewards(4, 2), Some(10));
+		assert_eq!(MiningStaking::pending_rewards(&1), 30);
+		// Claim to the wallet
+		assert_ok!(MiningStaking::claim_rewards(Origin::signed(1)));
+		assert_eq!(MiningStaking::wallet(1), Some(100));
+		assert_eq!(MiningStaking::rewards(1, 2), None);
+		assert_eq!(MiningStaking::pending_rewards(&1), 0);
+		assert_noop!(
+			MiningStaking::claim_rewards(Origin::signed(1)),
+			Error::<Test>::NothingToClaim
+		);
+		// Compound to the stake
+		assert_ok!(MiningStaking::set_auto_compound(Origin::signed(4), true));
+		MiningStaking::handle_round_end();
+		assert_eq!(MiningStaking::rewards(4, 2), None);
+		assert_eq!(MiningStaking::staked(4, 2), Some(20));
+		assert_eq!(MiningStaking::stake_received(2), Some(50));
+		assert_eq!(MiningStaking::wallet(4), Some(90));
+	});
+}
+
+#[test]
+fn test_reward_shared_with_stakers() {
+	new_test_ext().execute_with(|| {
+		use frame_support::traits::Imbalance;
+		use pallet_phala::OnReward;
+
+		setup_deposit();
+		let issuance = Balances::total_issuance();
+		// Nobody is staking, so the reward goes back
+		let left = MiningStaking::on_reward(&2, Balances::issue(40));
+		assert_eq!(left.peek(), 40);
+		drop(left);
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 50));
+		MiningStaking::handle_round_end();
+		let left = MiningStaking::on_reward(&2, Balances::issue(40));
+		assert_eq!(left.peek(), 0);
+		assert_eq!(MiningStaking::rewards(1, 2), Some(40));
+		assert_eq!(Balances::total_issuance(), issuance + 40);
+		assert_eq!(Balances::free_balance(MiningStaking::account_id()), 140);
+	});
+}
+
+#[test]
+fn test_unbonding_chunks_across_targets() {
+	new_test_ext().execute_with(|| {
+		setup_deposit();
+		for to in 2..=4 {
+			assert_ok!(MiningStaking::stake(Origin::signed(1), to, 20));
+		}
+		MiningStaking::handle_round_end();
+		for i in 1..=2 {
+			System::set_block_number(i);
+			assert_ok!(MiningStaking::unstake(Origin::signed(1), 2, 5));
+			MiningStaking::handle_round_end();
+		}
+		// Each target unstaked in the round adds a chunk at the end of it
+		assert_ok!(MiningStaking::unstake(Origin::signed(1), 3, 10));
+		assert_ok!(MiningStaking::unstake(Origin::signed(1), 3, 5));
+		assert_noop!(
+			MiningStaking::unstake(Origin::signed(1), 4, 10),
+			Error::<Test>::TooManyUnbondingChunks
+		);
+		MiningStaking::handle_round_end();
+		assert_eq!(MiningStaking::unbonding(1).len(), 3);
+	});
+}
+
+#[test]
+fn test_stakers_index() {
+	new_test_ext().execute_with(|| {
+		for who in 1..=4 {
+			let imbalance = Balances::deposit_creating(&who, 100);
+			drop(imbalance);
+			assert_ok!(MiningStaking::deposit(Origin::signed(who), 100));
+		}
+		for who in 1..=3 {
+			assert_ok!(MiningStaking::stake(Origin::signed(who), 9, 10));
+		}
+		assert_eq!(MiningStaking::staker_count(9), 3);
+		assert_noop!(
+			MiningStaking::stake(Origin::signed(4), 9, 10),
+			Error::<Test>::TooManyStakers
+		);
+		// Cancelling the pending staking frees the slot
+		assert_ok!(MiningStaking::unstake(Origin::signed(3), 9, 10));
+		assert_eq!(MiningStaking::staker_count(9), 2);
+		assert_ok!(MiningStaking::stake(Origin::signed(4), 9, 10));
+		MiningStaking::handle_round_end();
+		// So does unstaking all
+		assert_ok!(MiningStaking::unstake(Origin::signed(4), 9, 10));
+		MiningStaking::handle_round_end();
+		assert_eq!(MiningStaking::staker_count(9), 2);
+		assert!(!crate::Stakers::<Test>::contains_key(9, 4));
+	});
+}
+
+#[test]
+fn test_force_trigger_round_end_witness() {
+	new_test_ext().execute_with(|| {
+		setup_deposit();
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 30));
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 3, 30));
+		assert_ok!(MiningStaking::set_auto_compound(Origin::signed(1), true));
+		assert_eq!(MiningStaking::pending_staking_count(), 2);
+		assert_eq!(MiningStaking::auto_compound_count(), 1);
+		assert_noop!(
+			MiningStaking::force_trigger_round_end(Origin::root(), 1, 0, 1),
+			Error::<Test>::InvalidWitness
+		);
+		assert_ok!(MiningStaking::force_trigger_round_end(Origin::root(), 2, 0, 1));
+		assert_eq!(MiningStaking::pending_staking_count(), 0);
+		assert_eq!(MiningStaking::staked(1, 2), Some(30));
+		assert_ok!(MiningStaking::unstake(Origin::signed(1), 2, 30));
+		assert_eq!(MiningStaking::pending_unstaking_count(), 1);
+	});
+}
+
+#[test]
+fn test_slash() {
+	new_test_ext().execute_with(|| {
+		setup_deposit();
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 50));
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 3, 30));
+		MiningStaking::handle_round_end();
+		assert_ok!(MiningStaking::unstake(Origin::signed(1), 2, 50));
+		let issuance = Balances::total_issuance();
+		// Slash 10% of the stake to 2
+		assert_eq!(MiningStaking::slash(&2, Permill::from_percent(10)), 5);
+		assert_eq!(MiningStaking::staked(1, 2), Some(45));
+		assert_eq!(MiningStaking::stake_received(2), Some(45));
+		// The pending unstaking is clipped to the remaining stake
+		assert_eq!(MiningStaking::pending_unstaking(1, 2), Some(45));
+		// The stake to 3 is untouched
+		assert_eq!(MiningStaking::staked(1, 3), Some(30));
+		// The slashed tokens are burnt
+		assert_eq!(Balances::total_issuance(), issuance - 5);
+		assert_eq!(Balances::free_balance(MiningStaking::account_id()), 95);
+	});
+}
+
+#[test]
+fn test_slash_pending_and_unbonding() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		setup_deposit();
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 50));
+		MiningStaking::handle_round_end();
+		assert_ok!(MiningStaking::unstake(Origin::signed(1), 2, 50));
+		MiningStaking::handle_round_end();
+		// Still a staker of 2 while unbonding
+		assert_eq!(MiningStaking::staked(1, 2), Some(0));
+		assert_eq!(MiningStaking::staker_count(2), 1);
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 20));
+		// 10% of the pending staking and the unbonding chunk
+		assert_eq!(MiningStaking::slash(&2, Permill::from_percent(10)), 7);
+		assert_eq!(MiningStaking::pending_staking(1, 2), Some(18));
+		assert_eq!(MiningStaking::pending_stake_received(2), Some(18));
+		assert_eq!(MiningStaking::wallet_locked(1), Some(18));
+		assert_eq!(MiningStaking::wallet(1), Some(48));
+		assert_eq!(MiningStaking::unbonding(1)[0].value, 45);
+		assert_eq!(Balances::free_balance(MiningStaking::account_id()), 93);
+		// Leaves the index once the pending staking is cancelled and the chunk is withdrawn
+		assert_ok!(MiningStaking::unstake(Origin::signed(1), 2, 18));
+		System::set_block_number(1 + UnbondingPeriod::get());
+		assert_ok!(MiningStaking::withdraw_unbonded(Origin::signed(1)));
+		assert_eq!(MiningStaking::staker_count(2), 0);
+		assert_eq!(MiningStaking::slash(&2, Permill::from_percent(10)), 0);
+	});
+}
+
+#[test]
+fn test_offence_slashes_stake() {
+	new_test_ext().execute_with(|| {
+		setup_deposit();
+		assert_ok!(MiningStaking::stake(Origin::signed(1), 2, 100));
+		MiningStaking::handle_round_end();
+		// Miner 2 is challenged at block 5 but misses the heartbeat
+		System::set_block_number(20);
+		pallet_phala::RewardWindow::<Test>::put(8);
+		pallet_phala::SlashWindow::<Test>::put(40);
+		assert_ok!(Phala::set_stash(Origin::signed(2), 20));
+		pallet_phala::WorkerState::<Test>::insert(
+			2,
+			WorkerInfo {
+				pubkey: vec![2u8; 33],
+				state: WorkerStateEnum::Mining(1),
+				..Default::default()
+			},
+		);
+		pallet_phala::BlockRewardSeeds::<Test>::insert(
+			5,
+			BlockRewardInfo {
+				seed: U256::zero(),
+				online_target: U256::MAX,
+				compute_target: U256::MAX,
+			},
+		);
+		assert_ok!(Phala::report_offline(Origin::signed(3), 2, 5));
+		Phala::on_finalize(20 + OffenceDisputePeriod::get());
+		// The first offence slashes 1%
+		assert_eq!(MiningStaking::staked(1, 2), Some(99));
+		assert_eq!(MiningStaking::stake_received(2), Some(99));
 	});
 }
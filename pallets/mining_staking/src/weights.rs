@@ -0,0 +1,157 @@
+//! Weights for pallet_mining_staking
+//!
+//! Estimated from the storage accesses of each call, in the same scale as the weights of
+//! pallet_phala, with the components of the benchmarks. Regenerate with the benchmark CLI:
+//!
+//! ```bash
+//! ./target/release/phala-node benchmark --chain=dev --steps=50 --repeat=20 \
+//!     --pallet=pallet_mining_staking --extrinsic='*' --execution=wasm --wasm-execution=compiled \
+//!     --output=./pallets/mining_staking/src/weights.rs
+//! ```
+
+#![allow(unused_parens)]
+#![allow(unused_imports)]
+
+use frame_support::{
+	traits::Get,
+	weights::{constants::RocksDbWeight, Weight},
+};
+use sp_std::marker::PhantomData;
+
+/// Weight functions needed for pallet_mining_staking.
+pub trait WeightInfo {
+	fn deposit() -> Weight;
+	fn withdraw() -> Weight;
+	fn stake() -> Weight;
+	fn unstake() -> Weight;
+	fn withdraw_unbonded(c: u32) -> Weight;
+	fn claim_rewards() -> Weight;
+	fn set_auto_compound() -> Weight;
+	fn force_trigger_round_end(s: u32, u: u32, c: u32) -> Weight;
+	fn slash(n: u32) -> Weight;
+}
+
+/// Weight functions for pallet_mining_staking.
+pub struct SubstrateWeight<T>(PhantomData<T>);
+impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+	fn deposit() -> Weight {
+		(70_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+	}
+	fn withdraw() -> Weight {
+		(72_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+	}
+	fn stake() -> Weight {
+		(75_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(15 as Weight))
+			.saturating_add(T::DbWeight::get().writes(7 as Weight))
+	}
+	fn unstake() -> Weight {
+		(90_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(26 as Weight))
+			.saturating_add(T::DbWeight::get().writes(8 as Weight))
+	}
+	fn withdraw_unbonded(c: u32) -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add((1_200_000 as Weight).saturating_mul(c as Weight))
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	fn claim_rewards() -> Weight {
+		(35_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	fn set_auto_compound() -> Weight {
+		(18_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	fn force_trigger_round_end(s: u32, u: u32, c: u32) -> Weight {
+		(40_000_000 as Weight)
+			.saturating_add((45_000_000 as Weight).saturating_mul(s as Weight))
+			.saturating_add((50_000_000 as Weight).saturating_mul(u as Weight))
+			.saturating_add((55_000_000 as Weight).saturating_mul(c as Weight))
+			.saturating_add(T::DbWeight::get().reads(5 as Weight))
+			.saturating_add(T::DbWeight::get().reads((7 as Weight).saturating_mul(s as Weight)))
+			.saturating_add(T::DbWeight::get().reads((7 as Weight).saturating_mul(u as Weight)))
+			.saturating_add(T::DbWeight::get().reads((7 as Weight).saturating_mul(c as Weight)))
+			.saturating_add(T::DbWeight::get().writes(5 as Weight))
+			.saturating_add(T::DbWeight::get().writes((6 as Weight).saturating_mul(s as Weight)))
+			.saturating_add(T::DbWeight::get().writes((6 as Weight).saturating_mul(u as Weight)))
+			.saturating_add(T::DbWeight::get().writes((5 as Weight).saturating_mul(c as Weight)))
+	}
+	fn slash(n: u32) -> Weight {
+		(45_000_000 as Weight)
+			.saturating_add((38_000_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().reads((9 as Weight).saturating_mul(n as Weight)))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes((8 as Weight).saturating_mul(n as Weight)))
+	}
+}
+
+// For backwards compatibility and tests
+impl WeightInfo for () {
+	fn deposit() -> Weight {
+		(70_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+	}
+	fn withdraw() -> Weight {
+		(72_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+	}
+	fn stake() -> Weight {
+		(75_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(15 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(7 as Weight))
+	}
+	fn unstake() -> Weight {
+		(90_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(26 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(8 as Weight))
+	}
+	fn withdraw_unbonded(c: u32) -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add((1_200_000 as Weight).saturating_mul(c as Weight))
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn claim_rewards() -> Weight {
+		(35_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn set_auto_compound() -> Weight {
+		(18_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn force_trigger_round_end(s: u32, u: u32, c: u32) -> Weight {
+		(40_000_000 as Weight)
+			.saturating_add((45_000_000 as Weight).saturating_mul(s as Weight))
+			.saturating_add((50_000_000 as Weight).saturating_mul(u as Weight))
+			.saturating_add((55_000_000 as Weight).saturating_mul(c as Weight))
+			.saturating_add(RocksDbWeight::get().reads(5 as Weight))
+			.saturating_add(RocksDbWeight::get().reads((7 as Weight).saturating_mul(s as Weight)))
+			.saturating_add(RocksDbWeight::get().reads((7 as Weight).saturating_mul(u as Weight)))
+			.saturating_add(RocksDbWeight::get().reads((7 as Weight).saturating_mul(c as Weight)))
+			.saturating_add(RocksDbWeight::get().writes(5 as Weight))
+			.saturating_add(RocksDbWeight::get().writes((6 as Weight).saturating_mul(s as Weight)))
+			.saturating_add(RocksDbWeight::get().writes((6 as Weight).saturating_mul(u as Weight)))
+			.saturating_add(RocksDbWeight::get().writes((5 as Weight).saturating_mul(c as Weight)))
+	}
+	fn slash(n: u32) -> Weight {
+		(45_000_000 as Weight)
+			.saturating_add((38_000_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
+			.saturating_add(RocksDbWeight::get().reads((9 as Weight).saturating_mul(n as Weight)))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes((8 as Weight).saturating_mul(n as Weight)))
+	}
+}
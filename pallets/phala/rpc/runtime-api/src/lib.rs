@@ -0,0 +1,55 @@
+//! Runtime API definition for the Phala pallets.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Codec, Decode, Encode};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_runtime::RuntimeDebug;
+use sp_std::prelude::*;
+
+pub use phala_types::{
+    messaging::MessageOrigin, EcdhPublicKey, RoundInfo, RoundStats, StashInfo, WorkerPublicKey,
+};
+
+/// A worker registered in `pallet_registry`, with the miner it's bound to
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct RegisteredWorker<AccountId> {
+    pub pubkey: WorkerPublicKey,
+    pub ecdh_pubkey: EcdhPublicKey,
+    pub runtime_version: u32,
+    pub last_updated: u64,
+    pub confidence_level: u8,
+    pub initial_score: Option<u32>,
+    pub features: Vec<u32>,
+    /// The miner the worker is bound to by its operator in `pallet_mining`
+    pub miner: Option<AccountId>,
+}
+
+sp_api::decl_runtime_apis! {
+    pub trait PhalaApi<AccountId, Balance, BlockNumber> where
+        AccountId: Codec,
+        Balance: Codec,
+        BlockNumber: Codec,
+    {
+        /// Returns the registered worker, or `None` if the pubkey has never been registered.
+        fn worker_info(pubkey: WorkerPublicKey) -> Option<RegisteredWorker<AccountId>>;
+        /// Returns the controller and the payout preferences of a stash.
+        fn stash_info(stash: AccountId) -> Option<StashInfo<AccountId>>;
+        /// Returns the miner of a legacy worker whose pubkey couldn't be bound. The other workers
+        /// are bound by their pubkeys (see `worker_info`).
+        fn machine_owner(machine_id: Vec<u8>) -> Option<AccountId>;
+        /// Returns the current mining round.
+        fn round_info() -> RoundInfo<BlockNumber>;
+        /// Returns the statistics of a mining round, if it's still in the history.
+        fn round_stats(round: u32) -> Option<RoundStats>;
+        /// Returns the staking rewards of `who` not claimed yet, over all its targets.
+        fn pending_rewards(who: AccountId) -> Balance;
+        /// Returns the next sequence the message queue expects from an offchain sender.
+        fn mq_ingress_seq(sender: MessageOrigin) -> u64;
+        /// Returns the public keys of the registered gatekeepers.
+        fn gatekeepers() -> Vec<WorkerPublicKey>;
+    }
+}
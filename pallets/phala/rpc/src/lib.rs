@@ -0,0 +1,198 @@
+//! RPC interface for the Phala pallets.
+//!
+//! Clients should query the worker, stash and mining round states here instead of decoding the
+//! raw storage, whose layout changes as the pallets evolve.
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use codec::{Codec, Decode};
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::Bytes;
+use sp_rpc::number::NumberOrHex;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+pub use phala_pallets_rpc_runtime_api::{
+    MessageOrigin, PhalaApi as PhalaRuntimeApi, RegisteredWorker, RoundInfo, RoundStats,
+    StashInfo, WorkerPublicKey,
+};
+
+#[rpc]
+pub trait PhalaApi<BlockHash, AccountId, Balance, BlockNumber> {
+    /// Returns the registered worker and the miner it's bound to.
+    #[rpc(name = "pha_getWorkerInfo")]
+    fn worker_info(
+        &self,
+        pubkey: WorkerPublicKey,
+        at: Option<BlockHash>,
+    ) -> Result<Option<RegisteredWorker<AccountId>>>;
+
+    /// Returns the controller and the payout preferences of a stash.
+    #[rpc(name = "pha_getStashInfo")]
+    fn stash_info(
+        &self,
+        stash: AccountId,
+        at: Option<BlockHash>,
+    ) -> Result<Option<StashInfo<AccountId>>>;
+
+    /// Returns the miner of a legacy worker whose pubkey couldn't be bound.
+    #[rpc(name = "pha_getMachineOwner")]
+    fn machine_owner(&self, machine_id: Bytes, at: Option<BlockHash>) -> Result<Option<AccountId>>;
+
+    /// Returns the current mining round.
+    #[rpc(name = "pha_getRoundInfo")]
+    fn round_info(&self, at: Option<BlockHash>) -> Result<RoundInfo<BlockNumber>>;
+
+    /// Returns the statistics of a mining round (the current round by default).
+    #[rpc(name = "pha_getRoundStats")]
+    fn round_stats(&self, round: Option<u32>, at: Option<BlockHash>) -> Result<Option<RoundStats>>;
+
+    /// Returns the staking rewards of `who` not claimed yet.
+    #[rpc(name = "pha_getPendingRewards")]
+    fn pending_rewards(&self, who: AccountId, at: Option<BlockHash>) -> Result<NumberOrHex>;
+
+    /// Returns the next sequence the message queue expects from an offchain sender, given as the
+    /// SCALE encoded `MessageOrigin`.
+    #[rpc(name = "pha_getMqIngressSeq")]
+    fn mq_ingress_seq(&self, sender: Bytes, at: Option<BlockHash>) -> Result<u64>;
+
+    /// Returns the public keys of the registered gatekeepers.
+    #[rpc(name = "pha_getGatekeepers")]
+    fn gatekeepers(&self, at: Option<BlockHash>) -> Result<Vec<WorkerPublicKey>>;
+}
+
+/// Implements the PhalaApi RPC trait for querying the Phala pallets.
+pub struct Phala<C, Block> {
+    client: Arc<C>,
+    _marker: PhantomData<Block>,
+}
+
+impl<C, Block> Phala<C, Block> {
+    pub fn new(client: Arc<C>) -> Self {
+        Self {
+            client,
+            _marker: Default::default(),
+        }
+    }
+}
+
+/// Error code of the failed runtime calls.
+const RUNTIME_ERROR: i64 = 1;
+
+fn runtime_error(e: impl Debug) -> RpcError {
+    RpcError {
+        code: ErrorCode::ServerError(RUNTIME_ERROR),
+        message: "Runtime call failed.".into(),
+        data: Some(format!("{:?}", e).into()),
+    }
+}
+
+impl<C, Block> Phala<C, Block>
+where
+    Block: BlockT,
+    C: HeaderBackend<Block>,
+{
+    fn block_id(&self, at: Option<<Block as BlockT>::Hash>) -> BlockId<Block> {
+        BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash))
+    }
+}
+
+impl<C, Block, AccountId, Balance, BlockNumber>
+    PhalaApi<<Block as BlockT>::Hash, AccountId, Balance, BlockNumber> for Phala<C, Block>
+where
+    Block: BlockT,
+    C: 'static + ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync,
+    C::Api: PhalaRuntimeApi<Block, AccountId, Balance, BlockNumber>,
+    AccountId: Codec,
+    Balance: Codec + Into<NumberOrHex>,
+    BlockNumber: Codec,
+{
+    fn worker_info(
+        &self,
+        pubkey: WorkerPublicKey,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Option<RegisteredWorker<AccountId>>> {
+        self.client
+            .runtime_api()
+            .worker_info(&self.block_id(at), pubkey)
+            .map_err(runtime_error)
+    }
+
+    fn stash_info(
+        &self,
+        stash: AccountId,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Option<StashInfo<AccountId>>> {
+        self.client
+            .runtime_api()
+            .stash_info(&self.block_id(at), stash)
+            .map_err(runtime_error)
+    }
+
+    fn machine_owner(
+        &self,
+        machine_id: Bytes,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Option<AccountId>> {
+        self.client
+            .runtime_api()
+            .machine_owner(&self.block_id(at), machine_id.to_vec())
+            .map_err(runtime_error)
+    }
+
+    fn round_info(&self, at: Option<<Block as BlockT>::Hash>) -> Result<RoundInfo<BlockNumber>> {
+        self.client
+            .runtime_api()
+            .round_info(&self.block_id(at))
+            .map_err(runtime_error)
+    }
+
+    fn round_stats(
+        &self,
+        round: Option<u32>,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Option<RoundStats>> {
+        let api = self.client.runtime_api();
+        let at = self.block_id(at);
+        let round = match round {
+            Some(round) => round,
+            None => api.round_info(&at).map_err(runtime_error)?.round,
+        };
+        api.round_stats(&at, round).map_err(runtime_error)
+    }
+
+    fn pending_rewards(
+        &self,
+        who: AccountId,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<NumberOrHex> {
+        self.client
+            .runtime_api()
+            .pending_rewards(&self.block_id(at), who)
+            .map(Into::into)
+            .map_err(runtime_error)
+    }
+
+    fn mq_ingress_seq(&self, sender: Bytes, at: Option<<Block as BlockT>::Hash>) -> Result<u64> {
+        let sender = MessageOrigin::decode(&mut &sender[..]).map_err(|e| RpcError {
+            code: ErrorCode::InvalidParams,
+            message: "Invalid message origin.".into(),
+            data: Some(format!("{:?}", e).into()),
+        })?;
+        self.client
+            .runtime_api()
+            .mq_ingress_seq(&self.block_id(at), sender)
+            .map_err(runtime_error)
+    }
+
+    fn gatekeepers(&self, at: Option<<Block as BlockT>::Hash>) -> Result<Vec<WorkerPublicKey>> {
+        self.client
+            .runtime_api()
+            .gatekeepers(&self.block_id(at))
+            .map_err(runtime_error)
+    }
+}
@@ -7,6 +7,8 @@ pub const BLOCK_REWARD_TO_KEEP: u32 = 20;
 pub const ROUND_STATS_TO_KEEP: u32 = 2;
 pub const PERCENTAGE_BASE: u32 = 100_000;
 pub const DEFAULT_BLOCK_REWARD_TO_KEEP: u32 = 20;
+/// The rounds without any offence to reset the slashing span of a miner
+pub const OFFENCE_SPAN_ROUNDS: u32 = 24;
 
 pub const IAS_QUOTE_STATUS_LEVEL_1: &'static [&str] = &["OK"];
 pub const IAS_QUOTE_STATUS_LEVEL_2: &'static [&str] = &["SW_HARDENING_NEEDED"];
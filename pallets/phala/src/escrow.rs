@@ -0,0 +1,223 @@
+/// Escrow of the funds in the confidential `Balances` contract.
+///
+/// A deposit locks the funds in the escrow account and tells the contract to credit them. The
+/// contract acknowledges it with `BalanceReport::DepositAccepted`. A withdrawal from the contract
+/// (`BalanceReport::Withdrawal`) is paid from the escrow and confirmed back to the contract, or
+/// refunded if it fails. So `TotalEscrowed` always equals the total issuance in the contract plus
+/// the withdrawals it hasn't seen confirmed yet.
+pub use self::pallet::*;
+
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+
+#[cfg(test)]
+mod tests;
+
+pub mod migrations;
+pub mod weights;
+
+pub use weights::WeightInfo;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{Currency, ExistenceRequirement::{AllowDeath, KeepAlive}},
+		PalletId,
+	};
+	use frame_system::pallet_prelude::*;
+	use sp_runtime::traits::{AccountIdConversion, Saturating, Zero};
+
+	use super::WeightInfo;
+	use crate::mq::MessageOriginInfo;
+	use phala_types::messaging::{BalanceEvent, BalanceReport, Message, MessageOrigin};
+
+	/// The id of the `Balances` contract in pRuntime
+	const BALANCES_CONTRACT_ID: u32 = 2;
+	const ESCROW_PALLET_ID: PalletId = PalletId(*b"phala/es");
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config {
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		type Currency: Currency<Self::AccountId>;
+
+		type WeightInfo: WeightInfo;
+	}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(_);
+
+	/// The number of the next deposit
+	#[pallet::storage]
+	pub type NextDeposit<T> = StorageValue<_, u64, ValueQuery>;
+
+	/// The deposits not acknowledged by the contract yet
+	#[pallet::storage]
+	pub type PendingDeposits<T: Config> =
+		StorageMap<_, Twox64Concat, u64, (T::AccountId, BalanceOf<T>)>;
+
+	/// The receipts of the withdrawals, by the withdrawal number assigned by the contract
+	#[pallet::storage]
+	pub type Withdrawals<T: Config> = StorageMap<
+		_,
+		Twox64Concat,
+		u64,
+		WithdrawalReceipt<T::AccountId, BalanceOf<T>, T::BlockNumber>,
+	>;
+
+	/// The total funds in the escrow
+	#[pallet::storage]
+	pub type TotalEscrowed<T: Config> = StorageValue<_, BalanceOf<T>, ValueQuery>;
+
+	/// Whether the funds of the deprecated `transfer_to_tee` are moved to the escrow
+	#[pallet::storage]
+	pub type LegacyDepositsMigrated<T> = StorageValue<_, bool, ValueQuery>;
+
+	#[pallet::event]
+	#[pallet::metadata(T::AccountId = "AccountId", BalanceOf<T> = "Balance")]
+	#[pallet::generate_deposit(pub(crate) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// Funds are escrowed for the contract. [number, who, amount]
+		Deposited(u64, T::AccountId, BalanceOf<T>),
+		/// The contract credited the deposit. [number]
+		DepositAccepted(u64),
+		/// A withdrawal is paid. [number, dest, amount]
+		Withdrawn(u64, T::AccountId, BalanceOf<T>),
+		/// A withdrawal failed and is refunded in the contract. [number, dest, amount]
+		WithdrawalFailed(u64, T::AccountId, BalanceOf<T>),
+		/// The funds of the deprecated `transfer_to_tee` are moved to the escrow. [amount]
+		LegacyDepositsEscrowed(BalanceOf<T>),
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// Can't deposit nothing
+		ZeroAmount,
+		/// The message isn't sent by the `Balances` contract
+		InvalidSender,
+		/// Cannot decode the message
+		InvalidInput,
+		/// No such pending deposit
+		UnknownDeposit,
+		/// The withdrawal has been processed
+		DuplicateWithdrawal,
+	}
+
+	pub(crate) type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
+	#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+	pub struct WithdrawalReceipt<AccountId, Balance, BlockNumber> {
+		pub dest: AccountId,
+		pub amount: Balance,
+		pub block: BlockNumber,
+		/// Whether the funds are paid. Failed withdrawals are refunded in the contract.
+		pub paid: bool,
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T>
+	where
+		T: crate::mq::Config,
+	{
+		/// Escrows `amount` and credits it to the sender in the `Balances` contract
+		#[pallet::weight(T::WeightInfo::deposit())]
+		pub fn deposit(
+			origin: OriginFor<T>,
+			#[pallet::compact] amount: BalanceOf<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(!amount.is_zero(), Error::<T>::ZeroAmount);
+			T::Currency::transfer(&who, &Self::account_id(), amount, AllowDeath)?;
+
+			let number = NextDeposit::<T>::mutate(|next| {
+				let number = *next;
+				*next += 1;
+				number
+			});
+			let escrowed = TotalEscrowed::<T>::mutate(|total| {
+				*total = total.saturating_add(amount);
+				*total
+			});
+			PendingDeposits::<T>::insert(number, (who.clone(), amount));
+			Self::push_message(BalanceEvent::Deposit {
+				number,
+				who: who.clone(),
+				amount,
+				escrowed,
+			});
+			Self::deposit_event(Event::Deposited(number, who, amount));
+			Ok(())
+		}
+	}
+
+	impl<T: Config + crate::mq::Config> Pallet<T> {
+		pub fn account_id() -> T::AccountId {
+			ESCROW_PALLET_ID.into_account()
+		}
+
+		pub fn on_message_received(message: &Message) -> DispatchResult {
+			ensure!(
+				message.sender == MessageOrigin::native_contract(BALANCES_CONTRACT_ID),
+				Error::<T>::InvalidSender
+			);
+			let report: BalanceReport<T::AccountId, BalanceOf<T>> =
+				message.decode_payload().ok_or(Error::<T>::InvalidInput)?;
+
+			match report {
+				BalanceReport::DepositAccepted { number } => {
+					PendingDeposits::<T>::take(number).ok_or(Error::<T>::UnknownDeposit)?;
+					Self::deposit_event(Event::DepositAccepted(number));
+				}
+				BalanceReport::Withdrawal {
+					number,
+					dest,
+					amount,
+				} => {
+					ensure!(
+						!Withdrawals::<T>::contains_key(number),
+						Error::<T>::DuplicateWithdrawal
+					);
+					let paid = amount <= TotalEscrowed::<T>::get()
+						&& T::Currency::transfer(&Self::account_id(), &dest, amount, KeepAlive)
+							.is_ok();
+					Withdrawals::<T>::insert(
+						number,
+						WithdrawalReceipt {
+							dest: dest.clone(),
+							amount,
+							block: frame_system::Pallet::<T>::block_number(),
+							paid,
+						},
+					);
+					if paid {
+						let escrowed = TotalEscrowed::<T>::mutate(|total| {
+							*total -= amount;
+							*total
+						});
+						Self::push_message(
+							BalanceEvent::<T::AccountId, BalanceOf<T>>::WithdrawalConfirmed {
+								number,
+								escrowed,
+							},
+						);
+						Self::deposit_event(Event::Withdrawn(number, dest, amount));
+					} else {
+						Self::push_message(
+							BalanceEvent::<T::AccountId, BalanceOf<T>>::WithdrawalFailed { number },
+						);
+						Self::deposit_event(Event::WithdrawalFailed(number, dest, amount));
+					}
+				}
+			}
+			Ok(())
+		}
+	}
+
+	impl<T: Config + crate::mq::Config> MessageOriginInfo for Pallet<T> {
+		type Config = T;
+	}
+}
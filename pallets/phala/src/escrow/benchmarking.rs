@@ -0,0 +1,31 @@
+//! Escrow pallet benchmarking.
+
+#![cfg(feature = "runtime-benchmarks")]
+
+use super::*;
+
+use frame_benchmarking::{benchmarks, impl_benchmark_test_suite, whitelisted_caller};
+use frame_support::traits::Currency;
+use frame_system::RawOrigin;
+
+use crate::escrow::Pallet as PhalaEscrow;
+
+benchmarks! {
+	where_clause { where T: crate::mq::Config }
+
+	deposit {
+		let caller: T::AccountId = whitelisted_caller();
+		let amount = T::Currency::minimum_balance().max(1u32.into()) * 100u32.into();
+		T::Currency::make_free_balance_be(&caller, amount + amount);
+	}: _(RawOrigin::Signed(caller.clone()), amount)
+	verify {
+		assert_eq!(PendingDeposits::<T>::get(0), Some((caller, amount)));
+		assert_eq!(TotalEscrowed::<T>::get(), amount);
+	}
+}
+
+impl_benchmark_test_suite!(
+	PhalaEscrow,
+	crate::phala_legacy::mock::new_test_ext(),
+	crate::phala_legacy::mock::Test,
+);
@@ -0,0 +1,56 @@
+//! Storage migrations of the escrow pallet
+
+use frame_support::{
+	traits::{Currency, ExistenceRequirement::AllowDeath, Get, OnRuntimeUpgrade},
+	weights::Weight,
+};
+use sp_runtime::traits::{AccountIdConversion, Saturating, Zero};
+use sp_std::marker::PhantomData;
+
+use super::*;
+use crate::mq::MessageOriginInfo;
+use phala_types::messaging::BalanceEvent;
+
+/// Moves the funds deposited by the deprecated `pallet_phala::transfer_to_tee` to the escrow.
+///
+/// The `Balances` contract credited them without escrowing, but pays their withdrawals from the
+/// escrow like any other funds. After the move `TotalEscrowed` covers them, and the contract is
+/// told by `BalanceEvent::LegacyEscrowed` to count them as escrowed.
+pub struct MigrateLegacyDeposits<T>(PhantomData<T>);
+
+impl<T: Config + crate::mq::Config> OnRuntimeUpgrade for MigrateLegacyDeposits<T> {
+	fn on_runtime_upgrade() -> Weight {
+		if LegacyDepositsMigrated::<T>::get() {
+			return T::DbWeight::get().reads(1);
+		}
+		let legacy_account: T::AccountId = crate::constants::PALLET_ID.into_account();
+		let amount = T::Currency::free_balance(&legacy_account);
+		let escrow_account = Pallet::<T>::account_id();
+		let moved = !amount.is_zero()
+			&& T::Currency::transfer(&legacy_account, &escrow_account, amount, AllowDeath).is_ok();
+		if moved {
+			let escrowed = TotalEscrowed::<T>::mutate(|total| {
+				*total = total.saturating_add(amount);
+				*total
+			});
+			Pallet::<T>::push_message(
+				BalanceEvent::<T::AccountId, BalanceOf<T>>::LegacyEscrowed { amount, escrowed },
+			);
+			Pallet::<T>::deposit_event(Event::LegacyDepositsEscrowed(amount));
+		}
+		LegacyDepositsMigrated::<T>::put(true);
+		T::DbWeight::get().reads_writes(4, 5)
+	}
+
+	#[cfg(feature = "try-runtime")]
+	fn post_upgrade() -> Result<(), &'static str> {
+		if !LegacyDepositsMigrated::<T>::get() {
+			return Err("migration flag not set");
+		}
+		let escrow_balance = T::Currency::free_balance(&Pallet::<T>::account_id());
+		if escrow_balance < TotalEscrowed::<T>::get() {
+			return Err("escrow account can't cover TotalEscrowed");
+		}
+		Ok(())
+	}
+}
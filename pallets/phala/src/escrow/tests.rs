@@ -0,0 +1,124 @@
+use codec::Encode;
+use frame_support::{
+	assert_noop, assert_ok,
+	traits::{Currency, OnRuntimeUpgrade},
+};
+use sp_runtime::traits::AccountIdConversion;
+
+use super::{
+	migrations::MigrateLegacyDeposits, Error, LegacyDepositsMigrated, PendingDeposits,
+	TotalEscrowed, Withdrawals,
+};
+use crate::phala_legacy::mock::*;
+use phala_types::messaging::{BalanceReport, BindTopic, Message, MessageOrigin};
+
+const ALICE: u64 = 1;
+const BOB: u64 = 2;
+
+fn report(report: BalanceReport<u64, Balance>) -> Message {
+	Message::new(
+		MessageOrigin::native_contract(2),
+		BalanceReport::<u64, Balance>::TOPIC.to_vec(),
+		report.encode(),
+	)
+}
+
+fn withdrawal(number: u64, amount: Balance) -> Message {
+	report(BalanceReport::Withdrawal {
+		number,
+		dest: BOB,
+		amount,
+	})
+}
+
+#[test]
+fn test_deposit_and_withdraw() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let _ = Balances::deposit_creating(&ALICE, 1000);
+
+		assert_noop!(
+			PhalaEscrow::deposit(Origin::signed(ALICE), 0),
+			Error::<Test>::ZeroAmount
+		);
+		assert_ok!(PhalaEscrow::deposit(Origin::signed(ALICE), 300));
+		assert_eq!(Balances::free_balance(&ALICE), 700);
+		assert_eq!(TotalEscrowed::<Test>::get(), 300);
+		assert_eq!(PendingDeposits::<Test>::get(0), Some((ALICE, 300)));
+
+		// Only the Balances contract can report
+		let accepted = report(BalanceReport::DepositAccepted { number: 0 });
+		let forged = Message::new(
+			MessageOrigin::AccountId(Default::default()),
+			BalanceReport::<u64, Balance>::TOPIC.to_vec(),
+			BalanceReport::<u64, Balance>::DepositAccepted { number: 0 }.encode(),
+		);
+		assert_noop!(
+			PhalaEscrow::on_message_received(&forged),
+			Error::<Test>::InvalidSender
+		);
+		assert_ok!(PhalaEscrow::on_message_received(&accepted));
+		assert_eq!(PendingDeposits::<Test>::get(0), None);
+		assert_noop!(
+			PhalaEscrow::on_message_received(&accepted),
+			Error::<Test>::UnknownDeposit
+		);
+
+		assert_ok!(PhalaEscrow::on_message_received(&withdrawal(0, 100)));
+		assert_eq!(Balances::free_balance(&BOB), 100);
+		assert_eq!(TotalEscrowed::<Test>::get(), 200);
+		assert!(Withdrawals::<Test>::get(0).unwrap().paid);
+		assert_noop!(
+			PhalaEscrow::on_message_received(&withdrawal(0, 100)),
+			Error::<Test>::DuplicateWithdrawal
+		);
+
+		// More than escrowed: recorded as failed, to be refunded by the contract
+		assert_ok!(PhalaEscrow::on_message_received(&withdrawal(1, 500)));
+		assert!(!Withdrawals::<Test>::get(1).unwrap().paid);
+		assert_eq!(Balances::free_balance(&BOB), 100);
+		assert_eq!(TotalEscrowed::<Test>::get(), 200);
+	});
+}
+
+#[test]
+fn test_withdrawal_keeps_escrow_alive() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let _ = Balances::deposit_creating(&ALICE, 1000);
+		assert_ok!(PhalaEscrow::deposit(Origin::signed(ALICE), 300));
+
+		// Draining the escrow account would reap it
+		assert_ok!(PhalaEscrow::on_message_received(&withdrawal(0, 300)));
+		assert!(!Withdrawals::<Test>::get(0).unwrap().paid);
+		assert_eq!(Balances::free_balance(&PhalaEscrow::account_id()), 300);
+		assert_eq!(TotalEscrowed::<Test>::get(), 300);
+
+		assert_ok!(PhalaEscrow::on_message_received(&withdrawal(1, 299)));
+		assert!(Withdrawals::<Test>::get(1).unwrap().paid);
+		assert_eq!(Balances::free_balance(&PhalaEscrow::account_id()), 1);
+	});
+}
+
+#[test]
+fn test_migrate_legacy_deposits() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let legacy_account: u64 = crate::constants::PALLET_ID.into_account();
+		let _ = Balances::deposit_creating(&legacy_account, 500);
+		let _ = Balances::deposit_creating(&ALICE, 1000);
+		assert_ok!(PhalaEscrow::deposit(Origin::signed(ALICE), 300));
+
+		MigrateLegacyDeposits::<Test>::on_runtime_upgrade();
+		assert!(LegacyDepositsMigrated::<Test>::get());
+		assert_eq!(Balances::free_balance(&legacy_account), 0);
+		assert_eq!(Balances::free_balance(&PhalaEscrow::account_id()), 800);
+		assert_eq!(TotalEscrowed::<Test>::get(), 800);
+
+		// Only once
+		let _ = Balances::deposit_creating(&legacy_account, 100);
+		MigrateLegacyDeposits::<Test>::on_runtime_upgrade();
+		assert_eq!(Balances::free_balance(&legacy_account), 100);
+		assert_eq!(TotalEscrowed::<Test>::get(), 800);
+	});
+}
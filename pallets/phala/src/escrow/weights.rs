@@ -0,0 +1,43 @@
+//! Weights for pallet_escrow
+//!
+//! Estimated from the storage accesses of each call, in the same scale as the weights of
+//! pallet_phala. Regenerate with the benchmark CLI:
+//!
+//! ```bash
+//! ./target/release/phala-node benchmark --chain=dev --steps=50 --repeat=20 \
+//!     --pallet=pallet_escrow --extrinsic='*' --execution=wasm --wasm-execution=compiled \
+//!     --output=./pallets/phala/src/escrow/weights.rs
+//! ```
+
+#![allow(unused_parens)]
+#![allow(unused_imports)]
+
+use frame_support::{
+	traits::Get,
+	weights::{constants::RocksDbWeight, Weight},
+};
+use sp_std::marker::PhantomData;
+
+/// Weight functions needed for pallet_escrow.
+pub trait WeightInfo {
+	fn deposit() -> Weight;
+}
+
+/// Weight functions for pallet_escrow.
+pub struct SubstrateWeight<T>(PhantomData<T>);
+impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+	fn deposit() -> Weight {
+		(80_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(5 as Weight))
+	}
+}
+
+// For backwards compatibility and tests
+impl WeightInfo for () {
+	fn deposit() -> Weight {
+		(80_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(5 as Weight))
+	}
+}
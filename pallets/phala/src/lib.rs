@@ -7,6 +7,7 @@
 //! - `phala_legacy`: The legacy `pallet-phala`; will be retired gradually
 //! - `mq`: The message queue to connect components in the network
 //! - `registry`: Manages the public key of offchain components (i.e. workers and contracts)
+//! - `escrow`: Escrows the funds deposited to the confidential `Balances` contract
 //!
 //! # Status
 //!
@@ -16,9 +17,10 @@
 //! The `mq` pallet has a basic implementation and it's supposed to work. The `registry` pallet
 //! only has very basic API for testing.
 
-pub(crate) mod attestation;
+pub mod attestation;
 pub mod constants;
 
+pub mod escrow;
 pub mod mining;
 pub mod mq;
 pub mod phala_legacy;
@@ -26,6 +28,7 @@ pub mod registry;
 pub mod stakepool;
 
 // Alias
+pub use escrow as pallet_escrow;
 pub use mining as pallet_mining;
 pub use mq as pallet_mq;
 pub use phala_legacy as pallet_phala;
@@ -5,6 +5,9 @@ pub mod pallet {
 	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, traits::Currency};
 	use frame_system::pallet_prelude::*;
 
+	use phala_types::WorkerPublicKey;
+	use sp_std::vec::Vec;
+
 	#[pallet::config]
 	pub trait Config: frame_system::Config {
 		type Event: From<Event> + IsType<<Self as frame_system::Config>::Event>;
@@ -20,6 +23,17 @@ pub mod pallet {
 	// #[pallet::storage]
 	// pub type OffchainIngress<T> = StorageMap<_, Twox64Concat, MessageOrigin, u64>;
 
+	/// Mapping from worker pubkey to the miner it's bound to, set by the operator of the worker
+	/// through the registry
+	#[pallet::storage]
+	pub type WorkerBindings<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, T::AccountId>;
+
+	/// Mapping from machine id to the miner, only for the legacy workers without a valid pubkey
+	/// to be bound
+	#[pallet::storage]
+	pub type MachineOwner<T: Config> = StorageMap<_, Blake2_128Concat, Vec<u8>, T::AccountId>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event {
@@ -82,4 +96,27 @@ pub mod pallet {
 			panic!("unimplemented")
 		}
 	}
+
+	impl<T: Config> crate::registry::WorkerBindings<T::AccountId> for Pallet<T> {
+		fn bound_miner(worker: &WorkerPublicKey) -> Option<T::AccountId> {
+			WorkerBindings::<T>::get(worker)
+		}
+
+		fn bind(worker: &WorkerPublicKey, miner: &T::AccountId) {
+			WorkerBindings::<T>::insert(worker, miner);
+		}
+
+		fn unbind(worker: &WorkerPublicKey) {
+			WorkerBindings::<T>::remove(worker);
+		}
+	}
+
+	impl<T: Config> crate::registry::OnWorkerKeyRotated for Pallet<T> {
+		/// Keeps the worker bound to its miner after the key handover
+		fn on_worker_key_rotated(old_pubkey: &WorkerPublicKey, new_pubkey: &WorkerPublicKey) {
+			if let Some(miner) = WorkerBindings::<T>::take(old_pubkey) {
+				WorkerBindings::<T>::insert(new_pubkey, miner);
+			}
+		}
+	}
 }
@@ -19,6 +19,9 @@ pub mod pallet {
 	use primitive_types::H256;
 	use sp_std::vec::Vec;
 
+	/// The weight to decode and hash a byte of a message payload
+	pub(crate) const PAYLOAD_BYTE_WEIGHT: Weight = 2_000;
+
 	#[pallet::config]
 	pub trait Config: frame_system::Config {
 		type Event: From<Event> + IsType<<Self as frame_system::Config>::Event>;
@@ -56,7 +59,15 @@ pub mod pallet {
 		T::AccountId: IntoH256,
 	{
 		/// Syncs an unverified offchain message to the message queue
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		///
+		/// The handlers of the message do work linear to its payload at most (e.g. the Merkle
+		/// proofs of a benchmark report), so the weight grows with the payload size.
+		#[pallet::weight(
+			10_000
+				+ T::DbWeight::get().reads_writes(3, 3)
+				+ (signed_message.message.payload.len() as Weight)
+					.saturating_mul(PAYLOAD_BYTE_WEIGHT)
+		)]
 		pub fn sync_offchain_message(
 			origin: OriginFor<T>,
 			signed_message: SignedMessage,
@@ -4,80 +4,14 @@
 
 use super::*;
 
-use crate::types::{
-	RoundStats, SignedWorkerMessage, WorkerMessage, WorkerMessagePayload, WorkerStateEnum,
-};
-use crate::Pallet as PhalaPallet;
-use codec::Encode;
-use frame_benchmarking::{account, benchmarks, whitelisted_caller};
+use frame_benchmarking::{account, benchmarks, impl_benchmark_test_suite, whitelisted_caller};
 use frame_system::RawOrigin;
-use sp_runtime::traits::Bounded;
-const CONTRACT_ID: u32 = 123;
-const SEED: u32 = 0;
-const DOLLARS: u128 = 1_000_000_000_000;
+use sp_core::testing::ECDSA;
 
-type SignatureAlgorithms = &'static [&'static webpki::SignatureAlgorithm];
-static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
-	// &webpki::ECDSA_P256_SHA256,
-	// &webpki::ECDSA_P256_SHA384,
-	// &webpki::ECDSA_P384_SHA256,
-	// &webpki::ECDSA_P384_SHA384,
-	&webpki::RSA_PKCS1_2048_8192_SHA256,
-	&webpki::RSA_PKCS1_2048_8192_SHA384,
-	&webpki::RSA_PKCS1_2048_8192_SHA512,
-	&webpki::RSA_PKCS1_3072_8192_SHA384,
-];
+use crate::phala_legacy::Pallet as PhalaPallet;
 
-pub static IAS_SERVER_ROOTS: webpki::TLSServerTrustAnchors = webpki::TLSServerTrustAnchors(&[
-	/*
-	 * -----BEGIN CERTIFICATE-----
-	 * MIIFSzCCA7OgAwIBAgIJANEHdl0yo7CUMA0GCSqGSIb3DQEBCwUAMH4xCzAJBgNV
-	 * BAYTAlVTMQswCQYDVQQIDAJDQTEUMBIGA1UEBwwLU2FudGEgQ2xhcmExGjAYBgNV
-	 * BAoMEUludGVsIENvcnBvcmF0aW9uMTAwLgYDVQQDDCdJbnRlbCBTR1ggQXR0ZXN0
-	 * YXRpb24gUmVwb3J0IFNpZ25pbmcgQ0EwIBcNMTYxMTE0MTUzNzMxWhgPMjA0OTEy
-	 * MzEyMzU5NTlaMH4xCzAJBgNVBAYTAlVTMQswCQYDVQQIDAJDQTEUMBIGA1UEBwwL
-	 * U2FudGEgQ2xhcmExGjAYBgNVBAoMEUludGVsIENvcnBvcmF0aW9uMTAwLgYDVQQD
-	 * DCdJbnRlbCBTR1ggQXR0ZXN0YXRpb24gUmVwb3J0IFNpZ25pbmcgQ0EwggGiMA0G
-	 * CSqGSIb3DQEBAQUAA4IBjwAwggGKAoIBgQCfPGR+tXc8u1EtJzLA10Feu1Wg+p7e
-	 * LmSRmeaCHbkQ1TF3Nwl3RmpqXkeGzNLd69QUnWovYyVSndEMyYc3sHecGgfinEeh
-	 * rgBJSEdsSJ9FpaFdesjsxqzGRa20PYdnnfWcCTvFoulpbFR4VBuXnnVLVzkUvlXT
-	 * L/TAnd8nIZk0zZkFJ7P5LtePvykkar7LcSQO85wtcQe0R1Raf/sQ6wYKaKmFgCGe
-	 * NpEJUmg4ktal4qgIAxk+QHUxQE42sxViN5mqglB0QJdUot/o9a/V/mMeH8KvOAiQ
-	 * byinkNndn+Bgk5sSV5DFgF0DffVqmVMblt5p3jPtImzBIH0QQrXJq39AT8cRwP5H
-	 * afuVeLHcDsRp6hol4P+ZFIhu8mmbI1u0hH3W/0C2BuYXB5PC+5izFFh/nP0lc2Lf
-	 * 6rELO9LZdnOhpL1ExFOq9H/B8tPQ84T3Sgb4nAifDabNt/zu6MmCGo5U8lwEFtGM
-	 * RoOaX4AS+909x00lYnmtwsDVWv9vBiJCXRsCAwEAAaOByTCBxjBgBgNVHR8EWTBX
-	 * MFWgU6BRhk9odHRwOi8vdHJ1c3RlZHNlcnZpY2VzLmludGVsLmNvbS9jb250ZW50
-	 * L0NSTC9TR1gvQXR0ZXN0YXRpb25SZXBvcnRTaWduaW5nQ0EuY3JsMB0GA1UdDgQW
-	 * BBR4Q3t2pn680K9+QjfrNXw7hwFRPDAfBgNVHSMEGDAWgBR4Q3t2pn680K9+Qjfr
-	 * NXw7hwFRPDAOBgNVHQ8BAf8EBAMCAQYwEgYDVR0TAQH/BAgwBgEB/wIBADANBgkq
-	 * hkiG9w0BAQsFAAOCAYEAeF8tYMXICvQqeXYQITkV2oLJsp6J4JAqJabHWxYJHGir
-	 * IEqucRiJSSx+HjIJEUVaj8E0QjEud6Y5lNmXlcjqRXaCPOqK0eGRz6hi+ripMtPZ
-	 * sFNaBwLQVV905SDjAzDzNIDnrcnXyB4gcDFCvwDFKKgLRjOB/WAqgscDUoGq5ZVi
-	 * zLUzTqiQPmULAQaB9c6Oti6snEFJiCQ67JLyW/E83/frzCmO5Ru6WjU4tmsmy8Ra
-	 * Ud4APK0wZTGtfPXU7w+IBdG5Ez0kE1qzxGQaL4gINJ1zMyleDnbuS8UicjJijvqA
-	 * 152Sq049ESDz+1rRGc2NVEqh1KaGXmtXvqxXcTB+Ljy5Bw2ke0v8iGngFBPqCTVB
-	 * 3op5KBG3RjbF6RRSzwzuWfL7QErNC8WEy5yDVARzTA5+xmBc388v9Dm21HGfcC8O
-	 * DD+gT9sSpssq0ascmvH49MOgjt1yoysLtdCtJW/9FZpoOypaHx0R+mJTLwPXVMrv
-	 * DaVzWh5aiEx+idkSGMnX
-	 * -----END CERTIFICATE-----
-	 */
-	webpki::TrustAnchor {
-		subject: b"1\x0b0\t\x06\x03U\x04\x06\x13\x02US1\x0b0\t\x06\x03U\x04\x08\x0c\x02CA1\x140\x12\x06\x03U\x04\x07\x0c\x0bSanta Clara1\x1a0\x18\x06\x03U\x04\n\x0c\x11Intel Corporation100.\x06\x03U\x04\x03\x0c\'Intel SGX Attestation Report Signing CA",
-		spki: b"0\r\x06\t*\x86H\x86\xf7\r\x01\x01\x01\x05\x00\x03\x82\x01\x8f\x000\x82\x01\x8a\x02\x82\x01\x81\x00\x9f<d~\xb5w<\xbbQ-\'2\xc0\xd7A^\xbbU\xa0\xfa\x9e\xde.d\x91\x99\xe6\x82\x1d\xb9\x10\xd51w7\twFjj^G\x86\xcc\xd2\xdd\xeb\xd4\x14\x9dj/c%R\x9d\xd1\x0c\xc9\x877\xb0w\x9c\x1a\x07\xe2\x9cG\xa1\xae\x00IHGlH\x9fE\xa5\xa1]z\xc8\xec\xc6\xac\xc6E\xad\xb4=\x87g\x9d\xf5\x9c\t;\xc5\xa2\xe9ilTxT\x1b\x97\x9euKW9\x14\xbeU\xd3/\xf4\xc0\x9d\xdf\'!\x994\xcd\x99\x05\'\xb3\xf9.\xd7\x8f\xbf)$j\xbe\xcbq$\x0e\xf3\x9c-q\x07\xb4GTZ\x7f\xfb\x10\xeb\x06\nh\xa9\x85\x80!\x9e6\x91\tRh8\x92\xd6\xa5\xe2\xa8\x08\x03\x19>@u1@N6\xb3\x15b7\x99\xaa\x82Pt@\x97T\xa2\xdf\xe8\xf5\xaf\xd5\xfec\x1e\x1f\xc2\xaf8\x08\x90o(\xa7\x90\xd9\xdd\x9f\xe0`\x93\x9b\x12W\x90\xc5\x80]\x03}\xf5j\x99S\x1b\x96\xdei\xde3\xed\"l\xc1 }\x10B\xb5\xc9\xab\x7f@O\xc7\x11\xc0\xfeGi\xfb\x95x\xb1\xdc\x0e\xc4i\xea\x1a%\xe0\xff\x99\x14\x88n\xf2i\x9b#[\xb4\x84}\xd6\xff@\xb6\x06\xe6\x17\x07\x93\xc2\xfb\x98\xb3\x14X\x7f\x9c\xfd%sb\xdf\xea\xb1\x0b;\xd2\xd9vs\xa1\xa4\xbdD\xc4S\xaa\xf4\x7f\xc1\xf2\xd3\xd0\xf3\x84\xf7J\x06\xf8\x9c\x08\x9f\r\xa6\xcd\xb7\xfc\xee\xe8\xc9\x82\x1a\x8eT\xf2\\\x04\x16\xd1\x8cF\x83\x9a_\x80\x12\xfb\xdd=\xc7M%by\xad\xc2\xc0\xd5Z\xffo\x06\"B]\x1b\x02\x03\x01\x00\x01",
-		name_constraints: None
-	},
-]);
+const SEED: u32 = 0;
 
-pub const IAS_REPORT_SAMPLE: &[u8] = include_bytes!("../sample/report");
-pub const IAS_REPORT_SIGNATURE: &[u8] = include_bytes!("../sample/report_signature");
-pub const IAS_REPORT_SIGNING_CERTIFICATE: &[u8] =
-	include_bytes!("../sample/report_signing_certificate");
-pub const ENCODED_RUNTIME_INFO: &[u8] = &[
-	1, 122, 238, 139, 126, 110, 55, 54, 207, 3, 19, 185, 137, 120, 238, 90, 71, 2, 28, 239, 90,
-	188, 129, 213, 193, 164, 64, 149, 82, 38, 229, 204, 150, 142, 110, 10, 182, 8, 122, 212, 50,
-	211, 194, 12, 193, 229, 219, 235, 185, 232, 8, 4, 0, 0, 0, 1, 0, 0, 0,
-];
 pub const MR_ENCLAVE: &[u8] = &[
 	197, 133, 134, 94, 240, 217, 241, 198, 183, 30, 13, 63, 33, 137, 194, 220, 173, 192, 217, 60,
 	149, 183, 155, 167, 154, 211, 78, 127, 110, 181, 249, 174,
@@ -88,31 +22,59 @@ pub const MR_SIGNER: &[u8] = &[
 ];
 pub const ISV_PROD_ID: &[u8] = &[0, 0];
 pub const ISV_SVN: &[u8] = &[0, 0];
-pub const TRANSFERDATASIG: &[u8] = &[
-	183, 44, 217, 136, 114, 98, 81, 55, 139, 1, 17, 128, 125, 144, 62, 253, 168, 121, 42, 165, 3,
-	167, 211, 202, 133, 18, 248, 42, 189, 204, 69, 75, 68, 12, 46, 11, 158, 139, 159, 198, 37, 100,
-	52, 130, 225, 156, 204, 89, 158, 25, 86, 246, 182, 241, 193, 182, 200, 224, 155, 139, 192, 232,
-	181, 212, 0,
-];
-pub const WORKMESSAGESIG: &[u8] = &[
-	77, 39, 136, 77, 181, 131, 61, 148, 132, 59, 159, 217, 196, 162, 190, 219, 179, 121, 60, 89,
-	35, 21, 101, 185, 217, 143, 154, 196, 56, 49, 153, 13, 37, 157, 76, 131, 244, 5, 217, 70, 162,
-	20, 13, 246, 218, 146, 27, 249, 180, 68, 150, 252, 166, 123, 167, 66, 114, 102, 31, 138, 237,
-	221, 220, 55, 1,
+/// A compressed secp256k1 public key
+pub const WORKER_PUBKEY: [u8; 33] = [
+	2, 121, 190, 102, 126, 249, 220, 187, 172, 85, 160, 98, 149, 206, 135, 11, 7, 2, 155, 252,
+	219, 45, 206, 40, 217, 89, 242, 129, 91, 22, 248, 23, 152,
 ];
 
-benchmarks! {
-	push_command {
-		let caller = whitelisted_caller();
-		let payload = b"hello world".to_vec();
-	}: {
-		PhalaPallet::<T>::push_command(RawOrigin::Signed(caller).into(), CONTRACT_ID, payload)?;
-	}
-	verify {
-		// CommandNumber = 0; CommandNumber++;
-		assert_eq!(PhalaPallet::<T>::command_number().unwrap(), 1);
-	}
+/// Sets up `caller` as both the stash and the controller of a free worker
+fn setup_worker<T: Config>(caller: &T::AccountId) -> Result<(), &'static str> {
+	PhalaPallet::<T>::set_stash(RawOrigin::Signed(caller.clone()).into(), caller.clone())?;
+	WorkerState::<T>::insert(
+		caller,
+		WorkerInfo::<T::BlockNumber> {
+			machine_id: BUILTIN_MACHINE_ID.as_bytes().to_vec(),
+			pubkey: WORKER_PUBKEY.to_vec(),
+			last_updated: 0,
+			state: WorkerStateEnum::Free,
+			score: Some(Score {
+				overall_score: 100,
+				features: vec![1, 4],
+			}),
+			confidence_level: 128u8,
+			runtime_version: 0,
+		},
+	);
+	Ok(())
+}
+
+/// Sets up `stash` as a mining worker missing the heartbeat challenge at block 5
+fn setup_offline_worker<T: Config>(
+	stash: &T::AccountId,
+	pubkey: Vec<u8>,
+) -> Result<T::BlockNumber, &'static str> {
+	frame_system::Pallet::<T>::set_block_number(10u32.into());
+	setup_worker::<T>(stash)?;
+	WorkerState::<T>::mutate(stash, |info| {
+		info.pubkey = pubkey;
+		info.state = WorkerStateEnum::Mining(1u32.into());
+	});
+	SlashWindow::<T>::put(T::BlockNumber::from(40u32));
+	let block_num: T::BlockNumber = 5u32.into();
+	// Every worker is challenged
+	BlockRewardSeeds::<T>::insert(
+		block_num,
+		BlockRewardInfo {
+			seed: U256::zero(),
+			online_target: U256::MAX,
+			compute_target: U256::MAX,
+		},
+	);
+	Ok(block_num)
+}
 
+benchmarks! {
 	// To create the worst scenario, we set a controller as coller first,
 	// then set_stash would remove it before set new controller.
 	set_stash {
@@ -128,12 +90,10 @@ benchmarks! {
 
 		StashState::<T>::insert(&caller, stash_state);
 		Stash::<T>::insert(&caller, caller.clone());
-	}: {
-		PhalaPallet::<T>::set_stash(RawOrigin::Signed(caller.clone()).into(), new_controller.clone())?;
-	}
+	}: _(RawOrigin::Signed(caller.clone()), new_controller.clone())
 	verify {
 		// new controller should be set
-		assert_eq!(PhalaPallet::<T>::stash(&new_controller), caller.clone());
+		assert_eq!(Stash::<T>::get(&new_controller), caller.clone());
 	}
 
 	set_payout_prefs {
@@ -142,199 +102,180 @@ benchmarks! {
 		let payout_target: T::AccountId = account("payouttarget", 0, SEED);
 
 		PhalaPallet::<T>::set_stash(RawOrigin::Signed(caller.clone()).into(), caller.clone())?;
-	}: {
-		PhalaPallet::<T>::set_payout_prefs(RawOrigin::Signed(caller.clone()).into(), payout_commission.clone().into(), payout_target.clone().into())?;
-	}
+	}: _(RawOrigin::Signed(caller.clone()), Some(payout_commission), Some(payout_target.clone()))
 	verify {
-		let mut stash_info = StashState::<T>::get(&caller);
+		let stash_info = StashState::<T>::get(&caller);
 		assert_eq!(stash_info.payout_prefs.commission, payout_commission);
 		assert_eq!(stash_info.payout_prefs.target, payout_target);
 	}
 
-	register_worker {
-		let caller: T::AccountId = whitelisted_caller();
-		let sig: Vec<u8> = match base64::decode(&IAS_REPORT_SIGNATURE) {
-			Ok(x) => x,
-			Err(_) => panic!("decode sig failed")
-		};
-		let sig_cert_dec: Vec<u8> = match base64::decode_config(&IAS_REPORT_SIGNING_CERTIFICATE, base64::STANDARD) {
-			Ok(x) => x,
-			Err(_) => panic!("decode cert failed")
-		};
-
-		PhalaPallet::<T>::add_mrenclave(RawOrigin::Root.into(), MR_ENCLAVE.to_vec(), MR_SIGNER.to_vec(), ISV_PROD_ID.to_vec(), ISV_SVN.to_vec())?;
-		PhalaPallet::<T>::set_stash(RawOrigin::Signed(caller.clone()).into(), caller.clone())?;
-	}: {
-		PhalaPallet::<T>::register_worker(RawOrigin::Signed(caller).into(), ENCODED_RUNTIME_INFO.to_vec(), IAS_REPORT_SAMPLE.to_vec(), sig.clone(), sig_cert_dec.clone())?;
-	}
-
-	force_register_worker {
-		let caller: T::AccountId = whitelisted_caller();
-
-		PhalaPallet::<T>::set_stash(RawOrigin::Signed(caller.clone()).into(), caller.clone())?;
-	}: {
-		PhalaPallet::<T>::force_register_worker(RawOrigin::Root.into(), caller, vec![0], vec![1])?;
-	}
-
 	force_set_contract_key {
-
-	}: {
-		PhalaPallet::<T>::force_set_contract_key(RawOrigin::Root.into(), 0, vec![0])?;
+	}: _(RawOrigin::Root, 0, vec![0])
+	verify {
+		assert_eq!(ContractKey::<T>::get(0), vec![0]);
 	}
 
 	start_mining_intention {
 		let caller: T::AccountId = whitelisted_caller();
-		let sig: Vec<u8> = match base64::decode(&IAS_REPORT_SIGNATURE) {
-			Ok(x) => x,
-			Err(_) => panic!("decode sig failed")
-		};
-		let sig_cert_dec: Vec<u8> = match base64::decode_config(&IAS_REPORT_SIGNING_CERTIFICATE, base64::STANDARD) {
-			Ok(x) => x,
-			Err(_) => panic!("decode cert failed")
-		};
-
 		frame_system::Pallet::<T>::set_block_number(1u32.into());
-		PhalaPallet::<T>::add_mrenclave(RawOrigin::Root.into(), MR_ENCLAVE.to_vec(), MR_SIGNER.to_vec(), ISV_PROD_ID.to_vec(), ISV_SVN.to_vec())?;
-		PhalaPallet::<T>::set_stash(RawOrigin::Signed(caller.clone()).into(), caller.clone())?;
-		PhalaPallet::<T>::register_worker(RawOrigin::Signed(caller.clone()).into(), ENCODED_RUNTIME_INFO.to_vec(), IAS_REPORT_SAMPLE.to_vec(), sig.clone(), sig_cert_dec.clone())?;
-	}: {
-		PhalaPallet::<T>::start_mining_intention(RawOrigin::Signed(caller.clone()).into())?;
-	}
+		setup_worker::<T>(&caller)?;
+	}: _(RawOrigin::Signed(caller.clone()))
 	verify {
-		let stash = Stash::<T>::get(caller);
-		let mut worker_info = WorkerState::<T>::get(&stash);
+		let worker_info = WorkerState::<T>::get(&caller);
 		assert_eq!(worker_info.state, WorkerStateEnum::MiningPending);
 	}
 
 	stop_mining_intention {
 		let caller: T::AccountId = whitelisted_caller();
-		let sig: Vec<u8> = match base64::decode(&IAS_REPORT_SIGNATURE) {
-			Ok(x) => x,
-			Err(_) => panic!("decode sig failed")
-		};
-		let sig_cert_dec: Vec<u8> = match base64::decode_config(&IAS_REPORT_SIGNING_CERTIFICATE, base64::STANDARD) {
-			Ok(x) => x,
-			Err(_) => panic!("decode cert failed")
-		};
-
 		frame_system::Pallet::<T>::set_block_number(1u32.into());
-		PhalaPallet::<T>::add_mrenclave(RawOrigin::Root.into(), MR_ENCLAVE.to_vec(), MR_SIGNER.to_vec(), ISV_PROD_ID.to_vec(), ISV_SVN.to_vec())?;
-		PhalaPallet::<T>::set_stash(RawOrigin::Signed(caller.clone()).into(), caller.clone())?;
-		PhalaPallet::<T>::register_worker(RawOrigin::Signed(caller.clone()).into(), ENCODED_RUNTIME_INFO.to_vec(), IAS_REPORT_SAMPLE.to_vec(), sig.clone(), sig_cert_dec.clone())?;
+		setup_worker::<T>(&caller)?;
 		PhalaPallet::<T>::start_mining_intention(RawOrigin::Signed(caller.clone()).into())?;
-	}: {
-		PhalaPallet::<T>::stop_mining_intention(RawOrigin::Signed(caller.clone()).into())?;
-	}
+	}: _(RawOrigin::Signed(caller.clone()))
 	verify {
-		let stash = Stash::<T>::get(caller);
-		let mut worker_info = WorkerState::<T>::get(&stash);
+		let worker_info = WorkerState::<T>::get(&caller);
 		assert_eq!(worker_info.state, WorkerStateEnum::Free);
 	}
 
-
-	transfer_to_tee {
-		let caller: T::AccountId = whitelisted_caller();
-		// set contract key
-		let pubkey = hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
-		PhalaPallet::<T>::force_set_contract_key(RawOrigin::Root.into(), 2, pubkey)?;
-		let imbalance: BalanceOf<T> = 100u32.into();
-		T::TEECurrency::deposit_creating(&caller, imbalance);
-		assert_eq!(imbalance, T::TEECurrency::free_balance(&caller));
-	}: {
-		PhalaPallet::<T>::transfer_to_tee(RawOrigin::Signed(caller.clone()).into(), 50u32.into())?;
-	}
+	report_offline {
+		let stash: T::AccountId = account("stash", 0, SEED);
+		let reporter: T::AccountId = whitelisted_caller();
+		let block_num = setup_offline_worker::<T>(&stash, WORKER_PUBKEY.to_vec())?;
+	}: _(RawOrigin::Signed(reporter), stash.clone(), block_num)
 	verify {
-		let free_balance: BalanceOf<T> = 50u32.into();
-		assert_eq!(free_balance, T::TEECurrency::free_balance(&caller));
-	}
-	verify {
-		let free_balance: BalanceOf<T> = 50u32.into();
-		assert_eq!(free_balance, T::TEECurrency::free_balance(&receiver));
+		assert!(Offences::<T>::contains_key(&stash, block_num));
 	}
 
-	sync_worker_message {
+	// The worst case: the evidence is valid, so the signature is checked
+	dispute_offence {
+		let stash: T::AccountId = account("stash", 0, SEED);
+		let reporter: T::AccountId = account("reporter", 0, SEED);
 		let caller: T::AccountId = whitelisted_caller();
-		// let raw_sk = hex::decode("0000000000000000000000000000000000000000000000000000000000000001").unwrap();
-		// let sk = ecdsa_load_sk(&raw_sk);
-		let pubkey = hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
-
-		let sig: Vec<u8> = match base64::decode(&IAS_REPORT_SIGNATURE) {
-			Ok(x) => x,
-			Err(_) => panic!("decode sig failed")
+		let pubkey = sp_io::crypto::ecdsa_generate(ECDSA, None);
+		let block_num = setup_offline_worker::<T>(&stash, pubkey.as_ref().to_vec())?;
+		PhalaPallet::<T>::report_offline(RawOrigin::Signed(reporter).into(), stash.clone(), block_num)?;
+		let heartbeat = WorkerReportEvent::Heartbeat {
+			machine_id: BUILTIN_MACHINE_ID.as_bytes().to_vec(),
+			block_num: 5,
+			claim_online: true,
+			claim_compute: false,
 		};
-		let sig_cert_dec: Vec<u8> = match base64::decode_config(&IAS_REPORT_SIGNING_CERTIFICATE, base64::STANDARD) {
-			Ok(x) => x,
-			Err(_) => panic!("decode cert failed")
-		};
-		PhalaPallet::<T>::add_mrenclave(RawOrigin::Root.into(), MR_ENCLAVE.to_vec(), MR_SIGNER.to_vec(), ISV_PROD_ID.to_vec(), ISV_SVN.to_vec())?;
-		PhalaPallet::<T>::set_stash(RawOrigin::Signed(caller.clone()).into(), caller.clone())?;
-		PhalaPallet::<T>::register_worker(RawOrigin::Signed(caller.clone()).into(), ENCODED_RUNTIME_INFO.to_vec(), IAS_REPORT_SAMPLE.to_vec(), sig.clone(), sig_cert_dec.clone())?;
-		PhalaPallet::<T>::start_mining_intention(RawOrigin::Signed(caller.clone()).into())?;
-
-		let stash = Stash::<T>::get(&caller);
-		let mut worker_info = WorkerState::<T>::get(&stash);
-		worker_info.pubkey = pubkey.clone();
-		WorkerState::<T>::insert(stash, worker_info);
-
-		let work_message = WorkerMessage {
-			payload: WorkerMessagePayload::Heartbeat{
-				block_num: 123u32,
-				claim_online: true,
-				claim_compute: true,
-			},
+		let mut evidence = SignedMessage {
+			message: Message::new(
+				MessageOrigin::Worker(pubkey.clone()),
+				WorkerReportEvent::TOPIC.to_vec(),
+				heartbeat.encode(),
+			),
 			sequence: 0,
+			signature: Vec::new(),
 		};
-		// let signature = ecdsa_sign(&sk, &work_message);
+		let signature = sp_io::crypto::ecdsa_sign(ECDSA, &pubkey, &evidence.data_be_signed())
+			.ok_or("cannot sign the evidence")?;
+		evidence.signature = signature.0.to_vec();
+	}: _(RawOrigin::Signed(caller), stash.clone(), block_num, evidence)
+	verify {
+		assert!(!Offences::<T>::contains_key(&stash, block_num));
+	}
 
-		// use prepared WorkMessage signature, see fn test_mockdata_sync_worker_message() in tests.rs
-		let signed_workmessage = SignedWorkerMessage {
-			data: work_message,
-			signature: WORKMESSAGESIG.to_vec(),
-		};
+	// Excluding the stake slashed by `OnOffence`, which is charged separately
+	apply_offence {
+		let stash: T::AccountId = account("stash", 0, SEED);
+		let reporter: T::AccountId = whitelisted_caller();
+		let block_num = setup_offline_worker::<T>(&stash, WORKER_PUBKEY.to_vec())?;
+		PhalaPallet::<T>::report_offline(RawOrigin::Signed(reporter).into(), stash.clone(), block_num)?;
+		let due = frame_system::Pallet::<T>::block_number() + T::OffenceDisputePeriod::get();
 	}: {
-		PhalaPallet::<T>::sync_worker_message(RawOrigin::Signed(caller.clone()).into(), signed_workmessage.encode())?;
+		PhalaPallet::<T>::apply_offences(due, 0);
+	}
+	verify {
+		assert!(!Offences::<T>::contains_key(&stash, block_num));
+		assert_eq!(SlashingSpans::<T>::get(&stash).offences, 1);
 	}
 
 	force_next_round {
-	}: {
-		PhalaPallet::<T>::force_next_round(RawOrigin::Root.into())?;
+	}: _(RawOrigin::Root)
+	verify {
+		assert!(ForceNextRound::<T>::get());
 	}
 
 	force_add_fire {
 		let fire0: T::AccountId = account("fire0", 0, SEED);
 		let fire1: T::AccountId = account("fire1", 0, SEED);
 		let fire2: T::AccountId = account("fire2", 0, SEED);
+	}: _(
+		RawOrigin::Root,
+		vec![fire1.clone(), fire2.clone()],
+		vec![100u32.into(), 200u32.into()]
+	)
+	verify {
+		assert_eq!(PhalaPallet::<T>::fire2(fire0.clone()), 0u32.into());
+		assert_eq!(PhalaPallet::<T>::fire2(fire1.clone()), 100u32.into());
+		assert_eq!(PhalaPallet::<T>::fire2(fire2.clone()), 200u32.into());
+	}
 
-	}: {
-		PhalaPallet::<T>::force_add_fire(
-			RawOrigin::Root.into(),
-			vec![fire1.clone(), fire2.clone()],
-			vec![100u32.into(), 200u32.into()],
-		)?;
+	force_set_virtual_tasks {
+	}: _(RawOrigin::Root, 10)
+	verify {
+		assert_eq!(TargetVirtualTaskCount::<T>::get(), 10);
+	}
+
+	force_reset_fire {
+		let fire1: T::AccountId = account("fire1", 0, SEED);
+		PhalaPallet::<T>::force_add_fire(RawOrigin::Root.into(), vec![fire1.clone()], vec![100u32.into()])?;
+	}: _(RawOrigin::Root)
+	verify {
+		assert_eq!(PhalaPallet::<T>::fire2(fire1), 0u32.into());
+		assert_eq!(AccumulatedFire2::<T>::get(), 0u32.into());
 	}
+
+	// Shrinking the slash window by `n` blocks, removing `n` seeds
+	force_set_window {
+		let n in 0 .. 1000;
+		let now = n + 100;
+		frame_system::Pallet::<T>::set_block_number(now.into());
+		RewardWindow::<T>::put(T::BlockNumber::from(8u32));
+		SlashWindow::<T>::put(T::BlockNumber::from(n + 8));
+		for i in (now - n - 8)..=now {
+			BlockRewardSeeds::<T>::insert(T::BlockNumber::from(i), BlockRewardInfo::default());
+		}
+	}: _(RawOrigin::Root, None, Some(8u32.into()), n)
 	verify {
-		assert_eq!(PhalaPallet::<T>::fire(fire0.clone()), 0u32.into());
-		assert_eq!(PhalaPallet::<T>::fire(fire1.clone()), 100u32.into());
-		assert_eq!(PhalaPallet::<T>::fire(fire2.clone()), 200u32.into());
+		assert_eq!(SlashWindow::<T>::get(), 8u32.into());
+		assert!(n == 0 || !BlockRewardSeeds::<T>::contains_key(T::BlockNumber::from(now - 8)));
+	}
+
+	// Always rejected, since the deposits are moved to `pallet_escrow`
+	transfer_to_tee {
+		let caller: T::AccountId = whitelisted_caller();
+	}: {
+		let _ = PhalaPallet::<T>::transfer_to_tee(RawOrigin::Signed(caller).into(), 100u32.into());
 	}
 
 	add_mrenclave {
 		frame_system::Pallet::<T>::set_block_number(1u32.into());
-	}: {
-		PhalaPallet::<T>::add_mrenclave(RawOrigin::Root.into(), MR_ENCLAVE.to_vec(), MR_SIGNER.to_vec(), ISV_PROD_ID.to_vec(), ISV_SVN.to_vec())?;
+	}: _(RawOrigin::Root, MR_ENCLAVE.to_vec(), MR_SIGNER.to_vec(), ISV_PROD_ID.to_vec(), ISV_SVN.to_vec())
+	verify {
+		assert_eq!(MREnclaveWhitelist::<T>::get().len(), 1);
 	}
 
 	remove_mrenclave_by_raw_data {
 		frame_system::Pallet::<T>::set_block_number(1u32.into());
 		PhalaPallet::<T>::add_mrenclave(RawOrigin::Root.into(), MR_ENCLAVE.to_vec(), MR_SIGNER.to_vec(), ISV_PROD_ID.to_vec(), ISV_SVN.to_vec())?;
-	}: {
-		PhalaPallet::<T>::remove_mrenclave_by_raw_data(RawOrigin::Root.into(), MR_ENCLAVE.to_vec(), MR_SIGNER.to_vec(), ISV_PROD_ID.to_vec(), ISV_SVN.to_vec())?;
+	}: _(RawOrigin::Root, MR_ENCLAVE.to_vec(), MR_SIGNER.to_vec(), ISV_PROD_ID.to_vec(), ISV_SVN.to_vec())
+	verify {
+		assert!(MREnclaveWhitelist::<T>::get().is_empty());
 	}
 
 	remove_mrenclave_by_index {
 		frame_system::Pallet::<T>::set_block_number(1u32.into());
 		PhalaPallet::<T>::add_mrenclave(RawOrigin::Root.into(), MR_ENCLAVE.to_vec(), MR_SIGNER.to_vec(), ISV_PROD_ID.to_vec(), ISV_SVN.to_vec())?;
-	}: {
-		PhalaPallet::<T>::remove_mrenclave_by_index(RawOrigin::Root.into(), 0)?;
+	}: _(RawOrigin::Root, 0)
+	verify {
+		assert!(MREnclaveWhitelist::<T>::get().is_empty());
 	}
 }
+
+impl_benchmark_test_suite!(
+	PhalaPallet,
+	crate::phala_legacy::mock::new_test_ext(),
+	crate::phala_legacy::mock::Test,
+);
@@ -0,0 +1,216 @@
+//! Storage migrations of the legacy pallet
+
+use frame_support::{
+	migration::{storage_key_iter, take_storage_value},
+	traits::{Get, OnRuntimeUpgrade},
+	weights::Weight,
+	Blake2_128Concat,
+};
+use sp_std::{convert::TryFrom, marker::PhantomData};
+
+use super::*;
+
+const LEGACY_PREFIX: &[u8] = b"Phala";
+
+/// The max number of the legacy entries moved in a block
+pub const MIGRATION_BATCH: u32 = 128;
+
+/// Moves the worker, stash, machine owner and whitelist data to the new pallets.
+///
+/// - `StashState` and `Stash` go to `pallet_stakepool`
+/// - Each worker in `MachineOwner` with a valid pubkey is bound to its stash in
+///   `pallet_mining::WorkerBindings`, and the identity part of its `WorkerState` is copied to
+///   `pallet_registry::Worker`. The other machines go to `pallet_mining::MachineOwner`.
+/// - `MREnclaveWhitelist` goes to `pallet_registry`
+///
+/// `WorkerState` itself is kept because the mining state is still driven by the round logic of
+/// this pallet.
+///
+/// At most `MIGRATION_BATCH` entries are moved in the upgrade, and the rest in the following
+/// blocks by `on_initialize`, until `MigratedToNewPallets` is set.
+pub struct MigrateToNewPallets<T>(PhantomData<T>);
+
+impl<T: Config> OnRuntimeUpgrade for MigrateToNewPallets<T> {
+	fn on_runtime_upgrade() -> Weight {
+		migrate_batch::<T>()
+	}
+
+	#[cfg(feature = "try-runtime")]
+	fn pre_upgrade() -> Result<(), &'static str> {
+		if MigratedToNewPallets::<T>::get() {
+			return Ok(());
+		}
+		let counts = MigrationCounts {
+			stashes: legacy_stash_state::<T>().count() as u32,
+			controllers: legacy_stash::<T>().count() as u32,
+			machines: legacy_machine_owner::<T>().count() as u32,
+			whitelist: legacy_whitelist().map(|v| v.len() as u32),
+		};
+		frame_support::storage::unhashed::put(MIGRATION_COUNTS_KEY, &counts);
+		Ok(())
+	}
+
+	/// Checks the first batch. The entries not moved yet must still be in the legacy storage.
+	#[cfg(feature = "try-runtime")]
+	fn post_upgrade() -> Result<(), &'static str> {
+		let counts: MigrationCounts =
+			match frame_support::storage::unhashed::take(MIGRATION_COUNTS_KEY) {
+				Some(counts) => counts,
+				// Migrated before this upgrade
+				None => return Ok(()),
+			};
+		let moved = StashState::<T>::iter().count() as u32;
+		if moved + legacy_stash_state::<T>().count() as u32 != counts.stashes {
+			return Err("StashState count mismatch");
+		}
+		let moved = Stash::<T>::iter().count() as u32;
+		if moved + legacy_stash::<T>().count() as u32 != counts.controllers {
+			return Err("Stash count mismatch");
+		}
+		let moved = mining::WorkerBindings::<T>::iter().count() as u32
+			+ MachineOwner::<T>::iter().count() as u32;
+		if moved + legacy_machine_owner::<T>().count() as u32 != counts.machines {
+			return Err("MachineOwner count mismatch");
+		}
+		for (pubkey, stash) in mining::WorkerBindings::<T>::iter() {
+			if WorkerState::<T>::get(&stash).pubkey != pubkey.as_ref() {
+				return Err("worker bound to a wrong stash");
+			}
+			if !registry::Worker::<T>::contains_key(&pubkey) {
+				return Err("worker not registered");
+			}
+		}
+		if MigratedToNewPallets::<T>::get() {
+			if legacy_whitelist().is_some() {
+				return Err("legacy whitelist not moved");
+			}
+			if let Some(len) = counts.whitelist {
+				if MREnclaveWhitelist::<T>::get().len() as u32 != len {
+					return Err("MREnclaveWhitelist length mismatch");
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Moves at most `MIGRATION_BATCH` legacy entries, and sets `MigratedToNewPallets` once all the
+/// entries are moved.
+///
+/// The legacy entries are drained as they are moved, so the next batch continues with the rest.
+/// The stash entries updated after the upgrade are newer than the legacy ones and kept.
+pub(super) fn migrate_batch<T: Config>() -> Weight {
+	if MigratedToNewPallets::<T>::get() {
+		return T::DbWeight::get().reads(1);
+	}
+	let mut moved = 0u32;
+
+	for (stash, info) in legacy_stash_state::<T>()
+		.drain()
+		.take(MIGRATION_BATCH as usize)
+	{
+		if !StashState::<T>::contains_key(&stash) {
+			StashState::<T>::insert(&stash, info);
+		}
+		moved += 1;
+	}
+	for (controller, stash) in legacy_stash::<T>()
+		.drain()
+		.take((MIGRATION_BATCH - moved) as usize)
+	{
+		if !Stash::<T>::contains_key(&controller) {
+			Stash::<T>::insert(&controller, stash);
+		}
+		moved += 1;
+	}
+	for (machine_id, stash) in legacy_machine_owner::<T>()
+		.drain()
+		.take((MIGRATION_BATCH - moved) as usize)
+	{
+		moved += 1;
+		let info = WorkerState::<T>::get(&stash);
+		let pubkey = match WorkerPublicKey::try_from(info.pubkey.as_slice()) {
+			Ok(pubkey) => pubkey,
+			Err(_) => {
+				MachineOwner::<T>::insert(&machine_id, stash);
+				continue;
+			}
+		};
+		mining::WorkerBindings::<T>::insert(&pubkey, &stash);
+		if registry::Worker::<T>::contains_key(&pubkey) {
+			continue;
+		}
+		let (intial_score, features) = match info.score {
+			Some(score) => (Some(score.overall_score), score.features),
+			None => (None, Vec::new()),
+		};
+		registry::Worker::<T>::insert(
+			&pubkey,
+			registry::WorkerInfo {
+				pubkey: pubkey.clone(),
+				// Unknown until the worker registers again
+				ecdh_pubkey: Default::default(),
+				runtime_version: info.runtime_version,
+				// The legacy timestamp is in milliseconds
+				last_updated: info.last_updated / 1000,
+				confidence_level: info.confidence_level,
+				intial_score,
+				// Not attached to the registry yet
+				session_id: 0,
+				features,
+			},
+		);
+	}
+
+	// Each entry takes at most 3 reads and 3 writes, for a worker
+	let mut reads: Weight = 1 + 3 * moved as Weight;
+	let mut writes: Weight = 3 * moved as Weight;
+	if moved < MIGRATION_BATCH {
+		if let Some(whitelist) =
+			take_storage_value::<Vec<Vec<u8>>>(LEGACY_PREFIX, b"MREnclaveWhitelist", &[])
+		{
+			MREnclaveWhitelist::<T>::put(whitelist);
+			writes += 2;
+		}
+		MigratedToNewPallets::<T>::put(true);
+		reads += 1;
+		writes += 1;
+	}
+	T::DbWeight::get().reads_writes(reads, writes)
+}
+
+#[cfg(feature = "try-runtime")]
+const MIGRATION_COUNTS_KEY: &[u8] = b":phala:migration:new_pallets:counts";
+
+/// The number of legacy entries recorded before the migration
+#[cfg(feature = "try-runtime")]
+#[derive(Encode, Decode)]
+struct MigrationCounts {
+	stashes: u32,
+	controllers: u32,
+	machines: u32,
+	whitelist: Option<u32>,
+}
+
+fn legacy_stash_state<T: Config>() -> frame_support::migration::StorageKeyIterator<
+	T::AccountId,
+	StashInfo<T::AccountId>,
+	Blake2_128Concat,
+> {
+	storage_key_iter(LEGACY_PREFIX, b"StashState")
+}
+
+fn legacy_stash<T: Config>(
+) -> frame_support::migration::StorageKeyIterator<T::AccountId, T::AccountId, Blake2_128Concat> {
+	storage_key_iter(LEGACY_PREFIX, b"Stash")
+}
+
+fn legacy_machine_owner<T: Config>(
+) -> frame_support::migration::StorageKeyIterator<Vec<u8>, T::AccountId, Blake2_128Concat> {
+	storage_key_iter(LEGACY_PREFIX, b"MachineOwner")
+}
+
+#[cfg(feature = "try-runtime")]
+fn legacy_whitelist() -> Option<Vec<Vec<u8>>> {
+	frame_support::migration::get_storage_value(LEGACY_PREFIX, b"MREnclaveWhitelist", &[])
+}
@@ -0,0 +1,216 @@
+// Creating mock runtime here
+
+use crate::{escrow, mining, mq, phala_legacy, registry, stakepool};
+use frame_support::parameter_types;
+use frame_support_test::TestRandomness;
+use frame_system as system;
+use std::{cell::RefCell, sync::Arc};
+use sp_core::H256;
+use sp_keystore::{testing::KeyStore, KeystoreExt};
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+	Permill,
+};
+
+pub(crate) type Balance = u128;
+pub(crate) type BlockNumber = u64;
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+// Configure a mock runtime to test the pallet.
+frame_support::construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
+		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
+		Phala: phala_legacy::{Pallet, Call, Config<T>, Storage, Event<T>},
+		PhalaMq: mq::{Pallet, Call, Event, Storage},
+		PhalaRegistry: registry::{Pallet, Call, Event<T>, Storage},
+		PhalaMining: mining::{Pallet, Call, Event, Storage},
+		PhalaStakePool: stakepool::{Pallet, Call, Event, Storage},
+		PhalaEscrow: escrow::{Pallet, Call, Event<T>, Storage},
+	}
+);
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const SS58Prefix: u8 = 42;
+	pub const MinimumPeriod: u64 = 1;
+	pub const ExistentialDeposit: Balance = 1;
+}
+impl system::Config for Test {
+	type BaseCallFilter = ();
+	type BlockWeights = ();
+	type BlockLength = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = BlockNumber;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type DbWeight = ();
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = pallet_balances::AccountData<Balance>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = SS58Prefix;
+	type OnSetCode = ();
+}
+
+impl pallet_balances::Config for Test {
+	type Balance = Balance;
+	type DustRemoval = ();
+	type Event = Event;
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+	type MaxLocks = ();
+	type MaxReserves = ();
+	type ReserveIdentifier = [u8; 8];
+}
+
+impl pallet_timestamp::Config for Test {
+	type Moment = u64;
+	type OnTimestampSet = ();
+	type MinimumPeriod = MinimumPeriod;
+	type WeightInfo = ();
+}
+
+pub const HOURS: BlockNumber = 600;
+pub const DAYS: BlockNumber = HOURS * 24;
+pub const DOLLARS: Balance = 1_000_000_000_000;
+
+parameter_types! {
+	pub const MaxHeartbeatPerWorkerPerHour: u32 = 2;
+	pub const RoundInterval: BlockNumber = 1 * HOURS;
+	pub const DecayInterval: BlockNumber = 180 * DAYS;
+	pub const DecayFactor: Permill = Permill::from_percent(75);
+	pub const InitialReward: Balance = 129600000 * DOLLARS;
+	pub const TreasuryRation: u32 = 20_000;
+	pub const RewardRation: u32 = 80_000;
+	pub const OnlineRewardPercentage: Permill = Permill::from_parts(375_000);
+	pub const ComputeRewardPercentage: Permill = Permill::from_parts(625_000);
+	pub const StakerRewardPercentage: Permill = Permill::from_percent(20);
+	pub const OfflineOffenseSlash: Balance = 100 * DOLLARS;
+	pub const OfflineReportReward: Balance = 50 * DOLLARS;
+	pub const OffenceDisputePeriod: BlockNumber = 10;
+	pub const OffenceSlashBase: Permill = Permill::from_percent(1);
+	pub const OffenceSlashMax: Permill = Permill::from_percent(10);
+}
+
+impl phala_legacy::Config for Test {
+	type Event = Event;
+	type Randomness = TestRandomness<Self>;
+	type TEECurrency = Balances;
+	type UnixTime = Timestamp;
+	type Treasury = ();
+	type WeightInfo = ();
+	type OnRoundEnd = ();
+	type OnOffence = MockOnOffence;
+	type OnReward = ();
+
+	// Parameters
+	type MaxHeartbeatPerWorkerPerHour = MaxHeartbeatPerWorkerPerHour;
+	type RoundInterval = RoundInterval;
+	type DecayInterval = DecayInterval;
+	type DecayFactor = DecayFactor;
+	type InitialReward = InitialReward;
+	type TreasuryRation = TreasuryRation;
+	type RewardRation = RewardRation;
+	type OnlineRewardPercentage = OnlineRewardPercentage;
+	type ComputeRewardPercentage = ComputeRewardPercentage;
+	type StakerRewardPercentage = StakerRewardPercentage;
+	type OfflineOffenseSlash = OfflineOffenseSlash;
+	type OfflineReportReward = OfflineReportReward;
+	type OffenceDisputePeriod = OffenceDisputePeriod;
+	type OffenceSlashBase = OffenceSlashBase;
+	type OffenceSlashMax = OffenceSlashMax;
+}
+
+thread_local! {
+	pub static SLASHED_STAKE: RefCell<Vec<(u64, Permill)>> = RefCell::new(Vec::new());
+}
+
+/// Records the stake slashes, and slashes 1000 units by 100%
+pub struct MockOnOffence;
+impl phala_legacy::OnOffence<u64, Balance> for MockOnOffence {
+	fn on_offence(stash: &u64, fraction: Permill) -> Balance {
+		SLASHED_STAKE.with(|v| v.borrow_mut().push((*stash, fraction)));
+		fraction * 1000
+	}
+}
+
+pub fn slashed_stake() -> Vec<(u64, Permill)> {
+	SLASHED_STAKE.with(|v| v.borrow().clone())
+}
+
+impl mq::Config for Test {
+	type Event = Event;
+	type QueueNotifyConfig = ();
+}
+
+parameter_types! {
+	pub const MaxGatekeepers: u32 = 5;
+	pub const MinGatekeepers: u32 = 1;
+	pub const GatekeeperRotationDelay: BlockNumber = 10;
+	pub const ContractDeposit: Balance = 100;
+	pub const MaxInitParamsLen: u32 = 64;
+	pub const MaxSaltLen: u32 = 32;
+}
+
+impl registry::Config for Test {
+	type Event = Event;
+	type UnixTime = Timestamp;
+	type Randomness = TestRandomness<Self>;
+	type GatekeeperOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type ContractAdminOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type MaxGatekeepers = MaxGatekeepers;
+	type MinGatekeepers = MinGatekeepers;
+	type GatekeeperRotationDelay = GatekeeperRotationDelay;
+	type Currency = Balances;
+	type ContractDeposit = ContractDeposit;
+	type MaxInitParamsLen = MaxInitParamsLen;
+	type MaxSaltLen = MaxSaltLen;
+	type OnWorkerKeyRotated = PhalaMining;
+	type WorkerBindings = PhalaMining;
+}
+
+impl mining::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+}
+
+impl stakepool::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+}
+
+impl escrow::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+	type WeightInfo = ();
+}
+
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let t = system::GenesisConfig::default()
+		.build_storage::<Test>()
+		.unwrap();
+	SLASHED_STAKE.with(|v| v.borrow_mut().clear());
+	let mut ext = sp_io::TestExternalities::new(t);
+	// Benchmarks sign the offence evidence with the keystore
+	ext.register_extension(KeystoreExt(Arc::new(KeyStore::new())));
+	ext
+}
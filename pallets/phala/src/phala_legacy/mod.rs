@@ -4,19 +4,20 @@ use sp_core::U256;
 use sp_std::prelude::*;
 use sp_std::{cmp, vec};
 
-use frame_support::{decl_error, decl_event, decl_module, decl_storage, dispatch, ensure};
-use frame_system::{ensure_root, ensure_signed, Pallet as System};
+use frame_support::{dispatch, ensure};
+use frame_system::Pallet as System;
 
 use super::attestation::Error as AttestationError;
+use crate::mining::{self, MachineOwner};
 use crate::mq::{self, MessageOriginInfo};
+use crate::registry::{self, MREnclaveWhitelist};
+use crate::stakepool::{self, Stash, StashState};
 use alloc::{borrow::ToOwned, vec::Vec};
 use codec::Decode;
 use frame_support::{
 	dispatch::DispatchResult,
-	traits::{
-		Currency, ExistenceRequirement::AllowDeath, Get, Imbalance, OnUnbalanced, Randomness,
-		UnixTime,
-	},
+	traits::{Currency, Get, Imbalance, OnUnbalanced, Randomness, UnixTime},
+	weights::Weight,
 };
 use sp_runtime::{
 	traits::{AccountIdConversion, One, Zero},
@@ -24,26 +25,34 @@ use sp_runtime::{
 };
 use types::messaging::WorkerReportEvent;
 
-#[macro_use]
+#[cfg(feature = "runtime-benchmarks")]
 mod benchmarking;
 
+#[cfg(test)]
+pub(crate) mod mock;
+
+#[cfg(test)]
+mod tests;
+
 // modules
+pub mod migrations;
 pub mod weights;
 
 // types
 extern crate phala_types as types;
 use types::{
 	messaging::{
-		BalanceEvent, BalanceTransfer, BindTopic, BlockRewardInfo, Message, MessageOrigin,
-		SystemEvent,
+		BindTopic, BlockRewardInfo, Message, MessageOrigin, SignedMessage, SystemEvent,
 	},
-	MinerStatsDelta, PRuntimeInfo, PayoutPrefs, PayoutReason, RoundInfo, RoundStats, Score,
-	StashInfo, StashWorkerStats, WorkerInfo, WorkerStateEnum,
+	MinerStatsDelta, OffenceRecord, PRuntimeInfo, PayoutPrefs, PayoutReason, RoundInfo,
+	RoundStats, Score, SlashingSpan, StashInfo, StashWorkerStats, WorkerInfo, WorkerPublicKey,
+	WorkerStateEnum,
 };
 
 // constants
 pub use super::constants::*;
 
+pub use self::pallet::*;
 pub use weights::WeightInfo;
 
 type BalanceOf<T> =
@@ -56,9 +65,35 @@ type NegativeImbalanceOf<T> = <<T as Config>::TEECurrency as Currency<
 
 pub trait OnRoundEnd {
 	fn on_round_end(_round: u32) {}
+	/// The weight of `on_round_end` if the round ended now
+	fn round_end_weight() -> Weight {
+		0
+	}
 }
 impl OnRoundEnd for () {}
 
+/// Pays the stakers of a miner their share of its rewards
+pub trait OnReward<AccountId, Imbalance> {
+	/// Distributes `reward` to the stakers of `stash`. Returns the part not distributed.
+	fn on_reward(_stash: &AccountId, reward: Imbalance) -> Imbalance {
+		reward
+	}
+}
+impl<AccountId, Imbalance> OnReward<AccountId, Imbalance> for () {}
+
+/// Slashes the stake backing a miner when an offence is applied
+pub trait OnOffence<AccountId, Balance: Default> {
+	/// Slashes `fraction` of the stake received by `stash`. Returns the slashed amount.
+	fn on_offence(_stash: &AccountId, _fraction: Permill) -> Balance {
+		Default::default()
+	}
+	/// The weight of `on_offence` for `stash`
+	fn offence_weight(_stash: &AccountId) -> Weight {
+		0
+	}
+}
+impl<AccountId, Balance: Default> OnOffence<AccountId, Balance> for () {}
+
 pub trait OnMessageReceived {
 	fn on_message_received(_message: &Message) -> DispatchResult {
 		Ok(())
@@ -66,122 +101,288 @@ pub trait OnMessageReceived {
 }
 impl OnMessageReceived for () {}
 
-/// Configure the pallet by specifying the parameters and types on which it depends.
-pub trait Config: frame_system::Config + mq::Config {
-	type Event: From<Event<Self>> + Into<<Self as frame_system::Config>::Event>;
-	type Randomness: Randomness<Self::Hash, Self::BlockNumber>;
-	type TEECurrency: Currency<Self::AccountId>;
-	type UnixTime: UnixTime;
-	type Treasury: OnUnbalanced<NegativeImbalanceOf<Self>>;
-	type WeightInfo: WeightInfo;
-	type OnRoundEnd: OnRoundEnd;
-
-	// Parameters
-	type MaxHeartbeatPerWorkerPerHour: Get<u32>; // 2 tx
-	type RoundInterval: Get<Self::BlockNumber>; // 1 hour
-	type DecayInterval: Get<Self::BlockNumber>; // 180 days
-	type DecayFactor: Get<Permill>; // 75%
-	type InitialReward: Get<BalanceOf<Self>>; // 129600000 PHA
-	type TreasuryRation: Get<u32>; // 20%
-	type RewardRation: Get<u32>; // 80%
-	type OnlineRewardPercentage: Get<Permill>; // rel: 37.5% post-taxed: 30%
-	type ComputeRewardPercentage: Get<Permill>; // rel: 62.5% post-taxed: 50%
-	type OfflineOffenseSlash: Get<BalanceOf<Self>>;
-	type OfflineReportReward: Get<BalanceOf<Self>>;
-}
+#[frame_support::pallet]
+pub mod pallet {
+	use super::*;
+	use frame_support::pallet_prelude::*;
+	use frame_system::pallet_prelude::*;
 
-decl_storage! {
-	trait Store for Module<T: Config> as Phala {
-		// Messaging
-		/// Number of all commands
-		CommandNumber get(fn command_number): Option<u64>;
-		/// Contract assignment
-		ContractAssign get(fn contract_assign): map hasher(twox_64_concat) u32 => T::AccountId;
-		/// Ingress message queue
-		IngressSequence get(fn ingress_sequence): map hasher(twox_64_concat) u32 => u64;
-		/// Worker Ingress message queue
-		WorkerIngress get(fn worker_ingress): map hasher(twox_64_concat) T::AccountId => u64;
-
-		// Worker registry
-		/// Map from stash account to worker info
-		///
-		/// (Indexed: MachineOwner, PendingUpdate, PendingExitingDelta, OnlineWorkers, TotalPower)
-		WorkerState get(fn worker_state):
-			map hasher(blake2_128_concat) T::AccountId => WorkerInfo<T::BlockNumber>;
-		/// Map from stash account to stash info (indexed: Stash)
-		StashState get(fn stash_state):
-			map hasher(blake2_128_concat) T::AccountId => StashInfo<T::AccountId>;
-		// Power and Fire
-		/// Fire measures the total reward the miner can get (PoC3 1604-I specific)
-		Fire get(fn fire): map hasher(blake2_128_concat) T::AccountId => BalanceOf<T>;
-		/// Fire2 measures the total reward the miner can get (PoC3 1605-II specific)
-		Fire2 get(fn fire2): map hasher(twox_64_concat) T::AccountId => BalanceOf<T>;
-		/// Heartbeat counts
-		Heartbeats get(fn heartbeats): map hasher(blake2_128_concat) T::AccountId => u32;
-
-		// Indices
-		/// Map from machine_id to stash
-		MachineOwner get(fn machine_owner): map hasher(blake2_128_concat) Vec<u8> => T::AccountId;
-		/// Map from controller to stash
-		Stash get(fn stash): map hasher(blake2_128_concat) T::AccountId => T::AccountId;
-		/// Number of all online workers in this round
-		OnlineWorkers get(fn online_workers): u32;
-		/// Number of all computation workers that will be elected in this round
-		ComputeWorkers get(fn compute_workers): u32;
-		/// Total Power points in this round. Updated at handle_round_ends().
-		TotalPower get(fn total_power): u32;
-		/// Total Fire points (1605-I specific)
-		AccumulatedFire get(fn accumulated_fire): BalanceOf<T>;
-		/// Total Fire points (1605-II specific)
-		AccumulatedFire2 get(fn accumulated_fire2): BalanceOf<T>;
-
-		// Stats (poc3-only)
-		WorkerComputeReward: map hasher(twox_64_concat) T::AccountId => u32;
-		PayoutComputeReward: map hasher(twox_64_concat) T::AccountId => u32;
-
-		RoundWorkerStats get(fn round_worker_stats): map hasher(twox_64_concat) T::AccountId => StashWorkerStats<BalanceOf<T>>;
-
-		// Round management
-		/// The current mining round id
-		Round get(fn round): RoundInfo<T::BlockNumber>;
-		/// Indicates if we force the next round when the block finalized
-		ForceNextRound: bool;
-		/// Stash accounts with pending updates
-		PendingUpdate get(fn pending_updates): Vec<T::AccountId>;
-		/// The delta of the worker stats applaying at the end of this round due to exiting miners.
-		PendingExitingDelta get(fn pending_exiting): MinerStatsDelta;
-		/// Historical round stats; only the current and the last round are kept.
-		RoundStatsHistory get(fn round_stats_history):
-			map hasher(twox_64_concat) u32 => RoundStats;
-
-		// Probabilistic rewarding
-		BlockRewardSeeds: map hasher(twox_64_concat) T::BlockNumber => BlockRewardInfo;
-		/// The last block where a worker has on-chain activity, updated by `sync_worker_message`
-		LastWorkerActivity: map hasher(twox_64_concat) T::AccountId => T::BlockNumber;
-
-		// Key Management
-		/// Map from contract id to contract public key (TODO: migrate to real contract key from
-		/// worker identity key)
-		ContractKey get(fn contract_key): map hasher(twox_64_concat) u32 => Vec<u8>;
-
-		// Configurations
-		/// MREnclave Whitelist
-		MREnclaveWhitelist get(fn mr_enclave_whitelist): Vec<Vec<u8>>;
-		TargetOnlineRewardCount get(fn target_online_reward_count): u32;
-		TargetComputeRewardCount get(fn target_compute_reward_count): u32;
-		TargetVirtualTaskCount get(fn target_virtual_task_count): u32;
-		/// Miners must submit the heartbeat in `(now - reward_window, now]`
-		RewardWindow get(fn reward_window): T::BlockNumber;
-		/// Miners could be slashed in `(now - slash_window, now - reward_window]`
-		SlashWindow get(fn slash_window): T::BlockNumber;
+	/// Configure the pallet by specifying the parameters and types on which it depends.
+	///
+	/// The worker, stash and whitelist data live in the new pallets, so they must be configured
+	/// as well.
+	#[pallet::config]
+	pub trait Config:
+		frame_system::Config + mq::Config + registry::Config + mining::Config + stakepool::Config
+	{
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+		type Randomness: Randomness<Self::Hash, Self::BlockNumber>;
+		type TEECurrency: Currency<Self::AccountId>;
+		type UnixTime: UnixTime;
+		type Treasury: OnUnbalanced<NegativeImbalanceOf<Self>>;
+		type WeightInfo: WeightInfo;
+		type OnRoundEnd: OnRoundEnd;
+		type OnOffence: OnOffence<Self::AccountId, BalanceOf<Self>>;
+		type OnReward: OnReward<Self::AccountId, NegativeImbalanceOf<Self>>;
+
+		// Parameters
+		#[pallet::constant]
+		type MaxHeartbeatPerWorkerPerHour: Get<u32>; // 2 tx
+		#[pallet::constant]
+		type RoundInterval: Get<Self::BlockNumber>; // 1 hour
+		#[pallet::constant]
+		type DecayInterval: Get<Self::BlockNumber>; // 180 days
+		#[pallet::constant]
+		type DecayFactor: Get<Permill>; // 75%
+		#[pallet::constant]
+		type InitialReward: Get<BalanceOf<Self>>; // 129600000 PHA
+		#[pallet::constant]
+		type TreasuryRation: Get<u32>; // 20%
+		#[pallet::constant]
+		type RewardRation: Get<u32>; // 80%
+		#[pallet::constant]
+		type OnlineRewardPercentage: Get<Permill>; // rel: 37.5% post-taxed: 30%
+		#[pallet::constant]
+		type ComputeRewardPercentage: Get<Permill>; // rel: 62.5% post-taxed: 50%
+		/// The share of the mining rewards paid to the stakers of the miner
+		#[pallet::constant]
+		type StakerRewardPercentage: Get<Permill>;
+		#[pallet::constant]
+		type OfflineOffenseSlash: Get<BalanceOf<Self>>;
+		#[pallet::constant]
+		type OfflineReportReward: Get<BalanceOf<Self>>;
+		/// The blocks an offence can be disputed before it's applied
+		#[pallet::constant]
+		type OffenceDisputePeriod: Get<Self::BlockNumber>;
+		/// The stake slashed by the first offence in a span; doubled by each following one
+		#[pallet::constant]
+		type OffenceSlashBase: Get<Permill>;
+		/// The upper bound of the stake slashed by a single offence
+		#[pallet::constant]
+		type OffenceSlashMax: Get<Permill>;
+	}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(_);
+
+	// The storage layout is identical to the former `decl_storage` pallet (prefix "Phala").
+
+	// Messaging
+	/// Number of all commands
+	#[pallet::storage]
+	#[pallet::getter(fn command_number)]
+	pub type CommandNumber<T> = StorageValue<_, u64>;
+
+	/// Contract assignment
+	#[pallet::storage]
+	#[pallet::getter(fn contract_assign)]
+	pub type ContractAssign<T: Config> =
+		StorageMap<_, Twox64Concat, u32, T::AccountId, ValueQuery>;
+
+	/// Ingress message queue
+	#[pallet::storage]
+	#[pallet::getter(fn ingress_sequence)]
+	pub type IngressSequence<T> = StorageMap<_, Twox64Concat, u32, u64, ValueQuery>;
+
+	/// Worker Ingress message queue
+	#[pallet::storage]
+	#[pallet::getter(fn worker_ingress)]
+	pub type WorkerIngress<T: Config> = StorageMap<_, Twox64Concat, T::AccountId, u64, ValueQuery>;
+
+	// Worker registry
+	/// Map from stash account to worker info
+	///
+	/// (Indexed: MachineOwner, PendingUpdate, PendingExitingDelta, OnlineWorkers, TotalPower)
+	///
+	/// The stash info and the indices `Stash` and `MachineOwner` are moved to
+	/// `pallet_stakepool` and `pallet_mining` by `migrations::MigrateToNewPallets`.
+	#[pallet::storage]
+	#[pallet::getter(fn worker_state)]
+	pub type WorkerState<T: Config> =
+		StorageMap<_, Blake2_128Concat, T::AccountId, WorkerInfo<T::BlockNumber>, ValueQuery>;
+
+	// Power and Fire
+	/// Fire measures the total reward the miner can get (PoC3 1604-I specific)
+	#[pallet::storage]
+	#[pallet::getter(fn fire)]
+	pub type Fire<T: Config> =
+		StorageMap<_, Blake2_128Concat, T::AccountId, BalanceOf<T>, ValueQuery>;
+
+	/// Fire2 measures the total reward the miner can get (PoC3 1605-II specific)
+	#[pallet::storage]
+	#[pallet::getter(fn fire2)]
+	pub type Fire2<T: Config> =
+		StorageMap<_, Twox64Concat, T::AccountId, BalanceOf<T>, ValueQuery>;
+
+	/// Heartbeat counts
+	#[pallet::storage]
+	#[pallet::getter(fn heartbeats)]
+	pub type Heartbeats<T: Config> =
+		StorageMap<_, Blake2_128Concat, T::AccountId, u32, ValueQuery>;
+
+	// Indices
+	/// Number of all online workers in this round
+	#[pallet::storage]
+	#[pallet::getter(fn online_workers)]
+	pub type OnlineWorkers<T> = StorageValue<_, u32, ValueQuery>;
+
+	/// Number of all computation workers that will be elected in this round
+	#[pallet::storage]
+	#[pallet::getter(fn compute_workers)]
+	pub type ComputeWorkers<T> = StorageValue<_, u32, ValueQuery>;
+
+	/// Total Power points in this round. Updated at handle_round_ends().
+	#[pallet::storage]
+	#[pallet::getter(fn total_power)]
+	pub type TotalPower<T> = StorageValue<_, u32, ValueQuery>;
+
+	/// Total Fire points (1605-I specific)
+	#[pallet::storage]
+	#[pallet::getter(fn accumulated_fire)]
+	pub type AccumulatedFire<T: Config> = StorageValue<_, BalanceOf<T>, ValueQuery>;
+
+	/// Total Fire points (1605-II specific)
+	#[pallet::storage]
+	#[pallet::getter(fn accumulated_fire2)]
+	pub type AccumulatedFire2<T: Config> = StorageValue<_, BalanceOf<T>, ValueQuery>;
+
+	// Stats (poc3-only)
+	#[pallet::storage]
+	pub type WorkerComputeReward<T: Config> =
+		StorageMap<_, Twox64Concat, T::AccountId, u32, ValueQuery>;
+
+	#[pallet::storage]
+	pub type PayoutComputeReward<T: Config> =
+		StorageMap<_, Twox64Concat, T::AccountId, u32, ValueQuery>;
+
+	#[pallet::storage]
+	#[pallet::getter(fn round_worker_stats)]
+	pub type RoundWorkerStats<T: Config> =
+		StorageMap<_, Twox64Concat, T::AccountId, StashWorkerStats<BalanceOf<T>>, ValueQuery>;
+
+	// Round management
+	/// The current mining round id
+	#[pallet::storage]
+	#[pallet::getter(fn round)]
+	pub type Round<T: Config> = StorageValue<_, RoundInfo<T::BlockNumber>, ValueQuery>;
+
+	/// Indicates if we force the next round when the block finalized
+	#[pallet::storage]
+	pub type ForceNextRound<T> = StorageValue<_, bool, ValueQuery>;
+
+	/// Stash accounts with pending updates
+	#[pallet::storage]
+	#[pallet::getter(fn pending_updates)]
+	pub type PendingUpdate<T: Config> = StorageValue<_, Vec<T::AccountId>, ValueQuery>;
+
+	/// The delta of the worker stats applaying at the end of this round due to exiting miners.
+	#[pallet::storage]
+	#[pallet::getter(fn pending_exiting)]
+	pub type PendingExitingDelta<T> = StorageValue<_, MinerStatsDelta, ValueQuery>;
+
+	/// Historical round stats; only the current and the last round are kept.
+	#[pallet::storage]
+	#[pallet::getter(fn round_stats_history)]
+	pub type RoundStatsHistory<T> = StorageMap<_, Twox64Concat, u32, RoundStats, ValueQuery>;
+
+	// Probabilistic rewarding
+	#[pallet::storage]
+	pub type BlockRewardSeeds<T: Config> =
+		StorageMap<_, Twox64Concat, T::BlockNumber, BlockRewardInfo, ValueQuery>;
+
+	/// The last block where a worker has on-chain activity, updated by `sync_worker_message`
+	#[pallet::storage]
+	pub type LastWorkerActivity<T: Config> =
+		StorageMap<_, Twox64Concat, T::AccountId, T::BlockNumber, ValueQuery>;
+
+	// Offences
+	/// The unapplied offences, one per missed heartbeat challenge: (stash, challenge block)
+	#[pallet::storage]
+	#[pallet::getter(fn offences)]
+	pub type Offences<T: Config> = StorageDoubleMap<
+		_,
+		Twox64Concat,
+		T::AccountId,
+		Twox64Concat,
+		T::BlockNumber,
+		OffenceRecord<T::AccountId, T::BlockNumber>,
+	>;
+
+	/// The offences to apply at a block, unless disputed before
+	#[pallet::storage]
+	pub type PendingOffences<T: Config> = StorageMap<
+		_,
+		Twox64Concat,
+		T::BlockNumber,
+		Vec<(T::AccountId, T::BlockNumber)>,
+		ValueQuery,
+	>;
+
+	/// The recent offences of each miner
+	#[pallet::storage]
+	#[pallet::getter(fn slashing_spans)]
+	pub type SlashingSpans<T: Config> =
+		StorageMap<_, Twox64Concat, T::AccountId, SlashingSpan, ValueQuery>;
+
+	// Key Management
+	/// Map from contract id to contract public key (TODO: migrate to real contract key from
+	/// worker identity key)
+	#[pallet::storage]
+	#[pallet::getter(fn contract_key)]
+	pub type ContractKey<T> = StorageMap<_, Twox64Concat, u32, Vec<u8>, ValueQuery>;
+
+	// Configurations
+	#[pallet::storage]
+	#[pallet::getter(fn target_online_reward_count)]
+	pub type TargetOnlineRewardCount<T> = StorageValue<_, u32, ValueQuery>;
+
+	#[pallet::storage]
+	#[pallet::getter(fn target_compute_reward_count)]
+	pub type TargetComputeRewardCount<T> = StorageValue<_, u32, ValueQuery>;
+
+	#[pallet::storage]
+	#[pallet::getter(fn target_virtual_task_count)]
+	pub type TargetVirtualTaskCount<T> = StorageValue<_, u32, ValueQuery>;
+
+	/// Miners must submit the heartbeat in `(now - reward_window, now]`
+	#[pallet::storage]
+	#[pallet::getter(fn reward_window)]
+	pub type RewardWindow<T: Config> = StorageValue<_, T::BlockNumber, ValueQuery>;
+
+	/// Miners could be slashed in `(now - slash_window, now - reward_window]`
+	#[pallet::storage]
+	#[pallet::getter(fn slash_window)]
+	pub type SlashWindow<T: Config> = StorageValue<_, T::BlockNumber, ValueQuery>;
+
+	// Migrations
+	/// Whether the worker, stash and whitelist data have been moved to the new pallets
+	#[pallet::storage]
+	#[pallet::getter(fn migrated_to_new_pallets)]
+	pub type MigratedToNewPallets<T> = StorageValue<_, bool, ValueQuery>;
+
+	#[pallet::genesis_config]
+	pub struct GenesisConfig<T: Config> {
+		/// `(stash, controller, pubkey)`
+		pub stakers: Vec<(T::AccountId, T::AccountId, Vec<u8>)>,
+		pub contract_keys: Vec<Vec<u8>>,
 	}
 
-	add_extra_genesis {
-		config(stakers): Vec<(T::AccountId, T::AccountId, Vec<u8>)>;  // <stash, controller, pubkey>
-		config(contract_keys): Vec<Vec<u8>>;
-		build(|config: &GenesisConfig<T>| {
+	#[cfg(feature = "std")]
+	impl<T: Config> Default for GenesisConfig<T> {
+		fn default() -> Self {
+			Self {
+				stakers: Default::default(),
+				contract_keys: Default::default(),
+			}
+		}
+	}
+
+	#[pallet::genesis_build]
+	impl<T: Config> GenesisBuild<T> for GenesisConfig<T> {
+		fn build(&self) {
 			let base_mid = BUILTIN_MACHINE_ID.as_bytes().to_vec();
-			for (i, (stash, controller, pubkey)) in config.stakers.iter().enumerate() {
+			for (i, (stash, controller, pubkey)) in self.stakers.iter().enumerate() {
 				// Mock worker / stash info
 				let mut machine_id = base_mid.clone();
 				machine_id.push(b'0' + (i as u8));
@@ -192,10 +393,10 @@ decl_storage! {
 					state: WorkerStateEnum::Free,
 					score: Some(Score {
 						overall_score: 100,
-						features: vec![1, 4]
+						features: vec![1, 4],
 					}),
 					confidence_level: 128u8,
-					runtime_version: 0
+					runtime_version: 0,
 				};
 				WorkerState::<T>::insert(&stash, worker_info);
 				let stash_info = StashInfo {
@@ -203,58 +404,69 @@ decl_storage! {
 					payout_prefs: PayoutPrefs {
 						commission: 0,
 						target: stash.clone(),
-					}
+					},
 				};
 				StashState::<T>::insert(&stash, stash_info);
 				// Update indices (skip MachineOwenr because we won't use it in anyway)
 				Stash::<T>::insert(&controller, &stash);
 			}
 			// Insert the default contract key here
-			for (i, key) in config.contract_keys.iter().enumerate() {
-				ContractKey::insert(i as u32, key);
+			for (i, key) in self.contract_keys.iter().enumerate() {
+				ContractKey::<T>::insert(i as u32, key);
 			}
 
+			// The new chains start with the data in the new pallets
+			MigratedToNewPallets::<T>::put(true);
+
 			// TODO: reconsider the window length
-			RewardWindow::<T>::put(T::BlockNumber::from(8u32));  // 5 blocks (3 for finalizing)
-			SlashWindow::<T>::put(T::BlockNumber::from(40u32));  // 5x larger window
-			TargetOnlineRewardCount::put(20u32);
-			TargetComputeRewardCount::put(10u32);
-			TargetVirtualTaskCount::put(5u32);
-		});
+			RewardWindow::<T>::put(T::BlockNumber::from(8u32)); // 5 blocks (3 for finalizing)
+			SlashWindow::<T>::put(T::BlockNumber::from(40u32)); // 5x larger window
+			TargetOnlineRewardCount::<T>::put(20u32);
+			TargetComputeRewardCount::<T>::put(10u32);
+			TargetVirtualTaskCount::<T>::put(5u32);
+		}
 	}
-}
 
-decl_event!(
-	pub enum Event<T>
-	where
-		AccountId = <T as frame_system::Config>::AccountId,
-		Balance = BalanceOf<T>,
-	{
+	#[pallet::event]
+	#[pallet::metadata(T::AccountId = "AccountId", BalanceOf<T> = "Balance")]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
 		/// Some worker got slashed. [stash, payout_addr, lost_amount, reporter, win_amount]
-		Slash(AccountId, AccountId, Balance, AccountId, Balance),
-		_GotCredits(AccountId, u32, u32), // [DEPRECATED] [account, updated, delta]
-		WorkerStateUpdated(AccountId),
+		Slash(
+			T::AccountId,
+			T::AccountId,
+			BalanceOf<T>,
+			T::AccountId,
+			BalanceOf<T>,
+		),
+		_GotCredits(T::AccountId, u32, u32), // [DEPRECATED] [account, updated, delta]
+		WorkerStateUpdated(T::AccountId),
 		WhitelistAdded(Vec<u8>),
 		WhitelistRemoved(Vec<u8>),
 		/// [round, stash]
-		MinerStarted(u32, AccountId),
+		MinerStarted(u32, T::AccountId),
 		/// [round, stash]
-		MinerStopped(u32, AccountId),
+		MinerStopped(u32, T::AccountId),
 		/// [round]
 		NewMiningRound(u32),
-		_Payout(AccountId, Balance, Balance), // [DEPRECATED] dest, reward, treasury
+		_Payout(T::AccountId, BalanceOf<T>, BalanceOf<T>), // [DEPRECATED] dest, reward, treasury
 		/// [stash, dest]
-		PayoutMissed(AccountId, AccountId),
+		PayoutMissed(T::AccountId, T::AccountId),
 		/// [dest, reward, treasury, reason]
-		PayoutReward(AccountId, Balance, Balance, PayoutReason),
+		PayoutReward(T::AccountId, BalanceOf<T>, BalanceOf<T>, PayoutReason),
 		/// A lottery contract message was received. [sequence]
 		LotteryMessageReceived(u64),
+		/// A miner missed a heartbeat challenge. [stash, challenge_block, reporter]
+		OffenceReported(T::AccountId, T::BlockNumber, T::AccountId),
+		/// An offence was withdrawn by the evidence of the heartbeat. [stash, challenge_block]
+		OffenceDisputed(T::AccountId, T::BlockNumber),
+		/// An offence was applied. [stash, challenge_block, fraction, slashed_stake]
+		OffenceSlashed(T::AccountId, T::BlockNumber, Permill, BalanceOf<T>),
 	}
-);
 
-// Errors inform users that something went wrong.
-decl_error! {
-	pub enum Error for Module<T: Config> {
+	// Errors inform users that something went wrong.
+	#[pallet::error]
+	pub enum Error<T> {
 		InvalidIASSigningCert,
 		InvalidIASReportSignature,
 		InvalidQuoteStatus,
@@ -323,49 +535,63 @@ decl_error! {
 		NotAllowed,
 		/// Unable to parse the quote body
 		UnknownQuoteBodyFormat,
+		/// The missed challenge has been reported
+		DuplicateOffence,
+		/// No pending offence for the challenge
+		OffenceNotFound,
+		/// The evidence is not a heartbeat of the worker to the challenge
+		InvalidEvidence,
+		/// The witness is less than the number of the reward seeds to remove
+		BadWitness,
 	}
-}
 
-impl<T: Config> From<AttestationError> for Error<T> {
-	fn from(err: AttestationError) -> Self {
-		match err {
-			AttestationError::InvalidIASSigningCert => Self::InvalidIASSigningCert,
-			AttestationError::InvalidReport => Self::InvalidInput,
-			AttestationError::InvalidQuoteStatus => Self::InvalidQuoteStatus,
-			AttestationError::BadIASReport => Self::BadIASReport,
-			AttestationError::OutdatedIASReport => Self::OutdatedIASReport,
-			AttestationError::UnknownQuoteBodyFormat => Self::UnknownQuoteBodyFormat,
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			// The offences due now, applied in `on_finalize`
+			let mut weight = T::DbWeight::get().reads(1);
+			for (stash, _) in PendingOffences::<T>::get(now).iter() {
+				weight = weight
+					.saturating_add(T::WeightInfo::apply_offence())
+					.saturating_add(T::OnOffence::offence_weight(stash));
+			}
+			// The forced round ends are charged by `force_next_round`
+			let interval = T::RoundInterval::get();
+			if now % interval == interval - 1u32.into() {
+				weight = weight.saturating_add(T::OnRoundEnd::round_end_weight());
+			}
+			// Continues moving the data to the new pallets after the upgrade
+			weight.saturating_add(migrations::migrate_batch::<T>())
 		}
-	}
-}
-
-// Dispatchable functions allows users to interact with the pallet and invoke state changes.
-// These functions materialize as "extrinsics", which are often compared to transactions.
-// Dispatchable functions must be annotated with a weight and must return a DispatchResult.
-decl_module! {
-	pub struct Module<T: Config> for enum Call where origin: T::Origin {
-		type Error = Error<T>;
-		fn deposit_event() = default;
 
-		fn on_finalize() {
-			let now = System::<T>::block_number();
+		fn on_finalize(now: T::BlockNumber) {
 			let round = Round::<T>::get();
 			Self::handle_block_reward(now, &round);
+			Self::apply_offences(now, round.round);
 			// Should we end the current round?
 			let interval = T::RoundInterval::get();
-			if ForceNextRound::get() || now % interval == interval - 1u32.into() {
-				ForceNextRound::put(false);
+			if ForceNextRound::<T>::get() || now % interval == interval - 1u32.into() {
+				ForceNextRound::<T>::put(false);
 				Self::handle_round_ends(now, &round);
 			}
 		}
+	}
 
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
 		// Registry
 		/// Crerate a new stash or update an existing one.
-		#[weight = T::WeightInfo::set_stash()]
-		pub fn set_stash(origin, controller: T::AccountId) -> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::set_stash())]
+		pub fn set_stash(origin: OriginFor<T>, controller: T::AccountId) -> DispatchResult {
 			let who = ensure_signed(origin)?;
-			ensure!(!Stash::<T>::contains_key(&controller), Error::<T>::AlreadyPaired);
-			ensure!(!StashState::<T>::contains_key(&controller), Error::<T>::AlreadyBonded);
+			ensure!(
+				!Stash::<T>::contains_key(&controller),
+				Error::<T>::AlreadyPaired
+			);
+			ensure!(
+				!StashState::<T>::contains_key(&controller),
+				Error::<T>::AlreadyBonded
+			);
 			let stash_state = if StashState::<T>::contains_key(&who) {
 				// Remove previous controller
 				let prev = StashState::<T>::get(&who);
@@ -379,8 +605,8 @@ decl_module! {
 					controller: controller.clone(),
 					payout_prefs: PayoutPrefs {
 						commission: 0,
-						target: who.clone(),  // Set to the stash by default
-					}
+						target: who.clone(), // Set to the stash by default
+					},
 				}
 			};
 			StashState::<T>::insert(&who, stash_state);
@@ -389,14 +615,22 @@ decl_module! {
 		}
 
 		/// Update the payout preferences. Must be called by the controller.
-		#[weight = T::WeightInfo::set_payout_prefs()]
-		pub fn set_payout_prefs(origin, payout_commission: Option<u32>,
-								payout_target: Option<T::AccountId>)
-								-> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::set_payout_prefs())]
+		pub fn set_payout_prefs(
+			origin: OriginFor<T>,
+			payout_commission: Option<u32>,
+			payout_target: Option<T::AccountId>,
+		) -> DispatchResult {
 			let who = ensure_signed(origin)?;
-			ensure!(Stash::<T>::contains_key(who.clone()), Error::<T>::NotController);
+			ensure!(
+				Stash::<T>::contains_key(who.clone()),
+				Error::<T>::NotController
+			);
 			let stash = Stash::<T>::get(who.clone());
-			ensure!(StashState::<T>::contains_key(&stash), Error::<T>::StashNotFound);
+			ensure!(
+				StashState::<T>::contains_key(&stash),
+				Error::<T>::StashNotFound
+			);
 			let mut stash_info = StashState::<T>::get(&stash);
 			if let Some(val) = payout_commission {
 				ensure!(val <= 100, Error::<T>::InvalidCommission);
@@ -409,43 +643,53 @@ decl_module! {
 			Ok(())
 		}
 
-		#[weight = T::WeightInfo::force_set_contract_key()]
-		fn force_set_contract_key(origin, id: u32, pubkey: Vec<u8>) -> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::force_set_contract_key())]
+		pub fn force_set_contract_key(
+			origin: OriginFor<T>,
+			id: u32,
+			pubkey: Vec<u8>,
+		) -> DispatchResult {
 			ensure_root(origin)?;
-			ContractKey::insert(id, pubkey);
+			ContractKey::<T>::insert(id, pubkey);
 			Ok(())
 		}
 
 		// Mining
 
-		#[weight = T::WeightInfo::start_mining_intention()]
-		fn start_mining_intention(origin) -> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::start_mining_intention())]
+		pub fn start_mining_intention(origin: OriginFor<T>) -> DispatchResult {
 			let who = ensure_signed(origin)?;
-			ensure!(Stash::<T>::contains_key(&who), Error::<T>::ControllerNotFound);
+			ensure!(
+				Stash::<T>::contains_key(&who),
+				Error::<T>::ControllerNotFound
+			);
 			let stash = Stash::<T>::get(who);
 			let mut worker_info = WorkerState::<T>::get(&stash);
 
 			match worker_info.state {
 				WorkerStateEnum::Free => {
 					worker_info.state = WorkerStateEnum::MiningPending;
-					Self::deposit_event(RawEvent::WorkerStateUpdated(stash.clone()));
-				},
+					Self::deposit_event(Event::WorkerStateUpdated(stash.clone()));
+				}
 				// WorkerStateEnum::MiningStopping => {
 				// 	worker_info.state = WorkerStateEnum::Mining;
-				// 	Self::deposit_event(RawEvent::WorkerStateUpdated(stash.clone()));
+				// 	Self::deposit_event(Event::WorkerStateUpdated(stash.clone()));
 				// }
 				WorkerStateEnum::Mining(_) | WorkerStateEnum::MiningPending => return Ok(()),
-				_ => return Err(Error::<T>::InvalidState.into())
+				_ => return Err(Error::<T>::InvalidState.into()),
 			};
 			WorkerState::<T>::insert(&stash, worker_info);
 			Self::mark_dirty(stash);
 			Ok(())
 		}
 
-		#[weight = T::WeightInfo::stop_mining_intention()]
-		fn stop_mining_intention(origin) -> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::stop_mining_intention())]
+		pub fn stop_mining_intention(origin: OriginFor<T>) -> DispatchResult {
 			let who = ensure_signed(origin)?;
-			ensure!(Stash::<T>::contains_key(&who), Error::<T>::ControllerNotFound);
+			ensure!(
+				Stash::<T>::contains_key(&who),
+				Error::<T>::ControllerNotFound
+			);
 			let stash = Stash::<T>::get(who);
 
 			Self::stop_mining_internal(&stash)?;
@@ -454,31 +698,46 @@ decl_module! {
 
 		// Token
 
-		#[weight = T::WeightInfo::transfer_to_tee()]
-		fn transfer_to_tee(origin, #[compact] amount: BalanceOf<T>) -> dispatch::DispatchResult {
-			let who = ensure_signed(origin)?;
-			T::TEECurrency::transfer(&who, &Self::account_id(), amount, AllowDeath)
-				.map_err(|_| Error::<T>::CannotDeposit)?;
-			Self::push_message(BalanceEvent::TransferToTee(who, amount));
-			Ok(())
+		/// Deprecated: the deposits are escrowed by `pallet_escrow::deposit` now.
+		#[pallet::weight(T::WeightInfo::transfer_to_tee())]
+		pub fn transfer_to_tee(
+			origin: OriginFor<T>,
+			#[pallet::compact] _amount: BalanceOf<T>,
+		) -> DispatchResult {
+			ensure_signed(origin)?;
+			Err(Error::<T>::CannotDeposit.into())
 		}
 
 		// Violence
-		#[weight = 0]
-		fn report_offline(
-			origin, stash: T::AccountId, block_num: T::BlockNumber
-		) -> dispatch::DispatchResult {
+
+		/// Reports a miner missing the heartbeat challenge at `block_num`.
+		///
+		/// The offence is applied after `OffenceDisputePeriod` unless the heartbeat is presented
+		/// by `dispute_offence`.
+		#[pallet::weight(T::WeightInfo::report_offline())]
+		pub fn report_offline(
+			origin: OriginFor<T>,
+			stash: T::AccountId,
+			block_num: T::BlockNumber,
+		) -> DispatchResult {
 			let reporter = ensure_signed(origin)?;
 			let now = System::<T>::block_number();
 			let slash_window = SlashWindow::<T>::get();
 			ensure!(block_num + slash_window > now, Error::<T>::TooAncientReport);
+			// The worker still has a chance to submit the heartbeat
+			ensure!(
+				block_num + RewardWindow::<T>::get() < now,
+				Error::<T>::ReportedWorkerStillAlive
+			);
 
-			// TODO: should slash force replacement of TEE worker as well!
 			// TODO: how to handle the report to the previous round?
 			let round_start = Round::<T>::get().start_block;
 			ensure!(block_num >= round_start, Error::<T>::TooAncientReport);
 			// Worker is online (Mining / PendingStopping)
-			ensure!(WorkerState::<T>::contains_key(&stash), Error::<T>::StashNotFound);
+			ensure!(
+				WorkerState::<T>::contains_key(&stash),
+				Error::<T>::StashNotFound
+			);
 			let worker_info = WorkerState::<T>::get(&stash);
 			let is_mining = match worker_info.state {
 				WorkerStateEnum::Mining(_) | WorkerStateEnum::MiningStopping => true,
@@ -490,29 +749,103 @@ decl_module! {
 				LastWorkerActivity::<T>::get(&stash) < block_num,
 				Error::<T>::ReportedWorkerStillAlive
 			);
-			// Check worker's pubkey xor privkey < target (!)
-			let reward_info = BlockRewardSeeds::<T>::get(block_num);
+			// The worker was challenged at the block
+			let (online_hit, _) = heartbeat_challenge(
+				worker_info.pubkey.as_slice(),
+				&BlockRewardSeeds::<T>::get(block_num),
+			);
+			ensure!(online_hit, Error::<T>::InvalidProof);
 			ensure!(
-				check_pubkey_hit_target(worker_info.pubkey.as_slice(), &reward_info),
-				Error::<T>::InvalidProof
+				!Offences::<T>::contains_key(&stash, block_num),
+				Error::<T>::DuplicateOffence
 			);
 
-			Self::slash_offline(&stash, &reporter, &worker_info.machine_id)?;
+			Offences::<T>::insert(
+				&stash,
+				block_num,
+				OffenceRecord {
+					reporter: reporter.clone(),
+					reported_at: now,
+				},
+			);
+			PendingOffences::<T>::append(
+				now + T::OffenceDisputePeriod::get(),
+				(stash.clone(), block_num),
+			);
+			Self::deposit_event(Event::OffenceReported(stash, block_num, reporter));
+			Ok(())
+		}
+
+		/// Withdraws a pending offence by presenting a heartbeat signed by the worker, answering
+		/// a challenge to it no later than `RewardWindow` after the missed one.
+		#[pallet::weight(T::WeightInfo::dispute_offence())]
+		pub fn dispute_offence(
+			origin: OriginFor<T>,
+			stash: T::AccountId,
+			block_num: T::BlockNumber,
+			evidence: SignedMessage,
+		) -> DispatchResult {
+			ensure_signed(origin)?;
+			ensure!(
+				Offences::<T>::contains_key(&stash, block_num),
+				Error::<T>::OffenceNotFound
+			);
+			let worker_info = WorkerState::<T>::get(&stash);
+			let from_worker = match &evidence.message.sender {
+				MessageOrigin::Worker(pubkey) => pubkey.as_ref() == worker_info.pubkey.as_slice(),
+				_ => false,
+			};
+			ensure!(
+				from_worker
+					&& evidence.message.destination.path().as_slice() == WorkerReportEvent::TOPIC,
+				Error::<T>::InvalidEvidence
+			);
+			let heartbeat: WorkerReportEvent = evidence
+				.message
+				.decode_payload()
+				.ok_or(Error::<T>::InvalidEvidence)?;
+			let answered = match heartbeat {
+				WorkerReportEvent::Heartbeat {
+					block_num: heartbeat_block,
+					claim_online,
+					..
+				} => {
+					let heartbeat_block = T::BlockNumber::from(heartbeat_block);
+					let (online_hit, _) = heartbeat_challenge(
+						worker_info.pubkey.as_slice(),
+						&BlockRewardSeeds::<T>::get(heartbeat_block),
+					);
+					claim_online
+						&& online_hit
+						&& heartbeat_block >= block_num
+						&& heartbeat_block <= block_num + RewardWindow::<T>::get()
+				}
+			};
+			ensure!(answered, Error::<T>::InvalidEvidence);
+			registry::Pallet::<T>::check_message(&evidence)?;
+
+			Offences::<T>::remove(&stash, block_num);
+			Self::deposit_event(Event::OffenceDisputed(stash, block_num));
 			Ok(())
 		}
 
 		// Debug only
 
-		#[weight = T::WeightInfo::force_next_round()]
-		fn force_next_round(origin) -> dispatch::DispatchResult {
+		#[pallet::weight(
+			T::WeightInfo::force_next_round().saturating_add(T::OnRoundEnd::round_end_weight())
+		)]
+		pub fn force_next_round(origin: OriginFor<T>) -> DispatchResult {
 			ensure_root(origin)?;
-			ForceNextRound::put(true);
+			ForceNextRound::<T>::put(true);
 			Ok(())
 		}
 
-		#[weight = T::WeightInfo::force_add_fire()]
-		fn force_add_fire(origin, targets: Vec<T::AccountId>, amounts: Vec<BalanceOf<T>>)
-		-> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::force_add_fire())]
+		pub fn force_add_fire(
+			origin: OriginFor<T>,
+			targets: Vec<T::AccountId>,
+			amounts: Vec<BalanceOf<T>>,
+		) -> DispatchResult {
 			ensure_root(origin)?;
 			ensure!(targets.len() == amounts.len(), Error::<T>::InvalidInput);
 			for i in 0..targets.len() {
@@ -523,46 +856,55 @@ decl_module! {
 			Ok(())
 		}
 
-		#[weight = T::WeightInfo::force_set_virtual_tasks()]
-		fn force_set_virtual_tasks(origin, target: u32) -> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::force_set_virtual_tasks())]
+		pub fn force_set_virtual_tasks(origin: OriginFor<T>, target: u32) -> DispatchResult {
 			ensure_root(origin)?;
-			TargetVirtualTaskCount::put(target);
+			TargetVirtualTaskCount::<T>::put(target);
 			Ok(())
 		}
 
-		#[weight = T::WeightInfo::force_reset_fire()]
-		fn force_reset_fire(origin) -> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::force_reset_fire())]
+		pub fn force_reset_fire(origin: OriginFor<T>) -> DispatchResult {
 			ensure_root(origin)?;
 			Fire2::<T>::remove_all(None);
 			AccumulatedFire2::<T>::kill();
 			Ok(())
 		}
 
-		#[weight = 0]
-		fn force_set_window(
-			origin, reward_window: Option<T::BlockNumber>, slash_window: Option<T::BlockNumber>
-		) -> dispatch::DispatchResult {
+		/// Sets the reward and the slash windows, removing the reward seeds out of a shorter
+		/// slash window.
+		///
+		/// `removal_witness` must be at least the number of the seeds to remove, i.e. the
+		/// difference of the old and the new slash windows.
+		#[pallet::weight(T::WeightInfo::force_set_window(*removal_witness))]
+		pub fn force_set_window(
+			origin: OriginFor<T>,
+			reward_window: Option<T::BlockNumber>,
+			slash_window: Option<T::BlockNumber>,
+			#[pallet::compact] removal_witness: u32,
+		) -> DispatchResult {
 			ensure_root(origin)?;
 			let old_reward = RewardWindow::<T>::get();
-			let old_slash = SlashWindow::<T>::try_get()
-				.unwrap_or(DEFAULT_BLOCK_REWARD_TO_KEEP.into());
+			let old_slash =
+				SlashWindow::<T>::try_get().unwrap_or(DEFAULT_BLOCK_REWARD_TO_KEEP.into());
 			let reward = reward_window.unwrap_or(old_reward);
 			let slash = slash_window.unwrap_or(old_slash);
 			ensure!(slash >= reward, Error::<T>::InvalidInput);
 			// Clean up (now - old, now - new] when the new slash window is shorter
-			if slash < old_slash {
-				let now = System::<T>::block_number();
-				if now > slash {
-					let last_empty_idx = if now >= old_slash {
-						(now - old_slash).into()
-					} else {
-						Zero::zero()
-					};
-					let mut i = now - slash;
-					while i > last_empty_idx {
-						BlockRewardSeeds::<T>::remove(i);
-						i -= One::one();
-					}
+			let now = System::<T>::block_number();
+			if slash < old_slash && now > slash {
+				let last_empty_idx = if now >= old_slash {
+					now - old_slash
+				} else {
+					Zero::zero()
+				};
+				let first = now - slash;
+				let removals: u32 = (first - last_empty_idx).saturated_into();
+				ensure!(removals <= removal_witness, Error::<T>::BadWitness);
+				let mut i = first;
+				while i > last_empty_idx {
+					BlockRewardSeeds::<T>::remove(i);
+					i -= One::one();
 				}
 			}
 			RewardWindow::<T>::put(reward);
@@ -572,24 +914,53 @@ decl_module! {
 
 		// Whitelist
 
-		#[weight = T::WeightInfo::add_mrenclave()]
-		fn add_mrenclave(origin, mr_enclave: Vec<u8>, mr_signer: Vec<u8>, isv_prod_id: Vec<u8>, isv_svn: Vec<u8>) -> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::add_mrenclave())]
+		pub fn add_mrenclave(
+			origin: OriginFor<T>,
+			mr_enclave: Vec<u8>,
+			mr_signer: Vec<u8>,
+			isv_prod_id: Vec<u8>,
+			isv_svn: Vec<u8>,
+		) -> DispatchResult {
 			ensure_root(origin)?;
-			ensure!(mr_enclave.len() == 32 && mr_signer.len() == 32 && isv_prod_id.len() == 2 && isv_svn.len() == 2, Error::<T>::InvalidInputBadLength);
+			ensure!(
+				mr_enclave.len() == 32
+					&& mr_signer.len() == 32
+					&& isv_prod_id.len() == 2
+					&& isv_svn.len() == 2,
+				Error::<T>::InvalidInputBadLength
+			);
 			Self::add_mrenclave_to_whitelist(&mr_enclave, &mr_signer, &isv_prod_id, &isv_svn)?;
 			Ok(())
 		}
 
-		#[weight = T::WeightInfo::remove_mrenclave_by_raw_data()]
-		fn remove_mrenclave_by_raw_data(origin, mr_enclave: Vec<u8>, mr_signer: Vec<u8>, isv_prod_id: Vec<u8>, isv_svn: Vec<u8>) -> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::remove_mrenclave_by_raw_data())]
+		pub fn remove_mrenclave_by_raw_data(
+			origin: OriginFor<T>,
+			mr_enclave: Vec<u8>,
+			mr_signer: Vec<u8>,
+			isv_prod_id: Vec<u8>,
+			isv_svn: Vec<u8>,
+		) -> DispatchResult {
 			ensure_root(origin)?;
-			ensure!(mr_enclave.len() == 32 && mr_signer.len() == 32 && isv_prod_id.len() == 2 && isv_svn.len() == 2, Error::<T>::InvalidInputBadLength);
-			Self::remove_mrenclave_from_whitelist_by_raw_data(&mr_enclave, &mr_signer, &isv_prod_id, &isv_svn)?;
+			ensure!(
+				mr_enclave.len() == 32
+					&& mr_signer.len() == 32
+					&& isv_prod_id.len() == 2
+					&& isv_svn.len() == 2,
+				Error::<T>::InvalidInputBadLength
+			);
+			Self::remove_mrenclave_from_whitelist_by_raw_data(
+				&mr_enclave,
+				&mr_signer,
+				&isv_prod_id,
+				&isv_svn,
+			)?;
 			Ok(())
 		}
 
-		#[weight = T::WeightInfo::remove_mrenclave_by_index()]
-		fn remove_mrenclave_by_index(origin, index: u32) -> dispatch::DispatchResult {
+		#[pallet::weight(T::WeightInfo::remove_mrenclave_by_index())]
+		pub fn remove_mrenclave_by_index(origin: OriginFor<T>, index: u32) -> DispatchResult {
 			ensure_root(origin)?;
 			Self::remove_mrenclave_from_whitelist_by_index(index as usize)?;
 			Ok(())
@@ -597,7 +968,20 @@ decl_module! {
 	}
 }
 
-impl<T: Config> Module<T> {
+impl<T: Config> From<AttestationError> for Error<T> {
+	fn from(err: AttestationError) -> Self {
+		match err {
+			AttestationError::InvalidIASSigningCert => Self::InvalidIASSigningCert,
+			AttestationError::InvalidReport => Self::InvalidInput,
+			AttestationError::InvalidQuoteStatus => Self::InvalidQuoteStatus,
+			AttestationError::BadIASReport => Self::BadIASReport,
+			AttestationError::OutdatedIASReport => Self::OutdatedIASReport,
+			AttestationError::UnknownQuoteBodyFormat => Self::UnknownQuoteBodyFormat,
+		}
+	}
+}
+
+impl<T: Config> Pallet<T> {
 	pub fn account_id() -> T::AccountId {
 		PALLET_ID.into_account()
 	}
@@ -611,11 +995,11 @@ impl<T: Config> Module<T> {
 		match worker_info.state {
 			WorkerStateEnum::Mining(_) => {
 				worker_info.state = WorkerStateEnum::MiningStopping;
-				Self::deposit_event(RawEvent::WorkerStateUpdated(stash.clone()));
+				Self::deposit_event(Event::WorkerStateUpdated(stash.clone()));
 			}
 			WorkerStateEnum::MiningPending => {
 				worker_info.state = WorkerStateEnum::Free;
-				Self::deposit_event(RawEvent::WorkerStateUpdated(stash.clone()));
+				Self::deposit_event(Event::WorkerStateUpdated(stash.clone()));
 			}
 			WorkerStateEnum::Free | WorkerStateEnum::MiningStopping => return Ok(()),
 			_ => return Err(Error::<T>::InvalidState.into()),
@@ -638,12 +1022,12 @@ impl<T: Config> Module<T> {
 					stats_delta.num_power -= score.overall_score as i32;
 				}
 				// Set the state to Free
-				info.last_updated = T::UnixTime::now().as_millis().saturated_into::<u64>();
+				info.last_updated = <T as Config>::UnixTime::now().as_millis().saturated_into::<u64>();
 				info.state = WorkerStateEnum::Free;
 				WorkerState::<T>::insert(&stash, info);
 				// MinerStopped event
 				let round = Round::<T>::get().round;
-				Self::deposit_event(RawEvent::MinerStopped(round, stash.clone()));
+				Self::deposit_event(Event::MinerStopped(round, stash.clone()));
 				// TODO: slash?
 				return true;
 			}
@@ -678,31 +1062,50 @@ impl<T: Config> Module<T> {
 		Heartbeats::<T>::remove_all(None);
 	}
 
-	/// Slashes a worker and put it offline by force
+	/// Applies the undisputed offences due at `now`
+	fn apply_offences(now: T::BlockNumber, round: u32) {
+		for (stash, block_num) in PendingOffences::<T>::take(now) {
+			// Skip the disputed ones
+			if let Some(record) = Offences::<T>::take(&stash, block_num) {
+				Self::slash_offence(&stash, block_num, &record.reporter, round);
+			}
+		}
+	}
+
+	/// Slashes a miner for a missed challenge and puts it offline by force
 	///
-	/// The `stash` account will be slashed by 100 FIRE, and the `reporter` account will earn half
-	/// as a reward. This method ensures no worker will be slashed twice.
-	fn slash_offline(
+	/// The stake backing `stash` is slashed by `OffenceSlashBase`, doubled by every following
+	/// offence in the same slashing span and capped at `OffenceSlashMax`. A span ends after
+	/// `OFFENCE_SPAN_ROUNDS` rounds without an offence. On top of the stake, the payout account
+	/// loses `OfflineOffenseSlash` FIRE and the `reporter` earns `OfflineReportReward`.
+	fn slash_offence(
 		stash: &T::AccountId,
+		block_num: T::BlockNumber,
 		reporter: &T::AccountId,
-		machine_id: &Vec<u8>,
-	) -> dispatch::DispatchResult {
-		// We have to kick the worker by force to avoid double slash
-		PendingExitingDelta::mutate(|stats_delta| Self::kick_worker(stash, stats_delta));
+		round: u32,
+	) {
+		// Kick the worker by force because it's offline
+		PendingExitingDelta::<T>::mutate(|stats_delta| Self::kick_worker(stash, stats_delta));
 
-		// TODO.kevin:
-		// Self::push_message(SystemEvent::WorkerUnregistered(
-		// 	stash.clone(),
-		// 	machine_id.clone(),
-		// ));
+		let span = SlashingSpans::<T>::mutate(stash, |span| {
+			if span.offences > 0 && round > span.last_round + OFFENCE_SPAN_ROUNDS {
+				span.offences = 0;
+			}
+			span.offences += 1;
+			span.last_round = round;
+			span.clone()
+		});
+		let fraction = offence_slash_fraction(
+			span.offences,
+			T::OffenceSlashBase::get(),
+			T::OffenceSlashMax::get(),
+		);
+		let slashed = T::OnOffence::on_offence(stash, fraction);
 
 		// Assume ensure!(StashState::<T>::contains_key(&stash));
 		let payout = StashState::<T>::get(&stash).payout_prefs.target;
 		let lost_amount = T::OfflineOffenseSlash::get();
 		let win_amount = T::OfflineReportReward::get();
-		// TODO: what if the worker suddently change its payout address?
-		// Not necessary a problem on PoC-3 testnet, because it's unwise to switch the payout
-		// address in anyway. On mainnet, we should slash the stake instead.
 		let to_sub = Self::try_sub_fire(&payout, lost_amount);
 		Self::add_fire(reporter, win_amount);
 
@@ -714,14 +1117,19 @@ impl<T: Config> Module<T> {
 		};
 		RoundWorkerStats::<T>::insert(&stash, worker_state);
 
-		Self::deposit_event(RawEvent::Slash(
+		Self::deposit_event(Event::Slash(
 			stash.clone(),
-			payout.clone(),
+			payout,
 			lost_amount,
 			reporter.clone(),
 			win_amount,
 		));
-		Ok(())
+		Self::deposit_event(Event::OffenceSlashed(
+			stash.clone(),
+			block_num,
+			fraction,
+			slashed,
+		));
 	}
 
 	fn extend_mrenclave(
@@ -744,15 +1152,15 @@ impl<T: Config> Module<T> {
 		isv_prod_id: &[u8],
 		isv_svn: &[u8],
 	) -> dispatch::DispatchResult {
-		let mut whitelist = MREnclaveWhitelist::get();
+		let mut whitelist = MREnclaveWhitelist::<T>::get();
 		let white_mrenclave = Self::extend_mrenclave(mr_enclave, mr_signer, isv_prod_id, isv_svn);
 		ensure!(
 			!whitelist.contains(&white_mrenclave),
 			Error::<T>::MREnclaveAlreadyExist
 		);
 		whitelist.push(white_mrenclave.clone());
-		MREnclaveWhitelist::put(whitelist);
-		Self::deposit_event(RawEvent::WhitelistAdded(white_mrenclave));
+		MREnclaveWhitelist::<T>::put(whitelist);
+		Self::deposit_event(Event::WhitelistAdded(white_mrenclave));
 		Ok(())
 	}
 
@@ -762,7 +1170,7 @@ impl<T: Config> Module<T> {
 		isv_prod_id: &[u8],
 		isv_svn: &[u8],
 	) -> dispatch::DispatchResult {
-		let mut whitelist = MREnclaveWhitelist::get();
+		let mut whitelist = MREnclaveWhitelist::<T>::get();
 		let t_mrenclave = Self::extend_mrenclave(mr_enclave, mr_signer, isv_prod_id, isv_svn);
 		ensure!(
 			whitelist.contains(&t_mrenclave),
@@ -775,18 +1183,18 @@ impl<T: Config> Module<T> {
 				break;
 			}
 		}
-		MREnclaveWhitelist::put(whitelist);
-		Self::deposit_event(RawEvent::WhitelistRemoved(t_mrenclave));
+		MREnclaveWhitelist::<T>::put(whitelist);
+		Self::deposit_event(Event::WhitelistRemoved(t_mrenclave));
 		Ok(())
 	}
 
 	fn remove_mrenclave_from_whitelist_by_index(index: usize) -> dispatch::DispatchResult {
-		let mut whitelist = MREnclaveWhitelist::get();
+		let mut whitelist = MREnclaveWhitelist::<T>::get();
 		ensure!(whitelist.len() > index, Error::<T>::WrongWhitelistIndex);
 		let t_mrenclave = whitelist[index].clone();
 		whitelist.remove(index);
-		MREnclaveWhitelist::put(&whitelist);
-		Self::deposit_event(RawEvent::WhitelistRemoved(t_mrenclave));
+		MREnclaveWhitelist::<T>::put(&whitelist);
+		Self::deposit_event(Event::WhitelistRemoved(t_mrenclave));
 		Ok(())
 	}
 
@@ -795,14 +1203,14 @@ impl<T: Config> Module<T> {
 	/// Shall call this function only when the new round have started.
 	fn update_round_stats(round: u32, online_workers: u32, compute_workers: u32, total_power: u32) {
 		if round >= ROUND_STATS_TO_KEEP {
-			RoundStatsHistory::remove(round - ROUND_STATS_TO_KEEP);
+			RoundStatsHistory::<T>::remove(round - ROUND_STATS_TO_KEEP);
 		}
-		let online_target = TargetOnlineRewardCount::get();
+		let online_target = TargetOnlineRewardCount::<T>::get();
 		let frac_target_online_reward = Self::clipped_target_number(online_target, online_workers);
 		let frac_target_compute_reward =
-			Self::clipped_target_number(TargetComputeRewardCount::get(), compute_workers);
+			Self::clipped_target_number(TargetComputeRewardCount::<T>::get(), compute_workers);
 
-		RoundStatsHistory::insert(
+		RoundStatsHistory::<T>::insert(
 			round,
 			RoundStats {
 				round,
@@ -847,7 +1255,7 @@ impl<T: Config> Module<T> {
 					if let Some(ref score) = worker_info.score {
 						power_delta += score.overall_score as i32;
 					}
-					Self::deposit_event(RawEvent::MinerStarted(new_round, account.clone()));
+					Self::deposit_event(Event::MinerStarted(new_round, account.clone()));
 					updated = true;
 				}
 				WorkerStateEnum::MiningStopping => {
@@ -856,7 +1264,7 @@ impl<T: Config> Module<T> {
 					if let Some(ref score) = worker_info.score {
 						power_delta -= score.overall_score as i32;
 					}
-					Self::deposit_event(RawEvent::MinerStopped(new_round, account.clone()));
+					Self::deposit_event(Event::MinerStopped(new_round, account.clone()));
 					updated = true;
 				}
 				_ => {}
@@ -864,21 +1272,21 @@ impl<T: Config> Module<T> {
 			// TODO: slash
 			if updated {
 				WorkerState::<T>::insert(&account, worker_info);
-				Self::deposit_event(RawEvent::WorkerStateUpdated(account.clone()));
+				Self::deposit_event(Event::WorkerStateUpdated(account.clone()));
 			}
 		}
 		// Handle PendingExitingDelta
-		let exit_delta = PendingExitingDelta::take();
+		let exit_delta = PendingExitingDelta::<T>::take();
 		delta += exit_delta.num_worker;
 		power_delta += exit_delta.num_power;
 		// New stats
-		let new_online = (OnlineWorkers::get() as i32 + delta) as u32;
-		OnlineWorkers::put(new_online);
-		let new_total_power = ((TotalPower::get() as i32) + power_delta) as u32;
-		TotalPower::put(new_total_power);
+		let new_online = (OnlineWorkers::<T>::get() as i32 + delta) as u32;
+		OnlineWorkers::<T>::put(new_online);
+		let new_total_power = ((TotalPower::<T>::get() as i32) + power_delta) as u32;
+		TotalPower::<T>::put(new_total_power);
 		// Computation tasks
-		let compute_workers = cmp::min(new_online, TargetVirtualTaskCount::get());
-		ComputeWorkers::put(compute_workers);
+		let compute_workers = cmp::min(new_online, TargetVirtualTaskCount::<T>::get());
+		ComputeWorkers::<T>::put(compute_workers);
 
 		// Start new round
 		Self::clear_dirty();
@@ -888,7 +1296,7 @@ impl<T: Config> Module<T> {
 		});
 		Self::update_round_stats(new_round, new_online, compute_workers, new_total_power);
 		RoundWorkerStats::<T>::remove_all(None);
-		Self::deposit_event(RawEvent::NewMiningRound(new_round));
+		Self::deposit_event(Event::NewMiningRound(new_round));
 	}
 
 	fn handle_block_reward(now: T::BlockNumber, round: &RoundInfo<T::BlockNumber>) {
@@ -898,9 +1306,9 @@ impl<T: Config> Module<T> {
 			BlockRewardSeeds::<T>::remove(now - slash_window);
 		}
 		// Generate the seed and targets
-		let seed_hash = T::Randomness::random(RANDOMNESS_SUBJECT).0;
+		let seed_hash = <T as Config>::Randomness::random(RANDOMNESS_SUBJECT).0;
 		let seed: U256 = AsRef::<[u8]>::as_ref(&seed_hash).into();
-		let round_stats = RoundStatsHistory::get(round.round);
+		let round_stats = RoundStatsHistory::<T>::get(round.round);
 		let seed_info = BlockRewardInfo {
 			seed,
 			online_target: {
@@ -944,9 +1352,16 @@ impl<T: Config> Module<T> {
 			let now = System::<T>::block_number();
 			let reward_window = RewardWindow::<T>::get();
 			if claiming_block + reward_window < now {
-				Self::deposit_event(RawEvent::PayoutMissed(stash.clone(), payout_target.clone()));
+				Self::deposit_event(Event::PayoutMissed(stash.clone(), payout_target.clone()));
 				return;
 			}
+			// Only pay for the challenges the worker really hit
+			let (online_hit, compute_hit) = heartbeat_challenge(
+				worker_info.pubkey.as_slice(),
+				&BlockRewardSeeds::<T>::get(claiming_block),
+			);
+			let claim_online = claim_online && online_hit;
+			let claim_compute = claim_compute && compute_hit;
 			if claim_online || claim_compute {
 				let round_stats = Self::round_stats_at(claiming_block);
 				if round_stats.online_workers == 0 {
@@ -963,7 +1378,7 @@ impl<T: Config> Module<T> {
 						round_stats.online_workers,
 					);
 					let coin_reward =
-						Self::payout(online, payout_target, PayoutReason::OnlineReward);
+						Self::payout(online, stash, payout_target, PayoutReason::OnlineReward);
 					let prev = RoundWorkerStats::<T>::get(&stash);
 					let worker_state = StashWorkerStats {
 						slash: prev.slash,
@@ -980,7 +1395,7 @@ impl<T: Config> Module<T> {
 						round_stats.compute_workers,
 					);
 					let coin_reward =
-						Self::payout(compute, payout_target, PayoutReason::ComputeReward);
+						Self::payout(compute, stash, payout_target, PayoutReason::ComputeReward);
 					let prev = RoundWorkerStats::<T>::get(&stash);
 					let worker_state = StashWorkerStats {
 						slash: prev.slash,
@@ -994,7 +1409,6 @@ impl<T: Config> Module<T> {
 					PayoutComputeReward::<T>::mutate(payout_target, |x| *x += 1);
 				}
 			}
-			// TODO: do we need to check xor threshold?
 		}
 	}
 
@@ -1035,7 +1449,7 @@ impl<T: Config> Module<T> {
 		} else {
 			current_round.round
 		};
-		RoundStatsHistory::get(round)
+		RoundStatsHistory::<T>::get(round)
 	}
 
 	/// Calculates the adjusted online reward for a specific miner
@@ -1094,15 +1508,26 @@ impl<T: Config> Module<T> {
 		reward
 	}
 
-	/// Actually pays out the reward
-	fn payout(value: BalanceOf<T>, target: &T::AccountId, reason: PayoutReason) -> BalanceOf<T> {
+	/// Actually pays out the reward of `stash`, sharing `StakerRewardPercentage` of it with the
+	/// stakers of the miner
+	fn payout(
+		value: BalanceOf<T>,
+		stash: &T::AccountId,
+		target: &T::AccountId,
+		reason: PayoutReason,
+	) -> BalanceOf<T> {
 		// Retion the reward and the treasury deposit
 		let coins = T::TEECurrency::issue(value);
 		let (coin_reward, coin_treasury) =
 			coins.ration(T::RewardRation::get(), T::TreasuryRation::get());
+		let total_reward = coin_reward.peek();
+		// The part the stakers can't take goes back to the miner
+		let (coin_stakers, coin_reward) =
+			coin_reward.split(T::StakerRewardPercentage::get() * total_reward);
+		let coin_reward = coin_reward.merge(T::OnReward::on_reward(stash, coin_stakers));
 		// Payout!
 		// TODO: in real => T::TEECurrency::resolve_creating(payout_target, coin_reward);
-		Self::deposit_event(RawEvent::PayoutReward(
+		Self::deposit_event(Event::PayoutReward(
 			target.clone(),
 			coin_reward.peek(),
 			coin_treasury.peek(),
@@ -1110,7 +1535,7 @@ impl<T: Config> Module<T> {
 		));
 		Self::add_fire(&target, coin_reward.peek());
 		T::Treasury::on_unbalanced(coin_treasury);
-		coin_reward.peek()
+		total_reward
 	}
 
 	fn add_fire(dest: &T::AccountId, amount: BalanceOf<T>) {
@@ -1130,29 +1555,13 @@ impl<T: Config> Module<T> {
 	}
 }
 
-impl<T: Config> MessageOriginInfo for Module<T> {
+impl<T: Config> MessageOriginInfo for Pallet<T> {
 	type Config = T;
 }
 
-impl<T: Config> Module<T> {
-	pub fn on_transfer_message_received(message: &Message) -> DispatchResult {
-		const CONTRACT_ID: u32 = 2;
-
-		if message.sender != MessageOrigin::native_contract(CONTRACT_ID) {
-			return Err(Error::<T>::NotAllowed)?;
-		}
-
-		let data: BalanceTransfer<T::AccountId, BalanceOf<T>> =
-			message.decode_payload().ok_or(Error::<T>::InvalidInput)?;
-
-		// Release funds
-		T::TEECurrency::transfer(&Self::account_id(), &data.dest, data.amount, AllowDeath)
-			.map_err(|_| Error::<T>::CannotWithdraw)?;
-		Ok(())
-	}
-
+impl<T: Config> Pallet<T> {
 	pub fn on_worker_message_received(message: &Message) -> DispatchResult {
-		let _worker = match &message.sender {
+		let worker = match &message.sender {
 			MessageOrigin::Worker(worker) => worker,
 			_ => return Err(Error::<T>::NotAllowed.into()),
 		};
@@ -1166,7 +1575,8 @@ impl<T: Config> Module<T> {
 				claim_online,
 				claim_compute,
 			} => {
-				let stash = MachineOwner::<T>::get(&machine_id);
+				let stash = Self::heartbeat_miner(worker, &machine_id)
+					.ok_or(Error::<T>::InvalidState)?;
 				let stash_info = StashState::<T>::get(&stash);
 				let worker_info = WorkerState::<T>::get(&stash);
 				if worker_info.state == WorkerStateEnum::<_>::Empty {
@@ -1190,6 +1600,14 @@ impl<T: Config> Module<T> {
 		}
 		Ok(())
 	}
+
+	/// Resolves the miner (stash) a heartbeat is submitted for.
+	///
+	/// Workers are bound to miners by pubkey in `pallet_mining`. The heartbeats still carry the
+	/// machine id, which is the only identity of the legacy workers without a valid pubkey.
+	fn heartbeat_miner(worker: &WorkerPublicKey, machine_id: &[u8]) -> Option<T::AccountId> {
+		mining::WorkerBindings::<T>::get(worker).or_else(|| MachineOwner::<T>::get(machine_id))
+	}
 }
 
 fn calc_overall_score(features: &Vec<u32>) -> Result<u32, ()> {
@@ -1209,9 +1627,23 @@ fn u256_target(m: u64, n: u64) -> U256 {
 	U256::MAX / n * m
 }
 
-fn check_pubkey_hit_target(raw_pubkey: &[u8], reward_info: &BlockRewardInfo) -> bool {
+/// Derives the heartbeat challenge of a worker from the reward seed of a block.
+///
+/// Returns whether the worker is challenged to claim the online and the compute reward. The
+/// compute reward also requires the worker to be elected in the round, which is decided offchain.
+fn heartbeat_challenge(raw_pubkey: &[u8], reward_info: &BlockRewardInfo) -> (bool, bool) {
 	let pkh = crate::hashing::blake2_256(raw_pubkey);
 	let id: U256 = pkh.into();
 	let x = id ^ reward_info.seed;
-	x <= reward_info.online_target
+	(
+		x <= reward_info.online_target,
+		x <= reward_info.compute_target,
+	)
+}
+
+/// The fraction of the stake to slash for the `offences`-th offence in a slashing span
+fn offence_slash_fraction(offences: u32, base: Permill, max: Permill) -> Permill {
+	let factor = 1u32.checked_shl(offences.saturating_sub(1)).unwrap_or(u32::MAX);
+	let parts = base.deconstruct().saturating_mul(factor);
+	cmp::min(Permill::from_parts(parts), max)
 }
@@ -0,0 +1,530 @@
+use codec::Encode;
+use frame_support::{
+	assert_noop, assert_ok,
+	migration::{put_storage_value, storage_key_iter},
+	traits::{OnFinalize, OnInitialize, OnRuntimeUpgrade},
+	Blake2_128Concat, StorageHasher,
+};
+use sp_core::{ecdsa, Pair, U256};
+use sp_runtime::Permill;
+use sp_std::convert::TryFrom;
+
+use super::{
+	migrations::{MigrateToNewPallets, MIGRATION_BATCH},
+	mining,
+	mock::*,
+	offence_slash_fraction, registry, BindTopic, BlockRewardInfo, BlockRewardSeeds, Error, Fire2,
+	Heartbeats, LastWorkerActivity, MREnclaveWhitelist, MachineOwner, Message, MessageOrigin,
+	MigratedToNewPallets, Offences, PayoutPrefs, PendingUpdate, RewardWindow, Score, SignedMessage,
+	SlashWindow, SlashingSpans, Stash, StashInfo, StashState, WorkerInfo, WorkerPublicKey,
+	WorkerReportEvent, WorkerState, WorkerStateEnum,
+};
+
+const STASH: u64 = 1;
+const CONTROLLER: u64 = 2;
+
+fn worker_pubkey() -> WorkerPublicKey {
+	WorkerPublicKey::try_from(&[2u8; 33][..]).unwrap()
+}
+
+fn worker_info(state: WorkerStateEnum<BlockNumber>) -> WorkerInfo<BlockNumber> {
+	WorkerInfo {
+		machine_id: b"machine".to_vec(),
+		pubkey: worker_pubkey().as_ref().to_vec(),
+		last_updated: 5000,
+		state,
+		score: Some(Score {
+			overall_score: 100,
+			features: vec![1, 4],
+		}),
+		confidence_level: 128u8,
+		runtime_version: 1,
+	}
+}
+
+#[test]
+fn test_set_stash() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Phala::set_stash(Origin::signed(STASH), CONTROLLER));
+		assert_eq!(Stash::<Test>::get(CONTROLLER), STASH);
+		assert_eq!(StashState::<Test>::get(STASH).controller, CONTROLLER);
+		// A controller can't be paired twice
+		assert_noop!(
+			Phala::set_stash(Origin::signed(3), CONTROLLER),
+			Error::<Test>::AlreadyPaired
+		);
+		// Update the payout prefs by the controller
+		assert_noop!(
+			Phala::set_payout_prefs(Origin::signed(STASH), Some(10), None),
+			Error::<Test>::NotController
+		);
+		assert_noop!(
+			Phala::set_payout_prefs(Origin::signed(CONTROLLER), Some(101), None),
+			Error::<Test>::InvalidCommission
+		);
+		assert_ok!(Phala::set_payout_prefs(
+			Origin::signed(CONTROLLER),
+			Some(10),
+			Some(3)
+		));
+		let prefs = StashState::<Test>::get(STASH).payout_prefs;
+		assert_eq!(prefs.commission, 10);
+		assert_eq!(prefs.target, 3);
+	});
+}
+
+#[test]
+fn test_mining_intention() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Phala::set_stash(Origin::signed(STASH), CONTROLLER));
+		WorkerState::<Test>::insert(STASH, worker_info(WorkerStateEnum::Free));
+		assert_ok!(Phala::start_mining_intention(Origin::signed(CONTROLLER)));
+		assert_eq!(
+			WorkerState::<Test>::get(STASH).state,
+			WorkerStateEnum::MiningPending
+		);
+		assert_eq!(PendingUpdate::<Test>::get(), vec![STASH]);
+		assert_ok!(Phala::stop_mining_intention(Origin::signed(CONTROLLER)));
+		assert_eq!(WorkerState::<Test>::get(STASH).state, WorkerStateEnum::Free);
+	});
+}
+
+#[test]
+fn test_whitelist() {
+	new_test_ext().execute_with(|| {
+		let mr_enclave = vec![1u8; 32];
+		let mr_signer = vec![2u8; 32];
+		assert_noop!(
+			Phala::add_mrenclave(
+				Origin::root(),
+				vec![1u8; 31],
+				mr_signer.clone(),
+				vec![0, 0],
+				vec![0, 0]
+			),
+			Error::<Test>::InvalidInputBadLength
+		);
+		assert_ok!(Phala::add_mrenclave(
+			Origin::root(),
+			mr_enclave.clone(),
+			mr_signer.clone(),
+			vec![0, 0],
+			vec![0, 0]
+		));
+		assert_eq!(MREnclaveWhitelist::<Test>::get().len(), 1);
+		assert_noop!(
+			Phala::add_mrenclave(
+				Origin::root(),
+				mr_enclave.clone(),
+				mr_signer.clone(),
+				vec![0, 0],
+				vec![0, 0]
+			),
+			Error::<Test>::MREnclaveAlreadyExist
+		);
+		assert_noop!(
+			Phala::remove_mrenclave_by_index(Origin::root(), 1),
+			Error::<Test>::WrongWhitelistIndex
+		);
+		assert_ok!(Phala::remove_mrenclave_by_raw_data(
+			Origin::root(),
+			mr_enclave,
+			mr_signer,
+			vec![0, 0],
+			vec![0, 0]
+		));
+		assert!(MREnclaveWhitelist::<Test>::get().is_empty());
+	});
+}
+
+#[test]
+fn test_force_set_window() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(100);
+		RewardWindow::<Test>::put(8);
+		SlashWindow::<Test>::put(40);
+		for i in 60..=100 {
+			BlockRewardSeeds::<Test>::insert(i, BlockRewardInfo::default());
+		}
+		assert_noop!(
+			Phala::force_set_window(Origin::root(), Some(10), Some(9), 31),
+			Error::<Test>::InvalidInput
+		);
+		assert_noop!(
+			Phala::force_set_window(Origin::root(), None, Some(8), 31),
+			Error::<Test>::BadWitness
+		);
+		assert_ok!(Phala::force_set_window(Origin::root(), None, Some(8), 32));
+		assert_eq!(SlashWindow::<Test>::get(), 8);
+		// (now - 40, now - 8] is cleaned up
+		assert!(!BlockRewardSeeds::<Test>::contains_key(61));
+		assert!(!BlockRewardSeeds::<Test>::contains_key(92));
+		assert!(BlockRewardSeeds::<Test>::contains_key(93));
+	});
+}
+
+#[test]
+fn test_heartbeat_bound_worker() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(10);
+		assert_ok!(Phala::set_stash(Origin::signed(STASH), CONTROLLER));
+		WorkerState::<Test>::insert(STASH, worker_info(WorkerStateEnum::Mining(1)));
+		let heartbeat = WorkerReportEvent::Heartbeat {
+			machine_id: b"unknown".to_vec(),
+			block_num: 9,
+			claim_online: false,
+			claim_compute: false,
+		};
+		let message = Message::new(
+			MessageOrigin::Worker(worker_pubkey()),
+			WorkerReportEvent::TOPIC.to_vec(),
+			heartbeat.encode(),
+		);
+		// Neither bound nor known by the machine id
+		assert_noop!(
+			Phala::on_worker_message_received(&message),
+			Error::<Test>::InvalidState
+		);
+		mining::WorkerBindings::<Test>::insert(worker_pubkey(), STASH);
+		assert_ok!(Phala::on_worker_message_received(&message));
+		assert_eq!(Heartbeats::<Test>::get(STASH), 1);
+		assert_eq!(LastWorkerActivity::<Test>::get(STASH), 9);
+	});
+}
+
+#[test]
+fn test_heartbeat_legacy_machine_id() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(10);
+		assert_ok!(Phala::set_stash(Origin::signed(STASH), CONTROLLER));
+		WorkerState::<Test>::insert(STASH, worker_info(WorkerStateEnum::Mining(1)));
+		MachineOwner::<Test>::insert(b"machine".to_vec(), STASH);
+		let heartbeat = WorkerReportEvent::Heartbeat {
+			machine_id: b"machine".to_vec(),
+			block_num: 9,
+			claim_online: false,
+			claim_compute: false,
+		};
+		let message = Message::new(
+			MessageOrigin::Worker(worker_pubkey()),
+			WorkerReportEvent::TOPIC.to_vec(),
+			heartbeat.encode(),
+		);
+		assert_ok!(Phala::on_worker_message_received(&message));
+		assert_eq!(Heartbeats::<Test>::get(STASH), 1);
+	});
+}
+
+#[test]
+fn test_migrate_to_new_pallets() {
+	new_test_ext().execute_with(|| {
+		let stash_key = Blake2_128Concat::hash(&STASH.encode());
+		let controller_key = Blake2_128Concat::hash(&CONTROLLER.encode());
+		let machine_key = Blake2_128Concat::hash(&b"machine".to_vec().encode());
+		put_storage_value(
+			b"Phala",
+			b"StashState",
+			&stash_key,
+			StashInfo {
+				controller: CONTROLLER,
+				payout_prefs: PayoutPrefs {
+					commission: 5,
+					target: 3u64,
+				},
+			},
+		);
+		put_storage_value(b"Phala", b"Stash", &controller_key, STASH);
+		put_storage_value(b"Phala", b"MachineOwner", &machine_key, STASH);
+		put_storage_value(b"Phala", b"MREnclaveWhitelist", &[], vec![vec![1u8; 68]]);
+		WorkerState::<Test>::insert(STASH, worker_info(WorkerStateEnum::Free));
+		// A legacy worker without a valid pubkey
+		let legacy_machine_key = Blake2_128Concat::hash(&b"legacy".to_vec().encode());
+		put_storage_value(b"Phala", b"MachineOwner", &legacy_machine_key, 3u64);
+		let mut legacy_info = worker_info(WorkerStateEnum::Free);
+		legacy_info.pubkey = vec![1u8; 32];
+		WorkerState::<Test>::insert(3, legacy_info);
+
+		MigrateToNewPallets::<Test>::on_runtime_upgrade();
+
+		assert!(MigratedToNewPallets::<Test>::get());
+		assert_eq!(Stash::<Test>::get(CONTROLLER), STASH);
+		let stash_info = StashState::<Test>::get(STASH);
+		assert_eq!(stash_info.controller, CONTROLLER);
+		assert_eq!(stash_info.payout_prefs.commission, 5);
+		// Only the machines of the workers that can't be bound are kept
+		assert_eq!(MachineOwner::<Test>::get(b"machine".to_vec()), None);
+		assert_eq!(MachineOwner::<Test>::get(b"legacy".to_vec()), Some(3));
+		assert_eq!(MREnclaveWhitelist::<Test>::get(), vec![vec![1u8; 68]]);
+		assert_eq!(
+			mining::WorkerBindings::<Test>::get(worker_pubkey()),
+			Some(STASH)
+		);
+		let registered = registry::Worker::<Test>::get(worker_pubkey()).unwrap();
+		assert_eq!(registered.last_updated, 5);
+		assert_eq!(registered.intial_score, Some(100));
+		// The legacy storage is drained
+		assert!(frame_support::migration::get_storage_value::<StashInfo<u64>>(
+			b"Phala",
+			b"StashState",
+			&stash_key
+		)
+		.is_none());
+		assert!(frame_support::migration::get_storage_value::<Vec<Vec<u8>>>(
+			b"Phala",
+			b"MREnclaveWhitelist",
+			&[]
+		)
+		.is_none());
+		// The worker state is kept
+		assert!(WorkerState::<Test>::contains_key(STASH));
+	});
+}
+
+#[test]
+fn test_migrate_to_new_pallets_in_batches() {
+	new_test_ext().execute_with(|| {
+		MigratedToNewPallets::<Test>::put(false);
+		for controller in 0..(MIGRATION_BATCH as u64 + 1) {
+			let key = Blake2_128Concat::hash(&(controller + 100).encode());
+			put_storage_value(b"Phala", b"Stash", &key, controller + 1000);
+		}
+		// A controller paired after the upgrade isn't overwritten
+		let key = Blake2_128Concat::hash(&100u64.encode());
+		Stash::<Test>::insert(100, 1);
+
+		MigrateToNewPallets::<Test>::on_runtime_upgrade();
+		assert!(!MigratedToNewPallets::<Test>::get());
+		let legacy = storage_key_iter::<u64, u64, Blake2_128Concat>(b"Phala", b"Stash");
+		assert_eq!(legacy.count(), 1);
+		assert_eq!(Stash::<Test>::get(100), 1);
+
+		// The rest is moved in the next block
+		Phala::on_initialize(2);
+		assert!(MigratedToNewPallets::<Test>::get());
+		assert_eq!(Stash::<Test>::iter().count() as u32, MIGRATION_BATCH + 1);
+		assert_eq!(Stash::<Test>::get(100), 1);
+		assert!(
+			frame_support::migration::get_storage_value::<u64>(b"Phala", b"Stash", &key).is_none()
+		);
+	});
+}
+
+fn worker_pair() -> ecdsa::Pair {
+	ecdsa::Pair::from_seed(&[1u8; 32])
+}
+
+/// Sets up a mining worker challenged at block 5, and moves to block 20
+fn setup_offline_worker(pubkey: &[u8]) {
+	System::set_block_number(20);
+	RewardWindow::<Test>::put(8);
+	SlashWindow::<Test>::put(40);
+	assert_ok!(Phala::set_stash(Origin::signed(STASH), CONTROLLER));
+	let mut info = worker_info(WorkerStateEnum::Mining(1));
+	info.pubkey = pubkey.to_vec();
+	WorkerState::<Test>::insert(STASH, info);
+	BlockRewardSeeds::<Test>::insert(
+		5,
+		BlockRewardInfo {
+			seed: U256::zero(),
+			online_target: U256::MAX,
+			compute_target: U256::MAX,
+		},
+	);
+}
+
+fn signed_heartbeat(pair: &ecdsa::Pair, block_num: u32) -> SignedMessage {
+	let heartbeat = WorkerReportEvent::Heartbeat {
+		machine_id: b"machine".to_vec(),
+		block_num,
+		claim_online: true,
+		claim_compute: false,
+	};
+	let mut message = SignedMessage {
+		message: Message::new(
+			MessageOrigin::Worker(pair.public()),
+			WorkerReportEvent::TOPIC.to_vec(),
+			heartbeat.encode(),
+		),
+		sequence: 0,
+		signature: Vec::new(),
+	};
+	message.signature = pair.sign(&message.data_be_signed()).0.to_vec();
+	message
+}
+
+#[test]
+fn test_report_offline() {
+	new_test_ext().execute_with(|| {
+		setup_offline_worker(worker_pubkey().as_ref());
+		// Not challenged at block 6
+		assert_noop!(
+			Phala::report_offline(Origin::signed(3), STASH, 6),
+			Error::<Test>::InvalidProof
+		);
+		// Still in the reward window
+		assert_noop!(
+			Phala::report_offline(Origin::signed(3), STASH, 15),
+			Error::<Test>::ReportedWorkerStillAlive
+		);
+		assert_ok!(Phala::report_offline(Origin::signed(3), STASH, 5));
+		assert_eq!(Offences::<Test>::get(STASH, 5).unwrap().reporter, 3);
+		assert_noop!(
+			Phala::report_offline(Origin::signed(4), STASH, 5),
+			Error::<Test>::DuplicateOffence
+		);
+		// Nothing happens during the dispute period
+		Phala::on_finalize(29);
+		assert!(slashed_stake().is_empty());
+		assert_eq!(
+			WorkerState::<Test>::get(STASH).state,
+			WorkerStateEnum::Mining(1)
+		);
+		// Applied after the dispute period
+		Phala::on_finalize(30);
+		assert_eq!(slashed_stake(), vec![(STASH, Permill::from_percent(1))]);
+		assert!(!Offences::<Test>::contains_key(STASH, 5));
+		assert_eq!(WorkerState::<Test>::get(STASH).state, WorkerStateEnum::Free);
+		assert_eq!(SlashingSpans::<Test>::get(STASH).offences, 1);
+		assert_eq!(Fire2::<Test>::get(3), OfflineReportReward::get());
+	});
+}
+
+#[test]
+fn test_dispute_offence() {
+	new_test_ext().execute_with(|| {
+		let pair = worker_pair();
+		setup_offline_worker(pair.public().as_ref());
+		assert_noop!(
+			Phala::dispute_offence(
+				Origin::signed(CONTROLLER),
+				STASH,
+				5,
+				signed_heartbeat(&pair, 5)
+			),
+			Error::<Test>::OffenceNotFound
+		);
+		assert_ok!(Phala::report_offline(Origin::signed(3), STASH, 5));
+		// The heartbeat to another challenge
+		assert_noop!(
+			Phala::dispute_offence(
+				Origin::signed(CONTROLLER),
+				STASH,
+				5,
+				signed_heartbeat(&pair, 6)
+			),
+			Error::<Test>::InvalidEvidence
+		);
+		// Not signed by the worker
+		let mut forged = signed_heartbeat(&ecdsa::Pair::from_seed(&[2u8; 32]), 5);
+		forged.message.sender = MessageOrigin::Worker(pair.public());
+		assert_noop!(
+			Phala::dispute_offence(Origin::signed(CONTROLLER), STASH, 5, forged),
+			registry::Error::<Test>::InvalidSignature
+		);
+		assert_ok!(Phala::dispute_offence(
+			Origin::signed(CONTROLLER),
+			STASH,
+			5,
+			signed_heartbeat(&pair, 5)
+		));
+		assert!(!Offences::<Test>::contains_key(STASH, 5));
+		// The disputed offence is not applied
+		Phala::on_finalize(30);
+		assert!(slashed_stake().is_empty());
+		assert_eq!(
+			WorkerState::<Test>::get(STASH).state,
+			WorkerStateEnum::Mining(1)
+		);
+	});
+}
+
+#[test]
+fn test_dispute_offence_in_challenge_window() {
+	new_test_ext().execute_with(|| {
+		let pair = worker_pair();
+		setup_offline_worker(pair.public().as_ref());
+		let challenge_all = BlockRewardInfo {
+			seed: U256::zero(),
+			online_target: U256::MAX,
+			compute_target: U256::MAX,
+		};
+		BlockRewardSeeds::<Test>::insert(9, challenge_all.clone());
+		BlockRewardSeeds::<Test>::insert(14, challenge_all);
+		assert_ok!(Phala::report_offline(Origin::signed(3), STASH, 5));
+		// Answered after the reward window of the missed challenge
+		assert_noop!(
+			Phala::dispute_offence(
+				Origin::signed(CONTROLLER),
+				STASH,
+				5,
+				signed_heartbeat(&pair, 14)
+			),
+			Error::<Test>::InvalidEvidence
+		);
+		// Answered a later challenge in the window
+		assert_ok!(Phala::dispute_offence(
+			Origin::signed(CONTROLLER),
+			STASH,
+			5,
+			signed_heartbeat(&pair, 9)
+		));
+		assert!(!Offences::<Test>::contains_key(STASH, 5));
+	});
+}
+
+#[test]
+fn test_graduated_slash() {
+	let base = Permill::from_percent(1);
+	let max = Permill::from_percent(10);
+	assert_eq!(offence_slash_fraction(1, base, max), Permill::from_percent(1));
+	assert_eq!(offence_slash_fraction(2, base, max), Permill::from_percent(2));
+	assert_eq!(offence_slash_fraction(4, base, max), Permill::from_percent(8));
+	assert_eq!(offence_slash_fraction(5, base, max), max);
+	assert_eq!(offence_slash_fraction(100, base, max), max);
+}
+
+#[test]
+fn test_slashing_span() {
+	new_test_ext().execute_with(|| {
+		setup_offline_worker(worker_pubkey().as_ref());
+		SlashingSpans::<Test>::insert(
+			STASH,
+			super::SlashingSpan {
+				offences: 2,
+				last_round: 0,
+			},
+		);
+		assert_ok!(Phala::report_offline(Origin::signed(3), STASH, 5));
+		Phala::on_finalize(30);
+		// The third offence in the span
+		assert_eq!(slashed_stake(), vec![(STASH, Permill::from_percent(4))]);
+		assert_eq!(SlashingSpans::<Test>::get(STASH).offences, 3);
+	});
+}
+
+#[test]
+fn test_unchallenged_claim_ignored() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(10);
+		RewardWindow::<Test>::put(8);
+		assert_ok!(Phala::set_stash(Origin::signed(STASH), CONTROLLER));
+		WorkerState::<Test>::insert(STASH, worker_info(WorkerStateEnum::Mining(1)));
+		mining::WorkerBindings::<Test>::insert(worker_pubkey(), STASH);
+		// The seed at block 9 challenges nobody
+		BlockRewardSeeds::<Test>::insert(9, BlockRewardInfo::default());
+		let heartbeat = WorkerReportEvent::Heartbeat {
+			machine_id: b"machine".to_vec(),
+			block_num: 9,
+			claim_online: true,
+			claim_compute: true,
+		};
+		let message = Message::new(
+			MessageOrigin::Worker(worker_pubkey()),
+			WorkerReportEvent::TOPIC.to_vec(),
+			heartbeat.encode(),
+		);
+		assert_ok!(Phala::on_worker_message_received(&message));
+		assert_eq!(Fire2::<Test>::get(STASH), 0);
+		assert_eq!(Heartbeats::<Test>::get(STASH), 1);
+	});
+}
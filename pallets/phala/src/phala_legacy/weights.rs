@@ -3,6 +3,15 @@
 //! THIS FILE WAS AUTO-GENERATED USING THE SUBSTRATE BENCHMARK CLI VERSION 2.0.0
 //! DATE: 2021-01-13, STEPS: [10, ], REPEAT: 2, LOW RANGE: [], HIGH RANGE: []
 //! EXECUTION: Some(Wasm), WASM-EXECUTION: Compiled, CHAIN: Some("dev"), DB CACHE: 128
+//!
+//! `report_offline`, `dispute_offence`, `apply_offence` and `force_set_window` are estimated from
+//! their storage accesses until regenerated with the benchmark CLI:
+//!
+//! ```bash
+//! ./target/release/phala-node benchmark --chain=dev --steps=50 --repeat=20 \
+//!     --pallet=pallet_phala --extrinsic='*' --execution=wasm --wasm-execution=compiled \
+//!     --output=./pallets/phala/src/phala_legacy/weights.rs
+//! ```
 
 #![allow(unused_parens)]
 #![allow(unused_imports)]
@@ -15,17 +24,12 @@ use sp_std::marker::PhantomData;
 
 /// Weight functions needed for pallet-phala.
 pub trait WeightInfo {
-	fn push_command() -> Weight;
 	fn set_stash() -> Weight;
 	fn set_payout_prefs() -> Weight;
-	fn register_worker() -> Weight;
-	fn force_register_worker() -> Weight;
 	fn force_set_contract_key() -> Weight;
 	fn start_mining_intention() -> Weight;
 	fn stop_mining_intention() -> Weight;
 	fn transfer_to_tee() -> Weight;
-	fn transfer_to_chain() -> Weight;
-	fn sync_worker_message() -> Weight;
 	fn force_next_round() -> Weight;
 	fn force_add_fire() -> Weight;
 	fn add_mrenclave() -> Weight;
@@ -33,16 +37,15 @@ pub trait WeightInfo {
 	fn remove_mrenclave_by_index() -> Weight;
 	fn force_set_virtual_tasks() -> Weight;
 	fn force_reset_fire() -> Weight;
+	fn report_offline() -> Weight;
+	fn dispute_offence() -> Weight;
+	fn apply_offence() -> Weight;
+	fn force_set_window(n: u32) -> Weight;
 }
 
 /// Weight functions for pallet_phala.
 pub struct SubstrateWeight<T>(PhantomData<T>);
 impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
-	fn push_command() -> Weight {
-		(54_000_000 as Weight)
-			.saturating_add(T::DbWeight::get().reads(1 as Weight))
-			.saturating_add(T::DbWeight::get().writes(1 as Weight))
-	}
 	fn set_stash() -> Weight {
 		(68_000_000 as Weight)
 			.saturating_add(T::DbWeight::get().reads(3 as Weight))
@@ -53,16 +56,6 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add(T::DbWeight::get().reads(2 as Weight))
 			.saturating_add(T::DbWeight::get().writes(1 as Weight))
 	}
-	fn register_worker() -> Weight {
-		(858_000_000 as Weight)
-			.saturating_add(T::DbWeight::get().reads(6 as Weight))
-			.saturating_add(T::DbWeight::get().writes(4 as Weight))
-	}
-	fn force_register_worker() -> Weight {
-		(115_000_000 as Weight)
-			.saturating_add(T::DbWeight::get().reads(5 as Weight))
-			.saturating_add(T::DbWeight::get().writes(4 as Weight))
-	}
 	fn force_set_contract_key() -> Weight {
 		(9_000_000 as Weight).saturating_add(T::DbWeight::get().writes(1 as Weight))
 	}
@@ -81,16 +74,6 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add(T::DbWeight::get().reads(1 as Weight))
 			.saturating_add(T::DbWeight::get().writes(1 as Weight))
 	}
-	fn transfer_to_chain() -> Weight {
-		(364_000_000 as Weight)
-			.saturating_add(T::DbWeight::get().reads(4 as Weight))
-			.saturating_add(T::DbWeight::get().writes(3 as Weight))
-	}
-	fn sync_worker_message() -> Weight {
-		(345_000_000 as Weight)
-			.saturating_add(T::DbWeight::get().reads(5 as Weight))
-			.saturating_add(T::DbWeight::get().writes(2 as Weight))
-	}
 	fn force_next_round() -> Weight {
 		(5_000_000 as Weight).saturating_add(T::DbWeight::get().writes(1 as Weight))
 	}
@@ -124,15 +107,33 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add(T::DbWeight::get().reads(1 as Weight))
 			.saturating_add(T::DbWeight::get().writes(1 as Weight))
 	}
+	fn report_offline() -> Weight {
+		(86_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(8 as Weight))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+	}
+	fn dispute_offence() -> Weight {
+		(118_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(6 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	fn apply_offence() -> Weight {
+		(64_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(9 as Weight))
+			.saturating_add(T::DbWeight::get().writes(9 as Weight))
+	}
+	fn force_set_window(n: u32) -> Weight {
+		(22_000_000 as Weight)
+			// Standard Error: 1_000
+			.saturating_add((2_000_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(n as Weight)))
+	}
 }
 
 // For backwards compatibility and tests
 impl WeightInfo for () {
-	fn push_command() -> Weight {
-		(54_000_000 as Weight)
-			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
-			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
-	}
 	fn set_stash() -> Weight {
 		(68_000_000 as Weight)
 			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
@@ -143,16 +144,6 @@ impl WeightInfo for () {
 			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
 			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
 	}
-	fn register_worker() -> Weight {
-		(858_000_000 as Weight)
-			.saturating_add(RocksDbWeight::get().reads(6 as Weight))
-			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
-	}
-	fn force_register_worker() -> Weight {
-		(115_000_000 as Weight)
-			.saturating_add(RocksDbWeight::get().reads(5 as Weight))
-			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
-	}
 	fn force_set_contract_key() -> Weight {
 		(9_000_000 as Weight).saturating_add(RocksDbWeight::get().writes(1 as Weight))
 	}
@@ -171,16 +162,6 @@ impl WeightInfo for () {
 			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
 			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
 	}
-	fn transfer_to_chain() -> Weight {
-		(364_000_000 as Weight)
-			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
-			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
-	}
-	fn sync_worker_message() -> Weight {
-		(345_000_000 as Weight)
-			.saturating_add(RocksDbWeight::get().reads(5 as Weight))
-			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
-	}
 	fn force_next_round() -> Weight {
 		(5_000_000 as Weight).saturating_add(RocksDbWeight::get().writes(1 as Weight))
 	}
@@ -214,4 +195,27 @@ impl WeightInfo for () {
 			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
 			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
 	}
+	fn report_offline() -> Weight {
+		(86_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(8 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+	}
+	fn dispute_offence() -> Weight {
+		(118_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(6 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn apply_offence() -> Weight {
+		(64_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(9 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(9 as Weight))
+	}
+	fn force_set_window(n: u32) -> Weight {
+		(22_000_000 as Weight)
+			// Standard Error: 1_000
+			.saturating_add((2_000_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes((1 as Weight).saturating_mul(n as Weight)))
+	}
 }
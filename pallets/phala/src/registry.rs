@@ -1,11 +1,10 @@
 /// Public key registry for workers and contracts.
 pub use self::pallet::*;
 
-// #[cfg(test)]
-// mod mock;
+#[cfg(test)]
+mod tests;
 
-// #[cfg(test)]
-// mod tests;
+pub mod migrations;
 
 // #[cfg(feature = "runtime-benchmarks")]
 // mod benchmarking;
@@ -13,10 +12,14 @@ pub use self::pallet::*;
 #[frame_support::pallet]
 pub mod pallet {
 	use codec::Encode;
-	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, traits::UnixTime};
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{Currency, EnsureOrigin, Randomness, ReservableCurrency, UnixTime},
+	};
 	use frame_system::pallet_prelude::*;
 	use sp_core::H256;
-	use sp_runtime::SaturatedConversion;
+	use sp_runtime::{traits::One, SaturatedConversion};
 	use sp_std::convert::TryFrom;
 	use sp_std::prelude::*;
 	use sp_std::vec;
@@ -25,23 +28,124 @@ pub mod pallet {
 	use crate::mq::MessageOriginInfo;
 
 	use phala_types::{
-		messaging::{bind_topic, Message, MessageOrigin, SignedMessage, SystemEvent},
-		ContractPublicKey, PRuntimeInfo, WorkerPublicKey,
+		benchmark::{self, Challenge, Sample},
+		messaging::{
+			bind_topic, ContractEvent, GatekeeperEvent, Message, MessageOrigin, SignedMessage,
+			SystemEvent,
+		},
+		CodeIndex, ContractPublicKey, EcdhPublicKey, GatekeeperRotation, PRuntimeInfo, WorkerKeyHandover,
+		WorkerPublicKey,
 	};
 
 	bind_topic!(RegistryEvent, b"^phala/registry/event");
 	#[derive(Encode, Decode, Clone, Debug)]
 	pub enum RegistryEvent {
-		BenchReport { start_time: u64, iterations: u64 },
+		/// The commitment to the outputs of a benchmark session started by
+		/// `SystemEvent::BenchStart`, answered by `SystemEvent::BenchSeed` a few blocks later.
+		BenchCommit {
+			iterations: u64,
+			root: benchmark::Hash,
+		},
+		/// The iterations sampled by the seed of `SystemEvent::BenchSeed`, with their proofs.
+		BenchReport { samples: Vec<Sample> },
+		/// The worker hands over its identity to a new key pair. The handover statement must be
+		/// signed by the old identity key.
+		KeyHandover {
+			handover: WorkerKeyHandover,
+			signature: Vec<u8>,
+		},
+		/// The public key of an instantiated contract, reported by a gatekeeper
+		ContractKey {
+			contract_id: H256,
+			pubkey: ContractPublicKey,
+		},
+		/// The ECDH public key of a secret topic, derived from the contract key and reported by a
+		/// gatekeeper
+		TopicKey {
+			topic: Vec<u8>,
+			pubkey: EcdhPublicKey,
+		},
 	}
 
 	#[pallet::config]
 	pub trait Config: frame_system::Config {
-		type Event: From<Event> + IsType<<Self as frame_system::Config>::Event>;
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
 
 		type UnixTime: UnixTime;
+
+		/// The source of the benchmark challenges
+		type Randomness: Randomness<Self::Hash, Self::BlockNumber>;
+
+		/// The origin allowed to add or remove gatekeepers
+		type GatekeeperOrigin: EnsureOrigin<Self::Origin>;
+
+		/// The origin allowed to set the admin of the native contracts
+		type ContractAdminOrigin: EnsureOrigin<Self::Origin>;
+
+		/// The max number of the gatekeepers
+		#[pallet::constant]
+		type MaxGatekeepers: Get<u32>;
+
+		/// The min number of the gatekeepers once the set is bootstrapped. A gatekeeper can't be
+		/// removed if the set would go below it.
+		#[pallet::constant]
+		type MinGatekeepers: Get<u32>;
+
+		/// The blocks between a gatekeeper set change and the rotation to the new set, left to the
+		/// gatekeepers to hand over the master key
+		#[pallet::constant]
+		type GatekeeperRotationDelay: Get<Self::BlockNumber>;
+
+		/// The currency to reserve the contract deposits
+		type Currency: ReservableCurrency<Self::AccountId>;
+
+		/// The deposit reserved from the deployer for each instantiated contract
+		#[pallet::constant]
+		type ContractDeposit: Get<BalanceOf<Self>>;
+
+		/// The max length of the init params of a contract
+		#[pallet::constant]
+		type MaxInitParamsLen: Get<u32>;
+
+		/// The max length of the salt to derive a contract id
+		#[pallet::constant]
+		type MaxSaltLen: Get<u32>;
+
+		/// Moves the states keyed by the worker identity in the other pallets after a key handover
+		type OnWorkerKeyRotated: OnWorkerKeyRotated;
+
+		/// Keeps the miners the workers are bound to by their operators
+		type WorkerBindings: WorkerBindings<Self::AccountId>;
+	}
+
+	/// Handler of the worker key handovers
+	pub trait OnWorkerKeyRotated {
+		fn on_worker_key_rotated(old_pubkey: &WorkerPublicKey, new_pubkey: &WorkerPublicKey);
 	}
 
+	impl OnWorkerKeyRotated for () {
+		fn on_worker_key_rotated(_old_pubkey: &WorkerPublicKey, _new_pubkey: &WorkerPublicKey) {}
+	}
+
+	/// Storage of the worker bindings, set by `register_worker` and `unbind`
+	pub trait WorkerBindings<AccountId> {
+		/// The miner the worker is bound to
+		fn bound_miner(worker: &WorkerPublicKey) -> Option<AccountId>;
+		fn bind(worker: &WorkerPublicKey, miner: &AccountId);
+		fn unbind(worker: &WorkerPublicKey);
+	}
+
+	impl<AccountId> WorkerBindings<AccountId> for () {
+		fn bound_miner(_worker: &WorkerPublicKey) -> Option<AccountId> {
+			None
+		}
+		fn bind(_worker: &WorkerPublicKey, _miner: &AccountId) {}
+		fn unbind(_worker: &WorkerPublicKey) {}
+	}
+
+	pub(crate) type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
 	pub struct Pallet<T>(_);
@@ -50,6 +154,10 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type Gatekeeper<T: Config> = StorageValue<_, Vec<WorkerPublicKey>, ValueQuery>;
 
+	/// The changed gatekeeper set, replacing `Gatekeeper` at the scheduled block
+	#[pallet::storage]
+	pub type NextGatekeeper<T: Config> = StorageValue<_, GatekeeperRotation<T::BlockNumber>>;
+
 	/// Mapping from worker pubkey to WorkerInfo
 	#[pallet::storage]
 	pub type Worker<T: Config> = StorageMap<_, Twox64Concat, WorkerPublicKey, WorkerInfo>;
@@ -58,14 +166,112 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type ContractKey<T> = StorageMap<_, Twox64Concat, H256, ContractPublicKey>;
 
+	/// The contracts instantiated by `instantiate_contract`
+	#[pallet::storage]
+	pub type Contracts<T: Config> = StorageMap<
+		_,
+		Twox64Concat,
+		H256,
+		ContractInfo<T::AccountId, BalanceOf<T>, T::BlockNumber>,
+	>;
+
+	/// The admin of each native contract, read by the contracts in pRuntime from the synced state
+	#[pallet::storage]
+	pub type ContractAdmin<T: Config> = StorageMap<_, Twox64Concat, u32, T::AccountId>;
+
 	/// Pubkey for secret topics.
 	#[pallet::storage]
 	pub type TopicKey<T> = StorageMap<_, Blake2_128Concat, Vec<u8>, Vec<u8>>;
 
+	/// Allowed pRuntime builds, each encoded as `mr_enclave ++ isv_prod_id ++ isv_svn ++ mr_signer`
+	#[pallet::storage]
+	pub type MREnclaveWhitelist<T> = StorageValue<_, Vec<Vec<u8>>, ValueQuery>;
+
+	/// The ongoing benchmark sessions of the workers
+	#[pallet::storage]
+	pub type BenchChallenge<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, BenchSession>;
+
+	/// The committed outputs of the ongoing benchmark sessions
+	#[pallet::storage]
+	pub type BenchCommitment<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, BenchCommit<T::BlockNumber>>;
+
+	/// The revealed outputs of the last accepted benchmark report of each worker, kept to be
+	/// disputed by `dispute_bench_sample`
+	#[pallet::storage]
+	pub type BenchAudits<T: Config> = StorageMap<_, Twox64Concat, WorkerPublicKey, BenchAudit>;
+
+	/// The workers to receive their benchmark seeds at the block
+	#[pallet::storage]
+	pub type BenchReveals<T: Config> =
+		StorageMap<_, Twox64Concat, T::BlockNumber, Vec<WorkerPublicKey>, ValueQuery>;
+
+	/// Whether `WorkerInfo::ecdh_pubkey` is migrated to `EcdhPublicKey`
+	#[pallet::storage]
+	pub type WorkerEcdhPubkeyMigrated<T> = StorageValue<_, bool, ValueQuery>;
+
+	/// Whether `ContractAdmin` is initialized on a chain launched before it existed
+	#[pallet::storage]
+	pub type ContractAdminsInitialized<T> = StorageValue<_, bool, ValueQuery>;
+
+	#[pallet::genesis_config]
+	pub struct GenesisConfig<T: Config> {
+		/// The initial admins of the native contracts
+		pub contract_admins: Vec<(u32, T::AccountId)>,
+	}
+
+	#[cfg(feature = "std")]
+	impl<T: Config> Default for GenesisConfig<T> {
+		fn default() -> Self {
+			Self {
+				contract_admins: Default::default(),
+			}
+		}
+	}
+
+	#[pallet::genesis_build]
+	impl<T: Config> GenesisBuild<T> for GenesisConfig<T> {
+		fn build(&self) {
+			for (contract_id, admin) in &self.contract_admins {
+				ContractAdmin::<T>::insert(contract_id, admin);
+			}
+		}
+	}
+
 	#[pallet::event]
+	#[pallet::metadata(
+		T::BlockNumber = "BlockNumber",
+		T::AccountId = "AccountId",
+		BalanceOf<T> = "Balance"
+	)]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
-	pub enum Event {
+	pub enum Event<T: Config> {
+		/// A gatekeeper is added to the next set. [pubkey]
 		GatekeeperAdded(WorkerPublicKey),
+		/// A gatekeeper is removed from the next set. [pubkey]
+		GatekeeperRemoved(WorkerPublicKey),
+		/// The next gatekeeper set will take effect at the block. [apply_at]
+		GatekeeperRotationScheduled(T::BlockNumber),
+		/// The next gatekeeper set took effect. [num_gatekeepers]
+		GatekeeperRotated(u32),
+		/// A worker rotated its identity key. [old_pubkey, new_pubkey]
+		WorkerKeyRotated(WorkerPublicKey, WorkerPublicKey),
+		/// The admin of a native contract is changed. [contract_id, admin]
+		ContractAdminChanged(u32, Option<T::AccountId>),
+		/// A contract is instantiated. [contract_id, deployer, deposit]
+		ContractInstantiated(H256, T::AccountId, BalanceOf<T>),
+		/// The public key of a contract is registered. [contract_id, pubkey]
+		ContractKeyRegistered(H256, ContractPublicKey),
+		/// The public key of a secret topic is registered. [topic, pubkey]
+		TopicKeyRegistered(Vec<u8>, EcdhPublicKey),
+		/// A worker is bound to the miner set as its operator. [pubkey, miner]
+		WorkerBound(WorkerPublicKey, T::AccountId),
+		/// A worker is unbound from its miner. [pubkey, miner]
+		WorkerUnbound(WorkerPublicKey, T::AccountId),
+		/// A wrong output is found in the benchmark report of a worker, which loses its score.
+		/// [pubkey, iteration]
+		BenchReportDisputed(WorkerPublicKey, u64),
 	}
 
 	#[pallet::error]
@@ -89,7 +295,45 @@ pub mod pallet {
 		InvalidRuntimeInfo,
 		InvalidInput,
 		InvalidBenchReport,
+		BenchReportNotFound,
+		BenchSampleNotDisputable,
 		WorkerNotFound,
+		InvalidKeyHandover,
+		// Gatekeeper management
+		DuplicateGatekeeper,
+		GatekeeperNotFound,
+		TooManyGatekeepers,
+		TooFewGatekeepers,
+		// Contract admin management
+		NotContractAdmin,
+		// Contract instantiation
+		DuplicateContract,
+		InitParamsTooLong,
+		SaltTooLong,
+		NotGatekeeper,
+		ContractKeyAlreadyRegistered,
+		TopicKeyAlreadyRegistered,
+		// Worker bindings
+		WorkerAlreadyBound,
+		WorkerNotBound,
+		NotWorkerOperator,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T>
+	where
+		T: crate::mq::Config,
+	{
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			let weight = match NextGatekeeper::<T>::get() {
+				Some(next) if next.apply_at <= now => {
+					Self::apply_gatekeeper_rotation(next.gatekeepers);
+					T::DbWeight::get().reads_writes(1, 3)
+				}
+				_ => T::DbWeight::get().reads(1),
+			};
+			weight.saturating_add(Self::reveal_bench_seeds(now))
+		}
 	}
 
 	#[pallet::call]
@@ -102,7 +346,7 @@ pub mod pallet {
 		pub fn force_register_worker(
 			origin: OriginFor<T>,
 			pubkey: WorkerPublicKey,
-			ecdh_pubkey: WorkerPublicKey,
+			ecdh_pubkey: EcdhPublicKey,
 		) -> DispatchResult {
 			ensure_root(origin)?;
 			let worker_info = WorkerInfo {
@@ -149,19 +393,168 @@ pub mod pallet {
 
 		/// Register a gatekeeper.
 		///
-		/// Must be called by the Root origin.
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		/// The gatekeeper joins the set at the next rotation, `GatekeeperRotationDelay` blocks
+		/// after the first pending change. Must be called by the `GatekeeperOrigin`.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
 		pub fn register_gatekeeper(
 			origin: OriginFor<T>,
 			gatekeeper: WorkerPublicKey,
 		) -> DispatchResult {
-			ensure_root(origin)?;
-			let mut gatekeepers = Gatekeeper::<T>::get();
-			if !gatekeepers.contains(&gatekeeper) {
-				gatekeepers.push(gatekeeper.clone());
-				Gatekeeper::<T>::put(gatekeepers);
-				Self::deposit_event(Event::GatekeeperAdded(gatekeeper));
-			}
+			T::GatekeeperOrigin::ensure_origin(origin)?;
+			let mut gatekeepers = Self::next_gatekeepers();
+			ensure!(
+				!gatekeepers.contains(&gatekeeper),
+				Error::<T>::DuplicateGatekeeper
+			);
+			ensure!(
+				(gatekeepers.len() as u32) < T::MaxGatekeepers::get(),
+				Error::<T>::TooManyGatekeepers
+			);
+			gatekeepers.push(gatekeeper.clone());
+			Self::schedule_gatekeeper_rotation(gatekeepers);
+			Self::deposit_event(Event::GatekeeperAdded(gatekeeper));
+			Ok(())
+		}
+
+		/// Unregister a gatekeeper.
+		///
+		/// The gatekeeper leaves the set at the next rotation. Must be called by the
+		/// `GatekeeperOrigin`.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn unregister_gatekeeper(
+			origin: OriginFor<T>,
+			gatekeeper: WorkerPublicKey,
+		) -> DispatchResult {
+			T::GatekeeperOrigin::ensure_origin(origin)?;
+			let mut gatekeepers = Self::next_gatekeepers();
+			let index = gatekeepers
+				.iter()
+				.position(|pubkey| pubkey == &gatekeeper)
+				.ok_or(Error::<T>::GatekeeperNotFound)?;
+			ensure!(
+				gatekeepers.len() as u32 > T::MinGatekeepers::get(),
+				Error::<T>::TooFewGatekeepers
+			);
+			gatekeepers.remove(index);
+			Self::schedule_gatekeeper_rotation(gatekeepers);
+			Self::deposit_event(Event::GatekeeperRemoved(gatekeeper));
+			Ok(())
+		}
+
+		/// Set or remove the admin of a native contract. Must be called by the
+		/// `ContractAdminOrigin`.
+		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		pub fn set_contract_admin(
+			origin: OriginFor<T>,
+			contract_id: u32,
+			admin: Option<T::AccountId>,
+		) -> DispatchResult {
+			T::ContractAdminOrigin::ensure_origin(origin)?;
+			ContractAdmin::<T>::set(contract_id, admin.clone());
+			Self::deposit_event(Event::ContractAdminChanged(contract_id, admin));
+			Ok(())
+		}
+
+		/// Hand over the admin of a native contract. Must be called by the current admin.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
+		pub fn transfer_contract_admin(
+			origin: OriginFor<T>,
+			contract_id: u32,
+			new_admin: T::AccountId,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(
+				ContractAdmin::<T>::get(contract_id).as_ref() == Some(&who),
+				Error::<T>::NotContractAdmin
+			);
+			ContractAdmin::<T>::insert(contract_id, &new_admin);
+			Self::deposit_event(Event::ContractAdminChanged(contract_id, Some(new_admin)));
+			Ok(())
+		}
+
+		/// Instantiate a contract from `code_index`, initialized with `init_params`.
+		///
+		/// The contract id is derived from the deployer, the code and `salt`. `ContractDeposit` is
+		/// reserved from the deployer for the lifetime of the contract. The workers create the
+		/// instance once they see the block, and a gatekeeper reports its public key to
+		/// `ContractKey`. The commands to the instance are sent to
+		/// `phala_types::messaging::contract_command_topic`.
+		#[pallet::weight(
+			10_000
+				+ T::DbWeight::get().reads_writes(2, 2)
+				+ ((init_params.len() + salt.len()) as Weight)
+					.saturating_mul(crate::mq::PAYLOAD_BYTE_WEIGHT)
+		)]
+		pub fn instantiate_contract(
+			origin: OriginFor<T>,
+			code_index: CodeIndex,
+			init_params: Vec<u8>,
+			salt: Vec<u8>,
+		) -> DispatchResult {
+			let deployer = ensure_signed(origin)?;
+			ensure!(
+				init_params.len() <= T::MaxInitParamsLen::get() as usize,
+				Error::<T>::InitParamsTooLong
+			);
+			ensure!(
+				salt.len() <= T::MaxSaltLen::get() as usize,
+				Error::<T>::SaltTooLong
+			);
+			let contract_id = Self::contract_id(&deployer, &code_index, &salt);
+			ensure!(
+				!Contracts::<T>::contains_key(&contract_id),
+				Error::<T>::DuplicateContract
+			);
+			let deposit = T::ContractDeposit::get();
+			T::Currency::reserve(&deployer, deposit)?;
+			Contracts::<T>::insert(
+				&contract_id,
+				ContractInfo {
+					deployer: deployer.clone(),
+					code_index: code_index.clone(),
+					deposit,
+					instantiated_at: frame_system::Pallet::<T>::block_number(),
+				},
+			);
+			Self::push_message(ContractEvent::InstantiateCode {
+				contract_id,
+				code_index,
+				deployer: deployer.clone(),
+				init_params,
+			});
+			Self::deposit_event(Event::ContractInstantiated(contract_id, deployer, deposit));
+			Ok(())
+		}
+
+		/// Dispute the `sample`-th revealed output in the last benchmark report of `worker`.
+		///
+		/// The chain only checks the Merkle proofs of a report, so the sampled iteration is rerun
+		/// here. If the output differs from the reported one, the worker loses its score and has
+		/// to pass a new benchmark. Fails if the output is right, so the caller pays for a bad
+		/// dispute.
+		#[pallet::weight(BENCH_ITERATION_WEIGHT + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn dispute_bench_sample(
+			origin: OriginFor<T>,
+			worker: WorkerPublicKey,
+			sample: u32,
+		) -> DispatchResult {
+			ensure_signed(origin)?;
+			let audit = BenchAudits::<T>::get(&worker).ok_or(Error::<T>::BenchReportNotFound)?;
+			let (index, output) = *audit
+				.samples
+				.get(sample as usize)
+				.ok_or(Error::<T>::InvalidInput)?;
+			ensure!(
+				benchmark::run_iteration(&audit.challenge, index) != output,
+				Error::<T>::BenchSampleNotDisputable
+			);
+			BenchAudits::<T>::remove(&worker);
+			Worker::<T>::mutate(&worker, |val| {
+				if let Some(val) = val {
+					val.intial_score = None;
+				}
+			});
+			Self::deposit_event(Event::BenchReportDisputed(worker, index));
 			Ok(())
 		}
 
@@ -199,29 +592,38 @@ pub mod pallet {
 			);
 			let runtime_version = pruntime_info.version;
 			let machine_id = pruntime_info.machine_id.to_vec();
+			// The operator is committed in the report, so the worker can be bound to it. A bound
+			// worker must be unbound by its miner before it's bound to another one.
+			if let Some(operator) = &pruntime_info.operator {
+				let pubkey = &pruntime_info.pubkey;
+				match T::WorkerBindings::bound_miner(pubkey) {
+					Some(miner) => ensure!(&miner == operator, Error::<T>::WorkerAlreadyBound),
+					None => {
+						T::WorkerBindings::bind(pubkey, operator);
+						Self::deposit_event(Event::WorkerBound(pubkey.clone(), operator.clone()));
+					}
+				}
+			}
 			// Update the registry
 			Worker::<T>::mutate(pruntime_info.pubkey.clone(), |v| {
 				match v {
 					Some(worker_info) => {
 						// Case 1 - Refresh the RA report and redo benchmark
+						worker_info.ecdh_pubkey = pruntime_info.ecdh_pubkey;
 						worker_info.last_updated = now;
 						worker_info.session_id += 1;
 						Self::push_message(SystemEvent::WorkerAttached {
 							pubkey: pruntime_info.pubkey.clone(),
 							session_id: worker_info.session_id,
 						});
-						let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
-						Self::push_message(SystemEvent::BenchStart {
-							pubkey: pruntime_info.pubkey,
-							start_time: now,
-						});
+						Self::start_benchmark(pruntime_info.pubkey, worker_info.session_id, now);
 					}
 					None => {
 						// Case 2 - New worker register
 						let session_id = 1;
 						*v = Some(WorkerInfo {
 							pubkey: pruntime_info.pubkey.clone(),
-							ecdh_pubkey: Default::default(), // TODO(shelvenzhou): add ecdh key
+							ecdh_pubkey: pruntime_info.ecdh_pubkey,
 							runtime_version: pruntime_info.version,
 							last_updated: now,
 							confidence_level: fields.confidence_level,
@@ -233,11 +635,7 @@ pub mod pallet {
 							pubkey: pruntime_info.pubkey.clone(),
 							session_id,
 						});
-						let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
-						Self::push_message(SystemEvent::BenchStart {
-							pubkey: pruntime_info.pubkey,
-							start_time: now,
-						});
+						Self::start_benchmark(pruntime_info.pubkey, session_id, now);
 					}
 				}
 			});
@@ -249,9 +647,114 @@ pub mod pallet {
 		///
 		/// Requirements:
 		//  1. `origin` is the `worker`'s operator
-		#[pallet::weight(0)]
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
 		pub fn unbind(origin: OriginFor<T>, worker: WorkerPublicKey) -> DispatchResult {
-			panic!("unimpleneted");
+			let who = ensure_signed(origin)?;
+			let miner =
+				T::WorkerBindings::bound_miner(&worker).ok_or(Error::<T>::WorkerNotBound)?;
+			ensure!(miner == who, Error::<T>::NotWorkerOperator);
+			T::WorkerBindings::unbind(&worker);
+			Self::deposit_event(Event::WorkerUnbound(worker, miner));
+			Ok(())
+		}
+	}
+
+	impl<T: Config + crate::mq::Config> Pallet<T> {
+		/// The gatekeeper set after the pending changes
+		pub fn next_gatekeepers() -> Vec<WorkerPublicKey> {
+			match NextGatekeeper::<T>::get() {
+				Some(next) => next.gatekeepers,
+				None => Gatekeeper::<T>::get(),
+			}
+		}
+
+		/// Replaces the next gatekeeper set.
+		///
+		/// The rotation block is kept if there's a pending rotation, so that the changes in a
+		/// session are applied together. The very first gatekeepers are applied immediately since
+		/// there's no one to hand over the key.
+		fn schedule_gatekeeper_rotation(gatekeepers: Vec<WorkerPublicKey>) {
+			if Gatekeeper::<T>::get().is_empty() && NextGatekeeper::<T>::get().is_none() {
+				Self::apply_gatekeeper_rotation(gatekeepers);
+				return;
+			}
+			let apply_at = match NextGatekeeper::<T>::get() {
+				Some(next) => next.apply_at,
+				None => {
+					let now = frame_system::Pallet::<T>::block_number();
+					now + T::GatekeeperRotationDelay::get().max(One::one())
+				}
+			};
+			NextGatekeeper::<T>::put(GatekeeperRotation {
+				gatekeepers: gatekeepers.clone(),
+				apply_at,
+			});
+			Self::push_message(GatekeeperEvent::RotationScheduled {
+				gatekeepers,
+				apply_at: apply_at.saturated_into(),
+			});
+			Self::deposit_event(Event::GatekeeperRotationScheduled(apply_at));
+		}
+
+		fn apply_gatekeeper_rotation(gatekeepers: Vec<WorkerPublicKey>) {
+			NextGatekeeper::<T>::kill();
+			Gatekeeper::<T>::put(&gatekeepers);
+			Self::deposit_event(Event::GatekeeperRotated(gatekeepers.len() as u32));
+			Self::push_message(GatekeeperEvent::RotationApplied { gatekeepers });
+		}
+
+		/// The id of the contract instantiated by `deployer` from `code_index` with `salt`
+		pub fn contract_id(deployer: &T::AccountId, code_index: &CodeIndex, salt: &[u8]) -> H256 {
+			crate::hashing::blake2_256(&(b"phala/contract", deployer, code_index, salt).encode())
+				.into()
+		}
+
+		/// Sends the seeds to the workers committed `BENCH_REVEAL_DELAY` blocks ago.
+		///
+		/// The seeds are drawn from the randomness of a block after the commitments, so the
+		/// workers couldn't have chosen their Merkle roots against them.
+		fn reveal_bench_seeds(now: T::BlockNumber) -> Weight {
+			let workers = BenchReveals::<T>::take(now);
+			if workers.is_empty() {
+				return T::DbWeight::get().reads(1);
+			}
+			let (random_seed, _) = T::Randomness::random(BENCH_RANDOMNESS_SUBJECT);
+			for pubkey in &workers {
+				BenchCommitment::<T>::mutate(pubkey, |commitment| match commitment {
+					Some(commitment) if commitment.reveal_at == now => {
+						let seed = crate::hashing::blake2_256(&(random_seed, pubkey).encode());
+						commitment.seed = Some(seed);
+						Self::push_message(SystemEvent::BenchSeed {
+							pubkey: pubkey.clone(),
+							seed,
+						});
+					}
+					// The session is gone or restarted
+					_ => (),
+				});
+			}
+			let n = workers.len() as Weight;
+			T::DbWeight::get().reads_writes(1 + n, 1 + n)
+		}
+
+		/// Starts a benchmark session with a challenge derived from the on-chain randomness
+		fn start_benchmark(pubkey: WorkerPublicKey, session_id: u64, now: u64) {
+			let (random_seed, _) = T::Randomness::random(BENCH_RANDOMNESS_SUBJECT);
+			let challenge: Challenge =
+				crate::hashing::blake2_256(&(random_seed, &pubkey, session_id).encode());
+			BenchChallenge::<T>::insert(
+				&pubkey,
+				BenchSession {
+					challenge,
+					start_time: now,
+				},
+			);
+			BenchCommitment::<T>::remove(&pubkey);
+			Self::push_message(SystemEvent::BenchStart {
+				pubkey,
+				start_time: now,
+				challenge,
+			});
 		}
 	}
 
@@ -293,19 +796,67 @@ pub mod pallet {
 				message.decode_payload().ok_or(Error::<T>::InvalidInput)?;
 
 			match message {
-				RegistryEvent::BenchReport {
-					start_time,
-					iterations,
-				} => {
+				RegistryEvent::BenchCommit { iterations, root } => {
+					let session = BenchChallenge::<T>::get(worker_pubkey)
+						.ok_or(Error::<T>::InvalidBenchReport)?;
+					ensure!(
+						!BenchCommitment::<T>::contains_key(worker_pubkey),
+						Error::<T>::InvalidBenchReport
+					);
 					let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
-					if now <= start_time {
+					if now <= session.start_time {
 						// Oops, should not happen
 						return Err(Error::<T>::InvalidBenchReport.into());
 					}
+					let reveal_at =
+						frame_system::Pallet::<T>::block_number() + BENCH_REVEAL_DELAY.into();
+					BenchCommitment::<T>::insert(
+						worker_pubkey,
+						BenchCommit {
+							iterations,
+							root,
+							committed_at: now,
+							reveal_at,
+							seed: None,
+						},
+					);
+					BenchReveals::<T>::append(reveal_at, worker_pubkey);
+				}
+				RegistryEvent::BenchReport { samples } => {
+					// A bad report ends the session. The worker has to register again.
+					let session = BenchChallenge::<T>::take(worker_pubkey)
+						.ok_or(Error::<T>::InvalidBenchReport)?;
+					let commitment = BenchCommitment::<T>::take(worker_pubkey)
+						.ok_or(Error::<T>::InvalidBenchReport)?;
+					let seed = commitment.seed.ok_or(Error::<T>::InvalidBenchReport)?;
+					// Bounded by `SAMPLES * MAX_PROOF_DEPTH` hashes, and paid by the payload
+					// weight of `sync_offchain_message`
+					ensure!(
+						benchmark::verify_samples(
+							crate::hashing::blake2_256,
+							&commitment.root,
+							&seed,
+							commitment.iterations,
+							&samples
+						),
+						Error::<T>::InvalidBenchReport
+					);
 
-					const MAX_SCORE: u32 = 6000;
-					let score = iterations / (now - start_time);
-					let score = MAX_SCORE.min(score as u32);
+					let score = benchmark::score(
+						commitment.iterations,
+						commitment.committed_at - session.start_time,
+					);
+					// The outputs are only checked when disputed
+					BenchAudits::<T>::insert(
+						worker_pubkey,
+						BenchAudit {
+							challenge: session.challenge,
+							samples: samples
+								.iter()
+								.map(|sample| (sample.index, sample.output))
+								.collect(),
+						},
+					);
 
 					Worker::<T>::mutate(worker_pubkey, |val| {
 						if let Some(val) = val {
@@ -313,7 +864,115 @@ pub mod pallet {
 						}
 					})
 				}
+				RegistryEvent::KeyHandover {
+					handover,
+					signature,
+				} => Self::handover_worker_key(worker_pubkey, handover, signature)?,
+				RegistryEvent::ContractKey {
+					contract_id,
+					pubkey,
+				} => {
+					ensure!(
+						Gatekeeper::<T>::get().contains(worker_pubkey),
+						Error::<T>::NotGatekeeper
+					);
+					ensure!(
+						Contracts::<T>::contains_key(&contract_id),
+						Error::<T>::UnknwonContract
+					);
+					// The instances on all the workers derive the same key from the contract key
+					// shared by the gatekeepers, so the first report is enough
+					ensure!(
+						!ContractKey::<T>::contains_key(&contract_id),
+						Error::<T>::ContractKeyAlreadyRegistered
+					);
+					ContractKey::<T>::insert(&contract_id, &pubkey);
+					Self::deposit_event(Event::ContractKeyRegistered(contract_id, pubkey));
+				}
+				RegistryEvent::TopicKey { topic, pubkey } => {
+					ensure!(
+						Gatekeeper::<T>::get().contains(worker_pubkey),
+						Error::<T>::NotGatekeeper
+					);
+					// Derived by all the gatekeepers from the same contract key
+					ensure!(
+						!TopicKey::<T>::contains_key(&topic),
+						Error::<T>::TopicKeyAlreadyRegistered
+					);
+					TopicKey::<T>::insert(&topic, &pubkey);
+					Self::deposit_event(Event::TopicKeyRegistered(topic, pubkey));
+				}
+			}
+			Ok(())
+		}
+
+		/// Moves the registered worker identity to the new key declared in the handover statement
+		fn handover_worker_key(
+			sender: &WorkerPublicKey,
+			handover: WorkerKeyHandover,
+			signature: Vec<u8>,
+		) -> DispatchResult {
+			ensure!(
+				&handover.old_pubkey == sender,
+				Error::<T>::InvalidKeyHandover
+			);
+			ensure!(signature.len() == 65, Error::<T>::InvalidSignatureLength);
+			let sig = sp_core::ecdsa::Signature::try_from(signature.as_slice())
+				.or(Err(Error::<T>::MalformedSignature))?;
+			ensure!(
+				sp_io::crypto::ecdsa_verify(&sig, &handover.encode(), &handover.old_pubkey),
+				Error::<T>::InvalidSignature
+			);
+			ensure!(
+				!Worker::<T>::contains_key(&handover.new_pubkey),
+				Error::<T>::InvalidKeyHandover
+			);
+			let mut worker_info =
+				Worker::<T>::take(&handover.old_pubkey).ok_or(Error::<T>::WorkerNotFound)?;
+			worker_info.pubkey = handover.new_pubkey.clone();
+			worker_info.ecdh_pubkey = handover.new_ecdh_pubkey;
+			Worker::<T>::insert(&handover.new_pubkey, &worker_info);
+			// The ongoing benchmark session goes on with the new key
+			if let Some(session) = BenchChallenge::<T>::take(&handover.old_pubkey) {
+				BenchChallenge::<T>::insert(&handover.new_pubkey, session);
+			}
+			if let Some(commitment) = BenchCommitment::<T>::take(&handover.old_pubkey) {
+				if commitment.seed.is_none() {
+					BenchReveals::<T>::mutate(commitment.reveal_at, |workers| {
+						for worker in workers.iter_mut() {
+							if worker == &handover.old_pubkey {
+								*worker = handover.new_pubkey.clone();
+							}
+						}
+					});
+				}
+				BenchCommitment::<T>::insert(&handover.new_pubkey, commitment);
+			}
+			if let Some(audit) = BenchAudits::<T>::take(&handover.old_pubkey) {
+				BenchAudits::<T>::insert(&handover.new_pubkey, audit);
 			}
+			T::OnWorkerKeyRotated::on_worker_key_rotated(
+				&handover.old_pubkey,
+				&handover.new_pubkey,
+			);
+			// A gatekeeper keeps its seat after the rotation
+			let rotate_key = |gatekeepers: &mut Vec<WorkerPublicKey>| {
+				for gatekeeper in gatekeepers.iter_mut() {
+					if gatekeeper == &handover.old_pubkey {
+						*gatekeeper = handover.new_pubkey.clone();
+					}
+				}
+			};
+			Gatekeeper::<T>::mutate(rotate_key);
+			NextGatekeeper::<T>::mutate(|next| {
+				if let Some(next) = next {
+					rotate_key(&mut next.gatekeepers);
+				}
+			});
+			Self::deposit_event(Event::WorkerKeyRotated(
+				handover.old_pubkey,
+				handover.new_pubkey,
+			));
 			Ok(())
 		}
 	}
@@ -322,6 +981,36 @@ pub mod pallet {
 		type Config = T;
 	}
 
+	const BENCH_RANDOMNESS_SUBJECT: &[u8] = b"phala/registry/bench";
+	/// The blocks between a benchmark commitment and its seed
+	const BENCH_REVEAL_DELAY: u32 = 2;
+	/// An upper estimate of rerunning a benchmark iteration in the runtime, which fills and mixes
+	/// a 512 KiB scratchpad
+	const BENCH_ITERATION_WEIGHT: Weight = 20_000_000_000;
+
+	#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+	pub struct BenchSession {
+		pub challenge: Challenge,
+		pub start_time: u64,
+	}
+
+	#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+	pub struct BenchCommit<BlockNumber> {
+		pub iterations: u64,
+		pub root: benchmark::Hash,
+		/// The end of the benchmark, in seconds
+		pub committed_at: u64,
+		pub reveal_at: BlockNumber,
+		pub seed: Option<benchmark::Hash>,
+	}
+
+	#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+	pub struct BenchAudit {
+		pub challenge: Challenge,
+		/// The revealed `(index, output)` of the sampled iterations
+		pub samples: Vec<(u64, u64)>,
+	}
+
 	#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
 	pub enum Attestation {
 		SgxIas {
@@ -331,21 +1020,30 @@ pub mod pallet {
 		},
 	}
 
+	#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+	pub struct ContractInfo<AccountId, Balance, BlockNumber> {
+		pub deployer: AccountId,
+		pub code_index: CodeIndex,
+		/// Reserved from the deployer
+		pub deposit: Balance,
+		pub instantiated_at: BlockNumber,
+	}
+
 	// TODO.shelven: handle the WorkerInfo in phala_types
 	#[derive(Encode, Decode, Default, Debug, Clone)]
 	pub struct WorkerInfo {
 		// identity
-		pubkey: WorkerPublicKey,
-		ecdh_pubkey: WorkerPublicKey,
+		pub pubkey: WorkerPublicKey,
+		pub ecdh_pubkey: EcdhPublicKey,
 		// system
-		runtime_version: u32,
-		last_updated: u64,
+		pub runtime_version: u32,
+		pub last_updated: u64,
 		// platform
-		confidence_level: u8,
+		pub confidence_level: u8,
 		// scoring
-		session_id: u64,
-		intial_score: Option<u32>,
-		features: Vec<u32>,
+		pub session_id: u64,
+		pub intial_score: Option<u32>,
+		pub features: Vec<u32>,
 	}
 
 	impl<T: Config> From<AttestationError> for Error<T> {
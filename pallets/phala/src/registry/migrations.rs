@@ -0,0 +1,111 @@
+//! Storage migrations of the registry pallet
+
+use codec::{Decode, Encode};
+use frame_support::{
+	traits::{Get, OnRuntimeUpgrade},
+	weights::Weight,
+};
+use sp_std::{marker::PhantomData, prelude::*};
+
+use super::*;
+use phala_types::WorkerPublicKey;
+
+/// `WorkerInfo` before the ECDH public key was stored as raw bytes
+#[derive(Encode, Decode)]
+struct OldWorkerInfo {
+	pubkey: WorkerPublicKey,
+	ecdh_pubkey: WorkerPublicKey,
+	runtime_version: u32,
+	last_updated: u64,
+	confidence_level: u8,
+	session_id: u64,
+	intial_score: Option<u32>,
+	features: Vec<u32>,
+}
+
+/// Converts `WorkerInfo::ecdh_pubkey` from `WorkerPublicKey` to `EcdhPublicKey`.
+///
+/// The old registrations never filled the key, so the zero keys become empty, to be set by the
+/// next `register_worker`. Must run before any migration writing the new `WorkerInfo`.
+pub struct MigrateWorkerEcdhPubkey<T>(PhantomData<T>);
+
+impl<T: Config> OnRuntimeUpgrade for MigrateWorkerEcdhPubkey<T> {
+	fn on_runtime_upgrade() -> Weight {
+		if WorkerEcdhPubkeyMigrated::<T>::get() {
+			return T::DbWeight::get().reads(1);
+		}
+		let mut n: Weight = 0;
+		Worker::<T>::translate::<OldWorkerInfo, _>(|_, old| {
+			n += 1;
+			let ecdh_pubkey: &[u8] = old.ecdh_pubkey.as_ref();
+			let ecdh_pubkey = if ecdh_pubkey.iter().all(|b| *b == 0) {
+				Vec::new()
+			} else {
+				ecdh_pubkey.to_vec()
+			};
+			Some(WorkerInfo {
+				pubkey: old.pubkey,
+				ecdh_pubkey,
+				runtime_version: old.runtime_version,
+				last_updated: old.last_updated,
+				confidence_level: old.confidence_level,
+				session_id: old.session_id,
+				intial_score: old.intial_score,
+				features: old.features,
+			})
+		});
+		WorkerEcdhPubkeyMigrated::<T>::put(true);
+		T::DbWeight::get().reads_writes(1 + n, 1 + n)
+	}
+
+	#[cfg(feature = "try-runtime")]
+	fn post_upgrade() -> Result<(), &'static str> {
+		if !WorkerEcdhPubkeyMigrated::<T>::get() {
+			return Err("migration flag not set");
+		}
+		for (pubkey, info) in Worker::<T>::iter() {
+			if info.pubkey != pubkey {
+				return Err("worker info doesn't match its key");
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Sets the admins of the native contracts given by `Admins` on a chain launched before
+/// `ContractAdmin` existed, since it's otherwise only set in the genesis.
+///
+/// Existing admins are kept.
+pub struct InitContractAdmins<T, Admins>(PhantomData<(T, Admins)>);
+
+impl<T: Config, Admins: Get<Vec<(u32, T::AccountId)>>> OnRuntimeUpgrade
+	for InitContractAdmins<T, Admins>
+{
+	fn on_runtime_upgrade() -> Weight {
+		if ContractAdminsInitialized::<T>::get() {
+			return T::DbWeight::get().reads(1);
+		}
+		let admins = Admins::get();
+		let n = admins.len() as Weight;
+		for (contract_id, admin) in admins {
+			if !ContractAdmin::<T>::contains_key(contract_id) {
+				ContractAdmin::<T>::insert(contract_id, admin);
+			}
+		}
+		ContractAdminsInitialized::<T>::put(true);
+		T::DbWeight::get().reads_writes(1 + n, 1 + n)
+	}
+
+	#[cfg(feature = "try-runtime")]
+	fn post_upgrade() -> Result<(), &'static str> {
+		if !ContractAdminsInitialized::<T>::get() {
+			return Err("migration flag not set");
+		}
+		for (contract_id, _) in Admins::get() {
+			if !ContractAdmin::<T>::contains_key(contract_id) {
+				return Err("contract admin not set");
+			}
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,527 @@
+use codec::Encode;
+use frame_support::{
+	assert_noop, assert_ok, parameter_types,
+	traits::{Currency, OnInitialize, OnRuntimeUpgrade, ReservableCurrency},
+};
+use sp_core::{ecdsa, Pair, H256};
+use sp_runtime::DispatchError;
+
+use super::{
+	migrations::{InitContractAdmins, MigrateWorkerEcdhPubkey},
+	BenchAudits, BenchChallenge, BenchCommit, BenchCommitment, BenchReveals, BenchSession,
+	ContractAdmin, ContractAdminsInitialized, ContractKey, Contracts, Error, Gatekeeper,
+	NextGatekeeper, RegistryEvent, TopicKey, Worker, WorkerEcdhPubkeyMigrated,
+};
+use crate::{mining, phala_legacy::mock::*};
+use phala_types::{
+	benchmark::{self, MerkleTree},
+	messaging::{BindTopic, Message, MessageOrigin},
+	CodeIndex, GatekeeperRotation, WorkerKeyHandover, WorkerPublicKey,
+};
+
+fn worker_pair(seed: u8) -> ecdsa::Pair {
+	ecdsa::Pair::from_seed(&[seed; 32])
+}
+
+fn worker_pubkey(seed: u8) -> WorkerPublicKey {
+	worker_pair(seed).public()
+}
+
+fn worker_message(sender: &WorkerPublicKey, event: RegistryEvent) -> Message {
+	Message::new(
+		MessageOrigin::Worker(sender.clone()),
+		RegistryEvent::TOPIC.to_vec(),
+		event.encode(),
+	)
+}
+
+#[test]
+fn test_gatekeeper_rotation() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_noop!(
+			PhalaRegistry::register_gatekeeper(Origin::signed(1), worker_pubkey(1)),
+			DispatchError::BadOrigin
+		);
+		// The first gatekeeper takes effect immediately
+		assert_ok!(PhalaRegistry::register_gatekeeper(
+			Origin::root(),
+			worker_pubkey(1)
+		));
+		assert_eq!(Gatekeeper::<Test>::get(), vec![worker_pubkey(1)]);
+		assert_eq!(NextGatekeeper::<Test>::get(), None);
+		assert_noop!(
+			PhalaRegistry::register_gatekeeper(Origin::root(), worker_pubkey(1)),
+			Error::<Test>::DuplicateGatekeeper
+		);
+		// The later changes are applied together after the rotation delay
+		assert_ok!(PhalaRegistry::register_gatekeeper(
+			Origin::root(),
+			worker_pubkey(2)
+		));
+		System::set_block_number(5);
+		assert_ok!(PhalaRegistry::register_gatekeeper(
+			Origin::root(),
+			worker_pubkey(3)
+		));
+		assert_eq!(
+			NextGatekeeper::<Test>::get(),
+			Some(GatekeeperRotation {
+				gatekeepers: vec![worker_pubkey(1), worker_pubkey(2), worker_pubkey(3)],
+				apply_at: 11,
+			})
+		);
+		PhalaRegistry::on_initialize(10);
+		assert_eq!(Gatekeeper::<Test>::get(), vec![worker_pubkey(1)]);
+		PhalaRegistry::on_initialize(11);
+		assert_eq!(
+			Gatekeeper::<Test>::get(),
+			vec![worker_pubkey(1), worker_pubkey(2), worker_pubkey(3)]
+		);
+		assert_eq!(NextGatekeeper::<Test>::get(), None);
+	});
+}
+
+#[test]
+fn test_gatekeeper_limits() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(PhalaRegistry::register_gatekeeper(
+			Origin::root(),
+			worker_pubkey(1)
+		));
+		// Can't go below MinGatekeepers
+		assert_noop!(
+			PhalaRegistry::unregister_gatekeeper(Origin::root(), worker_pubkey(1)),
+			Error::<Test>::TooFewGatekeepers
+		);
+		assert_noop!(
+			PhalaRegistry::unregister_gatekeeper(Origin::root(), worker_pubkey(2)),
+			Error::<Test>::GatekeeperNotFound
+		);
+		for seed in 2..=5 {
+			assert_ok!(PhalaRegistry::register_gatekeeper(
+				Origin::root(),
+				worker_pubkey(seed)
+			));
+		}
+		// Can't go above MaxGatekeepers
+		assert_noop!(
+			PhalaRegistry::register_gatekeeper(Origin::root(), worker_pubkey(6)),
+			Error::<Test>::TooManyGatekeepers
+		);
+		// Removed from the next set only
+		assert_ok!(PhalaRegistry::unregister_gatekeeper(
+			Origin::root(),
+			worker_pubkey(1)
+		));
+		assert_eq!(Gatekeeper::<Test>::get(), vec![worker_pubkey(1)]);
+		assert_eq!(
+			PhalaRegistry::next_gatekeepers(),
+			(2..=5).map(worker_pubkey).collect::<Vec<_>>()
+		);
+	});
+}
+
+#[test]
+fn test_contract_key_reported_by_gatekeeper() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(PhalaRegistry::register_gatekeeper(
+			Origin::root(),
+			worker_pubkey(1)
+		));
+		let code_index = CodeIndex::NativeCode(2);
+		assert_ok!(PhalaRegistry::instantiate_contract(
+			Origin::signed(1),
+			code_index.clone(),
+			vec![],
+			b"salt".to_vec()
+		));
+		let contract_id = PhalaRegistry::contract_id(&1, &code_index, b"salt");
+		let report = |sender: u8| {
+			worker_message(
+				&worker_pubkey(sender),
+				RegistryEvent::ContractKey {
+					contract_id,
+					pubkey: worker_pubkey(9),
+				},
+			)
+		};
+		assert_noop!(
+			PhalaRegistry::on_message_received(&report(2)),
+			Error::<Test>::NotGatekeeper
+		);
+		let unknown = worker_message(
+			&worker_pubkey(1),
+			RegistryEvent::ContractKey {
+				contract_id: H256::repeat_byte(1),
+				pubkey: worker_pubkey(9),
+			},
+		);
+		assert_noop!(
+			PhalaRegistry::on_message_received(&unknown),
+			Error::<Test>::UnknwonContract
+		);
+		assert_ok!(PhalaRegistry::on_message_received(&report(1)));
+		assert_eq!(ContractKey::<Test>::get(contract_id), Some(worker_pubkey(9)));
+		assert_noop!(
+			PhalaRegistry::on_message_received(&report(1)),
+			Error::<Test>::ContractKeyAlreadyRegistered
+		);
+	});
+}
+
+#[test]
+fn test_topic_key_reported_by_gatekeeper() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(PhalaRegistry::register_gatekeeper(
+			Origin::root(),
+			worker_pubkey(1)
+		));
+		let topic = b"phala/balances/payment/00".to_vec();
+		let report = |sender: u8, pubkey: Vec<u8>| {
+			worker_message(
+				&worker_pubkey(sender),
+				RegistryEvent::TopicKey {
+					topic: topic.clone(),
+					pubkey,
+				},
+			)
+		};
+		assert_noop!(
+			PhalaRegistry::on_message_received(&report(2, vec![1])),
+			Error::<Test>::NotGatekeeper
+		);
+		assert_ok!(PhalaRegistry::on_message_received(&report(1, vec![1])));
+		assert_eq!(TopicKey::<Test>::get(&topic), Some(vec![1]));
+		assert_noop!(
+			PhalaRegistry::on_message_received(&report(1, vec![2])),
+			Error::<Test>::TopicKeyAlreadyRegistered
+		);
+	});
+}
+
+#[test]
+fn test_instantiate_contract_deposit_and_limits() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let code_index = CodeIndex::NativeCode(2);
+		let _ = Balances::deposit_creating(&1, 150);
+		assert_noop!(
+			PhalaRegistry::instantiate_contract(
+				Origin::signed(1),
+				code_index.clone(),
+				vec![0; MaxInitParamsLen::get() as usize + 1],
+				b"salt".to_vec()
+			),
+			Error::<Test>::InitParamsTooLong
+		);
+		assert_noop!(
+			PhalaRegistry::instantiate_contract(
+				Origin::signed(1),
+				code_index.clone(),
+				vec![],
+				vec![0; MaxSaltLen::get() as usize + 1]
+			),
+			Error::<Test>::SaltTooLong
+		);
+		assert_ok!(PhalaRegistry::instantiate_contract(
+			Origin::signed(1),
+			code_index.clone(),
+			vec![],
+			b"salt".to_vec()
+		));
+		let contract_id = PhalaRegistry::contract_id(&1, &code_index, b"salt");
+		assert_eq!(
+			Contracts::<Test>::get(contract_id).unwrap().deposit,
+			ContractDeposit::get()
+		);
+		assert_eq!(Balances::reserved_balance(&1), ContractDeposit::get());
+		assert_noop!(
+			PhalaRegistry::instantiate_contract(
+				Origin::signed(1),
+				code_index.clone(),
+				vec![],
+				b"salt".to_vec()
+			),
+			Error::<Test>::DuplicateContract
+		);
+		// Not enough free balance for another deposit
+		assert!(PhalaRegistry::instantiate_contract(
+			Origin::signed(1),
+			code_index,
+			vec![],
+			b"salt2".to_vec()
+		)
+		.is_err());
+	});
+}
+
+#[test]
+fn test_gatekeeper_keeps_seat_after_key_handover() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let old = worker_pair(1);
+		let new = worker_pair(2);
+		assert_ok!(PhalaRegistry::force_register_worker(
+			Origin::root(),
+			old.public(),
+			vec![1]
+		));
+		assert_ok!(PhalaRegistry::register_gatekeeper(
+			Origin::root(),
+			old.public()
+		));
+		let handover = WorkerKeyHandover {
+			old_pubkey: old.public(),
+			new_pubkey: new.public(),
+			new_ecdh_pubkey: vec![2],
+		};
+		// Must be signed by the old identity
+		let forged = worker_message(
+			&old.public(),
+			RegistryEvent::KeyHandover {
+				handover: handover.clone(),
+				signature: new.sign(&handover.encode()).0.to_vec(),
+			},
+		);
+		assert_noop!(
+			PhalaRegistry::on_message_received(&forged),
+			Error::<Test>::InvalidSignature
+		);
+		let signed = worker_message(
+			&old.public(),
+			RegistryEvent::KeyHandover {
+				handover: handover.clone(),
+				signature: old.sign(&handover.encode()).0.to_vec(),
+			},
+		);
+		assert_ok!(PhalaRegistry::on_message_received(&signed));
+		assert!(!Worker::<Test>::contains_key(&old.public()));
+		assert_eq!(
+			Worker::<Test>::get(&new.public()).unwrap().ecdh_pubkey,
+			vec![2]
+		);
+		assert_eq!(Gatekeeper::<Test>::get(), vec![new.public()]);
+	});
+}
+
+#[test]
+fn test_key_handover_moves_worker_states() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let old = worker_pair(1);
+		let new = worker_pair(2);
+		assert_ok!(PhalaRegistry::force_register_worker(
+			Origin::root(),
+			old.public(),
+			vec![1]
+		));
+		let session = BenchSession {
+			challenge: [1; 32],
+			start_time: 10,
+		};
+		let commitment = BenchCommit {
+			iterations: 100,
+			root: [2; 32],
+			committed_at: 20,
+			reveal_at: 3,
+			seed: None,
+		};
+		BenchChallenge::<Test>::insert(&old.public(), session.clone());
+		BenchCommitment::<Test>::insert(&old.public(), commitment.clone());
+		BenchReveals::<Test>::insert(3, vec![worker_pubkey(3), old.public()]);
+		mining::WorkerBindings::<Test>::insert(&old.public(), 7);
+
+		let handover = WorkerKeyHandover {
+			old_pubkey: old.public(),
+			new_pubkey: new.public(),
+			new_ecdh_pubkey: vec![2],
+		};
+		let signed = worker_message(
+			&old.public(),
+			RegistryEvent::KeyHandover {
+				handover: handover.clone(),
+				signature: old.sign(&handover.encode()).0.to_vec(),
+			},
+		);
+		assert_ok!(PhalaRegistry::on_message_received(&signed));
+		assert_eq!(BenchChallenge::<Test>::get(&old.public()), None);
+		assert_eq!(BenchChallenge::<Test>::get(&new.public()), Some(session));
+		assert_eq!(BenchCommitment::<Test>::get(&old.public()), None);
+		assert_eq!(BenchCommitment::<Test>::get(&new.public()), Some(commitment));
+		assert_eq!(
+			BenchReveals::<Test>::get(3),
+			vec![worker_pubkey(3), new.public()]
+		);
+		assert_eq!(mining::WorkerBindings::<Test>::get(&old.public()), None);
+		assert_eq!(mining::WorkerBindings::<Test>::get(&new.public()), Some(7));
+	});
+}
+
+#[test]
+fn test_migrate_worker_ecdh_pubkey() {
+	new_test_ext().execute_with(|| {
+		// Encoded as the old `WorkerInfo` with a `WorkerPublicKey` as the ECDH key
+		let old_info = |pubkey: WorkerPublicKey, ecdh_pubkey: WorkerPublicKey| {
+			(
+				pubkey,
+				ecdh_pubkey,
+				1u32,
+				2u64,
+				128u8,
+				3u64,
+				Some(4u32),
+				vec![5u32],
+			)
+				.encode()
+		};
+		let registered = worker_pubkey(1);
+		let forced = worker_pubkey(2);
+		sp_io::storage::set(
+			&Worker::<Test>::hashed_key_for(&registered),
+			&old_info(registered.clone(), Default::default()),
+		);
+		sp_io::storage::set(
+			&Worker::<Test>::hashed_key_for(&forced),
+			&old_info(forced.clone(), worker_pubkey(9)),
+		);
+
+		MigrateWorkerEcdhPubkey::<Test>::on_runtime_upgrade();
+		assert!(WorkerEcdhPubkeyMigrated::<Test>::get());
+		let info = Worker::<Test>::get(&registered).unwrap();
+		assert_eq!(info.pubkey, registered);
+		assert!(info.ecdh_pubkey.is_empty());
+		assert_eq!(info.session_id, 3);
+		assert_eq!(info.intial_score, Some(4));
+		assert_eq!(info.features, vec![5]);
+		let info = Worker::<Test>::get(&forced).unwrap();
+		let ecdh_pubkey: &[u8] = worker_pubkey(9).as_ref();
+		assert_eq!(info.ecdh_pubkey, ecdh_pubkey.to_vec());
+
+		// Only once
+		MigrateWorkerEcdhPubkey::<Test>::on_runtime_upgrade();
+		assert_eq!(Worker::<Test>::get(&forced).unwrap().ecdh_pubkey, ecdh_pubkey.to_vec());
+	});
+}
+
+parameter_types! {
+	pub TestContractAdmins: Vec<(u32, u64)> = vec![(1, 1), (8, 1)];
+}
+
+#[test]
+fn test_init_contract_admins() {
+	new_test_ext().execute_with(|| {
+		ContractAdmin::<Test>::insert(8, 2);
+
+		InitContractAdmins::<Test, TestContractAdmins>::on_runtime_upgrade();
+		assert!(ContractAdminsInitialized::<Test>::get());
+		assert_eq!(ContractAdmin::<Test>::get(1), Some(1));
+		// The existing admin is kept
+		assert_eq!(ContractAdmin::<Test>::get(8), Some(2));
+
+		// Only once, so a removed admin isn't restored
+		assert_ok!(PhalaRegistry::set_contract_admin(Origin::root(), 1, None));
+		InitContractAdmins::<Test, TestContractAdmins>::on_runtime_upgrade();
+		assert_eq!(ContractAdmin::<Test>::get(1), None);
+	});
+}
+
+#[test]
+fn test_unbind_worker() {
+	new_test_ext().execute_with(|| {
+		let worker = worker_pubkey(1);
+		assert_noop!(
+			PhalaRegistry::unbind(Origin::signed(1), worker.clone()),
+			Error::<Test>::WorkerNotBound
+		);
+		mining::WorkerBindings::<Test>::insert(&worker, 1);
+		// Only by the bound miner
+		assert_noop!(
+			PhalaRegistry::unbind(Origin::signed(2), worker.clone()),
+			Error::<Test>::NotWorkerOperator
+		);
+		assert_ok!(PhalaRegistry::unbind(Origin::signed(1), worker.clone()));
+		assert_eq!(mining::WorkerBindings::<Test>::get(&worker), None);
+	});
+}
+
+/// Accepts a benchmark report of `outputs` from the worker, which gets a score of 1
+fn report_bench(worker: &WorkerPublicKey, challenge: [u8; 32], outputs: &[u64]) {
+	let tree = MerkleTree::new(crate::hashing::blake2_256, outputs);
+	let seed = [3; 32];
+	BenchChallenge::<Test>::insert(
+		worker,
+		BenchSession {
+			challenge,
+			start_time: 10,
+		},
+	);
+	BenchCommitment::<Test>::insert(
+		worker,
+		BenchCommit {
+			iterations: outputs.len() as u64,
+			root: tree.root(),
+			committed_at: 10 + outputs.len() as u64,
+			reveal_at: 1,
+			seed: Some(seed),
+		},
+	);
+	let samples = benchmark::make_samples(crate::hashing::blake2_256, outputs, &tree, &seed);
+	assert_ok!(PhalaRegistry::on_message_received(&worker_message(
+		worker,
+		RegistryEvent::BenchReport { samples }
+	)));
+	assert_eq!(Worker::<Test>::get(worker).unwrap().intial_score, Some(1));
+}
+
+#[test]
+fn test_dispute_bench_sample() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let challenge = [1; 32];
+		let worker = worker_pubkey(1);
+		assert_ok!(PhalaRegistry::force_register_worker(
+			Origin::root(),
+			worker.clone(),
+			vec![1]
+		));
+		assert_noop!(
+			PhalaRegistry::dispute_bench_sample(Origin::signed(1), worker.clone(), 0),
+			Error::<Test>::BenchReportNotFound
+		);
+
+		// An honest report can't be disputed
+		let outputs: Vec<u64> = (0..2)
+			.map(|index| benchmark::run_iteration(&challenge, index))
+			.collect();
+		report_bench(&worker, challenge, &outputs);
+		let audit = BenchAudits::<Test>::get(&worker).unwrap();
+		assert_eq!(audit.challenge, challenge);
+		assert_eq!(audit.samples.len(), benchmark::SAMPLES);
+		assert_noop!(
+			PhalaRegistry::dispute_bench_sample(Origin::signed(1), worker.clone(), 0),
+			Error::<Test>::BenchSampleNotDisputable
+		);
+		assert_noop!(
+			PhalaRegistry::dispute_bench_sample(
+				Origin::signed(1),
+				worker.clone(),
+				benchmark::SAMPLES as u32
+			),
+			Error::<Test>::InvalidInput
+		);
+
+		// Made-up outputs pass the proofs, but not the dispute
+		report_bench(&worker, challenge, &[1, 2]);
+		assert_ok!(PhalaRegistry::dispute_bench_sample(
+			Origin::signed(1),
+			worker.clone(),
+			0
+		));
+		assert_eq!(Worker::<Test>::get(&worker).unwrap().intial_score, None);
+		assert_eq!(BenchAudits::<Test>::get(&worker), None);
+	});
+}
@@ -6,7 +6,7 @@ pub mod pallet {
 	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, traits::Currency};
 	use frame_system::pallet_prelude::*;
 
-	use phala_types::WorkerPublicKey;
+	use phala_types::{StashInfo, WorkerPublicKey};
 	use sp_runtime::Permill;
 	use sp_std::vec::Vec;
 
@@ -26,6 +26,16 @@ pub mod pallet {
 	pub type Pool<T: Config> =
 		StorageMap<_, Twox64Concat, u64, PoolInfo<T::AccountId, BalanceOf<T>>>;
 
+	/// Mapping from stash account to stash info (indexed: Stash)
+	#[pallet::storage]
+	pub type StashState<T: Config> =
+		StorageMap<_, Blake2_128Concat, T::AccountId, StashInfo<T::AccountId>, ValueQuery>;
+
+	/// Mapping from controller to stash
+	#[pallet::storage]
+	pub type Stash<T: Config> =
+		StorageMap<_, Blake2_128Concat, T::AccountId, T::AccountId, ValueQuery>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event {
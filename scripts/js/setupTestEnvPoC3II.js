@@ -61,8 +61,9 @@ async function main () {
     await api.tx.phala.startMiningIntention().signAndSend(bob, {nonce: nonceBob++});
 
     console.log('// Trigger next round');
+    // The witness covers the four pending stakes above
     await api.tx.sudo.sudo(
-        api.tx.miningStaking.forceTriggerRoundEnd()
+        api.tx.miningStaking.forceTriggerRoundEnd(4, 0, 0)
     ).signAndSend(root, {nonce: nonceAlice++});
     await api.tx.sudo.sudo(
         api.tx.phala.forceNextRound()
@@ -26,6 +26,7 @@ use node_runtime::{
 	DemocracyConfig,GrandpaConfig, ImOnlineConfig, SessionConfig, SessionKeys, StakerStatus,
 	StakingConfig, ElectionsConfig, IndicesConfig, SocietyConfig, SudoConfig, SystemConfig,
 	TechnicalCommitteeConfig, PhalaConfig, wasm_binary_unwrap, BridgeTransferConfig, KittyStorageConfig,
+	PhalaRegistryConfig,
 };
 use node_runtime::Block;
 use node_runtime::constants::currency::*;
@@ -246,6 +247,8 @@ pub fn testnet_genesis(
 	const STASH: Balance = ENDOWMENT / 1000;
 	// The pubkey of "0x1"
 	let dev_ecdsa_pubkey: Vec<u8> = hex!["0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"].to_vec();
+	// The root key administrates the built-in native contracts (ids 1 to 8 in pRuntime)
+	let contract_admins: Vec<(u32, AccountId)> = (1..=8).map(|id| (id, root_key.clone())).collect();
 
 	GenesisConfig {
 		system: SystemConfig {
@@ -338,6 +341,9 @@ pub fn testnet_genesis(
 			bridge_tokenid: pallet_bridge::derive_resource_id(1, &pallet_bridge::hashing::blake2_128(b"PHA")),
 			bridge_lotteryid: pallet_bridge::derive_resource_id(1, &pallet_bridge::hashing::blake2_128(b"lottery")),
 		},
+		phala_registry: PhalaRegistryConfig {
+			contract_admins,
+		},
 	}
 }
 
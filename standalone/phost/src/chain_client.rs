@@ -1,11 +1,11 @@
 use anyhow::{anyhow, Result};
 use sp_core::{storage::StorageKey};
-use phala_types::{messaging::MessageOrigin};
+use codec::Encode;
+use phala_types::{messaging::MessageOrigin, WorkerPublicKey};
+use phala_pallets_rpc_runtime_api::RegisteredWorker;
 use enclave_api::blocks::StorageProof;
-use super::runtimes;
-
 use super::XtClient;
-use crate::types::{ Hash, utils::raw_proof};
+use crate::types::{ AccountId, Hash, utils::raw_proof};
 use trie_storage::ser::StorageChanges;
 use rpc_ext::MakeInto as _;
 
@@ -53,8 +53,21 @@ pub async fn fetch_genesis_storage(client: &XtClient) -> Result<Vec<(Vec<u8>, Ve
 /// Fetch latest sequences for given sender
 pub async fn fetch_mq_ingress_seq(client: &XtClient, sender: MessageOrigin) -> Result<u64> {
     client
-        .fetch_or_default(&runtimes::phala_mq::OffchainIngressStore::new(sender), None)
+        .rpc
+        .get_mq_ingress_seq(sender.encode().into(), None)
         .await
-        .or(Ok(0))
+        .map_err(Into::into)
+}
+
+/// Fetch the registered worker, or `None` if the worker is not registered
+pub async fn fetch_worker_info(
+    client: &XtClient,
+    pubkey: WorkerPublicKey,
+) -> Result<Option<RegisteredWorker<AccountId>>> {
+    client
+        .rpc
+        .get_worker_info(pubkey, None)
+        .await
+        .map_err(Into::into)
 }
 
@@ -1,7 +1,9 @@
 use anyhow::{anyhow, Result};
 use log::{debug, error, info, warn};
 use phala_pallets::registry::Attestation;
+use phala_types::WorkerPublicKey;
 use std::cmp;
+use std::convert::TryFrom;
 use std::str::FromStr;
 use std::time::Duration;
 use structopt::StructOpt;
@@ -441,13 +443,6 @@ async fn batch_sync_block(
     Ok(synced_blocks)
 }
 
-async fn get_machine_owner(client: &XtClient, machine_id: Vec<u8>) -> Result<[u8; 32]> {
-    client
-        .fetch_or_default(&runtimes::phala::MachineOwnerStore::new(machine_id), None)
-        .await
-        .or(Ok([0u8; 32]))
-}
-
 /// Updates the nonce from the blockchain (system.account)
 async fn update_signer_nonce(client: &XtClient, signer: &mut SrSigner) -> Result<()> {
     // TODO: try to fetch the pending txs from mempool for a more accurate nonce
@@ -609,8 +604,12 @@ async fn bridge(args: Args) -> Result<()> {
             .ok();
         } else {
             info!("pRuntime already initialized. Fetching runtime info...");
-            let machine_owner = get_machine_owner(&client, info.machine_id).await?;
-            if machine_owner == [0u8; 32] {
+            let raw_pubkey = hex::decode(&info.public_key)
+                .map_err(|_| anyhow!("Bad worker pubkey hex"))?;
+            let pubkey = WorkerPublicKey::try_from(raw_pubkey.as_slice())
+                .map_err(|_| anyhow!("Bad worker pubkey"))?;
+            let worker = chain_client::fetch_worker_info(&client, pubkey).await?;
+            if worker.is_none() {
                 // Worker not registered
                 runtime_info = Some(
                     pr.req_decode("get_runtime_info", GetRuntimeInfoReq {})
@@ -7,6 +7,8 @@ use log::info;
 use bytes::buf::BufExt as _;
 use sp_runtime::DeserializeOwned;
 
+use phala_query_client::{ContractQuery, PreparedQuery};
+
 use crate::types::{RuntimeReq, Resp, SignedResp};
 
 pub struct PRuntimeClient {
@@ -81,4 +83,10 @@ impl PRuntimeClient {
         let result: Resp = serde_json::from_str(&resp.payload)?;
         Ok(result)
     }
+
+    /// Sends a contract query built by `phala_query_client::QueryBuilder` and decodes the reply
+    pub async fn query<C: ContractQuery>(&self, prepared: &PreparedQuery<C>) -> Result<C::Response> {
+        let reply = self.req_decode("query", prepared.query.clone()).await?;
+        prepared.decode_reply(reply)
+    }
 }
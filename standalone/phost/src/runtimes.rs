@@ -147,13 +147,12 @@ pub mod grandpa {
 }
 
 pub mod phala {
-    use codec::{Encode, Decode};
+    use codec::Decode;
     use subxt::{
-        module, Store,
+        module,
         system::System,
         balances::Balances
     };
-    use core::marker::PhantomData;
 
     use phala_types::{messaging::BlockRewardInfo, PayoutReason, WorkerPublicKey};
 
@@ -163,22 +162,6 @@ pub mod phala {
         #![event_type(PayoutReason)]
         #![event_type(WorkerPublicKey)]
     }
-
-    #[derive(Clone, Debug, Eq, PartialEq, Store, Encode)]
-    pub struct MachineOwnerStore<T: Phala> {
-        #[store(returns = [u8; 32])]
-        /// Runtime marker.
-        pub _runtime: PhantomData<T>,
-        pub machine_id: Vec<u8>,
-    }
-    impl<T: Phala> MachineOwnerStore<T> {
-        pub fn new(machine_id: Vec<u8>) -> Self {
-            Self {
-                _runtime: Default::default(),
-                machine_id,
-            }
-        }
-    }
 }
 
 pub mod phala_registry {
@@ -207,9 +190,9 @@ pub mod phala_registry {
 pub mod phala_mq {
     use codec::Encode;
     use core::marker::PhantomData;
-    use subxt::{balances::Balances, module, system::System, Call, Store};
+    use subxt::{balances::Balances, module, system::System, Call};
 
-    use phala_types::messaging::{MessageOrigin, SignedMessage};
+    use phala_types::messaging::SignedMessage;
 
     #[module]
     pub trait PhalaMq: System + Balances {}
@@ -219,23 +202,6 @@ pub mod phala_mq {
         pub _runtime: PhantomData<T>,
         pub message: SignedMessage,
     }
-
-    #[derive(Clone, Debug, Eq, PartialEq, Store, Encode)]
-    pub struct OffchainIngressStore<T: PhalaMq> {
-        #[store(returns = u64)]
-        /// Runtime marker.
-        pub _runtime: PhantomData<T>,
-        pub sender: MessageOrigin,
-    }
-
-    impl<T: PhalaMq> OffchainIngressStore<T> {
-        pub fn new(sender: MessageOrigin) -> Self {
-            Self {
-                _runtime: Default::default(),
-                sender,
-            }
-        }
-    }
 }
 
 pub mod mining_staking {
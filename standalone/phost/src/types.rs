@@ -20,6 +20,7 @@ pub type Hash = <Runtime as subxt::system::System>::Hash;
 pub type OpaqueBlock = sp_runtime::generic::Block<Header, OpaqueExtrinsic>;
 pub type OpaqueSignedBlock = SignedBlock<OpaqueBlock>;
 pub type BlockNumber = <Runtime as subxt::system::System>::BlockNumber;
+pub type AccountId = <Runtime as subxt::system::System>::AccountId;
 
 // pRuntime APIs
 
@@ -182,6 +183,10 @@ impl Resp for GetEgressMessagesReq {
     type Resp = GetEgressMessagesResp;
 }
 
+impl Resp for phala_query_client::SignedQuery {
+    type Resp = phala_query_client::Payload;
+}
+
 pub mod utils {
     use super::StorageProof;
     use subxt::ReadProof;
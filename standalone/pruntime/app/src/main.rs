@@ -373,6 +373,7 @@ delegate_rpc!("/test", test, actions::ACTION_TEST);
 delegate_rpc!("/init_runtime", init_runtime, actions::ACTION_INIT_RUNTIME);
 delegate_rpc!("/get_info", get_info, actions::ACTION_GET_INFO);
 delegate_rpc!("/get_runtime_info", get_runtime_info, actions::ACTION_GET_RUNTIME_INFO);
+delegate_rpc!("/list_contracts", list_contracts, actions::ACTION_LIST_CONTRACTS);
 delegate_rpc!("/dump_states", dump_states, actions::ACTION_DUMP_STATES);
 delegate_rpc!("/load_states", load_states, actions::ACTION_LOAD_STATES);
 delegate_rpc!("/sync_header", sync_header, actions::ACTION_SYNC_HEADER);
@@ -380,6 +381,10 @@ delegate_rpc!("/query", query, actions::ACTION_QUERY);
 delegate_rpc!("/dispatch_block", dispatch_block, actions::ACTION_DISPATCH_BLOCK);
 // TODO.kevin: becareful the limitation of ENCLAVE_OUTPUT_BUF_MAX_LEN
 delegate_rpc!("/get_egress_messages", get_egress_messages, actions::ACTION_GET_EGRESS_MESSAGES);
+delegate_rpc!("/rotate_keys", rotate_keys, actions::ACTION_ROTATE_KEYS);
+delegate_rpc!("/prepare_migration", prepare_migration, actions::ACTION_PREPARE_MIGRATION);
+delegate_rpc!("/export_secrets", export_secrets, actions::ACTION_EXPORT_SECRETS);
+delegate_rpc!("/import_secrets", import_secrets, actions::ACTION_IMPORT_SECRETS);
 delegate_rpc!("/test_ink", test_ink, actions::ACTION_TEST_INK);
 
 
@@ -412,7 +417,8 @@ fn rocket() -> rocket::Rocket {
             test, init_runtime, get_info,
             dump_states, load_states,
             sync_header, dispatch_block, query,
-            get_runtime_info, get_egress_messages, test_ink,
+            get_runtime_info, list_contracts, get_egress_messages, rotate_keys,
+            prepare_migration, export_secrets, import_secrets, test_ink,
             bin_api::sync_header_bin,
             bin_api::dispatch_block_bin,
             ]);
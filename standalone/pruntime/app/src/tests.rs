@@ -9,6 +9,9 @@ struct Plain {
     Plain: String
 }
 
+// The requests are made to the site "1", registered by `Web3AnalyticsCommand::RegisterSite` with
+// the hash of the API key "test_api_key".
+
 //SetPageView
 #[derive(Serialize, Deserialize)]
 struct PageViewCount {
@@ -130,7 +133,7 @@ fn test_w3a_setpageview() {
 
     let input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":655605,\\\"request\\\":{\\\"SetPageView\\\":{\\\"page_views\\\":[{\\\"id\\\":\\\"2cfcb3eb-a38e-494f-b6c0-a38c9cd2c267\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page1.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600675693},{\\\"id\\\":\\\"80aad35c-7d58-494d-ba95-be95c011eb2c\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page1.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600730399}],\\\"encrypted\\\":false}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":655605,\\\"request\\\":{\\\"SetPageView\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"page_views\\\":[{\\\"id\\\":\\\"2cfcb3eb-a38e-494f-b6c0-a38c9cd2c267\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page1.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600675693},{\\\"id\\\":\\\"80aad35c-7d58-494d-ba95-be95c011eb2c\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page1.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600730399}],\\\"encrypted\\\":false}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -156,7 +159,7 @@ fn test_w3a_getonlineusers() {
 
     let mut input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":655605,\\\"request\\\":{\\\"SetPageView\\\":{\\\"page_views\\\":[{\\\"id\\\":\\\"2cfcb3eb-a38e-494f-b6c0-a38c9cd2c267\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page1.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600675693},{\\\"id\\\":\\\"80aad35c-7d58-494d-ba95-be95c011eb2c\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page1.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600730399}],\\\"encrypted\\\":false}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":655605,\\\"request\\\":{\\\"SetPageView\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"page_views\\\":[{\\\"id\\\":\\\"2cfcb3eb-a38e-494f-b6c0-a38c9cd2c267\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page1.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600675693},{\\\"id\\\":\\\"80aad35c-7d58-494d-ba95-be95c011eb2c\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page1.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600730399}],\\\"encrypted\\\":false}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -174,7 +177,7 @@ fn test_w3a_getonlineusers() {
 
     input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":123165,\\\"request\\\":{\\\"GetOnlineUsers\\\":{\\\"start\\\":1600675680,\\\"end\\\":1600742280}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":123165,\\\"request\\\":{\\\"GetOnlineUsers\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"start\\\":1600675680,\\\"end\\\":1600742280}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -205,7 +208,7 @@ fn test_w3a_gethourlystats() {
 
     let mut input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":752078,\\\"request\\\":{\\\"SetPageView\\\":{\\\"page_views\\\":[{\\\"id\\\":\\\"47ca3f6f-d296-447e-90e8-5ef10e4b713e\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/page2.html\\\",\\\"referrer\\\":\\\"/index.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822028},{\\\"id\\\":\\\"81bee3d3-3b3c-4366-ba08-1ee9884e29ee\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page2.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822045},{\\\"id\\\":\\\"5effeb8f-b62e-43ae-a7ab-69fc49efb8ab\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/page1.html\\\",\\\"referrer\\\":\\\"/index.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822081}],\\\"encrypted\\\":false}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":752078,\\\"request\\\":{\\\"SetPageView\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"page_views\\\":[{\\\"id\\\":\\\"47ca3f6f-d296-447e-90e8-5ef10e4b713e\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/page2.html\\\",\\\"referrer\\\":\\\"/index.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822028},{\\\"id\\\":\\\"81bee3d3-3b3c-4366-ba08-1ee9884e29ee\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page2.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822045},{\\\"id\\\":\\\"5effeb8f-b62e-43ae-a7ab-69fc49efb8ab\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/page1.html\\\",\\\"referrer\\\":\\\"/index.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822081}],\\\"encrypted\\\":false}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -223,7 +226,7 @@ fn test_w3a_gethourlystats() {
 
     input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":448170,\\\"request\\\":{\\\"GetHourlyStats\\\":{\\\"start\\\":1600822020,\\\"end\\\":1600822800,\\\"start_of_week\\\":1600646400}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":448170,\\\"request\\\":{\\\"GetHourlyStats\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"start\\\":1600822020,\\\"end\\\":1600822800,\\\"start_of_week\\\":1600646400}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -300,7 +303,7 @@ fn test_w3a_clearpageview() {
 
     let mut input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":752078,\\\"request\\\":{\\\"SetPageView\\\":{\\\"page_views\\\":[{\\\"id\\\":\\\"47ca3f6f-d296-447e-90e8-5ef10e4b713e\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/page2.html\\\",\\\"referrer\\\":\\\"/index.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822028},{\\\"id\\\":\\\"81bee3d3-3b3c-4366-ba08-1ee9884e29ee\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page2.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822045},{\\\"id\\\":\\\"5effeb8f-b62e-43ae-a7ab-69fc49efb8ab\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/page1.html\\\",\\\"referrer\\\":\\\"/index.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822081}],\\\"encrypted\\\":false}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":752078,\\\"request\\\":{\\\"SetPageView\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"page_views\\\":[{\\\"id\\\":\\\"47ca3f6f-d296-447e-90e8-5ef10e4b713e\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/page2.html\\\",\\\"referrer\\\":\\\"/index.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822028},{\\\"id\\\":\\\"81bee3d3-3b3c-4366-ba08-1ee9884e29ee\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/index.html\\\",\\\"referrer\\\":\\\"/page2.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822045},{\\\"id\\\":\\\"5effeb8f-b62e-43ae-a7ab-69fc49efb8ab\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"http://localhost:9000\\\",\\\"path\\\":\\\"/page1.html\\\",\\\"referrer\\\":\\\"/index.html\\\",\\\"ip\\\":\\\"::1\\\",\\\"user_agent\\\":\\\"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36\\\",\\\"created_at\\\":1600822081}],\\\"encrypted\\\":false}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -318,7 +321,7 @@ fn test_w3a_clearpageview() {
 
     input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":503566,\\\"request\\\":{\\\"ClearPageView\\\":{\\\"timestamp\\\":1600826400}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":503566,\\\"request\\\":{\\\"ClearPageView\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"timestamp\\\":1600826400}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -343,7 +346,7 @@ fn test_w3a_encrypted_getonlineusers() {
 
     let mut input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":472339,\\\"request\\\":{\\\"SetPageView\\\":{\\\"page_views\\\":[{\\\"id\\\":\\\"2cfcb3eb-a38e-494f-b6c0-a38c9cd2c267\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"Rl1ZDc82BIoTipjA|FtXjNvvJCwj6S7IimvP8BzmySwoFH9PIM64sj02FQxGJY+ob7g==\\\",\\\"path\\\":\\\"7/yzt7blRiXvgVvC|fZbun6AiTf3BwOjFLx47frnXeDiiJfTx/IBT\\\",\\\"referrer\\\":\\\"0lT0tnE/PMCcarWR|b1Y3k81MomkrHzfDR3d8/9iQdLNP/nHUKrTR\\\",\\\"ip\\\":\\\"w2K5n/ZRtof8p9P0|29vBKOrRVDRLIBnRK6F1isoB6A==\\\",\\\"user_agent\\\":\\\"iWjDOvbhkRciGetZ|KweE9m2FimyYRM86eJM2aLcs4OPOUy+UHtTN8jhZVDLjiJeV+SGFr/loy+6/e/xdYCK9BfYTB0UErcljABtva7OEgNtKTK2cygW0Sb/QXwB3EhqpmABoMMVLR4NPOkqTqFiwC3vdxxT52Is4xZfJA6GUTD44FZfbOg==\\\",\\\"created_at\\\":1600675693},{\\\"id\\\":\\\"80aad35c-7d58-494d-ba95-be95c011eb2c\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"D0+sobfMLVjxknzm|WefGXy36Ovr2pukulGX5fieIBVWFzOelqx2EsuVBQ5OkgwAwCw==\\\",\\\"path\\\":\\\"I2188wkIE7brW4go|2o81adRytEimk27P5tnj96V2HTBOVCjTvMFf\\\",\\\"referrer\\\":\\\"JmtxXYxtW2ZH8cH/|A1DloMwS5F85vIM5h27ZGqLFM3R8hSPrzPIK\\\",\\\"ip\\\":\\\"q3Lybr/NRVC7DxCu|d8bvmbSmqSkxAkJ0Ed3boKFWRg==\\\",\\\"user_agent\\\":\\\"x+Br1zBK2glAPBW6|0+QwC2Rr+m3uWdD4u59VGxmGI4bBkFWJsTEH6NK3Mis8ejxASHkPu9pYAfvyPL3Er/5iagdHgoaJs7V6LpTCvBgs2vVb2f7Oa+xYtLOEgYuuzQgM03Fmlhd/CEAmOoBCoZdgjFzENNzT5Oh47Uc5OJFALRSgzRXXyw==\\\",\\\"created_at\\\":1600730399}],\\\"encrypted\\\":true}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":472339,\\\"request\\\":{\\\"SetPageView\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"page_views\\\":[{\\\"id\\\":\\\"2cfcb3eb-a38e-494f-b6c0-a38c9cd2c267\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"Rl1ZDc82BIoTipjA|FtXjNvvJCwj6S7IimvP8BzmySwoFH9PIM64sj02FQxGJY+ob7g==\\\",\\\"path\\\":\\\"7/yzt7blRiXvgVvC|fZbun6AiTf3BwOjFLx47frnXeDiiJfTx/IBT\\\",\\\"referrer\\\":\\\"0lT0tnE/PMCcarWR|b1Y3k81MomkrHzfDR3d8/9iQdLNP/nHUKrTR\\\",\\\"ip\\\":\\\"w2K5n/ZRtof8p9P0|29vBKOrRVDRLIBnRK6F1isoB6A==\\\",\\\"user_agent\\\":\\\"iWjDOvbhkRciGetZ|KweE9m2FimyYRM86eJM2aLcs4OPOUy+UHtTN8jhZVDLjiJeV+SGFr/loy+6/e/xdYCK9BfYTB0UErcljABtva7OEgNtKTK2cygW0Sb/QXwB3EhqpmABoMMVLR4NPOkqTqFiwC3vdxxT52Is4xZfJA6GUTD44FZfbOg==\\\",\\\"created_at\\\":1600675693},{\\\"id\\\":\\\"80aad35c-7d58-494d-ba95-be95c011eb2c\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"D0+sobfMLVjxknzm|WefGXy36Ovr2pukulGX5fieIBVWFzOelqx2EsuVBQ5OkgwAwCw==\\\",\\\"path\\\":\\\"I2188wkIE7brW4go|2o81adRytEimk27P5tnj96V2HTBOVCjTvMFf\\\",\\\"referrer\\\":\\\"JmtxXYxtW2ZH8cH/|A1DloMwS5F85vIM5h27ZGqLFM3R8hSPrzPIK\\\",\\\"ip\\\":\\\"q3Lybr/NRVC7DxCu|d8bvmbSmqSkxAkJ0Ed3boKFWRg==\\\",\\\"user_agent\\\":\\\"x+Br1zBK2glAPBW6|0+QwC2Rr+m3uWdD4u59VGxmGI4bBkFWJsTEH6NK3Mis8ejxASHkPu9pYAfvyPL3Er/5iagdHgoaJs7V6LpTCvBgs2vVb2f7Oa+xYtLOEgYuuzQgM03Fmlhd/CEAmOoBCoZdgjFzENNzT5Oh47Uc5OJFALRSgzRXXyw==\\\",\\\"created_at\\\":1600730399}],\\\"encrypted\\\":true}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -361,7 +364,7 @@ fn test_w3a_encrypted_getonlineusers() {
 
     input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":123165,\\\"request\\\":{\\\"GetOnlineUsers\\\":{\\\"start\\\":1600675680,\\\"end\\\":1600742280}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":123165,\\\"request\\\":{\\\"GetOnlineUsers\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"start\\\":1600675680,\\\"end\\\":1600742280}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -393,7 +396,7 @@ fn test_w3a_encrypted_gethourlystats() {
 
     let mut input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":545691,\\\"request\\\":{\\\"SetPageView\\\":{\\\"page_views\\\":[{\\\"id\\\":\\\"47ca3f6f-d296-447e-90e8-5ef10e4b713e\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"FtQA33sGdPDvSyxF|Jdsv5XME/P9CS/wKDSBbnoHTD0ziW19vB4R/KBkOXq43+O5+Ug==\\\",\\\"path\\\":\\\"cAiW+neSQQgKgzxK|bXT9fgwMJ/jCnBQ4yUTvCrt2wMZHoQTthe1t\\\",\\\"referrer\\\":\\\"oJZgGO1rkokNkxFQ|RG742mfs/tmxoKa7IgIf57wyHBXOKU6LTFex\\\",\\\"ip\\\":\\\"tg/sHe6tw3+5qxvQ|Nt/oU4Z+m4Q65j+QivJwGwyPsA==\\\",\\\"user_agent\\\":\\\"Tl8jKhJwK437PJyE|kU6rS8ZUS1I6pr+C8pUaJqSTmPcJjBEkYjWugcIj9PxGKOCLz5kNkrBgkTQ3eHCE790O4wFtQeA+ectpvhwPqG4aPPy+CxNCPLm79i+zyUaAgQPEvZfyoui5b35A4V8zH6kB3KHs77ZOX8Y/AfdUY4bcn3MeVJYHDQ==\\\",\\\"created_at\\\":1600822028},{\\\"id\\\":\\\"81bee3d3-3b3c-4366-ba08-1ee9884e29ee\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"kdg4FwbuCaNIe1N2|CKRaZd93Me87UtQ7DSNfB/vsDZAc2olri5Mu7FgLpPfwOkSfHw==\\\",\\\"path\\\":\\\"9V4pIkq1NH9BS4Ff|8aeZHcRcyJwupgq+FDECwxPsnNoLvPw2s1WH\\\",\\\"referrer\\\":\\\"fzNM5mJGVoJGcf3+|X5xWc8oWFGrusYNMruYRmtiUSOXncFMEBFR0\\\",\\\"ip\\\":\\\"XGAskyzn0fpSJOCM|d22loW3Xq8mKBGkfLn1IIwbPXQ==\\\",\\\"user_agent\\\":\\\"A42kO++N47wL9qIj|/fzJGVQq297JtOrbD4FlxcZ/QdRuqS5N6wdWKzKoSfWaWGmAJh9X4SjmSczCk4aSaPQlvt/N+DFzrzVhygLj4HWMx3KPWamTeCFfn30yYBN0SgfZ5Xcup8qAoOduzJz1wLC1uKz6YRRsx2jGuKQ45JGBAa1y7je6fQ==\\\",\\\"created_at\\\":1600822045},{\\\"id\\\":\\\"5effeb8f-b62e-43ae-a7ab-69fc49efb8ab\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"1/i9ec00/9XqKDQk|9YahTCR4CLB3y0tClKcJoclqCQV7iNIVwDeDzL8yl1wqzLCmdw==\\\",\\\"path\\\":\\\"UZ8ctCVjiOjTjNeA|woAlibmVqumKswBSp61kumOrOxD03z/xYLmm\\\",\\\"referrer\\\":\\\"fnwNh1B57GpHOkAt|2z7j6Mwiqii5chLSeWTqDB1alqK8/dYoditv\\\",\\\"ip\\\":\\\"XFe1Wsk6AngoLCT1|sZsFyF5mvmUcbn5UawsHocClwQ==\\\",\\\"user_agent\\\":\\\"rrzy2qlU8eHc2sls|YCQj1I/Hq/6nmxO1VDxX3bUwy4d/zaDjT7qD980oeTGtheyGiyKCCj4tCF9sQi0SpQDdj2YaWq0fvn6suPVmGwRElGzE6B7wGm7Howt/Tu5gOb53y+9I/+fcO+VL5T3SDz6u2N/p4bt4GtsNppVUHcitMw62z4Z0Uw==\\\",\\\"created_at\\\":1600822081}],\\\"encrypted\\\":true}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":545691,\\\"request\\\":{\\\"SetPageView\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"page_views\\\":[{\\\"id\\\":\\\"47ca3f6f-d296-447e-90e8-5ef10e4b713e\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"FtQA33sGdPDvSyxF|Jdsv5XME/P9CS/wKDSBbnoHTD0ziW19vB4R/KBkOXq43+O5+Ug==\\\",\\\"path\\\":\\\"cAiW+neSQQgKgzxK|bXT9fgwMJ/jCnBQ4yUTvCrt2wMZHoQTthe1t\\\",\\\"referrer\\\":\\\"oJZgGO1rkokNkxFQ|RG742mfs/tmxoKa7IgIf57wyHBXOKU6LTFex\\\",\\\"ip\\\":\\\"tg/sHe6tw3+5qxvQ|Nt/oU4Z+m4Q65j+QivJwGwyPsA==\\\",\\\"user_agent\\\":\\\"Tl8jKhJwK437PJyE|kU6rS8ZUS1I6pr+C8pUaJqSTmPcJjBEkYjWugcIj9PxGKOCLz5kNkrBgkTQ3eHCE790O4wFtQeA+ectpvhwPqG4aPPy+CxNCPLm79i+zyUaAgQPEvZfyoui5b35A4V8zH6kB3KHs77ZOX8Y/AfdUY4bcn3MeVJYHDQ==\\\",\\\"created_at\\\":1600822028},{\\\"id\\\":\\\"81bee3d3-3b3c-4366-ba08-1ee9884e29ee\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"kdg4FwbuCaNIe1N2|CKRaZd93Me87UtQ7DSNfB/vsDZAc2olri5Mu7FgLpPfwOkSfHw==\\\",\\\"path\\\":\\\"9V4pIkq1NH9BS4Ff|8aeZHcRcyJwupgq+FDECwxPsnNoLvPw2s1WH\\\",\\\"referrer\\\":\\\"fzNM5mJGVoJGcf3+|X5xWc8oWFGrusYNMruYRmtiUSOXncFMEBFR0\\\",\\\"ip\\\":\\\"XGAskyzn0fpSJOCM|d22loW3Xq8mKBGkfLn1IIwbPXQ==\\\",\\\"user_agent\\\":\\\"A42kO++N47wL9qIj|/fzJGVQq297JtOrbD4FlxcZ/QdRuqS5N6wdWKzKoSfWaWGmAJh9X4SjmSczCk4aSaPQlvt/N+DFzrzVhygLj4HWMx3KPWamTeCFfn30yYBN0SgfZ5Xcup8qAoOduzJz1wLC1uKz6YRRsx2jGuKQ45JGBAa1y7je6fQ==\\\",\\\"created_at\\\":1600822045},{\\\"id\\\":\\\"5effeb8f-b62e-43ae-a7ab-69fc49efb8ab\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"1/i9ec00/9XqKDQk|9YahTCR4CLB3y0tClKcJoclqCQV7iNIVwDeDzL8yl1wqzLCmdw==\\\",\\\"path\\\":\\\"UZ8ctCVjiOjTjNeA|woAlibmVqumKswBSp61kumOrOxD03z/xYLmm\\\",\\\"referrer\\\":\\\"fnwNh1B57GpHOkAt|2z7j6Mwiqii5chLSeWTqDB1alqK8/dYoditv\\\",\\\"ip\\\":\\\"XFe1Wsk6AngoLCT1|sZsFyF5mvmUcbn5UawsHocClwQ==\\\",\\\"user_agent\\\":\\\"rrzy2qlU8eHc2sls|YCQj1I/Hq/6nmxO1VDxX3bUwy4d/zaDjT7qD980oeTGtheyGiyKCCj4tCF9sQi0SpQDdj2YaWq0fvn6suPVmGwRElGzE6B7wGm7Howt/Tu5gOb53y+9I/+fcO+VL5T3SDz6u2N/p4bt4GtsNppVUHcitMw62z4Z0Uw==\\\",\\\"created_at\\\":1600822081}],\\\"encrypted\\\":true}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -411,7 +414,7 @@ fn test_w3a_encrypted_gethourlystats() {
 
     input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":448170,\\\"request\\\":{\\\"GetHourlyStats\\\":{\\\"start\\\":1600822020,\\\"end\\\":1600822800,\\\"start_of_week\\\":1600646400}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":448170,\\\"request\\\":{\\\"GetHourlyStats\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"start\\\":1600822020,\\\"end\\\":1600822800,\\\"start_of_week\\\":1600646400}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -458,7 +461,7 @@ fn test_w3a_encrypted_getdailystats() {
     // Sending SetPageView request just tell TEE it works in encrypted mode
     let mut input_string = r#"{
       "input": {
-        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":545691,\\\"request\\\":{\\\"SetPageView\\\":{\\\"page_views\\\":[{\\\"id\\\":\\\"47ca3f6f-d296-447e-90e8-5ef10e4b713e\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"FtQA33sGdPDvSyxF|Jdsv5XME/P9CS/wKDSBbnoHTD0ziW19vB4R/KBkOXq43+O5+Ug==\\\",\\\"path\\\":\\\"cAiW+neSQQgKgzxK|bXT9fgwMJ/jCnBQ4yUTvCrt2wMZHoQTthe1t\\\",\\\"referrer\\\":\\\"oJZgGO1rkokNkxFQ|RG742mfs/tmxoKa7IgIf57wyHBXOKU6LTFex\\\",\\\"ip\\\":\\\"tg/sHe6tw3+5qxvQ|Nt/oU4Z+m4Q65j+QivJwGwyPsA==\\\",\\\"user_agent\\\":\\\"Tl8jKhJwK437PJyE|kU6rS8ZUS1I6pr+C8pUaJqSTmPcJjBEkYjWugcIj9PxGKOCLz5kNkrBgkTQ3eHCE790O4wFtQeA+ectpvhwPqG4aPPy+CxNCPLm79i+zyUaAgQPEvZfyoui5b35A4V8zH6kB3KHs77ZOX8Y/AfdUY4bcn3MeVJYHDQ==\\\",\\\"created_at\\\":1600822028},{\\\"id\\\":\\\"81bee3d3-3b3c-4366-ba08-1ee9884e29ee\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"kdg4FwbuCaNIe1N2|CKRaZd93Me87UtQ7DSNfB/vsDZAc2olri5Mu7FgLpPfwOkSfHw==\\\",\\\"path\\\":\\\"9V4pIkq1NH9BS4Ff|8aeZHcRcyJwupgq+FDECwxPsnNoLvPw2s1WH\\\",\\\"referrer\\\":\\\"fzNM5mJGVoJGcf3+|X5xWc8oWFGrusYNMruYRmtiUSOXncFMEBFR0\\\",\\\"ip\\\":\\\"XGAskyzn0fpSJOCM|d22loW3Xq8mKBGkfLn1IIwbPXQ==\\\",\\\"user_agent\\\":\\\"A42kO++N47wL9qIj|/fzJGVQq297JtOrbD4FlxcZ/QdRuqS5N6wdWKzKoSfWaWGmAJh9X4SjmSczCk4aSaPQlvt/N+DFzrzVhygLj4HWMx3KPWamTeCFfn30yYBN0SgfZ5Xcup8qAoOduzJz1wLC1uKz6YRRsx2jGuKQ45JGBAa1y7je6fQ==\\\",\\\"created_at\\\":1600822045},{\\\"id\\\":\\\"5effeb8f-b62e-43ae-a7ab-69fc49efb8ab\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"1/i9ec00/9XqKDQk|9YahTCR4CLB3y0tClKcJoclqCQV7iNIVwDeDzL8yl1wqzLCmdw==\\\",\\\"path\\\":\\\"UZ8ctCVjiOjTjNeA|woAlibmVqumKswBSp61kumOrOxD03z/xYLmm\\\",\\\"referrer\\\":\\\"fnwNh1B57GpHOkAt|2z7j6Mwiqii5chLSeWTqDB1alqK8/dYoditv\\\",\\\"ip\\\":\\\"XFe1Wsk6AngoLCT1|sZsFyF5mvmUcbn5UawsHocClwQ==\\\",\\\"user_agent\\\":\\\"rrzy2qlU8eHc2sls|YCQj1I/Hq/6nmxO1VDxX3bUwy4d/zaDjT7qD980oeTGtheyGiyKCCj4tCF9sQi0SpQDdj2YaWq0fvn6suPVmGwRElGzE6B7wGm7Howt/Tu5gOb53y+9I/+fcO+VL5T3SDz6u2N/p4bt4GtsNppVUHcitMw62z4Z0Uw==\\\",\\\"created_at\\\":1600822081}],\\\"encrypted\\\":true}}}\"}"
+        "query_payload": "{\"Plain\":\"{\\\"contract_id\\\":4,\\\"nonce\\\":545691,\\\"request\\\":{\\\"SetPageView\\\":{\\\"sid\\\":\\\"1\\\",\\\"api_key\\\":\\\"test_api_key\\\",\\\"page_views\\\":[{\\\"id\\\":\\\"47ca3f6f-d296-447e-90e8-5ef10e4b713e\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"FtQA33sGdPDvSyxF|Jdsv5XME/P9CS/wKDSBbnoHTD0ziW19vB4R/KBkOXq43+O5+Ug==\\\",\\\"path\\\":\\\"cAiW+neSQQgKgzxK|bXT9fgwMJ/jCnBQ4yUTvCrt2wMZHoQTthe1t\\\",\\\"referrer\\\":\\\"oJZgGO1rkokNkxFQ|RG742mfs/tmxoKa7IgIf57wyHBXOKU6LTFex\\\",\\\"ip\\\":\\\"tg/sHe6tw3+5qxvQ|Nt/oU4Z+m4Q65j+QivJwGwyPsA==\\\",\\\"user_agent\\\":\\\"Tl8jKhJwK437PJyE|kU6rS8ZUS1I6pr+C8pUaJqSTmPcJjBEkYjWugcIj9PxGKOCLz5kNkrBgkTQ3eHCE790O4wFtQeA+ectpvhwPqG4aPPy+CxNCPLm79i+zyUaAgQPEvZfyoui5b35A4V8zH6kB3KHs77ZOX8Y/AfdUY4bcn3MeVJYHDQ==\\\",\\\"created_at\\\":1600822028},{\\\"id\\\":\\\"81bee3d3-3b3c-4366-ba08-1ee9884e29ee\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"kdg4FwbuCaNIe1N2|CKRaZd93Me87UtQ7DSNfB/vsDZAc2olri5Mu7FgLpPfwOkSfHw==\\\",\\\"path\\\":\\\"9V4pIkq1NH9BS4Ff|8aeZHcRcyJwupgq+FDECwxPsnNoLvPw2s1WH\\\",\\\"referrer\\\":\\\"fzNM5mJGVoJGcf3+|X5xWc8oWFGrusYNMruYRmtiUSOXncFMEBFR0\\\",\\\"ip\\\":\\\"XGAskyzn0fpSJOCM|d22loW3Xq8mKBGkfLn1IIwbPXQ==\\\",\\\"user_agent\\\":\\\"A42kO++N47wL9qIj|/fzJGVQq297JtOrbD4FlxcZ/QdRuqS5N6wdWKzKoSfWaWGmAJh9X4SjmSczCk4aSaPQlvt/N+DFzrzVhygLj4HWMx3KPWamTeCFfn30yYBN0SgfZ5Xcup8qAoOduzJz1wLC1uKz6YRRsx2jGuKQ45JGBAa1y7je6fQ==\\\",\\\"created_at\\\":1600822045},{\\\"id\\\":\\\"5effeb8f-b62e-43ae-a7ab-69fc49efb8ab\\\",\\\"sid\\\":\\\"1\\\",\\\"cid\\\":\\\"d540041d837820e4a5a868c9c45d40ad\\\",\\\"uid\\\":\\\"\\\",\\\"host\\\":\\\"1/i9ec00/9XqKDQk|9YahTCR4CLB3y0tClKcJoclqCQV7iNIVwDeDzL8yl1wqzLCmdw==\\\",\\\"path\\\":\\\"UZ8ctCVjiOjTjNeA|woAlibmVqumKswBSp61kumOrOxD03z/xYLmm\\\",\\\"referrer\\\":\\\"fnwNh1B57GpHOkAt|2z7j6Mwiqii5chLSeWTqDB1alqK8/dYoditv\\\",\\\"ip\\\":\\\"XFe1Wsk6AngoLCT1|sZsFyF5mvmUcbn5UawsHocClwQ==\\\",\\\"user_agent\\\":\\\"rrzy2qlU8eHc2sls|YCQj1I/Hq/6nmxO1VDxX3bUwy4d/zaDjT7qD980oeTGtheyGiyKCCj4tCF9sQi0SpQDdj2YaWq0fvn6suPVmGwRElGzE6B7wGm7Howt/Tu5gOb53y+9I/+fcO+VL5T3SDz6u2N/p4bt4GtsNppVUHcitMw62z4Z0Uw==\\\",\\\"created_at\\\":1600822081}],\\\"encrypted\\\":true}}}\"}"
       },
       "nonce": {
         "id": 1
@@ -12,6 +12,11 @@ pub mod actions {
     pub const ACTION_DISPATCH_BLOCK: u8 = 7;
     // Reserved: 8, 9
     pub const ACTION_GET_RUNTIME_INFO: u8 = 10;
+    pub const ACTION_ROTATE_KEYS: u8 = 11;
+    pub const ACTION_PREPARE_MIGRATION: u8 = 12;
+    pub const ACTION_EXPORT_SECRETS: u8 = 13;
+    pub const ACTION_IMPORT_SECRETS: u8 = 14;
+    pub const ACTION_LIST_CONTRACTS: u8 = 15;
     pub const ACTION_GET_EGRESS_MESSAGES: u8 = 23;
     pub const ACTION_TEST_INK: u8 = 100;
 }
@@ -1,46 +1,40 @@
 use crate::std;
+use crate::std::collections::BTreeMap;
+use crate::std::sync::SgxMutex;
+use crate::std::vec::Vec;
 
 use core::sync::atomic::{AtomicU64, AtomicBool, Ordering};
 use log::debug;
+use phala_types::benchmark::{self, Challenge};
 
 // TODO.kevin: block_box will do best-effort to prevent compiler optimizations, but not guaranteed.
 use core::hint::black_box;
 
-const UNIT: usize = 1;
-const MAX_NUM: u128 = 65536 * 128;
-
 static ITERATION_COUNTER: AtomicU64 = AtomicU64::new(0);
 static PAUSED: AtomicBool = AtomicBool::new(false);
 static SCORE: AtomicU64 = AtomicU64::new(0);
-
-fn is_prime(num: u128) -> bool {
-    let tmp = num - 1;
-    for i in tmp..=2 {
-        if num % black_box(i) == 0 {
-            return false;
-        }
-    }
-    true
-}
-
-fn count_prime(max: u128) -> usize {
-    let mut count = 0;
-    for i in 2..max {
-        if is_prime(black_box(i)) {
-            count += 1;
-        }
-    }
-    count
+// Only written while the benchmark is paused
+static CHALLENGE: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+lazy_static! {
+    // The outputs of the iterations of the current session, to be committed in the report
+    static ref OUTPUTS: SgxMutex<BTreeMap<u64, u64>> = Default::default();
 }
 
 pub fn run() {
     let since = now();
     let start = iteration_counter();
     loop {
-        for _ in 0..UNIT {
-            let _ = black_box(count_prime(black_box(MAX_NUM)));
-        }
+        // Each thread claims the next iteration index, so that the iterations counted are exactly
+        // the ones whose outputs can be sampled.
         let count = ITERATION_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let output = black_box(benchmark::run_iteration(&challenge(), black_box(count)));
+        OUTPUTS.lock().unwrap().insert(count, output);
         if count % 100 == 0 {
             let score = est_score(since, start);
             debug!(
@@ -62,6 +56,36 @@ pub fn iteration_counter() -> u64 {
 
 pub fn reset_iteration_counter() {
     ITERATION_COUNTER.store(0, Ordering::Relaxed);
+    OUTPUTS.lock().unwrap().clear();
+}
+
+/// Takes the outputs of the iterations finished in a row from the first one.
+///
+/// The iterations still running when the benchmark is paused are left out.
+pub fn take_outputs() -> Vec<u64> {
+    let outputs = core::mem::take(&mut *OUTPUTS.lock().unwrap());
+    outputs
+        .into_iter()
+        .enumerate()
+        .take_while(|(i, (index, _))| *i as u64 == *index)
+        .map(|(_, (_, output))| output)
+        .collect()
+}
+
+pub fn set_challenge(challenge: &Challenge) {
+    for (word, chunk) in CHALLENGE.iter().zip(challenge.chunks(8)) {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(chunk);
+        word.store(u64::from_le_bytes(buf), Ordering::Relaxed);
+    }
+}
+
+pub fn challenge() -> Challenge {
+    let mut challenge = [0u8; 32];
+    for (word, chunk) in CHALLENGE.iter().zip(challenge.chunks_mut(8)) {
+        chunk.copy_from_slice(&word.load(Ordering::Relaxed).to_le_bytes());
+    }
+    challenge
 }
 
 pub fn puase() {
@@ -85,8 +109,8 @@ fn est_score(since: u64, start: u64) -> u64 {
     if now <= since {
         return 0;
     }
-    // Normalize to 6s (standard block time)
-    (ITERATION_COUNTER.load(Ordering::Relaxed) - start) * 6 / (now - since)
+    let iterations = ITERATION_COUNTER.load(Ordering::Relaxed) - start;
+    benchmark::score(iterations, now - since) as u64
 }
 
 fn debugging() -> bool {
@@ -1,4 +1,5 @@
-use crate::std::string::String;
+use crate::std::collections::VecDeque;
+use crate::std::string::{String, ToString};
 use crate::std::vec::Vec;
 use anyhow::Result;
 use core::{fmt, str};
@@ -11,43 +12,39 @@ use super::{NativeContext, TransactionStatus};
 use crate::contracts;
 use crate::contracts::AccountIdWrapper;
 use crate::types::TxRef;
-use phala_types::messaging::{AssetCommand, AssetId, PushCommand};
+use phala_types::messaging::{AssetCommand, AssetEvent, AssetId, PushCommand};
 
 type Command = AssetCommand<chain::AccountId, chain::Balance>;
+type Event = AssetEvent<chain::AccountId, chain::Balance>;
 
 extern crate runtime as chain;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct AssetMetadata {
-    owner: AccountIdWrapper,
-    #[serde(with = "super::serde_balance")]
-    total_supply: u128,
-    symbol: String,
-    id: u32,
-}
+/// The maximum number of transactions kept in the history of an account
+pub const MAX_HISTORY_PER_ACCOUNT: usize = 1000;
+/// The maximum number of accounts with a history. The least recently active account is dropped
+/// when it's exceeded.
+pub const MAX_HISTORY_ACCOUNTS: usize = 10_000;
+/// The maximum number of items returned by `Request::History` and `Request::ListAssets`
+pub const MAX_ITEMS_PER_PAGE: u32 = 100;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct AssetMetadataBalance {
-    metadata: AssetMetadata,
-    #[serde(with = "super::serde_balance")]
-    balance: chain::Balance,
-}
+pub use phala_contract_queries::assets::AssetsTxKind;
+
+pub type AssetMetadata = phala_contract_queries::assets::AssetMetadata<AccountIdWrapper>;
+pub type AssetMetadataBalance =
+    phala_contract_queries::assets::AssetMetadataBalance<AccountIdWrapper>;
+pub type AssetsTx = phala_contract_queries::assets::AssetsTx<AccountIdWrapper>;
+pub type Request = phala_contract_queries::assets::Request<AccountIdWrapper>;
+pub type Response = phala_contract_queries::assets::Response<AccountIdWrapper>;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Assets {
     next_id: u32,
     assets: BTreeMap<u32, BTreeMap<AccountIdWrapper, chain::Balance>>,
+    /// asset id => owner => spender => allowance
+    allowances:
+        BTreeMap<AssetId, BTreeMap<AccountIdWrapper, BTreeMap<AccountIdWrapper, chain::Balance>>>,
     metadata: BTreeMap<u32, AssetMetadata>,
-    history: BTreeMap<AccountIdWrapper, Vec<AssetsTx>>,
-}
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct AssetsTx {
-    txref: TxRef,
-    asset_id: u32,
-    from: AccountIdWrapper,
-    to: AccountIdWrapper,
-    #[serde(with = "super::serde_balance")]
-    amount: chain::Balance,
+    history: BTreeMap<AccountIdWrapper, VecDeque<AssetsTx>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -65,45 +62,6 @@ impl fmt::Display for Error {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum Request {
-    Balance {
-        id: AssetId,
-        account: AccountIdWrapper,
-    },
-    TotalSupply {
-        id: AssetId,
-    },
-    Metadata,
-    History {
-        account: AccountIdWrapper,
-    },
-    ListAssets {
-        available_only: bool,
-    },
-}
-#[derive(Serialize, Deserialize, Debug)]
-pub enum Response {
-    Balance {
-        #[serde(with = "super::serde_balance")]
-        balance: chain::Balance,
-    },
-    TotalSupply {
-        #[serde(with = "super::serde_balance")]
-        total_issuance: chain::Balance,
-    },
-    Metadata {
-        metadata: Vec<AssetMetadata>,
-    },
-    History {
-        history: Vec<AssetsTx>,
-    },
-    ListAssets {
-        assets: Vec<AssetMetadataBalance>,
-    },
-    Error(#[serde(with = "super::serde_anyhow")] anyhow::Error),
-}
-
 impl Assets {
     pub fn new() -> Self {
         let assets = BTreeMap::<u32, BTreeMap<AccountIdWrapper, chain::Balance>>::new();
@@ -111,10 +69,388 @@ impl Assets {
         Assets {
             next_id: 0,
             assets,
+            allowances: Default::default(),
             metadata,
             history: Default::default(),
         }
     }
+
+    /// Returns the metadata of the asset `id` if `account` is its owner
+    fn owned_metadata(
+        &mut self,
+        id: AssetId,
+        account: &AccountIdWrapper,
+    ) -> Result<&mut AssetMetadata, TransactionStatus> {
+        let metadatum = self
+            .metadata
+            .get_mut(&id)
+            .ok_or(TransactionStatus::AssetIdNotFound)?;
+        if &metadatum.owner != account {
+            return Err(TransactionStatus::NotAssetOwner);
+        }
+        Ok(metadatum)
+    }
+
+    fn transfer(
+        &mut self,
+        id: AssetId,
+        from: &AccountIdWrapper,
+        to: &AccountIdWrapper,
+        value: chain::Balance,
+    ) -> Result<(), TransactionStatus> {
+        let accounts = self
+            .assets
+            .get_mut(&id)
+            .ok_or(TransactionStatus::AssetIdNotFound)?;
+        info!(
+            "Transfer: [{}] -> [{}]: {}",
+            from.to_string(),
+            to.to_string(),
+            value
+        );
+        let src_amount = accounts.get_mut(from).ok_or(TransactionStatus::NoBalance)?;
+        if *src_amount < value {
+            return Err(TransactionStatus::InsufficientBalance);
+        }
+        let src0 = *src_amount;
+        *src_amount -= value;
+        let dest_amount = accounts.entry(to.clone()).or_default();
+        let dest0 = *dest_amount;
+        // Can't overflow since the sum of the balances is the total supply
+        *dest_amount += value;
+
+        info!("   src: {:>20} -> {:>20}", src0, src0 - value);
+        info!("  dest: {:>20} -> {:>20}", dest0, dest0 + value);
+        Ok(())
+    }
+
+    fn record(&mut self, tx: AssetsTx) {
+        self.push_history(&tx.from, &tx);
+        if tx.to != tx.from {
+            self.push_history(&tx.to, &tx);
+        }
+    }
+
+    fn push_history(&mut self, account: &AccountIdWrapper, tx: &AssetsTx) {
+        if !self.history.contains_key(account) && self.history.len() >= MAX_HISTORY_ACCOUNTS {
+            self.evict_history();
+        }
+        let slot = self.history.entry(account.clone()).or_default();
+        if slot.len() >= MAX_HISTORY_PER_ACCOUNT {
+            slot.pop_front();
+        }
+        slot.push_back(tx.clone());
+    }
+
+    /// Drops the history of the account whose last transaction is the oldest
+    fn evict_history(&mut self) {
+        let oldest = self
+            .history
+            .iter()
+            .min_by_key(|(_, txs)| txs.back().map(|tx| (tx.txref.blocknum, tx.txref.index)))
+            .map(|(account, _)| account.clone());
+        if let Some(account) = oldest {
+            self.history.remove(&account);
+        }
+    }
+
+    /// Sends the event to the chain if the asset opted in
+    fn emit(&self, context: &NativeContext, id: AssetId, event: Event) {
+        if let Some(metadatum) = self.metadata.get(&id) {
+            if metadatum.public_events {
+                context.mq().send(&event);
+            }
+        }
+    }
+
+    fn issue(
+        &mut self,
+        o: &AccountIdWrapper,
+        symbol: String,
+        total: chain::Balance,
+    ) -> Result<AssetId, TransactionStatus> {
+        info!("Issue: [{}] -> [{}]: {}", o.to_string(), symbol, total);
+
+        if self
+            .metadata
+            .values()
+            .any(|metadatum| metadatum.symbol == symbol)
+        {
+            return Err(TransactionStatus::SymbolExist);
+        }
+        let mut accounts = BTreeMap::<AccountIdWrapper, chain::Balance>::new();
+        accounts.insert(o.clone(), total);
+
+        let id = self.next_id;
+        let metadatum = AssetMetadata {
+            owner: o.clone(),
+            total_supply: total,
+            name: symbol.clone(),
+            symbol,
+            id,
+            decimals: 0,
+            public_events: false,
+        };
+
+        self.metadata.insert(id, metadatum);
+        self.assets.insert(id, accounts);
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    fn approve(
+        &mut self,
+        id: AssetId,
+        owner: &AccountIdWrapper,
+        spender: &AccountIdWrapper,
+        value: chain::Balance,
+    ) -> Result<(), TransactionStatus> {
+        if !self.metadata.contains_key(&id) {
+            return Err(TransactionStatus::AssetIdNotFound);
+        }
+        let approved = self
+            .allowances
+            .entry(id)
+            .or_default()
+            .entry(owner.clone())
+            .or_default();
+        if value == 0 {
+            approved.remove(spender);
+        } else {
+            approved.insert(spender.clone(), value);
+        }
+        Ok(())
+    }
+
+    fn allowance(
+        &self,
+        id: AssetId,
+        owner: &AccountIdWrapper,
+        spender: &AccountIdWrapper,
+    ) -> chain::Balance {
+        self.allowances
+            .get(&id)
+            .and_then(|approved| approved.get(owner))
+            .and_then(|approved| approved.get(spender))
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    fn transfer_from(
+        &mut self,
+        txref: TxRef,
+        id: AssetId,
+        spender: &AccountIdWrapper,
+        owner: &AccountIdWrapper,
+        dest: &AccountIdWrapper,
+        value: chain::Balance,
+    ) -> Result<(), TransactionStatus> {
+        let allowance = self.allowance(id, owner, spender);
+        if allowance < value {
+            return Err(TransactionStatus::InsufficientAllowance);
+        }
+        self.transfer(id, owner, dest, value)?;
+        if let Some(approved) = self
+            .allowances
+            .get_mut(&id)
+            .and_then(|approved| approved.get_mut(owner))
+        {
+            if allowance == value {
+                approved.remove(spender);
+            } else {
+                approved.insert(spender.clone(), allowance - value);
+            }
+        }
+        self.record(AssetsTx {
+            txref,
+            asset_id: id,
+            kind: AssetsTxKind::Transfer,
+            from: owner.clone(),
+            to: dest.clone(),
+            amount: value,
+        });
+        Ok(())
+    }
+
+    fn mint(
+        &mut self,
+        txref: TxRef,
+        id: AssetId,
+        o: &AccountIdWrapper,
+        dest: &AccountIdWrapper,
+        value: chain::Balance,
+    ) -> Result<(), TransactionStatus> {
+        let metadatum = self.owned_metadata(id, o)?;
+        metadatum.total_supply = metadatum
+            .total_supply
+            .checked_add(value)
+            .ok_or(TransactionStatus::Overflow)?;
+        *self
+            .assets
+            .entry(id)
+            .or_default()
+            .entry(dest.clone())
+            .or_default() += value;
+        self.record(AssetsTx {
+            txref,
+            asset_id: id,
+            kind: AssetsTxKind::Mint,
+            from: o.clone(),
+            to: dest.clone(),
+            amount: value,
+        });
+        Ok(())
+    }
+
+    fn burn(
+        &mut self,
+        txref: TxRef,
+        id: AssetId,
+        o: &AccountIdWrapper,
+        value: chain::Balance,
+    ) -> Result<(), TransactionStatus> {
+        self.owned_metadata(id, o)?;
+        let balance = self
+            .assets
+            .get_mut(&id)
+            .and_then(|accounts| accounts.get_mut(o))
+            .ok_or(TransactionStatus::NoBalance)?;
+        if *balance < value {
+            return Err(TransactionStatus::InsufficientBalance);
+        }
+        *balance -= value;
+        self.owned_metadata(id, o)?.total_supply -= value;
+        self.record(AssetsTx {
+            txref,
+            asset_id: id,
+            kind: AssetsTxKind::Burn,
+            from: o.clone(),
+            to: o.clone(),
+            amount: value,
+        });
+        Ok(())
+    }
+
+    fn handle(
+        &mut self,
+        context: &NativeContext,
+        o: AccountIdWrapper,
+        cmd: PushCommand<Command>,
+    ) -> Result<(), TransactionStatus> {
+        let txref = TxRef {
+            blocknum: context.block_number,
+            index: cmd.number,
+        };
+        match cmd.command {
+            Command::Issue { symbol, total } => {
+                self.issue(&o, symbol, total)?;
+                Ok(())
+            }
+            Command::Destroy { id } => {
+                self.owned_metadata(id, &o)?;
+                self.metadata.remove(&id);
+                self.assets.remove(&id);
+                self.allowances.remove(&id);
+                Ok(())
+            }
+            Command::Transfer { id, dest, value } => {
+                let dest = AccountIdWrapper(dest);
+                self.transfer(id, &o, &dest, value)?;
+                self.record(AssetsTx {
+                    txref,
+                    asset_id: id,
+                    kind: AssetsTxKind::Transfer,
+                    from: o.clone(),
+                    to: dest.clone(),
+                    amount: value,
+                });
+                self.emit(
+                    context,
+                    id,
+                    Event::Transfer {
+                        id,
+                        from: o.0,
+                        to: dest.0,
+                        value,
+                    },
+                );
+                Ok(())
+            }
+            Command::Approve { id, spender, value } => {
+                let spender = AccountIdWrapper(spender);
+                self.approve(id, &o, &spender, value)?;
+                self.emit(
+                    context,
+                    id,
+                    Event::Approval {
+                        id,
+                        owner: o.0,
+                        spender: spender.0,
+                        value,
+                    },
+                );
+                Ok(())
+            }
+            Command::TransferFrom {
+                id,
+                owner,
+                dest,
+                value,
+            } => {
+                let owner = AccountIdWrapper(owner);
+                let dest = AccountIdWrapper(dest);
+                self.transfer_from(txref, id, &o, &owner, &dest, value)?;
+                self.emit(
+                    context,
+                    id,
+                    Event::Transfer {
+                        id,
+                        from: owner.0,
+                        to: dest.0,
+                        value,
+                    },
+                );
+                Ok(())
+            }
+            Command::Mint { id, dest, value } => {
+                let dest = AccountIdWrapper(dest);
+                self.mint(txref, id, &o, &dest, value)?;
+                self.emit(
+                    context,
+                    id,
+                    Event::Minted {
+                        id,
+                        dest: dest.0,
+                        value,
+                    },
+                );
+                Ok(())
+            }
+            Command::Burn { id, value } => {
+                self.burn(txref, id, &o, value)?;
+                self.emit(
+                    context,
+                    id,
+                    Event::Burned {
+                        id,
+                        from: o.0,
+                        value,
+                    },
+                );
+                Ok(())
+            }
+            Command::SetMetadata { id, name, decimals } => {
+                let metadatum = self.owned_metadata(id, &o)?;
+                metadatum.name = name;
+                metadatum.decimals = decimals;
+                Ok(())
+            }
+            Command::SetPublicEvents { id, enabled } => {
+                self.owned_metadata(id, &o)?.public_events = enabled;
+                Ok(())
+            }
+        }
+    }
 }
 
 impl contracts::NativeContract for Assets {
@@ -137,198 +473,236 @@ impl contracts::NativeContract for Assets {
             MessageOrigin::AccountId(acc) => acc,
             _ => return TransactionStatus::BadOrigin,
         };
+        match self.handle(context, AccountIdWrapper::from(origin), cmd) {
+            Ok(()) => TransactionStatus::Ok,
+            Err(status) => status,
+        }
+    }
 
-        match cmd.command {
-            Command::Issue { symbol, total } => {
-                let o = AccountIdWrapper::from(origin);
-                info!("Issue: [{}] -> [{}]: {}", o.to_string(), symbol, total);
-
-                if let None = self
-                    .metadata
-                    .iter()
-                    .find(|(_, metadatum)| metadatum.symbol == symbol)
-                {
-                    let mut accounts = BTreeMap::<AccountIdWrapper, chain::Balance>::new();
-                    accounts.insert(o.clone(), total);
-
-                    let id = self.next_id;
-                    let metadatum = AssetMetadata {
-                        owner: o.clone(),
-                        total_supply: total,
-                        symbol,
-                        id,
-                    };
-
-                    self.metadata.insert(id, metadatum);
-                    self.assets.insert(id.clone(), accounts);
-                    self.next_id += 1;
-
-                    TransactionStatus::Ok
-                } else {
-                    TransactionStatus::SymbolExist
-                }
-            }
-            Command::Destroy { id } => {
-                let o = AccountIdWrapper::from(origin);
-
-                if let Some(metadatum) = self.metadata.get(&id) {
-                    if metadatum.owner.to_string() == o.to_string() {
-                        self.metadata.remove(&id);
-                        self.assets.remove(&id);
-
-                        TransactionStatus::Ok
-                    } else {
-                        TransactionStatus::NotAssetOwner
-                    }
-                } else {
-                    TransactionStatus::AssetIdNotFound
-                }
-            }
-            Command::Transfer { id, dest, value } => {
-                let o = AccountIdWrapper::from(origin);
-                let dest = AccountIdWrapper(dest);
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        contracts::snapshot_of(self)
+    }
 
-                if let Some(metadatum) = self.metadata.get(&id) {
-                    let accounts = self.assets.get_mut(&metadatum.id).unwrap();
-
-                    info!(
-                        "Transfer: [{}] -> [{}]: {}",
-                        o.to_string(),
-                        dest.to_string(),
-                        value
-                    );
-                    if let Some(src_amount) = accounts.get_mut(&o) {
-                        if *src_amount >= value {
-                            let src0 = *src_amount;
-                            let mut dest0 = 0;
-
-                            *src_amount -= value;
-                            if let Some(dest_amount) = accounts.get_mut(&dest) {
-                                dest0 = *dest_amount;
-                                *dest_amount += value;
-                            } else {
-                                accounts.insert(dest.clone(), value);
-                            }
-
-                            info!("   src: {:>20} -> {:>20}", src0, src0 - value);
-                            info!("  dest: {:>20} -> {:>20}", dest0, dest0 + value);
-
-                            let txref = TxRef {
-                                blocknum: context.block_number,
-                                index: cmd.number,
-                            };
-                            let tx = AssetsTx {
-                                txref,
-                                asset_id: id,
-                                from: o.clone(),
-                                to: dest.clone(),
-                                amount: value,
-                            };
-                            if is_tracked(&o) {
-                                let slot = self.history.entry(o).or_default();
-                                slot.push(tx.clone());
-                                info!(" pushed history (src)");
-                            }
-                            if is_tracked(&dest) {
-                                let slot = self.history.entry(dest).or_default();
-                                slot.push(tx.clone());
-                                info!(" pushed history (dest)");
-                            }
-
-                            TransactionStatus::Ok
-                        } else {
-                            TransactionStatus::InsufficientBalance
-                        }
-                    } else {
-                        TransactionStatus::NoBalance
-                    }
-                } else {
-                    TransactionStatus::AssetIdNotFound
-                }
-            }
-        }
+    fn restore(&mut self, snapshot: &[u8]) -> Result<()> {
+        *self = contracts::restore_from(snapshot)?;
+        Ok(())
     }
 
     fn handle_query(&mut self, origin: Option<&chain::AccountId>, req: Self::QReq) -> Self::QResp {
         let inner = || -> Result<Response> {
+            let asset_not_found = || anyhow::Error::msg(Error::Other(String::from("Asset not found")));
             match req {
                 Request::Balance { id, account } => {
                     if origin == None || origin.unwrap() != &account.0 {
                         return Err(anyhow::Error::msg(Error::NotAuthorized));
                     }
 
-                    if let Some(metadatum) = self.metadata.get(&id) {
-                        let accounts = self.assets.get(&metadatum.id).unwrap();
-                        let mut balance: chain::Balance = 0;
-                        if let Some(ba) = accounts.get(&account) {
-                            balance = *ba;
-                        }
-                        Ok(Response::Balance { balance })
-                    } else {
-                        Err(anyhow::Error::msg(Error::Other(String::from(
-                            "Asset not found",
-                        ))))
-                    }
+                    let accounts = self.assets.get(&id).ok_or_else(asset_not_found)?;
+                    let balance = accounts.get(&account).cloned().unwrap_or(0);
+                    Ok(Response::Balance { balance })
                 }
                 Request::TotalSupply { id } => {
-                    if let Some(metadatum) = self.metadata.get(&id) {
-                        Ok(Response::TotalSupply {
-                            total_issuance: metadatum.total_supply,
-                        })
-                    } else {
-                        Err(anyhow::Error::msg(Error::Other(String::from(
-                            "Asset not found",
-                        ))))
-                    }
+                    let metadatum = self.metadata.get(&id).ok_or_else(asset_not_found)?;
+                    Ok(Response::TotalSupply {
+                        total_issuance: metadatum.total_supply,
+                    })
                 }
                 Request::Metadata => Ok(Response::Metadata {
                     metadata: self.metadata.values().cloned().collect(),
                 }),
-                Request::History { account } => {
-                    let tx_list = self
+                Request::Allowance { id, owner, spender } => {
+                    let raw_origin =
+                        origin.ok_or_else(|| anyhow::Error::msg(Error::NotAuthorized))?;
+                    if raw_origin != &owner.0 && raw_origin != &spender.0 {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    if !self.metadata.contains_key(&id) {
+                        return Err(asset_not_found());
+                    }
+                    let allowance = self.allowance(id, &owner, &spender);
+                    Ok(Response::Allowance { allowance })
+                }
+                Request::History {
+                    account,
+                    from,
+                    limit,
+                } => {
+                    if origin == None || origin.unwrap() != &account.0 {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    let limit = limit.min(MAX_ITEMS_PER_PAGE).max(1) as usize;
+                    let mut iter = self
                         .history
                         .get(&account)
-                        .cloned()
-                        .unwrap_or(Default::default());
-                    Ok(Response::History { history: tx_list })
+                        .into_iter()
+                        .flatten()
+                        .filter(|tx| tx.txref.index >= from);
+                    let history: Vec<_> = iter.by_ref().take(limit).cloned().collect();
+                    let next = iter.next().map(|tx| tx.txref.index);
+                    Ok(Response::History { history, next })
                 }
-                Request::ListAssets { available_only } => {
+                Request::ListAssets {
+                    available_only,
+                    from,
+                    limit,
+                } => {
                     let raw_origin =
                         origin.ok_or_else(|| anyhow::Error::msg(Error::NotAuthorized))?;
                     let o = AccountIdWrapper(raw_origin.clone());
-                    // TODO: simplify the two way logic here?
-                    let assets = if available_only {
-                        self.assets
-                            .iter()
-                            .filter_map(|(id, balances)| {
-                                balances.get(&o).filter(|b| **b > 0).map(|b| (id, *b))
-                            })
-                            .map(|(id, balance)| {
-                                let metadata = self.metadata.get(&id).unwrap().clone();
-                                AssetMetadataBalance { metadata, balance }
-                            })
-                            .collect()
-                    } else {
-                        self.assets
-                            .iter()
-                            .map(|(id, balances)| {
-                                let metadata = self.metadata.get(&id).unwrap().clone();
-                                let balance = *balances.get(&o).unwrap_or(&0);
-                                AssetMetadataBalance { metadata, balance }
-                            })
-                            .collect()
-                    };
-                    Ok(Response::ListAssets { assets })
+                    let limit = limit.min(MAX_ITEMS_PER_PAGE).max(1) as usize;
+                    let mut iter = self
+                        .assets
+                        .range(from..)
+                        .map(|(id, balances)| (id, *balances.get(&o).unwrap_or(&0)))
+                        .filter(|(_, balance)| !available_only || *balance > 0);
+                    let assets = iter
+                        .by_ref()
+                        .take(limit)
+                        .map(|(id, balance)| {
+                            let metadata = self.metadata.get(id).unwrap().clone();
+                            AssetMetadataBalance { metadata, balance }
+                        })
+                        .collect();
+                    let next = iter.next().map(|(id, _)| *id);
+                    Ok(Response::ListAssets { assets, next })
                 }
             }
         };
         match inner() {
-            Err(error) => Response::Error(error),
+            Err(error) => Response::Error(error.to_string()),
             Ok(resp) => resp,
         }
     }
 }
 
-fn is_tracked(_id: &AccountIdWrapper) -> bool {
-    false
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(n: u8) -> AccountIdWrapper {
+        AccountIdWrapper(chain::AccountId::from([n; 32]))
+    }
+
+    fn txref(index: u64) -> TxRef {
+        TxRef {
+            blocknum: 1,
+            index,
+        }
+    }
+
+    fn balance_of(assets: &Assets, id: AssetId, n: u8) -> chain::Balance {
+        assets.assets[&id].get(&account(n)).cloned().unwrap_or(0)
+    }
+
+    fn issued() -> (Assets, AssetId) {
+        let mut assets = Assets::new();
+        let id = assets.issue(&account(1), "PHA".to_string(), 100).unwrap();
+        (assets, id)
+    }
+
+    #[test]
+    fn transfer_from_consumes_the_allowance() {
+        let (mut assets, id) = issued();
+        assert!(matches!(
+            assets.transfer_from(txref(1), id, &account(2), &account(1), &account(3), 10),
+            Err(TransactionStatus::InsufficientAllowance)
+        ));
+        assets.approve(id, &account(1), &account(2), 30).unwrap();
+        assert_eq!(assets.allowance(id, &account(1), &account(2)), 30);
+
+        assets
+            .transfer_from(txref(2), id, &account(2), &account(1), &account(3), 10)
+            .unwrap();
+        assert_eq!(assets.allowance(id, &account(1), &account(2)), 20);
+        assert_eq!(balance_of(&assets, id, 1), 90);
+        assert_eq!(balance_of(&assets, id, 3), 10);
+        assert!(matches!(
+            assets.transfer_from(txref(3), id, &account(2), &account(1), &account(3), 21),
+            Err(TransactionStatus::InsufficientAllowance)
+        ));
+
+        // Spending all of it removes the allowance
+        assets
+            .transfer_from(txref(4), id, &account(2), &account(1), &account(3), 20)
+            .unwrap();
+        assert_eq!(assets.allowance(id, &account(1), &account(2)), 0);
+        assert!(assets.allowances[&id][&account(1)].is_empty());
+    }
+
+    #[test]
+    fn approve_zero_revokes() {
+        let (mut assets, id) = issued();
+        assert!(matches!(
+            assets.approve(id + 1, &account(1), &account(2), 30),
+            Err(TransactionStatus::AssetIdNotFound)
+        ));
+        assets.approve(id, &account(1), &account(2), 30).unwrap();
+        assets.approve(id, &account(1), &account(2), 0).unwrap();
+        assert_eq!(assets.allowance(id, &account(1), &account(2)), 0);
+        assert!(matches!(
+            assets.transfer_from(txref(1), id, &account(2), &account(1), &account(3), 1),
+            Err(TransactionStatus::InsufficientAllowance)
+        ));
+    }
+
+    #[test]
+    fn mint_and_burn_by_owner() {
+        let (mut assets, id) = issued();
+        assert!(matches!(
+            assets.mint(txref(1), id, &account(2), &account(2), 10),
+            Err(TransactionStatus::NotAssetOwner)
+        ));
+        assets.mint(txref(2), id, &account(1), &account(2), 10).unwrap();
+        assert_eq!(balance_of(&assets, id, 2), 10);
+        assert_eq!(assets.metadata[&id].total_supply, 110);
+        assert!(matches!(
+            assets.mint(txref(3), id, &account(1), &account(2), chain::Balance::MAX),
+            Err(TransactionStatus::Overflow)
+        ));
+        assert_eq!(assets.metadata[&id].total_supply, 110);
+
+        assert!(matches!(
+            assets.burn(txref(4), id, &account(1), 101),
+            Err(TransactionStatus::InsufficientBalance)
+        ));
+        assets.burn(txref(5), id, &account(1), 40).unwrap();
+        assert_eq!(balance_of(&assets, id, 1), 60);
+        assert_eq!(assets.metadata[&id].total_supply, 70);
+
+        let kinds: Vec<_> = assets.history[&account(1)].iter().map(|tx| tx.kind).collect();
+        assert_eq!(kinds, vec![AssetsTxKind::Mint, AssetsTxKind::Burn]);
+        assert_eq!(assets.history[&account(2)].len(), 1);
+    }
+
+    #[test]
+    fn history_accounts_are_bounded() {
+        let mut assets = Assets::new();
+        let tx = |index: u64, to: AccountIdWrapper| AssetsTx {
+            txref: txref(index),
+            asset_id: 0,
+            kind: AssetsTxKind::Transfer,
+            from: account(0),
+            to,
+            amount: 1,
+        };
+        let recipient = |index: u64| {
+            let mut to = [1u8; 32];
+            to[..8].copy_from_slice(&index.to_le_bytes());
+            AccountIdWrapper(to.into())
+        };
+        // The sender and the recipients fill up the history
+        for index in 0..MAX_HISTORY_ACCOUNTS as u64 - 1 {
+            assets.record(tx(index, recipient(index)));
+        }
+        assert_eq!(assets.history.len(), MAX_HISTORY_ACCOUNTS);
+        let first = recipient(0);
+        assert!(assets.history.contains_key(&first));
+
+        // The least recently active account is dropped
+        assets.record(tx(MAX_HISTORY_ACCOUNTS as u64, account(2)));
+        assert_eq!(assets.history.len(), MAX_HISTORY_ACCOUNTS);
+        assert!(!assets.history.contains_key(&first));
+        assert!(assets.history.contains_key(&account(0)));
+        assert!(assets.history.contains_key(&account(2)));
+    }
 }
@@ -1,9 +1,10 @@
 use crate::std::collections::BTreeMap;
-use crate::std::string::String;
+use crate::std::string::{String, ToString};
+use crate::std::vec::Vec;
 
 use anyhow::Result;
 use core::{fmt, str};
-use log::info;
+use log::{error, info, warn};
 use phala_mq::MessageOrigin;
 use serde::{Deserialize, Serialize};
 
@@ -14,14 +15,33 @@ use crate::contracts::{AccountIdWrapper, NativeContext};
 use crate::TransactionStatus;
 extern crate runtime as chain;
 
-use phala_types::messaging::{BalanceCommand, BalanceEvent, BalanceTransfer, PushCommand};
+use phala_types::messaging::{
+    BalanceCommand, BalanceEvent, BalancePayment, BalanceReport, PushCommand,
+};
 
 type Command = BalanceCommand<chain::AccountId, chain::Balance>;
 type Event = BalanceEvent<chain::AccountId, chain::Balance>;
+type Report = BalanceReport<chain::AccountId, chain::Balance>;
+type Payment = BalancePayment<chain::AccountId, chain::Balance>;
 
+/// The native token, backed by the funds escrowed in `pallet_escrow`.
+///
+/// Only the built-in instance handles the deposits and the withdrawals. The instances created by
+/// `pallet_registry::instantiate_contract` can't move funds from or to the chain.
+#[derive(Serialize, Deserialize)]
 pub struct Balances {
     total_issuance: chain::Balance,
     accounts: BTreeMap<AccountIdWrapper, chain::Balance>,
+    /// The number of the next deposit to credit
+    next_deposit: u64,
+    /// The number of the next withdrawal
+    next_withdrawal: u64,
+    /// The withdrawals not confirmed on chain yet, with the account to refund
+    pending_withdrawals: BTreeMap<u64, (AccountIdWrapper, chain::Balance)>,
+    /// The funds in `pallet_escrow`, as last reported by the chain
+    escrowed: chain::Balance,
+    /// The funds deposited by the deprecated `transfer_to_tee`, which are not escrowed
+    unescrowed: chain::Balance,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -39,30 +59,59 @@ impl fmt::Display for Error {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum Request {
-    FreeBalance { account: AccountIdWrapper },
-    TotalIssuance,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub enum Response {
-    FreeBalance {
-        #[serde(with = "super::serde_balance")]
-        balance: chain::Balance,
-    },
-    TotalIssuance {
-        #[serde(with = "super::serde_balance")]
-        total_issuance: chain::Balance,
-    },
-    Error(#[serde(with = "super::serde_anyhow")] anyhow::Error),
-}
+pub type Request = phala_contract_queries::balances::Request<AccountIdWrapper>;
+pub use phala_contract_queries::balances::Response;
 
 impl Balances {
     pub fn new() -> Self {
         Balances {
             total_issuance: 0,
             accounts: BTreeMap::new(),
+            next_deposit: 0,
+            next_withdrawal: 0,
+            pending_withdrawals: BTreeMap::new(),
+            escrowed: 0,
+            unescrowed: 0,
+        }
+    }
+
+    fn credit(&mut self, dest: AccountIdWrapper, amount: chain::Balance) {
+        info!("   dest: {}", dest.to_string());
+        let dest_amount = self.accounts.entry(dest).or_default();
+        let dest_amount0 = *dest_amount;
+        *dest_amount += amount;
+        info!("   value: {:>20} -> {:>20}", dest_amount0, *dest_amount);
+        self.total_issuance += amount;
+    }
+
+    fn transfer(
+        &mut self,
+        src: &AccountIdWrapper,
+        dest: AccountIdWrapper,
+        value: chain::Balance,
+    ) -> TransactionStatus {
+        if let Some(src_amount) = self.accounts.get_mut(src) {
+            if *src_amount >= value {
+                let src0 = *src_amount;
+                let mut dest0 = 0;
+
+                *src_amount -= value;
+                if let Some(dest_amount) = self.accounts.get_mut(&dest) {
+                    dest0 = *dest_amount;
+                    *dest_amount += value;
+                } else {
+                    self.accounts.insert(dest, value);
+                }
+
+                info!("   src: {:>20} -> {:>20}", src0, src0 - value);
+                info!("  dest: {:>20} -> {:>20}", dest0, dest0 + value);
+
+                TransactionStatus::Ok
+            } else {
+                TransactionStatus::InsufficientBalance
+            }
+        } else {
+            TransactionStatus::NoBalance
         }
     }
 }
@@ -83,14 +132,15 @@ impl contracts::NativeContract for Balances {
         origin: MessageOrigin,
         cmd: PushCommand<Command>,
     ) -> TransactionStatus {
-        let origin = match origin {
-            MessageOrigin::AccountId(acc) => acc,
+        // The contracts can pay with their own balances, e.g. to settle the orders of `DataPlaza`
+        let o = match origin {
+            MessageOrigin::AccountId(acc) => AccountIdWrapper(acc),
+            MessageOrigin::Contract(address) => AccountIdWrapper::from(address),
             _ => return TransactionStatus::BadOrigin,
         };
 
         let status = match cmd.command {
             Command::Transfer { dest, value } => {
-                let o = AccountIdWrapper::from(origin);
                 let dest = AccountIdWrapper(dest);
                 info!(
                     "Transfer: [{}] -> [{}]: {}",
@@ -98,37 +148,51 @@ impl contracts::NativeContract for Balances {
                     dest.to_string(),
                     value
                 );
-                if let Some(src_amount) = self.accounts.get_mut(&o) {
-                    if *src_amount >= value {
-                        let src0 = *src_amount;
-                        let mut dest0 = 0;
-
-                        *src_amount -= value;
-                        if let Some(dest_amount) = self.accounts.get_mut(&dest) {
-                            dest0 = *dest_amount;
-                            *dest_amount += value;
-                        } else {
-                            self.accounts.insert(dest, value);
+                self.transfer(&o, dest, value)
+            }
+            Command::Pay { dest, value, memo } => {
+                let payee = AccountIdWrapper(dest.clone());
+                info!(
+                    "Pay: [{}] -> [{}]: {}",
+                    o.to_string(),
+                    payee.to_string(),
+                    value
+                );
+                // An instance can't be paid before it can be told so
+                let instance = context.instance_of(&dest);
+                if let Some(address) = &instance {
+                    if !context.instance_reachable::<Payment>(address) {
+                        return TransactionStatus::InstanceNotReady;
+                    }
+                }
+                let status = self.transfer(&o, payee, value);
+                if let TransactionStatus::Ok = status {
+                    let payment = Payment {
+                        payer: o.0,
+                        payee: dest,
+                        amount: value,
+                        memo,
+                    };
+                    // The instances only receive the payments to themselves, encrypted
+                    match instance {
+                        Some(address) => {
+                            if let Err(e) = context.send_to_instance(&payment, &address) {
+                                error!("Failed to notify the payment: {:?}", e);
+                            }
                         }
-
-                        info!("   src: {:>20} -> {:>20}", src0, src0 - value);
-                        info!("  dest: {:>20} -> {:>20}", dest0, dest0 + value);
-
-                        TransactionStatus::Ok
-                    } else {
-                        TransactionStatus::InsufficientBalance
+                        None => context.mq().send(&payment),
                     }
-                } else {
-                    TransactionStatus::NoBalance
                 }
+                status
             }
             Command::TransferToChain { dest, value } => {
-                let o = AccountIdWrapper::from(origin);
-                let dest = AccountIdWrapper(dest);
+                if context.is_instance() {
+                    return TransactionStatus::TransferringNotAllowed;
+                }
                 info!(
                     "Transfer to chain: [{}] -> [{}]: {}",
                     o.to_string(),
-                    dest.to_string(),
+                    AccountIdWrapper(dest.clone()).to_string(),
                     value
                 );
                 if let Some(src_amount) = self.accounts.get_mut(&o) {
@@ -138,11 +202,14 @@ impl contracts::NativeContract for Balances {
                         self.total_issuance -= value;
                         info!("   src: {:>20} -> {:>20}", src0, src0 - value);
 
-                        let data = BalanceTransfer {
+                        let number = self.next_withdrawal;
+                        self.next_withdrawal += 1;
+                        self.pending_withdrawals.insert(number, (o, value));
+                        context.mq().send(&Report::Withdrawal {
+                            number,
                             dest,
                             amount: value,
-                        };
-                        context.mq().send(&data);
+                        });
                         TransactionStatus::Ok
                     } else {
                         TransactionStatus::InsufficientBalance
@@ -156,6 +223,15 @@ impl contracts::NativeContract for Balances {
         status
     }
 
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        contracts::snapshot_of(self)
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<()> {
+        *self = contracts::restore_from(snapshot)?;
+        Ok(())
+    }
+
     fn handle_query(&mut self, origin: Option<&chain::AccountId>, req: Request) -> Response {
         let inner = || -> Result<Response> {
             match req {
@@ -172,38 +248,102 @@ impl contracts::NativeContract for Balances {
                 Request::TotalIssuance => Ok(Response::TotalIssuance {
                     total_issuance: self.total_issuance,
                 }),
+                Request::Reconciliation => {
+                    let pending_withdrawals = self
+                        .pending_withdrawals
+                        .values()
+                        .map(|(_, amount)| *amount)
+                        .sum();
+                    Ok(Response::Reconciliation {
+                        total_issuance: self.total_issuance,
+                        pending_withdrawals,
+                        escrowed: self.escrowed,
+                        unescrowed: self.unescrowed,
+                        deposits: self.next_deposit,
+                        reconciled: self.total_issuance + pending_withdrawals
+                            == self.escrowed + self.unescrowed,
+                    })
+                }
             }
         };
         match inner() {
-            Err(error) => Response::Error(error),
+            Err(error) => Response::Error(error.to_string()),
             Ok(resp) => resp,
         }
     }
 
     fn handle_event(
         &mut self,
-        _context: &NativeContext,
+        context: &NativeContext,
         origin: MessageOrigin,
         event: Self::Event,
     ) {
-        if origin != chain::Phala::message_origin() {
-            error!("Received event from unexpected origin: {:?}", origin);
+        if context.is_instance() {
             return;
         }
         match event {
             Event::TransferToTee(who, amount) => {
+                if origin != chain::Phala::message_origin() {
+                    error!("Received event from unexpected origin: {:?}", origin);
+                    return;
+                }
                 info!("TransferToTee from :{:?}, {:}", who, amount);
-                let dest = AccountIdWrapper(who);
-                info!("   dest: {}", dest.to_string());
-                if let Some(dest_amount) = self.accounts.get_mut(&dest) {
-                    let dest_amount0 = *dest_amount;
-                    *dest_amount += amount;
-                    info!("   value: {:>20} -> {:>20}", dest_amount0, *dest_amount);
-                } else {
-                    self.accounts.insert(dest, amount);
-                    info!("   value: {:>20} -> {:>20}", 0, amount);
+                self.credit(AccountIdWrapper(who), amount);
+                self.unescrowed += amount;
+            }
+            Event::Deposit {
+                number,
+                who,
+                amount,
+                escrowed,
+            } => {
+                if origin != chain::PhalaEscrow::message_origin() {
+                    error!("Received event from unexpected origin: {:?}", origin);
+                    return;
+                }
+                if number < self.next_deposit {
+                    warn!("Deposit {} has been credited", number);
+                    return;
+                }
+                info!("Deposit {} from :{:?}, {:}", number, who, amount);
+                self.credit(AccountIdWrapper(who), amount);
+                self.next_deposit = number + 1;
+                self.escrowed = escrowed;
+                context.mq().send(&Report::DepositAccepted { number });
+            }
+            Event::WithdrawalConfirmed { number, escrowed } => {
+                if origin != chain::PhalaEscrow::message_origin() {
+                    error!("Received event from unexpected origin: {:?}", origin);
+                    return;
+                }
+                if self.pending_withdrawals.remove(&number).is_none() {
+                    warn!("Unknown withdrawal {}", number);
+                    return;
+                }
+                info!("Withdrawal {} confirmed", number);
+                self.escrowed = escrowed;
+            }
+            Event::WithdrawalFailed { number } => {
+                if origin != chain::PhalaEscrow::message_origin() {
+                    error!("Received event from unexpected origin: {:?}", origin);
+                    return;
+                }
+                match self.pending_withdrawals.remove(&number) {
+                    Some((refund_to, amount)) => {
+                        info!("Withdrawal {} failed, refunding {}", number, amount);
+                        self.credit(refund_to, amount);
+                    }
+                    None => warn!("Unknown withdrawal {}", number),
+                }
+            }
+            Event::LegacyEscrowed { amount, escrowed } => {
+                if origin != chain::PhalaEscrow::message_origin() {
+                    error!("Received event from unexpected origin: {:?}", origin);
+                    return;
                 }
-                self.total_issuance += amount;
+                info!("Legacy deposits escrowed: {}", amount);
+                self.unescrowed = self.unescrowed.saturating_sub(amount);
+                self.escrowed = escrowed;
             }
         }
     }
@@ -19,7 +19,7 @@ use parity_scale_codec::Encode;
 use phala_mq::{EcdsaMessageChannel as MessageChannel, MessageOrigin};
 use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
 use serde::{Deserialize, Serialize};
-use sp_core::{crypto::Pair, ecdsa, hashing::blake2_256, U256};
+use sp_core::{hashing::blake2_256, U256};
 use sp_runtime_interface::pass_by::PassByInner as _;
 
 use bitcoin;
@@ -38,7 +38,6 @@ use phala_types::messaging::{Lottery, LotteryCommand as Command, PushCommand, Tx
 use super::NativeContext;
 
 type SequenceType = u64;
-const ALICE: &'static str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
 const RBF: u32 = 0xffffffff - 2;
 lazy_static! {
     // 10000...000, used to tell if this is a NFT
@@ -51,10 +50,10 @@ pub struct BtcLottery {
     lottery_set: BTreeMap<u32, BTreeMap<String, PrivateKey>>,
     tx_set: Vec<Vec<u8>>,
     sequence: SequenceType, // Starting from zero
-    secret: Option<ecdsa::Pair>, // TODO: replace it with a seed.
+    /// The seed of the prize keys
+    secret: Option<[u8; 32]>,
     /// round_id => (txid, vout, amount)?
     utxo: BTreeMap<u32, BTreeMap<Address, (Txid, u32, u64)>>,
-    admin: AccountIdWrapper,
 }
 
 impl core::fmt::Debug for BtcLottery {
@@ -103,11 +102,10 @@ pub enum Response {
 
 impl BtcLottery {
     /// Initializes the contract
-    pub fn new(secret: Option<ecdsa::Pair>) -> Self {
+    pub fn new(secret: Option<[u8; 32]>) -> Self {
         let token_set = BTreeMap::<u32, Vec<String>>::new();
         let lottery_set = BTreeMap::<u32, BTreeMap<String, PrivateKey>>::new();
         let utxo = BTreeMap::<u32, BTreeMap<Address, (Txid, u32, u64)>>::new();
-        let admin = AccountIdWrapper::from_hex(ALICE).expect("Bad initial admin hex");
         BtcLottery {
             round_id: 0,
             token_set,
@@ -116,7 +114,6 @@ impl BtcLottery {
             sequence: 0,
             secret,
             utxo,
-            admin,
         }
     }
 
@@ -154,7 +151,7 @@ impl BtcLottery {
             }
             info!("new_round: n round_token: {}", round_token.len());
             let mut lottery_token = BTreeMap::<String, PrivateKey>::new();
-            let raw_seed = blake2_256(&Encode::encode(&(secret.to_raw_vec(), round_id)));
+            let raw_seed = blake2_256(&Encode::encode(&(secret.to_vec(), round_id)));
             let mut r: StdRng = SeedableRng::from_seed(raw_seed.clone());
             let sample = round_token
                 .iter()
@@ -317,7 +314,7 @@ impl contracts::NativeContract for BtcLottery {
 
     fn handle_command(
         &mut self,
-        _context: &NativeContext,
+        context: &NativeContext,
         origin: MessageOrigin,
         cmd: PushCommand<Command>,
     ) -> TransactionStatus {
@@ -337,26 +334,20 @@ impl contracts::NativeContract for BtcLottery {
                     Ok(e) => e,
                     Err(_) => return TransactionStatus::BadCommand,
                 };
-                if self.admin == sender {
-                    let round_utxo = match self.utxo.entry(round_id) {
-                        Occupied(entry) => return TransactionStatus::BadCommand,
-                        Vacant(entry) => entry.insert(Default::default()),
-                    };
-                    round_utxo.insert(btc_address, utxo);
+                if !context.is_admin(&sender) {
+                    return TransactionStatus::NotAdmin;
                 }
+                let round_utxo = match self.utxo.entry(round_id) {
+                    Occupied(entry) => return TransactionStatus::BadCommand,
+                    Vacant(entry) => entry.insert(Default::default()),
+                };
+                round_utxo.insert(btc_address, utxo);
                 TransactionStatus::Ok
             }
-            Command::SetAdmin { new_admin } => {
-                // TODO: listen to some specific privileged account instead of ALICE
-                let sender = AccountIdWrapper(origin);
-                if let Ok(new_admin) = AccountIdWrapper::from_hex(&new_admin) {
-                    if self.admin == sender {
-                        self.admin = new_admin;
-                    }
-                    TransactionStatus::Ok
-                } else {
-                    TransactionStatus::InvalidAccount
-                }
+            Command::SetAdmin { .. } => {
+                // The admin is set on chain by `pallet_registry::ContractAdmin`
+                error!("SetAdmin is replaced by PhalaRegistry.transfer_contract_admin");
+                TransactionStatus::BadCommand
             }
         }
     }
@@ -0,0 +1,602 @@
+use super::{NativeContext, TransactionStatus};
+use crate::std::fmt;
+use crate::std::prelude::v1::*;
+use crate::std::vec::Vec;
+use anyhow::Result;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::contracts;
+use crate::contracts::AccountIdWrapper;
+use crate::types::TxRef;
+use parity_scale_codec::{Decode, Encode};
+use phala_mq::{bind_topic, BindTopic, MessageOrigin, Path};
+use phala_types::messaging::{
+    contract_command_topic, BalanceCommand, BalancePayment, PushCommand,
+};
+use sp_core::hashing::blake2_256;
+
+use self::query::Query;
+use self::store::DatasetStore;
+
+mod query;
+mod store;
+
+pub type ItemId = u32;
+pub type OrderId = u32;
+
+type Payment = BalancePayment<chain::AccountId, chain::Balance>;
+
+bind_topic!(Command, b"phala/data_plaza/command");
+#[derive(Encode, Decode, Debug)]
+pub enum Command {
+    List(ItemDetails),
+    /// Opens an order to be paid by `BalanceCommand::Pay` to the contract, with the encoded order
+    /// id as the memo. The payment is the budget of the order.
+    OpenOrder(OrderDetails),
+    /// Runs the query of a paid order. Only for the buyer.
+    ///
+    /// The worker holding the dataset and the query of the order runs it and reports the outcome
+    /// by `Settle`, which pays the seller for the matched rows and refunds the rest of the budget.
+    Execute { order_id: OrderId },
+    /// Cancels an order not settled yet, refunding its payment. Only for the buyer.
+    CancelOrder { order_id: OrderId },
+    /// The outcome of an executing order. Only from a registered worker.
+    Settle { order_id: OrderId, outcome: Outcome },
+}
+
+#[derive(Serialize, Deserialize, Encode, Decode, Debug, Clone)]
+pub struct OrderDetails {
+    item_id: ItemId,
+    /// The blake2_256 hash of the query, which the uploaded query must match
+    query_hash: [u8; 32],
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub enum Outcome {
+    Matched {
+        matched_rows: u64,
+        output_rows: u64,
+        result_bytes: u64,
+        truncated: bool,
+        /// The blake2_256 hash of the result
+        result_hash: [u8; 32],
+    },
+    Failed(String),
+}
+
+#[derive(Serialize, Deserialize, Encode, Decode, Debug, Clone)]
+pub struct ItemDetails {
+    pub name: String,
+    pub category: String,
+    pub description: String,
+    pub price: PricePolicy,
+    pub dataset_link: String,
+    pub dataset_preview: String,
+    /// The blake2_256 hash of the dataset, which the uploaded dataset must match
+    pub dataset_hash: [u8; 32],
+}
+
+#[derive(Serialize, Deserialize, Encode, Decode, Debug, Clone)]
+pub enum PricePolicy {
+    PerRow { price: chain::Balance },
+}
+
+// item
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Item {
+    id: ItemId,
+    txref: TxRef,
+    seller: AccountIdWrapper,
+    details: ItemDetails,
+    /// Whether the seller has uploaded the dataset
+    dataset_ready: bool,
+}
+
+// order
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Order {
+    id: OrderId,
+    txref: TxRef,
+    buyer: AccountIdWrapper,
+    details: OrderDetails,
+    state: OrderState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum OrderStatus {
+    AwaitingPayment,
+    Paid,
+    /// Waiting for the outcome reported by a worker
+    Executing,
+    Settled,
+    Cancelled,
+    /// The query failed and the payment is refunded
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderState {
+    status: OrderStatus,
+    /// Whether the buyer has uploaded the query
+    query_ready: bool,
+    #[serde(with = "super::serde_balance")]
+    paid: chain::Balance,
+    /// The price of the matched rows, paid to the seller
+    #[serde(with = "super::serde_balance")]
+    charged: chain::Balance,
+    matched_rows: u64,
+    result_rows: u64,
+    result_bytes: u64,
+    /// Whether the matched rows are cut by the budget or the limit of the query
+    truncated: bool,
+    result_hash: Option<[u8; 32]>,
+    error: Option<String>,
+}
+
+// contract
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Error {
+    NotAuthorized,
+    ItemNotFound,
+    OrderNotFound,
+    BadOrderState,
+    NotReady,
+    HashMismatch,
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotAuthorized => write!(f, "not authorized"),
+            Error::ItemNotFound => write!(f, "item not found"),
+            Error::OrderNotFound => write!(f, "order not found"),
+            Error::BadOrderState => write!(f, "bad order state"),
+            Error::NotReady => write!(f, "not ready"),
+            Error::HashMismatch => write!(f, "hash mismatch"),
+            Error::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Request {
+    GetItems,
+    /// The orders of the origin, as the buyer or the seller
+    GetOrders,
+    /// Uploads the dataset of an item in base64. Only for the seller.
+    UploadDataset { item_id: ItemId, data: String },
+    /// Uploads the query of an order, see the `query` module. Only for the buyer.
+    UploadQuery { order_id: OrderId, query: String },
+    /// The result of an order in base64. Only for the buyer.
+    GetResult { order_id: OrderId },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    GetItems { items: Vec<Item> },
+    GetOrders { orders: Vec<Order> },
+    Uploaded,
+    GetResult { data: String },
+    Error(#[serde(with = "super::serde_anyhow")] anyhow::Error),
+}
+
+/// A marketplace of datasets, priced by the rows matched by the queries of the buyers.
+///
+/// The datasets, queries and results are kept in a `DatasetStore` of the worker they are uploaded
+/// to, while their hashes are committed on chain by `List` and `OpenOrder`. Only the worker holding
+/// the dataset and the query of an order can run it, so it reports the outcome on chain by
+/// `Command::Settle`, and all the workers settle the order the same way. The orders are paid and
+/// settled through `Balances`, so the amounts are public on chain while the datasets and the
+/// queries are not.
+pub struct DataPlaza {
+    items: Vec<Item>,
+    orders: Vec<Order>,
+    store: DatasetStore,
+    /// The number of transfers sent to `Balances`, to number their commands
+    transfers: u64,
+}
+
+fn dataset_file(item_id: ItemId) -> String {
+    format!("item/{}/dataset", item_id)
+}
+
+fn query_file(order_id: OrderId) -> String {
+    format!("order/{}/query", order_id)
+}
+
+fn result_file(order_id: OrderId) -> String {
+    format!("order/{}/result", order_id)
+}
+
+impl DataPlaza {
+    pub fn new(store_dir: String, store_key: [u8; 32]) -> Self {
+        Self {
+            items: Vec::<Item>::new(),
+            orders: Vec::<Order>::new(),
+            store: DatasetStore::new(store_dir, store_key),
+            transfers: 0,
+        }
+    }
+
+    /// Transfers `value` from the balance of the contract to `dest` in `Balances`
+    fn transfer(
+        &mut self,
+        context: &NativeContext,
+        dest: &AccountIdWrapper,
+        value: chain::Balance,
+    ) {
+        if value == 0 {
+            return;
+        }
+        // Keep clear of the numbers of the commands pushed on chain
+        let hash =
+            blake2_256(&(b"data_plaza/transfer", context.address(), self.transfers).encode());
+        let mut number = [0u8; 8];
+        number.copy_from_slice(&hash[..8]);
+        self.transfers += 1;
+        context.mq().send(&PushCommand {
+            command: BalanceCommand::<chain::AccountId, chain::Balance>::Transfer {
+                dest: dest.0.clone(),
+                value,
+            },
+            number: u64::from_le_bytes(number),
+        });
+    }
+
+    /// The topic of the commands to this contract
+    fn command_topic(context: &NativeContext) -> Path {
+        if context.is_instance() {
+            contract_command_topic(context.address())
+        } else {
+            Command::TOPIC.to_vec()
+        }
+    }
+
+    fn execute(
+        &mut self,
+        context: &NativeContext,
+        o: &AccountIdWrapper,
+        order_id: OrderId,
+    ) -> Result<(), TransactionStatus> {
+        let order = self
+            .orders
+            .get_mut(order_id as usize)
+            .ok_or(TransactionStatus::OrderNotFound)?;
+        if &order.buyer != o {
+            return Err(TransactionStatus::BadOrigin);
+        }
+        if order.state.status != OrderStatus::Paid {
+            return Err(TransactionStatus::BadOrderState);
+        }
+        order.state.status = OrderStatus::Executing;
+
+        if let Some(outcome) = self.run(order_id) {
+            let number =
+                blake2_256(&(b"data_plaza/settle", context.address(), order_id).encode());
+            let mut number_bytes = [0u8; 8];
+            number_bytes.copy_from_slice(&number[..8]);
+            context.worker_mq().sendto(
+                &PushCommand {
+                    command: Command::Settle { order_id, outcome },
+                    number: u64::from_le_bytes(number_bytes),
+                },
+                Self::command_topic(context),
+            );
+        }
+        Ok(())
+    }
+
+    /// Runs the query of an order, if this worker holds its dataset and query
+    fn run(&self, order_id: OrderId) -> Option<Outcome> {
+        let order = &self.orders[order_id as usize];
+        let item = &self.items[order.details.item_id as usize];
+        let PricePolicy::PerRow { price } = item.details.price;
+        let dataset = self.store.get(&dataset_file(item.id)).ok().flatten()?;
+        let query = self.store.get(&query_file(order_id)).ok().flatten()?;
+
+        let max_rows = if price == 0 {
+            None
+        } else {
+            Some((order.state.paid / price).min(u64::MAX as chain::Balance) as u64)
+        };
+        let outcome = match Query::parse(&query).and_then(|query| query.execute(&dataset, max_rows)) {
+            Ok(output) => {
+                // Not reported if the result can't be kept, so that another worker can settle it
+                if let Err(e) = self.store.put(&result_file(order_id), &output.data) {
+                    error!("Failed to store the result of order {}: {}", order_id, e);
+                    return None;
+                }
+                Outcome::Matched {
+                    matched_rows: output.matched_rows,
+                    output_rows: output.output_rows,
+                    result_bytes: output.data.len() as u64,
+                    truncated: output.truncated,
+                    result_hash: blake2_256(&output.data),
+                }
+            }
+            Err(e) => Outcome::Failed(e.to_string()),
+        };
+        Some(outcome)
+    }
+
+    /// Pays the seller for the matched rows and refunds the rest of the budget
+    fn settle(
+        &mut self,
+        context: &NativeContext,
+        origin: &MessageOrigin,
+        order_id: OrderId,
+        outcome: Outcome,
+    ) -> Result<(), TransactionStatus> {
+        match origin {
+            MessageOrigin::Worker(worker) if context.is_registered_worker(worker) => {}
+            _ => return Err(TransactionStatus::BadOrigin),
+        }
+        let order = self
+            .orders
+            .get(order_id as usize)
+            .ok_or(TransactionStatus::OrderNotFound)?;
+        // Also rejects the reports after the first one
+        if order.state.status != OrderStatus::Executing {
+            return Err(TransactionStatus::BadOrderState);
+        }
+        let item = &self.items[order.details.item_id as usize];
+        let PricePolicy::PerRow { price } = item.details.price;
+        let seller = item.seller.clone();
+        let buyer = order.buyer.clone();
+        let paid = order.state.paid;
+
+        let state = &mut self.orders[order_id as usize].state;
+        let charged = match outcome {
+            Outcome::Matched {
+                matched_rows,
+                output_rows,
+                result_bytes,
+                truncated,
+                result_hash,
+            } => {
+                let charged = price
+                    .saturating_mul(matched_rows as chain::Balance)
+                    .min(paid);
+                info!(
+                    "Order {} settled: {} rows matched, charged {}",
+                    order_id, matched_rows, charged
+                );
+                state.status = OrderStatus::Settled;
+                state.charged = charged;
+                state.matched_rows = matched_rows;
+                state.result_rows = output_rows;
+                state.result_bytes = result_bytes;
+                state.truncated = truncated;
+                state.result_hash = Some(result_hash);
+                charged
+            }
+            Outcome::Failed(error) => {
+                warn!("Order {} failed: {}", order_id, error);
+                state.status = OrderStatus::Failed;
+                state.error = Some(error);
+                0
+            }
+        };
+        self.transfer(context, &seller, charged);
+        self.transfer(context, &buyer, paid - charged);
+        Ok(())
+    }
+
+    fn handle(
+        &mut self,
+        context: &NativeContext,
+        o: AccountIdWrapper,
+        txref: TxRef,
+        command: Command,
+    ) -> Result<(), TransactionStatus> {
+        match command {
+            Command::Settle { .. } => return Err(TransactionStatus::BadOrigin),
+            Command::List(details) => {
+                let id = self.items.len() as ItemId;
+                self.items.push(Item {
+                    id,
+                    txref,
+                    seller: o,
+                    details,
+                    dataset_ready: self.store.contains(&dataset_file(id)),
+                });
+            }
+            Command::OpenOrder(details) => {
+                if details.item_id as usize >= self.items.len() {
+                    return Err(TransactionStatus::ItemNotFound);
+                }
+                let id = self.orders.len() as OrderId;
+                self.orders.push(Order {
+                    id,
+                    txref,
+                    buyer: o,
+                    details,
+                    state: OrderState {
+                        status: OrderStatus::AwaitingPayment,
+                        query_ready: self.store.contains(&query_file(id)),
+                        paid: 0,
+                        charged: 0,
+                        matched_rows: 0,
+                        result_rows: 0,
+                        result_bytes: 0,
+                        truncated: false,
+                        result_hash: None,
+                        error: None,
+                    },
+                });
+            }
+            Command::Execute { order_id } => self.execute(context, &o, order_id)?,
+            Command::CancelOrder { order_id } => {
+                let order = self
+                    .orders
+                    .get_mut(order_id as usize)
+                    .ok_or(TransactionStatus::OrderNotFound)?;
+                if order.buyer != o {
+                    return Err(TransactionStatus::BadOrigin);
+                }
+                let paid = match order.state.status {
+                    OrderStatus::AwaitingPayment => 0,
+                    // No worker may hold the data to settle it
+                    OrderStatus::Paid | OrderStatus::Executing => order.state.paid,
+                    _ => return Err(TransactionStatus::BadOrderState),
+                };
+                order.state.status = OrderStatus::Cancelled;
+                self.transfer(context, &o, paid);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl contracts::NativeContract for DataPlaza {
+    type Cmd = Command;
+    type Event = Payment;
+    type QReq = Request;
+    type QResp = Response;
+
+    fn id(&self) -> contracts::ContractId {
+        contracts::DATA_PLAZA
+    }
+
+    fn handle_command(
+        &mut self,
+        context: &NativeContext,
+        origin: MessageOrigin,
+        cmd: PushCommand<Self::Cmd>,
+    ) -> TransactionStatus {
+        let result = match (origin, cmd.command) {
+            (origin, Command::Settle { order_id, outcome }) => {
+                self.settle(context, &origin, order_id, outcome)
+            }
+            (MessageOrigin::AccountId(acc), command) => {
+                let txref = TxRef {
+                    blocknum: context.block_number,
+                    index: cmd.number,
+                };
+                self.handle(context, AccountIdWrapper(acc), txref, command)
+            }
+            _ => Err(TransactionStatus::BadOrigin),
+        };
+        match result {
+            Ok(()) => TransactionStatus::Ok,
+            Err(status) => status,
+        }
+    }
+
+    fn handle_query(&mut self, origin: Option<&chain::AccountId>, req: Request) -> Response {
+        let inner = || -> Result<Response> {
+            let sender = origin
+                .map(|acc| AccountIdWrapper(acc.clone()))
+                .ok_or_else(|| anyhow::Error::msg(Error::NotAuthorized));
+            match req {
+                Request::GetItems => Ok(Response::GetItems {
+                    items: self.items.clone(),
+                }),
+                Request::GetOrders => {
+                    let sender = sender?;
+                    let items = &self.items;
+                    let orders = self
+                        .orders
+                        .iter()
+                        .filter(|order| {
+                            order.buyer == sender
+                                || items[order.details.item_id as usize].seller == sender
+                        })
+                        .cloned()
+                        .collect();
+                    Ok(Response::GetOrders { orders })
+                }
+                Request::UploadDataset { item_id, data } => {
+                    let item = self
+                        .items
+                        .get_mut(item_id as usize)
+                        .ok_or_else(|| anyhow::Error::msg(Error::ItemNotFound))?;
+                    if item.seller != sender? {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    let data = base64::decode(data).map_err(|_| {
+                        anyhow::Error::msg(Error::Other("Base64 decode error".into()))
+                    })?;
+                    if blake2_256(&data) != item.details.dataset_hash {
+                        return Err(anyhow::Error::msg(Error::HashMismatch));
+                    }
+                    self.store.put(&dataset_file(item_id), &data)?;
+                    item.dataset_ready = true;
+                    Ok(Response::Uploaded)
+                }
+                Request::UploadQuery { order_id, query } => {
+                    let order = self
+                        .orders
+                        .get_mut(order_id as usize)
+                        .ok_or_else(|| anyhow::Error::msg(Error::OrderNotFound))?;
+                    if order.buyer != sender? {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    match order.state.status {
+                        OrderStatus::AwaitingPayment | OrderStatus::Paid => {}
+                        _ => return Err(anyhow::Error::msg(Error::BadOrderState)),
+                    }
+                    if blake2_256(query.as_bytes()) != order.details.query_hash {
+                        return Err(anyhow::Error::msg(Error::HashMismatch));
+                    }
+                    Query::parse(query.as_bytes())?;
+                    self.store.put(&query_file(order_id), query.as_bytes())?;
+                    order.state.query_ready = true;
+                    Ok(Response::Uploaded)
+                }
+                Request::GetResult { order_id } => {
+                    let order = self
+                        .orders
+                        .get(order_id as usize)
+                        .ok_or_else(|| anyhow::Error::msg(Error::OrderNotFound))?;
+                    if order.buyer != sender? {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    let data = self
+                        .store
+                        .get(&result_file(order_id))?
+                        .ok_or_else(|| anyhow::Error::msg(Error::NotReady))?;
+                    Ok(Response::GetResult {
+                        data: base64::encode(data),
+                    })
+                }
+            }
+        };
+        match inner() {
+            Err(error) => Response::Error(error),
+            Ok(resp) => resp,
+        }
+    }
+
+    fn handle_event(&mut self, context: &NativeContext, origin: MessageOrigin, event: Payment) {
+        if origin != MessageOrigin::native_contract(contracts::BALANCES) {
+            error!("Received event from unexpected origin: {:?}", origin);
+            return;
+        }
+        if AccountIdWrapper::from(*context.address()).0 != event.payee {
+            return;
+        }
+        let payer = AccountIdWrapper(event.payer);
+        let order = OrderId::decode(&mut &event.memo[..])
+            .ok()
+            .and_then(|order_id| self.orders.get_mut(order_id as usize));
+        match order {
+            Some(order)
+                if order.buyer == payer && order.state.status == OrderStatus::AwaitingPayment =>
+            {
+                info!("Order {} paid: {}", order.id, event.amount);
+                order.state.paid = event.amount;
+                order.state.status = OrderStatus::Paid;
+            }
+            _ => {
+                warn!("Refunding an unexpected payment from {}", payer.to_string());
+                self.transfer(context, &payer, event.amount);
+            }
+        }
+    }
+}
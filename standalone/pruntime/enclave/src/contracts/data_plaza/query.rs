@@ -0,0 +1,371 @@
+//! A small declarative query language over the CSV datasets of `DataPlaza`.
+//!
+//! The datasets must have a header row naming the columns. A query is a JSON object like:
+//!
+//! ```json
+//! {
+//!     "filters": [{"In": {"column": "phone", "values": ["13800000000"]}}],
+//!     "select": ["name", "phone"],
+//!     "limit": 100
+//! }
+//! ```
+//!
+//! or, to aggregate the matched rows into a single row:
+//!
+//! ```json
+//! {
+//!     "filters": [{"Ge": {"column": "age", "value": "18"}}],
+//!     "aggregates": ["Count", {"Avg": {"column": "income"}}]
+//! }
+//! ```
+
+use crate::std::cmp::Ordering;
+use crate::std::collections::BTreeSet;
+use crate::std::string::{String, ToString};
+use crate::std::vec::Vec;
+use anyhow::{Error, Result};
+use csv_core::{ReadRecordResult, Reader};
+use serde::{Deserialize, Serialize};
+
+const MAX_RECORD_BYTES: usize = 64 * 1024;
+const MAX_FIELDS: usize = 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Query {
+    /// The conditions which the rows must all meet
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    /// The columns to output, or all the columns if empty
+    #[serde(default)]
+    pub select: Vec<String>,
+    /// Outputs a single row aggregating the matched rows instead of the rows, if not empty
+    #[serde(default)]
+    pub aggregates: Vec<Aggregate>,
+    /// The max number of rows to match
+    #[serde(default)]
+    pub limit: Option<u64>,
+}
+
+/// Compares the values as numbers if both of them are numbers, or as strings otherwise
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Filter {
+    Eq { column: String, value: String },
+    Ne { column: String, value: String },
+    Lt { column: String, value: String },
+    Le { column: String, value: String },
+    Gt { column: String, value: String },
+    Ge { column: String, value: String },
+    Contains { column: String, value: String },
+    In { column: String, values: Vec<String> },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Aggregate {
+    Count,
+    Sum { column: String },
+    Avg { column: String },
+    Min { column: String },
+    Max { column: String },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueryOutput {
+    /// The result in CSV, with a header row
+    pub data: Vec<u8>,
+    /// The number of rows matching the filters, which is what the buyer pays for
+    pub matched_rows: u64,
+    /// The number of rows in `data`, excluding the header
+    pub output_rows: u64,
+    /// Whether there were more matching rows than the limit or the budget
+    pub truncated: bool,
+}
+
+/// A filter with its column resolved
+enum Condition<'a> {
+    Compare(usize, Ordering, bool, &'a str),
+    Contains(usize, &'a str),
+    In(usize, BTreeSet<&'a str>),
+}
+
+impl Condition<'_> {
+    fn check(&self, row: &[String]) -> bool {
+        match self {
+            Condition::Compare(i, ordering, equal, value) => {
+                let ord = compare(&row[*i], value);
+                (ord == *ordering) == *equal
+            }
+            Condition::Contains(i, value) => row[*i].contains(value),
+            Condition::In(i, values) => values.contains(row[*i].as_str()),
+        }
+    }
+}
+
+/// The running state of an aggregate
+struct Accumulator {
+    aggregate: Aggregate,
+    column: Option<usize>,
+    count: u64,
+    sum: f64,
+    best: Option<String>,
+}
+
+impl Accumulator {
+    fn add(&mut self, row: &[String]) -> Result<()> {
+        let column = match self.column {
+            Some(column) => column,
+            None => {
+                self.count += 1;
+                return Ok(());
+            }
+        };
+        let value = &row[column];
+        match &self.aggregate {
+            Aggregate::Sum { .. } | Aggregate::Avg { .. } => {
+                let number: f64 = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::msg(format!("Not a number: {}", value)))?;
+                self.sum += number;
+                self.count += 1;
+            }
+            Aggregate::Min { .. } | Aggregate::Max { .. } => {
+                let wanted = match self.aggregate {
+                    Aggregate::Min { .. } => Ordering::Less,
+                    _ => Ordering::Greater,
+                };
+                let replace = match &self.best {
+                    Some(best) => compare(value, best) == wanted,
+                    None => true,
+                };
+                if replace {
+                    self.best = Some(value.clone());
+                }
+            }
+            Aggregate::Count => {}
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        match &self.aggregate {
+            Aggregate::Count => "count".to_string(),
+            Aggregate::Sum { column } => format!("sum({})", column),
+            Aggregate::Avg { column } => format!("avg({})", column),
+            Aggregate::Min { column } => format!("min({})", column),
+            Aggregate::Max { column } => format!("max({})", column),
+        }
+    }
+
+    fn result(&self) -> String {
+        match &self.aggregate {
+            Aggregate::Count => self.count.to_string(),
+            Aggregate::Sum { .. } => self.sum.to_string(),
+            Aggregate::Avg { .. } if self.count == 0 => String::new(),
+            Aggregate::Avg { .. } => (self.sum / self.count as f64).to_string(),
+            Aggregate::Min { .. } | Aggregate::Max { .. } => {
+                self.best.clone().unwrap_or_default()
+            }
+        }
+    }
+}
+
+fn compare(a: &str, b: &str) -> Ordering {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+fn column_index(header: &[String], column: &str) -> Result<usize> {
+    header
+        .iter()
+        .position(|name| name == column)
+        .ok_or_else(|| Error::msg(format!("Unknown column: {}", column)))
+}
+
+/// Reads the records of a CSV document one by one
+struct Records<'a> {
+    reader: Reader,
+    input: &'a [u8],
+    output: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl<'a> Records<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Records {
+            reader: Reader::new(),
+            input,
+            output: vec![0; 1024],
+            ends: vec![0; 32],
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<String>>> {
+        let mut outlen = 0;
+        let mut endlen = 0;
+        loop {
+            let (result, nin, nout, nend) = self.reader.read_record(
+                self.input,
+                &mut self.output[outlen..],
+                &mut self.ends[endlen..],
+            );
+            self.input = &self.input[nin..];
+            outlen += nout;
+            endlen += nend;
+            match result {
+                // All the input is given, so the next read ends the document
+                ReadRecordResult::InputEmpty => {}
+                ReadRecordResult::OutputFull => {
+                    if self.output.len() >= MAX_RECORD_BYTES {
+                        return Err(Error::msg("Record too large"));
+                    }
+                    let len = self.output.len() * 2;
+                    self.output.resize(len, 0);
+                }
+                ReadRecordResult::OutputEndsFull => {
+                    if self.ends.len() >= MAX_FIELDS {
+                        return Err(Error::msg("Too many fields"));
+                    }
+                    let len = self.ends.len() * 2;
+                    self.ends.resize(len, 0);
+                }
+                ReadRecordResult::Record => {
+                    let mut fields = Vec::with_capacity(endlen);
+                    let mut start = 0;
+                    for &end in &self.ends[..endlen] {
+                        let field = String::from_utf8(self.output[start..end].to_vec())
+                            .map_err(|_| Error::msg("Bad UTF-8 field"))?;
+                        fields.push(field);
+                        start = end;
+                    }
+                    return Ok(Some(fields));
+                }
+                ReadRecordResult::End => return Ok(None),
+            }
+        }
+    }
+}
+
+fn write_record<S: AsRef<str>>(out: &mut Vec<u8>, fields: &[S]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+        let field = field.as_ref();
+        if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+            out.push(b'"');
+            out.extend_from_slice(field.replace('"', "\"\"").as_bytes());
+            out.push(b'"');
+        } else {
+            out.extend_from_slice(field.as_bytes());
+        }
+    }
+    out.push(b'\n');
+}
+
+impl Query {
+    pub fn parse(data: &[u8]) -> Result<Query> {
+        serde_json::from_slice(data).map_err(|e| Error::msg(format!("Bad query: {}", e)))
+    }
+
+    /// Runs the query on `dataset`, matching at most `max_rows` rows
+    pub fn execute(&self, dataset: &[u8], max_rows: Option<u64>) -> Result<QueryOutput> {
+        let mut records = Records::new(dataset);
+        let header = records
+            .next()?
+            .ok_or_else(|| Error::msg("Empty dataset"))?;
+
+        let mut conditions = Vec::new();
+        for filter in &self.filters {
+            let compare = |column, ordering, equal, value| -> Result<_> {
+                Ok(Condition::Compare(
+                    column_index(&header, column)?,
+                    ordering,
+                    equal,
+                    value,
+                ))
+            };
+            conditions.push(match filter {
+                Filter::Eq { column, value } => compare(column, Ordering::Equal, true, value)?,
+                Filter::Ne { column, value } => compare(column, Ordering::Equal, false, value)?,
+                Filter::Lt { column, value } => compare(column, Ordering::Less, true, value)?,
+                Filter::Ge { column, value } => compare(column, Ordering::Less, false, value)?,
+                Filter::Gt { column, value } => compare(column, Ordering::Greater, true, value)?,
+                Filter::Le { column, value } => compare(column, Ordering::Greater, false, value)?,
+                Filter::Contains { column, value } => {
+                    Condition::Contains(column_index(&header, column)?, value)
+                }
+                Filter::In { column, values } => Condition::In(
+                    column_index(&header, column)?,
+                    values.iter().map(|v| v.as_str()).collect(),
+                ),
+            });
+        }
+        let selected = if self.select.is_empty() {
+            (0..header.len()).collect()
+        } else {
+            self.select
+                .iter()
+                .map(|column| column_index(&header, column))
+                .collect::<Result<Vec<_>>>()?
+        };
+        let mut accumulators = Vec::new();
+        for aggregate in &self.aggregates {
+            let column = match aggregate {
+                Aggregate::Count => None,
+                Aggregate::Sum { column }
+                | Aggregate::Avg { column }
+                | Aggregate::Min { column }
+                | Aggregate::Max { column } => Some(column_index(&header, column)?),
+            };
+            accumulators.push(Accumulator {
+                aggregate: aggregate.clone(),
+                column,
+                count: 0,
+                sum: 0.0,
+                best: None,
+            });
+        }
+
+        let max_rows = match (self.limit, max_rows) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let mut output = QueryOutput::default();
+        if accumulators.is_empty() {
+            let names: Vec<_> = selected.iter().map(|&i| &header[i]).collect();
+            write_record(&mut output.data, &names);
+        }
+        while let Some(row) = records.next()? {
+            if row.len() != header.len() {
+                return Err(Error::msg("Inconsistent number of fields"));
+            }
+            if !conditions.iter().all(|condition| condition.check(&row)) {
+                continue;
+            }
+            if Some(output.matched_rows) == max_rows {
+                output.truncated = true;
+                break;
+            }
+            output.matched_rows += 1;
+            if accumulators.is_empty() {
+                let fields: Vec<_> = selected.iter().map(|&i| &row[i]).collect();
+                write_record(&mut output.data, &fields);
+                output.output_rows += 1;
+            } else {
+                for accumulator in accumulators.iter_mut() {
+                    accumulator.add(&row)?;
+                }
+            }
+        }
+        if !accumulators.is_empty() {
+            let names: Vec<_> = accumulators.iter().map(Accumulator::name).collect();
+            let results: Vec<_> = accumulators.iter().map(Accumulator::result).collect();
+            write_record(&mut output.data, &names);
+            write_record(&mut output.data, &results);
+            output.output_rows = 1;
+        }
+        Ok(output)
+    }
+}
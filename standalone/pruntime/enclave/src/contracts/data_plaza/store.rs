@@ -0,0 +1,60 @@
+use crate::cryptography::aead;
+use crate::std::io::ErrorKind;
+use crate::std::string::String;
+use crate::std::untrusted::fs;
+use crate::std::vec::Vec;
+use anyhow::{Error, Result};
+use parity_scale_codec::Encode;
+use sp_core::hashing::blake2_256;
+
+/// The files of the datasets, queries and results of `DataPlaza`, kept out of the enclave so that
+/// they survive restarts.
+///
+/// The files are sealed by AES-GCM with a key derived from the secret of the contract, and
+/// named by the keyed hash of their names, so the host learns nothing but their sizes.
+pub struct DatasetStore {
+    dir: String,
+    key: [u8; 32],
+}
+
+impl DatasetStore {
+    pub fn new(dir: String, key: [u8; 32]) -> Self {
+        DatasetStore { dir, key }
+    }
+
+    fn path(&self, name: &str) -> String {
+        let hash = blake2_256(&(b"data_plaza/file", &self.key, name).encode());
+        format!("{}/{}", self.dir, hex::encode(hash))
+    }
+
+    pub fn put(&self, name: &str, data: &[u8]) -> Result<()> {
+        let iv = aead::generate_iv();
+        let mut cipher = data.to_vec();
+        aead::encrypt(&iv, &self.key, &mut cipher);
+
+        let mut file = Vec::with_capacity(iv.len() + cipher.len());
+        file.extend_from_slice(&iv);
+        file.extend_from_slice(&cipher);
+        fs::create_dir_all(&self.dir).map_err(Error::msg)?;
+        fs::write(self.path(name), file).map_err(Error::msg)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let mut file = match fs::read(self.path(name)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::msg(e)),
+        };
+        if file.len() < aead::IV_BYTES {
+            return Err(Error::msg("Corrupted file"));
+        }
+        let (iv, cipher) = file.split_at_mut(aead::IV_BYTES);
+        let data = aead::try_decrypt(iv, &self.key, cipher)
+            .ok_or_else(|| Error::msg("Failed to decrypt the file"))?;
+        Ok(Some(data.to_vec()))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        fs::metadata(self.path(name)).is_ok()
+    }
+}
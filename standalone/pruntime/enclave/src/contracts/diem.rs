@@ -42,7 +42,6 @@ use diem_types::{
 type SparseMerkleProof = diem_types::proof::SparseMerkleProof<AccountStateBlob>;
 use parity_scale_codec::{Decode, Encode};
 use phala_types::messaging::{DiemCommand as Command, MessageOrigin, PushCommand};
-use rand::{rngs::OsRng, Rng, SeedableRng};
 
 use super::NativeContext;
 
@@ -50,10 +49,12 @@ const GAS_UNIT_PRICE: u64 = 0;
 const MAX_GAS_AMOUNT: u64 = 1_000_000;
 const TX_EXPIRATION: i64 = 180;
 const CHAIN_ID_UNINITIALIZED: u8 = 0;
+#[cfg(feature = "dev-keys")]
 const ALICE_PRIVATE_KEY: &[u8] =
     &hex_literal::hex!("818ad9a64e3d1bbc388f8bf1e43c78d125237b875a1b70a18f412f7d18efbeea");
+#[cfg(feature = "dev-keys")]
 const ALICE_ADDRESS: &str = "D4F0C053205BA934BB2AC0C4E8479E77";
-
+#[cfg(feature = "dev-keys")]
 const ALICE_PHALA: &str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
@@ -110,6 +111,8 @@ pub enum Request {
     },
     CurrentState,
     AccountData,
+    /// Gets the parent VASP account, to register it on Diem
+    ParentAccount,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -127,6 +130,11 @@ pub enum Response {
     AccountData {
         data: Vec<AccountData>,
     },
+    ParentAccount {
+        address: AccountAddress,
+        /// The hex encoded authentication key prefix
+        auth_key_prefix: String,
+    },
     /// Some other errors
     Error(#[serde(with = "super::serde_anyhow")] anyhow::Error),
 }
@@ -191,6 +199,8 @@ pub struct Diem {
 
     accounts: BTreeMap<AccountIdWrapper, Account>, //Phala => Diem
     address: BTreeMap<String, AccountIdWrapper>,   // Diem => Phala
+    /// The holder of the parent VASP account, following the contract admin
+    parent: Option<AccountIdWrapper>,
     account_address: Vec<String>,                  //Diem string
     pending_transactions: BTreeMap<String, Vec<PendingTransaction>>,
 
@@ -204,6 +214,32 @@ pub struct Diem {
 
 impl Diem {
     pub fn new() -> Self {
+        #[allow(unused_mut)]
+        let mut diem = Diem {
+            chain_id: CHAIN_ID_UNINITIALIZED,
+            account_info: Vec::new(),
+            transactions: BTreeMap::<String, Vec<Transaction>>::new(),
+            verified: BTreeMap::<String, bool>::new(),
+            seq_number: BTreeMap::<String, u64>::new(),
+            init_trusted_state: None,
+            trusted_state: None,
+            accounts: BTreeMap::<AccountIdWrapper, Account>::new(),
+            address: BTreeMap::<String, AccountIdWrapper>::new(),
+            parent: None,
+            account_address: Vec::new(),
+            pending_transactions: BTreeMap::<String, Vec<PendingTransaction>>::new(),
+            queue_seq: 1,
+            tx_queue: Vec::new(),
+            timestamp_usecs: 0,
+        };
+        #[cfg(feature = "dev-keys")]
+        diem.add_dev_parent_account();
+        diem
+    }
+
+    /// Gives the parent VASP account of the Diem testnet to the dev account ALICE
+    #[cfg(feature = "dev-keys")]
+    fn add_dev_parent_account(&mut self) {
         let alice_priv_key =
             Ed25519PrivateKey::from_bytes_unchecked(ALICE_PRIVATE_KEY).expect("Bad private key");
         let alice_key_pair: KeyPair<Ed25519PrivateKey, Ed25519PublicKey> =
@@ -222,31 +258,35 @@ impl Diem {
         };
 
         let alice_addr = AccountIdWrapper::from_hex(ALICE_PHALA).expect("Bad init master account");
-        let mut accounts = BTreeMap::<AccountIdWrapper, Account>::new();
-        accounts.insert(alice_addr.clone(), alice_account);
-
-        let mut address = BTreeMap::<String, AccountIdWrapper>::new();
-        address.insert(ALICE_ADDRESS.to_string(), alice_addr);
-
-        let mut account_address: Vec<String> = Vec::new();
-        account_address.push(ALICE_ADDRESS.to_string());
+        self.accounts.insert(alice_addr.clone(), alice_account);
+        self.address.insert(ALICE_ADDRESS.to_string(), alice_addr.clone());
+        self.account_address.push(ALICE_ADDRESS.to_string());
+        self.parent = Some(alice_addr);
+    }
 
-        Diem {
-            chain_id: CHAIN_ID_UNINITIALIZED,
-            account_info: Vec::new(),
-            transactions: BTreeMap::<String, Vec<Transaction>>::new(),
-            verified: BTreeMap::<String, bool>::new(),
-            seq_number: BTreeMap::<String, u64>::new(),
-            init_trusted_state: None,
-            trusted_state: None,
-            accounts,
-            address,
-            account_address,
-            pending_transactions: BTreeMap::<String, Vec<PendingTransaction>>::new(),
-            queue_seq: 1,
-            tx_queue: Vec::new(),
-            timestamp_usecs: 0,
+    /// Moves the parent VASP account to `admin` if the admin is transferred, so it isn't
+    /// orphaned. Returns false if there's no parent account or `admin` can't hold it.
+    fn bind_parent_account(&mut self, admin: &AccountIdWrapper) -> bool {
+        let holder = match &self.parent {
+            Some(holder) => holder.clone(),
+            None => return false,
+        };
+        if &holder == admin {
+            return true;
         }
+        if self.accounts.contains_key(admin) {
+            error!("The new admin already holds a Diem account");
+            return false;
+        }
+        let account = match self.accounts.remove(&holder) {
+            Some(account) => account,
+            None => return false,
+        };
+        info!("Moving the parent VASP account to the new admin {}", admin.to_string());
+        self.address.insert(account.address.to_string(), admin.clone());
+        self.accounts.insert(admin.clone(), account);
+        self.parent = Some(admin.clone());
+        true
     }
 
     pub fn get_transaction(
@@ -525,7 +565,7 @@ impl contracts::NativeContract for Diem {
 
     fn handle_command(
         &mut self,
-        _context: &NativeContext,
+        context: &NativeContext,
         origin: MessageOrigin,
         cmd: PushCommand<Self::Cmd>,
     ) -> TransactionStatus {
@@ -721,22 +761,34 @@ impl contracts::NativeContract for Diem {
                 let o = AccountIdWrapper::from(origin);
                 info!("NewAccount {:}, seq_number:{:}", o.to_string(), seq_number);
 
-                let alice =
-                    AccountIdWrapper::from_hex(ALICE_PHALA).expect("Bad init master account");
-                if o == alice {
-                    error!("Alice can't execute NewAccount command");
+                // The child accounts are created by the parent VASP account held by the admin
+                let admin = match context.contract_admin() {
+                    Some(admin) => admin,
+                    None => return TransactionStatus::NotAdmin,
+                };
+                if o == admin {
+                    error!("The admin can't execute NewAccount command");
                     return TransactionStatus::InvalidAccount;
                 }
-
-                let alice_account = self
+                if !self.bind_parent_account(&admin) {
+                    error!("The admin has no parent VASP account");
+                    return TransactionStatus::InvalidAccount;
+                }
+                let parent_account = self
                     .accounts
-                    .get(&alice)
-                    .expect("Alice account was required");
-
-                let alice_key_pair = &alice_account.key;
-                // TODO: make deterministic privkey generation
-                let mut seed_rng = OsRng;
-                let mut rng = rand::rngs::StdRng::from_seed(seed_rng.gen());
+                    .get(&admin)
+                    .expect("The parent account is bound to the admin; qed.");
+
+                let parent_key_pair = &parent_account.key;
+                // The key must stay private, so it can't be drawn from the public block seed. The
+                // secret is shared, so every worker derives the same child account.
+                let mut rng = match context.secret_rng(cmd.number) {
+                    Ok(rng) => rng,
+                    Err(err) => {
+                        error!("NewAccount: {}", err);
+                        return TransactionStatus::BadSecret;
+                    }
+                };
                 let keypair: KeyPair<Ed25519PrivateKey, Ed25519PublicKey> =
                     Ed25519PrivateKey::generate(&mut rng).into();
                 let auth_key = AuthenticationKey::ed25519(&keypair.public_key).to_vec();
@@ -754,9 +806,9 @@ impl contracts::NativeContract for Diem {
                 );
 
                 let txn = create_user_txn(
-                    alice_key_pair,
+                    parent_key_pair,
                     TransactionPayload::Script(script),
-                    alice_account.address,
+                    parent_account.address,
                     seq_number,
                     MAX_GAS_AMOUNT,
                     GAS_UNIT_PRICE,
@@ -792,6 +844,52 @@ impl contracts::NativeContract for Diem {
 
                 TransactionStatus::Ok
             }
+            Command::NewParentAccount => {
+                let o = AccountIdWrapper::from(origin);
+                info!("NewParentAccount {:}", o.to_string());
+
+                if !context.is_admin(&o) {
+                    return TransactionStatus::NotAdmin;
+                }
+                if self.parent.is_some() {
+                    error!("The parent VASP account already exists");
+                    return TransactionStatus::InvalidAccount;
+                }
+                if self.accounts.contains_key(&o) {
+                    error!("The admin already holds a child account");
+                    return TransactionStatus::InvalidAccount;
+                }
+
+                // Shared by the workers, but never leaves the enclaves
+                let mut rng = match context.secret_rng(cmd.number) {
+                    Ok(rng) => rng,
+                    Err(err) => {
+                        error!("NewParentAccount: {}", err);
+                        return TransactionStatus::BadSecret;
+                    }
+                };
+                let keypair: KeyPair<Ed25519PrivateKey, Ed25519PublicKey> =
+                    Ed25519PrivateKey::generate(&mut rng).into();
+                let address = AuthenticationKey::ed25519(&keypair.public_key).derived_address();
+                info!("new parent address:{:?}", address);
+
+                let account = Account {
+                    address,
+                    key: keypair,
+                    event_id: 0,
+                    sequence: 0,
+                    free: 0,
+                    locked: 0,
+                    is_child: false,
+                };
+
+                self.accounts.insert(o.clone(), account);
+                self.address.insert(address.to_string(), o.clone());
+                self.account_address.push(address.to_string());
+                self.parent = Some(o);
+
+                TransactionStatus::Ok
+            }
             Command::TransferXUS { to, amount } => {
                 let o = AccountIdWrapper::from(origin);
                 info!(
@@ -961,6 +1059,18 @@ impl contracts::NativeContract for Diem {
                     }
                     Ok(Response::AccountData { data: account_data })
                 }
+                Request::ParentAccount => {
+                    let account = self
+                        .parent
+                        .as_ref()
+                        .and_then(|holder| self.accounts.get(holder))
+                        .ok_or_else(|| anyhow::Error::msg("No parent VASP account"))?;
+                    let auth_key = AuthenticationKey::ed25519(&account.key.public_key).to_vec();
+                    Ok(Response::ParentAccount {
+                        address: account.address,
+                        auth_key_prefix: hex::encode(auth_key_prefix(auth_key)),
+                    })
+                }
             }
         };
         match inner() {
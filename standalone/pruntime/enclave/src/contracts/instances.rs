@@ -0,0 +1,418 @@
+//! The contract instances created by `pallet_registry::instantiate_contract`.
+//!
+//! Unlike the built-in contracts installed in dev mode, an instance is addressed by the `H256` id
+//! derived on chain, so a native contract can have many instances. Each instance has its own
+//! message channel, command topic (`contract_command_topic`) and event topics
+//! (`contract_event_topic`), and is administered by its deployer.
+//!
+//! The signing key and the secret of an instance are derived from the contract key shared by the
+//! gatekeepers, so the instance is the same on all the workers. Until the worker receives the
+//! contract key, the instance is pending: its messages are buffered and handled once it's
+//! activated.
+//!
+//! The event topic of an instance is a secret topic: its ECDH key is also derived from the
+//! contract key, and registered to `pallet_registry::TopicKey` by a gatekeeper, so that the
+//! events can be sent encrypted with `NativeContext::send_to_instance`.
+
+use crate::std::boxed::Box;
+use crate::std::collections::BTreeMap;
+use crate::std::vec::Vec;
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use parity_scale_codec::{Decode, Encode};
+use phala_mq::{
+    BindTopic, EcdsaMessageChannel as MessageChannel, Message, MessageDispatcher, MessageOrigin,
+    MessageSendQueue, Path, Receiver, TypedReceiver,
+};
+use phala_pallets::pallet_registry::RegistryEvent;
+use phala_types::{
+    messaging::{contract_command_topic, contract_event_topic, ContractEvent},
+    CodeIndex,
+};
+use ring::agreement::EphemeralPrivateKey;
+use sp_core::{crypto::Pair, ecdsa, hashing::blake2_256, H256};
+
+use super::{Contract, ContractId, NativeCompatContract, NativeContract};
+use crate::cryptography::ecdh;
+use crate::msg_channel::osp::{KeyPair, PeelingReceiver};
+use crate::system::SecretKey;
+use crate::Storage;
+
+extern crate runtime as chain;
+
+pub struct ContractInstance {
+    pub code_index: CodeIndex,
+    pub deployer: chain::AccountId,
+    pub instantiated_at: chain::BlockNumber,
+    pub contract: Box<dyn Contract>,
+}
+
+/// An instance waiting for the contract key
+struct PendingInstance {
+    code: ContractId,
+    deployer: chain::AccountId,
+    instantiated_at: chain::BlockNumber,
+    event_topic: Path,
+    cmd_rcv: Receiver<Message>,
+    evt_rcv: Receiver<Message>,
+}
+
+/// An instance carried over by a secret migration
+#[derive(Encode, Decode)]
+pub struct InstanceSnapshot {
+    address: H256,
+    code: ContractId,
+    deployer: chain::AccountId,
+    instantiated_at: chain::BlockNumber,
+    /// The state taken by `Contract::snapshot`
+    state: Vec<u8>,
+}
+
+pub struct ContractInstances {
+    id_pair: ecdsa::Pair,
+    ecdh_key: EphemeralPrivateKey,
+    ecdh_pubkey: Vec<u8>,
+    send_mq: MessageSendQueue,
+    /// The channel of this worker, to report the keys of the instances
+    worker_mq: MessageChannel,
+    event_rcv_mq: TypedReceiver<ContractEvent<chain::AccountId>>,
+    pending: BTreeMap<H256, PendingInstance>,
+    instances: BTreeMap<H256, ContractInstance>,
+}
+
+impl ContractInstances {
+    pub fn new(
+        id_pair: ecdsa::Pair,
+        ecdh_key: EphemeralPrivateKey,
+        ecdh_pubkey: Vec<u8>,
+        send_mq: &MessageSendQueue,
+        recv_mq: &mut MessageDispatcher,
+    ) -> Self {
+        let worker_mq = send_mq.channel(MessageOrigin::Worker(id_pair.public()), id_pair.clone());
+        ContractInstances {
+            id_pair,
+            ecdh_key,
+            ecdh_pubkey,
+            send_mq: send_mq.clone(),
+            worker_mq,
+            event_rcv_mq: recv_mq.subscribe_bound(),
+            pending: Default::default(),
+            instances: Default::default(),
+        }
+    }
+
+    /// Switches to a new worker identity after a key rotation.
+    ///
+    /// The instances keep their contract keys, and only decrypt with the new ECDH key.
+    pub fn set_identity(
+        &mut self,
+        id_pair: &ecdsa::Pair,
+        ecdh_key: EphemeralPrivateKey,
+        ecdh_pubkey: Vec<u8>,
+        send_mq: &MessageSendQueue,
+    ) {
+        self.worker_mq =
+            send_mq.channel(MessageOrigin::Worker(id_pair.public()), id_pair.clone());
+        self.id_pair = id_pair.clone();
+        for instance in self.instances.values_mut() {
+            instance.contract.set_worker_keys(
+                None,
+                KeyPair::new(ecdh::clone_key(&ecdh_key), ecdh_pubkey.clone()),
+            );
+        }
+        self.ecdh_key = ecdh_key;
+        self.ecdh_pubkey = ecdh_pubkey;
+    }
+
+    pub fn get_mut(&mut self, address: &H256) -> Option<&mut ContractInstance> {
+        self.instances.get_mut(address)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&H256, &ContractInstance)> {
+        self.instances.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&H256, &mut ContractInstance)> {
+        self.instances.iter_mut()
+    }
+
+    /// Takes the states of the instances to be carried over by a secret migration.
+    ///
+    /// Fails if any of the instances can't be migrated, rather than losing its state. The pending
+    /// instances can't be migrated because their messages are buffered in the receivers.
+    pub fn snapshot(&self) -> Result<Vec<InstanceSnapshot>> {
+        if let Some(address) = self.pending.keys().next() {
+            return Err(anyhow!("Contract {:?} is waiting for the contract key", address));
+        }
+        self.instances
+            .iter()
+            .map(|(address, instance)| {
+                let CodeIndex::NativeCode(code) = instance.code_index;
+                let state = instance
+                    .contract
+                    .snapshot()
+                    .ok_or_else(|| anyhow!("Contract {:?} can't be migrated", address))?;
+                Ok(InstanceSnapshot {
+                    address: *address,
+                    code,
+                    deployer: instance.deployer.clone(),
+                    instantiated_at: instance.instantiated_at,
+                    state,
+                })
+            })
+            .collect()
+    }
+
+    /// Restores the instances taken by `snapshot` on the previous pRuntime.
+    ///
+    /// The keys of the instances are already registered on chain, so they aren't reported again.
+    pub fn restore(
+        &mut self,
+        snapshots: Vec<InstanceSnapshot>,
+        contract_key: &SecretKey,
+        recv_mq: &mut MessageDispatcher,
+    ) -> Result<()> {
+        for snapshot in snapshots {
+            let InstanceSnapshot {
+                address,
+                code,
+                deployer,
+                instantiated_at,
+                state,
+            } = snapshot;
+            self.subscribe(
+                address,
+                CodeIndex::NativeCode(code),
+                deployer,
+                Vec::new(),
+                instantiated_at,
+                recv_mq,
+            )?;
+            let pending = self
+                .pending
+                .remove(&address)
+                .ok_or_else(|| anyhow!("Contract {:?} is restored twice", address))?;
+            self.instantiate(address, pending, contract_key, None)?;
+            self.instances
+                .get_mut(&address)
+                .expect("The instance was just instantiated; qed.")
+                .contract
+                .restore(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Subscribes the topics of the instances requested by the dispatched `ContractEvent`s.
+    ///
+    /// Called right after a `ContractEvent` is dispatched, so that the new instance receives the
+    /// commands sent to it later in the same block.
+    pub fn process_events(
+        &mut self,
+        block_number: chain::BlockNumber,
+        recv_mq: &mut MessageDispatcher,
+    ) {
+        loop {
+            let event = match self.event_rcv_mq.try_next() {
+                Ok(Some((_, event, origin))) => {
+                    if !origin.is_pallet() {
+                        error!("Contract event from a non-pallet origin: {:?}", origin);
+                        continue;
+                    }
+                    event
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Read contract event failed: {:?}", e);
+                    continue;
+                }
+            };
+            match event {
+                ContractEvent::InstantiateCode {
+                    contract_id,
+                    code_index,
+                    deployer,
+                    init_params,
+                } => {
+                    if let Err(e) = self.subscribe(
+                        contract_id,
+                        code_index,
+                        deployer,
+                        init_params,
+                        block_number,
+                        recv_mq,
+                    ) {
+                        error!("Failed to instantiate contract {:?}: {:?}", contract_id, e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn subscribe(
+        &mut self,
+        address: H256,
+        code_index: CodeIndex,
+        deployer: chain::AccountId,
+        init_params: Vec<u8>,
+        block_number: chain::BlockNumber,
+        recv_mq: &mut MessageDispatcher,
+    ) -> Result<()> {
+        if self.instances.contains_key(&address) || self.pending.contains_key(&address) {
+            warn!("Contract {:?} is already instantiated", address);
+            return Ok(());
+        }
+        let CodeIndex::NativeCode(code) = code_index;
+        let event_topic =
+            event_topic_of(code).ok_or_else(|| anyhow!("Unknown native code {}", code))?;
+        // None of the native contracts take init params yet
+        if !init_params.is_empty() {
+            return Err(anyhow!("Unexpected init params for native code {}", code));
+        }
+        let event_topic = contract_event_topic(event_topic, &address);
+        self.pending.insert(
+            address,
+            PendingInstance {
+                code,
+                deployer,
+                instantiated_at: block_number,
+                cmd_rcv: recv_mq.subscribe(contract_command_topic(&address)),
+                evt_rcv: recv_mq.subscribe(event_topic.clone()),
+                event_topic,
+            },
+        );
+        Ok(())
+    }
+
+    /// Creates the pending instances once the contract key is received from the gatekeepers.
+    ///
+    /// Called after the system has processed the key distribution messages of the block, and
+    /// before the contracts process their messages.
+    pub fn activate(&mut self, contract_key: Option<&SecretKey>, storage: &Storage) {
+        let contract_key = match contract_key {
+            Some(key) => key,
+            None => return,
+        };
+        let pending = core::mem::take(&mut self.pending);
+        for (address, pending) in pending {
+            if let Err(e) = self.instantiate(address, pending, contract_key, Some(storage)) {
+                error!("Failed to instantiate contract {:?}: {:?}", address, e);
+            }
+        }
+    }
+
+    /// Creates the instance from its pending state. The keys of the instance are reported if the
+    /// worker is a gatekeeper according to `storage`.
+    fn instantiate(
+        &mut self,
+        address: H256,
+        pending: PendingInstance,
+        contract_key: &SecretKey,
+        storage: Option<&Storage>,
+    ) -> Result<()> {
+        let PendingInstance {
+            code,
+            deployer,
+            instantiated_at,
+            event_topic,
+            cmd_rcv,
+            evt_rcv,
+        } = pending;
+        let key_pair = ecdsa::Pair::from_seed(&blake2_256(
+            &(b"phala/contract/key", contract_key, address).encode(),
+        ));
+        let secret = super::contract_secret(contract_key, &address);
+        let topic_key = ecdh::create_key(&blake2_256(
+            &(b"phala/contract/topic_key", contract_key, &event_topic).encode(),
+        ))?;
+        let topic_pubkey = topic_key
+            .compute_public_key()
+            .map_err(|_| anyhow!("Can't compute the topic pubkey"))?
+            .as_ref()
+            .to_vec();
+        let mq = self
+            .send_mq
+            .channel(MessageOrigin::Contract(address), key_pair.clone());
+
+        macro_rules! native {
+            ($inner: expr) => {{
+                let wrapped = NativeCompatContract::new(
+                    $inner,
+                    mq,
+                    PeelingReceiver::new_plain(cmd_rcv.into()),
+                    PeelingReceiver::new_osp(evt_rcv.into(), ecdh::clone_key(&topic_key)),
+                    KeyPair::new(ecdh::clone_key(&self.ecdh_key), self.ecdh_pubkey.clone()),
+                    Some(secret),
+                )
+                .instance(address, deployer.clone());
+                Box::new(wrapped) as Box<dyn Contract>
+            }};
+        }
+
+        let contract = match code {
+            super::DATA_PLAZA => native!(super::data_plaza::DataPlaza::new(
+                format!("data_plaza/{}", hex::encode(address)),
+                blake2_256(&(b"data_plaza/store", &secret).encode()),
+            )),
+            super::BALANCES => native!(super::balances::Balances::new()),
+            super::ASSETS => native!(super::assets::Assets::new()),
+            super::WEB3_ANALYTICS => native!(super::web3analytics::Web3Analytics::new()),
+            super::DIEM => native!(super::diem::Diem::new()),
+            super::SUBSTRATE_KITTIES => {
+                native!(super::substrate_kitties::SubstrateKitties::new())
+            }
+            super::BTC_LOTTERY => {
+                native!(super::btc_lottery::BtcLottery::new(Some(key_pair.seed())))
+            }
+            super::NFT => native!(super::nft::Nft::new()),
+            // Rejected by `subscribe`
+            _ => unreachable!(),
+        };
+        info!("Contract {:?} instantiated from native code {}", address, code);
+        self.instances.insert(
+            address,
+            ContractInstance {
+                code_index: CodeIndex::NativeCode(code),
+                deployer,
+                instantiated_at,
+                contract,
+            },
+        );
+
+        // Only the gatekeepers can register the contract keys
+        let is_gatekeeper = storage
+            .map(|storage| crate::identity::is_gatekeeper(&self.id_pair.public(), storage))
+            .unwrap_or(false);
+        if is_gatekeeper {
+            self.worker_mq.send(&RegistryEvent::ContractKey {
+                contract_id: address,
+                pubkey: key_pair.public(),
+            });
+            self.worker_mq.send(&RegistryEvent::TopicKey {
+                topic: event_topic,
+                pubkey: topic_pubkey,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The topic of the events handled by the native code, or None if the code is unknown
+fn event_topic_of(code: ContractId) -> Option<&'static [u8]> {
+    macro_rules! topic {
+        ($t: ty) => {
+            <<$t as NativeContract>::Event as BindTopic>::TOPIC
+        };
+    }
+    let topic = match code {
+        super::DATA_PLAZA => topic!(super::data_plaza::DataPlaza),
+        super::BALANCES => topic!(super::balances::Balances),
+        super::ASSETS => topic!(super::assets::Assets),
+        super::WEB3_ANALYTICS => topic!(super::web3analytics::Web3Analytics),
+        super::DIEM => topic!(super::diem::Diem),
+        super::SUBSTRATE_KITTIES => topic!(super::substrate_kitties::SubstrateKitties),
+        super::BTC_LOTTERY => topic!(super::btc_lottery::BtcLottery),
+        super::NFT => topic!(super::nft::Nft),
+        _ => return None,
+    };
+    Some(topic)
+}
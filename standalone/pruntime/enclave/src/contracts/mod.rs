@@ -1,11 +1,13 @@
 use crate::error_msg;
 use crate::msg_channel::osp::{
-    storage_prefix_for_topic_pubkey, KeyPair, OspMq, Peeler, PeelingReceiver,
+    storage_key_for_worker_info, storage_prefix_for_topic_pubkey, AeadCipher, KeyPair, OspMq,
+    OspPayload, Peeler, PeelingReceiver,
 };
 use crate::std::fmt::Debug;
 use crate::std::string::String;
+use crate::std::vec::Vec;
 use crate::system::System;
-use crate::system::TransactionReceipt;
+use crate::system::{SecretKey, TransactionReceipt};
 use crate::Storage;
 
 use super::TransactionStatus;
@@ -13,14 +15,18 @@ use crate::types::{deopaque_query, OpaqueError, OpaqueQuery, OpaqueReply};
 use anyhow::{Context, Error, Result};
 use core::{fmt, str};
 use parity_scale_codec::{Decode, Encode};
-use phala_mq::{BindTopic, EcdsaMessageChannel as MessageChannel, MessageOrigin};
-use phala_types::messaging::PushCommand;
+use phala_mq::{BindTopic, EcdsaMessageChannel as MessageChannel, MessageOrigin, Path, Topic};
+use phala_types::{
+    messaging::{contract_event_topic, BlockRewardInfo, PushCommand},
+    WorkerPublicKey,
+};
 
 use serde::{
     de::{self, DeserializeOwned, Visitor},
     Deserialize, Deserializer, Serialize, Serializer,
 };
-use sp_core::H256;
+use rand::{rngs::StdRng, SeedableRng};
+use sp_core::{hashing::blake2_256, H256, U256};
 use sp_runtime_interface::pass_by::PassByInner as _;
 
 pub mod assets;
@@ -28,6 +34,8 @@ pub mod balances;
 pub mod btc_lottery;
 pub mod data_plaza;
 pub mod diem;
+pub mod instances;
+pub mod nft;
 pub mod substrate_kitties;
 pub mod web3analytics;
 pub mod woothee;
@@ -41,6 +49,7 @@ pub const WEB3_ANALYTICS: ContractId = 4;
 pub const DIEM: ContractId = 5;
 pub const SUBSTRATE_KITTIES: ContractId = 6;
 pub const BTC_LOTTERY: ContractId = 7;
+pub const NFT: ContractId = 8;
 
 pub fn account_id_from_hex(accid_hex: &String) -> Result<chain::AccountId> {
     use core::convert::TryFrom;
@@ -48,6 +57,30 @@ pub fn account_id_from_hex(accid_hex: &String) -> Result<chain::AccountId> {
     chain::AccountId::try_from(bytes.as_slice()).map_err(|_| Error::msg("Bad account id"))
 }
 
+/// Derives the secret of the contract at `address` from the contract key shared by the
+/// gatekeepers, so every worker running the contract derives the same one
+pub fn contract_secret(contract_key: &SecretKey, address: &H256) -> [u8; 32] {
+    blake2_256(&(b"phala/contract/secret", contract_key, address).encode())
+}
+
+/// Serializes the state of a native contract, for `NativeContract::snapshot`
+pub fn snapshot_of<T: Serialize>(contract: &T) -> Option<Vec<u8>> {
+    serde_cbor::to_vec(contract).ok()
+}
+
+/// Deserializes the state of a native contract taken by `snapshot_of`
+pub fn restore_from<T: DeserializeOwned>(snapshot: &[u8]) -> Result<T> {
+    serde_cbor::from_slice(snapshot).map_err(|_| Error::msg("Bad contract snapshot"))
+}
+
+pub fn h256_from_hex(hex_str: &str) -> Result<H256> {
+    let bytes = hex::decode(hex_str.trim_start_matches("0x")).map_err(Error::msg)?;
+    if bytes.len() != 32 {
+        return Err(Error::msg("Bad H256 length"));
+    }
+    Ok(H256::from_slice(&bytes))
+}
+
 /// Serde module to serialize or deserialize parity scale codec types
 pub mod serde_scale {
     use crate::std::vec::Vec;
@@ -193,20 +226,318 @@ mod support {
 
     pub struct ExecuteEnv<'a> {
         pub block_number: chain::BlockNumber,
+        pub block_hash: H256,
         pub system: &'a mut System,
         pub storage: &'a Storage,
     }
 
     pub struct NativeContext<'a> {
         pub block_number: chain::BlockNumber,
+        pub block_hash: H256,
+        address: H256,
+        deployer: Option<&'a chain::AccountId>,
+        contract_id: ContractId,
+        secret: Option<[u8; 32]>,
         mq: &'a MessageChannel,
+        worker_mq: MessageChannel,
         osp_mq: OspMq<'a>,
+        storage: &'a Storage,
     }
 
     impl NativeContext<'_> {
         pub fn mq(&self) -> &MessageChannel {
             self.mq
         }
+
+        /// The channel of the worker running the contract.
+        ///
+        /// Unlike [`mq`](Self::mq), the messages are signed by the identity of the worker, to
+        /// report what only this worker knows, e.g. the outcome of the data it holds.
+        pub fn worker_mq(&self) -> &MessageChannel {
+            &self.worker_mq
+        }
+
+        /// Tells whether `worker` is registered in `pallet_registry::Worker`, so it runs a genuine
+        /// pRuntime
+        pub fn is_registered_worker(&self, worker: &WorkerPublicKey) -> bool {
+            self.storage
+                .get(&storage_key_for_worker_info(worker))
+                .is_some()
+        }
+
+        /// The address of the contract instance, also its `MessageOrigin::Contract`
+        pub fn address(&self) -> &H256 {
+            &self.address
+        }
+
+        /// The time of the block in seconds, from `pallet_timestamp::Now` in the synced state.
+        ///
+        /// Zero if the state isn't synced yet.
+        pub fn block_time(&self) -> u64 {
+            use crate::light_validation::utils::storage_prefix;
+            self.storage
+                .get(&storage_prefix("Timestamp", "Now"))
+                .and_then(|now| u64::decode(&mut &now[..]).ok())
+                .map_or(0, |now| now / 1000)
+        }
+
+        /// Tells whether the contract is instantiated by `pallet_registry::instantiate_contract`
+        /// rather than built in
+        pub fn is_instance(&self) -> bool {
+            self.deployer.is_some()
+        }
+
+        /// The seed of the block in `pallet_phala::BlockRewardSeeds`, zero if there's none.
+        ///
+        /// It's drawn by the chain, so it can't be chosen by the block author like the block
+        /// hash.
+        pub fn reward_seed(&self) -> U256 {
+            self.storage
+                .get(&storage_key_for_reward_seed(self.block_number))
+                .and_then(|info| BlockRewardInfo::decode(&mut &info[..]).ok())
+                .map_or_else(U256::zero, |info| info.seed)
+        }
+
+        /// Returns a RNG seeded by the current block hash, its reward seed, the contract address
+        /// and `nonce`.
+        ///
+        /// Every worker replaying the block draws the same values, so it's the one to use when
+        /// the values change the contract state. Pass a different nonce (e.g. the command number)
+        /// for each use within a block. The values can be predicted by anyone who knows the block,
+        /// so use [`secret_rng`](Self::secret_rng) for the values which must be kept private.
+        pub fn rng(&self, nonce: impl Encode) -> StdRng {
+            StdRng::from_seed(rng_seed(
+                &self.block_hash,
+                &self.reward_seed(),
+                &self.address,
+                nonce,
+            ))
+        }
+
+        /// Returns a RNG seeded by the secret of the contract, in addition to the seed of
+        /// [`rng`](Self::rng).
+        ///
+        /// The secret is derived from the contract key shared by the gatekeepers (see
+        /// [`contract_secret`]), so all the workers draw the same values while nobody outside the
+        /// enclaves can predict them.
+        ///
+        /// Fails until the key of the contract is registered on chain, so that every worker fails
+        /// the same commands whenever it received the contract key. The built-in contracts never
+        /// have their key registered, so it always fails for them.
+        pub fn secret_rng(&self, nonce: impl Encode) -> Result<StdRng> {
+            if self
+                .storage
+                .get(&storage_key_for_contract_key(&self.address))
+                .is_none()
+            {
+                return Err(Error::msg("Contract key not registered yet"));
+            }
+            let secret = self
+                .secret
+                .as_ref()
+                .ok_or_else(|| Error::msg("No secret for the contract"))?;
+            Ok(StdRng::from_seed(secret_rng_seed(
+                secret,
+                &self.block_hash,
+                &self.reward_seed(),
+                &self.address,
+                nonce,
+            )))
+        }
+
+        /// Sends a message encrypted to the key registered for its topic in
+        /// `pallet_registry::TopicKey`.
+        ///
+        /// Fails if the topic has no registered key or is an on-chain topic, since pallets can't
+        /// decrypt the message.
+        pub fn send_encrypted<M: Encode + BindTopic>(&self, message: &M) -> Result<()> {
+            let topic: Path = M::TOPIC.to_vec();
+            ensure_offchain(&topic)?;
+            let pubkey = self
+                .osp_mq
+                .get_pubkey(&topic)
+                .ok_or_else(|| Error::msg("No key registered for the topic"))?;
+            self.osp_mq.osp_send(message, Some(pubkey))
+        }
+
+        /// Sends a message to `to` encrypted to the ECDH key of a registered worker
+        pub fn send_encrypted_to_worker<M: Encode>(
+            &self,
+            message: &M,
+            to: impl Into<Path>,
+            worker: &WorkerPublicKey,
+        ) -> Result<()> {
+            let topic = to.into();
+            ensure_offchain(&topic)?;
+            let pubkey = self
+                .worker_ecdh_pubkey(worker)
+                .ok_or_else(|| Error::msg("Worker not registered"))?;
+            self.osp_mq.osp_sendto(message, topic, Some(pubkey))
+        }
+
+        /// Decrypts a message encrypted to the ECDH key of this worker.
+        ///
+        /// Fails if the message is encrypted to another key, tampered with or malformed.
+        pub fn decrypt<M: Decode>(&self, payload: OspPayload<M>) -> Result<M> {
+            match payload {
+                OspPayload::Plain(message) => Ok(message),
+                OspPayload::Encrypted(cipher) => self.decrypt_cipher(&cipher),
+            }
+        }
+
+        fn decrypt_cipher<M: Decode>(&self, cipher: &AeadCipher) -> Result<M> {
+            let data = self.osp_mq.decrypt(cipher)?;
+            M::decode(&mut &data[..]).map_err(|_| Error::msg("Failed to decode the decrypted data"))
+        }
+
+        /// Returns the admin of the contract: the deployer of an instantiated contract, or the one
+        /// set in `pallet_registry::ContractAdmin` for the built-in contracts
+        pub fn contract_admin(&self) -> Option<AccountIdWrapper> {
+            if let Some(deployer) = self.deployer {
+                return Some(AccountIdWrapper(deployer.clone()));
+            }
+            let admin = self
+                .storage
+                .get(&storage_key_for_contract_admin(self.contract_id))?;
+            chain::AccountId::decode(&mut &admin[..])
+                .ok()
+                .map(AccountIdWrapper)
+        }
+
+        /// Tells whether `account` is the admin of the contract
+        pub fn is_admin(&self, account: &AccountIdWrapper) -> bool {
+            self.contract_admin().as_ref() == Some(account)
+        }
+
+        /// Returns the address of the contract instance `account` belongs to, if any
+        pub fn instance_of(&self, account: &chain::AccountId) -> Option<H256> {
+            let raw: &[u8; 32] = account.as_ref();
+            let address = H256(*raw);
+            self.storage
+                .get(&storage_key_for_contract(&address))
+                .map(|_| address)
+        }
+
+        /// Sends an event encrypted to the contract instance `address` only, on its
+        /// `contract_event_topic`.
+        ///
+        /// Fails until a gatekeeper has registered the key of the topic, which happens when the
+        /// instance is activated.
+        pub fn send_to_instance<M: Encode + BindTopic>(
+            &self,
+            message: &M,
+            address: &H256,
+        ) -> Result<()> {
+            let topic = contract_event_topic(M::TOPIC, address);
+            let pubkey = self
+                .osp_mq
+                .get_pubkey(&topic)
+                .ok_or_else(|| Error::msg("No key registered for the topic of the instance"))?;
+            self.osp_mq.osp_sendto(message, topic, Some(pubkey))
+        }
+
+        /// Whether [`send_to_instance`](Self::send_to_instance) can send `M` to the instance
+        /// `address`
+        pub fn instance_reachable<M: BindTopic>(&self, address: &H256) -> bool {
+            self.osp_mq
+                .get_pubkey(&contract_event_topic(M::TOPIC, address))
+                .is_some()
+        }
+
+        fn worker_ecdh_pubkey(&self, worker: &WorkerPublicKey) -> Option<Vec<u8>> {
+            let info = self.storage.get(&storage_key_for_worker_info(worker))?;
+            let info = phala_pallets::pallet_registry::WorkerInfo::decode(&mut &info[..]).ok()?;
+            if info.ecdh_pubkey.is_empty() {
+                None
+            } else {
+                Some(info.ecdh_pubkey)
+            }
+        }
+    }
+
+    fn rng_seed(
+        block_hash: &H256,
+        reward_seed: &U256,
+        address: &H256,
+        nonce: impl Encode,
+    ) -> [u8; 32] {
+        blake2_256(&(b"native_rng", block_hash, reward_seed, address, nonce).encode())
+    }
+
+    fn secret_rng_seed(
+        secret: &[u8; 32],
+        block_hash: &H256,
+        reward_seed: &U256,
+        address: &H256,
+        nonce: impl Encode,
+    ) -> [u8; 32] {
+        blake2_256(
+            &(
+                b"native_secret_rng",
+                secret,
+                block_hash,
+                reward_seed,
+                address,
+                nonce,
+            )
+                .encode(),
+        )
+    }
+
+    fn storage_key_for_reward_seed(block_number: chain::BlockNumber) -> Vec<u8> {
+        use crate::light_validation::utils::storage_map_prefix_twox_64_concat;
+        use phala_pallets::pallet_mq::StorageMapTrait as _;
+
+        type BlockRewardSeeds = phala_pallets::pallet_phala::BlockRewardSeeds<chain::Runtime>;
+
+        let module_prefix = BlockRewardSeeds::module_prefix();
+        let storage_prefix = BlockRewardSeeds::storage_prefix();
+
+        storage_map_prefix_twox_64_concat(module_prefix, storage_prefix, &block_number)
+    }
+
+    fn storage_key_for_contract_admin(contract_id: ContractId) -> Vec<u8> {
+        use crate::light_validation::utils::storage_map_prefix_twox_64_concat;
+        use phala_pallets::pallet_mq::StorageMapTrait as _;
+
+        type ContractAdmin = phala_pallets::pallet_registry::ContractAdmin<chain::Runtime>;
+
+        let module_prefix = ContractAdmin::module_prefix();
+        let storage_prefix = ContractAdmin::storage_prefix();
+
+        storage_map_prefix_twox_64_concat(module_prefix, storage_prefix, &contract_id)
+    }
+
+    fn storage_key_for_contract_key(address: &H256) -> Vec<u8> {
+        use crate::light_validation::utils::storage_map_prefix_twox_64_concat;
+        use phala_pallets::pallet_mq::StorageMapTrait as _;
+
+        type ContractKey = phala_pallets::pallet_registry::ContractKey<chain::Runtime>;
+
+        let module_prefix = ContractKey::module_prefix();
+        let storage_prefix = ContractKey::storage_prefix();
+
+        storage_map_prefix_twox_64_concat(module_prefix, storage_prefix, address)
+    }
+
+    fn storage_key_for_contract(address: &H256) -> Vec<u8> {
+        use crate::light_validation::utils::storage_map_prefix_twox_64_concat;
+        use phala_pallets::pallet_mq::StorageMapTrait as _;
+
+        type Contracts = phala_pallets::pallet_registry::Contracts<chain::Runtime>;
+
+        let module_prefix = Contracts::module_prefix();
+        let storage_prefix = Contracts::storage_prefix();
+
+        storage_map_prefix_twox_64_concat(module_prefix, storage_prefix, address)
+    }
+
+    fn ensure_offchain(topic: &Path) -> Result<()> {
+        if Topic::new(topic.clone()).is_offchain() {
+            Ok(())
+        } else {
+            Err(Error::msg("Can not send encrypted messages to on-chain topics"))
+        }
     }
 
     pub trait Contract: Send + Sync {
@@ -217,6 +548,16 @@ mod support {
             req: OpaqueQuery,
         ) -> Result<OpaqueReply, OpaqueError>;
         fn process_events(&mut self, env: &mut ExecuteEnv);
+        /// Switches to the keys of the worker after its identity key is rotated.
+        ///
+        /// `send_mq` is only given to the built-in contracts, which send their messages with the
+        /// identity key of the worker.
+        fn set_worker_keys(&mut self, send_mq: Option<MessageChannel>, ecdh_key: KeyPair);
+        /// The state to be carried over by a secret migration, or None if the contract can't be
+        /// migrated
+        fn snapshot(&self) -> Option<Vec<u8>>;
+        /// Restores the state taken by `snapshot` on the previous pRuntime
+        fn restore(&mut self, snapshot: &[u8]) -> Result<()>;
     }
 
     pub trait NativeContract {
@@ -241,11 +582,21 @@ mod support {
             _event: Self::Event,
         ) {
         }
+        /// Called once per block, after the messages of the block are handled
+        fn on_block_end(&mut self, _context: &NativeContext) {}
         fn handle_query(
             &mut self,
             origin: Option<&chain::AccountId>,
             req: Self::QReq,
         ) -> Self::QResp;
+        /// The state to be carried over by a secret migration. The contracts whose state can't be
+        /// serialized can't be migrated.
+        fn snapshot(&self) -> Option<Vec<u8>> {
+            None
+        }
+        fn restore(&mut self, _snapshot: &[u8]) -> Result<()> {
+            Err(Error::msg("The contract can't be migrated"))
+        }
     }
 
     pub struct NativeCompatContract<
@@ -275,6 +626,11 @@ mod support {
         cmd_rcv_mq: PeelingReceiver<PushCommand<Cmd>, CmdWrp, CmdPlr>,
         event_rcv_mq: PeelingReceiver<Event, EventWrp, EventPlr>,
         ecdh_key: KeyPair,
+        /// Only set for the instances. The built-in ones have no secret, since the workers receive
+        /// the contract key at different blocks.
+        secret: Option<[u8; 32]>,
+        address: H256,
+        deployer: Option<chain::AccountId>,
     }
 
     impl<Con, Cmd, CmdWrp, CmdPlr, Event, EventWrp, EventPlr, QReq, QResp>
@@ -297,15 +653,28 @@ mod support {
             cmd_rcv_mq: PeelingReceiver<PushCommand<Cmd>, CmdWrp, CmdPlr>,
             event_rcv_mq: PeelingReceiver<Event, EventWrp, EventPlr>,
             ecdh_key: KeyPair,
+            secret: Option<[u8; 32]>,
         ) -> Self {
+            let address = H256::from_low_u64_be(contract.id() as u64);
             NativeCompatContract {
                 contract,
                 send_mq,
                 cmd_rcv_mq,
                 event_rcv_mq,
                 ecdh_key,
+                secret,
+                address,
+                deployer: None,
             }
         }
+
+        /// Makes it the contract instance `address` instantiated by `deployer`, instead of the
+        /// built-in one
+        pub fn instance(mut self, address: H256, deployer: chain::AccountId) -> Self {
+            self.address = address;
+            self.deployer = Some(deployer);
+            self
+        }
     }
 
     impl<Con, Cmd, CmdWrp, CmdPlr, Event, EventWrp, EventPlr, QReq, QResp> Contract
@@ -349,8 +718,15 @@ mod support {
             let osp_mq = OspMq::new(&self.ecdh_key, &self.send_mq, &key_map);
             let context = NativeContext {
                 block_number: env.block_number,
+                block_hash: env.block_hash,
+                address: self.address,
+                deployer: self.deployer.as_ref(),
+                contract_id: self.contract.id(),
+                secret: self.secret,
                 mq: &self.send_mq,
+                worker_mq: env.system.worker_mq().clone(),
                 osp_mq,
+                storage,
             };
             loop {
                 let ok = phala_mq::select! {
@@ -387,6 +763,65 @@ mod support {
                     break;
                 }
             }
+            self.contract.on_block_end(&context);
+        }
+
+        fn set_worker_keys(&mut self, send_mq: Option<MessageChannel>, ecdh_key: KeyPair) {
+            if let Some(send_mq) = send_mq {
+                self.send_mq = send_mq;
+            }
+            self.ecdh_key = ecdh_key;
+        }
+
+        fn snapshot(&self) -> Option<Vec<u8>> {
+            self.contract.snapshot()
+        }
+
+        fn restore(&mut self, snapshot: &[u8]) -> Result<()> {
+            self.contract.restore(snapshot)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const ADDRESS: H256 = H256([1; 32]);
+        const BLOCK_HASH: H256 = H256([2; 32]);
+
+        #[test]
+        fn rng_seed_is_deterministic() {
+            let reward_seed = U256::from(42);
+            assert_eq!(
+                rng_seed(&BLOCK_HASH, &reward_seed, &ADDRESS, 1u64),
+                rng_seed(&BLOCK_HASH, &reward_seed, &ADDRESS, 1u64)
+            );
+            let seed = rng_seed(&BLOCK_HASH, &reward_seed, &ADDRESS, 1u64);
+            assert_ne!(seed, rng_seed(&BLOCK_HASH, &reward_seed, &ADDRESS, 2u64));
+            assert_ne!(seed, rng_seed(&BLOCK_HASH, &U256::from(43), &ADDRESS, 1u64));
+            assert_ne!(seed, rng_seed(&H256([3; 32]), &reward_seed, &ADDRESS, 1u64));
+            assert_ne!(seed, rng_seed(&BLOCK_HASH, &reward_seed, &H256([3; 32]), 1u64));
+        }
+
+        #[test]
+        fn secret_rng_seed_depends_on_secret() {
+            let reward_seed = U256::from(42);
+            let secret = contract_secret(&[7; 32], &ADDRESS);
+            // Shared by the workers holding the same contract key
+            assert_eq!(secret, contract_secret(&[7; 32], &ADDRESS));
+            assert_ne!(secret, contract_secret(&[8; 32], &ADDRESS));
+            assert_ne!(secret, contract_secret(&[7; 32], &H256([3; 32])));
+
+            let seed = secret_rng_seed(&secret, &BLOCK_HASH, &reward_seed, &ADDRESS, 1u64);
+            assert_eq!(
+                seed,
+                secret_rng_seed(&secret, &BLOCK_HASH, &reward_seed, &ADDRESS, 1u64)
+            );
+            assert_ne!(
+                seed,
+                secret_rng_seed(&[0; 32], &BLOCK_HASH, &reward_seed, &ADDRESS, 1u64)
+            );
+            assert_ne!(seed, rng_seed(&BLOCK_HASH, &reward_seed, &ADDRESS, 1u64));
         }
     }
 }
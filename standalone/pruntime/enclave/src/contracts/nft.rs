@@ -0,0 +1,399 @@
+use crate::std::collections::{BTreeMap, BTreeSet};
+use crate::std::string::{String, ToString};
+use crate::std::vec::Vec;
+use anyhow::Result;
+use core::fmt;
+use log::info;
+use phala_mq::MessageOrigin;
+use serde::{Deserialize, Serialize};
+
+use super::{NativeContext, TransactionStatus};
+use crate::contracts;
+use crate::contracts::AccountIdWrapper;
+use phala_types::messaging::{NftCommand, PushCommand};
+
+extern crate runtime as chain;
+
+type Command = NftCommand<chain::AccountId>;
+
+pub use phala_contract_queries::nft::{Request, TokenId};
+pub type Response = phala_contract_queries::nft::Response<AccountIdWrapper>;
+
+/// The ownership, the approvals and the private metadata of a set of non-fungible tokens.
+///
+/// Shared by the contracts managing NFTs, e.g. `Nft` and the blind boxes of `SubstrateKitties`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NftLedger {
+    owners: BTreeMap<TokenId, AccountIdWrapper>,
+    owned: BTreeMap<AccountIdWrapper, BTreeSet<TokenId>>,
+    /// The account allowed to transfer or burn a token on behalf of its owner
+    approvals: BTreeMap<TokenId, AccountIdWrapper>,
+    /// Only readable by the holder of the token
+    metadata: BTreeMap<TokenId, Vec<u8>>,
+}
+
+impl NftLedger {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn mint(
+        &mut self,
+        id: TokenId,
+        owner: AccountIdWrapper,
+        metadata: Vec<u8>,
+    ) -> Result<(), TransactionStatus> {
+        if self.owners.contains_key(&id) {
+            return Err(TransactionStatus::TokenExists);
+        }
+        self.owned
+            .entry(owner.clone())
+            .or_default()
+            .insert(id.clone());
+        self.owners.insert(id.clone(), owner);
+        self.metadata.insert(id, metadata);
+        Ok(())
+    }
+
+    pub fn owner_of(&self, id: &str) -> Option<&AccountIdWrapper> {
+        self.owners.get(id)
+    }
+
+    pub fn approved(&self, id: &str) -> Option<&AccountIdWrapper> {
+        self.approvals.get(id)
+    }
+
+    pub fn tokens_of(&self, account: &AccountIdWrapper) -> Vec<TokenId> {
+        self.owned
+            .get(account)
+            .map(|tokens| tokens.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the metadata of the token if `holder` owns it
+    pub fn metadata_of(&self, holder: &AccountIdWrapper, id: &str) -> Option<&[u8]> {
+        if self.owners.get(id) != Some(holder) {
+            return None;
+        }
+        self.metadata.get(id).map(|metadata| &metadata[..])
+    }
+
+    /// Returns the owner of the token if `operator` is the owner or the approved account
+    fn check_operator(
+        &self,
+        operator: &AccountIdWrapper,
+        id: &str,
+    ) -> Result<AccountIdWrapper, TransactionStatus> {
+        let owner = self
+            .owners
+            .get(id)
+            .ok_or(TransactionStatus::TokenNotFound)?;
+        if owner != operator && self.approvals.get(id) != Some(operator) {
+            return Err(TransactionStatus::NotTokenOwner);
+        }
+        Ok(owner.clone())
+    }
+
+    fn remove_owned(&mut self, owner: &AccountIdWrapper, id: &str) {
+        if let Some(tokens) = self.owned.get_mut(owner) {
+            tokens.remove(id);
+            if tokens.is_empty() {
+                self.owned.remove(owner);
+            }
+        }
+    }
+
+    /// Transfers the token to `dest` and clears its approval
+    pub fn transfer(
+        &mut self,
+        operator: &AccountIdWrapper,
+        id: &str,
+        dest: AccountIdWrapper,
+    ) -> Result<(), TransactionStatus> {
+        let owner = self.check_operator(operator, id)?;
+        self.approvals.remove(id);
+        self.remove_owned(&owner, id);
+        self.owned
+            .entry(dest.clone())
+            .or_default()
+            .insert(id.to_string());
+        self.owners.insert(id.to_string(), dest);
+        Ok(())
+    }
+
+    /// Approves `spender` to transfer or burn the token, or clears the approval if it's `None`
+    pub fn approve(
+        &mut self,
+        owner: &AccountIdWrapper,
+        id: &str,
+        spender: Option<AccountIdWrapper>,
+    ) -> Result<(), TransactionStatus> {
+        let token_owner = self
+            .owners
+            .get(id)
+            .ok_or(TransactionStatus::TokenNotFound)?;
+        if token_owner != owner {
+            return Err(TransactionStatus::NotTokenOwner);
+        }
+        match spender {
+            Some(spender) => self.approvals.insert(id.to_string(), spender),
+            None => self.approvals.remove(id),
+        };
+        Ok(())
+    }
+
+    /// Destroys the token, returning its metadata
+    pub fn burn(
+        &mut self,
+        operator: &AccountIdWrapper,
+        id: &str,
+    ) -> Result<Vec<u8>, TransactionStatus> {
+        let owner = self.check_operator(operator, id)?;
+        self.approvals.remove(id);
+        self.remove_owned(&owner, id);
+        self.owners.remove(id);
+        Ok(self.metadata.remove(id).unwrap_or_default())
+    }
+}
+
+/// A confidential NFT collection. The admin mints the tokens, and only the holder of a token
+/// can read its metadata.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Nft {
+    tokens: NftLedger,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Error {
+    NotAuthorized,
+    TokenNotFound,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotAuthorized => write!(f, "not authorized"),
+            Error::TokenNotFound => write!(f, "token not found"),
+        }
+    }
+}
+
+impl Nft {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn handle(
+        &mut self,
+        context: &NativeContext,
+        o: AccountIdWrapper,
+        command: Command,
+    ) -> Result<(), TransactionStatus> {
+        match command {
+            Command::Mint { id, dest, metadata } => {
+                if !context.is_admin(&o) {
+                    return Err(TransactionStatus::NotAdmin);
+                }
+                info!("Mint: [{}] -> [{}]", id, AccountIdWrapper(dest.clone()).to_string());
+                self.tokens.mint(id, AccountIdWrapper(dest), metadata)
+            }
+            Command::Transfer { id, dest } => {
+                let dest = AccountIdWrapper(dest);
+                info!(
+                    "Transfer: [{}] -> [{}]: {}",
+                    o.to_string(),
+                    dest.to_string(),
+                    id
+                );
+                self.tokens.transfer(&o, &id, dest)
+            }
+            Command::Approve { id, spender } => {
+                self.tokens.approve(&o, &id, spender.map(AccountIdWrapper))
+            }
+            Command::Burn { id } => {
+                info!("Burn: [{}] by [{}]", id, o.to_string());
+                self.tokens.burn(&o, &id).map(|_| ())
+            }
+        }
+    }
+}
+
+impl contracts::NativeContract for Nft {
+    type Cmd = Command;
+    type Event = ();
+    type QReq = Request;
+    type QResp = Response;
+
+    fn id(&self) -> contracts::ContractId {
+        contracts::NFT
+    }
+
+    fn handle_command(
+        &mut self,
+        context: &NativeContext,
+        origin: MessageOrigin,
+        cmd: PushCommand<Self::Cmd>,
+    ) -> TransactionStatus {
+        let origin = match origin {
+            MessageOrigin::AccountId(acc) => acc,
+            _ => return TransactionStatus::BadOrigin,
+        };
+        match self.handle(context, AccountIdWrapper::from(origin), cmd.command) {
+            Ok(()) => TransactionStatus::Ok,
+            Err(status) => status,
+        }
+    }
+
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        contracts::snapshot_of(self)
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<()> {
+        *self = contracts::restore_from(snapshot)?;
+        Ok(())
+    }
+
+    fn handle_query(&mut self, origin: Option<&chain::AccountId>, req: Self::QReq) -> Self::QResp {
+        let inner = || -> Result<Response> {
+            let not_found = || anyhow::Error::msg(Error::TokenNotFound);
+            let sender = || {
+                origin
+                    .map(|acc| AccountIdWrapper(acc.clone()))
+                    .ok_or_else(|| anyhow::Error::msg(Error::NotAuthorized))
+            };
+            match req {
+                Request::OwnerOf { id } => {
+                    let owner = self.tokens.owner_of(&id).ok_or_else(not_found)?;
+                    let sender = sender()?;
+                    if owner != &sender && self.tokens.approved(&id) != Some(&sender) {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    Ok(Response::OwnerOf {
+                        owner: owner.clone(),
+                    })
+                }
+                Request::OwnedTokens => Ok(Response::OwnedTokens {
+                    tokens: self.tokens.tokens_of(&sender()?),
+                }),
+                Request::Metadata { id } => {
+                    let metadata = self
+                        .tokens
+                        .metadata_of(&sender()?, &id)
+                        .ok_or_else(|| anyhow::Error::msg(Error::NotAuthorized))?;
+                    Ok(Response::Metadata {
+                        metadata: metadata.to_vec(),
+                    })
+                }
+                Request::Approved { id } => {
+                    let owner = self.tokens.owner_of(&id).ok_or_else(not_found)?;
+                    if owner != &sender()? {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    Ok(Response::Approved {
+                        spender: self.tokens.approved(&id).cloned(),
+                    })
+                }
+            }
+        };
+        match inner() {
+            Err(error) => Response::Error(error.to_string()),
+            Ok(resp) => resp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use contracts::NativeContract as _;
+
+    fn account(n: u8) -> AccountIdWrapper {
+        AccountIdWrapper(chain::AccountId::from([n; 32]))
+    }
+
+    fn ledger() -> NftLedger {
+        let mut ledger = NftLedger::new();
+        ledger
+            .mint("a".to_string(), account(1), b"secret".to_vec())
+            .unwrap();
+        ledger
+    }
+
+    #[test]
+    fn mint_and_read_metadata() {
+        let mut ledger = ledger();
+        assert!(matches!(
+            ledger.mint("a".to_string(), account(2), Vec::new()),
+            Err(TransactionStatus::TokenExists)
+        ));
+        assert_eq!(ledger.owner_of("a"), Some(&account(1)));
+        assert_eq!(ledger.tokens_of(&account(1)), vec!["a".to_string()]);
+        assert_eq!(ledger.metadata_of(&account(1), "a"), Some(&b"secret"[..]));
+        // Only readable by the holder
+        assert_eq!(ledger.metadata_of(&account(2), "a"), None);
+    }
+
+    #[test]
+    fn transfer_by_owner_or_approved() {
+        let mut ledger = ledger();
+        assert!(matches!(
+            ledger.transfer(&account(2), "a", account(2)),
+            Err(TransactionStatus::NotTokenOwner)
+        ));
+        assert!(matches!(
+            ledger.transfer(&account(1), "b", account(2)),
+            Err(TransactionStatus::TokenNotFound)
+        ));
+        assert!(matches!(
+            ledger.approve(&account(2), "a", Some(account(2))),
+            Err(TransactionStatus::NotTokenOwner)
+        ));
+        ledger.approve(&account(1), "a", Some(account(2))).unwrap();
+        ledger.transfer(&account(2), "a", account(3)).unwrap();
+        assert_eq!(ledger.owner_of("a"), Some(&account(3)));
+        assert!(ledger.tokens_of(&account(1)).is_empty());
+        assert_eq!(ledger.metadata_of(&account(3), "a"), Some(&b"secret"[..]));
+        // The approval is cleared by the transfer
+        assert_eq!(ledger.approved("a"), None);
+        assert!(matches!(
+            ledger.transfer(&account(2), "a", account(2)),
+            Err(TransactionStatus::NotTokenOwner)
+        ));
+    }
+
+    #[test]
+    fn burn_by_owner_or_approved() {
+        let mut ledger = ledger();
+        assert!(matches!(
+            ledger.burn(&account(2), "a"),
+            Err(TransactionStatus::NotTokenOwner)
+        ));
+        ledger.approve(&account(1), "a", Some(account(2))).unwrap();
+        assert_eq!(ledger.burn(&account(2), "a").ok(), Some(b"secret".to_vec()));
+        assert_eq!(ledger.owner_of("a"), None);
+        assert_eq!(ledger.approved("a"), None);
+        assert!(ledger.tokens_of(&account(1)).is_empty());
+        assert!(matches!(
+            ledger.burn(&account(1), "a"),
+            Err(TransactionStatus::TokenNotFound)
+        ));
+    }
+
+    #[test]
+    fn owner_of_is_private() {
+        let mut nft = Nft::new();
+        nft.tokens = ledger();
+        let query = |nft: &mut Nft, origin: Option<u8>| {
+            let origin = origin.map(|n| account(n).0);
+            nft.handle_query(origin.as_ref(), Request::OwnerOf { id: "a".to_string() })
+        };
+        assert!(matches!(query(&mut nft, None), Response::Error(_)));
+        assert!(matches!(query(&mut nft, Some(2)), Response::Error(_)));
+        assert!(matches!(
+            query(&mut nft, Some(1)),
+            Response::OwnerOf { owner } if owner == account(1)
+        ));
+        nft.tokens.approve(&account(1), "a", Some(account(2))).unwrap();
+        assert!(matches!(query(&mut nft, Some(2)), Response::OwnerOf { .. }));
+    }
+}
@@ -3,10 +3,8 @@ use serde::{Deserialize, Serialize};
 use crate::contracts;
 use crate::contracts::AccountIdWrapper;
 use crate::TransactionStatus;
-use lazy_static;
 use sp_core::hashing::blake2_128;
 use sp_core::H256 as Hash;
-use sp_core::U256;
 extern crate runtime as chain;
 use parity_scale_codec::{Decode, Encode};
 
@@ -16,6 +14,7 @@ use crate::std::string::String;
 use crate::std::vec::Vec;
 use rand::Rng;
 
+use super::nft::NftLedger;
 use super::NativeContext;
 use chain::pallet_mq::MessageOriginInfo;
 use phala_types::messaging::{bind_topic, KittyEvent, KittyTransfer, MessageOrigin, PushCommand};
@@ -23,22 +22,6 @@ use phala_types::messaging::{bind_topic, KittyEvent, KittyTransfer, MessageOrigi
 type Event = KittyEvent<chain::AccountId, chain::Hash>;
 type Transfer = KittyTransfer<chain::AccountId>;
 
-const ALICE: &'static str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
-// 5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY
-// const BOB: &'static str = "8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48";
-// 5FHneW46xGXgs5mUiveU4sbTyGBzmstUspZC92UhjJM694ty
-// const CHARLIE: &'static str = "90b5ab205c6974c9ea841be688864633dc9ca8a357843eeacf2314649965fe22";
-// 5FLSigC9HGRKVhB9FiEo4Y3koPsNmBmLJbpXg2mp1hXcS59Y
-
-lazy_static! {
-    // 10000...000, used to tell if this is a NFT
-    static ref TYPE_NF_BIT: U256 = U256::from(1) << 255;
-    // 1111...11000...00, used to store the type in the upper 128 bits
-    static ref TYPE_MASK: U256 = U256::from(!u128::MIN) << 128;
-    // 00...00011....11, used to get NFT index in the lower 128 bits
-    static ref NF_INDEX_MASK: U256 = U256::from(!u128::MIN);
-}
-
 /// SubstrateKitties contract states.
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct SubstrateKitties {
@@ -46,13 +29,8 @@ pub struct SubstrateKitties {
     /// Use Vec<u8> to represent kitty id
     kitties: BTreeMap<Vec<u8>, Kitty>,
     blind_boxes: BTreeMap<String, BlindBox>,
-    /// Record the NFT's owner, if we introduce FT later, this field can be moved
-    owner: BTreeMap<String, AccountIdWrapper>,
-    /// Record the balance of an account's tokens, the first index is a tuple consists of tokenId and accountId
-    /// the second index is the balance, for NFT it's always 1, for FT, it can be any number
-    balances: BTreeMap<(String, AccountIdWrapper), chain::Balance>,
-    /// Record the boxes list which the owners own
-    owned_boxes: BTreeMap<AccountIdWrapper, Vec<String>>,
+    /// The ownership and the approvals of the blind boxes
+    boxes: NftLedger,
     /// Record the boxes the users opened
     opend_boxes: Vec<String>,
     /// This variable records if there are kitties that not in the boxes
@@ -65,11 +43,7 @@ impl core::fmt::Debug for SubstrateKitties {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
-pub struct BlindBox {
-    // Use String to store the U256 type ID, preventing the Serialize implementation
-    blind_box_id: String,
-}
+pub use phala_contract_queries::substrate_kitties::{BlindBox, Error, Request};
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Kitty {
@@ -83,50 +57,19 @@ bind_topic!(Command, b"phala/kitties/command");
 pub enum Command {
     /// Pack the kitties into the corresponding blind boxes
     Pack {},
-    /// Transfer the box to another account, need to judge if the sender is the owner
+    /// Transfer the box to another account, by its owner or the approved account
     Transfer { dest: String, blind_box_id: String },
-    /// Open the specific blind box to get the kitty
+    /// Open the specific blind box to get the kitty, burning the box
     Open { blind_box_id: String },
-}
-
-/// The errors that the contract could throw for some queries
-#[derive(Serialize, Deserialize, Debug)]
-pub enum Error {
-    NotAuthorized,
-}
-
-/// Query requests. The end users can only query the contract states by sending requests.
-/// Queries are not supposed to write to the contract states.
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum Request {
-    /// Users can require to see the blind boxes list
-    ObserveBox,
-    /// Users can require to see their owned boxes list
-    ObserveOwnedBox,
-    /// Users can require to see the kitties which are not in the boxes
-    ObserveLeftKitties,
-    /// Users can require to know the owner of the specific box(NFT only)
-    OwnerOf { blind_box_id: String },
+    /// Approve an account to transfer the box, or clear the approval
+    Approve {
+        blind_box_id: String,
+        spender: Option<String>,
+    },
 }
 
 /// Query responses.
-#[derive(Serialize, Deserialize, Debug)]
-pub enum Response {
-    ObserveBox {
-        blind_box: BTreeMap<String, BlindBox>,
-    },
-    ObserveOwnedBox {
-        owned_box: Vec<String>,
-    },
-    ObserveLeftKitties {
-        kitties: Vec<Vec<u8>>,
-    },
-    OwnerOf {
-        owner: AccountIdWrapper,
-    },
-    /// Something wrong happened
-    Error(Error),
-}
+pub type Response = phala_contract_queries::substrate_kitties::Response<AccountIdWrapper>;
 
 impl SubstrateKitties {
     /// Initializes the contract
@@ -134,16 +77,11 @@ impl SubstrateKitties {
         let schrodingers = BTreeMap::<String, Vec<u8>>::new();
         let kitties = BTreeMap::<Vec<u8>, Kitty>::new();
         let blind_boxes = BTreeMap::<String, BlindBox>::new();
-        let owner = BTreeMap::<String, AccountIdWrapper>::new();
-        let balances = BTreeMap::<(String, AccountIdWrapper), chain::Balance>::new();
-        let owned_boxes = BTreeMap::<AccountIdWrapper, Vec<String>>::new();
         SubstrateKitties {
             schrodingers,
             kitties,
             blind_boxes,
-            owner,
-            balances,
-            owned_boxes,
+            boxes: NftLedger::new(),
             opend_boxes: Vec::new(),
             left_kitties: Vec::new(),
         }
@@ -176,133 +114,123 @@ impl contracts::NativeContract for SubstrateKitties {
         match cmd.command {
             // Handle the `Pack` command
             Command::Pack {} => {
-                // Create corresponding amount of kitties and blind boxes if there are
-                // indeed some kitties that need to be packed
+                // Create corresponding amount of blind boxes if there are indeed some kitties
+                // that need to be packed
                 if !self.left_kitties.is_empty() {
+                    // The boxes must stay blind, so the assignment can't be drawn from the public
+                    // block seed
+                    let mut rng = match context.secret_rng(cmd.number) {
+                        Ok(rng) => rng,
+                        Err(err) => {
+                            error!("Pack: {}", err);
+                            return TransactionStatus::BadSecret;
+                        }
+                    };
                     let mut nonce = 1;
-                    let _sender = AccountIdWrapper::from(origin);
-                    // This indicates the token's kind, let's just suppose that 1 represents box,
-                    // maybe if we introduce more tokens later, this can be formulated strictly
-                    let kind = 1;
-                    // For now, the token id is 0....01000...000, this will be used to construct the
-                    // box ID below, combined with random index id and NFT flags
-                    let token_id: U256 = U256::from(kind) << 128;
-                    // Let's just suppose that ALICE owns all the boxes as default, and ALICE can
-                    // transfer to anyone that is on the chain
-                    let default_owner = AccountIdWrapper::from_hex(ALICE)
-                        .expect("Admin must has a good address; qed.");
-                    for (kitty_id, _kitty) in self.kitties.iter() {
-                        let mut rng = rand::thread_rng();
+                    // The contract admin owns all the boxes as default, and can transfer them
+                    // to anyone that is on the chain
+                    let default_owner = match context.contract_admin() {
+                        Some(admin) => admin,
+                        None => return TransactionStatus::NotAdmin,
+                    };
+                    let left_kitties = core::mem::take(&mut self.left_kitties);
+                    for kitty_id in left_kitties {
                         let seed: [u8; 16] = rng.gen();
                         let raw_data = (seed, nonce, &kitty_id);
                         nonce += 1;
                         let hash_data = blake2_128(&Encode::encode(&raw_data));
-                        let index_id = u128::from_be_bytes(hash_data);
-                        let nft_id = (token_id + index_id) | *TYPE_NF_BIT;
-                        let blind_box_id = format!("{:#x}", nft_id);
-                        let new_blind_box = BlindBox {
-                            blind_box_id: blind_box_id.clone(),
-                        };
-                        let mut box_list = match self.owned_boxes.get(&default_owner) {
-                            Some(_) => self.owned_boxes.get(&default_owner).unwrap().clone(),
-                            None => Vec::new(),
-                        };
-                        box_list.push(blind_box_id.clone());
-                        if nft_id & *TYPE_NF_BIT == *TYPE_NF_BIT {
-                            // is NFT
-                            self.owned_boxes.insert(default_owner.clone(), box_list);
-                            self.owner
-                                .insert(blind_box_id.clone(), default_owner.clone());
-                            self.balances
-                                .insert((blind_box_id.clone(), default_owner.clone()), 1);
-                        } else if nft_id & *TYPE_NF_BIT == U256::from(0) {
-                            // is FT
-                            let mut ft_balance = match self
-                                .balances
-                                .get(&(blind_box_id.clone(), default_owner.clone()))
-                            {
-                                Some(_) => self
-                                    .balances
-                                    .get(&(blind_box_id.clone(), default_owner.clone()))
-                                    .unwrap()
-                                    .clone(),
-                                None => 0,
-                            };
-                            ft_balance += 1;
-                            self.balances
-                                .insert((blind_box_id.clone(), default_owner.clone()), ft_balance);
+                        let blind_box_id = format!("{:#x}", u128::from_be_bytes(hash_data));
+                        if let Err(status) = self.boxes.mint(
+                            blind_box_id.clone(),
+                            default_owner.clone(),
+                            Vec::new(),
+                        ) {
+                            error!("Failed to pack kitty {:?}: {:?}", kitty_id, status);
+                            self.left_kitties.push(kitty_id);
+                            continue;
                         }
-                        self.schrodingers
-                            .insert(blind_box_id.clone(), (*kitty_id).clone());
-                        self.blind_boxes.insert(blind_box_id.clone(), new_blind_box);
+                        self.schrodingers.insert(blind_box_id.clone(), kitty_id);
+                        self.blind_boxes
+                            .insert(blind_box_id.clone(), BlindBox { blind_box_id });
                     }
-                    // After this, new kitties are all packed into boxes
-                    self.left_kitties.clear();
                 }
                 // Returns TransactionStatus::Ok to indicate a successful transaction
                 TransactionStatus::Ok
             }
             Command::Transfer { dest, blind_box_id } => {
-                // TODO: check owner & dest not overflow & sender not underflow
                 let sender = AccountIdWrapper::from(origin);
-                let original_owner = self.owner.get(&blind_box_id).unwrap().clone();
                 let reciever = match AccountIdWrapper::from_hex(&dest) {
                     Ok(a) => a,
                     Err(_) => return TransactionStatus::BadInput,
                 };
-                if sender == original_owner {
-                    println!(
-                        "Transfer: [{}] -> [{}]: {}",
-                        sender.to_string(),
-                        dest,
-                        blind_box_id
-                    );
-                    self.owner.insert(blind_box_id.clone(), reciever.clone());
-
-                    let mut box_list = self.owned_boxes.get(&sender).unwrap().clone();
-                    box_list.retain(|x| x != &blind_box_id);
-                    self.owned_boxes.insert(sender, box_list);
-
-                    // Add this opened box to the new owner
-                    let mut new_owned_list = match self.owned_boxes.get(&reciever) {
-                        Some(_) => self.owned_boxes.get(&reciever).unwrap().clone(),
-                        None => Vec::new(),
-                    };
-                    new_owned_list.push(blind_box_id);
-                    self.owned_boxes.insert(reciever, new_owned_list);
+                info!(
+                    "Transfer: [{}] -> [{}]: {}",
+                    sender.to_string(),
+                    dest,
+                    blind_box_id
+                );
+                match self.boxes.transfer(&sender, &blind_box_id, reciever) {
+                    Ok(()) => TransactionStatus::Ok,
+                    Err(status) => status,
+                }
+            }
+            Command::Approve {
+                blind_box_id,
+                spender,
+            } => {
+                let sender = AccountIdWrapper::from(origin);
+                let spender = match spender.map(|s| AccountIdWrapper::from_hex(&s)).transpose() {
+                    Ok(spender) => spender,
+                    Err(_) => return TransactionStatus::BadInput,
+                };
+                match self.boxes.approve(&sender, &blind_box_id, spender) {
+                    Ok(()) => TransactionStatus::Ok,
+                    Err(status) => status,
                 }
-                // Returns TransactionStatus::Ok to indicate a successful transaction
-                TransactionStatus::Ok
             }
             Command::Open { blind_box_id } => {
                 let sender = AccountIdWrapper::from(origin);
-                let original_owner = self.owner.get(&blind_box_id).unwrap().clone();
-                // Open the box if it's legal and not opened yet
-                if sender == original_owner
-                    && self.schrodingers.contains_key(&blind_box_id)
-                    && !self.opend_boxes.contains(&blind_box_id)
-                {
-                    // Get the kitty based on blind_box_id
-                    let kitty_id = self.schrodingers.get(&blind_box_id).unwrap();
-
-                    let kitty_id = Hash::from_slice(&kitty_id);
+                match self.boxes.owner_of(&blind_box_id) {
+                    Some(owner) if owner == &sender => {}
+                    Some(_) => return TransactionStatus::NotTokenOwner,
+                    None => return TransactionStatus::TokenNotFound,
+                }
+                // Get the kitty based on blind_box_id
+                let kitty_id = match self.schrodingers.get(&blind_box_id) {
+                    Some(kitty_id) => Hash::from_slice(kitty_id),
+                    None => return TransactionStatus::TokenNotFound,
+                };
+                // The box is gone once opened, so it can't be opened or traded again
+                if let Err(status) = self.boxes.burn(&sender, &blind_box_id) {
+                    return status;
+                }
+                self.schrodingers.remove(&blind_box_id);
+                self.blind_boxes.remove(&blind_box_id);
 
-                    // Queue the message to sync the owner transfer info to pallet
-                    let data = Transfer {
-                        dest: sender.0,
-                        kitty_id: kitty_id.clone().encode(),
-                    };
+                // Queue the message to sync the owner transfer info to pallet
+                let data = Transfer {
+                    dest: sender.0,
+                    kitty_id: kitty_id.encode(),
+                };
 
-                    self.opend_boxes.push(blind_box_id.clone());
-                    context.mq().send(&data);
-                }
+                self.opend_boxes.push(blind_box_id);
+                context.mq().send(&data);
                 TransactionStatus::Ok
             }
         }
     }
 
     // Handles a direct query and responds to the query. It shouldn't modify the contract states.
-    fn handle_query(&mut self, _origin: Option<&chain::AccountId>, req: Request) -> Response {
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        contracts::snapshot_of(self)
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> anyhow::Result<()> {
+        *self = contracts::restore_from(snapshot)?;
+        Ok(())
+    }
+
+    fn handle_query(&mut self, origin: Option<&chain::AccountId>, req: Request) -> Response {
         let inner = || -> Result<Response, Error> {
             match req {
                 Request::ObserveBox => {
@@ -311,20 +239,10 @@ impl contracts::NativeContract for SubstrateKitties {
                     })
                 }
                 Request::ObserveOwnedBox => {
-                    let sender = AccountIdWrapper(_origin.unwrap().clone());
-                    let owned_boxes = self.owned_boxes.get(&sender);
-                    match owned_boxes {
-                        Some(_) => {
-                            return Ok(Response::ObserveOwnedBox {
-                                owned_box: owned_boxes.unwrap().clone(),
-                            })
-                        }
-                        None => {
-                            return Ok(Response::ObserveOwnedBox {
-                                owned_box: Vec::new(),
-                            })
-                        }
-                    };
+                    let sender = AccountIdWrapper(origin.ok_or(Error::NotAuthorized)?.clone());
+                    return Ok(Response::ObserveOwnedBox {
+                        owned_box: self.boxes.tokens_of(&sender),
+                    });
                 }
                 Request::ObserveLeftKitties => {
                     return Ok(Response::ObserveLeftKitties {
@@ -332,9 +250,14 @@ impl contracts::NativeContract for SubstrateKitties {
                     })
                 }
                 Request::OwnerOf { blind_box_id } => {
+                    let sender = AccountIdWrapper(origin.ok_or(Error::NotAuthorized)?.clone());
+                    let owner = self.boxes.owner_of(&blind_box_id).ok_or(Error::NotFound)?;
+                    if owner != &sender && self.boxes.approved(&blind_box_id) != Some(&sender) {
+                        return Err(Error::NotAuthorized);
+                    }
                     return Ok(Response::OwnerOf {
-                        owner: self.owner.get(&blind_box_id).unwrap().clone(),
-                    })
+                        owner: owner.clone(),
+                    });
                 }
             }
         };
@@ -1,17 +1,17 @@
 use super::{NativeContext, TransactionStatus};
 use crate::contracts::AccountIdWrapper;
 use crate::cryptography::aead;
-use crate::std::collections::BTreeMap;
-use crate::std::collections::HashMap;
+use crate::std::collections::{BTreeMap, BTreeSet};
 use crate::std::prelude::v1::*;
 use crate::std::vec::Vec;
 use anyhow::Result;
 use core::fmt;
 use phala_mq::MessageOrigin;
 use serde::{Deserialize, Serialize};
+use sp_core::hashing::blake2_256;
 
 use crate::contracts;
-use phala_types::messaging::{PushCommand, Web3AnalyticsCommand as Command};
+use phala_types::messaging::{PushCommand, Retention, Web3AnalyticsCommand as Command};
 
 use super::woothee;
 
@@ -22,6 +22,10 @@ const MINUTE_IN_SECONDS: u32 = 60;
 const HOUR_IN_SECONDS: u32 = 60 * MINUTE_IN_SECONDS;
 const DAY_IN_SECONDS: u32 = 24 * HOUR_IN_SECONDS;
 const WEEK_IN_SECONDS: u32 = 7 * DAY_IN_SECONDS;
+/// The weeks start on Monday. 1970-01-05 is the first Monday of the Unix time.
+const FIRST_MONDAY: u32 = 4 * DAY_IN_SECONDS;
+/// How far the page views can be ahead of the block time
+const MAX_CLOCK_SKEW: u32 = 5 * MINUTE_IN_SECONDS;
 
 const KEY: &[u8] =
     &hex_literal::hex!("290c3c5d812a4ba7ce33adf09598a462692a615beb6c80fdafb3f9e3bbef8bc6");
@@ -74,58 +78,217 @@ pub struct WeeklyDevice {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct WeeklyClient {
-    sid: Sid,
-    cids: Vec<String>,
-    timestamp: u32,
+pub struct DailyStat {
+    stats: Vec<HourlyPageViewStat>,
 }
 
+/// All the data kept for a site, in plain text
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct SiteClient {
+pub struct SiteExport {
     sid: Sid,
-    cids: Vec<String>,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct HourlyStat {
-    hourly_page_view_stats: Vec<HourlyPageViewStat>,
-    site_clients: Vec<SiteClient>,
-    weekly_clients: Vec<WeeklyClient>,
+    page_views: Vec<PageView>,
+    hourly_stats: Vec<HourlyPageViewStat>,
+    daily_stats: Vec<HourlyPageViewStat>,
     weekly_sites: Vec<WeeklySite>,
     weekly_devices: Vec<WeeklyDevice>,
-    total_stats: Vec<HourlyPageViewStat>,
+    total_stat: HourlyPageViewStat,
 }
 
-impl HourlyStat {
-    pub fn new() -> Self {
-        Self {
-            hourly_page_view_stats: Vec::<HourlyPageViewStat>::new(),
-            site_clients: Vec::<SiteClient>::new(),
-            weekly_clients: Vec::<WeeklyClient>::new(),
-            weekly_sites: Vec::<WeeklySite>::new(),
-            weekly_devices: Vec::<WeeklyDevice>::new(),
-            total_stats: Vec::<HourlyPageViewStat>::new(),
+/// The page views, the unique clients and the sum of their visit durations in a period
+#[derive(Debug, Clone, Default)]
+struct Counts {
+    pv_count: u32,
+    cid_count: u32,
+    duration: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        self.pv_count += other.pv_count;
+        self.cid_count += other.cid_count;
+        self.duration += other.duration;
+    }
+
+    fn avg_duration(&self) -> u64 {
+        if self.cid_count == 0 {
+            0
+        } else {
+            self.duration / self.cid_count as u64
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct DailyStat {
-    stats: Vec<HourlyPageViewStat>,
+/// Counts the page views by hour
+fn hourly_counts(page_views: &[PageView]) -> BTreeMap<Timestamp, Counts> {
+    let mut cid_timestamp_map = BTreeMap::<(Timestamp, &str), Vec<Timestamp>>::new();
+    for pv in page_views {
+        let ca = pv.created_at / HOUR_IN_SECONDS * HOUR_IN_SECONDS;
+        cid_timestamp_map
+            .entry((ca, pv.cid.as_str()))
+            .or_default()
+            .push(pv.created_at);
+    }
+
+    let mut counts = BTreeMap::<Timestamp, Counts>::new();
+    for ((ca, _), tss) in cid_timestamp_map {
+        let duration = if tss.len() <= 2 {
+            60
+        } else {
+            let sum: u32 = tss.windows(2).map(|w| w[1] - w[0]).sum();
+            sum / (tss.len() as u32 - 1)
+        };
+        let count = counts.entry(ca).or_default();
+        count.pv_count += tss.len() as u32;
+        count.cid_count += 1;
+        count.duration += duration as u64;
+    }
+    counts
 }
 
-impl DailyStat {
-    pub fn new() -> Self {
-        Self {
-            stats: Vec::<HourlyPageViewStat>::new(),
+fn start_of_day(ts: Timestamp) -> Timestamp {
+    ts / DAY_IN_SECONDS * DAY_IN_SECONDS
+}
+
+fn start_of_week(ts: Timestamp) -> Timestamp {
+    ts.saturating_sub(FIRST_MONDAY) / WEEK_IN_SECONDS * WEEK_IN_SECONDS + FIRST_MONDAY
+}
+
+/// A registered site, with its recent page views and the aggregates of the older ones
+struct Site {
+    owner: AccountIdWrapper,
+    api_key_hash: [u8; 32],
+    retention: Retention,
+    /// Whether the page views are ingested encrypted, and so the stats are returned encrypted
+    encrypted: bool,
+    /// The page views not rolled up yet, sorted by `created_at`
+    page_views: Vec<PageView>,
+    /// The ids of `page_views`
+    page_view_ids: BTreeSet<String>,
+    hourly: BTreeMap<Timestamp, Counts>,
+    daily: BTreeMap<Timestamp, Counts>,
+    weekly_paths: BTreeMap<(Timestamp, String), u32>,
+    weekly_devices: BTreeMap<(Timestamp, String), u32>,
+    /// The counts of all the page views rolled up
+    total: Counts,
+    /// The page views before it have been rolled up, so the later ones are dropped
+    rolled_up: Timestamp,
+}
+
+impl Site {
+    fn new(owner: AccountIdWrapper, api_key_hash: [u8; 32]) -> Self {
+        Site {
+            owner,
+            api_key_hash,
+            retention: Retention::default(),
+            encrypted: false,
+            page_views: Vec::new(),
+            page_view_ids: BTreeSet::new(),
+            hourly: BTreeMap::new(),
+            daily: BTreeMap::new(),
+            weekly_paths: BTreeMap::new(),
+            weekly_devices: BTreeMap::new(),
+            total: Counts::default(),
+            rolled_up: 0,
         }
     }
+
+    /// Adds a page view, unless it's already added or rolled up
+    fn insert(&mut self, pv: PageView, path: String, device: String) {
+        // The page views before the cutoff are counted in the rolled up stats
+        if pv.created_at < self.rolled_up || !self.page_view_ids.insert(pv.id.clone()) {
+            return;
+        }
+        let week = start_of_week(pv.created_at);
+        *self.weekly_paths.entry((week, path)).or_default() += 1;
+        *self.weekly_devices.entry((week, device)).or_default() += 1;
+
+        let pos = self
+            .page_views
+            .partition_point(|x| x.created_at <= pv.created_at);
+        self.page_views.insert(pos, pv);
+    }
+
+    /// Drops the page views before `timestamp` without rolling them up
+    fn clear(&mut self, timestamp: Timestamp) {
+        let pos = self.page_views.partition_point(|x| x.created_at < timestamp);
+        for pv in self.page_views.drain(..pos) {
+            self.page_view_ids.remove(&pv.id);
+        }
+    }
+
+    /// Rolls up the page views expired at `now` into the hourly stats, and the hourly stats into
+    /// the daily ones, then drops the expired stats
+    fn roll_up(&mut self, now: Timestamp) {
+        let cutoff =
+            now.saturating_sub(self.retention.page_view_hours.saturating_mul(HOUR_IN_SECONDS));
+        // Never goes back, or the hours rolled up would be counted again
+        let cutoff = self.rolled_up.max(cutoff / HOUR_IN_SECONDS * HOUR_IN_SECONDS);
+        self.rolled_up = cutoff;
+        let pos = self.page_views.partition_point(|x| x.created_at < cutoff);
+        let expired: Vec<_> = self.page_views.drain(..pos).collect();
+        for pv in &expired {
+            self.page_view_ids.remove(&pv.id);
+        }
+        for (hour, counts) in hourly_counts(&expired) {
+            self.hourly.entry(hour).or_default().add(&counts);
+            self.daily.entry(start_of_day(hour)).or_default().add(&counts);
+            self.total.add(&counts);
+        }
+
+        let retention = self.retention;
+        let hourly_cutoff =
+            now.saturating_sub(retention.hourly_stat_days.saturating_mul(DAY_IN_SECONDS));
+        self.hourly = self.hourly.split_off(&hourly_cutoff);
+        let daily_cutoff =
+            now.saturating_sub(retention.daily_stat_days.saturating_mul(DAY_IN_SECONDS));
+        self.daily = self.daily.split_off(&start_of_day(daily_cutoff));
+        let week = start_of_week(daily_cutoff);
+        self.weekly_paths = self.weekly_paths.split_off(&(week, String::new()));
+        self.weekly_devices = self.weekly_devices.split_off(&(week, String::new()));
+    }
+
+    /// The rolled up hourly stats with the ones of the recent page views
+    fn hourly_stats(&self, start: Timestamp, end: Timestamp) -> BTreeMap<Timestamp, Counts> {
+        let mut stats: BTreeMap<_, _> = self
+            .hourly
+            .range(start..end)
+            .map(|(hour, counts)| (*hour, counts.clone()))
+            .collect();
+        for (hour, counts) in hourly_counts(&self.page_views).range(start..end) {
+            stats.entry(*hour).or_default().add(counts);
+        }
+        stats
+    }
+
+    fn daily_stats(&self, start: Timestamp, end: Timestamp) -> BTreeMap<Timestamp, Counts> {
+        let mut stats: BTreeMap<_, _> = self
+            .daily
+            .range(start..end)
+            .map(|(day, counts)| (*day, counts.clone()))
+            .collect();
+        for (hour, counts) in hourly_counts(&self.page_views) {
+            let day = start_of_day(hour);
+            if start <= day && day < end {
+                stats.entry(day).or_default().add(&counts);
+            }
+        }
+        stats
+    }
+
+    fn total_stat(&self) -> Counts {
+        let mut total = self.total.clone();
+        for counts in hourly_counts(&self.page_views).values() {
+            total.add(counts);
+        }
+        total
+    }
 }
 
 // contract
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Error {
     NotAuthorized,
+    SiteNotFound,
     Other(String),
 }
 
@@ -133,47 +296,79 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::NotAuthorized => write!(f, "not authorized"),
+            Error::SiteNotFound => write!(f, "site not found"),
             Error::Other(e) => write!(f, "{}", e),
         }
     }
 }
 
+/// The stats of a site are readable by its owner, or with its API key in `api_key`.
+///
+/// The page views are ingested by queries rather than commands, so that they are never published
+/// on chain. So they are only kept by the worker which receives them: `SetPageView` and
+/// `ClearPageView` change the state of this worker only, and the stats differ between workers.
+/// The sites themselves are registered by commands, and are the same on all the workers.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
+    /// Ingests the page views of a site, authenticated by its API key. The page views ahead of
+    /// the block time are moved back to it.
     SetPageView {
+        sid: Sid,
+        api_key: String,
         page_views: Vec<PageView>,
         encrypted: bool,
     },
+    /// Drops the page views before `timestamp` without rolling them up
     ClearPageView {
+        sid: Sid,
+        api_key: Option<String>,
         timestamp: Timestamp,
     },
     GetOnlineUsers {
+        sid: Sid,
+        api_key: Option<String>,
         start: Timestamp,
         end: Timestamp,
     },
     GetHourlyStats {
+        sid: Sid,
+        api_key: Option<String>,
         start: Timestamp,
         end: Timestamp,
-        start_of_week: Timestamp,
     },
     GetDailyStats {
-        daily_stat: DailyStat,
+        sid: Sid,
+        api_key: Option<String>,
+        start: Timestamp,
+        end: Timestamp,
     },
     GetWeeklySites {
-        weekly_sites_in_db: Vec<WeeklySite>,
-        weekly_sites_new: Vec<WeeklySite>,
+        sid: Sid,
+        api_key: Option<String>,
+        start: Timestamp,
+        end: Timestamp,
     },
     GetWeeklyDevices {
-        weekly_devices_in_db: Vec<WeeklyDevice>,
-        weekly_devices_new: Vec<WeeklyDevice>,
+        sid: Sid,
+        api_key: Option<String>,
+        start: Timestamp,
+        end: Timestamp,
     },
     GetTotalStat {
-        total_stat: HourlyPageViewStat,
-        count: String,
+        sid: Sid,
+        api_key: Option<String>,
     },
     GetConfiguration {
         account: AccountIdWrapper,
     },
+    /// The API key and the retention of a site. Only for the owner.
+    GetSite {
+        sid: Sid,
+    },
+    /// All the data of a site. Only for the owner.
+    Export {
+        sid: Sid,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -189,7 +384,7 @@ pub enum Response {
         encrypted: bool,
     },
     GetHourlyStats {
-        hourly_stat: HourlyStat,
+        hourly_stats: Vec<HourlyPageViewStat>,
         encrypted: bool,
     },
     GetDailyStats {
@@ -211,23 +406,27 @@ pub enum Response {
     GetConfiguration {
         skip_stat: bool,
     },
+    GetSite {
+        owner: AccountIdWrapper,
+        /// The blake2_256 hash of the API key, in hex
+        api_key_hash: String,
+        page_view_hours: u32,
+        hourly_stat_days: u32,
+        daily_stat_days: u32,
+    },
+    Export {
+        site: SiteExport,
+    },
 
     Error(#[serde(with = "super::serde_anyhow")] anyhow::Error),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
 pub struct Web3Analytics {
-    encrypted: bool,
-    page_views: Vec<PageView>,
-    online_users: Vec<OnlineUser>,
-    hourly_stat: HourlyStat,
-    daily_stat: DailyStat,
-    weekly_sites: Vec<WeeklySite>,
-    weekly_devices: Vec<WeeklyDevice>,
-    total_stat: HourlyPageViewStat,
+    sites: BTreeMap<Sid, Site>,
+    /// The time of the latest block, which drives the roll-ups
+    block_time: Timestamp,
 
     key: Vec<u8>,
-    #[serde(skip)]
     parser: woothee::parser::Parser,
 
     no_tracking: BTreeMap<AccountIdWrapper, bool>,
@@ -236,14 +435,8 @@ pub struct Web3Analytics {
 impl Web3Analytics {
     pub fn new() -> Self {
         Self {
-            encrypted: false,
-            page_views: Vec::<PageView>::new(),
-            online_users: Vec::<OnlineUser>::new(),
-            hourly_stat: HourlyStat::new(),
-            daily_stat: DailyStat::new(),
-            weekly_sites: Vec::<WeeklySite>::new(),
-            weekly_devices: Vec::<WeeklyDevice>::new(),
-            total_stat: HourlyPageViewStat::default(),
+            sites: BTreeMap::new(),
+            block_time: 0,
             key: KEY.to_owned(),
 
             parser: woothee::parser::Parser::new(),
@@ -252,643 +445,223 @@ impl Web3Analytics {
         }
     }
 
-    pub fn update_online_users(&mut self, start: Timestamp, end: Timestamp) {
-        let mut sids = Vec::<Sid>::new();
-        let mut cid_map = HashMap::<(Sid, Timestamp), Vec<String>>::new();
-        let mut ip_map = HashMap::<(Sid, Timestamp), Vec<String>>::new();
-
-        for pv in self.page_views.clone() {
-            if pv.created_at < start {
-                continue;
-            }
-
-            if pv.created_at > end {
-                break;
-            }
-
-            if !sids.contains(&pv.sid) {
-                sids.push(pv.sid.clone());
-            }
-
-            let cid = pv.cid;
-            let ca = pv.created_at / MINUTE_IN_SECONDS * MINUTE_IN_SECONDS;
-            let mut cids = Vec::<String>::new();
-            if cid_map.contains_key(&(pv.sid.clone(), ca)) {
-                cids = cid_map.get(&(pv.sid.clone(), ca)).unwrap().clone();
-                if !cids.contains(&cid) {
-                    cids.push(cid);
-                }
-            } else {
-                cids.push(cid);
-            }
-            cid_map.insert((pv.sid.clone(), ca), cids);
-
-            let ip = if self.encrypted {
-                self.decrypt(pv.ip)
-            } else {
-                pv.ip
-            };
-            let mut ips = Vec::<String>::new();
-            if ip_map.contains_key(&(pv.sid.clone(), ca)) {
-                ips = ip_map.get(&(pv.sid.clone(), ca)).unwrap().clone();
-                if !ips.contains(&ip) {
-                    ips.push(ip);
-                }
-            } else {
-                ips.push(ip);
-            }
-            ip_map.insert((pv.sid.clone(), ca), ips);
-        }
-
-        self.online_users.clear();
-
-        let mut index = start.clone();
-        while index < end {
-            for sid in sids.clone() {
-                if !cid_map.contains_key(&(sid.clone(), index)) {
-                    continue;
-                }
-
-                let cids = cid_map.get(&(sid.clone(), index)).unwrap();
-                let ips = ip_map.get(&(sid.clone(), index)).unwrap();
-                let cid_count = if self.encrypted {
-                    self.encrypt(cids.len().to_string())
-                } else {
-                    cids.len().to_string()
-                };
-                let ip_count = if self.encrypted {
-                    self.encrypt(ips.len().to_string())
-                } else {
-                    ips.len().to_string()
-                };
-                let ou = OnlineUser {
-                    sid,
-                    cid_count,
-                    ip_count,
-                    timestamp: index,
-                };
-                self.online_users.push(ou);
-            }
-            index += MINUTE_IN_SECONDS;
+    /// Returns the site if `origin` is its owner or `api_key` is its API key
+    fn authorize(
+        &self,
+        sid: &str,
+        origin: Option<&chain::AccountId>,
+        api_key: Option<&str>,
+    ) -> Result<&Site> {
+        let site = self
+            .sites
+            .get(sid)
+            .ok_or_else(|| anyhow::Error::msg(Error::SiteNotFound))?;
+        let by_owner = origin == Some(&site.owner.0);
+        let by_key = api_key.map(|key| blake2_256(key.as_bytes())) == Some(site.api_key_hash);
+        if !by_owner && !by_key {
+            return Err(anyhow::Error::msg(Error::NotAuthorized));
         }
+        Ok(site)
     }
 
-    pub fn update_hourly_stats(
-        &mut self,
-        start_s: Timestamp,
-        end_s: Timestamp,
-        start_of_week: Timestamp,
-    ) {
-        let mut sids = Vec::<Sid>::new();
-        let mut sid_map = HashMap::<Sid, Vec<String>>::new();
-        let mut cid_weekly_map = HashMap::<(Sid, Timestamp), Vec<String>>::new();
-        let mut cid_map = HashMap::<(Sid, Timestamp), Vec<String>>::new();
-        let mut pv_count_map = HashMap::<(Sid, Timestamp), u32>::new();
-        let mut path_map = HashMap::<Sid, Vec<String>>::new();
-        let mut path_weekly_map = HashMap::<(Sid, String, Timestamp), u32>::new();
-        let mut device_map = HashMap::<Sid, Vec<String>>::new();
-        let mut device_weekly_map = HashMap::<(Sid, String, Timestamp), u32>::new();
-
-        let mut cid_timestamp_map = HashMap::<(String, Timestamp), Vec<Timestamp>>::new();
-
-        let start = start_s / HOUR_IN_SECONDS * HOUR_IN_SECONDS;
-        let end = end_s / HOUR_IN_SECONDS * HOUR_IN_SECONDS;
-
-        for pv in self.page_views.clone() {
-            if pv.created_at <= start {
+    fn ingest(&mut self, sid: &str, page_views: Vec<PageView>, encrypted: bool) -> Result<u32> {
+        if self.block_time == 0 {
+            return Err(anyhow::Error::msg(Error::Other("Chain not synced".into())));
+        }
+        let latest = self.block_time.saturating_add(MAX_CLOCK_SKEW);
+        let mut parsed = Vec::new();
+        for mut pv in page_views {
+            pv.created_at = pv.created_at.min(latest);
+            if pv.sid != sid {
+                return Err(anyhow::Error::msg(Error::Other(
+                    "Page view of another site".into(),
+                )));
+            }
+            if pv.uid.len() == 64
+                && self
+                    .no_tracking
+                    .contains_key(&AccountIdWrapper::from_hex(&pv.uid)?)
+            {
                 continue;
             }
-
-            if pv.created_at > end {
-                break;
-            }
-
-            if !sids.contains(&pv.sid) {
-                sids.push(pv.sid.clone());
-            }
-
-            let cid = pv.cid;
-            let ca = pv.created_at / HOUR_IN_SECONDS * HOUR_IN_SECONDS;
-            let mut cids = Vec::<String>::new();
-            if cid_map.contains_key(&(pv.sid.clone(), ca)) {
-                cids = cid_map.get(&(pv.sid.clone(), ca)).unwrap().clone();
-                if !cids.contains(&cid) {
-                    cids.push(cid.clone());
-                }
-            } else {
-                cids.push(cid.clone());
-            }
-            cid_map.insert((pv.sid.clone(), ca), cids);
-
-            let mut tss = Vec::<Timestamp>::new();
-            if cid_timestamp_map.contains_key(&(cid.clone(), ca)) {
-                tss = cid_timestamp_map.get(&(cid.clone(), ca)).unwrap().clone();
-                tss.push(pv.created_at);
-            } else {
-                tss.push(pv.created_at);
-            }
-            cid_timestamp_map.insert((cid.clone(), ca), tss);
-
-            if pv_count_map.contains_key(&(pv.sid.clone(), ca)) {
-                let pc = pv_count_map.get(&(pv.sid.clone(), ca)).unwrap().clone();
-                pv_count_map.insert((pv.sid.clone(), ca), pc + 1);
-            } else {
-                pv_count_map.insert((pv.sid.clone(), ca), 1);
-            }
-
-            let mut cids = Vec::<String>::new();
-            if sid_map.contains_key(&pv.sid) {
-                cids = sid_map.get(&pv.sid).unwrap().clone();
-                if !cids.contains(&cid) {
-                    cids.push(cid.clone());
-                }
-            } else {
-                cids.push(cid.clone());
-            }
-            sid_map.insert(pv.sid.clone(), cids);
-
-            let path = if self.encrypted {
-                self.decrypt(pv.path)
-            } else {
-                pv.path
-            };
-            let mut paths = Vec::<String>::new();
-            if path_map.contains_key(&pv.sid) {
-                paths = path_map.get(&pv.sid).unwrap().clone();
-                if !paths.contains(&path) {
-                    paths.push(path.clone());
-                }
-            } else {
-                paths.push(path.clone());
+            if encrypted {
+                pv.ip = self.decrypt(&pv.ip)?;
+                pv.path = self.decrypt(&pv.path)?;
+                pv.user_agent = self.decrypt(&pv.user_agent)?;
             }
-            path_map.insert(pv.sid.clone(), paths);
-
-            let user_agent = if self.encrypted {
-                self.decrypt(pv.user_agent)
-            } else {
-                pv.user_agent
-            };
-            let device = match self.parser.parse(&user_agent) {
+            let device = match self.parser.parse(&pv.user_agent) {
                 Some(wr) => wr.name.to_string(),
                 None => "Unknown".to_string(),
             };
-            let mut devices = Vec::<String>::new();
-            if device_map.contains_key(&pv.sid) {
-                devices = device_map.get(&pv.sid).unwrap().clone();
-                if !devices.contains(&device) {
-                    devices.push(device.clone());
-                }
-            } else {
-                devices.push(device.clone());
-            }
-            device_map.insert(pv.sid.clone(), devices);
-
-            let diff = (pv.created_at - start_of_week.clone()) / WEEK_IN_SECONDS;
-            let date_of_week = start_of_week.clone() + diff * WEEK_IN_SECONDS;
-            let mut cids = Vec::<String>::new();
-            if cid_weekly_map.contains_key(&(pv.sid.clone(), date_of_week)) {
-                cids = cid_weekly_map
-                    .get(&(pv.sid.clone(), date_of_week))
-                    .unwrap()
-                    .clone();
-                if !cids.contains(&cid) {
-                    cids.push(cid.clone());
-                }
-            } else {
-                cids.push(cid.clone());
-            }
-            cid_weekly_map.insert((pv.sid.clone(), date_of_week), cids);
-
-            if path_weekly_map.contains_key(&(pv.sid.clone(), path.clone(), date_of_week)) {
-                let count = path_weekly_map
-                    .get(&(pv.sid.clone(), path.clone(), date_of_week))
-                    .unwrap()
-                    .clone();
-                path_weekly_map.insert((pv.sid.clone(), path.clone(), date_of_week), count + 1);
-            } else {
-                path_weekly_map.insert((pv.sid.clone(), path.clone(), date_of_week), 1);
-            }
-
-            if device_weekly_map.contains_key(&(pv.sid.clone(), device.clone(), date_of_week)) {
-                let count = device_weekly_map
-                    .get(&(pv.sid.clone(), device.clone(), date_of_week))
-                    .unwrap()
-                    .clone();
-                device_weekly_map.insert((pv.sid.clone(), device.clone(), date_of_week), count + 1);
-            } else {
-                device_weekly_map.insert((pv.sid.clone(), device.clone(), date_of_week), 1);
-            }
-        }
-
-        self.hourly_stat = HourlyStat::new();
-
-        let mut hpv = Vec::<HourlyPageViewStat>::new();
-        let mut index = start;
-        while index < end {
-            for sid in sids.clone() {
-                if !cid_map.contains_key(&(sid.clone(), index)) {
-                    continue;
-                }
-
-                let cids = cid_map.get(&(sid.clone(), index)).unwrap().clone();
-                let mut total_duration: u32 = 0;
-                for cid in cids.clone() {
-                    let tss = cid_timestamp_map.get(&(cid.clone(), index)).unwrap();
-                    if tss.len() <= 2 {
-                        total_duration += 60;
-                    } else {
-                        let mut sum: u32 = 0;
-                        for i in 1..tss.len() {
-                            sum += tss[i] - tss[i - 1];
-                        }
-                        total_duration += sum / (tss.len() as u32 - 1);
-                    }
-                }
-
-                let avg_duration_str = (total_duration / (cids.len() as u32)).to_string();
-
-                let pc = pv_count_map.get(&(sid.clone(), index)).unwrap();
-                let cid_count = if self.encrypted {
-                    self.encrypt(cids.len().to_string())
-                } else {
-                    cids.len().to_string()
-                };
-                let pv_count = if self.encrypted {
-                    self.encrypt((*pc).to_string())
-                } else {
-                    (*pc).to_string()
-                };
-                let avg_duration = if self.encrypted {
-                    self.encrypt(avg_duration_str)
-                } else {
-                    avg_duration_str
-                };
-                let hs = HourlyPageViewStat {
-                    sid,
-                    cid_count,
-                    pv_count,
-                    avg_duration,
-                    timestamp: index,
-                };
-                hpv.push(hs);
-            }
-            index += HOUR_IN_SECONDS;
+            parsed.push((pv, device));
         }
-        self.hourly_stat.hourly_page_view_stats = hpv;
-
-        let mut site_clients = Vec::<SiteClient>::new();
-        for sid in sids.clone() {
-            let cids = sid_map.get(&sid).unwrap().clone();
-            let sc = SiteClient { sid, cids };
 
-            site_clients.push(sc);
+        let site = self.sites.get_mut(sid).expect("Site is authorized; qed.");
+        site.encrypted = encrypted;
+        for (pv, device) in parsed {
+            let path = pv.path.clone();
+            site.insert(pv, path, device);
         }
-        self.hourly_stat.site_clients = site_clients;
-
-        let mut wcs = Vec::<WeeklyClient>::new();
-        let mut index = start_of_week.clone();
-        while index < end {
-            for sid in sids.clone() {
-                if !cid_weekly_map.contains_key(&(sid.clone(), index)) {
-                    continue;
-                }
+        site.roll_up(self.block_time);
+        Ok(site.page_views.len() as u32)
+    }
 
-                let cids = cid_weekly_map.get(&(sid.clone(), index)).unwrap().clone();
-                let wc = WeeklyClient {
-                    sid,
-                    cids,
-                    timestamp: index,
-                };
-                wcs.push(wc);
+    fn online_users(
+        &self,
+        site: &Site,
+        sid: &str,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Vec<OnlineUser> {
+        let mut cid_map = BTreeMap::<Timestamp, BTreeSet<&str>>::new();
+        let mut ip_map = BTreeMap::<Timestamp, BTreeSet<&str>>::new();
+        for pv in &site.page_views {
+            if pv.created_at < start || pv.created_at > end {
+                continue;
             }
-            index += WEEK_IN_SECONDS;
+            let ca = pv.created_at / MINUTE_IN_SECONDS * MINUTE_IN_SECONDS;
+            cid_map.entry(ca).or_default().insert(&pv.cid);
+            ip_map.entry(ca).or_default().insert(&pv.ip);
         }
-        self.hourly_stat.weekly_clients = wcs;
-
-        let mut wss = Vec::<WeeklySite>::new();
-        index = start_of_week.clone();
-        while index < end {
-            for sid in sids.clone() {
-                let paths = path_map.get(&sid).unwrap().clone();
-                for p in paths {
-                    if !path_weekly_map.contains_key(&(sid.clone(), p.clone(), index)) {
-                        continue;
-                    }
 
-                    let count = path_weekly_map
-                        .get(&(sid.clone(), p.clone(), index))
-                        .unwrap();
-                    let path = if self.encrypted {
-                        self.encrypt(p.clone())
-                    } else {
-                        p.clone()
-                    };
-                    let count = if self.encrypted {
-                        self.encrypt((*count).to_string())
-                    } else {
-                        (*count).to_string()
-                    };
-                    let ws = WeeklySite {
-                        sid: sid.clone(),
-                        path,
-                        count,
-                        timestamp: index,
-                    };
-                    wss.push(ws);
-                }
-            }
-            index += WEEK_IN_SECONDS;
-        }
-        self.hourly_stat.weekly_sites = wss;
-
-        let mut wds = Vec::<WeeklyDevice>::new();
-        index = start_of_week.clone();
-        while index < end {
-            for sid in sids.clone() {
-                let devices = device_map.get(&sid).unwrap().clone();
-                for dev in devices {
-                    if !device_weekly_map.contains_key(&(sid.clone(), dev.clone(), index)) {
-                        continue;
-                    }
+        cid_map
+            .iter()
+            .filter(|(minute, _)| **minute < end)
+            .map(|(minute, cids)| OnlineUser {
+                sid: sid.to_string(),
+                cid_count: self.output(site, cids.len().to_string()),
+                ip_count: self.output(site, ip_map[minute].len().to_string()),
+                timestamp: *minute,
+            })
+            .collect()
+    }
 
-                    let count = device_weekly_map
-                        .get(&(sid.clone(), dev.clone(), index))
-                        .unwrap();
-                    let device = if self.encrypted {
-                        self.encrypt(dev.clone())
-                    } else {
-                        dev.clone()
-                    };
-                    let count = if self.encrypted {
-                        self.encrypt((*count).to_string())
-                    } else {
-                        (*count).to_string()
-                    };
-                    let wd = WeeklyDevice {
-                        sid: sid.clone(),
-                        device,
-                        count,
-                        timestamp: index,
-                    };
-                    wds.push(wd);
-                }
-            }
-            index += WEEK_IN_SECONDS;
+    fn stat(
+        &self,
+        site: &Site,
+        sid: &str,
+        timestamp: Timestamp,
+        counts: &Counts,
+    ) -> HourlyPageViewStat {
+        HourlyPageViewStat {
+            sid: sid.to_string(),
+            pv_count: self.output(site, counts.pv_count.to_string()),
+            cid_count: self.output(site, counts.cid_count.to_string()),
+            avg_duration: self.output(site, counts.avg_duration().to_string()),
+            timestamp,
         }
-        self.hourly_stat.weekly_devices = wds;
     }
 
-    fn update_daily_stats(&mut self, daily_stat: DailyStat) {
-        let mut sids = Vec::<Sid>::new();
-        let mut daily_map = HashMap::<(Sid, Timestamp), (u32, u32, u32)>::new();
-        let mut first_date: Timestamp = 0;
-        let mut last_date: Timestamp = 0;
-        for hourly_stat in daily_stat.stats.clone() {
-            let ts = hourly_stat.timestamp;
-            let sid = hourly_stat.sid.clone();
-
-            if first_date == 0 {
-                first_date = ts;
-            }
-            last_date = ts;
+    fn weekly_sites(
+        &self,
+        site: &Site,
+        sid: &str,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Vec<WeeklySite> {
+        site.weekly_paths
+            .range((start, String::new())..(end, String::new()))
+            .map(|((week, path), count)| WeeklySite {
+                sid: sid.to_string(),
+                path: self.output(site, path.clone()),
+                count: self.output(site, count.to_string()),
+                timestamp: *week,
+            })
+            .collect()
+    }
 
-            if !sids.contains(&sid) {
-                sids.push(sid.clone());
-            }
+    fn weekly_devices(
+        &self,
+        site: &Site,
+        sid: &str,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Vec<WeeklyDevice> {
+        site.weekly_devices
+            .range((start, String::new())..(end, String::new()))
+            .map(|((week, device), count)| WeeklyDevice {
+                sid: sid.to_string(),
+                device: self.output(site, device.clone()),
+                count: self.output(site, count.to_string()),
+                timestamp: *week,
+            })
+            .collect()
+    }
 
-            let pv_count = if self.encrypted {
-                self.decrypt(hourly_stat.pv_count)
-            } else {
-                hourly_stat.pv_count
-            };
-            let cid_count = if self.encrypted {
-                self.decrypt(hourly_stat.cid_count)
-            } else {
-                hourly_stat.cid_count
-            };
-            let avg_duration = if self.encrypted {
-                self.decrypt(hourly_stat.avg_duration)
-            } else {
-                hourly_stat.avg_duration
-            };
-            let mut hourly_stat_pv_count = pv_count.parse::<u32>().unwrap();
-            let mut hourly_stat_cid_count = cid_count.parse::<u32>().unwrap();
-            let mut hourly_stat_avg_duration = avg_duration.parse::<u32>().unwrap();
-            if daily_map.contains_key(&(sid.clone(), ts)) {
-                let (pv_count0, cid_count0, avg_duration0) =
-                    daily_map.get(&(sid.clone(), ts)).unwrap().clone();
-                hourly_stat_pv_count += pv_count0;
-                hourly_stat_cid_count += cid_count0;
-                hourly_stat_avg_duration += avg_duration0;
-            }
-            daily_map.insert(
-                (sid.clone(), ts),
-                (
-                    hourly_stat_pv_count,
-                    hourly_stat_cid_count,
-                    hourly_stat_avg_duration,
-                ),
-            );
+    /// Encrypts the value if the site ingests encrypted page views
+    fn output(&self, site: &Site, value: String) -> String {
+        if site.encrypted {
+            self.encrypt(value)
+        } else {
+            value
         }
+    }
 
-        if first_date == 0 {
-            return;
-        }
+    fn encrypt(&self, data: String) -> String {
+        let mut msg = data.as_bytes().to_vec();
+        let iv = aead::generate_iv();
+        aead::encrypt(&iv, &self.key, &mut msg);
 
-        self.daily_stat = DailyStat::new();
-
-        let mut dss = Vec::<HourlyPageViewStat>::new();
-        while first_date <= last_date {
-            for sid in sids.clone() {
-                if daily_map.contains_key(&(sid.clone(), first_date.clone())) {
-                    let (pv_count, cid_count, avg_duration) = daily_map
-                        .get(&(sid.clone(), first_date.clone()))
-                        .unwrap()
-                        .clone();
-                    let ds = HourlyPageViewStat {
-                        sid,
-                        pv_count: if self.encrypted {
-                            self.encrypt(pv_count.to_string())
-                        } else {
-                            pv_count.to_string()
-                        },
-                        cid_count: if self.encrypted {
-                            self.encrypt(cid_count.to_string())
-                        } else {
-                            cid_count.to_string()
-                        },
-                        avg_duration: if self.encrypted {
-                            self.encrypt(avg_duration.to_string())
-                        } else {
-                            avg_duration.to_string()
-                        },
-                        timestamp: first_date.clone(),
-                    };
+        format!("{:}|{:}", base64::encode(&iv), base64::encode(&msg))
+    }
 
-                    dss.push(ds);
-                }
-            }
-            first_date += DAY_IN_SECONDS;
+    fn decrypt(&self, data: &str) -> Result<String> {
+        let bad_cipher = || anyhow::Error::msg(Error::Other("Bad cipher".into()));
+        let v: Vec<&str> = data.split("|").collect();
+        if v.len() != 2 {
+            return Err(bad_cipher());
+        }
+        let iv = base64::decode(v[0]).map_err(|_| bad_cipher())?;
+        let mut cipher_data = base64::decode(v[1]).map_err(|_| bad_cipher())?;
+        if iv.len() != aead::IV_BYTES {
+            return Err(bad_cipher());
         }
 
-        self.daily_stat.stats = dss;
+        let plain = aead::try_decrypt(&iv, &*self.key, &mut cipher_data).ok_or_else(bad_cipher)?;
+        String::from_utf8(plain.to_vec()).map_err(|_| bad_cipher())
     }
 
-    fn update_weekly_sites(
-        &mut self,
-        weekly_sites_in_db: Vec<WeeklySite>,
-        weekly_sites_new: Vec<WeeklySite>,
-    ) {
-        self.weekly_sites.clear();
-
-        for ws in weekly_sites_new {
-            let path = if self.encrypted {
-                self.decrypt(ws.path.clone())
-            } else {
-                ws.path.clone()
-            };
-            let mut matched = false;
-            for ws_db in weekly_sites_in_db.clone() {
-                let path_db = if self.encrypted {
-                    self.decrypt(ws_db.path.clone())
-                } else {
-                    ws_db.path.clone()
-                };
-                if ws.sid == ws_db.sid && ws.timestamp == ws_db.timestamp && path == path_db {
-                    matched = true;
-                    let count = if self.encrypted {
-                        self.decrypt(ws.count.clone()).parse::<u32>().unwrap()
-                    } else {
-                        ws.count.clone().parse::<u32>().unwrap()
-                    };
-                    let count_db = if self.encrypted {
-                        self.decrypt(ws_db.count).parse::<u32>().unwrap()
-                    } else {
-                        ws_db.count.parse::<u32>().unwrap()
-                    };
-                    let total = if self.encrypted {
-                        self.encrypt((count + count_db).to_string())
-                    } else {
-                        (count + count_db).to_string()
-                    };
-                    let w = WeeklySite {
-                        sid: ws.sid.clone(),
-                        count: total,
-                        path: ws.path.clone(),
-                        timestamp: ws.timestamp,
-                    };
-                    self.weekly_sites.push(w);
+    fn handle(&mut self, o: AccountIdWrapper, command: Command) -> TransactionStatus {
+        match command {
+            Command::SetConfiguration { skip_stat } => {
+                log::info!("SetConfiguration: [{}] -> {}", o.to_string(), skip_stat);
 
-                    break;
+                if skip_stat {
+                    self.no_tracking.insert(o, skip_stat);
+                } else {
+                    self.no_tracking.remove(&o);
                 }
             }
-
-            if !matched {
-                self.weekly_sites.push(ws);
+            Command::RegisterSite { sid, api_key_hash } => {
+                if self.sites.contains_key(&sid) {
+                    return TransactionStatus::SiteExists;
+                }
+                log::info!("RegisterSite: [{}] by [{}]", sid, o.to_string());
+                self.sites.insert(sid, Site::new(o, api_key_hash));
             }
-        }
-    }
-
-    fn update_weekly_devices(
-        &mut self,
-        weekly_devices_in_db: Vec<WeeklyDevice>,
-        weekly_devices_new: Vec<WeeklyDevice>,
-    ) {
-        self.weekly_devices.clear();
-
-        for wd in weekly_devices_new {
-            let device = if self.encrypted {
-                self.decrypt(wd.device.clone())
-            } else {
-                wd.device.clone()
-            };
-            let mut matched = false;
-            for wd_db in weekly_devices_in_db.clone() {
-                let device_db = if self.encrypted {
-                    self.decrypt(wd_db.device.clone())
-                } else {
-                    wd_db.device.clone()
+            Command::RotateApiKey { sid, api_key_hash } => {
+                let site = match self.sites.get_mut(&sid) {
+                    Some(site) => site,
+                    None => return TransactionStatus::SiteNotFound,
                 };
-                if wd.sid == wd_db.sid && wd.timestamp == wd_db.timestamp && device == device_db {
-                    matched = true;
-                    let count = if self.encrypted {
-                        self.decrypt(wd.count.clone()).parse::<u32>().unwrap()
-                    } else {
-                        wd.count.clone().parse::<u32>().unwrap()
-                    };
-                    let count_db = if self.encrypted {
-                        self.decrypt(wd_db.count).parse::<u32>().unwrap()
-                    } else {
-                        wd_db.count.parse::<u32>().unwrap()
-                    };
-                    let total = if self.encrypted {
-                        self.encrypt((count + count_db).to_string())
-                    } else {
-                        (count + count_db).to_string()
-                    };
-                    let w = WeeklyDevice {
-                        sid: wd.sid.clone(),
-                        count: total,
-                        device: wd.device.clone(),
-                        timestamp: wd.timestamp,
-                    };
-                    self.weekly_devices.push(w);
-
-                    break;
+                if site.owner != o {
+                    return TransactionStatus::NotSiteOwner;
                 }
+                site.api_key_hash = api_key_hash;
             }
-
-            if !matched {
-                self.weekly_devices.push(wd);
+            Command::SetRetention { sid, retention } => {
+                let site = match self.sites.get_mut(&sid) {
+                    Some(site) => site,
+                    None => return TransactionStatus::SiteNotFound,
+                };
+                if site.owner != o {
+                    return TransactionStatus::NotSiteOwner;
+                }
+                site.retention = retention;
+                site.roll_up(self.block_time);
             }
         }
-    }
-
-    fn update_total_stat(&mut self, total_stat: HourlyPageViewStat, count_str: String) {
-        self.total_stat = HourlyPageViewStat::default();
-
-        if !self.encrypted {
-            return;
-        }
-
-        let mut count = self.decrypt(total_stat.pv_count).parse::<u32>().unwrap();
-        if !count_str.is_empty() {
-            count += self.decrypt(count_str).parse::<u32>().unwrap();
-        }
-
-        let avg_duration = self
-            .decrypt(total_stat.avg_duration)
-            .parse::<u32>()
-            .unwrap()
-            / 2;
-
-        self.total_stat = HourlyPageViewStat {
-            sid: total_stat.sid,
-            cid_count: total_stat.cid_count,
-            pv_count: self.encrypt(count.to_string()),
-            avg_duration: self.encrypt(avg_duration.to_string()),
-            timestamp: total_stat.timestamp,
-        }
-    }
-
-    fn encrypt(&mut self, data: String) -> String {
-        let mut msg = data.as_bytes().to_vec();
-        let iv = aead::generate_iv();
-        aead::encrypt(&iv, &self.key, &mut msg);
-
-        format!("{:}|{:}", base64::encode(&iv), base64::encode(&msg))
-    }
-
-    fn decrypt(&mut self, data: String) -> String {
-        let v: Vec<&str> = data.split("|").collect();
-        let iv_b64 = v[0];
-        let cipher_b64 = v[1];
-
-        let iv = base64::decode(&iv_b64).unwrap();
-        let mut cipher_data = base64::decode(&cipher_b64).unwrap();
-
-        let cid = aead::decrypt(&iv, &*self.key, &mut cipher_data);
-        String::from_utf8(cid.to_vec()).unwrap()
+        TransactionStatus::Ok
     }
 }
 
@@ -913,111 +686,122 @@ impl contracts::NativeContract for Web3Analytics {
             _ => return TransactionStatus::BadOrigin,
         };
 
-        let status = match cmd.command {
-            Command::SetConfiguration { skip_stat } => {
-                let o = AccountIdWrapper::from(origin);
-                log::info!("SetConfiguration: [{}] -> {}", o.to_string(), skip_stat);
-
-                if skip_stat {
-                    self.no_tracking.insert(o, skip_stat);
-                } else {
-                    self.no_tracking.remove(&o);
-                }
+        self.handle(AccountIdWrapper(origin), cmd.command)
+    }
 
-                TransactionStatus::Ok
+    fn on_block_end(&mut self, context: &NativeContext) {
+        let now = context.block_time().min(Timestamp::MAX as u64) as Timestamp;
+        let hour = now / HOUR_IN_SECONDS;
+        let rolled_up_hour = self.block_time / HOUR_IN_SECONDS;
+        self.block_time = now;
+        // The retention is counted in hours, so the page views expire at most once an hour
+        if hour != rolled_up_hour {
+            for site in self.sites.values_mut() {
+                site.roll_up(now);
             }
-        };
-
-        status
+        }
     }
 
     fn handle_query(&mut self, origin: Option<&chain::AccountId>, req: Request) -> Response {
         let inner = || -> Result<Response> {
             match req {
                 Request::SetPageView {
+                    sid,
+                    api_key,
                     page_views,
                     encrypted,
                 } => {
-                    for page_view in page_views {
-                        if page_view.uid.len() == 64
-                            && self
-                                .no_tracking
-                                .contains_key(&AccountIdWrapper::from_hex(&page_view.uid)?)
-                        {
-                            continue;
-                        }
-                        let b = self
-                            .page_views
-                            .clone()
-                            .into_iter()
-                            .any(|x| x.id == page_view.id);
-                        if !b {
-                            self.page_views.push(page_view);
-                        }
-                    }
-
-                    self.encrypted = encrypted;
-
-                    Ok(Response::SetPageView {
-                        page_view_count: self.page_views.len() as u32,
-                    })
+                    self.authorize(&sid, None, Some(api_key.as_str()))?;
+                    let page_view_count = self.ingest(&sid, page_views, encrypted)?;
+                    Ok(Response::SetPageView { page_view_count })
                 }
-                Request::ClearPageView { timestamp: _ } => {
-                    self.page_views.clear();
+                Request::ClearPageView {
+                    sid,
+                    api_key,
+                    timestamp,
+                } => {
+                    self.authorize(&sid, origin, api_key.as_deref())?;
+                    let site = self.sites.get_mut(&sid).expect("Site is authorized; qed.");
+                    site.clear(timestamp);
                     Ok(Response::ClearPageView {
-                        page_view_count: self.page_views.len() as u32,
+                        page_view_count: site.page_views.len() as u32,
                     })
                 }
-                Request::GetOnlineUsers { start, end } => {
-                    self.update_online_users(start, end);
+                Request::GetOnlineUsers {
+                    sid,
+                    api_key,
+                    start,
+                    end,
+                } => {
+                    let site = self.authorize(&sid, origin, api_key.as_deref())?;
                     Ok(Response::GetOnlineUsers {
-                        online_users: self.online_users.clone(),
-                        encrypted: self.encrypted,
+                        online_users: self.online_users(site, &sid, start, end),
+                        encrypted: site.encrypted,
                     })
                 }
                 Request::GetHourlyStats {
+                    sid,
+                    api_key,
                     start,
                     end,
-                    start_of_week,
                 } => {
-                    self.update_hourly_stats(start, end, start_of_week);
+                    let site = self.authorize(&sid, origin, api_key.as_deref())?;
+                    let hourly_stats = site
+                        .hourly_stats(start, end)
+                        .iter()
+                        .map(|(hour, counts)| self.stat(site, &sid, *hour, counts))
+                        .collect();
                     Ok(Response::GetHourlyStats {
-                        hourly_stat: self.hourly_stat.clone(),
-                        encrypted: self.encrypted,
+                        hourly_stats,
+                        encrypted: site.encrypted,
                     })
                 }
-                Request::GetDailyStats { daily_stat } => {
-                    self.update_daily_stats(daily_stat);
+                Request::GetDailyStats {
+                    sid,
+                    api_key,
+                    start,
+                    end,
+                } => {
+                    let site = self.authorize(&sid, origin, api_key.as_deref())?;
+                    let stats = site
+                        .daily_stats(start, end)
+                        .iter()
+                        .map(|(day, counts)| self.stat(site, &sid, *day, counts))
+                        .collect();
                     Ok(Response::GetDailyStats {
-                        daily_stat: self.daily_stat.clone(),
-                        encrypted: self.encrypted,
+                        daily_stat: DailyStat { stats },
+                        encrypted: site.encrypted,
                     })
                 }
                 Request::GetWeeklySites {
-                    weekly_sites_in_db,
-                    weekly_sites_new,
+                    sid,
+                    api_key,
+                    start,
+                    end,
                 } => {
-                    self.update_weekly_sites(weekly_sites_in_db, weekly_sites_new);
+                    let site = self.authorize(&sid, origin, api_key.as_deref())?;
                     Ok(Response::GetWeeklySites {
-                        weekly_sites: self.weekly_sites.clone(),
-                        encrypted: self.encrypted,
+                        weekly_sites: self.weekly_sites(site, &sid, start, end),
+                        encrypted: site.encrypted,
                     })
                 }
                 Request::GetWeeklyDevices {
-                    weekly_devices_in_db,
-                    weekly_devices_new,
+                    sid,
+                    api_key,
+                    start,
+                    end,
                 } => {
-                    self.update_weekly_devices(weekly_devices_in_db, weekly_devices_new);
+                    let site = self.authorize(&sid, origin, api_key.as_deref())?;
                     Ok(Response::GetWeeklyDevices {
-                        weekly_devices: self.weekly_devices.clone(),
-                        encrypted: self.encrypted,
+                        weekly_devices: self.weekly_devices(site, &sid, start, end),
+                        encrypted: site.encrypted,
                     })
                 }
-                Request::GetTotalStat { total_stat, count } => {
-                    self.update_total_stat(total_stat, count);
+                Request::GetTotalStat { sid, api_key } => {
+                    let site = self.authorize(&sid, origin, api_key.as_deref())?;
                     Ok(Response::GetTotalStat {
-                        total_stat: self.total_stat.clone(),
-                        encrypted: self.encrypted,
+                        total_stat: self.stat(site, &sid, self.block_time, &site.total_stat()),
+                        encrypted: site.encrypted,
                     })
                 }
                 Request::GetConfiguration { account } => {
@@ -1031,6 +815,54 @@ impl contracts::NativeContract for Web3Analytics {
                     }
                     Ok(Response::GetConfiguration { skip_stat: off })
                 }
+                Request::GetSite { sid } => {
+                    let site = self.authorize(&sid, origin, None)?;
+                    Ok(Response::GetSite {
+                        owner: site.owner.clone(),
+                        api_key_hash: hex::encode(site.api_key_hash),
+                        page_view_hours: site.retention.page_view_hours,
+                        hourly_stat_days: site.retention.hourly_stat_days,
+                        daily_stat_days: site.retention.daily_stat_days,
+                    })
+                }
+                Request::Export { sid } => {
+                    let site = self.authorize(&sid, origin, None)?;
+                    let plain = |(ts, counts): (&Timestamp, &Counts)| HourlyPageViewStat {
+                        sid: sid.clone(),
+                        pv_count: counts.pv_count.to_string(),
+                        cid_count: counts.cid_count.to_string(),
+                        avg_duration: counts.avg_duration().to_string(),
+                        timestamp: *ts,
+                    };
+                    let site = SiteExport {
+                        sid: sid.clone(),
+                        page_views: site.page_views.clone(),
+                        hourly_stats: site.hourly.iter().map(plain).collect(),
+                        daily_stats: site.daily.iter().map(plain).collect(),
+                        weekly_sites: site
+                            .weekly_paths
+                            .iter()
+                            .map(|((week, path), count)| WeeklySite {
+                                sid: sid.clone(),
+                                path: path.clone(),
+                                count: count.to_string(),
+                                timestamp: *week,
+                            })
+                            .collect(),
+                        weekly_devices: site
+                            .weekly_devices
+                            .iter()
+                            .map(|((week, device), count)| WeeklyDevice {
+                                sid: sid.clone(),
+                                device: device.clone(),
+                                count: count.to_string(),
+                                timestamp: *week,
+                            })
+                            .collect(),
+                        total_stat: plain((&self.block_time, &site.total_stat())),
+                    };
+                    Ok(Response::Export { site })
+                }
             }
         };
         match inner() {
@@ -1039,3 +871,113 @@ impl contracts::NativeContract for Web3Analytics {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: Timestamp = 1600819200;
+
+    fn page_view(id: &str, cid: &str, created_at: Timestamp) -> PageView {
+        PageView {
+            id: id.to_string(),
+            sid: "1".to_string(),
+            cid: cid.to_string(),
+            uid: String::new(),
+            host: String::new(),
+            path: "/index.html".to_string(),
+            referrer: String::new(),
+            ip: String::new(),
+            user_agent: String::new(),
+            created_at,
+        }
+    }
+
+    fn new_site() -> Site {
+        Site::new(AccountIdWrapper(chain::AccountId::from([1u8; 32])), [0; 32])
+    }
+
+    fn insert(site: &mut Site, pv: PageView) {
+        site.insert(pv, "/index.html".to_string(), "Chrome".to_string());
+    }
+
+    #[test]
+    fn test_roll_up_keeps_the_counts() {
+        let mut site = new_site();
+        insert(&mut site, page_view("a", "c1", START));
+        insert(&mut site, page_view("b", "c1", START + 10));
+        insert(&mut site, page_view("c", "c2", START + HOUR_IN_SECONDS));
+        insert(&mut site, page_view("d", "c2", START + 60 * HOUR_IN_SECONDS));
+        let total = site.total_stat();
+        let hourly = site.hourly_stats(START, START + 2 * HOUR_IN_SECONDS);
+
+        site.roll_up(START + 50 * HOUR_IN_SECONDS);
+        assert_eq!(site.rolled_up, START + 2 * HOUR_IN_SECONDS);
+        assert_eq!(site.page_views.len(), 1);
+        assert_eq!(site.page_view_ids.len(), 1);
+        assert_eq!(site.hourly.len(), 2);
+        let rolled_up_total = site.total_stat();
+        assert_eq!(rolled_up_total.pv_count, total.pv_count);
+        assert_eq!(rolled_up_total.cid_count, total.cid_count);
+        let rolled_up_hourly = site.hourly_stats(START, START + 2 * HOUR_IN_SECONDS);
+        assert_eq!(rolled_up_hourly.len(), hourly.len());
+        assert_eq!(rolled_up_hourly[&START].pv_count, 2);
+        assert_eq!(site.daily_stats(START, START + DAY_IN_SECONDS)[&START].pv_count, 3);
+    }
+
+    #[test]
+    fn test_page_views_counted_once() {
+        let mut site = new_site();
+        insert(&mut site, page_view("a", "c1", START));
+        insert(&mut site, page_view("a", "c1", START + 10));
+        assert_eq!(site.page_views.len(), 1);
+
+        site.roll_up(START + 50 * HOUR_IN_SECONDS);
+        // Already rolled up
+        insert(&mut site, page_view("b", "c1", START + 20));
+        assert!(site.page_views.is_empty());
+        assert_eq!(site.total_stat().pv_count, 1);
+        // Doesn't go back with an earlier time
+        site.roll_up(START);
+        assert_eq!(site.rolled_up, START + 2 * HOUR_IN_SECONDS);
+        assert_eq!(site.total_stat().pv_count, 1);
+    }
+
+    #[test]
+    fn test_clear_page_views() {
+        let mut site = new_site();
+        insert(&mut site, page_view("a", "c1", START));
+        insert(&mut site, page_view("b", "c1", START + HOUR_IN_SECONDS));
+        site.clear(START + 1);
+        assert_eq!(site.page_views.len(), 1);
+        assert!(!site.page_view_ids.contains("a"));
+        insert(&mut site, page_view("a", "c1", START));
+        assert_eq!(site.page_views.len(), 2);
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut site = new_site();
+        site.retention = Retention {
+            page_view_hours: 1,
+            hourly_stat_days: 1,
+            daily_stat_days: 7,
+        };
+        insert(&mut site, page_view("a", "c1", START));
+        insert(&mut site, page_view("b", "c1", START + 3 * DAY_IN_SECONDS));
+
+        site.roll_up(START + 3 * DAY_IN_SECONDS + 2 * HOUR_IN_SECONDS);
+        assert!(site.page_views.is_empty());
+        // The hourly stats of the first day are expired, but not the daily ones
+        assert_eq!(site.hourly.keys().collect::<Vec<_>>(), [&(START + 3 * DAY_IN_SECONDS)]);
+        assert_eq!(site.daily.len(), 2);
+        assert_eq!(site.total.pv_count, 2);
+
+        site.roll_up(START + 9 * DAY_IN_SECONDS);
+        assert!(site.hourly.is_empty());
+        assert_eq!(site.daily.keys().collect::<Vec<_>>(), [&(START + 3 * DAY_IN_SECONDS)]);
+        assert_eq!(site.weekly_paths.len(), 1);
+        // The total is kept forever
+        assert_eq!(site.total.pv_count, 2);
+    }
+}
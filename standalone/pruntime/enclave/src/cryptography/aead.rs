@@ -29,13 +29,23 @@ pub fn encrypt(iv: &IV, secret: &[u8], in_out: &mut Vec<u8>) {
 
 // Decrypts the cipher (with 128 auth tag appended) in-place and returns the message as a slice.
 pub fn decrypt<'in_out>(iv: &[u8], secret: &[u8], in_out: &'in_out mut [u8]) -> &'in_out mut [u8] {
+    try_decrypt(iv, secret, in_out).expect("open_in_place failed")
+}
+
+// Like `decrypt`, but returns None instead of panicking if the cipher can't be authenticated.
+pub fn try_decrypt<'in_out>(
+    iv: &[u8],
+    secret: &[u8],
+    in_out: &'in_out mut [u8],
+) -> Option<&'in_out mut [u8]> {
+    if iv.len() < IV_BYTES {
+        return None;
+    }
     let mut iv_arr = [0u8; IV_BYTES];
     iv_arr.copy_from_slice(&iv[..IV_BYTES]);
-    let key = load_key(secret);
+    let unbound_key = ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, secret).ok()?;
+    let key = ring::aead::LessSafeKey::new(unbound_key);
     let nonce = ring::aead::Nonce::assume_unique_for_key(iv_arr);
 
-    key.open_in_place(nonce, ring::aead::Aad::empty(), in_out)
-        .expect("open_in_place failed")
+    key.open_in_place(nonce, ring::aead::Aad::empty(), in_out).ok()
 }
-
-// TODO: handle error
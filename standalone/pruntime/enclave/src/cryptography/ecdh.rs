@@ -60,10 +60,15 @@ where
 
 // Derives a secret key for symmetric encryption without a KDF
 pub fn agree(sk: &EphemeralPrivateKey, pubkey: &[u8]) -> Vec<u8> {
+    try_agree(sk, pubkey).expect("ecdh failed")
+}
+
+// Like `agree`, but fails instead of panicking if `pubkey` isn't a valid public key
+pub fn try_agree(sk: &EphemeralPrivateKey, pubkey: &[u8]) -> Result<Vec<u8>> {
     let unparsed_pk = ring::agreement::UnparsedPublicKey::new(&ring::agreement::ECDH_P256, pubkey);
 
     agree_longlived(sk, &unparsed_pk, ring::error::Unspecified, |key_material| {
         Ok(key_material.to_vec())
     })
-    .expect("ecdh failed")
+    .map_err(|_| anyhow::Error::msg("ECDH agreement failed"))
 }
@@ -34,9 +34,11 @@ pub fn decrypt(
     let iv = base64::decode(&cipher.iv_b64)
         .map_err(|_| anyhow::Error::msg(Error::BadInput("iv_b64")))?;
     // ECDH derived secret
-    let secret = ecdh::agree(privkey, &pubkey);
+    let secret = ecdh::try_agree(privkey, &pubkey)
+        .map_err(|_| anyhow::Error::msg(Error::BadInput("pubkey_b64")))?;
     log::info!("Agreed SK: {:?}", hex::encode(&secret));
-    let msg = aead::decrypt(iv.as_slice(), secret.as_slice(), &mut data);
+    let msg = aead::try_decrypt(iv.as_slice(), secret.as_slice(), &mut data)
+        .ok_or_else(|| anyhow::Error::msg(Error::BadInput("cipher_b64")))?;
     Ok(DecryptOutput {
         msg: msg.to_vec(),
         secret,
@@ -57,14 +57,16 @@ use pink::InkModule;
 use enclave_api::actions::*;
 use phala_mq::{BindTopic, MessageDispatcher, MessageOrigin, MessageSendQueue};
 use phala_pallets::pallet_mq;
-use phala_types::{PRuntimeInfo, WorkerInfo};
+use phala_types::{PRuntimeInfo, WorkerInfo, WorkerKeyHandover};
 use enclave_api::blocks::{BlockHeaderWithEvents, HeaderToSync, StorageKV};
 
 mod cert;
 mod contracts;
 mod cryptography;
 mod light_validation;
+mod migration;
 mod msg_channel;
+mod query_guard;
 mod rpc_types;
 mod system;
 mod types;
@@ -156,6 +158,7 @@ type EcdhKey = ring::agreement::EphemeralPrivateKey;
 
 struct RuntimeState {
     contracts: BTreeMap<ContractId, Box<dyn contracts::Contract>>,
+    instances: contracts::instances::ContractInstances,
     light_client: ChainLightValidation,
     main_bridge: u64,
     send_mq: MessageSendQueue,
@@ -172,8 +175,24 @@ struct LocalState {
     ecdh_public_key: Option<ring::agreement::PublicKey>,
     machine_id: [u8; 16],
     dev_mode: bool,
+    seal_policy: SealPolicy,
+    migration_key: Option<EcdhKey>,
+    /// The sealed keys received from the gatekeepers
+    master_keys: system::MasterKeys,
+    /// The keys generated by `rotate_keys`, waiting for the handover to be applied on chain
+    pending_keys: Option<PendingKeys>,
     runtime_info: Option<InitRuntimeResp>,
     runtime_state: Storage,
+    /// The state received from the previous pRuntime, applied by `init_runtime`
+    migrated_state: Option<migration::StateSnapshot>,
+    /// Set once the secrets are exported to an upgraded pRuntime
+    retired: bool,
+}
+
+/// The identity and ECDH keys to take over the worker identity
+struct PendingKeys {
+    identity_key: ecdsa::Pair,
+    ecdh_key: EcdhKey,
 }
 
 struct TestContract {
@@ -236,11 +255,18 @@ lazy_static! {
             ecdh_public_key: None,
             machine_id: [0; 16],
             dev_mode: false,
+            seal_policy: Default::default(),
+            migration_key: None,
+            master_keys: Default::default(),
+            pending_keys: None,
             runtime_info: None,
             runtime_state: Default::default(),
+            migrated_state: None,
+            retired: false,
         })
     };
     static ref SYSTEM_STATE: SgxMutex<Option<system::System>> = Default::default();
+    static ref QUERY_GUARD: SgxMutex<query_guard::QueryGuard> = Default::default();
 }
 
 fn ias_spid() -> sgx_spid_t {
@@ -648,12 +674,21 @@ pub extern "C" fn ecall_handle(
         serde_json::from_value(input_value).unwrap()
     }
 
+    // A retired pRuntime only reports its info
+    let retired = LOCAL_STATE.lock().unwrap().retired;
     let result = match action {
+        _ if retired && action != ACTION_GET_INFO => {
+            Err(error_msg("pRuntime retired after the secret migration"))
+        }
         ACTION_INIT_RUNTIME => init_runtime(load_param(input_value)),
         ACTION_TEST => test(load_param(input_value)),
         ACTION_QUERY => query(load_param(input_value)),
         ACTION_SYNC_HEADER => sync_header(load_param(input_value)),
         ACTION_DISPATCH_BLOCK => dispatch_block(load_param(input_value)),
+        ACTION_ROTATE_KEYS => rotate_keys(load_param(input_value)),
+        ACTION_PREPARE_MIGRATION => prepare_migration(),
+        ACTION_EXPORT_SECRETS => export_secrets(load_param(input_value)),
+        ACTION_IMPORT_SECRETS => import_secrets(load_param(input_value)),
         _ => {
             let payload = input_value.as_object().unwrap();
             match action {
@@ -661,6 +696,7 @@ pub extern "C" fn ecall_handle(
                 ACTION_DUMP_STATES => dump_states(payload),
                 ACTION_LOAD_STATES => load_states(payload),
                 ACTION_GET_RUNTIME_INFO => get_runtime_info(payload),
+                ACTION_LIST_CONTRACTS => list_contracts(payload),
                 ACTION_TEST_INK => test_ink(payload),
                 ACTION_GET_EGRESS_MESSAGES => get_egress_messages(),
                 _ => unknown(),
@@ -706,18 +742,96 @@ pub extern "C" fn ecall_handle(
 
 const SEAL_DATA_BUF_MAX_LEN: usize = 2048 as usize;
 
+// The attribute masks `SgxSealedData::seal_data` applies internally. The bits without security
+// implications are cleared to allow the sealed data to be migrated.
+const SEAL_FLAGS_NON_SECURITY_BITS: u64 = 0x00FF_FFFF_FFFF_FFC0
+    | SGX_FLAGS_MODE64BIT
+    | SGX_FLAGS_PROVISION_KEY
+    | SGX_FLAGS_EINITTOKEN_KEY;
+const SEAL_DEFAULT_FLAGSMASK: u64 = !SEAL_FLAGS_NON_SECURITY_BITS;
+const SEAL_DEFAULT_MISCMASK: u32 = !0x0FFF_FFFF;
+
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
 struct PersistentRuntimeData {
     version: u32,
     sk: String,
     ecdh_sk: String,
     dev_mode: bool,
+    #[serde(default)]
+    seal_policy: SealPolicy,
+    #[serde(default)]
+    master_key: Option<String>,
+    #[serde(default)]
+    contract_key: Option<String>,
+    #[serde(default)]
+    pending_sk: Option<String>,
+    #[serde(default)]
+    pending_ecdh_sk: Option<String>,
+    /// The secrets have been exported to an upgraded pRuntime
+    #[serde(default)]
+    retired: bool,
+}
+
+impl PersistentRuntimeData {
+    fn master_keys(&self) -> Result<system::MasterKeys> {
+        fn decode_key(key: &Option<String>) -> Result<Option<system::SecretKey>> {
+            match key {
+                Some(key) => {
+                    let raw = hex::decode(key).map_err(|_| anyhow!("Bad key hex"))?;
+                    let key = raw.as_slice().try_into().map_err(|_| anyhow!("Bad key length"))?;
+                    Ok(Some(key))
+                }
+                None => Ok(None),
+            }
+        }
+        Ok(system::MasterKeys {
+            master_key: decode_key(&self.master_key)?,
+            contract_key: decode_key(&self.contract_key)?,
+        })
+    }
+
+    fn pending_keys(&self) -> Result<Option<PendingKeys>> {
+        let (sk, ecdh_sk) = match (&self.pending_sk, &self.pending_ecdh_sk) {
+            (Some(sk), Some(ecdh_sk)) => (sk, ecdh_sk),
+            _ => return Ok(None),
+        };
+        let raw_key = hex::decode(sk).map_err(|_| anyhow!("Bad key hex"))?;
+        let identity_key = ecdsa::Pair::from_seed_slice(&raw_key)
+            .map_err(|_| anyhow!("Bad identity key"))?;
+        let raw_key = hex::decode(ecdh_sk).map_err(|_| anyhow!("Bad key hex"))?;
+        let ecdh_key = ecdh::create_key(raw_key.as_slice())?;
+        Ok(Some(PendingKeys {
+            identity_key,
+            ecdh_key,
+        }))
+    }
+}
+
+fn seal_data<'a>(policy: SealPolicy, data: &'a [u8]) -> Result<SgxSealedData<'a, [u8]>> {
+    let aad: [u8; 0] = [0_u8; 0];
+    let sealed_data = match policy {
+        SealPolicy::MrEnclave => SgxSealedData::<[u8]>::seal_data(&aad, data),
+        SealPolicy::MrSigner => SgxSealedData::<[u8]>::seal_data_ex(
+            SGX_KEYPOLICY_MRSIGNER,
+            sgx_attributes_t {
+                flags: SEAL_DEFAULT_FLAGSMASK,
+                xfrm: 0,
+            },
+            SEAL_DEFAULT_MISCMASK,
+            &aad,
+            data,
+        ),
+    };
+    sealed_data.map_err(anyhow::Error::msg)
 }
 
 fn save_secret_keys(
     ecdsa_sk: ecdsa::Pair,
     ecdh_sk: EcdhKey,
+    master_keys: &system::MasterKeys,
+    pending_keys: Option<&PendingKeys>,
     dev_mode: bool,
+    seal_policy: SealPolicy,
 ) -> Result<PersistentRuntimeData> {
     // Put in PresistentRuntimeData
     let serialized_sk = ecdsa_sk.to_raw_vec();
@@ -728,16 +842,31 @@ fn save_secret_keys(
         sk: hex::encode(&serialized_sk),
         ecdh_sk: hex::encode(serialized_ecdh_sk.as_ref()),
         dev_mode,
+        seal_policy,
+        master_key: master_keys.master_key.map(hex::encode),
+        contract_key: master_keys.contract_key.map(hex::encode),
+        pending_sk: pending_keys.map(|keys| hex::encode(&keys.identity_key.to_raw_vec())),
+        pending_ecdh_sk: pending_keys.map(|keys| hex::encode(ecdh::dump_key(&keys.ecdh_key))),
+        retired: false,
     };
-    let encoded_vec = serde_cbor::to_vec(&data).unwrap();
+    seal_runtime_data(&data)?;
+    Ok(data)
+}
+
+fn seal_runtime_data(data: &PersistentRuntimeData) -> Result<()> {
+    let seal_policy = data.seal_policy;
+    let encoded_vec = serde_cbor::to_vec(data).unwrap();
     let encoded_slice = encoded_vec.as_slice();
     info!("Length of encoded slice: {}", encoded_slice.len());
     info!("Encoded slice: {:?}", hex::encode(encoded_slice));
 
     // Seal
-    let aad: [u8; 0] = [0_u8; 0];
-    let sealed_data =
-        SgxSealedData::<[u8]>::seal_data(&aad, encoded_slice).map_err(anyhow::Error::msg)?;
+    let sealed_data = seal_data(seal_policy, encoded_slice)?;
+    let sealed_len =
+        SgxSealedData::<[u8]>::calc_raw_sealed_data_size(0, encoded_slice.len() as u32) as usize;
+    if sealed_len > SEAL_DATA_BUF_MAX_LEN {
+        return Err(anyhow!("Sealed data too large ({} bytes)", sealed_len));
+    }
 
     let mut return_output_buf = vec![0; SEAL_DATA_BUF_MAX_LEN].into_boxed_slice();
     let output_len: usize = return_output_buf.len();
@@ -752,11 +881,16 @@ fn save_secret_keys(
         ));
     }
 
-    // TODO: check retval and result
-    let mut _retval = sgx_status_t::SGX_SUCCESS;
-    let _result = unsafe { ocall_save_persistent_data(&mut _retval, output_ptr, output_len) };
-    info!("Persistent Runtime Data saved");
-    Ok(data)
+    let mut retval = sgx_status_t::SGX_SUCCESS;
+    let result = unsafe { ocall_save_persistent_data(&mut retval, output_ptr, output_len) };
+    if result != sgx_status_t::SGX_SUCCESS {
+        return Err(anyhow::Error::msg(result));
+    }
+    if retval != sgx_status_t::SGX_SUCCESS {
+        return Err(anyhow::Error::msg(retval));
+    }
+    info!("Persistent Runtime Data saved ({:?})", seal_policy);
+    Ok(())
 }
 
 fn load_secret_keys() -> Result<PersistentRuntimeData> {
@@ -810,7 +944,14 @@ fn init_secret_keys(
     predefined_keys: Option<(ecdsa::Pair, EcdhKey)>,
 ) -> Result<PersistentRuntimeData> {
     let data = if let Some((ecdsa_sk, ecdh_sk)) = predefined_keys {
-        save_secret_keys(ecdsa_sk, ecdh_sk, true)?
+        save_secret_keys(
+            ecdsa_sk,
+            ecdh_sk,
+            &Default::default(),
+            None,
+            true,
+            SealPolicy::MrEnclave,
+        )?
     } else {
         match load_secret_keys() {
             Ok(data) => data,
@@ -824,7 +965,14 @@ fn init_secret_keys(
                 warn!("Persistent data not found.");
                 let ecdsa_sk = new_ecdsa_key()?;
                 let ecdh_sk = ecdh::generate_key();
-                save_secret_keys(ecdsa_sk, ecdh_sk, false)?
+                save_secret_keys(
+                    ecdsa_sk,
+                    ecdh_sk,
+                    &Default::default(),
+                    None,
+                    false,
+                    SealPolicy::MrEnclave,
+                )?
             }
             other_err => return other_err,
         }
@@ -858,6 +1006,19 @@ fn init_secret_keys(
     local_state.ecdh_public_key = Some(ecdh_pk);
     local_state.machine_id = machine_id.clone();
     local_state.dev_mode = data.dev_mode;
+    local_state.seal_policy = data.seal_policy;
+    local_state.master_keys = data.master_keys()?;
+    local_state.pending_keys = data.pending_keys()?;
+    local_state.retired = data.retired;
+    if local_state.retired {
+        warn!("The secrets have been migrated to an upgraded pRuntime");
+    }
+    if let Some(keys) = &local_state.pending_keys {
+        info!(
+            "Pending key rotation to {:?}",
+            hex::encode(&keys.identity_key.public())
+        );
+    }
 
     info!("Init done.");
     Ok(data)
@@ -897,12 +1058,28 @@ fn unknown() -> Result<Value, Value> {
     }))
 }
 
+// TODO: dump the states of the contracts as well
 fn dump_states(_input: &Map<String, Value>) -> Result<Value, Value> {
-    todo!("@Kevin")
+    let system_state = SYSTEM_STATE.lock().unwrap();
+    let system = system_state
+        .as_ref()
+        .ok_or_else(|| error_msg("Runtime not initialized"))?;
+    Ok(json!({
+        "receipts": system.receipts(),
+    }))
 }
 
-fn load_states(_input: &Map<String, Value>) -> Result<Value, Value> {
-    todo!("@Kevin")
+fn load_states(input: &Map<String, Value>) -> Result<Value, Value> {
+    let mut system_state = SYSTEM_STATE.lock().unwrap();
+    let system = system_state
+        .as_mut()
+        .ok_or_else(|| error_msg("Runtime not initialized"))?;
+    if let Some(receipts) = input.get("receipts") {
+        let receipts: system::ReceiptStore = serde_json::from_value(receipts.clone())
+            .map_err(|_| error_msg("Failed to decode receipts"))?;
+        system.load_receipts(receipts);
+    }
+    Ok(json!({}))
 }
 
 fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
@@ -980,6 +1157,7 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
         version: VERSION,
         machine_id: local_state.machine_id.clone(),
         pubkey: ecdsa_pk,
+        ecdh_pubkey: ecdh_pk.as_ref().to_vec(),
         features: vec![cpu_core_num, cpu_feature_level],
         operator,
     };
@@ -1014,21 +1192,38 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
         });
     }
 
+    // Continue with the state of the previous pRuntime if the secrets are migrated
+    let migrated_state = local_state.migrated_state.take();
+    let migrated_system = match &migrated_state {
+        Some(snapshot) => Some(
+            serde_cbor::from_slice::<system::SystemSnapshot>(&snapshot.system)
+                .map_err(|_| error_msg("Failed to decode the migrated system state"))?,
+        ),
+        None => None,
+    };
+
     // Initialize bridge
-    let raw_genesis =
-        base64::decode(&input.bridge_genesis_info_b64).expect("Bad bridge_genesis_info_b64");
-    let genesis =
-        light_validation::BridgeInitInfo::<chain::Runtime>::decode(&mut raw_genesis.as_slice())
-            .expect("Can't decode bridge_genesis_info_b64");
     let mut state = STATE.lock().unwrap();
-    let mut light_client = LightValidation::new();
-    let main_bridge = light_client
-        .initialize_bridge(
-            genesis.block_header,
-            genesis.validator_set,
-            genesis.validator_set_proof,
-        )
-        .expect("Bridge initialize failed");
+    let (light_client, main_bridge) = match &migrated_state {
+        Some(snapshot) => (snapshot.light_client.clone(), snapshot.main_bridge),
+        None => {
+            let raw_genesis = base64::decode(&input.bridge_genesis_info_b64)
+                .expect("Bad bridge_genesis_info_b64");
+            let genesis = light_validation::BridgeInitInfo::<chain::Runtime>::decode(
+                &mut raw_genesis.as_slice(),
+            )
+            .expect("Can't decode bridge_genesis_info_b64");
+            let mut light_client = LightValidation::new();
+            let main_bridge = light_client
+                .initialize_bridge(
+                    genesis.block_header,
+                    genesis.validator_set,
+                    genesis.validator_set_proof,
+                )
+                .expect("Bridge initialize failed");
+            (light_client, main_bridge)
+        }
+    };
     let id_pair = local_state
         .identity_key
         .clone()
@@ -1042,9 +1237,16 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
     *system_state = Some(system::System::new(
         local_state.machine_id.to_vec(),
         &id_pair,
+        ecdh::clone_key(&ecdh_privkey),
+        ecdh_pk.as_ref().to_vec(),
+        local_state.master_keys.clone(),
         &send_mq,
         &mut recv_mq,
+        input.receipt_retention.unwrap_or_default(),
     ));
+    if let (Some(system), Some(snapshot)) = (system_state.as_mut(), migrated_system) {
+        system.restore(snapshot);
+    }
     drop(system_state);
 
     let mut other_contracts: BTreeMap<ContractId, Box<dyn contracts::Contract>> =
@@ -1053,6 +1255,11 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
     if local_state.dev_mode {
         // Install contracts when running in dev_mode.
 
+        // The local secret of the lottery keys and the plaza store, stable since the keys can't
+        // be rotated in dev mode
+        let contract_secret =
+            |id: ContractId| sp_core::hashing::blake2_256(&(id_pair.to_raw_vec(), id).encode());
+
         macro_rules! install_contract {
             ($id: expr, $inner: expr) => {{
                 let ecdh_privkey = ecdh::clone_key(&ecdh_privkey);
@@ -1066,6 +1273,7 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
                     cmd_mq,
                     evt_mq,
                     KeyPair::new(ecdh_privkey, ecdh_pk.as_ref().to_vec()),
+                    None,
                 ));
                 other_contracts.insert($id, wrapped);
             }};
@@ -1080,7 +1288,7 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
         );
         install_contract!(
             contracts::BTC_LOTTERY,
-            contracts::btc_lottery::BtcLottery::new(Some(id_pair.clone()))
+            contracts::btc_lottery::BtcLottery::new(Some(contract_secret(contracts::BTC_LOTTERY)))
         );
         install_contract!(
             contracts::WEB3_ANALYTICS,
@@ -1088,34 +1296,74 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
         );
         install_contract!(
             contracts::DATA_PLAZA,
-            contracts::data_plaza::DataPlaza::new()
+            contracts::data_plaza::DataPlaza::new(
+                "data_plaza".into(),
+                sp_core::hashing::blake2_256(
+                    &(b"data_plaza/store", &contract_secret(contracts::DATA_PLAZA)).encode()
+                )
+            )
         );
+        install_contract!(contracts::NFT, contracts::nft::Nft::new());
+    }
+
+    let mut instances = contracts::instances::ContractInstances::new(
+        id_pair.clone(),
+        ecdh::clone_key(&ecdh_privkey),
+        ecdh_pk.as_ref().to_vec(),
+        &send_mq,
+        &mut recv_mq,
+    );
+
+    local_state.block_hashes.clear();
+    if let Some(snapshot) = migrated_state {
+        send_mq.load(snapshot.send_queue);
+        if !snapshot.instances.is_empty() {
+            let contract_key = local_state
+                .master_keys
+                .contract_key
+                .as_ref()
+                .ok_or_else(|| error_msg("Missing the contract key to restore the contracts"))?;
+            instances
+                .restore(snapshot.instances, contract_key, &mut recv_mq)
+                .map_err(|e| error_msg(&format!("Failed to restore the contracts: {}", e)))?;
+        }
+        local_state.runtime_state.load(snapshot.storage.into_iter());
+        info!(
+            "Migrated state loaded at block {}: {:?}",
+            snapshot.blocknum,
+            local_state.runtime_state.root()
+        );
+        local_state.headernum = snapshot.headernum;
+        local_state.blocknum = snapshot.blocknum;
+        local_state.block_hashes.extend(snapshot.block_hashes);
+    } else {
+        let genesis_state_scl = base64::decode(input.genesis_state_b64)
+            .map_err(|_| error_msg("Base64 decode genesis state failed"))?;
+        let mut genesis_state_scl = &genesis_state_scl[..];
+        let genesis_state: Vec<(Vec<u8>, Vec<u8>)> = Decode::decode(&mut genesis_state_scl)
+            .map_err(|_| error_msg("Scale decode genesis state failed"))?;
+
+        // Initialize other states
+        local_state.runtime_state.load(genesis_state.into_iter());
+
+        info!(
+            "Genesis state loaded: {:?}",
+            local_state.runtime_state.root()
+        );
+
+        local_state.headernum = 1;
+        local_state.blocknum = 1;
     }
 
     *state = Some(RuntimeState {
         contracts: other_contracts,
+        instances,
         light_client,
         main_bridge,
         send_mq,
         recv_mq,
     });
 
-    let genesis_state_scl = base64::decode(input.genesis_state_b64)
-        .map_err(|_| error_msg("Base64 decode genesis state failed"))?;
-    let mut genesis_state_scl = &genesis_state_scl[..];
-    let genesis_state: Vec<(Vec<u8>, Vec<u8>)> = Decode::decode(&mut genesis_state_scl)
-        .map_err(|_| error_msg("Scale decode genesis state failed"))?;
-
-    // Initialize other states
-    local_state.runtime_state.load(genesis_state.into_iter());
-
-    info!(
-        "Genesis state loaded: {:?}",
-        local_state.runtime_state.root()
-    );
-
-    local_state.headernum = 1;
-    local_state.blocknum = 1;
     // Response
     let resp = InitRuntimeResp {
         encoded_runtime_info,
@@ -1124,11 +1372,331 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
         attestation,
     };
     local_state.runtime_info = Some(resp.clone());
-    local_state.block_hashes.clear();
     local_state.initialized = true;
     Ok(serde_json::to_value(resp).unwrap())
 }
 
+/// Generates the identity and ECDH keys to replace the current ones.
+///
+/// The old identity key signs a handover statement which is published to `pallet_registry` by the
+/// old identity. The new keys are sealed as pending, and only take effect when the
+/// `WorkerKeyRotated` event is observed in a dispatched block, so the old identity keeps working
+/// until then.
+fn rotate_keys(input: RotateKeysReq) -> Result<Value, Value> {
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    if !local_state.initialized {
+        return Err(error_msg("Runtime not initialized"));
+    }
+    if local_state.dev_mode {
+        return Err(error_msg("Key rotation is disallowed in dev mode"));
+    }
+    if local_state.pending_keys.is_some() {
+        return Err(error_msg("A key rotation is pending"));
+    }
+    let old_pair = local_state
+        .identity_key
+        .clone()
+        .expect("Identity key must be initialized; qed.");
+    let old_ecdh_sk = ecdh::clone_key(
+        local_state
+            .ecdh_private_key
+            .as_ref()
+            .expect("ECDH not initizlied"),
+    );
+
+    let new_pair = new_ecdsa_key().map_err(|_| error_msg("Failed to generate identity key"))?;
+    let new_ecdh_sk = ecdh::generate_key();
+    let new_ecdh_pk = new_ecdh_sk
+        .compute_public_key()
+        .map_err(|_| error_msg("Can't compute ECDH pubkey"))?;
+
+    let handover = WorkerKeyHandover {
+        old_pubkey: old_pair.public(),
+        new_pubkey: new_pair.public(),
+        new_ecdh_pubkey: new_ecdh_pk.as_ref().to_vec(),
+    };
+    let signature = old_pair.sign(&handover.encode()).0.to_vec();
+
+    let pending_keys = PendingKeys {
+        identity_key: new_pair,
+        ecdh_key: new_ecdh_sk,
+    };
+    let seal_policy = input.seal_policy.unwrap_or(local_state.seal_policy);
+    save_secret_keys(
+        old_pair,
+        old_ecdh_sk,
+        &local_state.master_keys,
+        Some(&pending_keys),
+        false,
+        seal_policy,
+    )
+    .map_err(|e| error_msg(&format!("Failed to seal the new keys: {:?}", e)))?;
+
+    let system_state = SYSTEM_STATE.lock().unwrap();
+    let system = system_state
+        .as_ref()
+        .ok_or_else(|| error_msg("Runtime not initialized"))?;
+    system.announce_key_handover(handover, signature);
+
+    let pubkey_hex = hex::encode(&pending_keys.identity_key.public());
+    let ecdh_pubkey_hex = hex::encode(new_ecdh_pk.as_ref());
+    info!("Identity key rotation announced. New pubkey: {:?}", pubkey_hex);
+    local_state.pending_keys = Some(pending_keys);
+    local_state.seal_policy = seal_policy;
+
+    Ok(json!({
+        "public_key": pubkey_hex,
+        "ecdh_public_key": ecdh_pubkey_hex,
+        "seal_policy": seal_policy,
+    }))
+}
+
+/// Makes the pending keys the identity once the handover is applied on chain, and seals them
+fn complete_key_rotation(local_state: &mut LocalState) -> Result<(), Value> {
+    let keys = match local_state.pending_keys.take() {
+        Some(keys) => keys,
+        None => return Ok(()),
+    };
+    let ecdh_pk = keys
+        .ecdh_key
+        .compute_public_key()
+        .map_err(|_| error_msg("Can't compute ECDH pubkey"))?;
+    save_secret_keys(
+        keys.identity_key.clone(),
+        ecdh::clone_key(&keys.ecdh_key),
+        &local_state.master_keys,
+        None,
+        local_state.dev_mode,
+        local_state.seal_policy,
+    )
+    .map_err(|e| error_msg(&format!("Failed to seal the rotated keys: {:?}", e)))?;
+    info!(
+        "Identity key rotated. New pubkey: {:?}",
+        hex::encode(&keys.identity_key.public())
+    );
+    local_state.identity_key = Some(keys.identity_key);
+    local_state.ecdh_private_key = Some(keys.ecdh_key);
+    local_state.ecdh_public_key = Some(ecdh_pk);
+    Ok(())
+}
+
+/// Generates the attested ECDH key to receive the secrets of a previous pRuntime.
+///
+/// Called on the upgraded pRuntime before `init_runtime`.
+fn prepare_migration() -> Result<Value, Value> {
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    if local_state.initialized {
+        return Err(error_msg("Already initialized"));
+    }
+
+    let ecdh_sk = ecdh::generate_key();
+    let ecdh_pk = ecdh_sk
+        .compute_public_key()
+        .map_err(|_| error_msg("Can't compute ECDH pubkey"))?;
+    let report_data = migration::report_data_for(ecdh_pk.as_ref());
+    let (attn_report, sig, cert) = match create_attestation_report(
+        &report_data,
+        sgx_quote_sign_type_t::SGX_LINKABLE_SIGNATURE,
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Error in create_attestation_report: {:?}", e);
+            return Err(error_msg("Error while connecting to IAS"));
+        }
+    };
+    local_state.migration_key = Some(ecdh_sk);
+
+    let challenge = MigrationChallenge {
+        ecdh_public_key: hex::encode(ecdh_pk.as_ref()),
+        attestation: InitRespAttestation {
+            version: 1,
+            provider: "SGX".to_string(),
+            payload: AttestationReport {
+                report: attn_report,
+                signature: sig,
+                signing_cert: cert,
+            },
+        },
+    };
+    Ok(serde_json::to_value(challenge).unwrap())
+}
+
+/// Encrypts the secret keys and the runtime state to an upgraded pRuntime whose MRENCLAVE is
+/// whitelisted on chain, and retires this pRuntime.
+fn export_secrets(input: ExportSecretsReq) -> Result<Value, Value> {
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    if !local_state.initialized {
+        return Err(error_msg("Runtime not initialized"));
+    }
+    if local_state.dev_mode {
+        return Err(error_msg("Secret migration is disallowed in dev mode"));
+    }
+
+    let target_pubkey = migration::verify_target(&input.challenge, &local_state.runtime_state)
+        .map_err(|e| error_msg(&format!("Bad migration target: {}", e)))?;
+
+    let identity_key = local_state
+        .identity_key
+        .as_ref()
+        .expect("Identity key must be initialized; qed.");
+    let ecdh_sk = local_state
+        .ecdh_private_key
+        .as_ref()
+        .expect("ECDH not initizlied");
+    let data = PersistentRuntimeData {
+        version: 1,
+        sk: hex::encode(&identity_key.to_raw_vec()),
+        ecdh_sk: hex::encode(ecdh::dump_key(ecdh_sk).as_ref()),
+        dev_mode: false,
+        seal_policy: local_state.seal_policy,
+        master_key: local_state.master_keys.master_key.map(hex::encode),
+        contract_key: local_state.master_keys.contract_key.map(hex::encode),
+        // The handover may be applied after the migration
+        pending_sk: local_state
+            .pending_keys
+            .as_ref()
+            .map(|keys| hex::encode(&keys.identity_key.to_raw_vec())),
+        pending_ecdh_sk: local_state
+            .pending_keys
+            .as_ref()
+            .map(|keys| hex::encode(ecdh::dump_key(&keys.ecdh_key))),
+        retired: false,
+    };
+
+    let state = STATE.lock().unwrap();
+    let state = state
+        .as_ref()
+        .ok_or_else(|| error_msg("Runtime not initialized"))?;
+    let system_state = SYSTEM_STATE.lock().unwrap();
+    let system = system_state
+        .as_ref()
+        .ok_or_else(|| error_msg("Runtime not initialized"))?;
+    let instances = state
+        .instances
+        .snapshot()
+        .map_err(|e| error_msg(&format!("Failed to take the contract states: {}", e)))?;
+    let snapshot = migration::StateSnapshot {
+        headernum: local_state.headernum,
+        blocknum: local_state.blocknum,
+        block_hashes: local_state.block_hashes.iter().cloned().collect(),
+        light_client: state.light_client.clone(),
+        main_bridge: state.main_bridge,
+        storage: local_state.runtime_state.pairs(b""),
+        send_queue: state.send_mq.dump(),
+        system: serde_cbor::to_vec(&system.snapshot()).unwrap(),
+        instances,
+    };
+    let payload = migration::MigrationPayload {
+        secrets: serde_cbor::to_vec(&data).unwrap(),
+        state: snapshot,
+    };
+    let cipher = migration::encrypt_secrets(payload.encode(), &target_pubkey)
+        .map_err(|e| error_msg(&format!("Failed to encrypt the secrets: {}", e)))?;
+    let cipher_pubkey = base64::decode(&cipher.pubkey_b64).unwrap();
+    let report_data = migration::source_report_data_for(&cipher_pubkey, &target_pubkey);
+    let (attn_report, sig, cert) = match create_attestation_report(
+        &report_data,
+        sgx_quote_sign_type_t::SGX_LINKABLE_SIGNATURE,
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Error in create_attestation_report: {:?}", e);
+            return Err(error_msg("Error while connecting to IAS"));
+        }
+    };
+    let attestation = InitRespAttestation {
+        version: 1,
+        provider: "SGX".to_string(),
+        payload: AttestationReport {
+            report: attn_report,
+            signature: sig,
+            signing_cert: cert,
+        },
+    };
+
+    // Never run again with the exported identity, even after a restart
+    seal_runtime_data(&PersistentRuntimeData {
+        retired: true,
+        ..data
+    })
+    .map_err(|e| error_msg(&format!("Failed to seal the retirement: {:?}", e)))?;
+    local_state.retired = true;
+
+    info!(
+        "Secrets exported to migration target {}, at block {}",
+        input.challenge.ecdh_public_key, local_state.blocknum
+    );
+    Ok(json!({ "cipher": cipher, "attestation": attestation }))
+}
+
+/// Seals the secret keys received from the previous pRuntime and loads them. The runtime state
+/// received along is applied by the following `init_runtime`.
+fn import_secrets(input: ImportSecretsReq) -> Result<Value, Value> {
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    if local_state.initialized {
+        return Err(error_msg("Already initialized"));
+    }
+    let migration_key = local_state
+        .migration_key
+        .take()
+        .ok_or_else(|| error_msg("Migration not prepared"))?;
+    let migration_pubkey = migration_key
+        .compute_public_key()
+        .map_err(|_| error_msg("Can't compute ECDH pubkey"))?;
+    migration::verify_source(&input.attestation, &input.cipher, migration_pubkey.as_ref())
+        .map_err(|e| error_msg(&format!("Bad migration source: {}", e)))?;
+
+    let decrypted = cryptography::decrypt(&input.cipher, &migration_key)
+        .map_err(|_| error_msg("Failed to decrypt the secrets"))?;
+    let payload = migration::MigrationPayload::decode(&mut &decrypted.msg[..])
+        .map_err(|_| error_msg("Failed to decode the migration payload"))?;
+    let data: PersistentRuntimeData = serde_cbor::from_slice(&payload.secrets)
+        .map_err(|_| error_msg("Failed to decode the secrets"))?;
+    if data.dev_mode {
+        return Err(error_msg("Secret migration is disallowed in dev mode"));
+    }
+    if data.retired {
+        return Err(error_msg("The secrets are from a retired pRuntime"));
+    }
+
+    let raw_key = hex::decode(&data.sk).map_err(|_| error_msg("Can't decode key hex"))?;
+    let ecdsa_key = ecdsa::Pair::from_seed_slice(&raw_key)
+        .map_err(|_| error_msg("can't parse private key"))?;
+    let raw_ecdh_key =
+        hex::decode(&data.ecdh_sk).map_err(|_| error_msg("Can't decode ECDH key hex"))?;
+    let ecdh_key =
+        ecdh::create_key(raw_ecdh_key.as_slice()).map_err(|_| error_msg("can't create ecdh key"))?;
+
+    let master_keys = data
+        .master_keys()
+        .map_err(|_| error_msg("Failed to decode the master keys"))?;
+    let pending_keys = data
+        .pending_keys()
+        .map_err(|_| error_msg("Failed to decode the pending keys"))?;
+    save_secret_keys(
+        ecdsa_key,
+        ecdh_key,
+        &master_keys,
+        pending_keys.as_ref(),
+        false,
+        data.seal_policy,
+    )
+    .map_err(|e| error_msg(&format!("Failed to seal the secrets: {:?}", e)))?;
+    init_secret_keys(&mut local_state, None)
+        .map_err(|_| error_msg("Failed to load the migrated secrets"))?;
+    local_state.migrated_state = Some(payload.state);
+
+    let pubkey_hex = hex::encode(
+        &local_state
+            .identity_key
+            .as_ref()
+            .expect("Identity key must be initialized; qed.")
+            .public(),
+    );
+    info!("Secrets imported. Identity pubkey: {:?}", pubkey_hex);
+    Ok(json!({ "public_key": pubkey_hex }))
+}
+
 fn sync_header(input: SyncHeaderReq) -> Result<Value, Value> {
     // Parse base64 to data
     let parsed_data: Result<Vec<_>, _> = (&input.headers_b64).iter().map(base64::decode).collect();
@@ -1243,13 +1811,6 @@ fn dispatch_block(input: DispatchBlockReq) -> Result<Value, Value> {
         }
     }
 
-    // TODO.kevin: enable e2e encryption mq for contracts
-    let _ecdh_privkey = ecdh::clone_key(
-        local_state
-            .ecdh_private_key
-            .as_ref()
-            .expect("ECDH not initizlied"),
-    );
     let mut last_block = 0;
     for block in blocks.into_iter() {
         let expected_root = local_state
@@ -1306,20 +1867,59 @@ fn dispatch_block(input: DispatchBlockReq) -> Result<Value, Value> {
             .get(&event_storage_key)
             .ok_or(error_msg("Can not get Events from storage"))?;
 
-        handle_events(
+        let rotated = handle_events(
             block.block_header.number,
+            block.block_header.hash(),
             events,
             &local_state.runtime_state,
+            local_state.pending_keys.as_ref(),
         )?;
+        if rotated {
+            complete_key_rotation(&mut local_state)?;
+        }
 
         last_block = block.block_header.number;
         let _ = local_state.block_hashes.pop_front();
         local_state.blocknum = last_block + 1;
     }
 
+    seal_master_keys(&mut local_state)?;
+
     Ok(json!({ "dispatched_to": last_block }))
 }
 
+/// Seals the keys received from the gatekeepers, if any of them is new
+fn seal_master_keys(local_state: &mut LocalState) -> Result<(), Value> {
+    let master_keys = match SYSTEM_STATE.lock().unwrap().as_ref() {
+        Some(system) => system.master_keys().clone(),
+        None => return Ok(()),
+    };
+    if master_keys == local_state.master_keys {
+        return Ok(());
+    }
+    let identity_key = local_state
+        .identity_key
+        .clone()
+        .expect("Identity key must be initialized; qed.");
+    let ecdh_sk = ecdh::clone_key(
+        local_state
+            .ecdh_private_key
+            .as_ref()
+            .expect("ECDH not initizlied"),
+    );
+    save_secret_keys(
+        identity_key,
+        ecdh_sk,
+        &master_keys,
+        local_state.pending_keys.as_ref(),
+        local_state.dev_mode,
+        local_state.seal_policy,
+    )
+    .map_err(|e| error_msg(&format!("Failed to seal the master keys: {:?}", e)))?;
+    local_state.master_keys = master_keys;
+    Ok(())
+}
+
 fn parse_authority_set_change(data_b64: String) -> Result<AuthoritySetChange, Value> {
     let data = base64::decode(&data_b64)
         .map_err(|_| error_msg("cannot decode authority_set_change_b64"))?;
@@ -1327,11 +1927,16 @@ fn parse_authority_set_change(data_b64: String) -> Result<AuthoritySetChange, Va
         .map_err(|_| error_msg("cannot decode authority_set_change"))
 }
 
+/// Dispatches the messages in the events of a block to the system and the contracts.
+///
+/// Returns whether the handover to `pending_keys` is applied in the block.
 fn handle_events(
     block_number: chain::BlockNumber,
+    block_hash: Hash,
     events: Vec<u8>,
     storage: &Storage,
-) -> Result<(), Value> {
+    pending_keys: Option<&PendingKeys>,
+) -> Result<bool, Value> {
     let ref mut state = STATE.lock().unwrap();
     let state = state.as_mut().ok_or(error_msg("Runtime not initialized"))?;
     // Dispatch events
@@ -1346,9 +1951,29 @@ fn handle_events(
 
     state.recv_mq.reset_local_index();
 
+    let mut rotated = false;
     for evt in events {
+        if let chain::Event::PhalaRegistry(chain::pallet_registry::Event::WorkerKeyRotated(
+            old_pubkey,
+            new_pubkey,
+        )) = &evt.event
+        {
+            match pending_keys {
+                Some(keys)
+                    if old_pubkey == system.pubkey()
+                        && new_pubkey == &keys.identity_key.public() =>
+                {
+                    // The messages from now on must be signed by the new identity
+                    switch_identity(state, system, keys)?;
+                    rotated = true;
+                }
+                _ => (),
+            }
+            continue;
+        }
         if let chain::Event::PhalaMq(pallet_mq::Event::OutboundMessage(message)) = evt.event {
-            use phala_types::messaging::SystemEvent;
+            use phala_types::messaging::{ContractEvent, SystemEvent};
+            type ChainContractEvent = ContractEvent<chain::AccountId>;
             macro_rules! log_message {
                 ($msg: expr, $t: ident) => {{
                     let event: Result<$t, _> = parity_scale_codec::Decode::decode(&mut &$msg.payload[..]);
@@ -1369,11 +1994,20 @@ fn handle_events(
                 SystemEvent::TOPIC => {
                     log_message!(message, SystemEvent);
                 }
+                ChainContractEvent::TOPIC => {
+                    log_message!(message, ChainContractEvent);
+                }
                 _ => {
                     info!("mq dispatching message: {:?}", message);
                 }
             }
+            let is_contract_event = message.destination.path()[..] == *ChainContractEvent::TOPIC;
             state.recv_mq.dispatch(message);
+            if is_contract_event {
+                state
+                    .instances
+                    .process_events(block_number, &mut state.recv_mq);
+            }
         }
     }
 
@@ -1381,9 +2015,11 @@ fn handle_events(
         error!("System process events failed: {:?}", e);
         return Err(error_msg("System process events failed"));
     }
+    state.instances.activate(system.contract_key(), storage);
 
     let mut env = ExecuteEnv {
         block_number,
+        block_hash,
         system,
         storage,
     };
@@ -1391,6 +2027,47 @@ fn handle_events(
     for contract in state.contracts.values_mut() {
         contract.process_events(&mut env);
     }
+    for (_, instance) in state.instances.iter_mut() {
+        instance.contract.process_events(&mut env);
+    }
+    Ok(rotated)
+}
+
+/// Switches the system and the contracts to the keys taking over the worker identity
+fn switch_identity(
+    state: &mut RuntimeState,
+    system: &mut system::System,
+    keys: &PendingKeys,
+) -> Result<(), Value> {
+    let pair = &keys.identity_key;
+    let ecdh_pubkey = keys
+        .ecdh_key
+        .compute_public_key()
+        .map_err(|_| error_msg("Can't compute ECDH pubkey"))?
+        .as_ref()
+        .to_vec();
+    system.set_identity(
+        pair,
+        ecdh::clone_key(&keys.ecdh_key),
+        ecdh_pubkey.clone(),
+        &state.send_mq,
+    );
+    state.instances.set_identity(
+        pair,
+        ecdh::clone_key(&keys.ecdh_key),
+        ecdh_pubkey.clone(),
+        &state.send_mq,
+    );
+    // The built-in contracts send their messages with the identity key
+    for (id, contract) in state.contracts.iter_mut() {
+        let mq = state
+            .send_mq
+            .channel(MessageOrigin::native_contract(*id), pair.clone());
+        contract.set_worker_keys(
+            Some(mq),
+            KeyPair::new(ecdh::clone_key(&keys.ecdh_key), ecdh_pubkey.clone()),
+        );
+    }
     Ok(())
 }
 
@@ -1411,6 +2088,7 @@ fn get_info(_input: &Map<String, Value>) -> Result<Value, Value> {
     let state_root = hex::encode(&local_state.runtime_state.root());
     let machine_id = local_state.machine_id;
     let dev_mode = local_state.dev_mode;
+    let retired = local_state.retired;
     drop(local_state);
 
     let runtime_state = STATE.lock().unwrap();
@@ -1431,6 +2109,7 @@ fn get_info(_input: &Map<String, Value>) -> Result<Value, Value> {
         "state_root": state_root,
         "machine_id": machine_id,
         "dev_mode": dev_mode,
+        "retired": retired,
         "pending_messages": pending_messages,
         "score": score,
     }))
@@ -1445,6 +2124,25 @@ fn get_runtime_info(_input: &Map<String, Value>) -> Result<Value, Value> {
     Ok(serde_json::to_value(resp).unwrap())
 }
 
+fn list_contracts(_input: &Map<String, Value>) -> Result<Value, Value> {
+    let state = STATE.lock().unwrap();
+    let state = state.as_ref().ok_or(error_msg("Runtime not initialized"))?;
+    let contracts: Vec<_> = state
+        .instances
+        .iter()
+        .map(|(address, instance)| {
+            let phala_types::CodeIndex::NativeCode(code) = instance.code_index;
+            json!({
+                "id": hex::encode(address),
+                "code_index": { "NativeCode": code },
+                "deployer": hex::encode(&instance.deployer),
+                "instantiated_at": instance.instantiated_at,
+            })
+        })
+        .collect();
+    Ok(json!({ "contracts": contracts }))
+}
+
 fn test_ink(_input: &Map<String, Value>) -> Result<Value, Value> {
     info!("=======Begin Ink Contract Test=======");
 
@@ -1525,10 +2223,23 @@ fn query(q: types::SignedQuery) -> Result<Value, Value> {
     // Load and decrypt if necessary
     let payload: types::Payload =
         serde_json::from_slice(payload_data).map_err(|_| error_msg("Failed to decode payload"))?;
-    let (msg, secret, pubkey) = {
+    let (msg, secret, pubkey, worker_pubkey, current_block) = {
         let local_state = LOCAL_STATE.lock().unwrap();
+        let worker_pubkey = local_state
+            .identity_key
+            .as_ref()
+            .map(|pair| hex::encode(&pair.public()))
+            .ok_or_else(|| error_msg("Identity key not initialized"))?;
+        // The last dispatched block
+        let current_block = local_state.blocknum.saturating_sub(1);
         match payload {
-            types::Payload::Plain(data) => (data.into_bytes(), None, None),
+            types::Payload::Plain(data) => (
+                data.into_bytes(),
+                None,
+                None,
+                worker_pubkey,
+                current_block,
+            ),
             types::Payload::Cipher(cipher) => {
                 info!("cipher: {:?}", cipher);
                 let ecdh_privkey = local_state
@@ -1541,6 +2252,8 @@ fn query(q: types::SignedQuery) -> Result<Value, Value> {
                     result.msg,
                     Some(result.secret),
                     local_state.ecdh_public_key.clone(),
+                    worker_pubkey,
+                    current_block,
                 )
             }
         }
@@ -1548,6 +2261,21 @@ fn query(q: types::SignedQuery) -> Result<Value, Value> {
     debug!("msg: {}", String::from_utf8_lossy(&msg));
     let opaque_query: types::OpaqueQuery =
         serde_json::from_slice(&msg).map_err(|_| error_msg("Malformed request (Query)"))?;
+    // Replay protection
+    if let Some(origin) = &q.origin {
+        QUERY_GUARD
+            .lock()
+            .unwrap()
+            .check(
+                &origin.origin,
+                opaque_query.nonce,
+                opaque_query.worker_pubkey.as_deref(),
+                opaque_query.expiry,
+                &worker_pubkey,
+                current_block,
+            )
+            .map_err(|e| error_msg(&format!("Rejected query: {}", e)))?;
+    }
     // Origin
     let accid_origin = match q.origin.as_ref() {
         Some(o) => {
@@ -1559,31 +2287,43 @@ fn query(q: types::SignedQuery) -> Result<Value, Value> {
     };
     // Dispatch
     let ref_origin = accid_origin.as_ref();
-    let res = match opaque_query.contract_id {
-        SYSTEM => {
-            let mut guard = SYSTEM_STATE.lock().unwrap();
-            let system_state = guard
-                .as_mut()
-                .ok_or_else(|| error_msg("Runtime not initialized"))?;
-            serde_json::to_value(
-                system_state.handle_query(
-                    ref_origin,
-                    types::deopaque_query(opaque_query)
-                        .map_err(|_| error_msg("Malformed request (system::Request)"))?
-                        .request,
-                ),
-            )
-            .unwrap()
-        }
-        _ => {
-            let mut state = STATE.lock().unwrap();
-            let state = state.as_mut().ok_or(error_msg("Runtime not initialized"))?;
-            let contract = state
-                .contracts
-                .get_mut(&opaque_query.contract_id)
-                .ok_or(error_msg("Contract not found"))?;
-            let response = contract.handle_query(ref_origin, opaque_query)?;
-            response
+    let res = if let Some(instance_id) = &opaque_query.instance_id {
+        let address =
+            contracts::h256_from_hex(instance_id).map_err(|_| error_msg("Bad instance_id"))?;
+        let mut state = STATE.lock().unwrap();
+        let state = state.as_mut().ok_or(error_msg("Runtime not initialized"))?;
+        let instance = state
+            .instances
+            .get_mut(&address)
+            .ok_or(error_msg("Contract not found"))?;
+        instance.contract.handle_query(ref_origin, opaque_query)?
+    } else {
+        match opaque_query.contract_id {
+            SYSTEM => {
+                let mut guard = SYSTEM_STATE.lock().unwrap();
+                let system_state = guard
+                    .as_mut()
+                    .ok_or_else(|| error_msg("Runtime not initialized"))?;
+                serde_json::to_value(
+                    system_state.handle_query(
+                        ref_origin,
+                        types::deopaque_query(opaque_query)
+                            .map_err(|_| error_msg("Malformed request (system::Request)"))?
+                            .request,
+                    ),
+                )
+                .unwrap()
+            }
+            _ => {
+                let mut state = STATE.lock().unwrap();
+                let state = state.as_mut().ok_or(error_msg("Runtime not initialized"))?;
+                let contract = state
+                    .contracts
+                    .get_mut(&opaque_query.contract_id)
+                    .ok_or(error_msg("Contract not found"))?;
+                let response = contract.handle_query(ref_origin, opaque_query)?;
+                response
+            }
         }
     };
     // Encrypt response if necessary
@@ -1625,4 +2365,16 @@ mod identity {
 
         gatekeepers.contains(pubkey)
     }
+
+    /// Whether `pubkey` is in the gatekeeper set after the scheduled rotation
+    pub fn is_next_gatekeeper(pubkey: &WorkerPublicKey, runtime_state: &Storage) -> bool {
+        let key = storage_prefix("PhalaRegistry", "NextGatekeeper");
+        match runtime_state.get(&key) {
+            Some(v) => phala_types::GatekeeperRotation::<chain::BlockNumber>::decode(&mut &v[..])
+                .expect("Decode value of NextGatekeeper Failed. (This should not happen)")
+                .gatekeepers
+                .contains(pubkey),
+            None => is_gatekeeper(pubkey, runtime_state),
+        }
+    }
 }
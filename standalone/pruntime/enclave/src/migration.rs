@@ -0,0 +1,172 @@
+//! Secret migration between pRuntime enclaves.
+//!
+//! Data sealed with `SealPolicy::MrEnclave` can't be unsealed by an upgraded pRuntime. To keep the
+//! worker identity across an upgrade, the new enclave (the target) generates an ephemeral ECDH key
+//! and binds it to an IAS report. The old enclave (the source) validates the report, checks the
+//! MRENCLAVE of the target against the on-chain whitelist, and encrypts its persistent runtime
+//! data to the key agreed with the target.
+//!
+//! In turn, the source attests the one-time ECDH key it encrypts the payload with. The target only
+//! accepts a payload from an enclave signed by the same vendor as itself, so a host can't feed it
+//! secrets or a state of its own choosing.
+//!
+//! Along with the secrets, the payload carries a snapshot of the runtime state, so the target
+//! continues from the block the source has dispatched instead of replaying the chain. The target
+//! applies it in `init_runtime`. Once the payload is exported, the source is retired and refuses
+//! to process any further block, so the two enclaves never run with the same identity.
+
+use crate::std::vec::Vec;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use parity_scale_codec::{Decode, Encode};
+use phala_mq::{MessageOrigin, SignedMessage};
+use sp_core::H256;
+
+use crate::contracts::instances::InstanceSnapshot;
+
+use crate::cryptography::{aead, ecdh, AeadCipher};
+use crate::light_validation::utils::storage_prefix;
+use crate::rpc_types::{InitRespAttestation, MigrationChallenge};
+use crate::Storage;
+use phala_pallets::attestation::{validate_ias_report, IasFields};
+use sgx_tse::rsgx_self_report;
+
+/// The payload encrypted to the migration target
+#[derive(Encode, Decode)]
+pub struct MigrationPayload {
+    /// The CBOR encoded `PersistentRuntimeData`
+    pub secrets: Vec<u8>,
+    pub state: StateSnapshot,
+}
+
+/// The runtime state of the source at the time of the migration
+#[derive(Encode, Decode)]
+pub struct StateSnapshot {
+    pub headernum: u32,
+    pub blocknum: u32,
+    /// The synced headers not dispatched yet, as `(hash, state_root)`
+    pub block_hashes: Vec<(H256, H256)>,
+    pub light_client: crate::ChainLightValidation,
+    pub main_bridge: u64,
+    /// The key-value pairs of the synced chain storage
+    pub storage: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The next sequence and the unconfirmed messages of each sender
+    pub send_queue: Vec<(MessageOrigin, u64, Vec<SignedMessage>)>,
+    /// The CBOR encoded `system::SystemSnapshot`
+    pub system: Vec<u8>,
+    pub instances: Vec<InstanceSnapshot>,
+}
+
+/// The report data the target enclave commits to in its attestation report
+pub fn report_data_for(ecdh_pubkey: &[u8]) -> [u8; 32] {
+    sp_core::hashing::blake2_256(ecdh_pubkey)
+}
+
+/// The report data the source enclave commits to in its attestation report
+pub fn source_report_data_for(cipher_pubkey: &[u8], target_pubkey: &[u8]) -> [u8; 32] {
+    sp_core::hashing::blake2_256(&[cipher_pubkey, target_pubkey].concat())
+}
+
+/// Reads the MRENCLAVE whitelist from the synced chain state.
+///
+/// Each entry is `mr_enclave ++ isv_prod_id ++ isv_svn ++ mr_signer`, as stored by the registry.
+fn mrenclave_whitelist(chain_storage: &Storage) -> Result<Vec<Vec<u8>>> {
+    let key = storage_prefix("PhalaRegistry", "MREnclaveWhitelist");
+    match chain_storage.get(&key) {
+        Some(v) => Vec::<Vec<u8>>::decode(&mut &v[..])
+            .map_err(|_| anyhow!("Failed to decode MREnclaveWhitelist")),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Validates an IAS report and checks that it commits to `report_data`.
+fn validate_attestation(attestation: &InitRespAttestation, report_data: &[u8]) -> Result<IasFields> {
+    let payload = &attestation.payload;
+    let signature =
+        base64::decode(&payload.signature).map_err(|_| anyhow!("Failed to decode signature"))?;
+    let raw_signing_cert = base64::decode_config(&payload.signing_cert, base64::STANDARD)
+        .map_err(|_| anyhow!("Failed to decode signing_cert"))?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| anyhow!("Bad system time"))?
+        .as_secs();
+
+    let fields = validate_ias_report(
+        &payload.report.as_bytes().to_vec(),
+        &signature,
+        &raw_signing_cert,
+        now,
+    )
+    .map_err(|_| anyhow!("Invalid attestation report"))?;
+
+    if fields.report_data[..32] != report_data[..] {
+        return Err(anyhow!("The attestation report doesn't commit to the ECDH key"));
+    }
+    Ok(fields)
+}
+
+/// Validates the attestation of a migration target and returns its ECDH public key.
+pub fn verify_target(challenge: &MigrationChallenge, chain_storage: &Storage) -> Result<Vec<u8>> {
+    let ecdh_pubkey = hex::decode(&challenge.ecdh_public_key)
+        .map_err(|_| anyhow!("Failed to decode ecdh_public_key"))?;
+    let fields = validate_attestation(&challenge.attestation, &report_data_for(&ecdh_pubkey))?;
+
+    let mut t_mrenclave = Vec::new();
+    t_mrenclave.extend_from_slice(&fields.mr_enclave);
+    t_mrenclave.extend_from_slice(&fields.isv_prod_id);
+    t_mrenclave.extend_from_slice(&fields.isv_svn);
+    t_mrenclave.extend_from_slice(&fields.mr_signer);
+    if !mrenclave_whitelist(chain_storage)?.contains(&t_mrenclave) {
+        return Err(anyhow!(
+            "MRENCLAVE {} not in the whitelist",
+            hex::encode(&fields.mr_enclave)
+        ));
+    }
+
+    Ok(ecdh_pubkey)
+}
+
+/// Validates the attestation of the migration source against the cipher it sent to
+/// `target_pubkey`.
+///
+/// The source must be an enclave of the same product signed by the same vendor as this one. The
+/// chain state isn't available to the target yet, so the MRSIGNER stands in for the whitelist.
+pub fn verify_source(
+    attestation: &InitRespAttestation,
+    cipher: &AeadCipher,
+    target_pubkey: &[u8],
+) -> Result<()> {
+    let cipher_pubkey =
+        base64::decode(&cipher.pubkey_b64).map_err(|_| anyhow!("Failed to decode pubkey_b64"))?;
+    let fields = validate_attestation(
+        attestation,
+        &source_report_data_for(&cipher_pubkey, target_pubkey),
+    )?;
+
+    let own = rsgx_self_report().body;
+    if fields.mr_signer != own.mr_signer.m || fields.isv_prod_id != own.isv_prod_id.to_le_bytes() {
+        return Err(anyhow!(
+            "The source enclave isn't signed by {}",
+            hex::encode(&own.mr_signer.m)
+        ));
+    }
+    Ok(())
+}
+
+/// Encrypts the secrets with a key agreed between a one-time ECDH key and the target pubkey.
+pub fn encrypt_secrets(mut data: Vec<u8>, target_pubkey: &[u8]) -> Result<AeadCipher> {
+    let sk = ecdh::generate_key();
+    let pk = sk
+        .compute_public_key()
+        .map_err(|_| anyhow!("Can't compute ECDH pubkey"))?;
+    let secret = ecdh::try_agree(&sk, target_pubkey)?;
+    let iv = aead::generate_iv();
+    aead::encrypt(&iv, &secret, &mut data);
+    Ok(AeadCipher {
+        iv_b64: base64::encode(&iv),
+        cipher_b64: base64::encode(&data),
+        pubkey_b64: base64::encode(pk.as_ref()),
+    })
+}
+
@@ -28,6 +28,7 @@ pub mod osp {
             cryptography::{aead, ecdh},
             std::vec::Vec,
         };
+        use anyhow::Result;
         use parity_scale_codec::Encode;
         use phala_mq::{BindTopic, EcdsaMessageChannel, Path};
         use ring::agreement::EphemeralPrivateKey;
@@ -62,12 +63,15 @@ pub mod osp {
                 (self.key_map)(topic)
             }
 
+            /// Sends a message to `to`, encrypted if `remote_pubkey` is given.
+            ///
+            /// Fails without sending if `remote_pubkey` isn't a valid ECDH public key.
             pub fn osp_sendto<M: Encode>(
                 &self,
                 message: &M,
                 to: impl Into<Path>,
                 remote_pubkey: Option<Vec<u8>>,
-            ) {
+            ) -> Result<()> {
                 match remote_pubkey {
                     None => {
                         let msg = OspPayload::Plain(message);
@@ -75,25 +79,47 @@ pub mod osp {
                         self.mq.send_data(data, to)
                     }
                     Some(pubkey) => {
-                        let mut data = message.encode();
-                        let iv = aead::generate_iv();
-                        let sk = ecdh::agree(&self.key.privkey, &pubkey);
-                        aead::encrypt(&iv, &sk, &mut data);
-                        let payload: OspPayload<M> = OspPayload::Encrypted(AeadCipher {
-                            iv: iv.into(),
-                            cipher: data,
-                            pubkey: self.key.pubkey.clone(),
-                        });
+                        let payload: OspPayload<M> =
+                            OspPayload::Encrypted(self.encrypt(message, &pubkey)?);
                         self.mq.send_data(payload.encode(), to)
                     }
                 }
+                Ok(())
+            }
+
+            /// Encrypts a message with the key agreed with `remote_pubkey`
+            pub fn encrypt<M: Encode>(
+                &self,
+                message: &M,
+                remote_pubkey: &[u8],
+            ) -> Result<AeadCipher> {
+                let mut data = message.encode();
+                let iv = aead::generate_iv();
+                let sk = ecdh::try_agree(&self.key.privkey, remote_pubkey)?;
+                aead::encrypt(&iv, &sk, &mut data);
+                Ok(AeadCipher {
+                    iv: iv.into(),
+                    cipher: data,
+                    pubkey: self.key.pubkey.clone(),
+                })
+            }
+
+            /// Decrypts a cipher sent to the key pair of this channel.
+            ///
+            /// Fails if the cipher isn't encrypted to this key pair or was tampered with.
+            pub fn decrypt(&self, cipher: &AeadCipher) -> Result<Vec<u8>> {
+                let sk = ecdh::try_agree(&self.key.privkey, &cipher.pubkey)?;
+                let mut data = cipher.cipher.clone();
+                aead::try_decrypt(&cipher.iv, &sk, &mut data)
+                    .map(|data| data.to_vec())
+                    .ok_or_else(|| anyhow::anyhow!("Failed to decrypt the cipher"))
             }
 
             pub fn osp_send<M: Encode + BindTopic>(
                 &self,
                 message: &M,
                 remote_pubkey: Option<Vec<u8>>,
-            ) {
+            ) -> Result<()> {
                 self.osp_sendto(message, <M as BindTopic>::TOPIC, remote_pubkey)
             }
         }
@@ -148,8 +174,9 @@ pub mod osp {
                 match msg {
                     OspPayload::Plain(msg) => Ok(msg),
                     OspPayload::Encrypted(mut cipher) => {
-                        let sk = ecdh::agree(&self.privkey, &cipher.pubkey);
-                        let msg = aead::decrypt(&cipher.iv, &sk, &mut cipher.cipher);
+                        let sk = ecdh::try_agree(&self.privkey, &cipher.pubkey)?;
+                        let msg = aead::try_decrypt(&cipher.iv, &sk, &mut cipher.cipher)
+                            .ok_or_else(|| anyhow::anyhow!("Failed to decrypt Osp payload"))?;
                         let msg = Decode::decode(&mut msg.as_ref()).map_err(|_| {
                             anyhow::anyhow!("SCALE decode Osp decrypted data failed")
                         })?;
@@ -210,6 +237,19 @@ pub mod osp {
         }
     }
 
+    /// Calculates the Substrate storage key of the registered info of a worker
+    pub fn storage_key_for_worker_info(pubkey: &phala_types::WorkerPublicKey) -> Vec<u8> {
+        use crate::light_validation::utils::storage_map_prefix_twox_64_concat;
+        use phala_pallets::pallet_mq::StorageMapTrait as _;
+
+        type Worker = phala_pallets::pallet_registry::Worker<chain::Runtime>;
+
+        let module_prefix = Worker::module_prefix();
+        let storage_prefix = Worker::storage_prefix();
+
+        storage_map_prefix_twox_64_concat(module_prefix, storage_prefix, pubkey)
+    }
+
     /// Calculates the Substrate storage key prefix for a StorageMap
     pub fn storage_prefix_for_topic_pubkey(topic: &phala_mq::Path) -> Vec<u8> {
         use phala_pallets::pallet_mq::StorageMapTrait as _;
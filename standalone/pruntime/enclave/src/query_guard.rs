@@ -0,0 +1,221 @@
+//! Replay protection of the signed queries.
+//!
+//! A signed query must name the worker it is sent to and the last block it is valid at. The nonces
+//! of the unexpired queries are remembered per origin, so a captured query can neither be replayed
+//! to another worker, nor to the same worker before it expires, nor at all after it expires.
+//!
+//! The nonces live in memory only. A query is accepted at most `MAX_QUERY_TTL` blocks ahead, which
+//! bounds how long a query remains replayable across a restart of pRuntime.
+
+use crate::std::collections::BTreeMap;
+use crate::std::string::String;
+use core::fmt;
+
+extern crate runtime as chain;
+
+/// How many blocks ahead of the dispatched block a signed query may expire
+pub const MAX_QUERY_TTL: chain::BlockNumber = 100;
+/// How many unexpired queries an origin may have
+pub const MAX_PENDING_QUERIES: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    /// The worker public key or the expiry is missing
+    MissingReplayProtection,
+    /// The query is sent to another worker
+    WrongWorker,
+    /// The query expired at the given block
+    Expired(chain::BlockNumber),
+    /// The expiry is further than `MAX_QUERY_TTL` blocks ahead
+    ExpiryTooFar(chain::BlockNumber),
+    /// The nonce has been used by the origin
+    Replayed(u32),
+    /// The origin has `MAX_PENDING_QUERIES` unexpired queries
+    TooManyQueries,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::MissingReplayProtection => {
+                write!(f, "signed query must set worker_pubkey and expiry")
+            }
+            QueryError::WrongWorker => write!(f, "query is sent to another worker"),
+            QueryError::Expired(expiry) => write!(f, "query expired at block {}", expiry),
+            QueryError::ExpiryTooFar(max) => {
+                write!(f, "query expiry is too far, must be no later than block {}", max)
+            }
+            QueryError::Replayed(nonce) => write!(f, "query nonce {} has been used", nonce),
+            QueryError::TooManyQueries => write!(f, "too many pending queries of the origin"),
+        }
+    }
+}
+
+/// The nonces of the unexpired signed queries
+#[derive(Default)]
+pub struct QueryGuard {
+    /// origin => nonce => expiry
+    nonces: BTreeMap<String, BTreeMap<u32, chain::BlockNumber>>,
+    pruned_at: chain::BlockNumber,
+}
+
+impl QueryGuard {
+    /// Accepts a signed query at `current_block`, or explains why it's rejected.
+    ///
+    /// `origin` is the hex public key of the signer and `worker_pubkey` is the hex identity public
+    /// key of this worker.
+    pub fn check(
+        &mut self,
+        origin: &str,
+        nonce: u32,
+        target_worker: Option<&str>,
+        expiry: Option<chain::BlockNumber>,
+        worker_pubkey: &str,
+        current_block: chain::BlockNumber,
+    ) -> Result<(), QueryError> {
+        let (target_worker, expiry) = match (target_worker, expiry) {
+            (Some(worker), Some(expiry)) => (worker, expiry),
+            _ => return Err(QueryError::MissingReplayProtection),
+        };
+        if normalize_hex(target_worker) != normalize_hex(worker_pubkey) {
+            return Err(QueryError::WrongWorker);
+        }
+        if expiry < current_block {
+            return Err(QueryError::Expired(expiry));
+        }
+        let max_expiry = current_block.saturating_add(MAX_QUERY_TTL);
+        if expiry > max_expiry {
+            return Err(QueryError::ExpiryTooFar(max_expiry));
+        }
+
+        self.prune(current_block);
+        let pending = self.nonces.entry(normalize_hex(origin)).or_default();
+        if pending.contains_key(&nonce) {
+            return Err(QueryError::Replayed(nonce));
+        }
+        if pending.len() >= MAX_PENDING_QUERIES {
+            return Err(QueryError::TooManyQueries);
+        }
+        pending.insert(nonce, expiry);
+        Ok(())
+    }
+
+    /// Forgets the expired nonces, once per block
+    fn prune(&mut self, current_block: chain::BlockNumber) {
+        if self.pruned_at == current_block {
+            return;
+        }
+        self.pruned_at = current_block;
+        for pending in self.nonces.values_mut() {
+            pending.retain(|_, expiry| *expiry >= current_block);
+        }
+        self.nonces.retain(|_, pending| !pending.is_empty());
+    }
+}
+
+/// Lowercases a hex string and strips its `0x` prefix, so that all the forms of a key match
+fn normalize_hex(hex: &str) -> String {
+    let hex = hex
+        .strip_prefix("0x")
+        .or_else(|| hex.strip_prefix("0X"))
+        .unwrap_or(hex);
+    hex.to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORKER: &str = "02aabb";
+    const ORIGIN: &str = "03ccdd";
+
+    fn check(
+        guard: &mut QueryGuard,
+        origin: &str,
+        nonce: u32,
+        expiry: chain::BlockNumber,
+        current_block: chain::BlockNumber,
+    ) -> Result<(), QueryError> {
+        guard.check(origin, nonce, Some(WORKER), Some(expiry), WORKER, current_block)
+    }
+
+    #[test]
+    fn rejects_missing_replay_protection() {
+        let mut guard = QueryGuard::default();
+        assert_eq!(
+            guard.check(ORIGIN, 1, None, Some(10), WORKER, 1),
+            Err(QueryError::MissingReplayProtection)
+        );
+        assert_eq!(
+            guard.check(ORIGIN, 1, Some(WORKER), None, WORKER, 1),
+            Err(QueryError::MissingReplayProtection)
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_worker() {
+        let mut guard = QueryGuard::default();
+        assert_eq!(
+            guard.check(ORIGIN, 1, Some("02aabc"), Some(10), WORKER, 1),
+            Err(QueryError::WrongWorker)
+        );
+        // Any form of the right key is accepted
+        assert_eq!(
+            guard.check(ORIGIN, 1, Some("0x02AABB"), Some(10), WORKER, 1),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_expired_or_too_far() {
+        let mut guard = QueryGuard::default();
+        assert_eq!(check(&mut guard, ORIGIN, 1, 9, 10), Err(QueryError::Expired(9)));
+        assert_eq!(
+            check(&mut guard, ORIGIN, 1, 10 + MAX_QUERY_TTL + 1, 10),
+            Err(QueryError::ExpiryTooFar(10 + MAX_QUERY_TTL))
+        );
+        assert_eq!(check(&mut guard, ORIGIN, 1, 10, 10), Ok(()));
+        assert_eq!(check(&mut guard, ORIGIN, 2, 10 + MAX_QUERY_TTL, 10), Ok(()));
+    }
+
+    #[test]
+    fn rejects_replayed_nonce() {
+        let mut guard = QueryGuard::default();
+        assert_eq!(check(&mut guard, ORIGIN, 1, 20, 10), Ok(()));
+        assert_eq!(check(&mut guard, ORIGIN, 1, 20, 11), Err(QueryError::Replayed(1)));
+        // The forms of the origin share the nonces
+        assert_eq!(check(&mut guard, "0x03CCDD", 1, 20, 11), Err(QueryError::Replayed(1)));
+        // The nonces are per origin
+        assert_eq!(check(&mut guard, "03ccde", 1, 20, 11), Ok(()));
+    }
+
+    #[test]
+    fn caps_pending_queries_per_origin() {
+        let mut guard = QueryGuard::default();
+        for nonce in 0..MAX_PENDING_QUERIES as u32 {
+            assert_eq!(check(&mut guard, ORIGIN, nonce, 20, 10), Ok(()));
+        }
+        let nonce = MAX_PENDING_QUERIES as u32;
+        assert_eq!(
+            check(&mut guard, ORIGIN, nonce, 20, 10),
+            Err(QueryError::TooManyQueries)
+        );
+        // Switching the form of the origin doesn't get around the cap
+        assert_eq!(
+            check(&mut guard, "0x03CCDD", nonce, 20, 10),
+            Err(QueryError::TooManyQueries)
+        );
+        // Until the queries expire
+        assert_eq!(check(&mut guard, ORIGIN, nonce, 30, 21), Ok(()));
+    }
+
+    #[test]
+    fn expired_nonce_is_freed() {
+        let mut guard = QueryGuard::default();
+        assert_eq!(check(&mut guard, ORIGIN, 1, 10, 5), Ok(()));
+        assert_eq!(check(&mut guard, ORIGIN, 1, 20, 10), Err(QueryError::Replayed(1)));
+        // The first query can't be replayed once expired, so its nonce can be reused
+        assert_eq!(check(&mut guard, ORIGIN, 1, 20, 11), Ok(()));
+        assert_eq!(guard.nonces[ORIGIN].get(&1), Some(&20));
+    }
+}
@@ -1,4 +1,6 @@
+use crate::cryptography::AeadCipher;
 use crate::std::{string::String, vec::Vec};
+use crate::system::ReceiptRetention;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -8,6 +10,8 @@ pub struct InitRuntimeReq {
     pub debug_set_key: Option<String>,
     pub genesis_state_b64: String,
     pub operator_hex: Option<String>,
+    #[serde(default)]
+    pub receipt_retention: Option<ReceiptRetention>,
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InitRuntimeResp {
@@ -50,3 +54,42 @@ pub struct SyncHeaderReq {
 pub struct DispatchBlockReq {
     pub blocks_b64: Vec<String>,
 }
+
+/// The key policy used to seal the persistent runtime data
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SealPolicy {
+    /// Bound to the enclave measurement. Sealed data is lost when pRuntime is upgraded.
+    MrEnclave,
+    /// Bound to the enclave signer. Sealed data survives upgrades signed by the same vendor.
+    MrSigner,
+}
+
+impl Default for SealPolicy {
+    fn default() -> Self {
+        SealPolicy::MrEnclave
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RotateKeysReq {
+    pub seal_policy: Option<SealPolicy>,
+}
+
+/// The attested ECDH public key of an enclave to migrate the secrets to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MigrationChallenge {
+    pub ecdh_public_key: String,
+    pub attestation: InitRespAttestation,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportSecretsReq {
+    pub challenge: MigrationChallenge,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportSecretsReq {
+    pub cipher: AeadCipher,
+    /// The attestation of the source, committing to the ECDH key of the cipher
+    pub attestation: InitRespAttestation,
+}
@@ -0,0 +1,328 @@
+//! The master key held by the gatekeepers.
+//!
+//! The first gatekeeper generates the master key when the very first gatekeeper set is applied.
+//! When a new set is scheduled, the current gatekeepers hand over the master key to the joining
+//! ones before the rotation takes effect. The gatekeepers also send the contract key, derived
+//! from the master key, to each attached worker, so that the contract instances have the same
+//! keys on all the workers.
+//!
+//! All the keys are encrypted to the ECDH keys registered in `pallet_registry::Worker`.
+
+use crate::std::prelude::v1::*;
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use parity_scale_codec::{Decode, Encode};
+use phala_mq::{
+    EcdsaMessageChannel, MessageDispatcher, MessageOrigin, MessageSendQueue, TypedReceiveError,
+    TypedReceiver,
+};
+use phala_types::{
+    messaging::{EncryptedKey, GatekeeperEvent, KeyDistribution},
+    WorkerPublicKey,
+};
+use ring::agreement::EphemeralPrivateKey;
+use sp_core::{ecdsa, hashing::blake2_256};
+
+use crate::cryptography::{aead, ecdh};
+use crate::light_validation::utils::storage_prefix;
+use crate::msg_channel::osp::storage_key_for_worker_info;
+use crate::Storage;
+
+pub type SecretKey = [u8; 32];
+
+/// The keys received from the gatekeepers, sealed with the identity of the worker
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MasterKeys {
+    /// Only held by the gatekeepers
+    pub master_key: Option<SecretKey>,
+    /// The root of the keys of the contract instances
+    pub contract_key: Option<SecretKey>,
+}
+
+impl MasterKeys {
+    fn set_master_key(&mut self, master_key: SecretKey) {
+        self.master_key = Some(master_key);
+        self.contract_key = Some(contract_key_of(&master_key));
+    }
+}
+
+fn contract_key_of(master_key: &SecretKey) -> SecretKey {
+    blake2_256(&(b"phala/gatekeeper/contract_key", master_key).encode())
+}
+
+pub struct Gatekeeper {
+    pubkey: WorkerPublicKey,
+    ecdh_key: EphemeralPrivateKey,
+    ecdh_pubkey: Vec<u8>,
+    egress: EcdsaMessageChannel,
+    event_ingress: TypedReceiver<GatekeeperEvent>,
+    key_ingress: TypedReceiver<KeyDistribution>,
+    keys: MasterKeys,
+    /// Whether the first gatekeeper set has been applied
+    bootstrapped: bool,
+}
+
+impl Gatekeeper {
+    pub fn new(
+        pair: &ecdsa::Pair,
+        ecdh_key: EphemeralPrivateKey,
+        ecdh_pubkey: Vec<u8>,
+        send_mq: &MessageSendQueue,
+        recv_mq: &mut MessageDispatcher,
+        keys: MasterKeys,
+    ) -> Self {
+        let pubkey = ecdsa::Public::from(pair.clone());
+        Gatekeeper {
+            egress: send_mq.channel(MessageOrigin::Worker(pubkey.clone()), pair.clone()),
+            pubkey,
+            ecdh_key,
+            ecdh_pubkey,
+            event_ingress: recv_mq.subscribe_bound(),
+            key_ingress: recv_mq.subscribe_bound(),
+            keys,
+            bootstrapped: false,
+        }
+    }
+
+    /// Switches to a new identity after a key rotation
+    pub fn set_identity(
+        &mut self,
+        pair: &ecdsa::Pair,
+        ecdh_key: EphemeralPrivateKey,
+        ecdh_pubkey: Vec<u8>,
+        send_mq: &MessageSendQueue,
+    ) {
+        let pubkey = ecdsa::Public::from(pair.clone());
+        self.egress = send_mq.channel(MessageOrigin::Worker(pubkey.clone()), pair.clone());
+        self.pubkey = pubkey;
+        self.ecdh_key = ecdh_key;
+        self.ecdh_pubkey = ecdh_pubkey;
+    }
+
+    pub fn keys(&self) -> &MasterKeys {
+        &self.keys
+    }
+
+    /// Whether the first gatekeeper set has been applied, to be carried over by a secret migration
+    pub fn bootstrapped(&self) -> bool {
+        self.bootstrapped
+    }
+
+    pub fn set_bootstrapped(&mut self, bootstrapped: bool) {
+        self.bootstrapped = bootstrapped;
+    }
+
+    pub fn process_messages(&mut self, storage: &Storage) -> Result<()> {
+        loop {
+            match self.event_ingress.try_next() {
+                Ok(Some((_, event, origin))) => {
+                    if !origin.is_pallet() {
+                        error!("Invalid GatekeeperEvent sender: {:?}", origin);
+                        continue;
+                    }
+                    self.handle_event(event, storage);
+                }
+                Ok(None) => break,
+                Err(TypedReceiveError::CodecError(e)) => {
+                    error!("Decode gatekeeper event failed: {:?}", e);
+                }
+                Err(TypedReceiveError::SenderGone) => {
+                    return Err(anyhow!("Gatekeeper event channel broken"));
+                }
+            }
+        }
+        loop {
+            match self.key_ingress.try_next() {
+                Ok(Some((_, message, origin))) => {
+                    if let Err(e) = self.handle_key(message, origin, storage) {
+                        error!("Failed to receive the key: {:?}", e);
+                    }
+                }
+                Ok(None) => break,
+                Err(TypedReceiveError::CodecError(e)) => {
+                    error!("Decode key distribution failed: {:?}", e);
+                }
+                Err(TypedReceiveError::SenderGone) => {
+                    return Err(anyhow!("Key distribution channel broken"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: GatekeeperEvent, storage: &Storage) {
+        match event {
+            GatekeeperEvent::RotationScheduled { gatekeepers, .. } => {
+                if self.keys.master_key.is_none()
+                    || !crate::identity::is_gatekeeper(&self.pubkey, storage)
+                {
+                    return;
+                }
+                for gatekeeper in gatekeepers {
+                    if crate::identity::is_gatekeeper(&gatekeeper, storage) {
+                        continue;
+                    }
+                    info!("Handing over the master key to {:?}", gatekeeper);
+                    self.send_key(&gatekeeper, storage, true);
+                }
+            }
+            GatekeeperEvent::RotationApplied { gatekeepers } => {
+                let bootstrapping = !self.bootstrapped;
+                self.bootstrapped = true;
+                if !gatekeepers.contains(&self.pubkey) {
+                    return;
+                }
+                if !bootstrapping {
+                    if self.keys.master_key.is_none() {
+                        warn!("Joined the gatekeepers without the master key");
+                    }
+                    return;
+                }
+                // The first set has only one gatekeeper, and no one to receive the key from. The
+                // key is already sealed when the block is replayed, but the messages are sent
+                // again to keep the egress sequence.
+                if self.keys.master_key.is_none() {
+                    info!("Generating the master key");
+                    self.keys.set_master_key(new_secret_key());
+                }
+                for (_, value) in storage.pairs(&storage_prefix("PhalaRegistry", "Worker")) {
+                    match chain::pallet_registry::WorkerInfo::decode(&mut &value[..]) {
+                        Ok(info) if info.pubkey != self.pubkey => {
+                            self.send_key(&info.pubkey, storage, false)
+                        }
+                        Ok(_) => (),
+                        Err(_) => error!("Decode WorkerInfo failed"),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends the contract key to an attached worker
+    pub fn on_worker_attached(&self, worker: &WorkerPublicKey, storage: &Storage) {
+        if worker == &self.pubkey
+            || self.keys.master_key.is_none()
+            || !crate::identity::is_gatekeeper(&self.pubkey, storage)
+        {
+            return;
+        }
+        self.send_key(worker, storage, false);
+    }
+
+    fn send_key(&self, dest: &WorkerPublicKey, storage: &Storage, master: bool) {
+        let key = if master {
+            self.keys.master_key
+        } else {
+            self.keys.contract_key
+        };
+        let key = match key {
+            Some(key) => key,
+            None => return,
+        };
+        let key = match self.encrypt_key(&key, dest, storage) {
+            Ok(key) => key,
+            Err(e) => {
+                warn!("Can't send the key to {:?}: {:?}", dest, e);
+                return;
+            }
+        };
+        let dest = dest.clone();
+        if master {
+            self.egress.send(&KeyDistribution::MasterKey { dest, key });
+        } else {
+            self.egress.send(&KeyDistribution::ContractKey { dest, key });
+        }
+    }
+
+    fn encrypt_key(
+        &self,
+        key: &SecretKey,
+        dest: &WorkerPublicKey,
+        storage: &Storage,
+    ) -> Result<EncryptedKey> {
+        let info = storage
+            .get(&storage_key_for_worker_info(dest))
+            .ok_or_else(|| anyhow!("Worker not registered"))?;
+        let info = chain::pallet_registry::WorkerInfo::decode(&mut &info[..])
+            .map_err(|_| anyhow!("Decode WorkerInfo failed"))?;
+        let secret = ecdh::try_agree(&self.ecdh_key, &info.ecdh_pubkey)?;
+        let iv = aead::generate_iv();
+        let mut encrypted_key = key.to_vec();
+        aead::encrypt(&iv, &secret, &mut encrypted_key);
+        Ok(EncryptedKey {
+            ecdh_pubkey: self.ecdh_pubkey.clone(),
+            iv: iv.to_vec(),
+            encrypted_key,
+        })
+    }
+
+    fn decrypt_key(&self, key: &EncryptedKey) -> Result<SecretKey> {
+        let secret = ecdh::try_agree(&self.ecdh_key, &key.ecdh_pubkey)?;
+        let mut data = key.encrypted_key.clone();
+        let plain = aead::try_decrypt(&key.iv, &secret, &mut data)
+            .ok_or_else(|| anyhow!("Failed to decrypt the key"))?;
+        if plain.len() != 32 {
+            return Err(anyhow!("Bad key length {}", plain.len()));
+        }
+        let mut out = SecretKey::default();
+        out.copy_from_slice(plain);
+        Ok(out)
+    }
+
+    fn handle_key(
+        &mut self,
+        message: KeyDistribution,
+        origin: MessageOrigin,
+        storage: &Storage,
+    ) -> Result<()> {
+        let sender = match &origin {
+            MessageOrigin::Worker(pubkey) => pubkey,
+            _ => return Err(anyhow!("Invalid key sender: {:?}", origin)),
+        };
+        let (dest, key, master) = match &message {
+            KeyDistribution::MasterKey { dest, key } => (dest, key, true),
+            KeyDistribution::ContractKey { dest, key } => (dest, key, false),
+        };
+        if dest != &self.pubkey {
+            return Ok(());
+        }
+        if !crate::identity::is_gatekeeper(sender, storage) {
+            return Err(anyhow!("Key sent by a non-gatekeeper {:?}", sender));
+        }
+        let key = self.decrypt_key(key)?;
+        if master {
+            if !crate::identity::is_next_gatekeeper(&self.pubkey, storage) {
+                return Err(anyhow!("Received the master key without joining the gatekeepers"));
+            }
+            match &self.keys.master_key {
+                Some(current) if current != &key => {
+                    return Err(anyhow!("Received a different master key"));
+                }
+                Some(_) => (),
+                None => {
+                    info!("Master key received from {:?}", sender);
+                    self.keys.set_master_key(key);
+                }
+            }
+        } else {
+            match &self.keys.contract_key {
+                Some(current) if current != &key => {
+                    return Err(anyhow!("Received a different contract key"));
+                }
+                Some(_) => (),
+                None => {
+                    info!("Contract key received from {:?}", sender);
+                    self.keys.contract_key = Some(key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn new_secret_key() -> SecretKey {
+    use rand::RngCore;
+    let mut key = SecretKey::default();
+    rand::thread_rng().fill_bytes(&mut key);
+    key
+}
@@ -4,7 +4,7 @@ use core::fmt;
 use log::info;
 use serde::{Deserialize, Serialize};
 use sp_application_crypto::Public;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::HashSet;
 
 use chain::pallet_mq::MessageOriginInfo;
 use enclave_api::blocks::StorageKV;
@@ -15,15 +15,22 @@ use phala_mq::{
     TypedReceiver,
 };
 use phala_types::{
+    benchmark::{self as bench, MerkleTree},
     messaging::{BlockRewardInfo, SystemEvent, WorkerReportEvent},
-    WorkerPublicKey, WorkerStateEnum,
+    WorkerKeyHandover, WorkerPublicKey, WorkerStateEnum,
 };
+use ring::agreement::EphemeralPrivateKey;
 use sp_core::{ecdsa, hashing::blake2_256, storage::StorageKey, U256};
 
 use crate::light_validation::utils::storage_prefix;
 use crate::OnlineWorkerSnapshot;
 
 mod comp_election;
+mod gatekeeper;
+mod receipts;
+
+pub use gatekeeper::{MasterKeys, SecretKey};
+pub use receipts::{ReceiptRetention, ReceiptStore};
 
 pub type CommandIndex = u64;
 
@@ -48,6 +55,7 @@ pub enum TransactionStatus {
     FailedToSign,
     BadDecimal,
     DestroyNotAllowed,
+    NotAdmin,
     // for pdiem
     BadAccountInfo,
     BadLedgerInfo,
@@ -61,6 +69,24 @@ pub enum TransactionStatus {
     FailedToCalculateBalance,
     BadChainId,
     TransferringNotAllowed,
+    // for assets
+    InsufficientAllowance,
+    Overflow,
+    // for nft
+    TokenNotFound,
+    TokenExists,
+    NotTokenOwner,
+    // for data plaza
+    ItemNotFound,
+    OrderNotFound,
+    BadOrderState,
+    DataNotReady,
+    // for web3 analytics
+    SiteExists,
+    SiteNotFound,
+    NotSiteOwner,
+    // for the payments to the contract instances
+    InstanceNotReady,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -75,10 +101,15 @@ pub struct TransactionReceipt {
     pub status: TransactionStatus,
 }
 
-#[derive(Debug)]
-struct BenchState {
-    block: chain::BlockNumber,
-    time: u64,
+enum BenchState {
+    Running {
+        block: chain::BlockNumber,
+    },
+    /// The outputs are committed, waiting for the seed to sample them
+    Committed {
+        outputs: Vec<u64>,
+        tree: MerkleTree,
+    },
 }
 
 #[derive(Debug)]
@@ -93,6 +124,17 @@ impl AttachState {
     }
 }
 
+/// The state of the system carried over by a secret migration.
+///
+/// An unfinished benchmark isn't carried over. The worker takes the next one started on chain.
+#[derive(Serialize, Deserialize)]
+pub struct SystemSnapshot {
+    comp_elected: bool,
+    session_id: Option<u64>,
+    gatekeeper_bootstrapped: bool,
+    receipts: ReceiptStore,
+}
+
 pub struct System {
     // Keys and identity
     pubkey: WorkerPublicKey,
@@ -101,46 +143,133 @@ pub struct System {
     // Computation task electino
     comp_elected: bool,
     // Transaction
-    receipts: BTreeMap<CommandIndex, TransactionReceipt>,
+    receipts: ReceiptStore,
     // Messageing
     egress: EcdsaMessageChannel,
     ingress: TypedReceiver<Event>,
     attach_state: AttachState,
     bench_state: Option<BenchState>,
+    gatekeeper: gatekeeper::Gatekeeper,
 }
 
 impl System {
     pub fn new(
         machine_id: Vec<u8>,
         pair: &ecdsa::Pair,
+        ecdh_key: EphemeralPrivateKey,
+        ecdh_pubkey: Vec<u8>,
+        master_keys: MasterKeys,
         send_mq: &MessageSendQueue,
         recv_mq: &mut MessageDispatcher,
+        receipt_retention: ReceiptRetention,
     ) -> Self {
         let pubkey = ecdsa::Public::from(pair.clone());
-        let raw_pubkey: &[u8] = pubkey.as_ref();
-        let pkh = blake2_256(raw_pubkey);
-        let hashed_id: U256 = pkh.into();
-        info!("System::set_id: hashed identity key: {:?}", hashed_id);
+        let hashed_id = hashed_id(&pubkey);
         let sender = MessageOrigin::Worker(pubkey.clone());
         System {
             pubkey,
             hashed_id,
             machine_id,
             comp_elected: false,
-            receipts: Default::default(),
+            receipts: ReceiptStore::new(receipt_retention),
             egress: send_mq.channel(sender, pair.clone()),
             ingress: recv_mq.subscribe_bound(),
             attach_state: AttachState::Detached,
             bench_state: None,
+            gatekeeper: gatekeeper::Gatekeeper::new(
+                pair,
+                ecdh_key,
+                ecdh_pubkey,
+                send_mq,
+                recv_mq,
+                master_keys,
+            ),
         }
     }
 
+    /// Switches to a new identity key after a key rotation.
+    ///
+    /// Messages already queued by the old identity are kept in the queue under the old sender.
+    pub fn set_identity(
+        &mut self,
+        pair: &ecdsa::Pair,
+        ecdh_key: EphemeralPrivateKey,
+        ecdh_pubkey: Vec<u8>,
+        send_mq: &MessageSendQueue,
+    ) {
+        let pubkey = ecdsa::Public::from(pair.clone());
+        self.hashed_id = hashed_id(&pubkey);
+        self.egress = send_mq.channel(MessageOrigin::Worker(pubkey.clone()), pair.clone());
+        self.pubkey = pubkey;
+        self.gatekeeper.set_identity(pair, ecdh_key, ecdh_pubkey, send_mq);
+    }
+
+    /// The current identity of this worker
+    pub fn pubkey(&self) -> &WorkerPublicKey {
+        &self.pubkey
+    }
+
+    /// The keys received from the gatekeepers, to be sealed
+    pub fn master_keys(&self) -> &MasterKeys {
+        self.gatekeeper.keys()
+    }
+
+    /// The channel signed by the identity of this worker
+    pub fn worker_mq(&self) -> &EcdsaMessageChannel {
+        &self.egress
+    }
+
+    /// The root of the keys of the contract instances, once received from the gatekeepers
+    pub fn contract_key(&self) -> Option<&SecretKey> {
+        self.gatekeeper.keys().contract_key.as_ref()
+    }
+
+    /// Publishes the key handover statement signed by the current identity key
+    pub fn announce_key_handover(&self, handover: WorkerKeyHandover, signature: Vec<u8>) {
+        info!("System::announce_key_handover: {:?}", handover);
+        self.egress.send(&RegistryEvent::KeyHandover { handover, signature });
+    }
+
     pub fn add_receipt(&mut self, command_index: CommandIndex, tr: TransactionReceipt) {
         self.receipts.insert(command_index, tr);
     }
 
     pub fn get_receipt(&self, command_index: CommandIndex) -> Option<&TransactionReceipt> {
-        self.receipts.get(&command_index)
+        self.receipts.get(command_index)
+    }
+
+    /// The receipts to be included in a state snapshot
+    pub fn receipts(&self) -> &ReceiptStore {
+        &self.receipts
+    }
+
+    /// Restores the receipts from a state snapshot, keeping the configured retention
+    pub fn load_receipts(&mut self, receipts: ReceiptStore) {
+        self.receipts.load(receipts);
+    }
+
+    /// Takes the state to be carried over by a secret migration
+    pub fn snapshot(&self) -> SystemSnapshot {
+        SystemSnapshot {
+            comp_elected: self.comp_elected,
+            session_id: match self.attach_state {
+                AttachState::Attached { session_id } => Some(session_id),
+                AttachState::Detached => None,
+            },
+            gatekeeper_bootstrapped: self.gatekeeper.bootstrapped(),
+            receipts: self.receipts.clone(),
+        }
+    }
+
+    /// Restores the state taken by `snapshot` on the previous pRuntime
+    pub fn restore(&mut self, snapshot: SystemSnapshot) {
+        self.comp_elected = snapshot.comp_elected;
+        self.attach_state = match snapshot.session_id {
+            Some(session_id) => AttachState::Attached { session_id },
+            None => AttachState::Detached,
+        };
+        self.gatekeeper.set_bootstrapped(snapshot.gatekeeper_bootstrapped);
+        self.load_receipts(snapshot.receipts);
     }
 
     pub fn handle_query(
@@ -167,6 +296,24 @@ impl System {
                         "Transaction hash not found",
                     )))),
                 },
+                Request::GetReceipts {
+                    account,
+                    from,
+                    limit,
+                } => {
+                    let origin =
+                        accid_origin.ok_or_else(|| anyhow::Error::msg(Error::NotAuthorized))?;
+                    let account = crate::contracts::account_id_from_hex(&account)?;
+                    if &account != origin {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    let account: [u8; 32] = *account.as_ref();
+                    let limit = limit.min(MAX_RECEIPTS_PER_PAGE).max(1) as usize;
+                    let (receipts, next) =
+                        self.receipts
+                            .list(&MessageOrigin::AccountId(account.into()), from, limit);
+                    Ok(Response::GetReceipts { receipts, next })
+                }
             }
         };
         match inner() {
@@ -189,6 +336,8 @@ impl System {
         block_number: chain::BlockNumber,
         storage: &crate::Storage,
     ) -> anyhow::Result<()> {
+        self.receipts.prune(block_number);
+        self.gatekeeper.process_messages(storage)?;
         let mut event_handler = self.feed_event();
         loop {
             match event_handler.system.ingress.try_next() {
@@ -212,17 +361,19 @@ impl System {
             }
         }
         drop(event_handler);
-        if let Some(BenchState { block, time }) = self.bench_state {
+        if let Some(BenchState::Running { block }) = self.bench_state {
             const BENCH_DURATION: u32 = 8;
             if block_number - block >= BENCH_DURATION {
                 benchmark::puase();
-                let report = RegistryEvent::BenchReport {
-                    start_time: time,
-                    iterations: benchmark::iteration_counter(),
+                let outputs = benchmark::take_outputs();
+                let tree = MerkleTree::new(blake2_256, &outputs);
+                let commit = RegistryEvent::BenchCommit {
+                    iterations: outputs.len() as u64,
+                    root: tree.root(),
                 };
-                info!("Reporting benchmark: {:?}", report);
-                self.egress.send(&report);
-                self.bench_state = None;
+                info!("Committing benchmark: {:?}", commit);
+                self.egress.send(&commit);
+                self.bench_state = Some(BenchState::Committed { outputs, tree });
             }
         }
         Ok(())
@@ -289,17 +440,33 @@ impl<'a> EventHandler<'a> {
         storage: &crate::Storage,
     ) -> Result<()> {
         match event {
-            Event::BenchStart { pubkey, start_time } => {
+            Event::BenchStart {
+                pubkey, challenge, ..
+            } => {
                 if pubkey == &self.system.pubkey {
-                    self.system.bench_state = Some(BenchState {
+                    self.system.bench_state = Some(BenchState::Running {
                         block: block_number,
-                        time: *start_time,
                     });
+                    benchmark::set_challenge(challenge);
                     benchmark::reset_iteration_counter();
                     benchmark::resume();
                 }
             }
+            Event::BenchSeed { pubkey, seed } => {
+                if pubkey == &self.system.pubkey {
+                    if let Some(BenchState::Committed { outputs, tree }) =
+                        self.system.bench_state.take()
+                    {
+                        let samples = bench::make_samples(blake2_256, &outputs, &tree, seed);
+                        info!("Reporting {} benchmark samples", samples.len());
+                        self.system
+                            .egress
+                            .send(&RegistryEvent::BenchReport { samples });
+                    }
+                }
+            }
             Event::WorkerAttached { pubkey, session_id } => {
+                self.system.gatekeeper.on_worker_attached(pubkey, storage);
                 if pubkey == &self.system.pubkey {
                     info!("System::handle_event: WorkerAttached");
                     self.system.attach_state = AttachState::Attached {
@@ -418,6 +585,14 @@ fn snapshot_online_worker(trie: &crate::Storage) -> anyhow::Result<Option<Online
     }))
 }
 
+fn hashed_id(pubkey: &WorkerPublicKey) -> U256 {
+    let raw_pubkey: &[u8] = pubkey.as_ref();
+    let pkh = blake2_256(raw_pubkey);
+    let hashed_id: U256 = pkh.into();
+    info!("System::set_id: hashed identity key: {:?}", hashed_id);
+    hashed_id
+}
+
 fn storage_kv_from_data<T>(storage_data: Vec<(StorageKey, T)>) -> Vec<StorageKV<T>>
 where
     T: FullCodec + Clone,
@@ -460,9 +635,20 @@ impl fmt::Display for Error {
     }
 }
 
+/// The maximum number of receipts returned by `Request::GetReceipts`
+pub const MAX_RECEIPTS_PER_PAGE: u32 = 100;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
-    QueryReceipt { command_index: CommandIndex },
+    QueryReceipt {
+        command_index: CommandIndex,
+    },
+    /// Lists the receipts of `account` (hex) with command index no less than `from`
+    GetReceipts {
+        account: String,
+        from: CommandIndex,
+        limit: u32,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -470,6 +656,11 @@ pub enum Response {
     QueryReceipt {
         receipt: TransactionReceipt,
     },
+    GetReceipts {
+        receipts: Vec<(CommandIndex, TransactionReceipt)>,
+        /// The command index to continue with, if there are more receipts
+        next: Option<CommandIndex>,
+    },
     GetWorkerEgress {
         length: usize,
         encoded_egress_b64: String,
@@ -0,0 +1,260 @@
+use crate::std::collections::{BTreeMap, BTreeSet, VecDeque};
+use crate::std::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use phala_mq::MessageOrigin;
+
+use super::{CommandIndex, TransactionReceipt};
+
+/// Retention policy of the transaction receipts
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ReceiptRetention {
+    /// The maximum number of receipts to keep
+    pub max_receipts: usize,
+    /// Receipts older than `max_age` blocks are pruned
+    pub max_age: chain::BlockNumber,
+}
+
+impl Default for ReceiptRetention {
+    fn default() -> Self {
+        ReceiptRetention {
+            max_receipts: 100_000,
+            // About 7 days with 6s block time
+            max_age: 100_800,
+        }
+    }
+}
+
+/// A bounded store of the transaction receipts, indexed by the command index and the account.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ReceiptStore {
+    retention: ReceiptRetention,
+    receipts: BTreeMap<CommandIndex, TransactionReceipt>,
+    /// The receipts in insertion order, used for pruning
+    #[serde(skip)]
+    history: VecDeque<(chain::BlockNumber, CommandIndex)>,
+    #[serde(skip)]
+    by_account: BTreeMap<MessageOrigin, BTreeSet<CommandIndex>>,
+}
+
+impl ReceiptStore {
+    pub fn new(retention: ReceiptRetention) -> Self {
+        ReceiptStore {
+            retention,
+            ..Default::default()
+        }
+    }
+
+    pub fn insert(&mut self, command_index: CommandIndex, receipt: TransactionReceipt) {
+        self.history.push_back((receipt.block_num, command_index));
+        let account = receipt.account.clone();
+        if let Some(replaced) = self.receipts.insert(command_index, receipt) {
+            self.unindex(&replaced.account, command_index);
+        }
+        self.by_account
+            .entry(account)
+            .or_default()
+            .insert(command_index);
+        self.prune_excess();
+    }
+
+    pub fn get(&self, command_index: CommandIndex) -> Option<&TransactionReceipt> {
+        self.receipts.get(&command_index)
+    }
+
+    /// Returns at most `limit` receipts of `account` starting from `from`, and the command index to
+    /// continue with if there are more.
+    ///
+    /// At least one receipt is returned if there is any, so that paging always moves forward.
+    pub fn list(
+        &self,
+        account: &MessageOrigin,
+        from: CommandIndex,
+        limit: usize,
+    ) -> (Vec<(CommandIndex, TransactionReceipt)>, Option<CommandIndex>) {
+        let indices = match self.by_account.get(account) {
+            Some(indices) => indices,
+            None => return (Vec::new(), None),
+        };
+        let mut iter = indices.range(from..);
+        let page = iter
+            .by_ref()
+            .take(limit.max(1))
+            .filter_map(|index| self.receipts.get(index).map(|r| (*index, r.clone())))
+            .collect();
+        (page, iter.next().cloned())
+    }
+
+    /// Removes the receipts older than the retention age
+    pub fn prune(&mut self, block_number: chain::BlockNumber) {
+        while let Some(&(block, _)) = self.history.front() {
+            if block.saturating_add(self.retention.max_age) >= block_number {
+                break;
+            }
+            self.pop_oldest();
+        }
+    }
+
+    /// Replaces the receipts with the ones of a snapshot.
+    ///
+    /// The retention configured for this pRuntime is kept rather than the one in the snapshot, and
+    /// applied to the loaded receipts.
+    pub fn load(&mut self, snapshot: ReceiptStore) {
+        self.receipts = snapshot.receipts;
+        self.rebuild_index();
+        self.prune_excess();
+    }
+
+    /// Rebuilds the indices, which are not part of the snapshots
+    fn rebuild_index(&mut self) {
+        let mut history: Vec<_> = self
+            .receipts
+            .iter()
+            .map(|(index, receipt)| (receipt.block_num, *index))
+            .collect();
+        history.sort();
+        self.history = history.into_iter().collect();
+        self.by_account.clear();
+        for (index, receipt) in self.receipts.iter() {
+            self.by_account
+                .entry(receipt.account.clone())
+                .or_default()
+                .insert(*index);
+        }
+    }
+
+    /// Removes the oldest receipts over `max_receipts`
+    fn prune_excess(&mut self) {
+        while self.receipts.len() > self.retention.max_receipts {
+            if !self.pop_oldest() {
+                break;
+            }
+        }
+    }
+
+    fn pop_oldest(&mut self) -> bool {
+        let (block, command_index) = match self.history.pop_front() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        // The entry may be stale if the receipt was replaced later
+        let is_current = matches!(
+            self.receipts.get(&command_index),
+            Some(receipt) if receipt.block_num == block
+        );
+        if is_current {
+            if let Some(receipt) = self.receipts.remove(&command_index) {
+                self.unindex(&receipt.account, command_index);
+            }
+        }
+        true
+    }
+
+    fn unindex(&mut self, account: &MessageOrigin, command_index: CommandIndex) {
+        if let Some(indices) = self.by_account.get_mut(account) {
+            indices.remove(&command_index);
+            if indices.is_empty() {
+                self.by_account.remove(account);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::TransactionStatus;
+
+    fn origin(n: u8) -> MessageOrigin {
+        MessageOrigin::AccountId([n; 32].into())
+    }
+
+    fn receipt(n: u8, block_num: chain::BlockNumber) -> TransactionReceipt {
+        TransactionReceipt {
+            account: origin(n),
+            block_num,
+            contract_id: 2,
+            status: TransactionStatus::Ok,
+        }
+    }
+
+    fn store(max_receipts: usize, max_age: chain::BlockNumber) -> ReceiptStore {
+        ReceiptStore::new(ReceiptRetention {
+            max_receipts,
+            max_age,
+        })
+    }
+
+    #[test]
+    fn prunes_the_oldest_over_max_receipts() {
+        let mut store = store(2, 100);
+        store.insert(1, receipt(1, 1));
+        store.insert(2, receipt(1, 2));
+        store.insert(3, receipt(2, 3));
+        assert!(store.get(1).is_none());
+        assert!(store.get(2).is_some());
+        assert_eq!(store.list(&origin(1), 0, 10).0.len(), 1);
+    }
+
+    #[test]
+    fn prunes_by_age() {
+        let mut store = store(100, 10);
+        store.insert(1, receipt(1, 1));
+        store.insert(2, receipt(1, 5));
+        store.prune(11);
+        assert!(store.get(1).is_some());
+        store.prune(12);
+        assert!(store.get(1).is_none());
+        assert!(store.get(2).is_some());
+    }
+
+    #[test]
+    fn replaced_receipt_is_reindexed() {
+        let mut store = store(100, 100);
+        store.insert(1, receipt(1, 1));
+        store.insert(1, receipt(2, 2));
+        assert!(store.list(&origin(1), 0, 10).0.is_empty());
+        assert_eq!(store.list(&origin(2), 0, 10).0.len(), 1);
+        // The stale history entry doesn't prune the new receipt
+        store.prune(102);
+        assert!(store.get(1).is_some());
+    }
+
+    #[test]
+    fn lists_in_pages() {
+        let mut store = store(100, 100);
+        for index in 1..=5 {
+            store.insert(index, receipt(1, 1));
+        }
+        store.insert(6, receipt(2, 1));
+        let (page, next) = store.list(&origin(1), 0, 2);
+        let indices: Vec<_> = page.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![1, 2]);
+        assert_eq!(next, Some(3));
+        let (page, next) = store.list(&origin(1), 5, 2);
+        assert_eq!(page.len(), 1);
+        assert_eq!(next, None);
+        // A zero limit still moves forward
+        let (page, next) = store.list(&origin(1), 3, 0);
+        assert_eq!(page.len(), 1);
+        assert_eq!(next, Some(4));
+    }
+
+    #[test]
+    fn load_keeps_the_configured_retention() {
+        let mut snapshot = store(100, 100);
+        for index in 1..=3 {
+            snapshot.insert(index, receipt(1, index as _));
+        }
+        let encoded = serde_json::to_vec(&snapshot).unwrap();
+        let snapshot: ReceiptStore = serde_json::from_slice(&encoded).unwrap();
+
+        let mut store = store(2, 100);
+        store.load(snapshot);
+        assert_eq!(store.retention.max_receipts, 2);
+        assert!(store.get(1).is_none());
+        let (page, _) = store.list(&origin(1), 0, 10);
+        let indices: Vec<_> = page.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![2, 3]);
+    }
+}
@@ -10,11 +10,9 @@ extern crate runtime as chain;
 
 // supportive
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct TxRef {
-    pub blocknum: chain::BlockNumber,
-    pub index: u64,
-}
+pub use phala_contract_queries::TxRef;
+
+// The query envelope below must stay in sync with `phala_types::query` used by the clients
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Payload {
@@ -31,7 +29,17 @@ pub struct SignedQuery {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Query<T> {
     pub contract_id: u32,
+    /// The hex id of the contract instance to query, instead of the built-in contract
+    /// `contract_id`
+    #[serde(default)]
+    pub instance_id: Option<String>,
     pub nonce: u32,
+    /// The hex identity public key of the target worker. Required by the signed queries.
+    #[serde(default)]
+    pub worker_pubkey: Option<String>,
+    /// The last block the query is valid at. Required by the signed queries.
+    #[serde(default)]
+    pub expiry: Option<chain::BlockNumber>,
     pub request: T,
 }
 impl<T> Query<T> where T: Serialize + DeserializeOwned + Debug + Clone {}
@@ -46,7 +54,10 @@ where
 {
     Ok(Query {
         contract_id: q.contract_id,
+        instance_id: q.instance_id,
         nonce: q.nonce,
+        worker_pubkey: q.worker_pubkey,
+        expiry: q.expiry,
         request: serde_json::from_value(q.request).map_err(|_| Error::DecodeError)?,
     })
 }
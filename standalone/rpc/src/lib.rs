@@ -116,6 +116,8 @@ pub fn create_full<C, P, SC, B>(
 		HeaderMetadata<Block, Error=BlockChainError> + Sync + Send + 'static,
 	C::Api: substrate_frame_rpc_system::AccountNonceApi<Block, AccountId, Index>,
 	C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
+	C::Api: pallet_mining_staking_rpc::MiningStakingRuntimeApi<Block, AccountId, Balance>,
+	C::Api: phala_pallets_rpc::PhalaRuntimeApi<Block, AccountId, Balance, BlockNumber>,
 	C::Api: BabeApi<Block>,
 	C::Api: BlockBuilder<Block>,
 	P: TransactionPool + 'static,
@@ -125,6 +127,8 @@ pub fn create_full<C, P, SC, B>(
 {
 	use substrate_frame_rpc_system::{FullSystem, SystemApi};
 	use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApi};
+	use pallet_mining_staking_rpc::{MiningStaking, MiningStakingApi};
+	use phala_pallets_rpc::{Phala, PhalaApi};
 
 	let mut io = jsonrpc_core::IoHandler::default();
 	let FullDeps {
@@ -159,6 +163,12 @@ pub fn create_full<C, P, SC, B>(
 	io.extend_with(
 		TransactionPaymentApi::to_delegate(TransactionPayment::new(client.clone()))
 	);
+	io.extend_with(
+		MiningStakingApi::to_delegate(MiningStaking::new(client.clone()))
+	);
+	io.extend_with(
+		PhalaApi::to_delegate(Phala::new(client.clone()))
+	);
 	io.extend_with(
 		sc_consensus_babe_rpc::BabeApi::to_delegate(
 			BabeRpcHandler::new(
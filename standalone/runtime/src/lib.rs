@@ -102,6 +102,7 @@ pub use phala_pallets::{
 	pallet_registry,
 	pallet_mining,
 	pallet_stakepool,
+	pallet_escrow,
 };
 pub use pallet_bridge;
 pub use pallet_bridge_transfer;
@@ -133,10 +134,10 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	// and set impl_version to 0. If only runtime
 	// implementation changes and behavior does not, then leave spec_version as
 	// is and increment impl_version.
-	spec_version: 1,
+	spec_version: 7,
 	impl_version: 0,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	transaction_version: 6,
 };
 
 /// The BABE epoch configuration at genesis.
@@ -1029,8 +1030,12 @@ parameter_types! {
 	pub const RewardRation: u32 = 80_000;
 	pub const OnlineRewardPercentage: Permill = Permill::from_parts(375_000);
 	pub const ComputeRewardPercentage: Permill = Permill::from_parts(625_000);
+	pub const StakerRewardPercentage: Permill = Permill::from_percent(20);
 	pub const OfflineOffenseSlash: Balance = 100 * DOLLARS;
 	pub const OfflineReportReward: Balance = 50 * DOLLARS;
+	pub const OffenceDisputePeriod: BlockNumber = 1 * HOURS;
+	pub const OffenceSlashBase: Permill = Permill::from_percent(1);
+	pub const OffenceSlashMax: Permill = Permill::from_percent(10);
 }
 
 impl pallet_phala::Config for Runtime {
@@ -1040,6 +1045,8 @@ impl pallet_phala::Config for Runtime {
 	type UnixTime = Timestamp;
 	type Treasury = Treasury;
 	type OnRoundEnd = MiningStaking;
+	type OnOffence = MiningStaking;
+	type OnReward = MiningStaking;
 	type WeightInfo = pallet_phala::weights::SubstrateWeight<Runtime>;
 
 	// Parameters
@@ -1052,8 +1059,12 @@ impl pallet_phala::Config for Runtime {
 	type RewardRation = RewardRation;
 	type OnlineRewardPercentage = OnlineRewardPercentage;
 	type ComputeRewardPercentage = ComputeRewardPercentage;
+	type StakerRewardPercentage = StakerRewardPercentage;
 	type OfflineOffenseSlash = OfflineOffenseSlash;
 	type OfflineReportReward = OfflineReportReward;
+	type OffenceDisputePeriod = OffenceDisputePeriod;
+	type OffenceSlashBase = OffenceSlashBase;
+	type OffenceSlashMax = OffenceSlashMax;
 }
 
 impl pallet_kitties::Config for Runtime {
@@ -1061,14 +1072,31 @@ impl pallet_kitties::Config for Runtime {
 	type Randomness = RandomnessCollectiveFlip;
 }
 
+parameter_types! {
+	pub const UnbondingPeriod: BlockNumber = 7 * DAYS;
+	pub const MaxUnbondingChunks: u32 = 32;
+	pub const MinStake: Balance = 10 * DOLLARS;
+	pub const MaxStakePerTarget: Balance = 1_000_000 * DOLLARS;
+	pub const MaxTargetsPerStaker: u32 = 16;
+	pub const MaxStakersPerTarget: u32 = 256;
+}
+
 impl pallet_mining_staking::Config for Runtime {
 	type Event = Event;
 	type Currency = Balances;
+	type UnbondingPeriod = UnbondingPeriod;
+	type MaxUnbondingChunks = MaxUnbondingChunks;
+	type MinStake = MinStake;
+	type MaxStakePerTarget = MaxStakePerTarget;
+	type MaxTargetsPerStaker = MaxTargetsPerStaker;
+	type MaxStakersPerTarget = MaxStakersPerTarget;
+	type WeightInfo = pallet_mining_staking::weights::SubstrateWeight<Runtime>;
 }
 
 parameter_types! {
 	pub const BridgeChainId: u8 = 1;
 	pub const ProposalLifetime: BlockNumber = 50;
+	pub const MaxProposalsPerBatch: u32 = 64;
 }
 
 impl pallet_bridge::Config for Runtime {
@@ -1077,17 +1105,57 @@ impl pallet_bridge::Config for Runtime {
 	type Proposal = Call;
 	type ChainId = BridgeChainId;
 	type ProposalLifetime = ProposalLifetime;
+	type Currency = Balances;
+	type MaxProposalsPerBatch = MaxProposalsPerBatch;
+	type WeightInfo = pallet_bridge::weights::SubstrateWeight<Runtime>;
 }
 
 impl pallet_bridge_transfer::Config for Runtime {
 	type Event = Event;
 	type BridgeOrigin = pallet_bridge::EnsureBridge<Runtime>;
 	type Currency = Balances;
+	type PauseOrigin = EnsureOneOf<
+		AccountId,
+		EnsureRootOrHalfCouncil,
+		pallet_collective::EnsureProportionAtLeast<_1, _2, AccountId, TechnicalCollective>,
+	>;
+	type WeightInfo = pallet_bridge_transfer::weights::SubstrateWeight<Runtime>;
+}
+
+parameter_types! {
+	pub const MaxGatekeepers: u32 = 16;
+	pub const MinGatekeepers: u32 = 1;
+	pub const GatekeeperRotationDelay: BlockNumber = 1 * HOURS;
+	pub const ContractDeposit: Balance = 10 * DOLLARS;
+	pub const MaxInitParamsLen: u32 = 16 * 1024;
+	pub const MaxSaltLen: u32 = 64;
+}
+
+/// The sudo key administrates the built-in native contracts (ids 1 to 8 in pRuntime) on the chains
+/// launched before `pallet_registry::ContractAdmin`, like the root key in the genesis
+pub struct SudoAsContractAdmins;
+impl frame_support::traits::Get<Vec<(u32, AccountId)>> for SudoAsContractAdmins {
+	fn get() -> Vec<(u32, AccountId)> {
+		let key = Sudo::key();
+		(1..=8).map(|id| (id, key.clone())).collect()
+	}
 }
 
 impl pallet_registry::Config for Runtime {
 	type Event = Event;
 	type UnixTime = Timestamp;
+	type Randomness = RandomnessCollectiveFlip;
+	type GatekeeperOrigin = EnsureRootOrHalfCouncil;
+	type ContractAdminOrigin = EnsureRootOrHalfCouncil;
+	type MaxGatekeepers = MaxGatekeepers;
+	type MinGatekeepers = MinGatekeepers;
+	type GatekeeperRotationDelay = GatekeeperRotationDelay;
+	type Currency = Balances;
+	type ContractDeposit = ContractDeposit;
+	type MaxInitParamsLen = MaxInitParamsLen;
+	type MaxSaltLen = MaxSaltLen;
+	type OnWorkerKeyRotated = PhalaMining;
+	type WorkerBindings = PhalaMining;
 }
 impl pallet_mq::Config for Runtime {
 	type Event = Event;
@@ -1101,6 +1169,11 @@ impl pallet_stakepool::Config for Runtime {
 	type Event = Event;
 	type Currency = Balances;
 }
+impl pallet_escrow::Config for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type WeightInfo = pallet_escrow::weights::SubstrateWeight<Runtime>;
+}
 
 construct_runtime!(
 	pub enum Runtime where
@@ -1149,9 +1222,10 @@ construct_runtime!(
 		BridgeTransfer: pallet_bridge_transfer::{Pallet, Call, Event<T>, Config, Storage},
 		// Phala new pallets
 		PhalaMq: pallet_mq::{Pallet, Call, Event, Storage},
-		PhalaRegistry: pallet_registry::{Pallet, Call, Event, Storage},
-		PhalaMining: pallet_mining::{Pallet, Call, Event},
+		PhalaRegistry: pallet_registry::{Pallet, Call, Config<T>, Event<T>, Storage},
+		PhalaMining: pallet_mining::{Pallet, Call, Event, Storage},
 		PhalaStakePool: pallet_stakepool::{Pallet, Call, Event, Storage},
+		PhalaEscrow: pallet_escrow::{Pallet, Call, Event<T>, Storage},
 	}
 );
 
@@ -1192,7 +1266,12 @@ pub type Executive = frame_executive::Executive<
 	frame_system::ChainContext<Runtime>,
 	Runtime,
 	AllPallets,
-	(),
+	(
+		pallet_registry::migrations::MigrateWorkerEcdhPubkey<Runtime>,
+		pallet_registry::migrations::InitContractAdmins<Runtime, SudoAsContractAdmins>,
+		pallet_phala::migrations::MigrateToNewPallets<Runtime>,
+		pallet_escrow::migrations::MigrateLegacyDeposits<Runtime>,
+	),
 >;
 
 impl_runtime_apis! {
@@ -1359,6 +1438,65 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl phala_pallets_rpc_runtime_api::PhalaApi<Block, AccountId, Balance, BlockNumber> for Runtime {
+		fn worker_info(
+			pubkey: phala_types::WorkerPublicKey,
+		) -> Option<phala_pallets_rpc_runtime_api::RegisteredWorker<AccountId>> {
+			let worker = pallet_registry::Worker::<Runtime>::get(&pubkey)?;
+			Some(phala_pallets_rpc_runtime_api::RegisteredWorker {
+				pubkey: worker.pubkey,
+				ecdh_pubkey: worker.ecdh_pubkey,
+				runtime_version: worker.runtime_version,
+				last_updated: worker.last_updated,
+				confidence_level: worker.confidence_level,
+				initial_score: worker.intial_score,
+				features: worker.features,
+				miner: pallet_mining::WorkerBindings::<Runtime>::get(&pubkey),
+			})
+		}
+		fn stash_info(stash: AccountId) -> Option<phala_types::StashInfo<AccountId>> {
+			if pallet_stakepool::StashState::<Runtime>::contains_key(&stash) {
+				Some(pallet_stakepool::StashState::<Runtime>::get(&stash))
+			} else {
+				None
+			}
+		}
+		fn machine_owner(machine_id: Vec<u8>) -> Option<AccountId> {
+			pallet_mining::MachineOwner::<Runtime>::get(&machine_id)
+		}
+		fn round_info() -> phala_types::RoundInfo<BlockNumber> {
+			pallet_phala::Round::<Runtime>::get()
+		}
+		fn round_stats(round: u32) -> Option<phala_types::RoundStats> {
+			if pallet_phala::RoundStatsHistory::<Runtime>::contains_key(round) {
+				Some(pallet_phala::RoundStatsHistory::<Runtime>::get(round))
+			} else {
+				None
+			}
+		}
+		fn pending_rewards(who: AccountId) -> Balance {
+			MiningStaking::pending_rewards(&who)
+		}
+		fn mq_ingress_seq(sender: phala_types::messaging::MessageOrigin) -> u64 {
+			pallet_mq::OffchainIngress::<Runtime>::get(&sender).unwrap_or(0)
+		}
+		fn gatekeepers() -> Vec<phala_types::WorkerPublicKey> {
+			pallet_registry::Gatekeeper::<Runtime>::get()
+		}
+	}
+
+	impl pallet_mining_staking_rpc_runtime_api::MiningStakingApi<
+		Block,
+		AccountId,
+		Balance,
+	> for Runtime {
+		fn staker_position(
+			who: AccountId,
+		) -> pallet_mining_staking_rpc_runtime_api::StakerPosition<AccountId, Balance> {
+			MiningStaking::staker_position(&who)
+		}
+	}
+
 	impl sp_session::SessionKeys<Block> for Runtime {
 		fn generate_session_keys(seed: Option<Vec<u8>>) -> Vec<u8> {
 			SessionKeys::generate(seed)
@@ -1441,6 +1579,10 @@ impl_runtime_apis! {
 			add_benchmark!(params, batches, pallet_vesting, Vesting);
 			add_benchmark!(params, batches, pallet_election_provider_multi_phase, ElectionProviderMultiPhase);
 			add_benchmark!(params, batches, pallet_phala, Phala);
+			add_benchmark!(params, batches, pallet_escrow, PhalaEscrow);
+			add_benchmark!(params, batches, pallet_mining_staking, MiningStaking);
+			add_benchmark!(params, batches, pallet_bridge, ChainBridge);
+			add_benchmark!(params, batches, pallet_bridge_transfer, BridgeTransfer);
 
 			if batches.is_empty() { return Err("Benchmark not found for this pallet.".into()) }
 			Ok(batches)
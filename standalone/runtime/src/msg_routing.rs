@@ -3,7 +3,7 @@ use phala_pallets::phala_legacy::OnMessageReceived;
 use phala_types::messaging::{self, BindTopic, Lottery, Message, WorkerReportEvent};
 use super::pallet_registry::RegistryEvent;
 
-type BalanceTransfer = messaging::BalanceTransfer<super::AccountId, super::Balance>;
+type BalanceReport = messaging::BalanceReport<super::AccountId, super::Balance>;
 type KittyTransfer = messaging::KittyTransfer<super::AccountId>;
 
 pub struct MessageRouteConfig;
@@ -13,7 +13,7 @@ impl pallet_mq::QueueNotifyConfig for MessageRouteConfig {
     fn on_message_received(message: &Message) {
         let result = match &message.destination.path()[..] {
             Lottery::TOPIC => super::BridgeTransfer::on_message_received(message),
-            BalanceTransfer::TOPIC => super::Phala::on_transfer_message_received(message),
+            BalanceReport::TOPIC => super::PhalaEscrow::on_message_received(message),
             KittyTransfer::TOPIC => super::KittyStorage::on_message_received(message),
             WorkerReportEvent::TOPIC => super::Phala::on_worker_message_received(message),
             RegistryEvent::TOPIC => super::PhalaRegistry::on_message_received(message),
@@ -378,6 +378,53 @@ impl<T: Runtime> Rpc<T> {
             .map_err(Into::into)
     }
 
+    /// Query the next expected ingress sequence of an offchain message sender
+    ///
+    /// The sender is the SCALE encoded `MessageOrigin`.
+    pub async fn get_mq_ingress_seq(
+        &self,
+        sender: Bytes,
+        at: Option<T::Hash>,
+    ) -> Result<u64, Error> {
+        let params = &[to_json_value(sender)?, to_json_value(at)?];
+        self.client
+            .request("pha_getMqIngressSeq", params)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Query a registered worker and the miner it's bound to
+    pub async fn get_worker_info<W>(
+        &self,
+        pubkey: sp_core::ecdsa::Public,
+        at: Option<T::Hash>,
+    ) -> Result<Option<W>, Error>
+    where
+        W: DeserializeOwned + std::fmt::Debug,
+    {
+        let params = &[to_json_value(pubkey)?, to_json_value(at)?];
+        self.client
+            .request("pha_getWorkerInfo", params)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Query the miner of a legacy worker whose pubkey couldn't be bound
+    pub async fn get_machine_owner(
+        &self,
+        machine_id: Bytes,
+        at: Option<T::Hash>,
+    ) -> Result<Option<T::AccountId>, Error>
+    where
+        T::AccountId: DeserializeOwned,
+    {
+        let params = &[to_json_value(machine_id)?, to_json_value(at)?];
+        self.client
+            .request("pha_getMachineOwner", params)
+            .await
+            .map_err(Into::into)
+    }
+
     /// Fetch the genesis hash
     pub async fn genesis_hash(&self) -> Result<T::Hash, Error> {
         let block_zero = Some(ListOrValue::Value(NumberOrHex::Number(0)));